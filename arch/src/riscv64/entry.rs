@@ -1,10 +1,93 @@
+use core::{mem::size_of, ops::Range, ptr::addr_of};
+
 use config::{
     board::MAX_HARTS,
-    mm::{KERNEL_STACK_SIZE, PTES_PER_PAGE, VIRT_RAM_OFFSET},
+    mm::{
+        KERNEL_EMERGENCY_STACK_SIZE, KERNEL_STACK_GUARD_SIZE, KERNEL_STACK_SIZE, PTES_PER_PAGE,
+        VIRT_RAM_OFFSET,
+    },
 };
 
+use super::register;
+
+/// Kernel stacks of all harts, each of which is aligned to its size, so that
+/// the trap entry can tell from the low bits of sp alone whether it has
+/// overflowed into the guard page at the bottom of the stack.
+#[repr(C, align(65536))]
+pub struct KernelStacks([[u8; KERNEL_STACK_SIZE]; MAX_HARTS]);
+
+const _: () = assert!(KERNEL_STACK_SIZE == 65536);
+
+#[link_section = ".bss.stack"]
+pub static mut BOOT_STACK: KernelStacks = KernelStacks([[0; KERNEL_STACK_SIZE]; MAX_HARTS]);
+
+/// Stacks of all harts to report a kernel stack overflow on, since the kernel
+/// stack can not be used any more.
+#[repr(C, align(16))]
+pub struct EmergencyStacks([[u8; KERNEL_EMERGENCY_STACK_SIZE]; MAX_HARTS]);
+
 #[link_section = ".bss.stack"]
-static mut BOOT_STACK: [u8; KERNEL_STACK_SIZE * MAX_HARTS] = [0u8; KERNEL_STACK_SIZE * MAX_HARTS];
+pub static mut EMERGENCY_STACK: EmergencyStacks =
+    EmergencyStacks([[0; KERNEL_EMERGENCY_STACK_SIZE]; MAX_HARTS]);
+
+fn kernel_stacks_base() -> usize {
+    unsafe { addr_of!(BOOT_STACK) as usize }
+}
+
+/// The kernel stack of the hart, excluding the guard page.
+pub fn kernel_stack(hart_id: usize) -> Range<usize> {
+    let bottom = kernel_stacks_base() + hart_id * KERNEL_STACK_SIZE;
+    bottom + KERNEL_STACK_GUARD_SIZE..bottom + KERNEL_STACK_SIZE
+}
+
+/// The guard page below the kernel stack of the hart, which is left unmapped.
+pub fn kernel_stack_guard(hart_id: usize) -> Range<usize> {
+    let bottom = kernel_stacks_base() + hart_id * KERNEL_STACK_SIZE;
+    bottom..bottom + KERNEL_STACK_GUARD_SIZE
+}
+
+/// The hart owning the kernel stack, including its guard page, that `sp`
+/// points into.
+pub fn kernel_stack_owner(sp: usize) -> Option<usize> {
+    let offset = sp.wrapping_sub(kernel_stacks_base());
+    (offset < KERNEL_STACK_SIZE * MAX_HARTS).then_some(offset / KERNEL_STACK_SIZE)
+}
+
+/// Pattern filled into the unused part of a kernel stack to find out how deep
+/// it has been used.
+const STACK_PATTERN: usize = 0x5a5a_5a5a_5a5a_5a5a;
+/// Bytes below sp that are left alone when filling the pattern, for the
+/// frames of the callees.
+const STACK_FILL_MARGIN: usize = 1024;
+
+/// Fill the unused part of the local kernel stack with `STACK_PATTERN`, with
+/// interrupts disabled.
+#[inline(never)]
+pub fn fill_local_stack() {
+    let sp = register::sp();
+    let Some(hart_id) = kernel_stack_owner(sp) else {
+        return;
+    };
+    let stack = kernel_stack(hart_id);
+    for addr in (stack.start..sp - STACK_FILL_MARGIN).step_by(size_of::<usize>()) {
+        unsafe { (addr as *mut usize).write_volatile(STACK_PATTERN) };
+    }
+}
+
+/// The most bytes of the local kernel stack ever used since it is filled by
+/// `fill_local_stack`, which are found by scanning for the first word
+/// overwritten from the bottom.
+pub fn local_stack_usage() -> usize {
+    let Some(hart_id) = kernel_stack_owner(register::sp()) else {
+        return 0;
+    };
+    let stack = kernel_stack(hart_id);
+    let untouched = (stack.start..stack.end)
+        .step_by(size_of::<usize>())
+        .find(|&addr| unsafe { (addr as *const usize).read_volatile() } != STACK_PATTERN)
+        .unwrap_or(stack.end);
+    stack.end - untouched
+}
 
 #[repr(C, align(4096))]
 struct BootPageTable([u64; PTES_PER_PAGE]);
@@ -23,7 +106,7 @@ static mut BOOT_PAGE_TABLE: BootPageTable = {
 unsafe extern "C" fn _start(hart_id: usize, dtb_addr: usize) -> ! {
     core::arch::asm!(
         // 1. set boot stack
-        // sp = boot_stack + (hartid + 1) * 64KB
+        // sp = boot_stack + (hartid + 1) * 64KB, the top of the stack of the hart
         "
             addi    t0, a0, 1
             slli    t0, t0, 16              // t0 = (hart_id + 1) * 64KB
@@ -1,7 +1,8 @@
 use riscv::register::{
-    sie, sstatus,
+    sie, sip, sstatus,
     stvec::{self, TrapMode},
 };
+use sbi_rt::HartMask;
 
 pub fn is_interrupt_enabled() -> bool {
     sstatus::read().sie()
@@ -25,6 +26,27 @@ pub unsafe fn enable_external_interrupt() {
     sie::set_sext();
 }
 
+pub unsafe fn enable_software_interrupt() {
+    sie::set_ssoft();
+}
+
+/// Send an inter-processor interrupt to the hart, i.e. a supervisor software
+/// interrupt.
+pub fn send_ipi(hart_id: usize) {
+    sbi_rt::send_ipi(HartMask::from_mask_base(1, hart_id));
+}
+
+/// Clear the pending inter-processor interrupt of the local hart.
+pub fn clear_ipi() {
+    unsafe { sip::clear_ssoft() };
+}
+
+/// Stall the local hart until an interrupt is pending, even if interrupts are
+/// disabled.
+pub fn wait_for_interrupt() {
+    unsafe { riscv::asm::wfi() };
+}
+
 pub fn get_trap_handler() -> usize {
     stvec::read().bits()
 }
@@ -5,3 +5,11 @@ pub unsafe fn sfence_vma_vaddr(vaddr: usize) {
 pub unsafe fn sfence_vma_all() {
     core::arch::riscv64::sfence_vma_all();
 }
+
+pub unsafe fn sfence_vma_asid(asid: usize) {
+    core::arch::riscv64::sfence_vma_asid(asid);
+}
+
+pub unsafe fn sfence_vma(vaddr: usize, asid: usize) {
+    core::arch::riscv64::sfence_vma(vaddr, asid);
+}
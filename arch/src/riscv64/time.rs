@@ -1,12 +1,18 @@
 use core::time::Duration;
 
 use config::{board::clock_freq, time::INTERRUPTS_PER_SECOND};
-use riscv::register::time;
+use riscv::register::{cycle, time};
 
 pub fn get_time() -> usize {
     time::read()
 }
 
+/// Cycles elapsed on this hart, which is cheaper to read than the time but
+/// not synchronized among harts.
+pub fn get_cycles() -> usize {
+    cycle::read()
+}
+
 /// milliseconds 毫秒
 pub fn get_time_ms() -> usize {
     time::read() / (clock_freq() / 1_000)
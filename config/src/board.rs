@@ -5,6 +5,8 @@ use crate::{
 
 pub const BLOCK_SIZE: usize = 512;
 pub const BLOCK_MASK: usize = BLOCK_SIZE - 1;
+/// Upper bound of the linear map of RAM, beyond which RAM found in the device
+/// tree is left unused.
 pub const MEMORY_END: usize = VIRT_START + RAM_SIZE;
 
 pub const UART_BUF_LEN: usize = 512;
@@ -12,3 +14,5 @@ pub const UART_BUF_LEN: usize = 512;
 pub const MAX_HARTS: usize = 4;
 register_mut_const!(pub HARTS, usize, 1);
 register_mut_const!(pub CLOCK_FREQ, usize, 10000000);
+// ISA extensions shared by all harts, reported to user by AT_HWCAP.
+register_mut_const!(pub HWCAP, usize, 0);
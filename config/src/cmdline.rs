@@ -0,0 +1,85 @@
+//! Kernel command line, which is the bootargs of the chosen node in the device
+//! tree, e.g. `console=ttyS0 log=info init=/bin/sh`, so that the same kernel
+//! can be run differently without being rebuilt.
+//!
+//! Each argument is a `key=value` pair separated by whitespace, or a key
+//! alone whose value is empty. The last one wins if a key is repeated.
+
+use core::ptr::addr_of;
+
+/// Max length of the command line, as Linux on RISC-V.
+pub const COMMAND_LINE_SIZE: usize = 1024;
+
+/// Keys consumed by the kernel, besides `log.<module>`.
+///
+/// - `console=ttyS<N>`: the serial port of the console.
+/// - `strace=<level>`: the verbosity of the syscall trace.
+/// - `log=<level>` and `log.<module>=<level>`: the level of logs printed.
+/// - `ip=<addr>` and `gw=<addr>`: the address and gateway of eth0.
+/// - `root=<dev>`: the block device of the root, e.g. `/dev/vdb`.
+/// - `rootfstype=<fs>`: the filesystem of the root, which is probed if absent
+///   or `auto`.
+/// - `init=<path>`: the first user program.
+const KNOWN_KEYS: &[&str] = &[
+    "console",
+    "strace",
+    "log",
+    "ip",
+    "gw",
+    "root",
+    "rootfstype",
+    "init",
+];
+
+static mut CMDLINE: [u8; COMMAND_LINE_SIZE] = [0; COMMAND_LINE_SIZE];
+static mut CMDLINE_LEN: usize = 0;
+
+/// Save `bootargs` as the command line, which is truncated to
+/// `COMMAND_LINE_SIZE` bytes. It should be called once on boot before any
+/// other hart is started.
+pub fn set_cmdline(bootargs: &str) {
+    let mut len = bootargs.len().min(COMMAND_LINE_SIZE);
+    while !bootargs.is_char_boundary(len) {
+        len -= 1;
+    }
+    unsafe {
+        CMDLINE[..len].copy_from_slice(&bootargs.as_bytes()[..len]);
+        CMDLINE_LEN = len;
+    }
+}
+
+pub fn cmdline() -> Cmdline {
+    let cmdline = unsafe { &(*addr_of!(CMDLINE))[..CMDLINE_LEN] };
+    Cmdline(core::str::from_utf8(cmdline).unwrap())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cmdline(&'static str);
+
+impl Cmdline {
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+
+    /// Arguments as `(key, value)`.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &'static str)> {
+        self.0
+            .split_whitespace()
+            .map(|arg| arg.split_once('=').unwrap_or((arg, "")))
+    }
+
+    /// Value of the last argument of `key`.
+    pub fn get(&self, key: &str) -> Option<&'static str> {
+        self.iter()
+            .filter(|&(k, _)| k == key)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    /// Keys not consumed by the kernel, which are ignored.
+    pub fn unknown_keys(&self) -> impl Iterator<Item = &'static str> {
+        self.iter()
+            .map(|(key, _)| key)
+            .filter(|key| !KNOWN_KEYS.contains(key) && !key.starts_with("log."))
+    }
+}
@@ -3,4 +3,16 @@ use super::mm::PAGE_SIZE;
 /// Max file descriptors counts
 pub const MAX_FDS: usize = 1024;
 
+/// Max length of a path passed to syscalls, including the null
+pub const PATH_MAX: usize = 4096;
+
 pub const PIPE_BUF_LEN: usize = 16 * PAGE_SIZE;
+
+/// Max number of dentries kept in the LRU list of the dentry cache
+pub const DCACHE_CAPACITY: usize = 4096;
+
+/// Pages read ahead when a file is first read sequentially
+pub const READ_AHEAD_INIT_PAGES: usize = 4;
+
+/// Max pages read ahead, which is 128 KiB
+pub const READ_AHEAD_MAX_PAGES: usize = 32;
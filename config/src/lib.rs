@@ -4,9 +4,12 @@
 #![feature(effects)]
 
 pub mod board;
+mod cmdline;
 pub mod fs;
 pub mod mm;
 pub mod process;
 pub mod signal;
 pub mod time;
 mod utils;
+
+pub use cmdline::{cmdline, set_cmdline, Cmdline, COMMAND_LINE_SIZE};
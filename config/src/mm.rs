@@ -11,8 +11,10 @@ pub const RAM_START: usize = 0x8000_0000;
 pub const HIGH_HALF: usize = 0xffff_ffc0_0000_0000;
 pub const VIRT_START: usize = HIGH_HALF + RAM_START;
 
+/// Upper bound of the RAM size. The RAM actually present is found in the
+/// device tree at boot.
 #[cfg(not(feature = "vf2"))]
-pub const RAM_SIZE: usize = 128 * 1024 * 1024;
+pub const RAM_SIZE: usize = 4 * 1024 * 1024 * 1024;
 #[cfg(feature = "vf2")]
 pub const RAM_SIZE: usize = 0x100000000 + RAM_START - 0x4000_0000;
 
@@ -22,7 +24,14 @@ pub const KERNEL_OFFSET: usize = 0x20_0000;
 pub const KERNEL_START_PHYS: usize = RAM_START + KERNEL_OFFSET;
 pub const KERNEL_START: usize = VIRT_START + KERNEL_OFFSET;
 
+/// Size of the kernel stack of each hart, including the guard page at its
+/// bottom.
 pub const KERNEL_STACK_SIZE: usize = 64 * 1024;
+/// Size of the page at the bottom of each kernel stack that is left unmapped,
+/// so that an overflow faults instead of corrupting the memory below.
+pub const KERNEL_STACK_GUARD_SIZE: usize = PAGE_SIZE;
+/// Size of the stack of each hart to report a kernel stack overflow on.
+pub const KERNEL_EMERGENCY_STACK_SIZE: usize = 16 * 1024;
 
 #[cfg(not(feature = "vf2"))]
 pub const KERNEL_HEAP_SIZE: usize = 64 * 1024 * 1024;
@@ -42,6 +51,10 @@ pub const PAGE_SIZE_BITS: usize = 12;
 pub const PTE_SIZE: usize = 8;
 pub const PTES_PER_PAGE: usize = PAGE_SIZE / PTE_SIZE;
 
+/// Pages mapped by a huge page, i.e. a leaf pte of the middle level.
+pub const HUGE_PAGE_PAGES: usize = PTES_PER_PAGE;
+pub const HUGE_PAGE_SIZE: usize = HUGE_PAGE_PAGES * PAGE_SIZE;
+
 /// 3 level for sv39 page table
 pub const PAGE_TABLE_LEVEL_NUM: usize = 3;
 
@@ -74,6 +87,10 @@ pub const U_SEG_FILE_END: usize = 0x0000_0006_0000_0000;
 pub const U_SEG_SHARE_BEG: usize = 0x0000_0006_0000_0000;
 pub const U_SEG_SHARE_END: usize = 0x0000_0008_0000_0000;
 
+/// End of user space, which is the lower half of sv39. User pointers passed to
+/// syscalls must lie below it.
+pub const U_SEG_END: usize = 0x0000_0040_0000_0000;
+
 // =========== Kernel segments ===========
 pub const K_SEG_BEG: usize = 0xffff_ffc0_0000_0000;
 
@@ -98,6 +115,11 @@ pub const K_SEG_DTB_BEG: usize = K_SEG_DTB_END - MAX_DTB_SIZE;
 pub const K_SEG_DTB_END: usize = 0xffff_ffff_f000_0000;
 pub const MAX_DTB_SIZE: usize = PAGE_SIZE * PAGE_SIZE;
 
+// vDSO fixed mapping, the vvar page followed by the vdso image, which is
+// readable by user
+pub const K_SEG_VDSO_BEG: usize = K_SEG_DTB_END;
+pub const K_SEG_VDSO_END: usize = K_SEG_VDSO_BEG + 2 * PAGE_SIZE;
+
 pub fn align_offset_to_page(offset: usize) -> (usize, usize) {
     let offset_aligned = offset & !PAGE_MASK;
     let offset_in_page = offset - offset_aligned;
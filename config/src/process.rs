@@ -1,10 +1,22 @@
 use crate::mm::PAGE_SIZE;
 
-/// Syscall string arg's max length
-pub const SYSCALL_STR_ARG_MAX_LEN: usize = 4096;
+/// Max length of an argument or environment string of execve, including the
+/// null
+pub const MAX_ARG_STRLEN: usize = 32 * PAGE_SIZE;
+
+/// Max total size of the arguments and environment of execve, including their
+/// pointers on the stack
+pub const ARG_MAX: usize = 128 * 1024;
 
 /// Init proc's pid
 pub const INIT_PROC_PID: usize = 1;
 
+/// Pids and tids are below it, the same as the default of Linux
+pub const PID_MAX: usize = 32768;
+
 pub const USER_STACK_SIZE: usize = 8 * 1024 * 1024;
 pub const USER_STACK_PRE_ALLOC_SIZE: usize = 4 * PAGE_SIZE;
+
+/// Default max bytes of memory a process can lock, i.e. `RLIMIT_MEMLOCK`, the
+/// same as Linux
+pub const USER_MEMLOCK_LIMIT: usize = 8 * 1024 * 1024;
@@ -2,5 +2,9 @@ use core::time::Duration;
 
 pub const INTERRUPTS_PER_SECOND: usize = 100;
 pub const NANOSECONDS_PER_SECOND: usize = 1_000_000_000;
-pub const TIME_SLICE_DUATION: Duration =
-    Duration::new(0, (NANOSECONDS_PER_SECOND / INTERRUPTS_PER_SECOND) as u32);
+/// Number of timer interrupts a user task can run before it is preempted.
+pub const TIME_SLICE_TICKS: usize = 1;
+pub const TIME_SLICE_DUATION: Duration = Duration::new(
+    0,
+    (NANOSECONDS_PER_SECOND / INTERRUPTS_PER_SECOND * TIME_SLICE_TICKS) as u32,
+);
@@ -8,11 +8,33 @@ extern crate alloc;
 use alloc::{boxed::Box, sync::Arc, task::Wake, vec::Vec};
 use core::{
     future::Future,
-    ops::{Deref, DerefMut},
+    iter::Chain,
+    ops::{Deref, DerefMut, Range},
     pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
     task::{ready, Context, Poll, Waker},
 };
 
+use crate_interface::call_interface;
+
+#[crate_interface::def_interface]
+pub trait BlockOnIf {
+    /// Id of the local hart.
+    fn hart_id() -> usize;
+    /// Run one task of the executor on the local hart, return false if there
+    /// is no task to run.
+    fn run_one() -> bool;
+    /// Wait for interrupts until something happens.
+    fn idle();
+    /// Wake up the hart waiting in `block_on`.
+    fn kick(hart_id: usize);
+    /// Whether the local hart is handling an interrupt.
+    fn in_irq() -> bool;
+    /// Whether the local hart may run other tasks or wait for interrupts,
+    /// i.e. interrupts are enabled and no spin lock is held.
+    fn can_switch() -> bool;
+}
+
 /// Get the waker of the current future.
 #[inline(always)]
 pub async fn get_waker() -> Waker {
@@ -56,31 +78,66 @@ impl<T: DerefMut> DerefMut for SendWrapper<T> {
     }
 }
 
-/// A waker that wakes up the current thread when called.
-struct BlockWaker;
+/// A waker that records wake ups of the future blocked on.
+struct BlockWaker {
+    hart_id: usize,
+    woken: AtomicBool,
+}
 
 impl Wake for BlockWaker {
     fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
         log::trace!("block waker wakes");
+        self.woken.store(true, Ordering::Release);
+        if call_interface!(BlockOnIf::hart_id()) != self.hart_id {
+            call_interface!(BlockOnIf::kick(self.hart_id));
+        }
     }
 }
 
 /// Run a future to completion on the current thread.
-/// Note that since this function is used in kernel mode,
-/// we won't switch thread when the inner future pending.
-/// Instead, we just poll the inner future again and again.
+///
+/// While the inner future is pending, tasks of the executor are run in place,
+/// since the future may wait for them to make progress, and the hart waits for
+/// interrupts if there is nothing to run. The future is polled again only
+/// after it is woken up.
+///
+/// If the caller holds a spin lock or has interrupts disabled, a task run in
+/// place may take the same lock again, so the future is polled in a busy loop
+/// instead.
+///
+/// Must not be called in interrupt context, where tasks can not be run.
 pub fn block_on<T>(fut: impl Future<Output = T>) -> T {
+    debug_assert!(
+        !call_interface!(BlockOnIf::in_irq()),
+        "block_on in interrupt context"
+    );
     // Pin the future so it can be polled.
     let mut fut = Box::pin(fut);
 
-    let waker = Arc::new(BlockWaker).into();
+    let block_waker = Arc::new(BlockWaker {
+        hart_id: call_interface!(BlockOnIf::hart_id()),
+        woken: AtomicBool::new(false),
+    });
+    let waker = block_waker.clone().into();
     let mut cx = Context::from_waker(&waker);
 
     // Run the future to completion.
     loop {
-        match fut.as_mut().poll(&mut cx) {
-            Poll::Ready(res) => return res,
-            Poll::Pending => continue,
+        if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+            return res;
+        }
+        if !call_interface!(BlockOnIf::can_switch()) {
+            core::hint::spin_loop();
+            continue;
+        }
+        while !block_waker.woken.swap(false, Ordering::Acquire) {
+            if !call_interface!(BlockOnIf::run_one()) {
+                call_interface!(BlockOnIf::idle());
+            }
         }
     }
 }
@@ -91,7 +148,8 @@ pub enum SelectOutput<T1, T2> {
 }
 
 /// Select two futures at a time.
-/// Note that future1 has a higher level than future2
+/// Note that future1 has a higher level than future2, unless constructed by
+/// `new_fair`.
 pub struct Select2Futures<T1, T2, F1, F2>
 where
     F1: Future<Output = T1>,
@@ -99,6 +157,10 @@ where
 {
     future1: F1,
     future2: F2,
+    /// Poll the two futures first by turns.
+    fair: bool,
+    /// Poll future2 first in the next poll, only used when `fair` is set.
+    future2_first: bool,
 }
 
 impl<T1, T2, F1, F2> Select2Futures<T1, T2, F1, F2>
@@ -107,7 +169,23 @@ where
     F2: Future<Output = T2>,
 {
     pub fn new(future1: F1, future2: F2) -> Self {
-        Self { future1, future2 }
+        Self {
+            future1,
+            future2,
+            fair: false,
+            future2_first: false,
+        }
+    }
+
+    /// Select two futures with alternate priority, so that neither of them will
+    /// be starved by the other one that is frequently ready.
+    pub fn new_fair(future1: F1, future2: F2) -> Self {
+        Self {
+            future1,
+            future2,
+            fair: true,
+            future2_first: false,
+        }
     }
 }
 
@@ -119,21 +197,39 @@ where
     type Output = SelectOutput<T1, T2>;
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = unsafe { self.get_unchecked_mut() };
+        let future2_first = this.future2_first;
+        if this.fair {
+            this.future2_first = !this.future2_first;
+        }
+        if future2_first {
+            let ret = unsafe { Pin::new_unchecked(&mut this.future2).poll(cx) };
+            if ret.is_ready() {
+                return Poll::Ready(SelectOutput::Output2(ready!(ret)));
+            }
+        }
         let ret = unsafe { Pin::new_unchecked(&mut this.future1).poll(cx) };
         if ret.is_ready() {
             return Poll::Ready(SelectOutput::Output1(ready!(ret)));
         }
-        let ret = unsafe { Pin::new_unchecked(&mut this.future2).poll(cx) };
-        if ret.is_ready() {
-            return Poll::Ready(SelectOutput::Output2(ready!(ret)));
+        if !future2_first {
+            let ret = unsafe { Pin::new_unchecked(&mut this.future2).poll(cx) };
+            if ret.is_ready() {
+                return Poll::Ready(SelectOutput::Output2(ready!(ret)));
+            }
         }
         Poll::Pending
     }
 }
 
+/// Wait for any of the futures to be ready.
+///
+/// Futures are polled in a rotating order starting from a different index on
+/// each poll, so that a frequently ready future will not starve the others.
 pub struct AnyFuture<'a, T> {
     futures: Vec<Async<'a, T>>,
     has_returned: bool,
+    /// Index of the future polled first in the next poll.
+    start: usize,
 }
 
 impl<'a, T> AnyFuture<'a, T> {
@@ -141,6 +237,7 @@ impl<'a, T> AnyFuture<'a, T> {
         Self {
             futures: Vec::new(),
             has_returned: false,
+            start: 0,
         }
     }
     pub fn push(&mut self, future: Async<'a, T>) {
@@ -152,21 +249,58 @@ impl<'a, T> AnyFuture<'a, T> {
         Self {
             futures,
             has_returned: false,
+            start: 0,
         }
     }
+
+    /// Drop all futures so that the combinator can be reused with new ones
+    /// pushed, without reallocating.
+    pub fn reset(&mut self) {
+        self.futures.clear();
+        self.has_returned = false;
+        self.start = 0;
+    }
+
+    /// Indexes of futures in the order to be polled, and rotate the order.
+    fn poll_order(&mut self) -> Chain<Range<usize>, Range<usize>> {
+        let (start, len) = (self.start, self.futures.len());
+        if len > 0 {
+            self.start = (start + 1) % len;
+        }
+        (start..len).chain(0..start)
+    }
+
+    /// Poll every future in one pass, and return all ready outputs with their
+    /// indexes if there is any.
+    pub fn poll_all(&mut self, cx: &mut Context<'_>) -> Poll<Vec<(usize, T)>> {
+        if self.has_returned {
+            return Poll::Pending;
+        }
+        let mut ready = Vec::new();
+        for i in self.poll_order() {
+            if let Poll::Ready(ret) = self.futures[i].as_mut().poll(cx) {
+                ready.push((i, ret));
+            }
+        }
+        if ready.is_empty() {
+            return Poll::Pending;
+        }
+        self.has_returned = true;
+        Poll::Ready(ready)
+    }
 }
 
 impl<T> Future for AnyFuture<'_, T> {
     type Output = (usize, T);
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
+        let this = self.get_mut();
         if this.has_returned {
             return Poll::Pending;
         }
 
-        for (i, future) in this.futures.iter_mut().enumerate() {
-            let result = unsafe { Pin::new_unchecked(future).poll(cx) };
+        for i in this.poll_order() {
+            let result = this.futures[i].as_mut().poll(cx);
             if let Poll::Ready(ret) = result {
                 this.has_returned = true;
                 return Poll::Ready((i, ret));
@@ -0,0 +1,125 @@
+//! Generate the symbol table embedded in the kernel with the `symbols`
+//! feature.
+//!
+//! The symbols are read from the kernel elf linked before, given by the
+//! `KSYM_ELF` environment variable, so the kernel is linked twice: first with
+//! an empty table, then with the table of the first link. The table is placed
+//! after the code, so the addresses of the functions stay the same.
+//!
+//! The table is laid out in little endian as:
+//!
+//! ```text
+//! count: u64
+//! entries: [(addr: u64, name_offset: u32, name_len: u32); count], sorted by addr
+//! names: [u8]
+//! ```
+
+use std::{env, fs, path::PathBuf};
+
+use xmas_elf::{
+    sections::SectionData,
+    symbol_table::{Entry, Type},
+    ElfFile,
+};
+
+fn main() {
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("ksym.bin");
+    println!("cargo:rerun-if-env-changed=KSYM_ELF");
+    let mut symbols = Vec::new();
+    if env::var_os("CARGO_FEATURE_SYMBOLS").is_some() {
+        if let Ok(path) = env::var("KSYM_ELF") {
+            println!("cargo:rerun-if-changed={path}");
+            let data = fs::read(&path).unwrap_or_else(|e| panic!("can not read {path}: {e}"));
+            symbols = read_functions(&data);
+        }
+    }
+    fs::write(out, serialize(symbols)).unwrap();
+}
+
+/// Read the functions from the symbol table of the elf.
+fn read_functions(data: &[u8]) -> Vec<(u64, String)> {
+    let elf = ElfFile::new(data).expect("invalid elf");
+    let mut symbols = Vec::new();
+    for section in elf.section_iter() {
+        let Ok(SectionData::SymbolTable64(entries)) = section.get_data(&elf) else {
+            continue;
+        };
+        for entry in entries {
+            if entry.get_type() != Ok(Type::Func) || entry.value() == 0 {
+                continue;
+            }
+            if let Ok(name) = entry.get_name(&elf) {
+                symbols.push((entry.value(), demangle(name)));
+            }
+        }
+    }
+    symbols.sort();
+    symbols.dedup_by_key(|(addr, _)| *addr);
+    symbols
+}
+
+fn serialize(symbols: Vec<(u64, String)>) -> Vec<u8> {
+    let mut table = Vec::new();
+    let mut names = Vec::new();
+    table.extend((symbols.len() as u64).to_le_bytes());
+    for (addr, name) in symbols {
+        table.extend(addr.to_le_bytes());
+        table.extend((names.len() as u32).to_le_bytes());
+        table.extend((name.len() as u32).to_le_bytes());
+        names.extend(name.as_bytes());
+    }
+    table.extend(names);
+    table
+}
+
+/// Demangle a symbol in the legacy Rust mangling, e.g.
+/// `_ZN4core3fmt5write17h0123456789abcdefE` to `core::fmt::write`, leaving
+/// other symbols as they are.
+fn demangle(symbol: &str) -> String {
+    let Some(mut rest) = symbol.strip_prefix("_ZN").and_then(|s| s.strip_suffix('E')) else {
+        return symbol.to_string();
+    };
+    let mut path = Vec::new();
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let Some(len) = rest[..digits].parse::<usize>().ok() else {
+            return symbol.to_string();
+        };
+        let Some(component) = rest.get(digits..digits + len) else {
+            return symbol.to_string();
+        };
+        // components beginning with an escape are prefixed by `_`
+        if component.starts_with("_$") {
+            path.push(&component[1..]);
+        } else {
+            path.push(component);
+        }
+        rest = &rest[digits + len..];
+    }
+    // drop the hash
+    if path
+        .last()
+        .is_some_and(|c| c.len() == 17 && c.starts_with('h'))
+    {
+        path.pop();
+    }
+    let path = path.join("::");
+    [
+        ("$LT$", "<"),
+        ("$GT$", ">"),
+        ("$RF$", "&"),
+        ("$BP$", "*"),
+        ("$C$", ","),
+        ("$SP$", "@"),
+        ("$u20$", " "),
+        ("$u27$", "'"),
+        ("$u5b$", "["),
+        ("$u5d$", "]"),
+        ("$u7b$", "{"),
+        ("$u7d$", "}"),
+        ("$u7e$", "~"),
+        ("..", "::"),
+    ]
+    .iter()
+    .fold(path, |path, (from, to)| path.replace(from, to))
+}
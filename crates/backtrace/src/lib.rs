@@ -1,11 +1,24 @@
 #![no_std]
 #![no_main]
 
+mod symbols;
+
 use core::mem::size_of;
 
 use sbi_print::sbi_println;
+pub use symbols::{resolve, resolve_offset};
 
 pub fn backtrace() {
+    walk(|pc| match resolve_offset(pc) {
+        Some((name, offset)) => sbi_println!("{:#018x} {}+{:#x}", pc, name, offset),
+        None => sbi_println!("{:#018x}", pc),
+    });
+}
+
+/// Call `f` with the pc of each frame from the caller outwards, which is
+/// inlined so that the first frame is the caller of the function using it.
+#[inline(always)]
+fn walk(mut f: impl FnMut(usize)) {
     extern "C" {
         fn _stext();
         fn _etext();
@@ -15,9 +28,33 @@ pub fn backtrace() {
         let mut current_fp = arch::register::fp();
 
         while current_pc >= _stext as usize && current_pc <= _etext as usize && current_fp != 0 {
-            sbi_println!("{:#018x}", current_pc - size_of::<usize>());
+            f(current_pc - size_of::<usize>());
             current_fp = *(current_fp as *const usize).offset(-2);
             current_pc = *(current_fp as *const usize).offset(-1);
         }
     }
 }
+
+/// Check that functions and the frames walked from them are resolved to their
+/// names, when the symbol table is embedded.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    #[inline(never)]
+    fn first_frame() -> Option<usize> {
+        let mut first = None;
+        walk(|pc| {
+            first.get_or_insert(pc);
+        });
+        first
+    }
+
+    if !cfg!(feature = "symbols") {
+        sbi_println!("[backtrace] selftest skipped: no symbol table");
+        return;
+    }
+    let addr = selftest as usize;
+    assert_eq!(resolve(addr), Some("backtrace::selftest"));
+    assert_eq!(resolve_offset(addr + 4), Some(("backtrace::selftest", 4)));
+    let pc = first_frame().expect("no frame is walked");
+    assert_eq!(resolve(pc), Some("backtrace::selftest"));
+}
@@ -0,0 +1,70 @@
+//! Symbol table of the kernel, generated by `build.rs` with the `symbols`
+//! feature, to symbolize the addresses in backtraces.
+
+/// The table, which is empty without the `symbols` feature.
+#[cfg(feature = "symbols")]
+#[used]
+#[link_section = ".ksym"]
+static KSYM: [u8; include_bytes!(concat!(env!("OUT_DIR"), "/ksym.bin")).len()] =
+    *include_bytes!(concat!(env!("OUT_DIR"), "/ksym.bin"));
+
+/// Size of an entry in the table: address, offset and length of the name.
+const ENTRY_SIZE: usize = 16;
+
+/// The table placed between `_sksym` and `_eksym` by the linker script.
+fn table() -> &'static [u8] {
+    #[cfg(feature = "symbols")]
+    {
+        extern "C" {
+            fn _sksym();
+            fn _eksym();
+        }
+        let start = _sksym as usize;
+        let len = _eksym as usize - start;
+        unsafe { core::slice::from_raw_parts(start as *const u8, len) }
+    }
+    #[cfg(not(feature = "symbols"))]
+    &[]
+}
+
+fn read_u64(table: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(table[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_u32(table: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes(table[offset..offset + 4].try_into().unwrap()) as usize
+}
+
+/// Find the function containing `pc`, returning its name and the offset of
+/// `pc` in it.
+pub fn resolve_offset(pc: usize) -> Option<(&'static str, usize)> {
+    let table = table();
+    if table.len() < 8 {
+        return None;
+    }
+    let count = read_u64(table, 0) as usize;
+    let entries = 8;
+    let names = entries + count * ENTRY_SIZE;
+    let addr = |i: usize| read_u64(table, entries + i * ENTRY_SIZE) as usize;
+    // the first function starting after `pc`
+    let (mut lo, mut hi) = (0, count);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if addr(mid) <= pc {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    let i = lo.checked_sub(1)?;
+    let entry = entries + i * ENTRY_SIZE;
+    let name_offset = names + read_u32(table, entry + 8);
+    let name_len = read_u32(table, entry + 12);
+    let name = core::str::from_utf8(table.get(name_offset..name_offset + name_len)?).ok()?;
+    Some((name, pc - addr(i)))
+}
+
+/// Find the name of the function containing `pc`.
+pub fn resolve(pc: usize) -> Option<&'static str> {
+    resolve_offset(pc).map(|(name, _)| name)
+}
@@ -1,20 +1,179 @@
-//! Any one import this crate should import `paste` crate as well.
+//! Macros generating accessors of locked fields, to be called in an `impl`
+//! block.
+//!
+//! Each field is given as `name: Type`, where `Type` is the type locked, and
+//! may be preceded by `#[rename(method)]` to name the methods after `method`
+//! instead of the field. `paste` is re-exported here, so the importers need
+//! not depend on it. Fields in other forms are rejected with an error telling
+//! the expected one:
+//!
+//! ```compile_fail
+//! use macro_utils::with_methods;
+//! use spin::Mutex;
+//!
+//! struct Device {
+//!     inner: Mutex<usize>,
+//! }
+//!
+//! impl Device {
+//!     // the type locked is missing
+//!     with_methods!(inner);
+//! }
+//! ```
 
 #![no_std]
 #![no_main]
 
+#[doc(hidden)]
+pub use paste;
+
+/// Generate `with_x` and `with_mut_x` for each field `x` of a mutex, which
+/// run a closure with the field locked by `lock()`.
+///
+/// ```
+/// use macro_utils::with_methods;
+/// use spin::Mutex;
+///
+/// struct Device {
+///     inner: Mutex<usize>,
+///     stats_inner: Mutex<(usize, usize)>,
+/// }
+///
+/// impl Device {
+///     with_methods!(inner: usize, #[rename(stats)] stats_inner: (usize, usize));
+/// }
+///
+/// let dev = Device { inner: Mutex::new(0), stats_inner: Mutex::new((0, 0)) };
+/// dev.with_mut_inner(|inner| *inner += 1);
+/// dev.with_mut_stats(|stats| stats.0 += 2);
+/// assert_eq!(dev.with_inner(|inner| *inner) + dev.with_stats(|stats| stats.0), 3);
+/// ```
 #[macro_export]
 macro_rules! with_methods {
-    ($($name:ident : $ty:ty),+) => {
-        paste::paste! {
-            $(
-                pub fn [<with_ $name>]<T>(&self, f: impl FnOnce(&$ty) -> T) -> T {
-                    f(&self.$name.lock())
-                }
-                pub fn [<with_mut_ $name>]<T>(&self, f: impl FnOnce(&mut $ty) -> T) -> T {
-                    f(&mut self.$name.lock())
-                }
-            )+
+    ($($(#[rename($method:ident)])? $name:ident : $ty:ty),+ $(,)?) => {
+        $(
+            $crate::__with_methods!(lock, lock, $name, $ty $(, $method)?);
+        )+
+    };
+    ($($fields:tt)*) => {
+        $crate::__invalid_fields!("with_methods");
+    };
+}
+
+/// Generate `with_x` and `with_mut_x` for each field `x` of a read-write
+/// lock, which run a closure with the field locked by `read()` and `write()`
+/// respectively.
+///
+/// ```
+/// use macro_utils::with_rw_methods;
+/// use spin::RwLock;
+///
+/// struct Table {
+///     entries: RwLock<[u8; 4]>,
+/// }
+///
+/// impl Table {
+///     with_rw_methods!(entries: [u8; 4]);
+/// }
+///
+/// let table = Table { entries: RwLock::new([0; 4]) };
+/// table.with_mut_entries(|entries| entries[1] = 7);
+/// assert_eq!(table.with_entries(|entries| entries[1]), 7);
+/// ```
+#[macro_export]
+macro_rules! with_rw_methods {
+    ($($(#[rename($method:ident)])? $name:ident : $ty:ty),+ $(,)?) => {
+        $(
+            $crate::__with_methods!(read, write, $name, $ty $(, $method)?);
+        )+
+    };
+    ($($fields:tt)*) => {
+        $crate::__invalid_fields!("with_rw_methods");
+    };
+}
+
+/// Generate `try_with_x` and `try_with_mut_x` for each field `x` of a mutex,
+/// which run a closure with the field locked by `try_lock()`, and return
+/// `None` without running it if the field is locked already.
+///
+/// ```
+/// use macro_utils::try_with_methods;
+/// use spin::Mutex;
+///
+/// struct Counter {
+///     count: Mutex<usize>,
+/// }
+///
+/// impl Counter {
+///     try_with_methods!(count: usize);
+/// }
+///
+/// let counter = Counter { count: Mutex::new(1) };
+/// assert_eq!(counter.try_with_mut_count(|count| { *count += 1; *count }), Some(2));
+/// let _guard = counter.count.lock();
+/// assert_eq!(counter.try_with_count(|count| *count), None);
+/// ```
+#[macro_export]
+macro_rules! try_with_methods {
+    ($($(#[rename($method:ident)])? $name:ident : $ty:ty),+ $(,)?) => {
+        $(
+            $crate::__try_with_methods!($name, $ty $(, $method)?);
+        )+
+    };
+    ($($fields:tt)*) => {
+        $crate::__invalid_fields!("try_with_methods");
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __invalid_fields {
+    ($macro:literal) => {
+        compile_error!(concat!(
+            "`",
+            $macro,
+            "!` expects fields as `name: Type` or `#[rename(method)] name: Type`, ",
+            "where the method names are joined by `paste`, which is re-exported by ",
+            "`macro_utils` and needs no dependency"
+        ));
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __with_methods {
+    ($read:ident, $write:ident, $name:ident, $ty:ty) => {
+        $crate::__with_methods!($read, $write, $name, $ty, $name);
+    };
+    ($read:ident, $write:ident, $name:ident, $ty:ty, $method:ident) => {
+        $crate::paste::paste! {
+            pub fn [<with_ $method>]<T>(&self, f: impl FnOnce(&$ty) -> T) -> T {
+                f(&self.$name.$read())
+            }
+            pub fn [<with_mut_ $method>]<T>(&self, f: impl FnOnce(&mut $ty) -> T) -> T {
+                f(&mut self.$name.$write())
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __try_with_methods {
+    ($name:ident, $ty:ty) => {
+        $crate::__try_with_methods!($name, $ty, $name);
+    };
+    ($name:ident, $ty:ty, $method:ident) => {
+        $crate::paste::paste! {
+            pub fn [<try_with_ $method>]<T>(&self, f: impl FnOnce(&$ty) -> T) -> Option<T> {
+                self.$name.try_lock().map(|guard| f(&guard))
+            }
+            pub fn [<try_with_mut_ $method>]<T>(
+                &self,
+                f: impl FnOnce(&mut $ty) -> T,
+            ) -> Option<T> {
+                self.$name.try_lock().map(|mut guard| f(&mut guard))
+            }
         }
     };
 }
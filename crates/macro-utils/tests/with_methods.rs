@@ -0,0 +1,69 @@
+use macro_utils::{try_with_methods, with_methods, with_rw_methods};
+use spin::{Mutex, RwLock};
+
+struct Stats {
+    hits: Mutex<usize>,
+    raw_misses: Mutex<usize>,
+    table: RwLock<[u8; 4]>,
+    raw_names: RwLock<Vec<&'static str>>,
+    pending: Mutex<Vec<usize>>,
+    raw_flags: Mutex<u32>,
+}
+
+impl Stats {
+    with_methods!(hits: usize, #[rename(misses)] raw_misses: usize,);
+    with_rw_methods!(table: [u8; 4], #[rename(names)] raw_names: Vec<&'static str>);
+    try_with_methods!(pending: Vec<usize>, #[rename(flags)] raw_flags: u32);
+
+    fn new() -> Self {
+        Self {
+            hits: Mutex::new(0),
+            raw_misses: Mutex::new(0),
+            table: RwLock::new([0; 4]),
+            raw_names: RwLock::new(Vec::new()),
+            pending: Mutex::new(Vec::new()),
+            raw_flags: Mutex::new(0),
+        }
+    }
+}
+
+#[test]
+fn with_methods() {
+    let stats = Stats::new();
+    stats.with_mut_hits(|hits| *hits += 2);
+    stats.with_mut_misses(|misses| *misses += 1);
+    assert_eq!(stats.with_hits(|hits| *hits), 2);
+    assert_eq!(stats.with_misses(|misses| *misses), 1);
+}
+
+#[test]
+fn with_rw_methods() {
+    let stats = Stats::new();
+    stats.with_mut_table(|table| table[3] = 9);
+    stats.with_mut_names(|names| names.push("ext4"));
+    // readers share the lock
+    let _reader = stats.table.read();
+    assert_eq!(stats.with_table(|table| table[3]), 9);
+    assert_eq!(stats.with_names(|names| names.clone()), ["ext4"]);
+}
+
+#[test]
+fn try_with_methods() {
+    let stats = Stats::new();
+    assert_eq!(
+        stats.try_with_mut_pending(|pending| pending.push(1)),
+        Some(())
+    );
+    assert_eq!(stats.try_with_pending(|pending| pending.len()), Some(1));
+    assert_eq!(stats.try_with_mut_flags(|flags| *flags |= 4), Some(()));
+
+    let guard = stats.pending.lock();
+    assert_eq!(stats.try_with_pending(|pending| pending.len()), None);
+    assert_eq!(stats.try_with_mut_pending(|_| unreachable!()), None::<()>);
+    drop(guard);
+
+    let guard = stats.raw_flags.lock();
+    assert_eq!(stats.try_with_flags(|flags| *flags), None);
+    drop(guard);
+    assert_eq!(stats.try_with_flags(|flags| *flags), Some(4));
+}
@@ -1,50 +1,124 @@
 #![no_std]
 #![no_main]
-#![feature(const_binary_heap_constructor)]
 
 extern crate alloc;
-use alloc::collections::BinaryHeap;
-use core::cmp::Reverse;
+use alloc::collections::BTreeSet;
 
 /// Used for allocating pid & tid
-// TODO: add maximium resource limit
+///
+/// The lowest free id is always allocated first, so ids are reused as soon as
+/// they are recycled, as pids are.
+///
+/// ```
+/// use recycle_allocator::RecycleAllocator;
+///
+/// let mut ids = RecycleAllocator::with_max(1, 4);
+/// assert_eq!((ids.alloc(), ids.alloc(), ids.alloc()), (1, 2, 3));
+/// assert_eq!(ids.try_alloc(), None);
+/// ids.dealloc(3);
+/// ids.dealloc(1);
+/// assert_eq!((ids.alloc(), ids.alloc()), (1, 3));
+/// assert_eq!(ids.try_alloc(), None);
+/// ```
 pub struct RecycleAllocator {
     /// Current max id allocated
     current: usize,
-    /// Hold deallocated id, will be recycled first when alloc happen
-    recycled: BinaryHeap<Reverse<usize>>,
+    /// Ids at or above it are never allocated
+    max: usize,
+    /// Hold deallocated id below `current`, will be recycled first when alloc
+    /// happen
+    recycled: BTreeSet<usize>,
 }
 
 impl RecycleAllocator {
     /// Create an empty `RecycleAllocator`
     pub const fn new(init_val: usize) -> Self {
+        Self::with_max(init_val, usize::MAX)
+    }
+
+    /// Create an empty `RecycleAllocator` allocating ids in `init_val..max`
+    pub const fn with_max(init_val: usize, max: usize) -> Self {
         RecycleAllocator {
             current: init_val,
-            recycled: BinaryHeap::new(),
+            max,
+            recycled: BTreeSet::new(),
+        }
+    }
+
+    /// Allocate an id, or return `None` if all ids below the max are
+    /// allocated
+    pub fn try_alloc(&mut self) -> Option<usize> {
+        if let Some(id) = self.recycled.pop_first() {
+            Some(id)
+        } else if self.current < self.max {
+            self.current += 1;
+            Some(self.current - 1)
+        } else {
+            None
         }
     }
 
     /// Allocate an id
+    ///
+    /// # Panics
+    ///
+    /// Panics if all ids below the max are allocated, see `try_alloc`.
     pub fn alloc(&mut self) -> usize {
-        if let Some(Reverse(id)) = self.recycled.pop() {
-            id
-        } else {
-            self.current += 1;
-            self.current - 1
+        self.try_alloc()
+            .unwrap_or_else(|| panic!("ids below {} are exhausted", self.max))
+    }
+
+    /// Allocate `n` contiguous ids and return the first one, or `None` if
+    /// there are not so many ids left above the current max
+    ///
+    /// ```
+    /// use recycle_allocator::RecycleAllocator;
+    ///
+    /// let mut ids = RecycleAllocator::with_max(0, 16);
+    /// let first = ids.alloc();
+    /// assert_eq!(ids.alloc_range(8), Some(1));
+    /// assert_eq!(ids.alloc_range(8), None);
+    /// ids.dealloc_range(1, 8);
+    /// ids.dealloc(first);
+    /// assert_eq!(ids.alloc_range(16), Some(0));
+    /// ```
+    pub fn alloc_range(&mut self, n: usize) -> Option<usize> {
+        if n > self.max - self.current {
+            return None;
         }
+        self.current += n;
+        Some(self.current - n)
     }
 
     /// Recycle an id
     pub fn dealloc(&mut self, id: usize) {
         debug_assert!(id < self.current);
-        debug_assert!(
-            !self.recycled.iter().any(|iid| iid.0 == id),
-            "id {} has been deallocated!",
-            id
-        );
-        self.recycled.push(Reverse(id));
+        if id + 1 == self.current {
+            // Give back the free ids at the top, so that they are allocated
+            // in ranges again
+            self.current = id;
+            while self
+                .recycled
+                .last()
+                .is_some_and(|&last| last + 1 == self.current)
+            {
+                self.recycled.pop_last();
+                self.current -= 1;
+            }
+        } else {
+            let fresh = self.recycled.insert(id);
+            debug_assert!(fresh, "id {} has been deallocated!", id);
+        }
+    }
+
+    /// Recycle `n` contiguous ids starting from `start`
+    pub fn dealloc_range(&mut self, start: usize, n: usize) {
+        for id in (start..start + n).rev() {
+            self.dealloc(id);
+        }
     }
 
+    /// Number of ids recycled and not allocated again, below the current max
     pub fn recycled_len(&self) -> usize {
         self.recycled.len()
     }
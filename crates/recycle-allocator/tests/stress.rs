@@ -0,0 +1,60 @@
+use recycle_allocator::RecycleAllocator;
+
+const MAX: usize = 4096;
+const ROUNDS: usize = 1_000_000;
+
+/// A xorshift generator, so that the run is the same every time.
+fn next(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// Allocate and recycle ids at random, checking each id allocated is the
+/// lowest free one and the allocator is exhausted exactly when all ids are
+/// allocated.
+#[test]
+fn stress() {
+    let mut ids = RecycleAllocator::with_max(1, MAX);
+    let mut allocated = vec![false; MAX];
+    let mut live = Vec::new();
+    let mut state = 0x2545_f491_4f6c_dd1d;
+    for _ in 0..ROUNDS {
+        if live.is_empty() || next(&mut state) & 1 == 0 {
+            let lowest = (1..MAX).find(|&id| !allocated[id]);
+            assert_eq!(ids.try_alloc(), lowest);
+            if let Some(id) = lowest {
+                allocated[id] = true;
+                live.push(id);
+            }
+        } else {
+            let id = live.swap_remove(next(&mut state) as usize % live.len());
+            allocated[id] = false;
+            ids.dealloc(id);
+        }
+    }
+}
+
+#[test]
+fn exhaustion() {
+    let mut ids = RecycleAllocator::with_max(0, 8);
+    assert_eq!(ids.alloc_range(6), Some(0));
+    assert_eq!(ids.alloc_range(3), None);
+    assert_eq!(ids.alloc_range(2), Some(6));
+    assert_eq!(ids.try_alloc(), None);
+    ids.dealloc(3);
+    assert_eq!(ids.alloc_range(1), None);
+    assert_eq!(ids.try_alloc(), Some(3));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic]
+fn double_dealloc() {
+    let mut ids = RecycleAllocator::new(0);
+    ids.alloc();
+    ids.alloc();
+    ids.dealloc(0);
+    ids.dealloc(0);
+}
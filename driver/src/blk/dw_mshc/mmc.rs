@@ -625,14 +625,19 @@ impl BlockDevice for MMC {
     }
 
     fn buffer_head_cnts(&self) -> usize {
-        todo!()
+        0
     }
 
-    fn remove_buffer_page(&self, block_id: usize) {
-        todo!()
-    }
+    fn remove_buffer_page(&self, _block_id: usize) {}
 
     fn base_read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        // one block per command
+        if buf.len() > BLOCK_SIZE {
+            for (i, block) in buf.chunks_mut(BLOCK_SIZE).enumerate() {
+                self.base_read_blocks(block_id + i, block);
+            }
+            return;
+        }
         assert!(buf.len() == BLOCK_SIZE);
 
         let buf_trans: &mut [usize] = unsafe {
@@ -649,6 +654,12 @@ impl BlockDevice for MMC {
     }
 
     fn base_write_blocks(&self, block_id: usize, buf: &[u8]) {
+        if buf.len() > BLOCK_SIZE {
+            for (i, block) in buf.chunks(BLOCK_SIZE).enumerate() {
+                self.base_write_blocks(block_id + i, block);
+            }
+            return;
+        }
         assert!(buf.len() == BLOCK_SIZE);
 
         #[allow(mutable_transmutes)]
@@ -1,60 +1,93 @@
 mod dw_mshc;
+mod sched;
+mod sdhci;
 mod vf2;
 mod virtio;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 
 use device_core::DeviceType;
 use fdt::Fdt;
 use memory::{pte::PTEFlags, PhysAddr};
+pub use sdhci::probe_sdhci_blk;
+#[cfg(feature = "selftest")]
+pub use sdhci::selftest as sdhci_selftest;
 pub use virtio::*;
 use visionfive2_sd::Vf2SdDriver;
 
 use self::dw_mshc::MMC;
 use super::wait_for;
-use crate::{blk::vf2::Vf2SDImpl, kernel_page_table_mut, virtio::probe_devices_common};
+use crate::{
+    blk::vf2::Vf2SDImpl,
+    kernel_page_table_mut,
+    virtio::{probe_devices_common, virtio_mmio_nodes},
+};
 
+/// Compatibles of the DesignWare MSHC, which are SD card hosts on VisionFive 2.
+const DW_MSHC_COMPATIBLE: [&str; 2] = ["starfive,jh7110-mmc", "snps,dw-mshc"];
+
+/// Probe the first DesignWare MSHC enabled with a removable card, i.e. the SD
+/// card rather than the eMMC on VisionFive 2.
 pub fn probe_sdio_blk(root: &Fdt) -> Option<Arc<MMC>> {
-    // Parse SD Card Host Controller
-    if let Some(sdhci) = root.find_node("/soc/sdio1@16020000") {
-        let base_address = sdhci.reg().unwrap().next().unwrap().starting_address as usize;
-        let size = sdhci.reg().unwrap().next().unwrap().size.unwrap();
-        let irq_number = 33; // Hard-coded from JH7110
-        let sdcard = MMC::new(base_address, size, irq_number);
-        log::info!("SD Card Host Controller found at 0x{:x}", base_address);
-        return Some(Arc::new(sdcard));
-    }
-    log::warn!("SD Card Host Controller not found");
-    None
+    let sdhci = root.all_nodes().find(|node| {
+        node.compatible()
+            .is_some_and(|c| c.all().any(|c| DW_MSHC_COMPATIBLE.contains(&c)))
+            && node.property("non-removable").is_none()
+            && node
+                .property("status")
+                .and_then(|status| status.as_str())
+                .map_or(true, |status| status == "okay" || status == "ok")
+    });
+    let Some(sdhci) = sdhci else {
+        log::warn!("SD Card Host Controller not found");
+        return None;
+    };
+    let reg = sdhci.reg()?.next()?;
+    let base_address = reg.starting_address as usize;
+    let size = reg.size?;
+    let irq_number = sdhci
+        .property("interrupts")
+        .and_then(|irq| irq.as_usize())
+        .unwrap_or(33); // JH7110
+    let sdcard = MMC::new(base_address, size, irq_number);
+    log::info!("SD Card Host Controller found at 0x{:x}", base_address);
+    Some(Arc::new(sdcard))
 }
 
-pub fn probe_virtio_blk(root: &Fdt) -> Option<Arc<VirtIoBlkDev>> {
-    let device_tree = root;
-    let mut dev = None;
-    for node in device_tree.find_all_nodes("/soc/virtio_mmio") {
-        for reg in node.reg()? {
+/// Probe every virtio block device, numbered as vda, vdb and so on in the order
+/// of their addresses.
+pub fn probe_virtio_blk(root: &Fdt) -> Vec<Arc<VirtIoBlkDev>> {
+    let mut nodes = virtio_mmio_nodes(root);
+    nodes.sort_by_key(|node| node.reg().unwrap().next().unwrap().starting_address as usize);
+    let mut devs = Vec::new();
+    for node in nodes {
+        for reg in node.reg().into_iter().flatten() {
             let mmio_base_paddr = PhysAddr::from(reg.starting_address as usize);
-            let mmio_size = reg.size?;
+            let Some(mmio_size) = reg.size else {
+                continue;
+            };
             let irq_no = node.property("interrupts").and_then(|i| i.as_usize());
+            let minor = devs.len();
             // First map memory, probe virtio device need to map it
             kernel_page_table_mut().ioremap(
                 mmio_base_paddr.bits(),
                 mmio_size,
                 PTEFlags::R | PTEFlags::W,
             );
-            dev = probe_devices_common(DeviceType::Block, mmio_base_paddr, mmio_size, |t| {
-                VirtIoBlkDev::try_new(mmio_base_paddr.bits(), mmio_size, irq_no, t)
+            let dev = probe_devices_common(DeviceType::Block, mmio_base_paddr, mmio_size, |t| {
+                VirtIoBlkDev::try_new(mmio_base_paddr.bits(), mmio_size, irq_no, minor, t)
             });
             kernel_page_table_mut().iounmap(mmio_base_paddr.to_vaddr().bits(), mmio_size);
-            if dev.is_some() {
+            if let Some(dev) = dev {
+                devs.push(dev);
                 break;
             }
         }
     }
-    if dev.is_none() {
+    if devs.is_empty() {
         log::warn!("No virtio block device found");
     }
-    dev
+    devs
 }
 
 pub fn probe_vf2_sd(root: &Fdt) -> Option<Arc<Vf2SDImpl>> {
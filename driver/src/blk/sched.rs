@@ -0,0 +1,241 @@
+//! Scheduling of block requests above the device queue.
+//!
+//! Requests are staged before being dispatched to the device, where adjacent
+//! requests in the same direction are merged into one transfer, and the
+//! transfers are dispatched in the order of their blocks. Requests are only
+//! staged while the device queue is full or the scheduler is plugged, and
+//! never longer than `STAGE_DEADLINE`, or than anyone waits for them.
+//!
+//! Requests to overlapping blocks are never in flight together, since the
+//! device may complete them in any order, so a request overlapping a pending
+//! one must wait until that is done, which makes a barrier.
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::{ops::Range, task::Waker, time::Duration};
+
+use arch::time::get_time_duration;
+use config::board::BLOCK_SIZE;
+use device_core::DiskStats;
+
+/// Longest time a request is staged, even if the scheduler is plugged.
+const STAGE_DEADLINE: Duration = Duration::from_millis(2);
+/// Requests are not merged into transfers larger than this.
+const MAX_MERGE_BYTES: usize = 64 * 1024;
+
+/// A request of a caller.
+pub struct Request {
+    block_id: usize,
+    write: bool,
+    pub buf: Vec<u8>,
+    staged_at: Duration,
+    done: bool,
+    waker: Option<Waker>,
+    /// Nobody waits for the request, which is freed once done.
+    detached: bool,
+}
+
+impl Request {
+    fn blocks(&self) -> Range<usize> {
+        self.block_id..self.block_id + self.buf.len() / BLOCK_SIZE
+    }
+}
+
+/// Adjacent requests merged into one transfer.
+pub struct Batch {
+    pub block_id: usize,
+    pub write: bool,
+    pub buf: Vec<u8>,
+    /// Ids of the requests, in the order of their blocks.
+    members: Vec<u64>,
+}
+
+pub struct Scheduler {
+    /// Requests staged, in flight or done but not taken, by their ids.
+    requests: BTreeMap<u64, Request>,
+    /// Ids of the requests staged, in the order of arrival.
+    staged: Vec<u64>,
+    next_id: u64,
+    plugged: usize,
+    stats: DiskStats,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            requests: BTreeMap::new(),
+            staged: Vec::new(),
+            next_id: 0,
+            plugged: 0,
+            stats: DiskStats::default(),
+        }
+    }
+
+    /// Stage a request to `buf.len()` bytes of blocks from `block_id`, and
+    /// return its id. A detached request is freed once done.
+    pub fn enqueue(&mut self, block_id: usize, write: bool, buf: Vec<u8>, detached: bool) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.requests.insert(
+            id,
+            Request {
+                block_id,
+                write,
+                buf,
+                staged_at: get_time_duration(),
+                done: false,
+                waker: None,
+                detached,
+            },
+        );
+        self.staged.push(id);
+        id
+    }
+
+    /// Whether a request to `blocks` has to wait for a pending one, i.e. it
+    /// overlaps a pending request and one of them is a write.
+    pub fn conflicts(&self, blocks: Range<usize>, write: bool) -> bool {
+        self.requests.values().any(|request| {
+            let other = request.blocks();
+            !request.done
+                && (write || request.write)
+                && other.start < blocks.end
+                && blocks.start < other.end
+        })
+    }
+
+    pub fn has_pending_writes(&self) -> bool {
+        self.requests
+            .values()
+            .any(|request| request.write && !request.done)
+    }
+
+    pub fn plug(&mut self) {
+        self.plugged += 1;
+    }
+
+    pub fn unplug(&mut self) {
+        self.plugged -= 1;
+    }
+
+    /// Whether the staged requests should be dispatched, which are held back
+    /// only if the scheduler is plugged and none of them is due, unless
+    /// `force`.
+    pub fn should_dispatch(&self, force: bool) -> bool {
+        let Some(oldest) = self.staged.first() else {
+            return false;
+        };
+        force
+            || self.plugged == 0
+            || get_time_duration() - self.requests[oldest].staged_at >= STAGE_DEADLINE
+    }
+
+    /// Take the staged requests merged into batches, in the order of blocks.
+    pub fn take_batches(&mut self) -> Vec<Batch> {
+        let mut staged = core::mem::take(&mut self.staged);
+        // stable, so that the order of requests to the same blocks is kept
+        staged.sort_by_key(|id| self.requests[id].block_id);
+        let mut batches: Vec<Batch> = Vec::new();
+        for id in staged {
+            let request = &self.requests[&id];
+            if let Some(batch) = batches.last_mut().filter(|batch| {
+                batch.write == request.write
+                    && batch.block_id + batch.buf.len() / BLOCK_SIZE == request.block_id
+                    && batch.buf.len() + request.buf.len() <= MAX_MERGE_BYTES
+            }) {
+                if request.write {
+                    batch.buf.extend_from_slice(&request.buf);
+                } else {
+                    batch.buf.resize(batch.buf.len() + request.buf.len(), 0);
+                }
+                batch.members.push(id);
+                continue;
+            }
+            batches.push(Batch {
+                block_id: request.block_id,
+                write: request.write,
+                buf: request.buf.clone(),
+                members: vec![id],
+            });
+        }
+        batches
+    }
+
+    /// Stage the requests of a batch not taken by the device again.
+    pub fn restage(&mut self, batch: Batch) {
+        self.staged.extend(batch.members);
+    }
+
+    /// Complete the requests of a batch done by the device, and wake their
+    /// waiters.
+    pub fn complete(&mut self, batch: Batch) {
+        let sectors = batch.buf.len() / BLOCK_SIZE;
+        let merged = batch.members.len() - 1;
+        if batch.write {
+            self.stats.writes += 1;
+            self.stats.writes_merged += merged;
+            self.stats.sectors_written += sectors;
+        } else {
+            self.stats.reads += 1;
+            self.stats.reads_merged += merged;
+            self.stats.sectors_read += sectors;
+        }
+        let mut offset = 0;
+        for id in batch.members {
+            let request = self.requests.get_mut(&id).unwrap();
+            let len = request.buf.len();
+            if !batch.write {
+                request
+                    .buf
+                    .copy_from_slice(&batch.buf[offset..offset + len]);
+            }
+            offset += len;
+            if request.detached {
+                self.requests.remove(&id);
+                continue;
+            }
+            request.done = true;
+            if let Some(waker) = request.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Take the request of `id` if it is done, or register `waker` to be woken
+    /// when it is.
+    pub fn poll_request(&mut self, id: u64, waker: &Waker) -> Option<Request> {
+        let request = self.requests.get_mut(&id).unwrap();
+        if request.done {
+            return self.requests.remove(&id);
+        }
+        if !request.waker.as_ref().is_some_and(|w| w.will_wake(waker)) {
+            request.waker = Some(waker.clone());
+        }
+        None
+    }
+
+    /// Take the request of `id` if it is done.
+    pub fn take_done(&mut self, id: u64) -> Option<Request> {
+        if !self.requests.get(&id)?.done {
+            return None;
+        }
+        self.requests.remove(&id)
+    }
+
+    /// Give up waiting for the request of `id`, which is dropped if it is not
+    /// in flight, and freed once done otherwise.
+    pub fn abandon(&mut self, id: u64) {
+        if let Some(pos) = self.staged.iter().position(|&staged| staged == id) {
+            self.staged.remove(pos);
+            self.requests.remove(&id);
+        } else if self.take_done(id).is_none() {
+            self.requests.get_mut(&id).unwrap().detached = true;
+        }
+    }
+
+    pub fn stats(&self) -> DiskStats {
+        DiskStats {
+            in_flight: self.requests.values().filter(|r| !r.done).count(),
+            ..self.stats
+        }
+    }
+}
@@ -0,0 +1,611 @@
+//! SD card behind a standard SD Host Controller, e.g. the DesignWare Cores
+//! MSHC. The MMC controllers of the JH7110 on VisionFive 2 are the older
+//! DesignWare MSHC instead, which is not SDHCI and is driven by `dw_mshc`.
+//!
+//! The controller is driven through the registers of the SD Host Controller
+//! Specification, with data moved by PIO through the buffer data port, and
+//! commands completed by polling, since interrupts of the controller are not
+//! wired yet. The card is brought up when probed, so a controller without a
+//! card does not become a block device.
+
+use alloc::{string::ToString, sync::Arc};
+use core::time::Duration;
+
+use arch::time::get_time_duration;
+use config::board::BLOCK_SIZE;
+use device_core::{BlockDevice, DevId, Device, DeviceMajor, DeviceMeta, DeviceType};
+use fdt::{node::FdtNode, Fdt};
+use log::{info, warn};
+use memory::{pte::PTEFlags, PhysAddr};
+use page::BufferCache;
+use sync::mutex::SpinNoIrqLock;
+
+use crate::kernel_page_table_mut;
+
+pub const SDHCI_COMPATIBLE: [&str; 2] = ["snps,dwcmshc-sdhci", "sdhci"];
+
+const BLOCK_SIZE_REG: usize = 0x04;
+const BLOCK_COUNT: usize = 0x06;
+const ARGUMENT: usize = 0x08;
+const TRANSFER_MODE: usize = 0x0c;
+const COMMAND: usize = 0x0e;
+const RESPONSE: usize = 0x10;
+const BUFFER_DATA_PORT: usize = 0x20;
+const PRESENT_STATE: usize = 0x24;
+const HOST_CONTROL: usize = 0x28;
+const POWER_CONTROL: usize = 0x29;
+const CLOCK_CONTROL: usize = 0x2c;
+const TIMEOUT_CONTROL: usize = 0x2e;
+const SOFTWARE_RESET: usize = 0x2f;
+const INT_STATUS: usize = 0x30;
+const ERROR_INT_STATUS: usize = 0x32;
+const INT_STATUS_ENABLE: usize = 0x34;
+const ERROR_INT_STATUS_ENABLE: usize = 0x36;
+const CAPABILITIES: usize = 0x40;
+const HOST_VERSION: usize = 0xfe;
+
+const TRANSFER_BLOCK_COUNT_ENABLE: u16 = 1 << 1;
+const TRANSFER_AUTO_CMD12: u16 = 1 << 2;
+const TRANSFER_READ: u16 = 1 << 4;
+const TRANSFER_MULTI_BLOCK: u16 = 1 << 5;
+
+const COMMAND_CRC_CHECK: u16 = 1 << 3;
+const COMMAND_INDEX_CHECK: u16 = 1 << 4;
+const COMMAND_DATA_PRESENT: u16 = 1 << 5;
+
+const STATE_CMD_INHIBIT: u32 = 1 << 0;
+const STATE_DAT_INHIBIT: u32 = 1 << 1;
+const STATE_CARD_INSERTED: u32 = 1 << 16;
+
+const HOST_4BIT_BUS: u8 = 1 << 1;
+/// Bus power on at 3.3V.
+const POWER_ON_330: u8 = (0b111 << 1) | 1;
+
+const CLOCK_INTERNAL_ENABLE: u16 = 1 << 0;
+const CLOCK_INTERNAL_STABLE: u16 = 1 << 1;
+const CLOCK_CARD_ENABLE: u16 = 1 << 2;
+
+const RESET_ALL: u8 = 1 << 0;
+const RESET_CMD: u8 = 1 << 1;
+const RESET_DAT: u8 = 1 << 2;
+
+const INT_COMMAND_COMPLETE: u16 = 1 << 0;
+const INT_TRANSFER_COMPLETE: u16 = 1 << 1;
+const INT_BUFFER_WRITE_READY: u16 = 1 << 4;
+const INT_BUFFER_READ_READY: u16 = 1 << 5;
+const INT_ERROR: u16 = 1 << 15;
+
+/// Clock of the card while it is identified, and then for transfers.
+const INIT_CLOCK_HZ: usize = 400_000;
+const TRANSFER_CLOCK_HZ: usize = 25_000_000;
+/// Base clock assumed if neither the controller nor the device tree tells.
+const DEFAULT_BASE_CLOCK_HZ: usize = 200_000_000;
+
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(100);
+const DATA_TIMEOUT: Duration = Duration::from_secs(1);
+/// Blocks transferred by one command at most.
+const MAX_BLOCKS: usize = 128;
+
+/// Response types of commands, which determine the flags of the command
+/// register.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Resp {
+    None,
+    /// R2, 136 bits with CRC.
+    R2,
+    /// R3, 48 bits without CRC or index.
+    R3,
+    /// R1, R6 and R7, 48 bits with CRC and index.
+    R1,
+    /// R1b, R1 with busy on DAT0.
+    R1b,
+}
+
+impl Resp {
+    fn flags(self) -> u16 {
+        match self {
+            Resp::None => 0,
+            Resp::R2 => 0b01 | COMMAND_CRC_CHECK,
+            Resp::R3 => 0b10,
+            Resp::R1 => 0b10 | COMMAND_CRC_CHECK | COMMAND_INDEX_CHECK,
+            Resp::R1b => 0b11 | COMMAND_CRC_CHECK | COMMAND_INDEX_CHECK,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum SdError {
+    Timeout,
+    /// Error interrupt status of the controller.
+    Controller(u16),
+    NoCard,
+    Unusable,
+}
+
+/// Data transferred by a command.
+enum Data<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+impl Data<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Data::Read(buf) => buf.len(),
+            Data::Write(buf) => buf.len(),
+        }
+    }
+}
+
+/// Spin until `f` is true, or `timeout` passes.
+fn wait_until(timeout: Duration, mut f: impl FnMut() -> bool) -> Result<(), SdError> {
+    let deadline = get_time_duration() + timeout;
+    while !f() {
+        if get_time_duration() > deadline {
+            return Err(SdError::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+struct SdhciHost {
+    base: usize,
+    version: u8,
+    base_clock: usize,
+    /// Relative card address.
+    rca: u32,
+    /// Addressed by blocks rather than by bytes.
+    high_capacity: bool,
+    blocks: u64,
+}
+
+impl SdhciHost {
+    fn read8(&self, offset: usize) -> u8 {
+        unsafe { ((self.base + offset) as *const u8).read_volatile() }
+    }
+
+    fn write8(&self, offset: usize, val: u8) {
+        unsafe { ((self.base + offset) as *mut u8).write_volatile(val) }
+    }
+
+    fn read16(&self, offset: usize) -> u16 {
+        unsafe { ((self.base + offset) as *const u16).read_volatile() }
+    }
+
+    fn write16(&self, offset: usize, val: u16) {
+        unsafe { ((self.base + offset) as *mut u16).write_volatile(val) }
+    }
+
+    fn read32(&self, offset: usize) -> u32 {
+        unsafe { ((self.base + offset) as *const u32).read_volatile() }
+    }
+
+    fn write32(&self, offset: usize, val: u32) {
+        unsafe { ((self.base + offset) as *mut u32).write_volatile(val) }
+    }
+
+    fn card_inserted(&self) -> bool {
+        self.read32(PRESENT_STATE) & STATE_CARD_INSERTED != 0
+    }
+
+    fn reset(&self, mask: u8) -> Result<(), SdError> {
+        self.write8(SOFTWARE_RESET, mask);
+        wait_until(COMMAND_TIMEOUT, || self.read8(SOFTWARE_RESET) & mask == 0)
+    }
+
+    fn set_clock(&self, hz: usize) -> Result<(), SdError> {
+        self.write16(CLOCK_CONTROL, 0);
+        let clock = clock_divider(self.base_clock, hz, self.version) | CLOCK_INTERNAL_ENABLE;
+        self.write16(CLOCK_CONTROL, clock);
+        wait_until(COMMAND_TIMEOUT, || {
+            self.read16(CLOCK_CONTROL) & CLOCK_INTERNAL_STABLE != 0
+        })?;
+        self.write16(CLOCK_CONTROL, clock | CLOCK_CARD_ENABLE);
+        Ok(())
+    }
+
+    /// Wait for and clear any of the interrupt status `mask`, or fail on an
+    /// error.
+    fn wait_int(&self, mask: u16, timeout: Duration) -> Result<(), SdError> {
+        let mut status = 0;
+        wait_until(timeout, || {
+            status = self.read16(INT_STATUS);
+            status & (mask | INT_ERROR) != 0
+        })?;
+        if status & INT_ERROR != 0 {
+            return Err(SdError::Controller(self.read16(ERROR_INT_STATUS)));
+        }
+        self.write16(INT_STATUS, status & mask);
+        Ok(())
+    }
+
+    fn send_cmd(
+        &self,
+        index: u8,
+        arg: u32,
+        resp: Resp,
+        data: Option<Data>,
+    ) -> Result<[u32; 4], SdError> {
+        let ret = self.do_send_cmd(index, arg, resp, data);
+        if let Err(err) = &ret {
+            warn!("[sdhci] CMD{index} failed: {err:?}");
+            self.write16(ERROR_INT_STATUS, 0xffff);
+            self.write16(INT_STATUS, 0xffff);
+            let _ = self.reset(RESET_CMD | RESET_DAT);
+        }
+        ret
+    }
+
+    fn do_send_cmd(
+        &self,
+        index: u8,
+        arg: u32,
+        resp: Resp,
+        data: Option<Data>,
+    ) -> Result<[u32; 4], SdError> {
+        let mut inhibit = STATE_CMD_INHIBIT;
+        if data.is_some() || resp == Resp::R1b {
+            inhibit |= STATE_DAT_INHIBIT;
+        }
+        wait_until(COMMAND_TIMEOUT, || {
+            self.read32(PRESENT_STATE) & inhibit == 0
+        })?;
+        self.write16(INT_STATUS, 0xffff);
+        self.write16(ERROR_INT_STATUS, 0xffff);
+
+        let mut command = ((index as u16) << 8) | resp.flags();
+        if let Some(data) = &data {
+            let blocks = data.len() / BLOCK_SIZE;
+            let mut mode = TRANSFER_BLOCK_COUNT_ENABLE;
+            if blocks > 1 {
+                mode |= TRANSFER_MULTI_BLOCK | TRANSFER_AUTO_CMD12;
+            }
+            if let Data::Read(_) = data {
+                mode |= TRANSFER_READ;
+            }
+            self.write16(BLOCK_SIZE_REG, data.len().min(BLOCK_SIZE) as u16);
+            self.write16(BLOCK_COUNT, blocks.max(1) as u16);
+            self.write16(TRANSFER_MODE, mode);
+            command |= COMMAND_DATA_PRESENT;
+        }
+        self.write32(ARGUMENT, arg);
+        self.write16(COMMAND, command);
+        self.wait_int(INT_COMMAND_COMPLETE, COMMAND_TIMEOUT)?;
+        let response = core::array::from_fn(|i| self.read32(RESPONSE + 4 * i));
+
+        match data {
+            Some(Data::Read(buf)) => {
+                for block in buf.chunks_mut(BLOCK_SIZE) {
+                    self.wait_int(INT_BUFFER_READ_READY, DATA_TIMEOUT)?;
+                    for word in block.chunks_mut(4) {
+                        word.copy_from_slice(&self.read32(BUFFER_DATA_PORT).to_le_bytes());
+                    }
+                }
+                self.wait_int(INT_TRANSFER_COMPLETE, DATA_TIMEOUT)?;
+            }
+            Some(Data::Write(buf)) => {
+                for block in buf.chunks(BLOCK_SIZE) {
+                    self.wait_int(INT_BUFFER_WRITE_READY, DATA_TIMEOUT)?;
+                    for word in block.chunks(4) {
+                        self.write32(
+                            BUFFER_DATA_PORT,
+                            u32::from_le_bytes(word.try_into().unwrap()),
+                        );
+                    }
+                }
+                self.wait_int(INT_TRANSFER_COMPLETE, DATA_TIMEOUT)?;
+            }
+            None if resp == Resp::R1b => self.wait_int(INT_TRANSFER_COMPLETE, DATA_TIMEOUT)?,
+            None => {}
+        }
+        Ok(response)
+    }
+
+    fn send_app_cmd(&self, index: u8, arg: u32, resp: Resp) -> Result<[u32; 4], SdError> {
+        self.send_cmd(55, self.rca << 16, Resp::R1, None)?;
+        self.send_cmd(index, arg, resp, None)
+    }
+
+    /// Power up the bus, then identify and select the card, and switch to
+    /// the 4-bit bus at the transfer clock. The card detect is not trusted
+    /// unless `detect_card`.
+    fn init_card(&mut self, detect_card: bool) -> Result<(), SdError> {
+        self.reset(RESET_ALL)?;
+        self.write8(POWER_CONTROL, POWER_ON_330);
+        // status is polled, and no interrupt is signaled
+        self.write16(INT_STATUS_ENABLE, 0xffff);
+        self.write16(ERROR_INT_STATUS_ENABLE, 0xffff);
+        self.write8(TIMEOUT_CONTROL, 0xe);
+        self.set_clock(INIT_CLOCK_HZ)?;
+        if detect_card && !self.card_inserted() {
+            return Err(SdError::NoCard);
+        }
+
+        self.send_cmd(0, 0, Resp::None, None)?;
+        // SEND_IF_COND with the check pattern, which version 1 cards ignore
+        let v2 = self
+            .send_cmd(8, 0x1aa, Resp::R1, None)
+            .is_ok_and(|resp| resp[0] & 0xfff == 0x1aa);
+        let hcs = if v2 { 1 << 30 } else { 0 };
+        let mut ocr = 0;
+        wait_until(DATA_TIMEOUT, || {
+            ocr = self
+                .send_app_cmd(41, hcs | 0x00ff_8000, Resp::R3)
+                .map_or(0, |resp| resp[0]);
+            ocr & (1 << 31) != 0
+        })?;
+        self.high_capacity = ocr & (1 << 30) != 0;
+
+        self.send_cmd(2, 0, Resp::R2, None)?;
+        self.rca = self.send_cmd(3, 0, Resp::R1, None)?[0] >> 16;
+        let csd = self.send_cmd(9, self.rca << 16, Resp::R2, None)?;
+        self.blocks = csd_blocks(csd).ok_or(SdError::Unusable)?;
+        self.send_cmd(7, self.rca << 16, Resp::R1b, None)?;
+
+        self.send_app_cmd(6, 0b10, Resp::R1)?;
+        self.write8(HOST_CONTROL, self.read8(HOST_CONTROL) | HOST_4BIT_BUS);
+        if !self.high_capacity {
+            self.send_cmd(16, BLOCK_SIZE as u32, Resp::R1, None)?;
+        }
+        self.set_clock(TRANSFER_CLOCK_HZ)
+    }
+
+    fn card_addr(&self, block_id: usize) -> u32 {
+        if self.high_capacity {
+            block_id as u32
+        } else {
+            (block_id * BLOCK_SIZE) as u32
+        }
+    }
+
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) -> Result<(), SdError> {
+        for (i, chunk) in buf.chunks_mut(MAX_BLOCKS * BLOCK_SIZE).enumerate() {
+            let addr = self.card_addr(block_id + i * MAX_BLOCKS);
+            let index = if chunk.len() > BLOCK_SIZE { 18 } else { 17 };
+            self.send_cmd(index, addr, Resp::R1, Some(Data::Read(chunk)))?;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, block_id: usize, buf: &[u8]) -> Result<(), SdError> {
+        for (i, chunk) in buf.chunks(MAX_BLOCKS * BLOCK_SIZE).enumerate() {
+            let addr = self.card_addr(block_id + i * MAX_BLOCKS);
+            let index = if chunk.len() > BLOCK_SIZE { 25 } else { 24 };
+            self.send_cmd(index, addr, Resp::R1, Some(Data::Write(chunk)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Divider field of the clock control register to run SDCLK at `hz` at most,
+/// from the base clock of a controller of `version`.
+fn clock_divider(base_clock: usize, hz: usize, version: u8) -> u16 {
+    // SDCLK is the base clock divided by 2N, N of 10 bits since version 3.00,
+    // and a power of 2 of 8 bits before
+    let mut div = base_clock.div_ceil(2 * hz);
+    if version >= 2 {
+        div = div.min(0x3ff);
+    } else {
+        div = div.next_power_of_two().min(0x80);
+    }
+    let div = div as u16;
+    ((div & 0xff) << 8) | ((div >> 8) << 6)
+}
+
+/// Capacity in blocks from the CSD, whose response lacks the CRC byte, i.e.
+/// bit `n` of the CSD is bit `n - 8` of the response.
+fn csd_blocks(csd: [u32; 4]) -> Option<u64> {
+    let csd = csd
+        .iter()
+        .rev()
+        .fold(0u128, |csd, &word| (csd << 32) | word as u128);
+    let bits = |hi: u32, lo: u32| (csd >> (lo - 8)) as u64 & ((1 << (hi - lo + 1)) - 1);
+    match bits(127, 126) {
+        0 => {
+            let c_size = bits(73, 62);
+            let c_size_mult = bits(49, 47);
+            let read_bl_len = bits(83, 80);
+            Some(((c_size + 1) << (c_size_mult + 2 + read_bl_len)) / BLOCK_SIZE as u64)
+        }
+        1 => Some((bits(69, 48) + 1) * 1024),
+        _ => None,
+    }
+}
+
+/// Check the decoding of the capacity from both versions of the CSD, the
+/// clock dividers of both layouts, and the addressing of both kinds of cards.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    // the CSD as sent by the card, without its CRC byte
+    let response =
+        |csd: u128| -> [u32; 4] { core::array::from_fn(|i| ((csd >> 8) >> (32 * i)) as u32) };
+    // version 1.0 of 1 GiB, with 512 byte blocks
+    let csd_v1 = (9u128 << 80) | (4095u128 << 62) | (7u128 << 47);
+    assert_eq!(csd_blocks(response(csd_v1)), Some(2 * 1024 * 1024));
+    // version 2.0 of 8 GB
+    let csd_v2 = (1u128 << 126) | (15159u128 << 48);
+    assert_eq!(csd_blocks(response(csd_v2)), Some(15160 * 1024));
+    assert_eq!(csd_blocks(response(3u128 << 126)), None);
+
+    assert_eq!(clock_divider(200_000_000, INIT_CLOCK_HZ, 2), 0xfa00);
+    assert_eq!(clock_divider(200_000_000, TRANSFER_CLOCK_HZ, 2), 0x0400);
+    // upper 2 bits of a 10 bit divider
+    assert_eq!(clock_divider(200_000_000, 100_000, 2), 0xe8c0);
+    // rounded up to a power of 2 before version 3.00
+    assert_eq!(clock_divider(50_000_000, INIT_CLOCK_HZ, 1), 0x4000);
+    assert_eq!(clock_divider(50_000_000, TRANSFER_CLOCK_HZ, 1), 0x0100);
+
+    let mut host = SdhciHost {
+        base: 0,
+        version: 2,
+        base_clock: 0,
+        rca: 0,
+        high_capacity: true,
+        blocks: 0,
+    };
+    assert_eq!(host.card_addr(3), 3);
+    host.high_capacity = false;
+    assert_eq!(host.card_addr(3), 3 * BLOCK_SIZE as u32);
+}
+
+pub struct SdhciBlkDev {
+    meta: DeviceMeta,
+    host: SpinNoIrqLock<SdhciHost>,
+    pub cache: SpinNoIrqLock<BufferCache>,
+}
+
+unsafe impl Send for SdhciBlkDev {}
+unsafe impl Sync for SdhciBlkDev {}
+
+impl SdhciBlkDev {
+    /// Bring up the card behind the controller of `node`, which is mapped
+    /// already.
+    fn try_new(node: &FdtNode, mmio_base: usize, mmio_size: usize) -> Option<Arc<Self>> {
+        let base = PhysAddr::from(mmio_base).to_vaddr().bits();
+        let mut host = SdhciHost {
+            base,
+            version: 0,
+            base_clock: 0,
+            rca: 0,
+            high_capacity: false,
+            blocks: 0,
+        };
+        host.version = host.read16(HOST_VERSION) as u8;
+        let caps_clock_mhz = if host.version >= 2 {
+            (host.read32(CAPABILITIES) >> 8) & 0xff
+        } else {
+            (host.read32(CAPABILITIES) >> 8) & 0x3f
+        };
+        host.base_clock = if caps_clock_mhz != 0 {
+            caps_clock_mhz as usize * 1_000_000
+        } else if let Some(hz) = node
+            .property("clock-frequency")
+            .and_then(|prop| prop.as_usize())
+        {
+            hz
+        } else {
+            warn!("[sdhci] base clock unknown, assume {DEFAULT_BASE_CLOCK_HZ} Hz");
+            DEFAULT_BASE_CLOCK_HZ
+        };
+        let detect_card =
+            node.property("broken-cd").is_none() && node.property("non-removable").is_none();
+        if let Err(err) = host.init_card(detect_card) {
+            warn!(
+                "[sdhci] failed to bring up the card at PA:{:#x}, {err:?}",
+                mmio_base
+            );
+            return None;
+        }
+        info!(
+            "[sdhci] card of {} blocks at PA:{:#x}, high capacity: {}",
+            host.blocks, mmio_base, host.high_capacity
+        );
+        let meta = DeviceMeta {
+            dev_id: DevId {
+                major: DeviceMajor::Block,
+                minor: 2,
+            },
+            name: "sdhci".to_string(),
+            mmio_base,
+            mmio_size,
+            irq_no: None,
+            dtype: DeviceType::Block,
+        };
+        let dev = Arc::new(Self {
+            meta,
+            host: SpinNoIrqLock::new(host),
+            cache: SpinNoIrqLock::new(BufferCache::new()),
+        });
+        dev.cache.lock().init_device(dev.clone());
+        Some(dev)
+    }
+}
+
+impl Device for SdhciBlkDev {
+    fn meta(&self) -> &DeviceMeta {
+        &self.meta
+    }
+
+    fn init(&self) {}
+
+    fn handle_irq(&self) {
+        // interrupts are not signaled, see `init_card`
+    }
+
+    fn as_blk(self: Arc<Self>) -> Option<Arc<dyn BlockDevice>> {
+        Some(self)
+    }
+}
+
+impl BlockDevice for SdhciBlkDev {
+    fn size(&self) -> u64 {
+        self.host.lock().blocks * BLOCK_SIZE as u64
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn buffer_head_cnts(&self) -> usize {
+        self.cache.lock().buffer_heads.len()
+    }
+
+    fn buffer_page_cnts(&self) -> usize {
+        self.cache.lock().pages.len()
+    }
+
+    fn remove_buffer_page(&self, block_id: usize) {
+        self.cache.lock().pages.pop(&block_id);
+    }
+
+    fn base_read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        if let Err(err) = self.host.lock().read_blocks(block_id, buf) {
+            panic!("Error when reading SDHCI, block_id {block_id}, err {err:?}");
+        }
+    }
+
+    fn base_write_blocks(&self, block_id: usize, buf: &[u8]) {
+        if let Err(err) = self.host.lock().write_blocks(block_id, buf) {
+            panic!("Error when writing SDHCI, block_id {block_id}, err {err:?}");
+        }
+    }
+
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.cache.lock().read_block(block_id, buf)
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.cache.lock().write_block(block_id, buf)
+    }
+
+    fn sync(&self) {
+        self.cache.lock().sync()
+    }
+}
+
+/// Probe the first SD Host Controller enabled with a card inserted.
+pub fn probe_sdhci_blk(root: &Fdt) -> Option<Arc<SdhciBlkDev>> {
+    let nodes = root.all_nodes().filter(|node| {
+        node.compatible()
+            .is_some_and(|c| c.all().any(|c| SDHCI_COMPATIBLE.contains(&c)))
+            && node
+                .property("status")
+                .and_then(|status| status.as_str())
+                .map_or(true, |status| status == "okay" || status == "ok")
+    });
+    for node in nodes {
+        let Some(reg) = node.reg().and_then(|mut reg| reg.next()) else {
+            continue;
+        };
+        let mmio_base = reg.starting_address as usize;
+        let mmio_size = reg.size.unwrap_or(0x1000);
+        kernel_page_table_mut().ioremap(mmio_base, mmio_size, PTEFlags::R | PTEFlags::W);
+        let dev = SdhciBlkDev::try_new(&node, mmio_base, mmio_size);
+        kernel_page_table_mut().iounmap(PhysAddr::from(mmio_base).to_vaddr().bits(), mmio_size);
+        if dev.is_some() {
+            return dev;
+        }
+    }
+    None
+}
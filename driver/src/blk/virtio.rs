@@ -1,31 +1,159 @@
-use alloc::{string::ToString, sync::Arc};
-use core::ptr::NonNull;
+//! Virtio block device, whose requests are completed by interrupts.
+//!
+//! Requests of callers go through the scheduler in `sched`, which merges them
+//! into batches submitted to the virtqueue. A batch is completed later when it
+//! is popped from the used ring, by the interrupt handler or by whoever polls
+//! the ring, so the lock of the device is only held for submitting and
+//! popping, and several batches can be in flight at once up to the size of
+//! the queue. Each batch owns its buffers until it is popped, which are copied
+//! from or to the buffers of the callers, so that a request whose waiter is
+//! dropped is still safe to be completed by the device.
 
+use alloc::{boxed::Box, collections::BTreeMap, string::ToString, sync::Arc, vec, vec::Vec};
+use core::{
+    future::Future,
+    hint::spin_loop,
+    pin::Pin,
+    ptr::NonNull,
+    task::{Context, Poll},
+};
+
+use arch::interrupts::is_interrupt_enabled;
+use async_trait::async_trait;
+use async_utils::block_on;
 use config::board::BLOCK_SIZE;
-use device_core::{BlockDevice, DevId, Device, DeviceMajor, DeviceMeta, DeviceType};
+use device_core::{BlockDevice, DevId, Device, DeviceMajor, DeviceMeta, DeviceType, DiskStats};
 use log::error;
 use memory::{alloc_frames, dealloc_frame, PhysAddr, PhysPageNum, VirtAddr};
 use page::BufferCache;
-use sync::mutex::SpinNoIrqLock;
-use virtio_drivers::{device::blk::VirtIOBlk, transport::mmio::MmioTransport, BufferDirection};
+use sync::{mutex::SpinNoIrqLock, WaitQueue};
+use virtio_drivers::{
+    device::blk::{BlkReq, BlkResp, VirtIOBlk},
+    transport::mmio::MmioTransport,
+    BufferDirection, Error,
+};
 
+use super::sched::{Batch, Request, Scheduler};
 use crate::virtio::VirtioHalImpl;
 
 pub type BlockDeviceImpl = VirtIoBlkDev;
 
+/// A batch submitted to the virtqueue.
+struct InFlight {
+    batch: Batch,
+    req: BlkReq,
+    resp: BlkResp,
+}
+
+struct BlkQueue {
+    device: VirtIOBlk<VirtioHalImpl, MmioTransport>,
+    sched: Scheduler,
+    /// Batches in flight by their tokens. They are boxed since the device
+    /// accesses them by address.
+    inflight: BTreeMap<u16, Box<InFlight>>,
+}
+
+impl BlkQueue {
+    /// Submit the batches staged if they are due or `force`, until the
+    /// virtqueue is full.
+    fn kick(&mut self, force: bool) {
+        if !self.sched.should_dispatch(force) {
+            return;
+        }
+        let mut batches = self.sched.take_batches().into_iter();
+        while let Some(batch) = batches.next() {
+            if let Err(batch) = self.submit(batch) {
+                self.sched.restage(batch);
+                batches.for_each(|batch| self.sched.restage(batch));
+                return;
+            }
+        }
+    }
+
+    /// Submit a batch, or give it back if the virtqueue is full.
+    fn submit(&mut self, batch: Batch) -> Result<(), Batch> {
+        let mut inflight = Box::new(InFlight {
+            batch,
+            req: BlkReq::default(),
+            resp: BlkResp::default(),
+        });
+        let InFlight { batch, req, resp } = &mut *inflight;
+        let token = unsafe {
+            if batch.write {
+                self.device
+                    .write_blocks_nb(batch.block_id, req, &batch.buf, resp)
+            } else {
+                self.device
+                    .read_blocks_nb(batch.block_id, req, &mut batch.buf, resp)
+            }
+        };
+        match token {
+            Ok(token) => {
+                self.inflight.insert(token, inflight);
+                Ok(())
+            }
+            Err(Error::QueueFull) => Err(inflight.batch),
+            Err(e) => panic!(
+                "Error when submitting to VirtIOBlk, block_id {}, err {e:?}",
+                batch.block_id
+            ),
+        }
+    }
+
+    /// Pop all batches completed from the used ring, complete their requests
+    /// and submit the ones staged behind them. Return if any is popped.
+    fn reap(&mut self) -> bool {
+        let mut reaped = false;
+        while let Some(token) = self.device.peek_used() {
+            let mut inflight = self
+                .inflight
+                .remove(&token)
+                .expect("VirtIOBlk completed an unknown request");
+            let InFlight { batch, req, resp } = &mut *inflight;
+            let result = unsafe {
+                if batch.write {
+                    self.device
+                        .complete_write_blocks_nb(token, req, &batch.buf, resp)
+                } else {
+                    self.device
+                        .complete_read_blocks_nb(token, req, &mut batch.buf, resp)
+                }
+            };
+            if let Err(e) = result {
+                panic!(
+                    "Error when {} VirtIOBlk, block_id {}, err {:?}",
+                    if batch.write { "writing" } else { "reading" },
+                    batch.block_id,
+                    e
+                );
+            }
+            self.sched.complete(inflight.batch);
+            reaped = true;
+        }
+        if reaped {
+            self.kick(false);
+        }
+        reaped
+    }
+}
+
 pub struct VirtIoBlkDev {
     meta: DeviceMeta,
-    device: SpinNoIrqLock<VirtIOBlk<VirtioHalImpl, MmioTransport>>,
+    queue: SpinNoIrqLock<BlkQueue>,
+    /// Tasks waiting for pending requests to be done, either overlapping their
+    /// own or all writes for `wait_writes`.
+    waiters: WaitQueue,
     pub cache: SpinNoIrqLock<BufferCache>,
 }
 
 unsafe impl Send for VirtIoBlkDev {}
 unsafe impl Sync for VirtIoBlkDev {}
 
+#[async_trait]
 impl BlockDevice for VirtIoBlkDev {
     // TODO: cached size value
     fn size(&self) -> u64 {
-        self.device.lock().capacity() * (BLOCK_SIZE as u64)
+        self.queue.lock().device.capacity() * (BLOCK_SIZE as u64)
     }
 
     fn block_size(&self) -> usize {
@@ -36,27 +164,31 @@ impl BlockDevice for VirtIoBlkDev {
         self.cache.lock().buffer_heads.len()
     }
 
+    fn buffer_page_cnts(&self) -> usize {
+        self.cache.lock().pages.len()
+    }
+
     fn remove_buffer_page(&self, block_id: usize) {
         self.cache.lock().pages.pop(&block_id);
     }
 
     fn base_read_blocks(&self, block_id: usize, buf: &mut [u8]) {
-        let res = self.device.lock().read_blocks(block_id, buf);
-        if res.is_err() {
-            panic!(
-                "Error when reading VirtIOBlk, block_id {} ,err {:?} ",
-                block_id, res
-            );
-        }
+        let request = if can_sleep() {
+            block_on(self.request(block_id, false, vec![0; buf.len()]))
+        } else {
+            self.request_polling(block_id, false, vec![0; buf.len()])
+        };
+        buf.copy_from_slice(&request.buf);
         // log::warn!("read buf {buf:?}");
         // log::error!("read hash value {}", exam_hash(buf));
     }
 
     fn base_write_blocks(&self, block_id: usize, buf: &[u8]) {
-        self.device
-            .lock()
-            .write_blocks(block_id, buf)
-            .expect("Error when writing VirtIOBlk");
+        if can_sleep() {
+            block_on(self.request(block_id, true, buf.to_vec()));
+        } else {
+            self.request_polling(block_id, true, buf.to_vec());
+        }
 
         // log::warn!("write buf {buf:?}");
         // log::error!("write hash value {}", exam_hash(buf));
@@ -69,32 +201,221 @@ impl BlockDevice for VirtIoBlkDev {
     fn write_block(&self, block_id: usize, buf: &[u8]) {
         self.cache.lock().write_block(block_id, buf)
     }
+
+    fn sync(&self) {
+        self.cache.lock().sync()
+    }
+
+    async fn read_blocks_async(&self, block_id: usize, buf: &mut [u8]) {
+        let request = self.request(block_id, false, vec![0; buf.len()]).await;
+        buf.copy_from_slice(&request.buf);
+    }
+
+    async fn write_blocks_async(&self, block_id: usize, buf: &[u8]) {
+        self.request(block_id, true, buf.to_vec()).await;
+    }
+
+    fn submit_write_blocks(&self, block_id: usize, buf: &[u8]) {
+        self.enqueue_sync(block_id, true, buf.to_vec(), true);
+    }
+
+    fn wait_writes(&self) {
+        let done = || {
+            let mut queue = self.queue.lock();
+            queue.reap();
+            queue.kick(true);
+            !queue.sched.has_pending_writes()
+        };
+        if can_sleep() {
+            block_on(self.waiters.wait_until(done));
+        } else {
+            while !done() {
+                spin_loop();
+            }
+        }
+    }
+
+    fn plug(&self) {
+        self.queue.lock().sched.plug();
+    }
+
+    fn unplug(&self) {
+        let mut queue = self.queue.lock();
+        queue.sched.unplug();
+        queue.kick(false);
+    }
+
+    fn disk_stats(&self) -> DiskStats {
+        self.queue.lock().sched.stats()
+    }
+}
+
+/// Whether the local hart can wait for the interrupt completing a request,
+/// which is not the case with interrupts disabled, e.g. when holding a
+/// `SpinNoIrqLock` or before the interrupts are enabled on boot.
+fn can_sleep() -> bool {
+    is_interrupt_enabled()
+}
+
+/// Waiter of a request staged or in flight, which resolves to the request
+/// once it is done.
+struct BlkWait<'a> {
+    dev: &'a VirtIoBlkDev,
+    id: u64,
+    done: bool,
+}
+
+impl Future for BlkWait<'_> {
+    type Output = Request;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut queue = self.dev.queue.lock();
+        // the interrupt may be routed to other harts, or not be taken yet
+        let reaped = queue.reap();
+        // someone waits for it, so there is no point holding it back
+        queue.kick(true);
+        let request = queue.sched.poll_request(self.id, cx.waker());
+        drop(queue);
+        if reaped {
+            self.dev.waiters.wake_all();
+        }
+        match request {
+            Some(request) => {
+                self.done = true;
+                Poll::Ready(request)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for BlkWait<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.dev.queue.lock().sched.abandon(self.id);
+        }
+    }
 }
 
 impl VirtIoBlkDev {
+    /// Stage a request once it does not overlap pending ones, and return its
+    /// id.
+    fn try_enqueue(
+        &self,
+        block_id: usize,
+        write: bool,
+        buf: &mut Option<Vec<u8>>,
+        detached: bool,
+    ) -> Option<u64> {
+        let mut queue = self.queue.lock();
+        queue.reap();
+        let len = buf.as_ref().unwrap().len();
+        if queue
+            .sched
+            .conflicts(block_id..block_id + len / BLOCK_SIZE, write)
+        {
+            // the pending ones may be held back
+            queue.kick(true);
+            return None;
+        }
+        let id = queue
+            .sched
+            .enqueue(block_id, write, buf.take().unwrap(), detached);
+        queue.kick(false);
+        Some(id)
+    }
+
+    /// Stage a request, waiting by interrupts or by polling for overlapping
+    /// ones to be done as the context allows.
+    fn enqueue_sync(&self, block_id: usize, write: bool, buf: Vec<u8>, detached: bool) -> u64 {
+        let mut buf = Some(buf);
+        let mut id = None;
+        let mut staged = || {
+            id = self.try_enqueue(block_id, write, &mut buf, detached);
+            id.is_some()
+        };
+        if can_sleep() {
+            block_on(self.waiters.wait_until(staged));
+        } else {
+            while !staged() {
+                spin_loop();
+            }
+        }
+        id.unwrap()
+    }
+
+    /// Stage a request and wait until it is done, letting other tasks run
+    /// meanwhile.
+    async fn request(&self, block_id: usize, write: bool, buf: Vec<u8>) -> Request {
+        let mut buf = Some(buf);
+        let mut id = None;
+        self.waiters
+            .wait_until(|| {
+                id = self.try_enqueue(block_id, write, &mut buf, false);
+                id.is_some()
+            })
+            .await;
+        BlkWait {
+            dev: self,
+            id: id.unwrap(),
+            done: false,
+        }
+        .await
+    }
+
+    /// Stage a request and poll the used ring until it is done, for contexts
+    /// that can not wait for interrupts.
+    fn request_polling(&self, block_id: usize, write: bool, buf: Vec<u8>) -> Request {
+        let id = self.enqueue_sync(block_id, write, buf, false);
+        loop {
+            let mut queue = self.queue.lock();
+            let reaped = queue.reap();
+            queue.kick(true);
+            let request = queue.sched.take_done(id);
+            drop(queue);
+            if reaped {
+                self.waiters.wake_all();
+            }
+            if let Some(request) = request {
+                return request;
+            }
+            spin_loop();
+        }
+    }
+}
+
+impl VirtIoBlkDev {
+    /// Initialize the device behind `transport` as the block device of
+    /// `minor`, which is vda for 0, vdb for 1 and so on.
     pub fn try_new(
         mmio_base: usize,
         mmio_size: usize,
-        _irq_no: Option<usize>,
+        irq_no: Option<usize>,
+        minor: usize,
         transport: MmioTransport,
     ) -> Option<Arc<Self>> {
         match VirtIOBlk::<VirtioHalImpl, MmioTransport>::new(transport) {
             Ok(virtio_blk) => {
-                let device = SpinNoIrqLock::new(virtio_blk);
+                let queue = SpinNoIrqLock::new(BlkQueue {
+                    device: virtio_blk,
+                    sched: Scheduler::new(),
+                    inflight: BTreeMap::new(),
+                });
                 let meta = DeviceMeta {
                     dev_id: DevId {
                         major: DeviceMajor::Block,
-                        minor: 0,
+                        minor,
                     },
                     name: "virtio-blk".to_string(),
                     mmio_base,
                     mmio_size,
-                    irq_no: None, // TODO: support interrupt for block device
+                    irq_no,
                     dtype: DeviceType::Block,
                 };
                 let blk_dev = Arc::new(Self {
                     meta,
-                    device,
+                    queue,
+                    waiters: WaitQueue::new(),
                     cache: SpinNoIrqLock::new(BufferCache::new()),
                 });
                 blk_dev.cache.lock().init_device(blk_dev.clone());
@@ -123,7 +444,13 @@ impl Device for VirtIoBlkDev {
     }
 
     fn handle_irq(&self) {
-        // todo!()
+        let mut queue = self.queue.lock();
+        queue.device.ack_interrupt();
+        let reaped = queue.reap();
+        drop(queue);
+        if reaped {
+            self.waiters.wake_all();
+        }
     }
 
     fn as_blk(self: Arc<Self>) -> Option<Arc<dyn BlockDevice>> {
@@ -15,6 +15,29 @@ pub struct CPU {
     pub usable: bool, // is the CPU usable? we need MMU
     pub clock_freq: usize,
     pub timebase_freq: usize,
+    /// Single letter extensions in the ISA string, in the layout of AT_HWCAP.
+    pub hwcap: usize,
+}
+
+/// Parse the single letter extensions reported to user of a ISA string like
+/// "rv64imafdc_zicsr", where extension `x` is bit `x - 'a'` as on Linux.
+fn isa_hwcap(isa: &str) -> usize {
+    let isa = isa.to_ascii_lowercase();
+    let Some(exts) = isa
+        .strip_prefix("rv64")
+        .or_else(|| isa.strip_prefix("rv32"))
+    else {
+        return 0;
+    };
+    let mut hwcap = 0;
+    for ext in exts.split('_').next().unwrap().bytes() {
+        match ext {
+            b'g' => b"imafd".iter().for_each(|e| hwcap |= 1 << (e - b'a')),
+            b'i' | b'm' | b'a' | b'f' | b'd' | b'c' | b'v' => hwcap |= 1 << (ext - b'a'),
+            _ => {}
+        }
+    }
+    hwcap
 }
 
 pub fn probe_cpu(root: &Fdt) -> Option<Vec<CPU>> {
@@ -43,11 +66,13 @@ pub fn probe_cpu(root: &Fdt) -> Option<Vec<CPU>> {
                 })
                 .unwrap_or(0),
             timebase_freq: dtb_cpu.timebase_frequency(),
+            hwcap: 0,
         };
 
         // Mask CPU without MMU
         // Get RISC-V ISA string
         let isa = dtb_cpu.property("riscv,isa").expect("RISC-V ISA not found");
+        cpu.hwcap = isa_hwcap(isa.as_str().unwrap());
         if isa.as_str().unwrap().contains('u') {
             // Privleged mode is in ISA string
             if !isa.as_str().unwrap().contains('s') {
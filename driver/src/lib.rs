@@ -12,10 +12,12 @@ extern crate alloc;
 extern crate macro_utils;
 
 use alloc::sync::Arc;
-use core::fmt::{self, Write};
+use core::{
+    fmt::{self, Write},
+    time::Duration,
+};
 
 use ::net::init_network;
-use async_utils::block_on;
 use config::{board::clock_freq, mm::K_SEG_DTB_BEG};
 use crate_interface::call_interface;
 use device_core::{BlockDevice, CharDevice, DeviceMajor, DeviceType};
@@ -28,7 +30,7 @@ use virtio_drivers::transport;
 
 use crate::{
     net::loopback::LoopbackDev,
-    serial::{Serial, UART0},
+    serial::{Serial, CONSOLE},
 };
 
 mod blk;
@@ -36,15 +38,21 @@ mod cpu;
 mod manager;
 pub mod net;
 mod plic;
+pub mod random;
+mod rtc;
 pub mod serial;
 pub mod virtio;
 
+#[cfg(feature = "selftest")]
+pub use blk::sdhci_selftest;
+
 type Mutex<T> = SpinLock<T>;
 
 pub fn init() {
     let device_tree = unsafe { fdt::Fdt::from_ptr(K_SEG_DTB_BEG as _).expect("Parse DTB failed") };
     config::board::set_clock_freq(device_tree.cpus().next().unwrap().timebase_frequency());
     log::info!("clock freq set to {} Hz", clock_freq());
+    random::init(&device_tree);
 
     init_device_manager();
     let manager = get_device_manager_mut();
@@ -55,26 +63,61 @@ pub fn init() {
     log::info!("Device initialization complete");
     manager.enable_device_interrupts();
     log::info!("External interrupts enabled");
-    let serial = manager
-        .find_devices_by_major(DeviceMajor::Serial)
-        .into_iter()
-        .map(|device| device.as_char().unwrap())
-        .next()
-        .unwrap();
-    UART0.call_once(|| serial.clone());
-
-    let blk = manager
-        .find_devices_by_major(DeviceMajor::Block)
-        .into_iter()
-        .map(|device| device.as_blk().unwrap())
-        .next()
+    let console = manager
+        .console
+        .and_then(|dev_id| manager.get(&dev_id))
+        .map(|device| device.clone().downcast_arc::<Serial>().ok().unwrap())
         .unwrap();
+    CONSOLE.call_once(|| console);
+
+    let blks = manager.find_devices_by_major(DeviceMajor::Block);
+    if blks.is_empty() {
+        panic!(
+            "No block device found: no virtio block device behind any virtio,mmio \
+             transport, and no SD card behind any SD host"
+        );
+    }
+    let root = config::cmdline().get("root").and_then(|root| {
+        let blk =
+            root_minor(root).and_then(|minor| blks.iter().find(|blk| blk.dev_id().minor == minor));
+        if blk.is_none() {
+            log::warn!("No root device {root}, falling back to the first block device");
+        }
+        blk
+    });
+    let blk = root.unwrap_or(&blks[0]).clone().as_blk().unwrap();
+    log::info!(
+        "Root device: {} of minor {}",
+        blk.name(),
+        blk.dev_id().minor
+    );
     BLOCK_DEVICE.call_once(|| blk.clone());
     manager.init_net();
 }
 
+/// Block device of the root, which is named by `root=` in the bootargs, or the
+/// first one found by default.
 pub static BLOCK_DEVICE: Once<Arc<dyn BlockDevice>> = Once::new();
 
+/// Minor of the block device named by `root=`, e.g. 1 of `/dev/vdb` or
+/// `/dev/mmcblk1`, where partitions are not supported.
+fn root_minor(root: &str) -> Option<usize> {
+    let name = root.strip_prefix("/dev/").unwrap_or(root);
+    if let Some(n) = name.strip_prefix("mmcblk") {
+        return n.parse().ok();
+    }
+    match name.strip_prefix("vd")?.as_bytes() {
+        &[c @ b'a'..=b'z'] => Some((c - b'a') as usize),
+        _ => None,
+    }
+}
+
+/// Wall-clock time since the Epoch read from the RTC, or `None` if there is no
+/// RTC.
+pub fn rtc_time() -> Option<Duration> {
+    get_device_manager().rtc.as_ref().map(|rtc| rtc.read_time())
+}
+
 static mut DEVICE_MANAGER: Option<DeviceManager> = None;
 
 pub fn get_device_manager() -> &'static DeviceManager {
@@ -104,8 +147,8 @@ struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        if let Some(serial) = unsafe { UART0.get() } {
-            block_on(async { serial.write(s.as_bytes()).await });
+        if let Some(serial) = unsafe { CONSOLE.get() } {
+            serial.write_sync(s.as_bytes());
             Ok(())
         } else {
             SbiStdout.write_str(s)
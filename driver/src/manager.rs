@@ -3,11 +3,14 @@
 //! Adapted from MankorOS
 
 use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
-use core::char;
+use core::{
+    char,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use arch::interrupts::{disable_interrupt, enable_external_interrupt};
 use config::{
-    board,
+    board::{self, MAX_HARTS},
     mm::{K_SEG_DTB_BEG, VIRT_RAM_OFFSET},
 };
 use device_core::{DevId, Device, DeviceMajor, DeviceMeta, DeviceType};
@@ -16,14 +19,15 @@ use memory::{pte::PTEFlags, PhysAddr};
 use net::init_network;
 
 use crate::{
-    blk::{probe_sdio_blk, probe_vf2_sd, probe_virtio_blk},
+    blk::{probe_sdhci_blk, probe_sdio_blk, probe_vf2_sd, probe_virtio_blk},
     cpu::{probe_cpu, CPU},
     kernel_page_table_mut,
     net::{loopback::LoopbackDev, probe_virtio_net, virtio::VirtIoNetDevImpl},
     plic::{probe_plic, PLIC},
     println,
+    rtc::{probe_rtc, GoldfishRtc},
     serial::probe_char_device,
-    virtio::probe_mmio_device,
+    virtio::{probe_mmio_device, rng::probe_virtio_rng},
 };
 
 /// The DeviceManager struct is responsible for managing the devices within the
@@ -46,10 +50,20 @@ pub struct DeviceManager {
 
     pub net: Option<DeviceMeta>,
 
+    /// Id of the serial port backing the console.
+    pub console: Option<DevId>,
+
+    /// Optional real time clock, the wall-clock time starts at the Epoch
+    /// without it.
+    pub rtc: Option<GoldfishRtc>,
+
     /// A BTreeMap that maps interrupt numbers (irq_no) to device instances
     /// (Arc<dyn Device>). This map is used to quickly locate the device
     /// responsible for handling a specific interrupt.
     pub irq_map: BTreeMap<usize, Arc<dyn Device>>,
+
+    /// Number of interrupts handled by each hart for each IRQ in `irq_map`.
+    irq_counts: BTreeMap<usize, [AtomicUsize; MAX_HARTS]>,
 }
 
 impl DeviceManager {
@@ -62,7 +76,10 @@ impl DeviceManager {
             cpus: Vec::with_capacity(8),
             devices: BTreeMap::new(),
             net: None,
+            console: None,
+            rtc: None,
             irq_map: BTreeMap::new(),
+            irq_counts: BTreeMap::new(),
         }
     }
 
@@ -82,28 +99,48 @@ impl DeviceManager {
         if let Some(cpus) = probe_cpu(&device_tree) {
             self.cpus = cpus;
             config::board::set_harts(self.cpus.len());
+            let hwcap = self
+                .cpus
+                .iter()
+                .fold(usize::MAX, |hwcap, cpu| hwcap & cpu.hwcap);
+            config::board::set_hwcap(if self.cpus.is_empty() { 0 } else { hwcap });
         }
 
-        if let Some(serial) = probe_char_device(&device_tree) {
+        let (serials, console) = probe_char_device(&device_tree);
+        for serial in serials {
+            if serial.dev_id().minor == console {
+                self.console = Some(serial.dev_id());
+            }
             self.devices.insert(serial.dev_id(), serial);
         }
 
-        if let Some(dev) = probe_virtio_blk(&device_tree) {
+        for dev in probe_virtio_blk(&device_tree) {
             self.devices.insert(dev.dev_id(), dev);
         }
         if let Some(dev) = probe_sdio_blk(&device_tree) {
             self.devices.insert(dev.dev_id(), dev);
         }
+        if let Some(dev) = probe_sdhci_blk(&device_tree) {
+            self.devices.insert(dev.dev_id(), dev);
+        }
         // if let Some(dev) = probe_vf2_sd(&device_tree) {
         //     self.devices.insert(dev.dev_id(), dev);
         // }
 
+        if let Some(dev) = probe_virtio_rng(&device_tree) {
+            self.devices.insert(dev.dev_id(), dev);
+        }
+
         self.net = probe_virtio_net(&device_tree);
 
+        self.rtc = probe_rtc(&device_tree);
+
         // Add to interrupt map if have interrupts
         for dev in self.devices.values() {
             if let Some(irq) = dev.irq_no() {
                 self.irq_map.insert(irq, dev.clone());
+                self.irq_counts
+                    .insert(irq, core::array::from_fn(|_| AtomicUsize::new(0)));
             }
         }
     }
@@ -174,44 +211,82 @@ impl DeviceManager {
             .collect()
     }
 
+    /// Route the IRQs of devices to their default harts, which are all harts
+    /// except for block devices kept on hart 0.
     pub fn enable_device_interrupts(&mut self) {
-        for i in 0..board::harts() * 2 {
-            for dev in self.devices.values() {
-                if let Some(irq) = dev.irq_no() {
-                    self.plic().enable_irq(irq, i);
-                    info!("Enable external interrupt:{irq}, context:{i}");
-                }
+        for hart in 0..board::harts() {
+            if let Some(ctx) = self.plic().context(hart) {
+                self.plic().init_context(ctx);
+            }
+        }
+        let all_harts = (1 << board::harts()) - 1;
+        for dev in self.devices.values() {
+            if let Some(irq) = dev.irq_no() {
+                let harts = match dev.dev_id().major {
+                    DeviceMajor::Block => 1,
+                    _ => all_harts,
+                };
+                self.set_irq_affinity(irq, harts);
             }
         }
         unsafe { enable_external_interrupt() }
     }
 
-    pub fn handle_irq(&mut self) {
+    /// Route `irq` to the harts in the bitmask `harts` only.
+    pub fn set_irq_affinity(&self, irq: usize, harts: usize) {
+        for hart in 0..board::harts() {
+            let Some(ctx) = self.plic().context(hart) else {
+                continue;
+            };
+            if harts & (1 << hart) != 0 {
+                self.plic().enable_irq(irq, ctx);
+                info!("Enable external interrupt:{irq}, hart:{hart}, context:{ctx}");
+            } else {
+                self.plic().disable_irq(irq, ctx);
+            }
+        }
+    }
+
+    /// Handle the external interrupts taken by `hart`, claimed from its own
+    /// context.
+    pub fn handle_irq(&self, hart: usize) {
         unsafe { disable_interrupt() }
 
         log::trace!("Handling interrupt");
+        let Some(ctx) = self.plic().context(hart) else {
+            warn!("Interrupt on hart {hart} without a context");
+            return;
+        };
         // First clain interrupt from PLIC
-        if let Some(irq_number) = self.plic().claim_irq(self.irq_context()) {
-            if let Some(dev) = self.irq_map.get(&irq_number) {
-                log::trace!(
-                    "Handling interrupt from device: {:?}, irq: {}",
-                    dev.name(),
-                    irq_number
-                );
-                dev.handle_irq();
-                // Complete interrupt when done
-                self.plic().complete_irq(irq_number, self.irq_context());
-                return;
-            }
-            warn!("Unknown interrupt: {}", irq_number);
+        let Some(irq_number) = self.plic().claim_irq(ctx) else {
+            // claimed by another hart it is routed to as well
+            log::trace!("No interrupt available");
+            return;
+        };
+        if let Some(dev) = self.irq_map.get(&irq_number) {
+            log::trace!(
+                "Handling interrupt from device: {:?}, irq: {}",
+                dev.name(),
+                irq_number
+            );
+            self.irq_counts[&irq_number][hart].fetch_add(1, Ordering::Relaxed);
+            dev.handle_irq();
         } else {
-            warn!("No interrupt available");
+            warn!("Unknown interrupt: {}", irq_number);
         }
+        // Complete interrupt when done
+        self.plic().complete_irq(irq_number, ctx);
     }
 
-    // Calculate the interrupt context from current hart id
-    fn irq_context(&self) -> usize {
-        // TODO:
-        1
+    /// Number of interrupts handled by each hart and the device for each IRQ.
+    pub fn irq_counts(&self) -> impl Iterator<Item = (usize, [usize; MAX_HARTS], &str)> + '_ {
+        self.irq_map.iter().map(|(&irq, dev)| {
+            let counts = &self.irq_counts[&irq];
+            (
+                irq,
+                core::array::from_fn(|hart| counts[hart].load(Ordering::Relaxed)),
+                dev.name(),
+            )
+        })
     }
 }
@@ -12,12 +12,16 @@ use fdt::Fdt;
 use memory::{pte::PTEFlags, PhysAddr};
 
 use self::virtio::NetBufPtr;
-use crate::{kernel_page_table_mut, virtio::probe_mmio_device, Mutex};
+use crate::{
+    kernel_page_table_mut,
+    virtio::{probe_mmio_device, virtio_mmio_nodes},
+    Mutex,
+};
 
 pub fn probe_virtio_net(root: &Fdt) -> Option<DeviceMeta> {
     let device_tree = root;
     let mut net_meta = None;
-    for node in device_tree.find_all_nodes("/soc/virtio_mmio") {
+    for node in virtio_mmio_nodes(device_tree) {
         for reg in node.reg()? {
             let mmio_base_paddr = PhysAddr::from(reg.starting_address as usize);
             let mmio_size = reg.size?;
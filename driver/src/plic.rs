@@ -3,9 +3,19 @@
 //! Adapted from MankorOS
 //!
 //! Controller setup helper
-
-use config::mm::VIRT_RAM_OFFSET;
-use fdt::Fdt;
+//!
+//! Every hart has its own S-mode context, whose enable bits decide which harts
+//! an IRQ is routed to. An IRQ routed to several harts interrupts all of them,
+//! but only one claims it, while the others claim nothing and return. Harts
+//! claiming at the same time from their own contexts are given different IRQs,
+//! since the PLIC never hands out an IRQ claimed and not completed yet, so
+//! devices need no locking against their handlers running on other harts,
+//! only against the tasks using them.
+
+use alloc::collections::BTreeMap;
+
+use config::{board::MAX_HARTS, mm::VIRT_RAM_OFFSET};
+use fdt::{node::FdtNode, Fdt};
 use memory::pte::PTEFlags;
 
 use crate::{kernel_page_table_mut, manager::DeviceManager};
@@ -15,18 +25,33 @@ pub struct PLIC {
     pub mmio_base: usize,
     /// MMIO region size.
     pub mmio_size: usize,
+    /// S-mode context of each hart.
+    contexts: [Option<usize>; MAX_HARTS],
 }
 
 // const PLIC_ADDR: usize = 0xc00_0000 + VIRT_RAM_OFFSET;
 
 impl PLIC {
-    pub fn new(mmio_base: usize, mmio_size: usize) -> PLIC {
+    pub fn new(mmio_base: usize, mmio_size: usize, contexts: [Option<usize>; MAX_HARTS]) -> PLIC {
         PLIC {
             mmio_base,
             mmio_size,
+            contexts,
         }
     }
 
+    /// The S-mode context of `hart`.
+    pub fn context(&self, hart: usize) -> Option<usize> {
+        self.contexts.get(hart).copied().flatten()
+    }
+
+    /// Let all IRQs with a priority above zero interrupt the context.
+    pub fn init_context(&self, ctx_id: usize) {
+        let plic = (self.mmio_base + VIRT_RAM_OFFSET) as *mut plic::Plic;
+        let ctx = PLICCtxWrapper::new(ctx_id);
+        unsafe { (*plic).set_threshold(ctx, 0) };
+    }
+
     pub fn enable_irq(&self, irq: usize, ctx_id: usize) {
         let plic = (self.mmio_base + VIRT_RAM_OFFSET) as *mut plic::Plic;
 
@@ -34,11 +59,17 @@ impl PLIC {
         let src = PLICSrcWrapper::new(irq);
         let ctx = PLICCtxWrapper::new(ctx_id);
 
-        unsafe { (*plic).set_threshold(ctx, 0) };
         unsafe { (*plic).enable(src, ctx) };
         unsafe { (*plic).set_priority(src, 6) };
     }
 
+    pub fn disable_irq(&self, irq: usize, ctx_id: usize) {
+        let plic = (self.mmio_base + VIRT_RAM_OFFSET) as *mut plic::Plic;
+        let src = PLICSrcWrapper::new(irq);
+        let ctx = PLICCtxWrapper::new(ctx_id);
+        unsafe { (*plic).disable(src, ctx) };
+    }
+
     /// Return the IRQ number of the highest priority pending interrupt
     pub fn claim_irq(&self, ctx_id: usize) -> Option<usize> {
         let plic = (self.mmio_base + VIRT_RAM_OFFSET) as *mut plic::Plic;
@@ -64,13 +95,53 @@ pub fn probe_plic(root: &Fdt) -> Option<PLIC> {
         let mmio_size = plic_reg.size.unwrap();
         log::info!("plic base_address:{mmio_base:#x}, size:{mmio_size:#x}");
         kernel_page_table_mut().ioremap(mmio_base, mmio_size, PTEFlags::R | PTEFlags::W);
-        Some(PLIC::new(mmio_base, mmio_size))
+        let contexts = probe_contexts(root, &plic_node).unwrap_or_else(|| {
+            log::warn!("[PLIC probe] no interrupts-extended, assuming M and S contexts per hart");
+            core::array::from_fn(|hart| Some(2 * hart + 1))
+        });
+        log::info!("plic S-mode contexts: {contexts:?}");
+        Some(PLIC::new(mmio_base, mmio_size, contexts))
     } else {
         log::error!("[PLIC probe] faild to find plic");
         None
     }
 }
 
+/// Cause of the supervisor external interrupt, which a context of the PLIC
+/// raises on its hart if it is an S-mode context.
+const SUPERVISOR_EXTERNAL: u32 = 9;
+
+/// Find the S-mode context of each hart from `interrupts-extended` of the
+/// PLIC, which lists the interrupt controller of a hart and the interrupt
+/// raised on it for each context in order.
+fn probe_contexts(root: &Fdt, plic_node: &FdtNode) -> Option<[Option<usize>; MAX_HARTS]> {
+    let cells = plic_node.property("interrupts-extended")?.value;
+    // phandle of the interrupt controller of each hart
+    let mut harts = BTreeMap::new();
+    for cpu in root.find_all_nodes("/cpus/cpu") {
+        let Some(hart) = cpu.property("reg").and_then(|reg| reg.as_usize()) else {
+            continue;
+        };
+        for intc in cpu.children() {
+            if let Some(phandle) = intc.property("phandle").and_then(|p| p.as_usize()) {
+                harts.insert(phandle as u32, hart);
+            }
+        }
+    }
+    let mut contexts = [None; MAX_HARTS];
+    for (ctx_id, pair) in cells.chunks_exact(8).enumerate() {
+        let phandle = u32::from_be_bytes(pair[..4].try_into().unwrap());
+        let cause = u32::from_be_bytes(pair[4..].try_into().unwrap());
+        if cause != SUPERVISOR_EXTERNAL {
+            continue;
+        }
+        if let Some(&hart) = harts.get(&phandle).filter(|&&hart| hart < MAX_HARTS) {
+            contexts[hart] = Some(ctx_id);
+        }
+    }
+    Some(contexts)
+}
+
 #[derive(Debug, Clone, Copy)]
 struct PLICSrcWrapper {
     irq: usize,
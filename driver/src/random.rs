@@ -0,0 +1,171 @@
+//! Kernel entropy pool, from which /dev/urandom and `getrandom` are served.
+//!
+//! The pool is a ChaCha20 key. Input is mixed in by xoring it into the key and
+//! replacing the key with a ChaCha20 block under it. Output is a ChaCha20
+//! keystream under a key extracted from the pool, which is replaced at the same
+//! time, so that earlier output can not be recovered from the pool later.
+//!
+//! Input from hardware sources is credited with entropy, and output debits it.
+//! The sources are asked for more when the entropy drops below
+//! `LOW_WATERMARK`, or when the pool is not reseeded for `RESEED_INTERVAL`.
+
+use core::time::Duration;
+
+use arch::time::{get_time, get_time_duration};
+use fdt::Fdt;
+use sync::mutex::SpinNoIrqLock;
+
+use crate::virtio::rng::request_entropy;
+
+/// Size of the pool, which is the most entropy it is credited with, in bits.
+pub const POOL_BITS: usize = 256;
+/// Entropy below which the hardware sources are asked for more, in bits.
+const LOW_WATERMARK: usize = 128;
+const RESEED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Nonces of ChaCha20 blocks, which separate the uses of the same key.
+const MIX_NONCE: u64 = 0;
+const EXTRACT_NONCE: u64 = 1;
+const STREAM_NONCE: u64 = 2;
+
+struct EntropyPool {
+    key: [u32; 8],
+    /// Counter of the next ChaCha20 block under `key`.
+    counter: u64,
+    /// Entropy credited, in bits.
+    entropy: usize,
+    last_reseed: Duration,
+}
+
+static POOL: SpinNoIrqLock<EntropyPool> = SpinNoIrqLock::new(EntropyPool {
+    key: [0; 8],
+    counter: 0,
+    entropy: 0,
+    last_reseed: Duration::ZERO,
+});
+
+impl EntropyPool {
+    fn next_block(&mut self, nonce: u64) -> [u32; 16] {
+        let block = chacha20_block(&self.key, self.counter, nonce);
+        self.counter = self.counter.wrapping_add(1);
+        block
+    }
+
+    fn mix(&mut self, data: &[u8]) {
+        for chunk in data.chunks(32) {
+            for (word, bytes) in self.key.iter_mut().zip(chunk.chunks(4)) {
+                let mut buf = [0; 4];
+                buf[..bytes.len()].copy_from_slice(bytes);
+                *word ^= u32::from_le_bytes(buf);
+            }
+            let block = self.next_block(MIX_NONCE);
+            self.key.copy_from_slice(&block[..8]);
+        }
+    }
+
+    /// Extract a key for output and replace the key of the pool.
+    fn extract(&mut self) -> [u32; 8] {
+        let block = self.next_block(EXTRACT_NONCE);
+        self.key.copy_from_slice(&block[..8]);
+        block[8..].try_into().unwrap()
+    }
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// The ChaCha20 block function with a 64-bit counter and a 64-bit nonce.
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u32; 16] {
+    let mut state = [0; 16];
+    state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = nonce as u32;
+    state[15] = (nonce >> 32) as u32;
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    for (x, state) in x.iter_mut().zip(state) {
+        *x = x.wrapping_add(state);
+    }
+    x
+}
+
+/// Seed the pool on boot with what differs between boots, i.e. the time and
+/// the seed passed by the bootloader in `/chosen/rng-seed`, neither of which
+/// is credited.
+pub fn init(root: &Fdt) {
+    add_device_randomness(&get_time().to_le_bytes());
+    if let Some(seed) = root
+        .find_node("/chosen")
+        .and_then(|chosen| chosen.property("rng-seed"))
+    {
+        add_device_randomness(seed.value);
+    }
+}
+
+/// Mix `data` which is not secret but differs between boots or machines into
+/// the pool, without crediting any entropy.
+pub fn add_device_randomness(data: &[u8]) {
+    POOL.lock().mix(data);
+}
+
+/// Mix `data` from a hardware source into the pool, crediting it with `bits`
+/// of entropy.
+pub fn add_hwgenerator_randomness(data: &[u8], bits: usize) {
+    let mut pool = POOL.lock();
+    pool.mix(data);
+    pool.entropy = (pool.entropy + bits).min(POOL_BITS);
+    pool.last_reseed = get_time_duration();
+}
+
+/// Entropy the pool is credited with, in bits.
+pub fn entropy_avail() -> usize {
+    POOL.lock().entropy
+}
+
+/// Whether the pool can be credited with more entropy.
+pub fn wants_entropy() -> bool {
+    entropy_avail() < POOL_BITS
+}
+
+/// Fill `buf` with random bytes, which never blocks even if the pool is not
+/// credited with any entropy yet.
+pub fn get_random_bytes(buf: &mut [u8]) {
+    let (key, refill) = {
+        let mut pool = POOL.lock();
+        // tells apart the calls until the pool is seeded by hardware sources
+        pool.mix(&get_time().to_le_bytes());
+        let key = pool.extract();
+        pool.entropy = pool.entropy.saturating_sub(buf.len() * 8);
+        let refill = pool.entropy < LOW_WATERMARK
+            || get_time_duration() - pool.last_reseed >= RESEED_INTERVAL;
+        (key, refill)
+    };
+    if refill {
+        request_entropy();
+    }
+    for (counter, chunk) in buf.chunks_mut(64).enumerate() {
+        let block = chacha20_block(&key, counter as u64, STREAM_NONCE);
+        for (bytes, word) in chunk.chunks_mut(4).zip(block) {
+            bytes.copy_from_slice(&word.to_le_bytes()[..bytes.len()]);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Goldfish RTC, the real time clock of qemu virt machine
+//!
+//! It counts nanoseconds since the Epoch in a 64 bit register, which should be
+//! read by `TIME_LOW` first, since reading it latches the high half into
+//! `TIME_HIGH`.
+
+use core::time::Duration;
+
+use config::mm::VIRT_RAM_OFFSET;
+use fdt::Fdt;
+use memory::pte::PTEFlags;
+
+use crate::kernel_page_table_mut;
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+pub struct GoldfishRtc {
+    /// MMIO base address.
+    pub mmio_base: usize,
+    /// MMIO region size.
+    pub mmio_size: usize,
+}
+
+impl GoldfishRtc {
+    pub fn new(mmio_base: usize, mmio_size: usize) -> Self {
+        Self {
+            mmio_base,
+            mmio_size,
+        }
+    }
+
+    /// Wall-clock time since the Epoch.
+    pub fn read_time(&self) -> Duration {
+        let base = self.mmio_base + VIRT_RAM_OFFSET;
+        let (low, high) = unsafe {
+            let low = ((base + TIME_LOW) as *const u32).read_volatile();
+            let high = ((base + TIME_HIGH) as *const u32).read_volatile();
+            (low, high)
+        };
+        Duration::from_nanos((high as u64) << 32 | low as u64)
+    }
+}
+
+pub fn probe_rtc(root: &Fdt) -> Option<GoldfishRtc> {
+    let rtc_node = root.find_compatible(&["google,goldfish-rtc"])?;
+    let rtc_reg = rtc_node.reg()?.next()?;
+    let mmio_base = rtc_reg.starting_address as usize;
+    let mmio_size = rtc_reg.size.unwrap_or(0x1000);
+    log::info!("rtc base_address:{mmio_base:#x}, size:{mmio_size:#x}");
+    kernel_page_table_mut().ioremap(mmio_base, mmio_size, PTEFlags::R | PTEFlags::W);
+    Some(GoldfishRtc::new(mmio_base, mmio_size))
+}
@@ -2,39 +2,75 @@
 
 pub mod uart8250;
 
-use alloc::{boxed::Box, collections::VecDeque, string::ToString, sync::Arc};
+use alloc::{boxed::Box, format, sync::Arc, vec::Vec};
 use core::{
     cell::UnsafeCell,
     cmp,
     fmt::{self, Debug, Write},
-    task::Waker,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use async_trait::async_trait;
-use async_utils::{block_on, get_waker, suspend_now};
+use async_utils::{block_on, get_waker};
 use config::{board::UART_BUF_LEN, mm::VIRT_RAM_OFFSET};
 use device_core::{DevId, Device, DeviceMajor, DeviceMeta, DeviceType};
-use fdt::Fdt;
-use macro_utils::with_methods;
+use fdt::{node::FdtNode, Fdt};
+use macro_utils::{try_with_methods, with_methods};
 use memory::pte::PTEFlags;
 use ring_buffer::RingBuffer;
 use spin::Once;
-use sync::mutex::SpinNoIrqLock;
+use sync::{mutex::SpinNoIrqLock, WaitQueue};
 
 use super::CharDevice;
 use crate::{
-    kernel_page_table_mut,
+    get_device_manager, kernel_page_table_mut,
     manager::DeviceManager,
     println,
     serial::{self, uart8250::Uart},
 };
 
-pub static UART0: Once<Arc<dyn CharDevice>> = Once::new();
+/// The serial port backing /dev/console and kernel printing.
+pub static CONSOLE: Once<Arc<Serial>> = Once::new();
+
+/// Compatible strings of the uarts driven by `Uart`.
+const UART_COMPATIBLE: &[&str] = &["ns16550a", "snps,dw-apb-uart"];
+
+/// The serial port ttyS<minor>.
+pub fn serial(minor: usize) -> Option<Arc<Serial>> {
+    let dev_id = DevId {
+        major: DeviceMajor::Serial,
+        minor,
+    };
+    get_device_manager()
+        .get(&dev_id)
+        .map(|dev| dev.clone().downcast_arc::<Serial>().ok().unwrap())
+}
+
+/// All serial ports, ordered by their minors.
+pub fn serials() -> Vec<Arc<Serial>> {
+    get_device_manager()
+        .find_devices_by_major(DeviceMajor::Serial)
+        .into_iter()
+        .map(|dev| dev.downcast_arc::<Serial>().ok().unwrap())
+        .collect()
+}
+
+/// Transmit the console output by polling if `polling`, or by interrupts
+/// otherwise. The console polls until the executor is up, and again after a
+/// panic, when interrupts may never be taken to drain the buffer. Switching to
+/// polling flushes the bytes buffered.
+pub fn set_console_polling(polling: bool) {
+    if let Some(serial) = CONSOLE.get() {
+        serial.set_polling(polling);
+    }
+}
 
 trait UartDriver: Send + Sync {
     fn init(&mut self);
     fn putc(&mut self, byte: u8);
     fn getc(&mut self) -> u8;
+    /// Enable or disable the interrupt raised when the transmitter is empty.
+    fn set_tx_irq(&mut self, enable: bool);
     fn poll_in(&self) -> bool;
     fn poll_out(&self) -> bool;
 }
@@ -43,25 +79,37 @@ pub struct Serial {
     meta: DeviceMeta,
     uart: UnsafeCell<Box<dyn UartDriver>>,
     inner: SpinNoIrqLock<SerialInner>,
+    /// Tasks waiting for input.
+    pollin_queue: WaitQueue,
+    /// Tasks waiting for room in `write_buf`.
+    pollout_queue: WaitQueue,
+    /// Transmit by polling instead of interrupts.
+    polling: AtomicBool,
 }
 
 pub struct SerialInner {
     read_buf: RingBuffer,
-    /// Hold wakers of pollin tasks.
-    pollin_queue: VecDeque<Waker>,
+    /// Bytes to transmit, drained by the interrupt handler.
+    write_buf: RingBuffer,
 }
 
 unsafe impl Send for Serial {}
 unsafe impl Sync for Serial {}
 
 impl Serial {
-    fn new(mmio_base: usize, mmio_size: usize, irq_no: usize, driver: Box<dyn UartDriver>) -> Self {
+    fn new(
+        minor: usize,
+        mmio_base: usize,
+        mmio_size: usize,
+        irq_no: usize,
+        driver: Box<dyn UartDriver>,
+    ) -> Self {
         let meta = DeviceMeta {
             dev_id: DevId {
                 major: DeviceMajor::Serial,
-                minor: 0,
+                minor,
             },
-            name: "serial".to_string(),
+            name: format!("ttyS{minor}"),
             mmio_base,
             mmio_size,
             irq_no: Some(irq_no),
@@ -73,8 +121,11 @@ impl Serial {
             uart: UnsafeCell::new(driver),
             inner: SpinNoIrqLock::new(SerialInner {
                 read_buf: RingBuffer::new(UART_BUF_LEN),
-                pollin_queue: VecDeque::new(),
+                write_buf: RingBuffer::new(UART_BUF_LEN),
             }),
+            pollin_queue: WaitQueue::new(),
+            pollout_queue: WaitQueue::new(),
+            polling: AtomicBool::new(true),
         }
     }
 
@@ -82,7 +133,70 @@ impl Serial {
         unsafe { &mut *self.uart.get() }
     }
 
+    fn readable(&self) -> bool {
+        self.uart().poll_in() || self.with_inner(|inner| !inner.read_buf.is_empty())
+    }
+
+    fn writable(&self) -> bool {
+        self.with_inner(|inner| !inner.write_buf.is_full())
+    }
+
+    fn polling(&self) -> bool {
+        self.polling.load(Ordering::Relaxed)
+    }
+
+    fn set_polling(&self, polling: bool) {
+        self.polling.store(polling, Ordering::Relaxed);
+        if polling {
+            // not waited for, as a hart that panicked may hold it forever, and
+            // any other holder flushes the buffer by polling once it is done
+            self.try_with_mut_inner(|inner| self.flush_tx(inner));
+        }
+    }
+
+    /// Move bytes from `write_buf` to the UART while it can take them, and
+    /// keep the transmit interrupt enabled only if some are left.
+    fn start_tx(&self, inner: &mut SerialInner) {
+        let uart = self.uart();
+        while uart.poll_out() {
+            match inner.write_buf.dequeue() {
+                Some(byte) => uart.putc(byte),
+                None => break,
+            }
+        }
+        uart.set_tx_irq(!inner.write_buf.is_empty());
+    }
+
+    /// Transmit all bytes in `write_buf` by polling.
+    fn flush_tx(&self, inner: &mut SerialInner) {
+        let uart = self.uart();
+        uart.set_tx_irq(false);
+        while let Some(byte) = inner.write_buf.dequeue() {
+            uart.putc(byte);
+        }
+    }
+
+    /// Write `buf` without blocking, for `print!` which may be called with
+    /// interrupts disabled or before the executor is up. The bytes are queued
+    /// behind those written before, and the buffer is flushed by polling when
+    /// it is full.
+    pub fn write_sync(&self, buf: &[u8]) {
+        self.with_mut_inner(|inner| {
+            let mut len = inner.write_buf.write(buf);
+            while len < buf.len() {
+                self.flush_tx(inner);
+                len += inner.write_buf.write(&buf[len..]);
+            }
+            if self.polling() {
+                self.flush_tx(inner);
+            } else {
+                self.start_tx(inner);
+            }
+        });
+    }
+
     with_methods!(inner: SerialInner);
+    try_with_methods!(inner: SerialInner);
 }
 
 impl fmt::Debug for Serial {
@@ -113,11 +227,15 @@ impl Device for Serial {
                     break;
                 }
             }
-            // Round Robin
-            if let Some(waiting) = inner.pollin_queue.pop_front() {
-                waiting.wake();
+            if !self.polling() {
+                self.start_tx(inner);
             }
         });
+        // Round Robin
+        self.pollin_queue.wake_one();
+        if self.writable() {
+            self.pollout_queue.wake_all();
+        }
     }
 
     fn as_char(self: Arc<Self>) -> Option<Arc<dyn CharDevice>> {
@@ -128,9 +246,7 @@ impl Device for Serial {
 #[async_trait]
 impl CharDevice for Serial {
     async fn read(&self, buf: &mut [u8]) -> usize {
-        while !self.poll_in().await {
-            suspend_now().await
-        }
+        self.pollin_queue.wait_until(|| self.readable()).await;
         let mut len = 0;
         self.with_mut_inner(|inner| {
             len = inner.read_buf.read(buf);
@@ -145,9 +261,17 @@ impl CharDevice for Serial {
     }
 
     async fn write(&self, buf: &[u8]) -> usize {
-        let uart = self.uart();
-        for &c in buf {
-            uart.putc(c)
+        if self.polling() {
+            self.write_sync(buf);
+            return buf.len();
+        }
+        let mut len = 0;
+        while len < buf.len() {
+            self.pollout_queue.wait_until(|| self.writable()).await;
+            self.with_mut_inner(|inner| {
+                len += inner.write_buf.write(&buf[len..]);
+                self.start_tx(inner);
+            });
         }
         buf.len()
     }
@@ -159,18 +283,102 @@ impl CharDevice for Serial {
             if uart.poll_in() || !inner.read_buf.is_empty() {
                 return true;
             }
-            inner.pollin_queue.push_back(waker);
+            self.pollin_queue.register(&waker);
             false
         })
     }
 
-    // TODO:
     async fn poll_out(&self) -> bool {
-        true
+        let waker = get_waker().await;
+        self.with_mut_inner(|inner| {
+            if !inner.write_buf.is_full() {
+                return true;
+            }
+            self.pollout_queue.register(&waker);
+            false
+        })
     }
 }
 
-pub fn probe_char_device(root: &Fdt) -> Option<Arc<Serial>> {
+/// Probe every uart enabled, numbered as ttyS<N> in the order of their
+/// addresses, and return them with the minor of the console. The console is
+/// named by the last `console=ttyS<N>` in the bootargs, or the stdout of the
+/// chosen node by default.
+pub fn probe_char_device(root: &Fdt) -> (Vec<Arc<Serial>>, usize) {
+    let mut nodes: Vec<_> = root
+        .all_nodes()
+        .filter(|node| {
+            node.compatible()
+                .is_some_and(|c| c.all().any(|c| UART_COMPATIBLE.contains(&c)))
+                && node
+                    .property("status")
+                    .and_then(|status| status.as_str())
+                    .map_or(true, |status| status == "okay" || status == "ok")
+        })
+        .collect();
+    nodes.sort_by_key(|node| node.reg().unwrap().next().unwrap().starting_address as usize);
+    if nodes.is_empty() {
+        panic!("Unsupported serial console");
+    }
+    let serials: Vec<_> = nodes
+        .iter()
+        .enumerate()
+        .map(|(minor, node)| Arc::new(probe_serial(node, minor)))
+        .collect();
+
+    let stdout = probe_stdout(root).and_then(|stdout| {
+        let base = stdout.reg()?.next()?.starting_address as usize;
+        serials.iter().position(|serial| serial.mmio_base() == base)
+    });
+    let console = select_console(config::cmdline().get("console"), stdout, serials.len());
+    println!("Serial ports: {}, console: ttyS{console}", serials.len());
+    (serials, console)
+}
+
+/// Select the console among `ports` serial ports by the value of `console=`
+/// in the bootargs, falling back to the port of the stdout if it is missing
+/// or names no port.
+fn select_console(console: Option<&str>, stdout: Option<usize>, ports: usize) -> usize {
+    match console.and_then(parse_console) {
+        Some(minor) if minor < ports => minor,
+        Some(minor) => {
+            println!("No console ttyS{minor}, falling back to stdout");
+            stdout.unwrap_or(0)
+        }
+        None => stdout.unwrap_or(0),
+    }
+}
+
+/// Parse `N` of `ttyS<N>[,options]`, the value of `console=` in the bootargs.
+fn parse_console(console: &str) -> Option<usize> {
+    console
+        .strip_prefix("ttyS")?
+        .split(',')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Check that the console is selected by `console=` in the bootargs, and by
+/// the stdout otherwise.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    assert_eq!(parse_console("ttyS1"), Some(1));
+    assert_eq!(parse_console("ttyS12,115200n8"), Some(12));
+    assert_eq!(parse_console("tty0"), None);
+    assert_eq!(parse_console("ttyS"), None);
+
+    assert_eq!(select_console(Some("ttyS1"), Some(0), 2), 1);
+    assert_eq!(select_console(Some("ttyS1,115200"), None, 2), 1);
+    // a port that does not exist, or a console that is not a serial port
+    assert_eq!(select_console(Some("ttyS2"), Some(1), 2), 1);
+    assert_eq!(select_console(Some("hvc0"), Some(1), 2), 1);
+    assert_eq!(select_console(None, Some(1), 2), 1);
+    assert_eq!(select_console(None, None, 2), 0);
+}
+
+/// Find the stdout device in the chosen node.
+fn probe_stdout<'b, 'a>(root: &'b Fdt<'a>) -> Option<FdtNode<'b, 'a>> {
     let chosen = root.chosen();
     // Serial
     let mut stdout = chosen.stdout();
@@ -202,45 +410,48 @@ pub fn probe_char_device(root: &Fdt) -> Option<Arc<Serial>> {
         stdout = root.find_compatible(&[
             "ns16550a",
             "snps,dw-apb-uart", // C910, VF2
-            "sifive,uart0",     // sifive_u QEMU (FU540)
         ])
     }
-    let stdout = stdout.expect("Still unable to get stdout device");
-    println!("Stdout: {}", stdout.name);
-
-    let serial = probe_serial_console(&stdout);
-    Some(Arc::new(serial))
+    if let Some(stdout) = &stdout {
+        println!("Stdout: {}", stdout.name);
+    }
+    stdout
 }
 
-/// This guarantees to return a Serial device
-/// The device is not initialized yet
-fn probe_serial_console(stdout: &fdt::node::FdtNode) -> Serial {
-    let reg = stdout.reg().unwrap().next().unwrap();
+/// Probe the uart of `node` as ttyS<minor>, which must be compatible with
+/// `UART_COMPATIBLE`. The device is not initialized yet.
+fn probe_serial(node: &FdtNode, minor: usize) -> Serial {
+    let reg = node.reg().unwrap().next().unwrap();
     let base_paddr = reg.starting_address as usize;
     let size = reg.size.unwrap();
     let base_vaddr = base_paddr + VIRT_RAM_OFFSET;
-    let irq_number = stdout.property("interrupts").unwrap().as_usize().unwrap();
+    let irq_number = node.property("interrupts").unwrap().as_usize().unwrap();
     log::info!("IRQ number: {}", irq_number);
-    let first_compatible = stdout.compatible().unwrap().first();
-    match first_compatible {
+    let compatible = node
+        .compatible()
+        .unwrap()
+        .all()
+        .find(|c| UART_COMPATIBLE.contains(c))
+        .unwrap();
+    match compatible {
         "ns16550a" | "snps,dw-apb-uart" => {
             // VisionFive 2 (FU740)
             // virt QEMU
 
             // Parse clock frequency
-            let freq_raw = stdout
+            let freq_raw = node
                 .property("clock-frequency")
-                .expect("No clock-frequency property of stdout serial device")
+                .expect("No clock-frequency property of serial device")
                 .as_usize()
                 .expect("Parse clock-frequency to usize failed");
             let mut reg_io_width = 1;
-            if let Some(reg_io_width_raw) = stdout.property("reg-io-width") {
+            if let Some(reg_io_width_raw) = node.property("reg-io-width") {
                 reg_io_width = reg_io_width_raw
                     .as_usize()
                     .expect("Parse reg-io-width to usize failed");
             }
             let mut reg_shift = 0;
-            if let Some(reg_shift_raw) = stdout.property("reg-shift") {
+            if let Some(reg_shift_raw) = node.property("reg-shift") {
                 reg_shift = reg_shift_raw
                     .as_usize()
                     .expect("Parse reg-shift to usize failed");
@@ -254,10 +465,10 @@ fn probe_serial_console(stdout: &fdt::node::FdtNode) -> Serial {
                     115200,
                     reg_io_width,
                     reg_shift,
-                    first_compatible == "snps,dw-apb-uart",
+                    compatible == "snps,dw-apb-uart",
                 )
             };
-            Serial::new(base_paddr, size, irq_number, Box::new(uart))
+            Serial::new(minor, base_paddr, size, irq_number, Box::new(uart))
         }
         _ => panic!("Unsupported serial console"),
     }
@@ -9,9 +9,8 @@ use core::fmt::Write;
 use bitflags::{bitflags, Flags};
 use log::info;
 
-use crate::wait_for;
-
 use super::UartDriver;
+use crate::wait_for;
 
 // the UART control registers.
 // some have different meanings for
@@ -174,6 +173,21 @@ impl Uart {
         });
     }
 
+    /// Enables the interrupts of `flags` and disables the others.
+    fn set_int_en(&mut self, flags: IntEnFlags) {
+        unsafe {
+            match self.reg_io_width {
+                1 => (self.mmio_base_vaddr as *mut u8)
+                    .byte_add(IER << self.reg_shift)
+                    .write_volatile(flags.bits()),
+                4 => (self.mmio_base_vaddr as *mut u32)
+                    .byte_add(IER << self.reg_shift)
+                    .write_volatile(flags.bits().into()),
+                _ => unimplemented!(),
+            }
+        }
+    }
+
     fn line_sts_u8(&self) -> LineStsFlags {
         let ptr = self.mmio_base_vaddr as *mut u8;
         unsafe {
@@ -266,6 +280,12 @@ impl UartDriver for Uart {
         self.receive()
     }
 
+    fn set_tx_irq(&mut self, enable: bool) {
+        let mut flags = IntEnFlags::RECEIVED;
+        flags.set(IntEnFlags::SENT, enable);
+        self.set_int_en(flags)
+    }
+
     fn poll_in(&self) -> bool {
         match self.reg_io_width {
             1 => self.line_sts_u8().contains(LineStsFlags::INPUT_FULL),
@@ -0,0 +1,389 @@
+pub mod rng;
+
+use alloc::{collections::BTreeSet, sync::Arc, vec::Vec};
+use core::{mem, ptr::NonNull};
+
+use config::mm::VIRT_RAM_OFFSET;
+use device_core::{error::DevError, Device, DeviceType};
+use fdt::{node::FdtNode, Fdt};
+use log::{error, warn};
+use memory::{alloc_frames, dealloc_frame, pte::PTEFlags, PhysAddr, PhysPageNum, VirtAddr};
+use net::init_network;
+use virtio_drivers::{
+    transport::{
+        self,
+        mmio::{MmioError, MmioTransport, VirtIOHeader},
+        DeviceType as VirtIoDevType, Transport,
+    },
+    BufferDirection,
+};
+
+use crate::{blk::VirtIoBlkDev, kernel_page_table_mut, manager::DeviceManager, BLOCK_DEVICE};
+
+pub(crate) const fn as_dev_err(e: virtio_drivers::Error) -> DevError {
+    use virtio_drivers::Error::*;
+    match e {
+        QueueFull => DevError::BadState,
+        NotReady => DevError::Again,
+        WrongToken => DevError::BadState,
+        AlreadyUsed => DevError::AlreadyExists,
+        InvalidParam => DevError::InvalidParam,
+        DmaError => DevError::NoMemory,
+        IoError => DevError::Io,
+        Unsupported => DevError::Unsupported,
+        ConfigSpaceTooSmall => DevError::BadState,
+        ConfigSpaceMissing => DevError::BadState,
+        _ => DevError::BadState,
+    }
+}
+
+pub(crate) const fn as_dev_type(t: transport::DeviceType) -> Option<DeviceType> {
+    use transport::DeviceType::*;
+    match t {
+        Block => Some(DeviceType::Block),
+        Network => Some(DeviceType::Net),
+        GPU => Some(DeviceType::Display),
+        EntropySource => Some(DeviceType::Rng),
+        _ => None,
+    }
+}
+
+pub(crate) fn probe_devices_common<D, F>(
+    dev_type: DeviceType,
+    mmio_base: PhysAddr,
+    mmio_size: usize,
+    ret: F,
+) -> Option<Arc<D>>
+where
+    D: Device + ?Sized,
+    F: FnOnce(MmioTransport) -> Option<Arc<D>>,
+{
+    if let Some(transport) =
+        probe_mmio_device(mmio_base.to_vaddr().as_mut_ptr(), mmio_size, Some(dev_type))
+    {
+        let dev = ret(transport)?;
+        log::info!("created a new {:?} device: {:?}", dev.dtype(), dev.name());
+        return Some(dev);
+    }
+    None
+}
+
+/// Compatible of virtio MMIO transports.
+const VIRTIO_MMIO_COMPATIBLE: &str = "virtio,mmio";
+
+/// Nodes of the virtio MMIO transports enabled, wherever they are in the tree,
+/// e.g. under `/soc` on qemu virt or directly under the root, with each
+/// register region only once.
+pub(crate) fn virtio_mmio_nodes<'b, 'a>(root: &'b Fdt<'a>) -> Vec<FdtNode<'b, 'a>> {
+    let mut bases = BTreeSet::new();
+    root.all_nodes()
+        .filter(|node| {
+            node.compatible()
+                .is_some_and(|c| c.all().any(|c| c == VIRTIO_MMIO_COMPATIBLE))
+                && node
+                    .property("status")
+                    .and_then(|status| status.as_str())
+                    .map_or(true, |status| status == "okay" || status == "ok")
+        })
+        .filter(|node| match node.reg().and_then(|mut reg| reg.next()) {
+            Some(reg) => bases.insert(reg.starting_address as usize),
+            None => {
+                warn!("[virtio] {} has no registers, skip it", node.name);
+                false
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn probe_mmio_device(
+    reg_base: *mut u8,
+    reg_size: usize,
+    type_match: Option<DeviceType>,
+) -> Option<MmioTransport> {
+    use transport::mmio::VirtIOHeader;
+
+    let header = NonNull::new(reg_base as *mut VirtIOHeader).unwrap();
+    let paddr = VirtAddr::from(reg_base as usize).to_paddr().bits();
+    match unsafe { MmioTransport::new(header) } {
+        Ok(transport) => {
+            if type_match.is_none() || as_dev_type(transport.device_type()) == type_match {
+                log::info!(
+                    "Detected virtio MMIO device with vendor id: {:#X}, device type: {:?}, version: {:?}",
+                    transport.vendor_id(),
+                    transport.device_type(),
+                    transport.version(),
+                );
+                Some(transport)
+            } else {
+                mem::forget(transport);
+                None
+            }
+        }
+        // an empty slot, as most of the transports on qemu virt
+        Err(MmioError::ZeroDeviceId) => {
+            log::debug!("[virtio] no device behind the transport at PA:{paddr:#x}");
+            None
+        }
+        Err(e) => {
+            warn!(
+                "[virtio] transport at [PA:{:#x}, PA:{:#x}) is unusable: {e:?}",
+                paddr,
+                paddr + reg_size
+            );
+            None
+        }
+    }
+}
+
+impl DeviceManager {
+    pub fn probe_virtio_device(&mut self, root: &Fdt) {
+        let mut init_net: bool = false;
+        let nodes = virtio_mmio_nodes(root);
+        let mut reg;
+        let mut base_paddr;
+        let mut size;
+        let mut irq_no;
+        let mut base_vaddr;
+        let mut header;
+
+        for node in nodes {
+            reg = node.reg().unwrap().next().unwrap();
+            base_paddr = reg.starting_address as usize;
+            size = reg.size.unwrap();
+            irq_no = node.property("interrupts").unwrap().as_usize().unwrap();
+            base_vaddr = base_paddr + VIRT_RAM_OFFSET;
+            header = NonNull::new(base_vaddr as *mut VirtIOHeader).unwrap();
+
+            // First map mmio memory since we need to read header.
+            kernel_page_table_mut().ioremap(base_paddr, size, PTEFlags::R | PTEFlags::W);
+            match unsafe { MmioTransport::new(header) } {
+                Ok(transport) => match transport.device_type() {
+                    VirtIoDevType::Block => {
+                        if let Some(blk) =
+                            VirtIoBlkDev::try_new(base_paddr, size, None, 0, transport)
+                        {
+                            BLOCK_DEVICE.call_once(|| blk.clone());
+                            self.devices.insert(blk.dev_id(), blk);
+                            continue;
+                        }
+                    }
+                    // VirtIoDevType::Network => {
+                    //     match NetDevice::try_new(transport) {
+                    //         Ok(net) => {
+                    //             init_network(net, false);
+                    //             init_net = true;
+                    //             continue;
+                    //         }
+                    //         Err(e) => error!(
+                    //             "[virtio-net] failed to initialize MMIO device at [PA:{:#x},
+                    // PA:{:#x}), {e:?}",
+                    //             base_paddr,
+                    //             base_paddr + size
+                    //         ),
+                    //     };
+                    // }
+                    _ => {
+                        warn!(
+                            "Unsupported VirtIO device type: {:?}",
+                            transport.device_type()
+                        );
+                    }
+                },
+                Err(e) => {
+                    warn!(
+                        "[virtio] transport of {} at [PA:{:#x}, PA:{:#x}) is unusable: {e:?}",
+                        node.name,
+                        base_paddr,
+                        base_paddr + size
+                    );
+                }
+            };
+            kernel_page_table_mut().iounmap(base_vaddr, size);
+        }
+
+        // if !init_net {
+        //     log::info!("[init_net] can't find qemu virtio-net. use
+        // LoopbackDev to test");     init_network(LoopbackDev::new(),
+        // true); }
+    }
+}
+
+pub struct VirtioHalImpl;
+
+unsafe impl virtio_drivers::Hal for VirtioHalImpl {
+    fn dma_alloc(
+        pages: usize,
+        _direction: BufferDirection,
+    ) -> (virtio_drivers::PhysAddr, NonNull<u8>) {
+        let pa = alloc_frames(pages);
+        let ppn = pa.floor();
+        for ppn in ppn..ppn + pages {
+            ppn.clear_page();
+        }
+        (pa.0, NonNull::new(pa.to_vaddr().as_mut_ptr()).unwrap())
+    }
+
+    unsafe fn dma_dealloc(
+        paddr: virtio_drivers::PhysAddr,
+        _vaddr: NonNull<u8>,
+        pages: usize,
+    ) -> i32 {
+        let pa = PhysAddr::from(paddr);
+        let ppn_base: PhysPageNum = pa.into();
+        // PERF:参考arceos或许可以一次性删除多个页面？
+        for ppn in ppn_base..ppn_base + pages {
+            dealloc_frame(ppn);
+        }
+        0
+    }
+
+    unsafe fn mmio_phys_to_virt(paddr: virtio_drivers::PhysAddr, _size: usize) -> NonNull<u8> {
+        NonNull::new(PhysAddr::from(paddr).to_vaddr().as_mut_ptr()).unwrap()
+    }
+
+    unsafe fn share(
+        buffer: NonNull<[u8]>,
+        _direction: BufferDirection,
+    ) -> virtio_drivers::PhysAddr {
+        VirtAddr::from(buffer.as_ptr() as *const u8 as usize)
+            .to_paddr()
+            .bits()
+            .into()
+    }
+
+    unsafe fn unshare(
+        _paddr: virtio_drivers::PhysAddr,
+        _buffer: NonNull<[u8]>,
+        _direction: BufferDirection,
+    ) {
+    }
+}
+
+/// Check that virtio MMIO transports are found by their compatible wherever
+/// they are in the tree, each register region once, with a device tree built
+/// here rather than the one passed by the bootloader.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    const FDT_MAGIC: u32 = 0xd00d_feed;
+    const FDT_BEGIN_NODE: u32 = 1;
+    const FDT_END_NODE: u32 = 2;
+    const FDT_PROP: u32 = 3;
+    const FDT_END: u32 = 9;
+    const HEADER_SIZE: usize = 40;
+    const RSVMAP_SIZE: usize = 16;
+
+    #[derive(Default)]
+    struct Builder {
+        structs: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn token(&mut self, token: u32) {
+            self.structs.extend_from_slice(&token.to_be_bytes());
+        }
+
+        fn pad(&mut self) {
+            while self.structs.len() % 4 != 0 {
+                self.structs.push(0);
+            }
+        }
+
+        fn begin_node(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structs.extend_from_slice(name.as_bytes());
+            self.structs.push(0);
+            self.pad();
+            self
+        }
+
+        fn end_node(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE);
+            self
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_off = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.token(FDT_PROP);
+            self.token(value.len() as u32);
+            self.token(name_off);
+            self.structs.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        fn prop_str(&mut self, name: &str, value: &str) -> &mut Self {
+            let mut bytes = Vec::from(value.as_bytes());
+            bytes.push(0);
+            self.prop(name, &bytes)
+        }
+
+        fn prop_cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let bytes: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+            self.prop(name, &bytes)
+        }
+
+        fn virtio(&mut self, name: &str, base: u32, status: Option<&str>) -> &mut Self {
+            self.begin_node(name)
+                .prop_str("compatible", "virtio,mmio")
+                .prop_cells("reg", &[0, base, 0, 0x1000]);
+            if let Some(status) = status {
+                self.prop_str("status", status);
+            }
+            self.end_node()
+        }
+
+        fn finish(&mut self) -> Vec<u8> {
+            self.token(FDT_END);
+            let off_struct = HEADER_SIZE + RSVMAP_SIZE;
+            let off_strings = off_struct + self.structs.len();
+            let total = off_strings + self.strings.len();
+            let header = [
+                FDT_MAGIC,
+                total as u32,
+                off_struct as u32,
+                off_strings as u32,
+                HEADER_SIZE as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structs.len() as u32,
+            ];
+            let mut blob: Vec<u8> = header.iter().flat_map(|word| word.to_be_bytes()).collect();
+            blob.resize(off_struct, 0);
+            blob.extend_from_slice(&self.structs);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    let mut builder = Builder::default();
+    builder
+        .begin_node("")
+        .prop_cells("#address-cells", &[2])
+        .prop_cells("#size-cells", &[2])
+        .virtio("virtio_mmio@10001000", 0x1000_1000, None)
+        .begin_node("platform-bus@20000000")
+        .prop_cells("#address-cells", &[2])
+        .prop_cells("#size-cells", &[2])
+        .virtio("virtio@20001000", 0x2000_1000, Some("okay"))
+        // the same transport described twice
+        .virtio("virtio@10001000", 0x1000_1000, None)
+        .virtio("virtio@20002000", 0x2000_2000, Some("disabled"))
+        .begin_node("serial@20003000")
+        .prop_str("compatible", "ns16550a")
+        .prop_cells("reg", &[0, 0x2000_3000, 0, 0x100])
+        .end_node()
+        .end_node()
+        .end_node();
+    let blob = builder.finish();
+    let root = Fdt::new(&blob).expect("synthetic device tree is malformed");
+    let bases: Vec<usize> = virtio_mmio_nodes(&root)
+        .iter()
+        .map(|node| node.reg().unwrap().next().unwrap().starting_address as usize)
+        .collect();
+    assert_eq!(bases, [0x1000_1000, 0x2000_1000]);
+}
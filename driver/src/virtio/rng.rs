@@ -0,0 +1,285 @@
+//! Virtio entropy device, which feeds the kernel entropy pool.
+//!
+//! The device has a single virtqueue, into which the driver puts buffers that
+//! the device fills with random bytes. One buffer is kept in flight while the
+//! pool wants entropy, and the bytes are mixed into the pool by the interrupt
+//! handler when the buffer is returned.
+//!
+//! `virtio-drivers` has no driver for this device, so the virtqueue is laid out
+//! here in the legacy layout, which modern devices accept as well.
+
+use alloc::{string::ToString, sync::Arc};
+use core::{
+    mem::size_of,
+    ptr::{addr_of_mut, read_volatile, write_volatile},
+    sync::atomic::{fence, Ordering},
+};
+
+use config::mm::PAGE_SIZE;
+use device_core::{DevId, Device, DeviceMajor, DeviceMeta, DeviceType};
+use fdt::Fdt;
+use log::error;
+use memory::{pte::PTEFlags, PhysAddr};
+use spin::Once;
+use sync::mutex::SpinNoIrqLock;
+use virtio_drivers::{
+    transport::{mmio::MmioTransport, DeviceStatus, Transport},
+    BufferDirection, Hal,
+};
+
+use super::{probe_mmio_device, virtio_mmio_nodes, VirtioHalImpl};
+use crate::{kernel_page_table_mut, random};
+
+/// Minor number of the hardware random number generator, as in Linux.
+const HWRNG_MINOR: usize = 183;
+const QUEUE_SIZE: usize = 4;
+/// Bytes requested from the device at a time.
+const BUF_SIZE: usize = 64;
+/// Pages of the virtqueue and the buffer.
+const QUEUE_PAGES: usize = 2;
+/// Offset of the used ring, which is page aligned in the legacy layout.
+const USED_OFFSET: usize = PAGE_SIZE;
+const BUF_OFFSET: usize = USED_OFFSET + PAGE_SIZE / 2;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+struct RngQueue {
+    transport: MmioTransport,
+    /// Physical address of the virtqueue and the buffer.
+    paddr: PhysAddr,
+    vaddr: *mut u8,
+    avail_idx: u16,
+    last_used_idx: u16,
+    /// Whether the buffer is in flight.
+    pending: bool,
+}
+
+impl RngQueue {
+    fn desc(&self) -> *mut Descriptor {
+        self.vaddr as _
+    }
+
+    fn avail(&self) -> *mut AvailRing {
+        unsafe { self.vaddr.add(size_of::<Descriptor>() * QUEUE_SIZE) as _ }
+    }
+
+    fn used(&self) -> *mut UsedRing {
+        unsafe { self.vaddr.add(USED_OFFSET) as _ }
+    }
+
+    fn buf(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.vaddr.add(BUF_OFFSET), BUF_SIZE) }
+    }
+
+    /// Put the buffer into the virtqueue unless it is in flight already.
+    fn request(&mut self) {
+        if self.pending {
+            return;
+        }
+        unsafe {
+            write_volatile(
+                self.desc(),
+                Descriptor {
+                    addr: (self.paddr.bits() + BUF_OFFSET) as u64,
+                    len: BUF_SIZE as u32,
+                    flags: DESC_F_WRITE,
+                    next: 0,
+                },
+            );
+            let avail = self.avail();
+            let slot = self.avail_idx as usize % QUEUE_SIZE;
+            write_volatile(addr_of_mut!((*avail).ring[slot]), 0);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            write_volatile(addr_of_mut!((*avail).idx), self.avail_idx);
+            fence(Ordering::SeqCst);
+        }
+        self.pending = true;
+        self.transport.notify(0);
+    }
+
+    /// Take the bytes filled in the buffer if it is returned by the device.
+    fn take(&mut self) -> Option<&[u8]> {
+        let used = self.used();
+        let used_idx = unsafe { read_volatile(addr_of_mut!((*used).idx)) };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let slot = self.last_used_idx as usize % QUEUE_SIZE;
+        let len = unsafe { read_volatile(addr_of_mut!((*used).ring[slot].len)) } as usize;
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        self.pending = false;
+        Some(&self.buf()[..len.min(BUF_SIZE)])
+    }
+}
+
+pub struct VirtIoRngDev {
+    meta: DeviceMeta,
+    queue: SpinNoIrqLock<RngQueue>,
+}
+
+unsafe impl Send for VirtIoRngDev {}
+unsafe impl Sync for VirtIoRngDev {}
+
+/// The entropy device, if there is one.
+static HWRNG: Once<Arc<VirtIoRngDev>> = Once::new();
+
+/// Ask the entropy device for random bytes, which are mixed into the pool
+/// once they arrive. Does nothing without the device.
+pub fn request_entropy() {
+    if let Some(rng) = HWRNG.get() {
+        rng.queue.lock().request();
+    }
+}
+
+impl VirtIoRngDev {
+    pub fn try_new(
+        mmio_base: usize,
+        mmio_size: usize,
+        irq_no: Option<usize>,
+        mut transport: MmioTransport,
+    ) -> Option<Arc<Self>> {
+        let max_size = transport.max_queue_size(0) as usize;
+        if max_size < QUEUE_SIZE {
+            error!("[virtio-rng] virtqueue too small, max size {max_size}");
+            return None;
+        }
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        // no features are supported
+        transport.read_device_features();
+        transport.write_driver_features(0);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let (paddr, vaddr) = VirtioHalImpl::dma_alloc(QUEUE_PAGES, BufferDirection::DeviceToDriver);
+        let avail_offset = size_of::<Descriptor>() * QUEUE_SIZE;
+        transport.queue_set(
+            0,
+            QUEUE_SIZE as u32,
+            paddr,
+            paddr + avail_offset,
+            paddr + USED_OFFSET,
+        );
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE
+                | DeviceStatus::DRIVER
+                | DeviceStatus::FEATURES_OK
+                | DeviceStatus::DRIVER_OK,
+        );
+
+        let queue = RngQueue {
+            transport,
+            paddr: PhysAddr::from(paddr),
+            vaddr: vaddr.as_ptr(),
+            avail_idx: 0,
+            last_used_idx: 0,
+            pending: false,
+        };
+        let meta = DeviceMeta {
+            dev_id: DevId {
+                major: DeviceMajor::Misc,
+                minor: HWRNG_MINOR,
+            },
+            name: "virtio-rng".to_string(),
+            mmio_base,
+            mmio_size,
+            irq_no,
+            dtype: DeviceType::Rng,
+        };
+        Some(Arc::new(Self {
+            meta,
+            queue: SpinNoIrqLock::new(queue),
+        }))
+    }
+}
+
+impl Device for VirtIoRngDev {
+    fn meta(&self) -> &DeviceMeta {
+        &self.meta
+    }
+
+    fn init(&self) {
+        // seeds the pool once interrupts are enabled
+        self.queue.lock().request();
+    }
+
+    fn handle_irq(&self) {
+        let mut queue = self.queue.lock();
+        queue.transport.ack_interrupt();
+        if let Some(bytes) = queue.take() {
+            random::add_hwgenerator_randomness(bytes, bytes.len() * 8);
+            if random::wants_entropy() {
+                queue.request();
+            }
+        }
+    }
+}
+
+pub fn probe_virtio_rng(root: &Fdt) -> Option<Arc<VirtIoRngDev>> {
+    let mut dev = None;
+    for node in virtio_mmio_nodes(root) {
+        for reg in node.reg()? {
+            let mmio_base_paddr = PhysAddr::from(reg.starting_address as usize);
+            let mmio_size = reg.size?;
+            let irq_no = node.property("interrupts").and_then(|i| i.as_usize());
+            kernel_page_table_mut().ioremap(
+                mmio_base_paddr.bits(),
+                mmio_size,
+                PTEFlags::R | PTEFlags::W,
+            );
+            dev = probe_mmio_device(
+                mmio_base_paddr.to_vaddr().as_mut_ptr(),
+                mmio_size,
+                Some(DeviceType::Rng),
+            )
+            .and_then(|t| VirtIoRngDev::try_new(mmio_base_paddr.bits(), mmio_size, irq_no, t));
+            kernel_page_table_mut().iounmap(mmio_base_paddr.to_vaddr().bits(), mmio_size);
+            if dev.is_some() {
+                break;
+            }
+        }
+        if dev.is_some() {
+            break;
+        }
+    }
+    match &dev {
+        Some(rng) => {
+            HWRNG.call_once(|| rng.clone());
+        }
+        None => log::warn!("No virtio entropy device found"),
+    }
+    dev
+}
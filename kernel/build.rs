@@ -3,13 +3,18 @@ use std::{
     fs::{read_dir, File},
     io::{Result, Write},
     path::PathBuf,
+    process::Command,
 };
 
 fn main() {
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let out_dir = env::var("OUT_DIR").unwrap();
 
-    let link_script = fs::read_to_string(PathBuf::from(manifest_dir).join("linker.ld")).unwrap();
+    let link_script_path = PathBuf::from(&manifest_dir).join("linker.ld");
+    let link_script = fs::read_to_string(&link_script_path).unwrap();
+    // once any file is listed, the script is only rerun on changes to those
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", link_script_path.display());
 
     let ram_size = config::mm::RAM_SIZE - config::mm::KERNEL_OFFSET;
 
@@ -21,4 +26,33 @@ fn main() {
     let dest = PathBuf::from(out_dir).join("linker.ld");
     fs::write(&dest, new).unwrap();
     println!("cargo:rustc-link-arg=-T{}", dest.display());
+
+    // Embedded in the version of uname, and updated on commits and checkouts,
+    // which change HEAD or the branch it points to.
+    let git_hash = git(&["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        let git_dir = PathBuf::from(manifest_dir).join(git_dir);
+        let watched = [
+            Some(git_dir.join("HEAD")),
+            git(&["symbolic-ref", "-q", "HEAD"]).map(|head_ref| git_dir.join(head_ref)),
+            // refs of branches are packed here by gc
+            Some(git_dir.join("packed-refs")),
+        ];
+        // a file missing would rerun the script on every build
+        for path in watched.into_iter().flatten().filter(|path| path.exists()) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
+
+/// Trimmed output of a git command run in the kernel crate, if it succeeds.
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
 }
@@ -1,5 +1,11 @@
-use config::{board, mm::HART_START_ADDR};
+use alloc::vec::Vec;
+
+use config::{
+    board,
+    mm::{HART_START_ADDR, VIRT_RAM_OFFSET},
+};
 use driver::println;
+use fdt::Fdt;
 
 const BOOT_BANNER: &str = r#"
     ____  __                     _
@@ -13,6 +19,24 @@ pub fn print_banner() {
     println!("{}", BOOT_BANNER);
 }
 
+/// Save the bootargs in the device tree at physical address `dtb_addr` as the
+/// command line, before anything configured by it is initialized.
+pub fn init_cmdline(dtb_addr: usize) {
+    let fdt = unsafe { Fdt::from_ptr((dtb_addr + VIRT_RAM_OFFSET) as *const u8) }
+        .expect("Parse DTB failed");
+    if let Some(bootargs) = fdt.chosen().bootargs() {
+        config::set_cmdline(bootargs);
+    }
+}
+
+/// Warn about the arguments in the command line which are ignored.
+pub fn warn_unknown_cmdline() {
+    let unknown: Vec<_> = config::cmdline().unknown_keys().collect();
+    if !unknown.is_empty() {
+        log::warn!("[kernel] unknown bootargs ignored: {}", unknown.join(" "));
+    }
+}
+
 /// Clear BSS segment at start up.
 pub fn clear_bss() {
     extern "C" {
@@ -1,19 +1,34 @@
 //! Impls of traits defined in other crates.
 
-use alloc::{fmt, string::ToString, sync::Arc};
+use alloc::{boxed::Box, fmt, string::ToString, sync::Arc};
+use core::{fmt::Write, future::Future, pin::Pin, sync::atomic::Ordering};
 
-use config::mm::VIRT_RAM_OFFSET;
+use async_utils::BlockOnIf;
+use config::{
+    board::{self, MAX_HARTS},
+    mm::VIRT_RAM_OFFSET,
+};
 use driver::KernelPageTableIf;
+use executor::ExecutorIf;
 use log::Level;
 use logging::{ColorCode, LogIf};
-use memory::{KernelMappingIf, PageTable, PhysAddr, VirtAddr};
+use memory::{asid::AsidIf, KernelMappingIf, PageTable, PhysAddr, VirtAddr};
 use net::HasSignalIf;
-use vfs::{procfs::KernelProcIf, sys_root_dentry};
-use vfs_core::{Dentry, SysRootDentryIf};
+use timer::{TimerIf, TIMER_MANAGER};
+use vfs::{pipefs::PipeInode, procfs::KernelProcIf, sys_root_dentry};
+use vfs_core::{Dentry, FifoIf, Inode, ReadAheadIf, SuperBlock, SysRootDentryIf};
 
 use crate::{
-    mm::kernel_page_table_mut,
-    processor::hart::{current_task_ref, local_hart},
+    mm::{
+        kernel_page_table_mut,
+        memory_space::vm_area::{
+            COW_COPIES, COW_REUSES, THP_FAULT_ALLOC, THP_FAULT_FALLBACK, THP_SPLIT_PAGE,
+        },
+        swap::{self, PSWPIN, PSWPOUT},
+    },
+    processor::hart::{self, current_task_ref, local_hart},
+    syscall,
+    task::spawn_kernel_task,
 };
 
 /// Print msg with color
@@ -83,6 +98,16 @@ impl HasSignalIf for HasSignalIfImpl {
         let mask = *task.sig_mask_ref();
         task.with_sig_pending(|pending| pending.has_expect_signals(!mask))
     }
+
+    fn set_interruptable() {
+        let task = current_task_ref();
+        task.set_interruptable();
+        task.set_wake_up_signal(!*task.sig_mask_ref());
+    }
+
+    fn set_running() {
+        current_task_ref().set_running();
+    }
 }
 
 struct KernelMappingIfImpl;
@@ -102,6 +127,73 @@ impl KernelMappingIf for KernelMappingIfImpl {
     }
 }
 
+struct AsidIfImpl;
+
+#[crate_interface::impl_interface]
+impl AsidIf for AsidIfImpl {
+    fn hart_id() -> usize {
+        local_hart().hart_id()
+    }
+}
+
+struct ExecutorIfImpl;
+
+#[crate_interface::impl_interface]
+impl ExecutorIf for ExecutorIfImpl {
+    fn hart_id() -> usize {
+        local_hart().hart_id()
+    }
+
+    fn send_ipi(hart_id: usize) {
+        arch::interrupts::send_ipi(hart_id);
+    }
+}
+
+struct BlockOnIfImpl;
+
+#[crate_interface::impl_interface]
+impl BlockOnIf for BlockOnIfImpl {
+    fn hart_id() -> usize {
+        local_hart().hart_id()
+    }
+
+    fn run_one() -> bool {
+        if !executor::has_task() {
+            return false;
+        }
+        // the blocked context is saved like kernel preemption
+        let mut old_hart = local_hart().enter_preempt_switch();
+        executor::run_one();
+        local_hart().leave_preempt_switch(&mut old_hart);
+        true
+    }
+
+    fn idle() {
+        hart::idle();
+    }
+
+    fn kick(hart_id: usize) {
+        arch::interrupts::send_ipi(hart_id);
+    }
+
+    fn in_irq() -> bool {
+        executor::in_irq()
+    }
+
+    fn can_switch() -> bool {
+        arch::interrupts::is_interrupt_enabled() && sync::mutex::local_held_locks() == 0
+    }
+}
+
+struct TimerIfImpl;
+
+#[crate_interface::impl_interface]
+impl TimerIf for TimerIfImpl {
+    fn hart_id() -> usize {
+        local_hart().hart_id()
+    }
+}
+
 struct KernelProcIfImpl;
 
 #[crate_interface::impl_interface]
@@ -109,6 +201,81 @@ impl KernelProcIf for KernelProcIfImpl {
     fn exe() -> alloc::string::String {
         current_task_ref().elf().dentry().path()
     }
+
+    fn stat() -> alloc::string::String {
+        current_task_ref().proc_stat()
+    }
+
+    fn vmstat() -> alloc::string::String {
+        alloc::format!(
+            "cow_copy {}\ncow_reuse {}\npswpin {}\npswpout {}\nthp_fault_alloc {}\nthp_fault_fallback {}\nthp_split_page {}\n",
+            COW_COPIES.load(Ordering::Relaxed),
+            COW_REUSES.load(Ordering::Relaxed),
+            PSWPIN.load(Ordering::Relaxed),
+            PSWPOUT.load(Ordering::Relaxed),
+            THP_FAULT_ALLOC.load(Ordering::Relaxed),
+            THP_FAULT_FALLBACK.load(Ordering::Relaxed),
+            THP_SPLIT_PAGE.load(Ordering::Relaxed)
+        )
+    }
+
+    fn schedstat() -> alloc::string::String {
+        let mut info = alloc::string::String::new();
+        for hart_id in 0..board::harts().min(MAX_HARTS) {
+            let (fetched, stolen) = executor::queue_stats(hart_id);
+            let _ = writeln!(info, "cpu{hart_id} {fetched} {stolen}");
+        }
+        info
+    }
+
+    fn timer_stats() -> alloc::string::String {
+        let stats = TIMER_MANAGER.stats();
+        alloc::format!(
+            "armed {}\nfired {}\ncancelled {}\nqueued {}\n",
+            stats.armed,
+            stats.fired,
+            stats.cancelled,
+            stats.queued
+        )
+    }
+
+    fn swap_pages() -> (usize, usize) {
+        swap::swap_stat()
+    }
+
+    fn syscall_stats() -> alloc::string::String {
+        syscall::stats::serialize()
+    }
+
+    fn reset_syscall_stats() {
+        syscall::stats::reset()
+    }
+
+    fn syscall_stats_enabled() -> bool {
+        syscall::stats::enabled()
+    }
+
+    fn set_syscall_stats_enabled(enabled: bool) {
+        syscall::stats::set_enabled(enabled)
+    }
+}
+
+struct FifoIfImpl;
+
+#[crate_interface::impl_interface]
+impl FifoIf for FifoIfImpl {
+    fn new_fifo(super_block: Arc<dyn SuperBlock>) -> Arc<dyn Inode> {
+        PipeInode::new_fifo(super_block)
+    }
+}
+
+struct ReadAheadIfImpl;
+
+#[crate_interface::impl_interface]
+impl ReadAheadIf for ReadAheadIfImpl {
+    fn spawn_read_ahead(future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        spawn_kernel_task(future);
+    }
 }
 
 struct SysRootDentryIfImpl;
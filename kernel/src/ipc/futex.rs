@@ -1,13 +1,36 @@
-use alloc::vec::Vec;
-use core::{cmp::min, hash::Hash, ops::DerefMut, task::Waker};
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    cmp::min,
+    hash::Hash,
+    ops::DerefMut,
+    sync::atomic::{AtomicU32, Ordering},
+    task::Waker,
+};
 
+use executor::SchedAttr;
 use hashbrown::HashMap;
 use memory::{PhysAddr, VirtAddr};
+use page::Page;
 use spin::Lazy;
 use sync::mutex::SpinNoIrqLock;
 use systype::{SysError, SyscallResult};
+
+use crate::processor::env::within_sum;
 type Tid = usize;
 
+/// Bitset that matches every waiter, plain `FUTEX_WAIT` and `FUTEX_WAKE`
+/// behave as if this bitset is given.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+// Bits of the futex word of a priority-inheritance futex
+/// Set when there are waiters blocked on the PI futex, so that the owner has
+/// to unlock it through `FUTEX_UNLOCK_PI`.
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// Set when the owner of the PI futex died without unlocking it.
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// Mask of the tid of the owner of the PI futex.
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
 #[derive(Clone, Copy, Default)]
 #[repr(C)]
 pub struct RobustListHead {
@@ -29,10 +52,47 @@ pub enum FutexHashKey {
     Private { mm: usize, vaddr: VirtAddr },
 }
 
+impl FutexHashKey {
+    /// Returns the futex word of the key.
+    ///
+    /// # Safety
+    ///
+    /// The page of the futex word must be mapped. A private key can only be
+    /// accessed within the address space it belongs to with SUM set.
+    pub unsafe fn word(&self) -> &AtomicU32 {
+        match self {
+            FutexHashKey::Shared { paddr } => paddr.to_vaddr().get_mut(),
+            FutexHashKey::Private { vaddr, .. } => vaddr.get_mut(),
+        }
+    }
+}
+
+/// Owner of a contended priority-inheritance futex, which is boosted while
+/// there are waiters on the futex.
+struct PiOwner {
+    tid: Tid,
+    attr: Arc<SchedAttr>,
+}
+
+impl PiOwner {
+    fn new(tid: Tid, attr: Arc<SchedAttr>) -> Self {
+        attr.boost();
+        Self { tid, attr }
+    }
+}
+
+impl Drop for PiOwner {
+    fn drop(&mut self) {
+        self.attr.unboost();
+    }
+}
+
 #[derive(Debug)]
 pub struct FutexWaiter {
     pub tid: Tid,
     pub waker: Waker,
+    /// Only woken by a wake operation whose bitset intersects with this one
+    pub bitset: u32,
 }
 
 impl FutexWaiter {
@@ -42,27 +102,45 @@ impl FutexWaiter {
 }
 
 /// `futex`: 一个32位的值，又称为`futex word`，将其地址传递给futex()系统调用
-pub struct FutexManager(HashMap<FutexHashKey, Vec<FutexWaiter>>);
+pub struct FutexManager {
+    waiters: HashMap<FutexHashKey, Vec<FutexWaiter>>,
+    /// Pages holding shared futex words that still have waiters. They are
+    /// pinned so that the physical address can not be reused by another page
+    /// while it is used as a key.
+    pinned: HashMap<FutexHashKey, Arc<Page>>,
+    /// Owners of priority-inheritance futexes that have waiters.
+    pi_owners: HashMap<FutexHashKey, PiOwner>,
+}
 
 impl FutexManager {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            waiters: HashMap::new(),
+            pinned: HashMap::new(),
+            pi_owners: HashMap::new(),
+        }
     }
 
     pub fn add_waiter(&mut self, key: &FutexHashKey, waiter: FutexWaiter) {
         log::info!("[futex::add_waiter] {:?} in {:?} ", waiter, key);
-        if let Some(waiters) = self.0.get_mut(key) {
-            waiters.push(waiter);
-        } else {
-            let mut waiters = Vec::new();
-            waiters.push(waiter);
-            self.0.insert(*key, waiters);
+        self.waiters
+            .entry(*key)
+            .or_insert_with(Vec::new)
+            .push(waiter);
+    }
+
+    /// Pins the page holding the shared futex word of `key` until all waiters
+    /// on it are gone.
+    pub fn pin_page(&mut self, key: &FutexHashKey, page: Arc<Page>) {
+        debug_assert!(matches!(key, FutexHashKey::Shared { .. }));
+        if self.waiters.contains_key(key) {
+            self.pinned.entry(*key).or_insert(page);
         }
     }
 
     /// 用于移除任务，任务可能是过期了，也可能是被信号中断了
     pub fn remove_waiter(&mut self, key: &FutexHashKey, tid: Tid) {
-        if let Some(waiters) = self.0.get_mut(key) {
+        if let Some(waiters) = self.waiters.get_mut(key) {
             for i in 0..waiters.len() {
                 if waiters[i].tid == tid {
                     waiters.swap_remove(i);
@@ -70,24 +148,33 @@ impl FutexManager {
                 }
             }
         }
+        self.remove_if_empty(key);
     }
 
-    pub fn wake(&mut self, key: &FutexHashKey, n: u32) -> SyscallResult {
-        if let Some(waiters) = self.0.get_mut(key) {
-            let n = min(n as usize, waiters.len());
-            for _ in 0..n {
-                let waiter = waiters.pop().unwrap();
+    /// Wakes at most `n` waiters on `key` whose bitset intersects with
+    /// `bitset`.
+    pub fn wake(&mut self, key: &FutexHashKey, n: u32, bitset: u32) -> SyscallResult {
+        if let Some(waiters) = self.waiters.get_mut(key) {
+            let mut n_woken = 0;
+            let mut i = waiters.len();
+            while i > 0 && n_woken < n as usize {
+                i -= 1;
+                if waiters[i].bitset & bitset == 0 {
+                    continue;
+                }
+                let waiter = waiters.remove(i);
                 log::info!("[futex_wake] {:?} has been woken", waiter);
                 waiter.wake();
+                n_woken += 1;
             }
-            drop(waiters);
             log::info!(
                 "[futex_wake] wake {} waiters in key {:?}, expect to wake {} waiters",
-                n,
+                n_woken,
                 key,
                 n,
             );
-            Ok(n)
+            self.remove_if_empty(key);
+            Ok(n_woken)
         } else {
             log::debug!("can not find key {key:?}");
             Err(SysError::EINVAL)
@@ -100,29 +187,115 @@ impl FutexManager {
         new: FutexHashKey,
         n_req: usize,
     ) -> SyscallResult {
-        let mut old_waiters = self.0.remove(&old).ok_or_else(|| {
+        let mut old_waiters = self.waiters.remove(&old).ok_or_else(|| {
             log::info!("[futex] no waiters in key {:?}", old);
             SysError::EINVAL
         })?;
         let n = min(n_req as usize, old_waiters.len());
-        if let Some(new_waiters) = self.0.get_mut(&new) {
-            for _ in 0..n {
-                new_waiters.push(old_waiters.pop().unwrap());
-            }
-        } else {
-            let mut new_waiters = Vec::with_capacity(n);
-            for _ in 0..n {
-                new_waiters.push(old_waiters.pop().unwrap());
-            }
-            self.0.insert(new, new_waiters);
+        let new_waiters = self
+            .waiters
+            .entry(new)
+            .or_insert_with(|| Vec::with_capacity(n));
+        for _ in 0..n {
+            new_waiters.push(old_waiters.pop().unwrap());
         }
+        self.remove_if_empty(&new);
 
         if !old_waiters.is_empty() {
-            self.0.insert(old, old_waiters);
+            self.waiters.insert(old, old_waiters);
+        } else {
+            self.pinned.remove(&old);
         }
 
         Ok(n)
     }
+
+    pub fn has_waiters(&self, key: &FutexHashKey) -> bool {
+        self.waiters
+            .get(key)
+            .is_some_and(|waiters| !waiters.is_empty())
+    }
+
+    /// Records `tid` as the owner of the PI futex `key`, which is boosted as
+    /// long as there are waiters on it.
+    pub fn set_pi_owner(&mut self, key: &FutexHashKey, tid: Tid, attr: Arc<SchedAttr>) {
+        if !self.has_waiters(key) {
+            self.pi_owners.remove(key);
+            return;
+        }
+        if self
+            .pi_owners
+            .get(key)
+            .is_some_and(|owner| owner.tid == tid)
+        {
+            return;
+        }
+        self.pi_owners.insert(*key, PiOwner::new(tid, attr));
+    }
+
+    /// Removes the waiter `tid` which gives up waiting on the PI futex `key`,
+    /// e.g. on timeout or signal. The owner is unboosted and `FUTEX_WAITERS`
+    /// is cleared from `word` if it is the last waiter.
+    pub fn remove_pi_waiter(&mut self, key: &FutexHashKey, word: &AtomicU32, tid: Tid) {
+        self.remove_waiter(key, tid);
+        if !self.has_waiters(key) {
+            self.pi_owners.remove(key);
+            word.fetch_and(!FUTEX_WAITERS, Ordering::AcqRel);
+        }
+    }
+
+    /// Hands the PI futex `key` over to its first waiter, storing the tid of
+    /// the waiter together with `extra` bits into `word`, or releases it if
+    /// there are no waiters. The new owner registers itself as the PI owner
+    /// once it runs.
+    pub fn handover_pi(&mut self, key: &FutexHashKey, word: &AtomicU32, extra: u32) {
+        self.pi_owners.remove(key);
+        let Some(waiters) = self.waiters.get_mut(key).filter(|w| !w.is_empty()) else {
+            word.store(extra, Ordering::Release);
+            return;
+        };
+        let waiter = waiters.remove(0);
+        let mut new = waiter.tid as u32 | extra;
+        if !waiters.is_empty() {
+            new |= FUTEX_WAITERS;
+        }
+        word.store(new, Ordering::Release);
+        log::info!("[handover_pi] {key:?} is handed over to {}", waiter.tid);
+        waiter.wake();
+        self.remove_if_empty(key);
+    }
+
+    fn remove_if_empty(&mut self, key: &FutexHashKey) {
+        if self
+            .waiters
+            .get(key)
+            .is_some_and(|waiters| waiters.is_empty())
+        {
+            self.waiters.remove(key);
+            self.pinned.remove(key);
+        }
+    }
+}
+
+/// Hands the contended PI futexes owned by the exiting task `tid` over to
+/// their waiters, marking them with `FUTEX_OWNER_DIED`.
+///
+/// Must be called within the address space of the exiting task.
+pub fn exit_pi_futexes(tid: Tid) {
+    let mut manager = futex_manager();
+    let keys: Vec<FutexHashKey> = manager
+        .pi_owners
+        .iter()
+        .filter(|(_, owner)| owner.tid == tid)
+        .map(|(key, _)| *key)
+        .collect();
+    for key in keys {
+        log::info!("[exit_pi_futexes] owner {tid} of {key:?} died");
+        within_sum(|| {
+            let word = unsafe { key.word() };
+            manager.handover_pi(&key, word, FUTEX_OWNER_DIED);
+        });
+    }
 }
 
 bitflags! {
@@ -165,5 +338,8 @@ bitflags! {
         /// Tells the kernel that the futex is process-private and not shared
         /// with another process.
         const Private = 128;
+        /// Only used with `WaitBitset`, the timeout is measured against
+        /// CLOCK_REALTIME instead of CLOCK_MONOTONIC.
+        const ClockRealtime = 256;
     }
 }
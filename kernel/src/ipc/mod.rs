@@ -1,13 +1,143 @@
+use alloc::sync::Arc;
+
+use hashbrown::HashMap;
+use recycle_allocator::RecycleAllocator;
+use systype::{SysError, SysResult};
+
 pub mod futex;
+pub mod msg;
+pub mod sem;
 pub mod shm;
+
+/// Create the object if it does not exist.
+pub const IPC_CREAT: i32 = 0o1000;
+/// Fail if the object exists, used with `IPC_CREAT`.
+pub const IPC_EXCL: i32 = 0o2000;
+/// Return error instead of waiting.
+pub const IPC_NOWAIT: i32 = 0o4000;
+
+// Commands of `*ctl` syscalls
+pub const IPC_RMID: i32 = 0;
+pub const IPC_SET: i32 = 1;
+pub const IPC_STAT: i32 = 2;
+
+/// Key of an IPC object that is never shared through `key`, the objects are
+/// always newly created.
+pub const IPC_PRIVATE: i32 = 0;
+
+/// Ownership and permissions of an IPC object, the same as `struct ipc64_perm`
+/// of asm-generic.
 #[repr(C)]
 #[derive(Default, Clone, Copy, Debug)]
 pub struct IpcPerm {
-    key: i32,
-    uid: u32,
-    gid: u32,
-    cuid: u32,
-    cgid: u32,
-    mode: u16,
-    seq: u16,
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub cuid: u32,
+    pub cgid: u32,
+    pub mode: u32,
+    pub seq: u16,
+    __pad2: u16,
+    __unused1: usize,
+    __unused2: usize,
+}
+
+impl IpcPerm {
+    /// Permissions of an object created by root, where only the lowest 9 bits
+    /// of `mode` are used.
+    pub fn new(key: i32, mode: u32) -> Self {
+        Self {
+            key,
+            mode: mode & 0o777,
+            ..Default::default()
+        }
+    }
+}
+
+/// IPC objects of one kind, identified by ids and optionally found by keys.
+pub struct IpcTable<T> {
+    /// Objects indexed by id, together with their keys.
+    objects: HashMap<usize, (i32, Arc<T>)>,
+    /// Ids of objects created with a key other than `IPC_PRIVATE` and not
+    /// removed yet.
+    keys: HashMap<i32, usize>,
+    ids: RecycleAllocator,
+}
+
+impl<T> Default for IpcTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IpcTable<T> {
+    pub fn new() -> Self {
+        Self {
+            objects: HashMap::new(),
+            keys: HashMap::new(),
+            ids: RecycleAllocator::new(1),
+        }
+    }
+
+    /// Find the object of `key` and check it with `check`, or create one with
+    /// `new` if there is none and `IPC_CREAT` is in `flags`. Return the id.
+    pub fn get_or_create(
+        &mut self,
+        key: i32,
+        flags: i32,
+        check: impl FnOnce(&T) -> SysResult<()>,
+        new: impl FnOnce() -> SysResult<T>,
+    ) -> SysResult<usize> {
+        if key != IPC_PRIVATE {
+            if let Some(&id) = self.keys.get(&key) {
+                if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                    return Err(SysError::EEXIST);
+                }
+                check(&self.objects[&id].1)?;
+                return Ok(id);
+            }
+            if flags & IPC_CREAT == 0 {
+                return Err(SysError::ENOENT);
+            }
+        }
+        let object = Arc::new(new()?);
+        let id = self.ids.alloc();
+        self.objects.insert(id, (key, object));
+        if key != IPC_PRIVATE {
+            self.keys.insert(key, id);
+        }
+        Ok(id)
+    }
+
+    pub fn get(&self, id: usize) -> SysResult<Arc<T>> {
+        self.objects
+            .get(&id)
+            .map(|(_, object)| object.clone())
+            .ok_or(SysError::EINVAL)
+    }
+
+    /// Remove the object, and its key can be used for new objects.
+    pub fn remove(&mut self, id: usize) -> SysResult<Arc<T>> {
+        let (key, object) = self.objects.remove(&id).ok_or(SysError::EINVAL)?;
+        if key != IPC_PRIVATE {
+            self.keys.remove(&key);
+        }
+        self.ids.dealloc(id);
+        Ok(object)
+    }
+
+    /// Forget the key of the object, which can then be used for new objects,
+    /// while the object is still found by id until it is removed.
+    pub fn remove_key(&mut self, id: usize) {
+        if let Some((key, _)) = self.objects.get_mut(&id) {
+            if *key != IPC_PRIVATE {
+                self.keys.remove(key);
+                *key = IPC_PRIVATE;
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<T>> {
+        self.objects.values().map(|(_, object)| object)
+    }
 }
@@ -0,0 +1,243 @@
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+
+use arch::time::get_time_sec;
+use spin::Lazy;
+use sync::{mutex::SpinNoIrqLock, WaitQueue};
+use systype::{SysError, SysResult};
+
+use super::{IpcPerm, IpcTable, IPC_NOWAIT};
+
+/// Max size of a message.
+pub const MSGMAX: usize = 8192;
+/// Default max number of bytes in a queue.
+pub const MSGMNB: usize = 16384;
+
+/// Truncate the message instead of failing with `E2BIG` if it is too long.
+pub const MSG_NOERROR: i32 = 0o10000;
+/// Receive the first message whose type is not `msgtyp`.
+pub const MSG_EXCEPT: i32 = 0o20000;
+
+/// The same as `struct msqid64_ds` of asm-generic.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MsqIdDs {
+    pub msg_perm: IpcPerm,
+    // Time of last msgsnd(2)
+    pub msg_stime: usize,
+    // Time of last msgrcv(2)
+    pub msg_rtime: usize,
+    // Creation time/time of last modification via msgctl()
+    pub msg_ctime: usize,
+    // Current number of bytes in queue
+    pub msg_cbytes: usize,
+    // Current number of messages in queue
+    pub msg_qnum: usize,
+    // Maximum number of bytes allowed in queue
+    pub msg_qbytes: usize,
+    // PID of last msgsnd(2)
+    pub msg_lspid: u32,
+    // PID of last msgrcv(2)
+    pub msg_lrpid: u32,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+struct Msg {
+    mtype: isize,
+    text: Vec<u8>,
+}
+
+struct MsgQueueInner {
+    msqid_ds: MsqIdDs,
+    msgs: VecDeque<Msg>,
+    /// Bumped on every change of the queue, waiters retry when it changes.
+    seq: usize,
+    removed: bool,
+}
+
+/// A System V message queue.
+pub struct MsgQueue {
+    inner: SpinNoIrqLock<MsgQueueInner>,
+    /// Tasks blocked in msgsnd or msgrcv, all woken up when the queue changes.
+    wait_queue: WaitQueue,
+}
+
+impl MsgQueue {
+    fn new(key: i32, mode: u32) -> Self {
+        Self {
+            inner: SpinNoIrqLock::new(MsgQueueInner {
+                msqid_ds: MsqIdDs {
+                    msg_perm: IpcPerm::new(key, mode),
+                    msg_stime: 0,
+                    msg_rtime: 0,
+                    msg_ctime: get_time_sec(),
+                    msg_cbytes: 0,
+                    msg_qnum: 0,
+                    msg_qbytes: MSGMNB,
+                    msg_lspid: 0,
+                    msg_lrpid: 0,
+                    __unused4: 0,
+                    __unused5: 0,
+                },
+                msgs: VecDeque::new(),
+                seq: 0,
+                removed: false,
+            }),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    pub fn stat(&self) -> MsqIdDs {
+        self.inner.lock().msqid_ds
+    }
+
+    /// Update ownership, permissions and `msg_qbytes` from `ds`.
+    pub fn set(&self, ds: &MsqIdDs) {
+        let mut inner = self.inner.lock();
+        let perm = &mut inner.msqid_ds.msg_perm;
+        perm.uid = ds.msg_perm.uid;
+        perm.gid = ds.msg_perm.gid;
+        perm.mode = (perm.mode & !0o777) | (ds.msg_perm.mode & 0o777);
+        inner.msqid_ds.msg_qbytes = ds.msg_qbytes;
+        inner.msqid_ds.msg_ctime = get_time_sec();
+        inner.seq += 1;
+        drop(inner);
+        // senders may fit now
+        self.wait_queue.wake_all();
+    }
+
+    /// Try to append a message, return `false` if the queue is full and the
+    /// caller should wait.
+    pub fn try_send(&self, mtype: isize, text: &[u8], flags: i32, pid: usize) -> SysResult<bool> {
+        let mut inner = self.inner.lock();
+        if inner.removed {
+            return Err(SysError::EIDRM);
+        }
+        let ds = &inner.msqid_ds;
+        // Linux also limits the number of messages by msg_qbytes, so that
+        // zero-length messages can not fill the memory.
+        if ds.msg_cbytes + text.len() > ds.msg_qbytes || ds.msg_qnum + 1 > ds.msg_qbytes {
+            if flags & IPC_NOWAIT != 0 {
+                return Err(SysError::EAGAIN);
+            }
+            return Ok(false);
+        }
+        inner.msgs.push_back(Msg {
+            mtype,
+            text: text.to_vec(),
+        });
+        let ds = &mut inner.msqid_ds;
+        ds.msg_cbytes += text.len();
+        ds.msg_qnum += 1;
+        ds.msg_lspid = pid as u32;
+        ds.msg_stime = get_time_sec();
+        inner.seq += 1;
+        drop(inner);
+        self.wait_queue.wake_all();
+        Ok(true)
+    }
+
+    /// Try to take a message selected by `msgtyp`, which is truncated to
+    /// `size` bytes with `MSG_NOERROR`. Return `None` if there is no such
+    /// message and the caller should wait.
+    pub fn try_recv(
+        &self,
+        msgtyp: isize,
+        size: usize,
+        flags: i32,
+        pid: usize,
+    ) -> SysResult<Option<(isize, Vec<u8>)>> {
+        let mut inner = self.inner.lock();
+        if inner.removed {
+            return Err(SysError::EIDRM);
+        }
+        let msgs = &inner.msgs;
+        let idx = match msgtyp {
+            0 => (!msgs.is_empty()).then_some(0),
+            msgtyp if msgtyp > 0 => {
+                if flags & MSG_EXCEPT != 0 {
+                    msgs.iter().position(|msg| msg.mtype != msgtyp)
+                } else {
+                    msgs.iter().position(|msg| msg.mtype == msgtyp)
+                }
+            }
+            // the first message of the lowest type less than or equal to
+            // |msgtyp|
+            msgtyp => msgs
+                .iter()
+                .enumerate()
+                .filter(|(_, msg)| msg.mtype <= msgtyp.saturating_neg())
+                .min_by_key(|(_, msg)| msg.mtype)
+                .map(|(idx, _)| idx),
+        };
+        let Some(idx) = idx else {
+            if flags & IPC_NOWAIT != 0 {
+                return Err(SysError::ENOMSG);
+            }
+            return Ok(None);
+        };
+        if msgs[idx].text.len() > size && flags & MSG_NOERROR == 0 {
+            return Err(SysError::E2BIG);
+        }
+        let mut msg = inner.msgs.remove(idx).unwrap();
+        let ds = &mut inner.msqid_ds;
+        ds.msg_cbytes -= msg.text.len();
+        ds.msg_qnum -= 1;
+        ds.msg_lrpid = pid as u32;
+        ds.msg_rtime = get_time_sec();
+        inner.seq += 1;
+        drop(inner);
+        self.wait_queue.wake_all();
+        msg.text.truncate(size);
+        Ok(Some((msg.mtype, msg.text)))
+    }
+
+    pub fn seq(&self) -> usize {
+        self.inner.lock().seq
+    }
+
+    /// Wait until the queue is changed since `seq`, or it is removed.
+    pub async fn wait_change(&self, seq: usize) {
+        self.wait_queue
+            .wait_until(|| {
+                let inner = self.inner.lock();
+                inner.seq != seq || inner.removed
+            })
+            .await
+    }
+
+    /// Wake up all waiters, which will see `EIDRM`.
+    fn mark_removed(&self) {
+        self.inner.lock().removed = true;
+        self.wait_queue.wake_all();
+    }
+}
+
+pub struct MsgManager(SpinNoIrqLock<IpcTable<MsgQueue>>);
+
+impl MsgManager {
+    pub fn init() -> Self {
+        Self(SpinNoIrqLock::new(IpcTable::new()))
+    }
+
+    pub fn get_or_create(&self, key: i32, flags: i32) -> SysResult<usize> {
+        self.0.lock().get_or_create(
+            key,
+            flags,
+            |_| Ok(()),
+            || Ok(MsgQueue::new(key, flags as u32)),
+        )
+    }
+
+    pub fn get(&self, msq_id: usize) -> SysResult<Arc<MsgQueue>> {
+        self.0.lock().get(msq_id)
+    }
+
+    pub fn remove(&self, msq_id: usize) -> SysResult<()> {
+        let queue = self.0.lock().remove(msq_id)?;
+        queue.mark_removed();
+        Ok(())
+    }
+}
+
+pub static MSG_MANAGER: Lazy<MsgManager> = Lazy::new(MsgManager::init);
@@ -0,0 +1,330 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+
+use arch::time::get_time_sec;
+use spin::Lazy;
+use sync::{mutex::SpinNoIrqLock, WaitQueue};
+use systype::{SysError, SysResult};
+
+use super::{IpcPerm, IpcTable, IPC_NOWAIT};
+
+/// Max number of semaphores in a set.
+pub const SEMMSL: usize = 32000;
+/// Max number of operations in a semop call.
+pub const SEMOPM: usize = 500;
+/// Max value of a semaphore.
+pub const SEMVMX: i32 = 32767;
+
+/// Undo the operation when the process exits.
+pub const SEM_UNDO: i16 = 0x1000;
+
+/// An operation of `semop`, the same as `struct sembuf`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SemBuf {
+    pub sem_num: u16,
+    pub sem_op: i16,
+    pub sem_flg: i16,
+}
+
+/// The same as `struct semid64_ds` of asm-generic.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SemIdDs {
+    pub sem_perm: IpcPerm,
+    // Last semop time
+    pub sem_otime: usize,
+    // Creation time/time of last modification via semctl()
+    pub sem_ctime: usize,
+    // No. of semaphores in set
+    pub sem_nsems: usize,
+    __unused3: usize,
+    __unused4: usize,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Sem {
+    val: i32,
+    /// PID of the process that last operated the semaphore.
+    pid: u32,
+    /// Number of tasks waiting for the value to increase.
+    ncnt: usize,
+    /// Number of tasks waiting for the value to become zero.
+    zcnt: usize,
+}
+
+/// Outcome of trying a `semop`.
+pub enum SemOpResult {
+    Done,
+    /// The operation on the semaphore can not proceed, and whether it waits
+    /// for zero.
+    Blocked(usize, bool),
+}
+
+struct SemSetInner {
+    semid_ds: SemIdDs,
+    sems: Vec<Sem>,
+    /// Adjustments to apply when a process exits, indexed by pid.
+    undos: BTreeMap<usize, Vec<i32>>,
+    /// Bumped on every change of values, waiters recheck their operations
+    /// when it changes.
+    seq: usize,
+    removed: bool,
+}
+
+/// A System V semaphore set.
+pub struct SemSet {
+    inner: SpinNoIrqLock<SemSetInner>,
+    /// Tasks blocked in semop, all woken up when values change.
+    wait_queue: WaitQueue,
+}
+
+impl SemSet {
+    fn new(key: i32, mode: u32, nsems: usize) -> Self {
+        Self {
+            inner: SpinNoIrqLock::new(SemSetInner {
+                semid_ds: SemIdDs {
+                    sem_perm: IpcPerm::new(key, mode),
+                    sem_otime: 0,
+                    sem_ctime: get_time_sec(),
+                    sem_nsems: nsems,
+                    __unused3: 0,
+                    __unused4: 0,
+                },
+                sems: vec![Sem::default(); nsems],
+                undos: BTreeMap::new(),
+                seq: 0,
+                removed: false,
+            }),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    pub fn nsems(&self) -> usize {
+        self.inner.lock().sems.len()
+    }
+
+    pub fn stat(&self) -> SemIdDs {
+        self.inner.lock().semid_ds
+    }
+
+    /// Try to perform all operations atomically, i.e. either all of them or
+    /// none of them are performed.
+    pub fn try_op(&self, sops: &[SemBuf], pid: usize) -> SysResult<SemOpResult> {
+        let mut inner = self.inner.lock();
+        if inner.removed {
+            return Err(SysError::EIDRM);
+        }
+        let mut vals: Vec<i32> = inner.sems.iter().map(|sem| sem.val).collect();
+        for sop in sops {
+            let num = sop.sem_num as usize;
+            let val = vals.get_mut(num).ok_or(SysError::EFBIG)?;
+            let op = sop.sem_op as i32;
+            let blocked = if op == 0 { *val != 0 } else { *val + op < 0 };
+            if blocked {
+                if sop.sem_flg as i32 & IPC_NOWAIT != 0 {
+                    return Err(SysError::EAGAIN);
+                }
+                return Ok(SemOpResult::Blocked(num, op == 0));
+            }
+            if *val + op > SEMVMX {
+                return Err(SysError::ERANGE);
+            }
+            *val += op;
+        }
+        let nsems = inner.sems.len();
+        for sop in sops.iter().filter(|sop| sop.sem_flg & SEM_UNDO != 0) {
+            let undo = inner.undos.entry(pid).or_insert_with(|| vec![0; nsems]);
+            undo[sop.sem_num as usize] -= sop.sem_op as i32;
+        }
+        for sop in sops {
+            inner.sems[sop.sem_num as usize].pid = pid as u32;
+        }
+        for (sem, val) in inner.sems.iter_mut().zip(vals) {
+            sem.val = val;
+        }
+        inner.semid_ds.sem_otime = get_time_sec();
+        inner.seq += 1;
+        drop(inner);
+        self.wait_queue.wake_all();
+        Ok(SemOpResult::Done)
+    }
+
+    pub fn seq(&self) -> usize {
+        self.inner.lock().seq
+    }
+
+    /// Wait until values are changed since `seq`, or the set is removed.
+    pub async fn wait_change(&self, seq: usize) {
+        self.wait_queue
+            .wait_until(|| {
+                let inner = self.inner.lock();
+                inner.seq != seq || inner.removed
+            })
+            .await
+    }
+
+    /// Count a task as waiting on the semaphore, or stop counting it.
+    pub fn count_waiter(&self, num: usize, wait_zero: bool, waiting: bool) {
+        let mut inner = self.inner.lock();
+        let sem = &mut inner.sems[num];
+        let cnt = if wait_zero {
+            &mut sem.zcnt
+        } else {
+            &mut sem.ncnt
+        };
+        if waiting {
+            *cnt += 1;
+        } else {
+            *cnt -= 1;
+        }
+    }
+
+    pub fn get_val(&self, num: usize) -> SysResult<i32> {
+        let inner = self.inner.lock();
+        inner
+            .sems
+            .get(num)
+            .map(|sem| sem.val)
+            .ok_or(SysError::EINVAL)
+    }
+
+    pub fn get_pid(&self, num: usize) -> SysResult<u32> {
+        let inner = self.inner.lock();
+        inner
+            .sems
+            .get(num)
+            .map(|sem| sem.pid)
+            .ok_or(SysError::EINVAL)
+    }
+
+    pub fn get_ncnt(&self, num: usize) -> SysResult<usize> {
+        let inner = self.inner.lock();
+        inner
+            .sems
+            .get(num)
+            .map(|sem| sem.ncnt)
+            .ok_or(SysError::EINVAL)
+    }
+
+    pub fn get_zcnt(&self, num: usize) -> SysResult<usize> {
+        let inner = self.inner.lock();
+        inner
+            .sems
+            .get(num)
+            .map(|sem| sem.zcnt)
+            .ok_or(SysError::EINVAL)
+    }
+
+    pub fn get_all(&self) -> Vec<u16> {
+        let inner = self.inner.lock();
+        inner.sems.iter().map(|sem| sem.val as u16).collect()
+    }
+
+    /// Set the value of semaphore `num`, where adjustments of it are cleared in
+    /// all processes.
+    pub fn set_val(&self, num: usize, val: i32, pid: usize) -> SysResult<()> {
+        if !(0..=SEMVMX).contains(&val) {
+            return Err(SysError::ERANGE);
+        }
+        let mut inner = self.inner.lock();
+        let sem = inner.sems.get_mut(num).ok_or(SysError::EINVAL)?;
+        sem.val = val;
+        sem.pid = pid as u32;
+        for undo in inner.undos.values_mut() {
+            undo[num] = 0;
+        }
+        inner.semid_ds.sem_ctime = get_time_sec();
+        inner.seq += 1;
+        drop(inner);
+        self.wait_queue.wake_all();
+        Ok(())
+    }
+
+    pub fn set_all(&self, vals: &[u16], pid: usize) -> SysResult<()> {
+        if vals.iter().any(|&val| val as i32 > SEMVMX) {
+            return Err(SysError::ERANGE);
+        }
+        let mut inner = self.inner.lock();
+        for (sem, &val) in inner.sems.iter_mut().zip(vals) {
+            sem.val = val as i32;
+            sem.pid = pid as u32;
+        }
+        inner.undos.clear();
+        inner.semid_ds.sem_ctime = get_time_sec();
+        inner.seq += 1;
+        drop(inner);
+        self.wait_queue.wake_all();
+        Ok(())
+    }
+
+    /// Apply the adjustments of the exited process `pid`.
+    fn undo(&self, pid: usize) {
+        let mut inner = self.inner.lock();
+        let Some(undo) = inner.undos.remove(&pid) else {
+            return;
+        };
+        for (sem, adj) in inner.sems.iter_mut().zip(undo) {
+            if adj != 0 {
+                // the value is clamped as Linux does
+                sem.val = (sem.val + adj).clamp(0, SEMVMX);
+                sem.pid = pid as u32;
+            }
+        }
+        inner.seq += 1;
+        drop(inner);
+        self.wait_queue.wake_all();
+    }
+
+    /// Wake up all waiters, which will see `EIDRM`.
+    fn mark_removed(&self) {
+        self.inner.lock().removed = true;
+        self.wait_queue.wake_all();
+    }
+}
+
+pub struct SemManager(SpinNoIrqLock<IpcTable<SemSet>>);
+
+impl SemManager {
+    pub fn init() -> Self {
+        Self(SpinNoIrqLock::new(IpcTable::new()))
+    }
+
+    pub fn get_or_create(&self, key: i32, nsems: usize, flags: i32) -> SysResult<usize> {
+        self.0.lock().get_or_create(
+            key,
+            flags,
+            |set| {
+                if set.nsems() < nsems {
+                    return Err(SysError::EINVAL);
+                }
+                Ok(())
+            },
+            || {
+                if nsems == 0 || nsems > SEMMSL {
+                    return Err(SysError::EINVAL);
+                }
+                Ok(SemSet::new(key, flags as u32, nsems))
+            },
+        )
+    }
+
+    pub fn get(&self, sem_id: usize) -> SysResult<Arc<SemSet>> {
+        self.0.lock().get(sem_id)
+    }
+
+    pub fn remove(&self, sem_id: usize) -> SysResult<()> {
+        let set = self.0.lock().remove(sem_id)?;
+        set.mark_removed();
+        Ok(())
+    }
+
+    /// Apply `SEM_UNDO` adjustments of the exited process `pid` to all sets.
+    pub fn exit(&self, pid: usize) {
+        let sets: Vec<_> = self.0.lock().iter().cloned().collect();
+        for set in sets {
+            set.undo(pid);
+        }
+    }
+}
+
+pub static SEM_MANAGER: Lazy<SemManager> = Lazy::new(SemManager::init);
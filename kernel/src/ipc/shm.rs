@@ -1,20 +1,26 @@
-use alloc::{sync::Weak, vec::Vec};
+use alloc::{sync::Arc, vec::Vec};
 
 use arch::time::get_time_sec;
 use config::mm::PAGE_SIZE;
-use hashbrown::HashMap;
 use page::Page;
-use recycle_allocator::RecycleAllocator;
 use spin::Lazy;
 use sync::mutex::SpinNoIrqLock;
+use systype::{SysError, SysResult};
 
-use super::IpcPerm;
+use super::{IpcPerm, IpcTable};
+
+/// Set in `shm_perm.mode` when the segment is removed by `IPC_RMID` but still
+/// attached.
+pub const SHM_DEST: u32 = 0o1000;
 
 pub struct SharedMemory {
     pub shmid_ds: ShmIdDs,
-    pub pages: Vec<Weak<Page>>,
+    /// Pages of the segment, which are allocated at the first attach and live
+    /// as long as the segment, even if no process attaches it.
+    pub pages: Vec<Arc<Page>>,
 }
 
+/// The same as `struct shmid64_ds` of asm-generic.
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct ShmIdDs {
@@ -29,24 +35,28 @@ pub struct ShmIdDs {
     // Creation time/time of last modification via shmctl()
     pub shm_ctime: usize,
     // PID of creator
-    pub shm_cpid: usize,
+    pub shm_cpid: u32,
     // PID of last shmat(2)/shmdt(2)
-    pub shm_lpid: usize,
+    pub shm_lpid: u32,
     // No. of current attaches
     pub shm_nattch: usize,
+    __unused4: usize,
+    __unused5: usize,
 }
 
 impl ShmIdDs {
-    pub fn new(sz: usize, cpid: usize) -> Self {
+    pub fn new(key: i32, mode: u32, sz: usize, cpid: usize) -> Self {
         Self {
-            shm_perm: IpcPerm::default(),
+            shm_perm: IpcPerm::new(key, mode),
             shm_segsz: sz,
             shm_atime: 0,
             shm_dtime: 0,
             shm_ctime: get_time_sec(),
-            shm_cpid: cpid,
+            shm_cpid: cpid as u32,
             shm_lpid: 0,
             shm_nattch: 0,
+            __unused4: 0,
+            __unused5: 0,
         }
     }
 
@@ -54,63 +64,125 @@ impl ShmIdDs {
         // shm_atime is set to the current time.
         self.shm_atime = get_time_sec();
         // shm_lpid is set to the process-ID of the calling process.
-        self.shm_lpid = lpid;
+        self.shm_lpid = lpid as u32;
         // shm_nattch is incremented by one.
         self.shm_nattch += 1;
     }
 
-    /// return whether the SHARED_MEMORY_MANAGER should remove the SharedMemory
-    /// which self ShmIdDs belongs to;
-    pub fn detach(&mut self, lpid: usize) -> bool {
+    pub fn detach(&mut self, lpid: usize) {
         // shm_dtime is set to the current time.
         self.shm_dtime = get_time_sec();
         // shm_lpid is set to the process-ID of the calling process.
-        self.shm_lpid = lpid;
+        self.shm_lpid = lpid as u32;
         // shm_nattch is decremented by one.
+        debug_assert!(self.shm_nattch > 0);
         self.shm_nattch -= 1;
-        debug_assert!(self.shm_nattch >= 0);
-        if self.shm_nattch == 0 {
-            return true;
-        }
-        false
+    }
+
+    /// Whether the segment should be destroyed, i.e. it is removed and no one
+    /// attaches it.
+    pub fn should_destroy(&self) -> bool {
+        self.shm_perm.mode & SHM_DEST != 0 && self.shm_nattch == 0
     }
 }
 
 impl SharedMemory {
-    pub fn new(sz: usize, pid: usize) -> Self {
+    pub fn new(key: i32, mode: u32, sz: usize, pid: usize) -> Self {
         Self {
-            shmid_ds: ShmIdDs::new(sz, pid),
+            shmid_ds: ShmIdDs::new(key, mode, sz, pid),
             pages: Vec::with_capacity(sz / PAGE_SIZE + 1),
         }
     }
+
     pub fn size(&self) -> usize {
         self.shmid_ds.shm_segsz
     }
 }
 
-pub struct SharedMemoryManager(pub SpinNoIrqLock<HashMap<usize, SharedMemory>>);
+pub struct SharedMemoryManager(SpinNoIrqLock<IpcTable<SpinNoIrqLock<SharedMemory>>>);
 
 impl SharedMemoryManager {
     pub fn init() -> Self {
-        Self(SpinNoIrqLock::new(HashMap::new()))
+        Self(SpinNoIrqLock::new(IpcTable::new()))
+    }
+
+    /// Find the segment of `key`, or create one if there is none and
+    /// `IPC_CREAT` is in `flags`. Return the shmid.
+    pub fn get_or_create(&self, key: i32, size: usize, flags: i32, pid: usize) -> SysResult<usize> {
+        self.0.lock().get_or_create(
+            key,
+            flags,
+            |shm| {
+                // A segment for the given key exists, but size is greater than the size of
+                // that segment.
+                if shm.lock().size() < size {
+                    return Err(SysError::EINVAL);
+                }
+                Ok(())
+            },
+            || {
+                let rounded_up_sz = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+                Ok(SpinNoIrqLock::new(SharedMemory::new(
+                    key,
+                    flags as u32,
+                    rounded_up_sz,
+                    pid,
+                )))
+            },
+        )
+    }
+
+    /// Call `f` with the segment of `shm_id`, return `EINVAL` if there is none.
+    ///
+    /// The table is locked meanwhile, so that the segment can not be destroyed
+    /// by others.
+    pub fn with_shm<T>(
+        &self,
+        shm_id: usize,
+        f: impl FnOnce(&mut SharedMemory) -> T,
+    ) -> SysResult<T> {
+        let table = self.0.lock();
+        let shm = table.get(shm_id)?;
+        let ret = f(&mut shm.lock());
+        Ok(ret)
     }
 
     pub fn attach(&self, shm_id: usize, lpid: usize) {
-        let mut shm_manager = self.0.lock();
-        let shm = shm_manager.get_mut(&shm_id).unwrap();
-        shm.shmid_ds.attach(lpid);
+        let table = self.0.lock();
+        let shm = table.get(shm_id).unwrap();
+        shm.lock().shmid_ds.attach(lpid);
     }
 
+    /// Detach the segment, which is destroyed if it has been removed and this
+    /// is the last attach.
     pub fn detach(&self, shm_id: usize, lpid: usize) {
-        let mut shm_manager = self.0.lock();
-        let shm = shm_manager.get_mut(&shm_id).unwrap();
-        if shm.shmid_ds.detach(lpid) {
-            shm_manager.remove(&shm_id);
-            SHARED_MEMORY_KEY_ALLOCATOR.lock().dealloc(shm_id);
+        let mut table = self.0.lock();
+        let shm = table.get(shm_id).unwrap();
+        let mut shm = shm.lock();
+        shm.shmid_ds.detach(lpid);
+        if shm.shmid_ds.should_destroy() {
+            drop(shm);
+            let _ = table.remove(shm_id);
+        }
+    }
+
+    /// Mark the segment to be destroyed after the last detach. The key of it
+    /// can be used for new segments immediately.
+    pub fn remove(&self, shm_id: usize) -> SysResult<()> {
+        let mut table = self.0.lock();
+        let shm = table.get(shm_id)?;
+        let mut shm = shm.lock();
+        shm.shmid_ds.shm_perm.mode |= SHM_DEST;
+        shm.shmid_ds.shm_ctime = get_time_sec();
+        let should_destroy = shm.shmid_ds.should_destroy();
+        drop(shm);
+        if should_destroy {
+            table.remove(shm_id)?;
+        } else {
+            table.remove_key(shm_id);
         }
+        Ok(())
     }
 }
 
 pub static SHARED_MEMORY_MANAGER: Lazy<SharedMemoryManager> = Lazy::new(SharedMemoryManager::init);
-pub static SHARED_MEMORY_KEY_ALLOCATOR: SpinNoIrqLock<RecycleAllocator> =
-    SpinNoIrqLock::new(RecycleAllocator::new(2));
@@ -3,6 +3,7 @@
 #![no_std]
 #![no_main]
 #![feature(alloc_error_handler)]
+#![feature(asm_const)]
 #![feature(core_intrinsics)]
 #![feature(let_chains)]
 #![feature(trait_upcasting)]
@@ -31,9 +32,12 @@ mod utils;
 use core::{
     arch::global_asm,
     sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
 
 use ::net::poll_interfaces;
+use arch::time::get_time_duration;
+use timer::TIMER_MANAGER;
 
 use crate::processor::hart;
 
@@ -52,6 +56,10 @@ global_asm!(include_str!("trampoline.asm"));
 
 static FIRST_HART: AtomicBool = AtomicBool::new(true);
 
+/// Time that all harts are idle with no pending timers before reporting a
+/// possible deadlock.
+const DEADLOCK_DETECT_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[no_mangle]
 fn rust_main(hart_id: usize, dtb_addr: usize) {
     if FIRST_HART
@@ -62,14 +70,23 @@ fn rust_main(hart_id: usize, dtb_addr: usize) {
         boot::print_banner();
 
         hart::init(hart_id);
+        boot::init_cmdline(dtb_addr);
         logging::init();
 
         println!("[kernel] ---------- main hart {hart_id} started ---------- ");
         config::mm::set_dtb_addr(dtb_addr);
 
         mm::init();
+        boot::warn_unknown_cmdline();
         trap::init();
         driver::init();
+        #[cfg(feature = "strace")]
+        syscall::strace::init();
+        if let Some(now) = driver::rtc_time() {
+            time::set_realtime(now);
+        }
+        // the clock frequency is probed by driver
+        mm::vdso::update();
         vfs::init();
 
         task::spawn_kernel_task(async move {
@@ -77,6 +94,7 @@ fn rust_main(hart_id: usize, dtb_addr: usize) {
         });
 
         // utils::spawn_timer_tasks_ms(
+        //     "net poll",
         //     || {
         //         poll_interfaces();
         //     },
@@ -84,7 +102,43 @@ fn rust_main(hart_id: usize, dtb_addr: usize) {
         // );
 
         #[cfg(feature = "debug")]
-        utils::spawn_timer_tasks(utils::print_proc_tree, 10);
+        utils::spawn_timer_tasks("proc tree", utils::print_proc_tree, 10);
+
+        #[cfg(feature = "selftest")]
+        {
+            systype::selftest();
+            log::info!("[systype] selftest passed");
+            memory::heap::selftest();
+            log::info!("[heap] selftest passed");
+            mm::selftest();
+            log::info!("[mm] selftest passed");
+            driver::virtio::selftest();
+            log::info!("[virtio] selftest passed");
+            driver::serial::selftest();
+            log::info!("[serial] selftest passed");
+            driver::sdhci_selftest();
+            log::info!("[sdhci] selftest passed");
+            ::net::selftest();
+            log::info!("[net] selftest passed");
+            sync::selftest();
+            log::info!("[wait_queue] selftest passed");
+            backtrace::selftest();
+            log::info!("[backtrace] selftest passed");
+            task::spawn_kernel_task(task::selftest());
+            task::spawn_kernel_task(task::blk_selftest());
+        }
+
+        #[cfg(feature = "lock-debug")]
+        task::spawn_kernel_task(task::lock_debug_test());
+
+        #[cfg(feature = "blk-bench")]
+        task::spawn_kernel_task(task::blk_bench());
+
+        #[cfg(feature = "panic-test")]
+        panic::panic_test();
+
+        // console output is transmitted by interrupts from now on
+        driver::serial::set_console_polling(false);
 
         #[cfg(feature = "smp")]
         boot::start_other_harts(hart_id);
@@ -96,20 +150,38 @@ fn rust_main(hart_id: usize, dtb_addr: usize) {
 
     unsafe {
         arch::interrupts::enable_timer_interrupt();
+        arch::interrupts::enable_software_interrupt();
         arch::time::set_next_timer_irq()
     };
 
     println!("[kernel] ---------- hart {hart_id} start to fetch task... ---------- ");
-    let mut try_count = 0usize;
+    run_tasks()
+}
+
+/// Run tasks on the local hart forever.
+fn run_tasks() -> ! {
+    let mut idle_since = None;
     loop {
         let tasks = executor::run_until_idle();
-        if tasks == 0 {
-            try_count += 1;
-        } else {
-            try_count = 0;
+        if tasks > 0 {
+            idle_since = None;
+            continue;
+        }
+        hart::idle();
+        // all harts waiting for interrupts with no pending timers is likely
+        // a deadlock, unless some task is waiting for device interrupts
+        if !executor::other_harts_idle() || !TIMER_MANAGER.is_empty() {
+            idle_since = None;
+            continue;
         }
-        if try_count >= 0x10000000 {
-            panic!("no tasks")
+        let now = get_time_duration();
+        let since = *idle_since.get_or_insert(now);
+        if now - since >= DEADLOCK_DETECT_TIMEOUT {
+            log::warn!(
+                "[kernel] all harts idle for {:?} with no timers, deadlock?",
+                now - since
+            );
+            idle_since = Some(now);
         }
     }
 }
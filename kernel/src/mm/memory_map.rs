@@ -0,0 +1,150 @@
+//! Physical memory layout found in the device tree at boot.
+//!
+//! RAM is described by the `/memory` nodes, and the regions in it that must
+//! not be allocated, e.g. those taken by the firmware, by the memory
+//! reservation block and the children of `/reserved-memory`. The device tree
+//! blob itself is reserved as well, since it is read by drivers later.
+//!
+//! The device tree is parsed before the kernel page table is built, through
+//! the boot page table, which maps the first and the third GiB of physical
+//! memory, where QEMU always places it.
+
+use alloc::vec::Vec;
+use core::{cmp, ops::Range};
+
+use config::{
+    board::MEMORY_END,
+    mm::{round_down_to_page, round_up_to_page, VIRT_RAM_OFFSET},
+};
+use fdt::Fdt;
+use memory::{PhysAddr, PhysPageNum};
+
+pub struct BootMemoryMap {
+    /// Physical ranges of RAM, clipped to `MEMORY_END`.
+    pub ram: Vec<Range<usize>>,
+    /// Physical ranges that must not be allocated.
+    pub reserved: Vec<Range<usize>>,
+    /// Size of the device tree blob.
+    pub dtb_size: usize,
+}
+
+impl BootMemoryMap {
+    /// Parse the device tree at physical address `dtb_addr`.
+    pub fn parse(dtb_addr: usize) -> Self {
+        let fdt = unsafe { Fdt::from_ptr((dtb_addr + VIRT_RAM_OFFSET) as *const u8) }
+            .expect("Parse DTB failed");
+        let ram_end = MEMORY_END - VIRT_RAM_OFFSET;
+
+        let mut ram = Vec::new();
+        for node in fdt.find_all_nodes("/memory") {
+            for region in node.reg().into_iter().flatten() {
+                let start = region.starting_address as usize;
+                let end = cmp::min(start + region.size.unwrap_or(0), ram_end);
+                if start >= end {
+                    log::warn!("[memory_map] RAM at {start:#x} beyond {ram_end:#x} is left unused");
+                    continue;
+                }
+                ram.push(round_up_to_page(start)..round_down_to_page(end));
+            }
+        }
+        assert!(!ram.is_empty(), "no RAM found in the device tree");
+        ram.sort_by_key(|range| range.start);
+
+        let mut reserved: Vec<_> = fdt
+            .memory_reservations()
+            .map(|resv| {
+                let start = resv.address() as usize;
+                start..start + resv.size()
+            })
+            .collect();
+        if let Some(node) = fdt.find_node("/reserved-memory") {
+            for child in node.children() {
+                for region in child.reg().into_iter().flatten() {
+                    let start = region.starting_address as usize;
+                    reserved.push(start..start + region.size.unwrap_or(0));
+                }
+            }
+        }
+        let dtb_size = fdt.total_size();
+        reserved.push(dtb_addr..dtb_addr + dtb_size);
+        // reserved ranges cover whole pages
+        for range in reserved.iter_mut() {
+            *range = round_down_to_page(range.start)..round_up_to_page(range.end);
+        }
+
+        for range in ram.iter() {
+            log::info!("[memory_map] RAM [{:#x}, {:#x})", range.start, range.end);
+        }
+        for range in reserved.iter() {
+            log::info!(
+                "[memory_map] reserved [{:#x}, {:#x})",
+                range.start,
+                range.end
+            );
+        }
+        Self {
+            ram,
+            reserved,
+            dtb_size,
+        }
+    }
+
+    /// Ranges of RAM from `start` on, e.g. after the kernel image.
+    pub fn ram_from(&self, start: usize) -> Vec<Range<usize>> {
+        self.ram
+            .iter()
+            .map(|range| cmp::max(range.start, start)..range.end)
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+
+    /// Frames of RAM from `start` on that are not reserved, which are managed
+    /// by the frame allocator.
+    pub fn free_frames(&self, start: usize) -> Vec<Range<PhysPageNum>> {
+        let mut ranges = self.ram_from(start);
+        for hole in self.reserved.iter() {
+            ranges = ranges
+                .into_iter()
+                .flat_map(|range| {
+                    [
+                        range.start..cmp::min(range.end, hole.start),
+                        cmp::max(range.start, hole.end)..range.end,
+                    ]
+                })
+                .filter(|range| !range.is_empty())
+                .collect();
+        }
+        ranges
+            .into_iter()
+            .map(|range| PhysAddr::from(range.start).floor()..PhysAddr::from(range.end).floor())
+            .collect()
+    }
+}
+
+/// Check that reserved regions and the kernel image are carved out of RAM,
+/// including regions reaching across the ends of RAM and holes between banks.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    let memory_map = BootMemoryMap {
+        ram: alloc::vec![0x8000_0000..0x9000_0000, 0xa000_0000..0xa010_0000],
+        reserved: alloc::vec![
+            // firmware
+            0x8000_0000..0x8020_0000,
+            // device tree
+            0x8f00_0000..0x8f00_2000,
+            // across the hole between the banks
+            0x9fff_f000..0xa000_1000,
+        ],
+        dtb_size: 0x2000,
+    };
+    let frames: Vec<_> = memory_map
+        .free_frames(0x8040_0000)
+        .into_iter()
+        .map(|range| range.start.0..range.end.0)
+        .collect();
+    assert_eq!(
+        frames,
+        [0x80400..0x8f000, 0x8f002..0x90000, 0xa0001..0xa0100]
+    );
+    assert_eq!(memory_map.ram_from(0x9800_0000), [0xa000_0000..0xa010_0000]);
+}
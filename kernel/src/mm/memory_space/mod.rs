@@ -1,46 +1,121 @@
-use alloc::{
-    string::{String, ToString},
-    sync::{Arc, Weak},
-    vec,
-    vec::Vec,
-};
+use alloc::{sync::Arc, vec::Vec};
 use core::{
     cell::SyncUnsafeCell,
     cmp,
     ops::{Range, RangeBounds},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use arch::memory::sfence_vma_vaddr;
 use async_utils::block_on;
 use config::{
     mm::{
-        is_aligned_to_page, round_down_to_page, DL_INTERP_OFFSET, MMAP_PRE_ALLOC_PAGES, PAGE_SIZE,
-        USER_ELF_PRE_ALLOC_PAGE_CNT, U_SEG_FILE_BEG, U_SEG_FILE_END, U_SEG_HEAP_BEG,
-        U_SEG_HEAP_END, U_SEG_SHARE_BEG, U_SEG_SHARE_END, U_SEG_STACK_BEG, U_SEG_STACK_END,
+        is_aligned_to_page, round_down_to_page, DL_INTERP_OFFSET, HUGE_PAGE_PAGES,
+        MMAP_PRE_ALLOC_PAGES, PAGE_SIZE, USER_ELF_PRE_ALLOC_PAGE_CNT, U_SEG_END, U_SEG_FILE_BEG,
+        U_SEG_FILE_END, U_SEG_HEAP_BEG, U_SEG_HEAP_END, U_SEG_SHARE_BEG, U_SEG_SHARE_END,
+        U_SEG_STACK_BEG, U_SEG_STACK_END,
     },
-    process::USER_STACK_PRE_ALLOC_SIZE,
+    process::{USER_MEMLOCK_LIMIT, USER_STACK_PRE_ALLOC_SIZE},
 };
 use memory::{pte::PTEFlags, PageTable, PhysAddr, VirtAddr, VirtPageNum};
 use page::Page;
 use range_map::RangeMap;
-use systype::{SysError, SysResult};
-use vfs_core::{Dentry, File};
-use xmas_elf::ElfFile;
+use systype::{RLimit, SysError, SysResult, RLIM_INFINITY};
+use vfs_core::File;
+use xmas_elf::{
+    header::{self, Class, Data, Machine},
+    program::{self, ProgramHeader64},
+    ElfFile,
+};
 
 use self::vm_area::VmArea;
-use super::{kernel_page_table, PageFaultAccessType};
+use super::{
+    kernel_page_table,
+    swap::{SwapEntry, SwapFile},
+    tlb, PageFaultAccessType,
+};
 use crate::{
     mm::memory_space::vm_area::{MapPerm, VmAreaType},
     processor::{env::SumGuard, hart::current_task_ref},
     syscall::MmapFlags,
     task::{
-        aux::{generate_early_auxv, AuxHeader, AT_BASE, AT_NULL, AT_PHDR, AT_RANDOM},
+        aux::{
+            generate_early_auxv, AuxHeader, AT_EXECFN, AT_NULL, AT_PHDR, AT_PLATFORM, AT_RANDOM,
+        },
+        exec_args::ExecArgs,
         Task,
     },
 };
 
 pub mod vm_area;
 
+/// Type of the program header telling whether the stack should be executable,
+/// which is not named by `xmas_elf`.
+const PT_GNU_STACK: u32 = 0x6474_e551;
+
+/// Parse `elf_data` and check that it is an elf executable for this machine,
+/// whose program headers lie in the file.
+///
+/// Return ENOEXEC if it is not.
+pub fn parse_elf(elf_data: &[u8]) -> SysResult<ElfFile> {
+    let elf = ElfFile::new(elf_data).map_err(|e| {
+        log::warn!("[parse_elf] invalid elf: {e}");
+        SysError::ENOEXEC
+    })?;
+    let pt1 = elf.header.pt1;
+    let pt2 = elf.header.pt2;
+    if pt1.class() != Class::SixtyFour
+        || pt1.data() != Data::LittleEndian
+        || pt2.machine().as_machine() != Machine::RISC_V
+        || !matches!(
+            pt2.type_().as_type(),
+            header::Type::Executable | header::Type::SharedObject
+        )
+    {
+        log::warn!(
+            "[parse_elf] unsupported elf: {:?}, {:?}",
+            pt1.class(),
+            pt2.machine().as_machine()
+        );
+        return Err(SysError::ENOEXEC);
+    }
+
+    let ph_end = (pt2.ph_count() as usize)
+        .checked_mul(pt2.ph_entry_size() as usize)
+        .and_then(|size| size.checked_add(pt2.ph_offset() as usize));
+    if pt2.ph_entry_size() as usize != core::mem::size_of::<ProgramHeader64>()
+        || !ph_end.is_some_and(|end| end <= elf_data.len())
+    {
+        log::warn!("[parse_elf] program headers out of the file");
+        return Err(SysError::ENOEXEC);
+    }
+    for ph in elf.program_iter() {
+        if !matches!(
+            ph.get_type(),
+            Ok(program::Type::Load | program::Type::Interp)
+        ) {
+            continue;
+        }
+        let in_file = ph
+            .offset()
+            .checked_add(ph.file_size())
+            .is_some_and(|end| end <= elf_data.len() as u64);
+        if !in_file
+            || ph.file_size() > ph.mem_size()
+            || ph.virtual_addr().checked_add(ph.mem_size()).is_none()
+        {
+            log::warn!(
+                "[parse_elf] malformed program header, offset {:#x}, file size {:#x}, mem size {:#x}",
+                ph.offset(),
+                ph.file_size(),
+                ph.mem_size()
+            );
+            return Err(SysError::ENOEXEC);
+        }
+    }
+    Ok(elf)
+}
+
 /// Virtual memory space for user.
 pub struct MemorySpace {
     // NOTE: The reason why `page_table` and `areas` are `SyncUnsafeCell` is because they both
@@ -50,6 +125,35 @@ pub struct MemorySpace {
     /// Map of `VmArea`s in this memory space.
     /// NOTE: stores range that is lazy allocated
     areas: SyncUnsafeCell<RangeMap<VirtAddr, VmArea>>,
+    /// Statistics of page faults and resident pages.
+    stat: MemoryStat,
+    /// Number of pages held by the areas, updated as they are mapped and
+    /// unmapped, see `insert_area`, `remove_area` and `update_area`.
+    resident: AtomicUsize,
+    /// Max bytes that can be locked in memory, i.e. `RLIMIT_MEMLOCK`.
+    memlock_limit: RLimit,
+    /// Max bytes of a core dump of this memory space, i.e. `RLIMIT_CORE`.
+    core_limit: RLimit,
+    /// Areas mapped from now on are locked, set by mlockall(MCL_FUTURE).
+    lock_future: bool,
+}
+
+/// Memory statistics of a process reported by getrusage(2) and wait4(2).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemoryStat {
+    /// Page faults serviced without any I/O activity.
+    pub minflt: usize,
+    /// Page faults serviced that required I/O activity.
+    pub majflt: usize,
+    /// Peak number of resident pages.
+    pub max_rss_pages: usize,
+}
+
+impl MemoryStat {
+    /// Maximum resident set size in kilobytes.
+    pub fn max_rss_kb(&self) -> usize {
+        self.max_rss_pages * PAGE_SIZE / 1024
+    }
 }
 
 impl MemorySpace {
@@ -58,6 +162,18 @@ impl MemorySpace {
         Self {
             page_table: SyncUnsafeCell::new(PageTable::new()),
             areas: SyncUnsafeCell::new(RangeMap::new()),
+            stat: MemoryStat::default(),
+            resident: AtomicUsize::new(0),
+            memlock_limit: RLimit {
+                rlim_cur: USER_MEMLOCK_LIMIT,
+                rlim_max: USER_MEMLOCK_LIMIT,
+            },
+            // core dumps are disabled by default, as Linux does
+            core_limit: RLimit {
+                rlim_cur: 0,
+                rlim_max: RLIM_INFINITY,
+            },
+            lock_future: false,
         }
     }
 
@@ -66,6 +182,18 @@ impl MemorySpace {
         Self {
             page_table: SyncUnsafeCell::new(PageTable::from_kernel(kernel_page_table())),
             areas: SyncUnsafeCell::new(RangeMap::new()),
+            stat: MemoryStat::default(),
+            resident: AtomicUsize::new(0),
+            memlock_limit: RLimit {
+                rlim_cur: USER_MEMLOCK_LIMIT,
+                rlim_max: USER_MEMLOCK_LIMIT,
+            },
+            // core dumps are disabled by default, as Linux does
+            core_limit: RLimit {
+                rlim_cur: 0,
+                rlim_max: RLIM_INFINITY,
+            },
+            lock_future: false,
         }
     }
 
@@ -85,33 +213,113 @@ impl MemorySpace {
         unsafe { &mut *self.page_table.get() }
     }
 
+    /// Flush `range` from the TLB of every hart running on this memory space,
+    /// after entries in it are revoked, downgraded or remapped.
+    ///
+    /// The other harts are waited for once the memory space is unlocked, see
+    /// `tlb::wait_for_shootdown`.
+    pub fn flush_range(&self, range: Range<VirtAddr>) {
+        tlb::shootdown(self.page_table(), range);
+    }
+
+    /// Insert `vma` into the areas, whose pages are resident from now on.
+    fn insert_area(&self, vma: VmArea) -> &mut VmArea {
+        self.resident.fetch_add(vma.pages.len(), Ordering::Relaxed);
+        self.areas_mut().try_insert(vma.range_va(), vma).unwrap()
+    }
+
+    /// Remove the area of `range` from the areas, whose pages are no longer
+    /// counted as resident.
+    fn remove_area(&self, range: Range<VirtAddr>) -> VmArea {
+        let vma = self.areas_mut().force_remove_one(range);
+        self.resident.fetch_sub(vma.pages.len(), Ordering::Relaxed);
+        vma
+    }
+
+    /// Run `f` on `area` in the areas, counting the pages it maps or unmaps.
+    fn update_area<T>(&self, area: &mut VmArea, f: impl FnOnce(&mut VmArea) -> T) -> T {
+        let before = area.pages.len();
+        let ret = f(area);
+        let after = area.pages.len();
+        if after >= before {
+            self.resident.fetch_add(after - before, Ordering::Relaxed);
+        } else {
+            self.resident.fetch_sub(before - after, Ordering::Relaxed);
+        }
+        ret
+    }
+
+    /// Unmap `vma` removed from the areas, whose pages are freed once no hart
+    /// can access them through stale TLB entries.
+    fn unmap_vma(&self, mut vma: VmArea) {
+        let pages = vma.unmap(self.page_table_mut());
+        self.flush_range(vma.range_va());
+        tlb::free_after_shootdown(pages.into_values());
+    }
+
+    /// Returns the page that is mapped at `va` if it has been allocated.
+    pub fn get_page(&self, va: VirtAddr) -> Option<Arc<Page>> {
+        let vm_area = self.areas().get(va)?;
+        vm_area.pages.get(&va.floor()).cloned()
+    }
+
+    pub fn stat(&self) -> MemoryStat {
+        self.stat
+    }
+
+    /// Memory statistics and the limits of locked memory and core dumps are
+    /// kept across execve(2), since they belong to the process rather than the
+    /// address space. Memory locks are not.
+    pub fn inherit_stat(&mut self, old: &Self) {
+        self.stat = old.stat;
+        self.memlock_limit = old.memlock_limit;
+        self.core_limit = old.core_limit;
+    }
+
+    pub fn memlock_limit(&self) -> RLimit {
+        self.memlock_limit
+    }
+
+    pub fn set_memlock_limit(&mut self, limit: RLimit) {
+        self.memlock_limit = limit;
+    }
+
+    pub fn core_limit(&self) -> RLimit {
+        self.core_limit
+    }
+
+    pub fn set_core_limit(&mut self, limit: RLimit) {
+        self.core_limit = limit;
+    }
+
+    /// Number of pages that are resident in memory now.
+    pub fn resident_pages(&self) -> usize {
+        self.resident.load(Ordering::Relaxed)
+    }
+
     /// Map the sections in the elf.
     ///
-    /// Return the max end vpn and the first section's va.
+    /// Return the max end vpn.
     pub fn map_elf(
         &mut self,
         elf_file: Arc<dyn File>,
         elf: &ElfFile,
         offset: VirtAddr,
-    ) -> (VirtPageNum, VirtAddr) {
-        let elf_header = elf.header;
-        let ph_count = elf_header.pt2.ph_count();
-
+    ) -> SysResult<VirtPageNum> {
         let mut max_end_vpn = offset.floor();
-        let mut header_va = 0;
-        let mut has_found_header_va = false;
         log::info!("[map_elf]: entry point {:#x}", elf.header.pt2.entry_point());
 
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() != xmas_elf::program::Type::Load {
+        for ph in elf.program_iter() {
+            if ph.get_type() != Ok(program::Type::Load) {
                 continue;
             }
             let start_va: VirtAddr = (ph.virtual_addr() as usize + offset.0).into();
             let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize + offset.0).into();
-            if !has_found_header_va {
-                header_va = start_va.0;
-                has_found_header_va = true;
+            // Segments of the program should lie below the interpreter, and those of the
+            // interpreter below the top of user space.
+            if end_va.0 > offset.0 + DL_INTERP_OFFSET {
+                log::warn!("[map_elf] segment [{start_va:#x}, {end_va:#x}] out of range");
+                return Err(SysError::ENOEXEC);
             }
             let mut map_perm = MapPerm::U;
             let ph_flags = ph.flags();
@@ -125,6 +333,10 @@ impl MemorySpace {
                 map_perm |= MapPerm::X;
             }
             let mut vm_area = VmArea::new(start_va..end_va, map_perm, VmAreaType::Elf);
+            if self.areas().is_range_free(vm_area.range_va()).is_err() {
+                log::warn!("[map_elf] segment [{start_va:#x}, {end_va:#x}] overlaps");
+                return Err(SysError::ENOEXEC);
+            }
 
             log::debug!("[map_elf] [{start_va:#x}, {end_va:#x}], map_perm: {map_perm:?} start...",);
 
@@ -139,8 +351,10 @@ impl MemorySpace {
                 ph.mem_size()
             );
 
-            if ph.file_size() == ph.mem_size() && is_aligned_to_page(ph.offset() as usize) {
-                assert!(!map_perm.contains(MapPerm::W));
+            if ph.file_size() == ph.mem_size()
+                && is_aligned_to_page(ph.offset() as usize)
+                && !map_perm.contains(MapPerm::W)
+            {
                 // NOTE: only add cow flag in elf page newly mapped.
                 // FIXME: mprotect is not checked yet
                 // WARN: the underlying elf file page cache may be edited, may cause unknown
@@ -151,10 +365,10 @@ impl MemorySpace {
                     let offset = start_offset + (vpn - vm_area.start_vpn()) * PAGE_SIZE;
                     let offset_aligned = round_down_to_page(offset);
                     if let Some(page) =
-                        block_on(async { elf_file.get_page_at(offset_aligned).await }).unwrap()
+                        block_on(async { elf_file.get_page_at(offset_aligned).await })?
                     {
                         if pre_alloc_page_cnt < USER_ELF_PRE_ALLOC_PAGE_CNT {
-                            let new_page = Page::new();
+                            let new_page = Page::try_new()?;
                             // WARN: area outer than region may should be set to zero
                             new_page.copy_from_slice(page.bytes_array());
                             self.page_table_mut()
@@ -184,40 +398,88 @@ impl MemorySpace {
                     vm_area,
                     map_offset,
                     &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize],
-                );
+                )?;
             }
         }
 
-        (max_end_vpn, header_va.into())
+        Ok(max_end_vpn)
+    }
+
+    /// Remap the `PT_GNU_RELRO` region of the elf read-only.
+    ///
+    /// The region is shrunk to whole pages, as the dynamic linker does.
+    fn protect_relro(&mut self, elf: &ElfFile, offset: VirtAddr) -> SysResult<()> {
+        let Some(ph) = elf
+            .program_iter()
+            .find(|ph| ph.get_type() == Ok(program::Type::GnuRelro))
+        else {
+            return Ok(());
+        };
+        let start = VirtAddr::from(ph.virtual_addr() as usize + offset.0).round_down();
+        let end =
+            VirtAddr::from((ph.virtual_addr() + ph.mem_size()) as usize + offset.0).round_down();
+        let Some((area_range, area)) = self.areas().get_key_value(start) else {
+            return Err(SysError::ENOEXEC);
+        };
+        let end = cmp::min(end, area_range.end);
+        if start >= end {
+            return Ok(());
+        }
+        let mut perm = area.perm();
+        perm.remove(MapPerm::W);
+        log::debug!("[protect_relro] [{start:#x}, {end:#x}], map_perm: {perm:?}");
+        self.mprotect(start..end, perm)
     }
 
     pub fn parse_and_map_elf(
         &mut self,
         elf_file: Arc<dyn File>,
         elf_data: &[u8],
-    ) -> (usize, Vec<AuxHeader>) {
-        const ELF_MAGIC: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46];
-
+    ) -> SysResult<(usize, Vec<AuxHeader>)> {
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        let elf = parse_elf(elf_data)?;
         let elf_header = elf.header;
-        assert_eq!(elf_header.pt1.magic, ELF_MAGIC, "invalid elf!");
         let entry = elf_header.pt2.entry_point() as usize;
         let ph_entry_size = elf_header.pt2.ph_entry_size() as usize;
         let ph_count = elf_header.pt2.ph_count() as usize;
 
         let mut auxv = generate_early_auxv(ph_entry_size, ph_count, entry);
 
-        auxv.push(AuxHeader::new(AT_BASE, 0));
-
-        let (_max_end_vpn, header_va) = self.map_elf(elf_file, &elf, 0.into());
+        self.map_elf(elf_file, &elf, 0.into())?;
+
+        // Nothing will relocate an elf without an interpreter or a dynamic section
+        // after it is loaded, so its relro region can be protected now. Otherwise
+        // the dynamic linker, or the startup code of a static pie, writes to the
+        // region first and protects it by itself.
+        let needs_relocation = elf.program_iter().any(|ph| {
+            matches!(
+                ph.get_type(),
+                Ok(program::Type::Interp | program::Type::Dynamic)
+            )
+        });
+        if !needs_relocation {
+            self.protect_relro(&elf, 0.into())?;
+        }
 
-        let ph_head_addr = header_va.0 + elf.header.pt2.ph_offset() as usize;
-        auxv.push(AuxHeader::new(AT_RANDOM, ph_head_addr));
+        // The program headers are mapped with the segment covering them in the file,
+        // which may not be the first one.
+        let ph_offset = elf_header.pt2.ph_offset();
+        let ph_head_addr = elf
+            .program_iter()
+            .find_map(|ph| match ph.get_type() {
+                Ok(program::Type::Phdr) => Some(ph.virtual_addr()),
+                Ok(program::Type::Load)
+                    if (ph.offset()..ph.offset() + ph.file_size()).contains(&ph_offset) =>
+                {
+                    Some(ph.virtual_addr() + ph_offset - ph.offset())
+                }
+                _ => None,
+            })
+            .unwrap_or(0) as usize;
         log::debug!("[parse_and_map_elf] AT_PHDR  ph_head_addr is {ph_head_addr:x}",);
         auxv.push(AuxHeader::new(AT_PHDR, ph_head_addr));
 
-        (entry, auxv)
+        Ok((entry, auxv))
     }
 
     /// Check whether the elf file is dynamic linked and if so, load the dl
@@ -225,47 +487,42 @@ impl MemorySpace {
     ///
     /// Return the interpreter's entry point(at the base of DL_INTERP_OFFSET) if
     /// so.
-    pub fn load_dl_interp_if_needed(&mut self, elf: &ElfFile) -> Option<usize> {
-        let elf_header = elf.header;
-        let ph_count = elf_header.pt2.ph_count();
-
-        let mut is_dl = false;
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Interp {
-                is_dl = true;
-                break;
-            }
-        }
-
-        if is_dl {
-            log::info!("[load_dl] encounter a dl elf");
-            let section = elf.find_section_by_name(".interp").unwrap();
-            let mut interp = String::from_utf8(section.raw_data(&elf).to_vec()).unwrap();
-            interp = interp.strip_suffix("\0").unwrap_or(&interp).to_string();
-            log::info!("[load_dl] interp {}", interp);
-
-            let mut interps: Vec<String> = vec![interp.clone()];
-
-            log::info!("interp {}", interp);
+    pub fn load_dl_interp_if_needed(&mut self, elf: &ElfFile) -> SysResult<Option<usize>> {
+        let Some(ph) = elf
+            .program_iter()
+            .find(|ph| ph.get_type() == Ok(program::Type::Interp))
+        else {
+            log::debug!("[load_dl] encounter a static elf");
+            return Ok(None);
+        };
 
-            let mut interp_dentry: SysResult<Arc<dyn Dentry>> = Err(SysError::ENOENT);
-            for interp in interps.into_iter() {
-                if let Ok(dentry) = current_task_ref().resolve_path(&interp) {
-                    interp_dentry = Ok(dentry);
-                    break;
-                }
-            }
-            let interp_dentry: Arc<dyn Dentry> = interp_dentry.unwrap();
-            let interp_file = interp_dentry.open().ok().unwrap();
-            let interp_elf_data = block_on(async { interp_file.read_all().await }).ok()?;
-            let interp_elf = xmas_elf::ElfFile::new(&interp_elf_data).unwrap();
-            self.map_elf(interp_file, &interp_elf, DL_INTERP_OFFSET.into());
+        log::info!("[load_dl] encounter a dl elf");
+        let data = &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize];
+        let interp = core::str::from_utf8(data).map_err(|_| SysError::ENOEXEC)?;
+        let interp = interp.strip_suffix('\0').unwrap_or(interp);
+        log::info!("[load_dl] interp {}", interp);
+
+        let interp_dentry = current_task_ref().resolve_path(interp)?;
+        let interp_file = interp_dentry.open()?;
+        let interp_elf_data = block_on(async { interp_file.read_all().await })?;
+        let interp_elf = parse_elf(&interp_elf_data)?;
+        self.map_elf(interp_file, &interp_elf, DL_INTERP_OFFSET.into())?;
+
+        Ok(Some(
+            interp_elf.header.pt2.entry_point() as usize + DL_INTERP_OFFSET,
+        ))
+    }
 
-            Some(interp_elf.header.pt2.entry_point() as usize + DL_INTERP_OFFSET)
+    /// Permission of the user stack requested by the `PT_GNU_STACK` header of
+    /// the elf, which is executable only if the header asks for it.
+    pub fn elf_stack_perm(elf: &ElfFile) -> MapPerm {
+        let exec_stack = elf.program_iter().any(|ph| {
+            ph.get_type() == Ok(program::Type::OsSpecific(PT_GNU_STACK)) && ph.flags().is_execute()
+        });
+        if exec_stack {
+            MapPerm::URWX
         } else {
-            log::debug!("[load_dl] encounter a static elf");
-            None
+            MapPerm::URW
         }
     }
 
@@ -280,7 +537,7 @@ impl MemorySpace {
         size: usize,
         shmaddr: VirtAddr,
         map_perm: MapPerm,
-        pages: &mut Vec<Weak<Page>>,
+        pages: &mut Vec<Arc<Page>>,
     ) -> VirtAddr {
         let mut ret_addr = shmaddr;
         let mut vm_area = if shmaddr == 0.into() {
@@ -301,14 +558,14 @@ impl MemorySpace {
             for vpn in vm_area.range_vpn() {
                 let page = Page::new();
                 self.page_table_mut().map(vpn, page.ppn(), map_perm.into());
-                pages.push(Arc::downgrade(&page));
+                pages.push(page.clone());
                 vm_area.pages.insert(vpn, page);
             }
         } else {
             debug_assert!(pages.len() == vm_area.range_vpn().end - vm_area.range_vpn().start);
             let mut pages = pages.iter();
             for vpn in vm_area.range_vpn() {
-                let page = pages.next().unwrap().upgrade().unwrap();
+                let page = pages.next().unwrap();
                 self.page_table_mut().map(vpn, page.ppn(), map_perm.into());
                 vm_area.pages.insert(vpn, page.clone());
             }
@@ -339,7 +596,9 @@ impl MemorySpace {
             panic!("[detach_shm] this won't happen");
         }
         if let Some(range) = range_to_remove {
-            self.areas_mut().force_remove_one(range);
+            let vma = self.remove_area(range.clone());
+            self.flush_range(range);
+            tlb::free_after_shootdown(vma.pages.into_values());
         } else {
             panic!("[detach_shm] range_to_remove is None! This should never happen");
         }
@@ -349,8 +608,8 @@ impl MemorySpace {
     ///
     /// Return the address of the stack top, which is aligned to 16 bytes.
     ///
-    /// The stack has a range of [sp - size, sp].
-    pub fn alloc_stack_lazily(&mut self, size: usize) -> VirtAddr {
+    /// The stack has a range of [sp - size, sp], mapped with `perm`.
+    pub fn alloc_stack_lazily(&mut self, size: usize, perm: MapPerm) -> VirtAddr {
         const STACK_RANGE: Range<VirtAddr> =
             VirtAddr::from_usize_range(U_SEG_STACK_BEG..U_SEG_STACK_END);
 
@@ -363,7 +622,7 @@ impl MemorySpace {
         let sp_init = VirtAddr::from((range.end.bits() - 1) & !0xf);
         log::debug!("[MemorySpace::alloc_stack] stack: {range:x?}, sp_init: {sp_init:x?}");
 
-        let mut vm_area = VmArea::new(range.clone(), MapPerm::URW, VmAreaType::Stack);
+        let mut vm_area = VmArea::new(range.clone(), perm, VmAreaType::Stack);
         vm_area.map_range(
             self.page_table_mut(),
             range.end - USER_STACK_PRE_ALLOC_SIZE..range.end,
@@ -403,24 +662,37 @@ impl MemorySpace {
             .find(|(_, vma)| vma.vma_type == VmAreaType::Heap)
             .unwrap();
         log::debug!("[MemorySpace::reset_heap_break] heap range: {range:?}, new_brk: {new_brk:?}");
+        // Shrinking the heap to empty would remove its area, so the break
+        // can not go down to the start, and it can not leave the heap segment
+        // either. This covers `brk(0)`, which is used to query the break.
+        if new_brk <= range.start || new_brk.bits() > U_SEG_HEAP_END {
+            return range.end;
+        }
         let result = if new_brk > range.end {
             let ret = self.areas_mut().extend_back(range.start..new_brk);
             if ret.is_ok() {
                 let (range_va, vm_area) = self.areas_mut().get_key_value_mut(range.start).unwrap();
                 vm_area.set_range_va(range_va);
+                // the break moves even if the locked heap can not be faulted in
+                if vm_area.locked {
+                    let populated =
+                        self.update_area(vm_area, |area| area.populate(self.page_table_mut()));
+                    if let Err(err) = populated {
+                        log::warn!("[MemorySpace::reset_heap_break] failed to populate: {err:?}");
+                    }
+                }
             }
             ret
         } else if new_brk < range.end {
             let ret = self.areas_mut().reduce_back(range.start, new_brk);
             if ret.is_ok() {
                 let (range_va, _) = self.areas_mut().get_key_value(range.start).unwrap();
-                let vma = self.areas_mut().force_remove_one(range_va.clone());
+                let vma = self.remove_area(range_va.clone());
                 let (left, middle, right) = vma.split(range_va);
                 debug_assert!(left.is_none());
                 debug_assert!(middle.is_some());
                 debug_assert!(right.is_some());
-                let mut right_vma = right.unwrap();
-                right_vma.unmap(self.page_table_mut());
+                self.unmap_vma(right.unwrap());
                 self.push_vma_lazily(middle.unwrap());
             }
             ret
@@ -436,9 +708,17 @@ impl MemorySpace {
     /// Clone a same `MemorySpace` lazily.
     pub fn from_user_lazily(user_space: &mut Self) -> Self {
         let mut memory_space = Self::new_user();
+        // copy-on-write works on base pages
+        for (_, area) in user_space.areas_mut().iter_mut() {
+            user_space.update_area(area, |area| {
+                area.split_huge_pages(user_space.page_table_mut(), area.range_vpn())
+            });
+        }
         for (range, area) in user_space.areas().iter() {
             log::debug!("[MemorySpace::from_user_lazily] cloning {area:?}");
             let mut new_area = area.clone();
+            // memory locks are not inherited by the child
+            new_area.locked = false;
             debug_assert_eq!(range, new_area.range_va());
             for vpn in area.range_vpn() {
                 if let Some(page) = area.pages.get(&vpn) {
@@ -468,27 +748,46 @@ impl MemorySpace {
             }
             memory_space.push_vma_lazily(new_area);
         }
+        // the pages are no longer writable by other threads of this process
+        user_space.flush_range(VirtAddr::from(0)..VirtAddr::from(U_SEG_END));
+        memory_space.memlock_limit = user_space.memlock_limit;
+        memory_space.core_limit = user_space.core_limit;
         memory_space
     }
 
     /// Push `VmArea` into `MemorySpace` and map it in page table.
-    pub fn push_vma(&mut self, mut vma: VmArea) {
-        vma.map(self.page_table_mut());
-        self.areas_mut().try_insert(vma.range_va(), vma).unwrap();
+    ///
+    /// Nothing is pushed if the pages can not be allocated.
+    pub fn push_vma(&mut self, mut vma: VmArea) -> SysResult<()> {
+        if let Err(e) = vma.map(self.page_table_mut()) {
+            self.unmap_vma(vma);
+            return Err(e);
+        }
+        self.insert_area(vma);
+        Ok(())
     }
 
     /// Push `VmArea` into `MemorySpace` without mapping it in page table.
     pub fn push_vma_lazily(&mut self, vma: VmArea) {
-        self.areas_mut().try_insert(vma.range_va(), vma).unwrap();
+        self.insert_area(vma);
     }
 
     /// Push `VmArea` into `MemorySpace` and map it in page table, also copy
     /// `data` at `offset` of `vma`.
-    pub fn push_vma_with_data(&mut self, mut vma: VmArea, offset: usize, data: &[u8]) {
-        vma.map(self.page_table_mut());
+    pub fn push_vma_with_data(
+        &mut self,
+        mut vma: VmArea,
+        offset: usize,
+        data: &[u8],
+    ) -> SysResult<()> {
+        if let Err(e) = vma.map(self.page_table_mut()) {
+            self.unmap_vma(vma);
+            return Err(e);
+        }
         vma.fill_zero();
         vma.copy_data_with_offset(self.page_table_mut(), offset, data);
-        self.areas_mut().try_insert(vma.range_va(), vma).unwrap();
+        self.insert_area(vma);
+        Ok(())
     }
 
     pub fn alloc_mmap_shared_anonymous(
@@ -505,12 +804,11 @@ impl MemorySpace {
         } else {
             self.areas_mut()
                 .find_free_range(SHARED_RANGE, length)
-                .expect("shared range is full")
+                .ok_or(SysError::ENOMEM)?
         };
         let start = range.start;
         let vma = VmArea::new(range, perm, VmAreaType::Shm);
-        self.push_vma(vma);
-        // self.areas_mut().try_insert(vma.range_va(), vma).unwrap();
+        self.push_vma(vma)?;
         Ok(start)
     }
 
@@ -528,11 +826,19 @@ impl MemorySpace {
         } else {
             self.areas_mut()
                 .find_free_range(MMAP_RANGE, length)
-                .expect("mmap range is full")
+                .ok_or(SysError::ENOMEM)?
         };
         let start = range.start;
-        let vma = VmArea::new_mmap(range, perm, flags, None, 0);
-        self.areas_mut().try_insert(vma.range_va(), vma).unwrap();
+        let mut vma = VmArea::new_mmap(range, perm, flags, None, 0);
+        if flags.contains(MmapFlags::MAP_POPULATE) {
+            // fail now rather than by a SIGKILL on a later page fault
+            if let Err(e) = vma.map(self.page_table_mut()) {
+                self.unmap_vma(vma);
+                return Err(e);
+            }
+            vma.fill_zero();
+        }
+        self.insert_area(vma);
         Ok(start)
     }
 
@@ -557,7 +863,7 @@ impl MemorySpace {
         } else {
             self.areas_mut()
                 .find_free_range(MMAP_RANGE, length)
-                .expect("mmap range is full")
+                .ok_or(SysError::ENOMEM)?
         };
         let start = range.start;
 
@@ -601,19 +907,16 @@ impl MemorySpace {
         Option<&mut VmArea>,
         Option<&mut VmArea>,
     ) {
-        let area = self.areas_mut().force_remove_one(old_range);
+        let mut area = self.remove_area(old_range);
+        for vpn in [split_range.start.floor(), split_range.end.floor()] {
+            if vpn.0 % HUGE_PAGE_PAGES != 0 {
+                area.split_huge_pages(self.page_table_mut(), vpn..vpn + 1);
+            }
+        }
         let (left, middle, right) = area.split(split_range);
-        let left_ret = left.map(|left| self.areas_mut().try_insert(left.range_va(), left).unwrap());
-        let right_ret = right.map(|right| {
-            self.areas_mut()
-                .try_insert(right.range_va(), right)
-                .unwrap()
-        });
-        let middle_ret = middle.map(|middle| {
-            self.areas_mut()
-                .try_insert(middle.range_va(), middle)
-                .unwrap()
-        });
+        let left_ret = left.map(|left| self.insert_area(left));
+        let right_ret = right.map(|right| self.insert_area(right));
+        let middle_ret = middle.map(|middle| self.insert_area(middle));
         (left_ret, middle_ret, right_ret)
     }
 
@@ -629,24 +932,24 @@ impl MemorySpace {
                     "[MemorySpace::unmap] remove left most area {:?}",
                     first_range.clone()
                 );
-                let mut vma = self.areas_mut().force_remove_one(first_range);
-                vma.unmap(self.page_table_mut());
+                let vma = self.remove_area(first_range);
+                self.unmap_vma(vma);
             } else {
                 // do split and unmap
                 let split_range = range.start..cmp::min(range.end, first_range.end);
                 log::debug!("[MemorySpace::unmap] split and remove left most vma {first_vma:?} in range {split_range:?}");
                 let (_, middle, _) = self.split_area(first_range, split_range);
                 if let Some(middle) = middle {
-                    let mut vma = self.areas_mut().force_remove_one(middle.range_va());
-                    vma.unmap(self.page_table_mut());
+                    let vma = self.remove_area(middle.range_va());
+                    self.unmap_vma(vma);
                 }
             }
         }
         for (r, vma) in self.areas_mut().range_mut(range.clone()) {
             if r.start >= range.start && r.end <= range.end {
                 log::debug!("[MemorySpace::unmap] remove area {:?}", r);
-                let mut vma = self.areas_mut().force_remove_one(r);
-                vma.unmap(self.page_table_mut());
+                let vma = self.remove_area(r);
+                self.unmap_vma(vma);
             } else if r.end > range.end {
                 // do split and unmap
                 log::debug!(
@@ -655,8 +958,8 @@ impl MemorySpace {
                 );
                 let (_, middle, _) = self.split_area(r.clone(), r.start..range.end);
                 if let Some(middle) = middle {
-                    let mut vma = self.areas_mut().force_remove_one(middle.range_va());
-                    vma.unmap(self.page_table_mut());
+                    let vma = self.remove_area(middle.range_va());
+                    self.unmap_vma(vma);
                 }
             }
         }
@@ -670,13 +973,253 @@ impl MemorySpace {
             .get_key_value_mut(range.start)
             .ok_or(SysError::ENOMEM)?;
         if range == old_range {
-            area.set_perm_and_flush(self.page_table_mut(), perm);
+            area.update_perm(self.page_table_mut(), perm);
         } else {
             debug_assert!(old_range.end >= range.end);
             // do split and remap
-            let (_, middle, _) = self.split_area(old_range, range);
+            let (_, middle, _) = self.split_area(old_range, range.clone());
             if let Some(middle) = middle {
-                middle.set_perm_and_flush(self.page_table_mut(), perm);
+                middle.update_perm(self.page_table_mut(), perm);
+            }
+        }
+        self.flush_range(range);
+        Ok(())
+    }
+
+    /// Bytes of memory locked by mlock(2) and mlockall(2).
+    pub fn locked_bytes(&self) -> usize {
+        self.areas()
+            .iter()
+            .filter(|(_, area)| area.locked)
+            .map(|(range, _)| range.end - range.start)
+            .sum()
+    }
+
+    /// Ranges of the areas in `range`, clipped to it, or `ENOMEM` if some page
+    /// in `range` is not mapped.
+    fn mapped_ranges(&self, range: Range<VirtAddr>) -> SysResult<Vec<Range<VirtAddr>>> {
+        let mut ranges = Vec::new();
+        let mut start = range.start;
+        while start < range.end {
+            let (area_range, _) = self.areas().get_key_value(start).ok_or(SysError::ENOMEM)?;
+            let end = cmp::min(area_range.end, range.end);
+            ranges.push(start..end);
+            start = end;
+        }
+        Ok(ranges)
+    }
+
+    /// Check that every page in `range` is mapped, or fail with `ENOMEM`.
+    pub fn check_mapped(&self, range: Range<VirtAddr>) -> SysResult<()> {
+        self.mapped_ranges(range).map(|_| ())
+    }
+
+    /// Split the area containing `range` so that `range` is an area of its
+    /// own.
+    fn isolate_area(&self, range: Range<VirtAddr>) -> &mut VmArea {
+        let (old_range, _) = self.areas().get_key_value(range.start).unwrap();
+        if old_range == range {
+            return self.areas_mut().get_mut(range.start).unwrap();
+        }
+        let (_, middle, _) = self.split_area(old_range, range);
+        middle.unwrap()
+    }
+
+    /// Check that `bytes` more can be locked, unless `privileged`, i.e. the
+    /// caller has `CAP_IPC_LOCK`.
+    fn check_memlock(&self, bytes: usize, privileged: bool) -> SysResult<()> {
+        if privileged {
+            return Ok(());
+        }
+        let limit = self.memlock_limit.rlim_cur;
+        if limit == 0 {
+            return Err(SysError::EPERM);
+        }
+        if self.locked_bytes().saturating_add(bytes) > limit {
+            return Err(SysError::ENOMEM);
+        }
+        Ok(())
+    }
+
+    /// Lock the pages in `range` in memory, which are all faulted in unless
+    /// `on_fault`, see mlock2(2).
+    pub fn mlock(
+        &mut self,
+        range: Range<VirtAddr>,
+        on_fault: bool,
+        privileged: bool,
+    ) -> SysResult<()> {
+        debug_assert!(range.start.is_aligned() && range.end.is_aligned());
+        let ranges = self.mapped_ranges(range)?;
+        let unlocked = ranges
+            .iter()
+            .filter(|range| !self.areas().get(range.start).unwrap().locked)
+            .map(|range| range.end - range.start)
+            .sum();
+        self.check_memlock(unlocked, privileged)?;
+        for range in ranges {
+            let area = self.isolate_area(range);
+            area.locked = true;
+            if !on_fault {
+                self.update_area(area, |area| area.populate(self.page_table_mut()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Unlock the pages in `range`, see munlock(2).
+    pub fn munlock(&mut self, range: Range<VirtAddr>) -> SysResult<()> {
+        debug_assert!(range.start.is_aligned() && range.end.is_aligned());
+        for range in self.mapped_ranges(range)? {
+            self.isolate_area(range).locked = false;
+        }
+        Ok(())
+    }
+
+    /// Lock all areas mapped now if `current`, and all areas mapped from now
+    /// on if `future`, see mlockall(2).
+    pub fn mlockall(
+        &mut self,
+        current: bool,
+        future: bool,
+        on_fault: bool,
+        privileged: bool,
+    ) -> SysResult<()> {
+        if current {
+            let unlocked = self
+                .areas()
+                .iter()
+                .filter(|(_, area)| !area.locked)
+                .map(|(range, _)| range.end - range.start)
+                .sum();
+            self.check_memlock(unlocked, privileged)?;
+            for (_, area) in self.areas_mut().iter_mut() {
+                area.locked = true;
+                if !on_fault {
+                    self.update_area(area, |area| area.populate(self.page_table_mut()))?;
+                }
+            }
+        }
+        self.lock_future = future;
+        Ok(())
+    }
+
+    /// Unlock all areas, and areas mapped from now on are not locked any
+    /// longer, see munlockall(2).
+    pub fn munlockall(&mut self) {
+        for (_, area) in self.areas_mut().iter_mut() {
+            area.locked = false;
+        }
+        self.lock_future = false;
+    }
+
+    /// Check that an area of `len` bytes can be mapped, which is locked if
+    /// mlockall(MCL_FUTURE) is in effect.
+    pub fn check_future_lock(&self, len: usize, privileged: bool) -> SysResult<()> {
+        if !self.lock_future {
+            return Ok(());
+        }
+        self.check_memlock(len, privileged)
+            .map_err(|_| SysError::EAGAIN)
+    }
+
+    /// Lock the area newly mapped at `start` if mlockall(MCL_FUTURE) is in
+    /// effect. The area is kept even if it can not be faulted in, as Linux
+    /// does.
+    pub fn lock_if_future(&mut self, start: VirtAddr) {
+        if !self.lock_future {
+            return;
+        }
+        if let Some(area) = self.areas_mut().get_mut(start) {
+            area.locked = true;
+            if let Err(err) = self.update_area(area, |area| area.populate(self.page_table_mut())) {
+                log::warn!("[MemorySpace::lock_if_future] failed to populate {area:?}: {err:?}");
+            }
+        }
+    }
+
+    /// Drop the pages in `range`, which read as zeros, or from the file of a
+    /// file mapping, when touched again, see `MADV_DONTNEED` of madvise(2).
+    pub fn madvise_dontneed(&mut self, range: Range<VirtAddr>) -> SysResult<()> {
+        debug_assert!(range.start.is_aligned() && range.end.is_aligned());
+        let ranges = self.mapped_ranges(range.clone())?;
+        if ranges
+            .iter()
+            .any(|range| self.areas().get(range.start).unwrap().locked)
+        {
+            return Err(SysError::EINVAL);
+        }
+        let mut pages = Vec::new();
+        for range in ranges {
+            let area = self.areas_mut().get_mut(range.start).unwrap();
+            pages.extend(self.update_area(area, |area| {
+                area.drop_pages(self.page_table_mut(), range.start.floor()..range.end.ceil())
+            }));
+        }
+        log::debug!(
+            "[MemorySpace::madvise_dontneed] {} pages dropped in {range:?}",
+            pages.len()
+        );
+        self.flush_range(range);
+        tlb::free_after_shootdown(pages);
+        Ok(())
+    }
+
+    /// The same as `madvise_dontneed` for now, rather than dropping the pages
+    /// lazily under memory pressure, see `MADV_FREE` of madvise(2). Only
+    /// anonymous pages can be freed.
+    pub fn madvise_free(&mut self, range: Range<VirtAddr>) -> SysResult<()> {
+        if self.areas().iter().any(|(r, area)| {
+            r.start < range.end && r.end > range.start && area.backed_file.is_some()
+        }) {
+            return Err(SysError::EINVAL);
+        }
+        self.madvise_dontneed(range)
+    }
+
+    /// Mark the areas in `range` to be faulted in by huge pages or not, see
+    /// `MADV_HUGEPAGE` and `MADV_NOHUGEPAGE` of madvise(2). Huge pages already
+    /// faulted in are kept either way.
+    pub fn madvise_hugepage(&mut self, range: Range<VirtAddr>, huge: bool) -> SysResult<()> {
+        debug_assert!(range.start.is_aligned() && range.end.is_aligned());
+        for range in self.mapped_ranges(range)? {
+            self.isolate_area(range).huge = huge;
+        }
+        Ok(())
+    }
+
+    /// Swap out at most `nr` pages of the areas that can be swapped, pushing
+    /// their entries to `entries`. The pages must not be written to the swap
+    /// file until the memory space is unlocked, see `swap::swap_out`.
+    pub fn swap_out(&mut self, swap: &Arc<SwapFile>, nr: usize, entries: &mut Vec<Arc<SwapEntry>>) {
+        let target = entries.len() + nr;
+        for (_, area) in self.areas_mut().iter_mut() {
+            if entries.len() >= target {
+                break;
+            }
+            if !area.can_swap() {
+                continue;
+            }
+            let nr = target - entries.len();
+            let swapped = self.update_area(area, |area| {
+                area.swap_out(self.page_table_mut(), swap, nr, entries)
+            });
+            if let Some(range) = swapped {
+                self.flush_range(range);
+            }
+        }
+    }
+
+    /// Read all pages swapped out back in, for swapoff(2).
+    pub fn swap_in_all(&mut self) -> SysResult<()> {
+        for (_, area) in self.areas_mut().iter_mut() {
+            // PROT_NONE pages can not be mapped, and are left swapped out
+            if !area.perm().intersects(MapPerm::RWX) {
+                continue;
+            }
+            let vpns: Vec<_> = area.swapped.keys().copied().collect();
+            for vpn in vpns {
+                self.update_area(area, |area| area.swap_in(self.page_table_mut(), vpn))?;
             }
         }
         Ok(())
@@ -692,20 +1235,29 @@ impl MemorySpace {
             log::error!("[handle_page_fault] no area containing {va:?}");
             SysError::EFAULT
         })?;
-        vm_area.handle_page_fault(self.page_table_mut(), va.floor(), access_type)?;
+        let major = self.update_area(vm_area, |area| {
+            area.handle_page_fault(self.page_table_mut(), va.floor(), access_type)
+        })?;
+        if major {
+            self.stat.majflt += 1;
+        } else {
+            self.stat.minflt += 1;
+        }
+        self.stat.max_rss_pages = cmp::max(self.stat.max_rss_pages, self.resident_pages());
         Ok(())
     }
 
     pub unsafe fn switch_page_table(&self) {
+        tlb::set_active_token(self.page_table().token());
         self.page_table().switch();
     }
 }
 
 pub fn init_stack(
     sp_init: VirtAddr,
-    args: Vec<String>,
-    envp: Vec<String>,
-    auxv: Vec<AuxHeader>,
+    execfn: &str,
+    args: &ExecArgs,
+    mut auxv: Vec<AuxHeader>,
 ) -> (usize, usize, usize, usize) {
     // spec says:
     //      In the standard RISC-V calling convention, the stack grows downward
@@ -741,6 +1293,7 @@ pub fn init_stack(
     //
     // [argument ASCIIZ strings]       >= 0
     // [environment ASCIIZ str]        >= 0
+    // [filename of program]           >= 0
     // --------------------------------------------------------------------------------
     // 在构建栈的时候，我们从底向上塞各个东西
 
@@ -767,23 +1320,38 @@ pub fn init_stack(
         *sp
     }
 
-    let env_ptrs: Vec<usize> = envp.iter().rev().map(|s| push_str(&mut sp, s)).collect();
-    let arg_ptrs: Vec<usize> = args.iter().rev().map(|s| push_str(&mut sp, s)).collect();
+    let execfn_ptr = push_str(&mut sp, execfn);
+    // strings of argv and envp are already laid out in order, so they are
+    // pushed as a whole
+    let strings = args.strings();
+    sp -= strings.len();
+    unsafe { core::ptr::copy_nonoverlapping(strings.as_ptr(), sp as *mut u8, strings.len()) };
+    let mut str_ptrs = Vec::with_capacity(args.argc() + args.envc());
+    let mut str_ptr = sp;
+    for s in strings.split_inclusive(|&c| c == 0) {
+        str_ptrs.push(str_ptr);
+        str_ptr += s.len();
+    }
+    let (arg_ptrs, env_ptrs) = str_ptrs.split_at(args.argc());
 
     // 随机对齐 (我们取 0 长度的随机对齐), 平台标识符，随机数与对齐
     fn align16(sp: &mut usize) {
         *sp = (*sp - 1) & !0xf;
     }
 
-    let rand_size = 0;
-    let platform = "RISC-V64";
-    let rand_bytes = "Meow~ O4 here;D"; // 15 + 1 char for 16bytes
-
-    sp -= rand_size;
-    push_str(&mut sp, platform);
-    push_str(&mut sp, rand_bytes);
+    let platform_ptr = push_str(&mut sp, "riscv64");
+    // NOTE: libc may use these bytes as the stack guard and pointer guard
+    let mut rand_bytes = [0u8; 16];
+    driver::random::get_random_bytes(&mut rand_bytes);
+    sp -= rand_bytes.len();
+    unsafe { core::ptr::copy_nonoverlapping(rand_bytes.as_ptr(), sp as *mut u8, rand_bytes.len()) };
+    let rand_ptr = sp;
     align16(&mut sp);
 
+    auxv.push(AuxHeader::new(AT_PLATFORM, platform_ptr));
+    auxv.push(AuxHeader::new(AT_RANDOM, rand_ptr));
+    auxv.push(AuxHeader::new(AT_EXECFN, execfn_ptr));
+
     // 存放 auxv
     fn push_aux_elm(sp: &mut usize, elm: &AuxHeader) {
         *sp -= core::mem::size_of::<AuxHeader>();
@@ -807,15 +1375,21 @@ pub fn init_stack(
     }
 
     push_usize(&mut sp, 0);
-    env_ptrs.iter().for_each(|ptr| push_usize(&mut sp, *ptr));
+    env_ptrs
+        .iter()
+        .rev()
+        .for_each(|ptr| push_usize(&mut sp, *ptr));
     let env_ptr_ptr = sp;
 
     push_usize(&mut sp, 0);
-    arg_ptrs.iter().for_each(|ptr| push_usize(&mut sp, *ptr));
+    arg_ptrs
+        .iter()
+        .rev()
+        .for_each(|ptr| push_usize(&mut sp, *ptr));
     let arg_ptr_ptr = sp;
 
     // 存放 argc
-    let argc = args.len();
+    let argc = args.argc();
     push_usize(&mut sp, argc);
 
     // 返回值
@@ -1,20 +1,46 @@
-use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
-use core::ops::{Range, RangeBounds};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    iter::zip,
+    mem,
+    ops::{Range, RangeBounds},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use arch::memory::sfence_vma_vaddr;
 use async_utils::block_on;
-use config::mm::{round_down_to_page, PAGE_SIZE};
-use memory::{pte::PTEFlags, VirtAddr, VirtPageNum};
-use page::Page;
+use config::mm::{round_down_to_page, HUGE_PAGE_PAGES, PAGE_SIZE};
+use memory::{
+    pte::PTEFlags, try_alloc_aligned_frame_trackers, PageTableEntry, VirtAddr, VirtPageNum,
+};
+use page::{Page, PageKind};
 use systype::{SysError, SysResult};
 use vfs_core::File;
 
 use crate::{
-    mm::{PageFaultAccessType, PageTable},
+    mm::{
+        swap::{self, SwapEntry, SwapFile},
+        tlb, PageFaultAccessType, PageTable,
+    },
     processor::env::SumGuard,
     syscall::MmapFlags,
 };
 
+/// Copy-on-write faults that copy the page, reported by /proc/vmstat.
+pub static COW_COPIES: AtomicUsize = AtomicUsize::new(0);
+/// Copy-on-write faults that make the page writable in place, since it is not
+/// shared any longer, e.g. the child has exited or execed after fork.
+pub static COW_REUSES: AtomicUsize = AtomicUsize::new(0);
+/// Huge pages faulted in, reported by /proc/vmstat.
+pub static THP_FAULT_ALLOC: AtomicUsize = AtomicUsize::new(0);
+/// Faults that fall back to base pages for lack of aligned contiguous frames.
+pub static THP_FAULT_FALLBACK: AtomicUsize = AtomicUsize::new(0);
+/// Huge pages split into base pages.
+pub static THP_SPLIT_PAGE: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VmAreaType {
     // For user.
@@ -103,6 +129,8 @@ pub struct VmArea {
     range_va: Range<VirtAddr>,
     /// Hold pages with RAII.
     pub pages: BTreeMap<VirtPageNum, Arc<Page>>,
+    /// Pages swapped out, which are unmapped and read back in on next access.
+    pub swapped: BTreeMap<VirtPageNum, Arc<SwapEntry>>,
     /// Map permission of this area.
     pub map_perm: MapPerm,
     /// Type of this area.
@@ -115,6 +143,17 @@ pub struct VmArea {
     pub backed_file: Option<Arc<dyn File>>,
     /// Start offset in the file.
     pub offset: usize,
+
+    /// Locked in memory by mlock(2) or mlockall(2). Pages of a locked area are
+    /// all committed when it is locked, and must not be reclaimed.
+    pub locked: bool,
+
+    /// Faulted in by huge pages where possible, as asked by
+    /// madvise(MADV_HUGEPAGE). Only private anonymous areas take it.
+    pub huge: bool,
+    /// Start of the ranges mapped by huge pages, whose frames are still held
+    /// one by one in `pages`.
+    pub huge_pages: BTreeSet<VirtPageNum>,
 }
 
 impl core::fmt::Debug for VmArea {
@@ -123,6 +162,8 @@ impl core::fmt::Debug for VmArea {
             .field("range_va", &self.range_va)
             .field("map_perm", &self.map_perm)
             .field("vma_type", &self.vma_type)
+            .field("locked", &self.locked)
+            .field("huge", &self.huge)
             .finish()
     }
 }
@@ -140,11 +181,15 @@ impl VmArea {
         let new = Self {
             range_va,
             pages: BTreeMap::new(),
+            swapped: BTreeMap::new(),
             vma_type,
             map_perm,
             backed_file: None,
             mmap_flags: MmapFlags::default(),
             offset: 0,
+            locked: false,
+            huge: false,
+            huge_pages: BTreeSet::new(),
         };
         log::debug!("[VmArea::new] {new:?}");
         new
@@ -161,11 +206,15 @@ impl VmArea {
         let new = Self {
             range_va,
             pages: BTreeMap::new(),
+            swapped: BTreeMap::new(),
             vma_type: VmAreaType::Mmap,
             map_perm,
             backed_file: file,
             mmap_flags,
             offset,
+            locked: false,
+            huge: false,
+            huge_pages: BTreeSet::new(),
         };
         log::debug!("[VmArea::new_mmap] {new:?}");
         new
@@ -176,11 +225,15 @@ impl VmArea {
         Self {
             range_va: another.range_va(),
             pages: BTreeMap::new(),
+            swapped: BTreeMap::new(),
             vma_type: another.vma_type,
             map_perm: another.map_perm,
             backed_file: another.backed_file.clone(),
             mmap_flags: another.mmap_flags,
             offset: another.offset,
+            locked: another.locked,
+            huge: another.huge,
+            huge_pages: BTreeSet::new(),
         }
     }
 
@@ -230,20 +283,63 @@ impl VmArea {
         }
     }
 
-    pub fn set_perm_and_flush(&mut self, page_table: &mut PageTable, perm: MapPerm) {
+    /// Start of the huge page mapping `vpn`, if any.
+    pub fn huge_page_of(&self, vpn: VirtPageNum) -> Option<VirtPageNum> {
+        self.huge_pages
+            .range(..=vpn)
+            .next_back()
+            .copied()
+            .filter(|&start| vpn - start < HUGE_PAGE_PAGES)
+    }
+
+    /// Split the huge pages overlapping `range_vpn` into base pages.
+    ///
+    /// The base pages map the same frames with the same flags, so stale TLB
+    /// entries of the huge pages do no harm, and are flushed along with the
+    /// range whose mappings are changed afterwards.
+    pub fn split_huge_pages(&mut self, page_table: &mut PageTable, range_vpn: Range<VirtPageNum>) {
+        let starts: Vec<_> = self
+            .huge_pages
+            .range(..range_vpn.end)
+            .rev()
+            .take_while(|&&start| start + HUGE_PAGE_PAGES > range_vpn.start)
+            .copied()
+            .collect();
+        for start in starts {
+            let split = page_table.split_huge(start);
+            debug_assert!(split);
+            self.huge_pages.remove(&start);
+            THP_SPLIT_PAGE.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn update_perm(&mut self, page_table: &mut PageTable, perm: MapPerm) {
+        if !perm.intersects(MapPerm::RWX) {
+            // a pte of the middle level without R, W and X points to a page table
+            self.split_huge_pages(page_table, self.range_vpn());
+        }
         self.set_perm(perm);
         let pte_flags = perm.into();
-        // NOTE: should flush pages that already been allocated, page fault handler will
-        // handle the permission of those unallocated pages
+        let update = |pte: &mut PageTableEntry| {
+            let mut new_flags = pte.flags().union(pte_flags);
+            // W is dropped when revoked, and never granted to a cow page, which is
+            // copied by the page fault handler.
+            if !perm.contains(MapPerm::W) || pte.flags().contains(PTEFlags::COW) {
+                new_flags.remove(PTEFlags::W);
+            }
+            log::trace!("[origin pte:{:?}, new_flag:{:?}]", pte.flags(), new_flags);
+            pte.set_flags(new_flags);
+        };
+        // NOTE: should update pages that already been allocated, page fault handler
+        // will handle the permission of those unallocated pages. The TLB is
+        // flushed by the caller.
+        for &start in self.huge_pages.iter() {
+            update(page_table.find_pte(start).unwrap().0);
+        }
         for &vpn in self.pages.keys() {
-            let pte = page_table.find_leaf_pte(vpn).unwrap();
-            log::trace!(
-                "[origin pte:{:?}, new_flag:{:?}]",
-                pte.flags(),
-                pte.flags().union(pte_flags)
-            );
-            pte.set_flags(pte.flags().union(pte_flags));
-            unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
+            if self.huge_page_of(vpn).is_none() {
+                update(page_table.find_leaf_pte(vpn).unwrap());
+            }
         }
     }
 
@@ -256,17 +352,20 @@ impl VmArea {
 
     /// Map `VmArea` into page table.
     ///
-    /// Will alloc new pages for `VmArea` according to `VmAreaType`.
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// Will alloc new pages for `VmArea` according to `VmAreaType`, and fail
+    /// with `ENOMEM` when out of memory, leaving the pages mapped so far to be
+    /// unmapped by the caller.
+    pub fn map(&mut self, page_table: &mut PageTable) -> SysResult<()> {
         // NOTE: set pte flag with global mapping for kernel memory
         let pte_flags: PTEFlags = self.map_perm.into();
 
         for vpn in self.range_vpn() {
-            let page = Page::new();
+            let page = Page::try_new()?;
             // page.clear();
             page_table.map(vpn, page.ppn(), pte_flags);
             self.pages.insert(vpn, page);
         }
+        Ok(())
     }
 
     pub fn map_range(&mut self, page_table: &mut PageTable, range: Range<VirtAddr>) {
@@ -281,13 +380,156 @@ impl VmArea {
         }
     }
 
-    pub fn unmap(&mut self, page_table: &mut PageTable) {
-        let vpns: Vec<_> = self.pages.keys().cloned().collect();
-        for vpn in vpns {
+    /// Unmap the pages allocated, which are returned to be freed after the TLB
+    /// is flushed by the caller.
+    pub fn unmap(&mut self, page_table: &mut PageTable) -> BTreeMap<VirtPageNum, Arc<Page>> {
+        for &start in self.huge_pages.iter() {
+            page_table.unmap_huge(start);
+        }
+        for &vpn in self.pages.keys() {
+            if self.huge_page_of(vpn).is_none() {
+                page_table.unmap(vpn);
+            }
+        }
+        self.huge_pages.clear();
+        mem::take(&mut self.pages)
+    }
+
+    /// Unmap the pages allocated in `range_vpn`, which are faulted in again on
+    /// next access, as zero pages or from the backing file. Pages that can
+    /// not be faulted in again, i.e. of ELF segments and shared memory, are
+    /// kept. Pages swapped out are dropped as well. The dropped pages are
+    /// returned to be freed after the TLB is flushed by the caller.
+    pub fn drop_pages(
+        &mut self,
+        page_table: &mut PageTable,
+        range_vpn: Range<VirtPageNum>,
+    ) -> Vec<Arc<Page>> {
+        if !matches!(
+            self.vma_type,
+            VmAreaType::Heap | VmAreaType::Stack | VmAreaType::Mmap
+        ) {
+            return Vec::new();
+        }
+        self.swapped.retain(|vpn, _| !range_vpn.contains(vpn));
+        self.split_huge_pages(page_table, range_vpn.clone());
+        let vpns: Vec<_> = self.pages.range(range_vpn).map(|(&vpn, _)| vpn).collect();
+        vpns.into_iter()
+            .map(|vpn| {
+                page_table.unmap(vpn);
+                self.pages.remove(&vpn).unwrap()
+            })
+            .collect()
+    }
+
+    /// Whether pages of this area can be swapped out, i.e. it is private
+    /// anonymous memory that is accessible and not locked.
+    pub fn can_swap(&self) -> bool {
+        let anonymous = match self.vma_type {
+            VmAreaType::Heap | VmAreaType::Stack => true,
+            VmAreaType::Mmap => {
+                self.backed_file.is_none() && !self.mmap_flags.contains(MmapFlags::MAP_SHARED)
+            }
+            _ => false,
+        };
+        anonymous && !self.locked && self.map_perm.contains(MapPerm::R)
+    }
+
+    /// Swap out at most `nr` pages that are not accessed since the last scan,
+    /// and clear the accessed bits of the others. Pages shared with other
+    /// areas, e.g. copy-on-write pages after fork, and huge pages are skipped.
+    ///
+    /// The entries of the pages swapped out are pushed to `entries`, and the
+    /// range of them is returned, which must be flushed from the TLB by the
+    /// caller before the pages are written to the swap file.
+    pub fn swap_out(
+        &mut self,
+        page_table: &mut PageTable,
+        swap: &Arc<SwapFile>,
+        nr: usize,
+        entries: &mut Vec<Arc<SwapEntry>>,
+    ) -> Option<Range<VirtAddr>> {
+        let mut victims = Vec::new();
+        for (&vpn, page) in self.pages.iter() {
+            if victims.len() >= nr {
+                break;
+            }
+            if Arc::strong_count(page) > 1
+                || !matches!(page.kind(), PageKind::Normal)
+                || self.huge_page_of(vpn).is_some()
+            {
+                continue;
+            }
+            let pte = page_table.find_leaf_pte(vpn).unwrap();
+            let flags = pte.flags();
+            if flags.contains(PTEFlags::A) {
+                pte.set_flags(flags.difference(PTEFlags::A));
+            } else {
+                victims.push(vpn);
+            }
+        }
+        let mut range: Option<Range<VirtAddr>> = None;
+        for vpn in victims {
+            let page = self.pages.remove(&vpn).unwrap();
+            let Some(entry) = swap::new_entry(swap, page.clone()) else {
+                self.pages.insert(vpn, page);
+                break;
+            };
             page_table.unmap(vpn);
-            unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
-            self.pages.remove(&vpn);
+            self.swapped.insert(vpn, entry.clone());
+            entries.push(entry);
+            let va = vpn.to_vaddr();
+            range = Some(match range {
+                Some(r) => r.start.min(va)..r.end.max(va + PAGE_SIZE),
+                None => va..va + PAGE_SIZE,
+            });
+        }
+        range
+    }
+
+    /// Read the page swapped out at `vpn` back in, and return whether it is
+    /// read from the swap file.
+    pub fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> SysResult<bool> {
+        let (page, major) = self.swapped[&vpn].swap_in()?;
+        page_table.map(vpn, page.ppn(), self.map_perm.into());
+        self.pages.insert(vpn, page);
+        self.swapped.remove(&vpn);
+        unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
+        Ok(major)
+    }
+
+    /// Fault in the huge page containing `vpn` if this area takes huge pages,
+    /// and the aligned range of it lies in this area with nothing mapped yet.
+    ///
+    /// Return false to fall back to a base page, e.g. when there are no
+    /// aligned contiguous frames for it.
+    fn fault_in_huge_page(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        if !self.huge {
+            return false;
+        }
+        let start = VirtPageNum::from(vpn.0 / HUGE_PAGE_PAGES * HUGE_PAGE_PAGES);
+        let range_vpn = start..start + HUGE_PAGE_PAGES;
+        if range_vpn.start < self.start_vpn()
+            || range_vpn.end > self.end_vpn()
+            || self.pages.range(range_vpn.clone()).next().is_some()
+            || self.swapped.range(range_vpn.clone()).next().is_some()
+        {
+            return false;
+        }
+        let Some(frames) = try_alloc_aligned_frame_trackers(HUGE_PAGE_PAGES) else {
+            THP_FAULT_FALLBACK.fetch_add(1, Ordering::Relaxed);
+            return false;
+        };
+        let ppn = frames[0].ppn;
+        for (vpn, frame) in zip(range_vpn, frames) {
+            frame.fill_zero();
+            self.pages.insert(vpn, Page::from_frame(frame));
         }
+        page_table.map_huge(start, ppn, self.map_perm.into());
+        self.huge_pages.insert(start);
+        THP_FAULT_ALLOC.fetch_add(1, Ordering::Relaxed);
+        unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
+        true
     }
 
     /// Copy the data to start_va + offset.
@@ -323,6 +565,9 @@ impl VmArea {
         }
     }
 
+    /// Split the area into the parts before, in and after `split_range`.
+    ///
+    /// Huge pages across the boundaries must be split by the caller first.
     pub fn split(self, split_range: Range<VirtAddr>) -> (Option<Self>, Option<Self>, Option<Self>) {
         debug_assert!(split_range.start.is_aligned() && split_range.end.is_aligned());
         debug_assert!(split_range.start >= self.start_va() && split_range.end <= self.end_va());
@@ -341,6 +586,14 @@ impl VmArea {
                     .into_iter()
                     .map(|(&k, v)| (k, v.clone())),
             );
+            left_vma.swapped.extend(
+                self.swapped
+                    .range(left_vma.range_vpn())
+                    .map(|(&k, v)| (k, v.clone())),
+            );
+            left_vma
+                .huge_pages
+                .extend(self.huge_pages.range(left_vma.range_vpn()));
             left_vma.offset += left_vma.start_va() - self.start_va();
             left = Some(left_vma)
         }
@@ -353,6 +606,14 @@ impl VmArea {
                     .into_iter()
                     .map(|(&k, v)| (k, v.clone())),
             );
+            middle_vma.swapped.extend(
+                self.swapped
+                    .range(middle_vma.range_vpn())
+                    .map(|(&k, v)| (k, v.clone())),
+            );
+            middle_vma
+                .huge_pages
+                .extend(self.huge_pages.range(middle_vma.range_vpn()));
             middle_vma.offset += middle_vma.start_va() - self.start_va();
             middle = Some(middle_vma)
         }
@@ -365,6 +626,14 @@ impl VmArea {
                     .into_iter()
                     .map(|(&k, v)| (k, v.clone())),
             );
+            right_vma.swapped.extend(
+                self.swapped
+                    .range(right_vma.range_vpn())
+                    .map(|(&k, v)| (k, v.clone())),
+            );
+            right_vma
+                .huge_pages
+                .extend(self.huge_pages.range(right_vma.range_vpn()));
             right_vma.offset += right_vma.start_va() - self.start_va();
             right = Some(right_vma)
         }
@@ -374,14 +643,51 @@ impl VmArea {
         (left, middle, right)
     }
 
+    /// Fault in all pages of this area, with copy-on-write broken if it is
+    /// writable, so that it takes no page fault any longer. Pages of a file
+    /// mapping beyond the end of the file are left unmapped.
+    pub fn populate(&mut self, page_table: &mut PageTable) -> SysResult<()> {
+        let perm = self.perm();
+        let access_type = if perm.contains(MapPerm::W) {
+            PageFaultAccessType::RW
+        } else if perm.contains(MapPerm::R) {
+            PageFaultAccessType::RO
+        } else if perm.contains(MapPerm::X) {
+            PageFaultAccessType::RX
+        } else {
+            // PROT_NONE can not be faulted in
+            return Ok(());
+        };
+        for vpn in self.range_vpn() {
+            if let Some(file) = &self.backed_file {
+                if self.offset + (vpn - self.start_vpn()) * PAGE_SIZE >= file.size() {
+                    break;
+                }
+            }
+            let populated = self.huge_page_of(vpn).is_some()
+                || page_table.find_leaf_pte(vpn).is_some_and(|pte| {
+                    !(access_type.contains(PageFaultAccessType::WRITE)
+                        && pte.flags().contains(PTEFlags::COW))
+                });
+            if !populated {
+                self.handle_page_fault(page_table, vpn, access_type)?;
+            }
+        }
+        Ok(())
+    }
+
     // FIXME: should kill user program if it deref a invalid pointer, e.g. try to
     // write at a read only area?
+    /// Handle page fault at `vpn` in this area.
+    ///
+    /// Return true if it is a major fault, i.e. the page has to be read from
+    /// the backing file because it is missed in the page cache.
     pub fn handle_page_fault(
         &mut self,
         page_table: &mut PageTable,
         vpn: VirtPageNum,
         access_type: PageFaultAccessType,
-    ) -> SysResult<()> {
+    ) -> SysResult<bool> {
         log::debug!(
             "[VmArea::handle_page_fault] {self:?}, {vpn:?} at page table {:?}",
             page_table.root_ppn()
@@ -395,28 +701,52 @@ impl VmArea {
             return Err(SysError::EFAULT);
         }
 
+        if self.huge_page_of(vpn).is_some() {
+            // resolved by another thread already, while the fault comes from a
+            // stale TLB entry of this hart
+            unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
+            return Ok(false);
+        }
+
         let page: Arc<Page>;
+        let mut major = false;
         let pte = page_table.find_leaf_pte(vpn);
         if let Some(pte) = pte {
             // if PTE is valid, then it must be COW
             log::debug!("[VmArea::handle_page_fault] pte flags: {:?}", pte.flags());
             let mut pte_flags = pte.flags();
+            if !pte_flags.contains(PTEFlags::A) {
+                // cleared by swap-out, which harts not setting it in hardware
+                // fault on
+                pte.set_flags(pte_flags | PTEFlags::A);
+                unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
+                return Ok(false);
+            }
+            if !pte_flags.contains(PTEFlags::COW) {
+                // resolved by another thread already, while the fault comes from
+                // a stale TLB entry of this hart
+                unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
+                return Ok(false);
+            }
 
-            debug_assert!(pte_flags.contains(PTEFlags::COW));
             debug_assert!(!pte_flags.contains(PTEFlags::W));
             debug_assert!(self.perm().contains(MapPerm::UW));
 
             // PERF: copying data vs. lock the area vs. atomic ref cnt
             let old_page = self.get_page(vpn);
             let cnt = Arc::strong_count(old_page);
-            if cnt > 1 {
-                // shared now
+            // NOTE: a page of a file may still be reached through the buffer
+            // cache after it is evicted from the page cache, so it is never
+            // written in place even if it is mapped here only
+            let exclusive = cnt == 1 && matches!(old_page.kind(), PageKind::Normal);
+            if !exclusive {
+                COW_COPIES.fetch_add(1, Ordering::Relaxed);
                 log::debug!(
                     "[VmArea::handle_page_fault] copying cow page {old_page:?} with count {cnt}",
                 );
 
                 // copy the data
-                page = Page::new();
+                page = Page::try_new()?;
                 page.copy_from_slice(old_page.bytes_array());
 
                 // unmap old page and map new page
@@ -424,10 +754,13 @@ impl VmArea {
                 pte_flags.insert(PTEFlags::W);
                 page_table.map_force(vpn, page.ppn(), pte_flags);
                 // NOTE: track `Page` with great care
-                self.pages.insert(vpn, page);
-                unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
+                let old_page = self.pages.insert(vpn, page).unwrap();
+                // other threads must not read the old page any longer
+                let va = vpn.to_vaddr();
+                tlb::shootdown(page_table, va..va + PAGE_SIZE);
+                tlb::free_after_shootdown([old_page]);
             } else {
-                // not shared
+                COW_REUSES.fetch_add(1, Ordering::Relaxed);
                 log::debug!("[VmArea::handle_page_fault] removing cow flag for page {old_page:?}",);
 
                 // set the pte to writable
@@ -436,6 +769,8 @@ impl VmArea {
                 pte.set_flags(pte_flags);
                 unsafe { sfence_vma_vaddr(vpn.to_vaddr().into()) };
             }
+        } else if self.swapped.contains_key(&vpn) {
+            major = self.swap_in(page_table, vpn)?;
         } else {
             log::debug!(
                 "[VmArea::handle_page_fault] handle for type {:?}",
@@ -444,7 +779,7 @@ impl VmArea {
             match self.vma_type {
                 VmAreaType::Heap | VmAreaType::Stack => {
                     // lazy allcation for heap
-                    page = Page::new();
+                    page = Page::try_new()?;
                     page.fill_zero();
                     page_table.map(vpn, page.ppn(), self.map_perm.into());
                     self.pages.insert(vpn, page);
@@ -456,6 +791,10 @@ impl VmArea {
                         let file = self.backed_file.as_ref().unwrap();
                         let offset = self.offset + (vpn - self.start_vpn()) * PAGE_SIZE;
                         let offset_aligned = round_down_to_page(offset);
+                        major = file
+                            .inode()
+                            .page_cache()
+                            .is_some_and(|cache| cache.get_page(offset_aligned).is_none());
                         if self.mmap_flags.contains(MmapFlags::MAP_SHARED) {
                             let page = block_on(async { file.get_page_at(offset_aligned).await })?
                                 .unwrap();
@@ -466,7 +805,7 @@ impl VmArea {
                             let page = block_on(async { file.get_page_at(offset_aligned).await })?
                                 .unwrap();
                             if access_type.contains(PageFaultAccessType::WRITE) {
-                                let new_page = Page::new();
+                                let new_page = Page::try_new()?;
                                 new_page.copy_from_slice(page.bytes_array());
                                 page_table.map(vpn, new_page.ppn(), self.map_perm.into());
                                 self.pages.insert(vpn, new_page);
@@ -485,9 +824,9 @@ impl VmArea {
                     } else if self.mmap_flags.contains(MmapFlags::MAP_PRIVATE) {
                         if self.mmap_flags.contains(MmapFlags::MAP_SHARED) {
                             todo!()
-                        } else {
+                        } else if !self.fault_in_huge_page(page_table, vpn) {
                             // private anonymous area
-                            page = Page::new();
+                            page = Page::try_new()?;
                             page.fill_zero();
                             page_table.map(vpn, page.ppn(), self.map_perm.into());
                             self.pages.insert(vpn, page);
@@ -498,6 +837,6 @@ impl VmArea {
                 _ => {}
             }
         }
-        Ok(())
+        Ok(major)
     }
 }
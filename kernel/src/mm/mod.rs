@@ -6,37 +6,48 @@
 //!
 //! Every task or process has a memory_space to control its virtual memory.
 
+mod memory_map;
 pub mod memory_space;
+pub mod swap;
+pub mod tlb;
 mod user_ptr;
+pub mod vdso;
 
 use core::cmp;
 
+use arch::memory::sfence_vma_all;
 use config::{
-    board::MEMORY_END,
-    mm::{K_SEG_DTB_BEG, MAX_DTB_SIZE, VIRT_RAM_OFFSET},
+    board::MAX_HARTS,
+    mm::{round_up_to_page, K_SEG_DTB_BEG, MAX_DTB_SIZE, VIRT_RAM_OFFSET},
 };
 pub use memory::page_table::PageTable;
 use memory::{frame, heap, pte::PTEFlags, VirtAddr};
+use memory_map::BootMemoryMap;
 pub use memory_space::MemorySpace;
 pub use user_ptr::{
     FutexAddr, PageFaultAccessType, UserMut, UserRdWrPtr, UserReadPtr, UserSlice, UserWritePtr,
 };
 
 /// Initialize heap allocator, frame allocator and kernel page table.
+///
+/// The frame allocator takes the RAM after the kernel image that is found in
+/// the device tree and not reserved.
 pub fn init() {
     extern "C" {
         fn _ekernel();
     }
     heap::init_heap_allocator();
-    frame::init_frame_allocator(
-        VirtAddr::from(_ekernel as usize).to_paddr().ceil(),
-        VirtAddr::from(MEMORY_END).to_paddr().floor(),
-    );
+    let memory_map = BootMemoryMap::parse(config::mm::dtb_addr());
+    let kernel_end = round_up_to_page(_ekernel as usize - VIRT_RAM_OFFSET);
+    frame::init_frame_allocator(&memory_map.free_frames(kernel_end));
     unsafe {
-        init_kernel_page_table();
+        init_kernel_page_table(&memory_map, kernel_end);
         switch_kernel_page_table()
     };
+    memory::asid::init();
     log::info!("KERNEL SPACE activated");
+    vdso::init();
+    unsafe { sfence_vma_all() };
 }
 
 /// Kernel space for all processes.
@@ -44,7 +55,7 @@ pub fn init() {
 /// There is no need to lock `KERNEL_PAGE_TABLE` since it won't be changed.
 static mut KERNEL_PAGE_TABLE: Option<PageTable> = None;
 
-unsafe fn init_kernel_page_table() {
+unsafe fn init_kernel_page_table(memory_map: &BootMemoryMap, kernel_end: usize) {
     extern "C" {
         fn _stext();
         fn _strampoline();
@@ -58,7 +69,6 @@ unsafe fn init_kernel_page_table() {
         fn _estack();
         fn _sbss();
         fn _ebss();
-        fn _ekernel();
     }
 
     let mut kernel_page_table = PageTable::new();
@@ -94,11 +104,6 @@ unsafe fn init_kernel_page_table() {
         _sbss as usize,
         _ebss as usize
     );
-    log::info!(
-        "[kernel] physical mem [{:#x}, {:#x})",
-        _ekernel as usize,
-        MEMORY_END
-    );
     log::debug!("[kernel] mapping .text section");
     kernel_page_table.map_kernel_region(
         (_stext as usize).into()..(_strampoline as usize).into(),
@@ -124,8 +129,18 @@ unsafe fn init_kernel_page_table() {
         PTEFlags::R | PTEFlags::W,
     );
     log::debug!("[kernel] mapping .stack section");
+    // the guard pages of the kernel stacks are left unmapped
+    let mut stack_start = _sstack as usize;
+    for hart_id in 0..MAX_HARTS {
+        let guard = arch::entry::kernel_stack_guard(hart_id);
+        kernel_page_table.map_kernel_region(
+            stack_start.into()..guard.start.into(),
+            PTEFlags::R | PTEFlags::W,
+        );
+        stack_start = guard.end;
+    }
     kernel_page_table.map_kernel_region(
-        (_sstack as usize).into()..(_estack as usize).into(),
+        stack_start.into()..(_estack as usize).into(),
         PTEFlags::R | PTEFlags::W,
     );
     log::debug!("[kernel] mapping .bss section");
@@ -134,13 +149,21 @@ unsafe fn init_kernel_page_table() {
         PTEFlags::R | PTEFlags::W,
     );
     log::debug!("[kernel] mapping physical memory");
-    kernel_page_table.map_kernel_region(
-        (_ekernel as usize).into()..MEMORY_END.into(),
-        PTEFlags::R | PTEFlags::W,
-    );
+    for range in memory_map.ram_from(kernel_end) {
+        log::info!(
+            "[kernel] physical mem [{:#x}, {:#x})",
+            range.start + VIRT_RAM_OFFSET,
+            range.end + VIRT_RAM_OFFSET
+        );
+        kernel_page_table.map_kernel_region(
+            VirtAddr::from(range.start + VIRT_RAM_OFFSET)
+                ..VirtAddr::from(range.end + VIRT_RAM_OFFSET),
+            PTEFlags::R | PTEFlags::W,
+        );
+    }
 
     let dtb_addr = config::mm::dtb_addr();
-    let dtb_end = cmp::min(MEMORY_END - VIRT_RAM_OFFSET, dtb_addr + MAX_DTB_SIZE);
+    let dtb_end = dtb_addr + cmp::min(memory_map.dtb_size, MAX_DTB_SIZE);
     log::debug!("dtb address {dtb_addr:#x}, dtb end {dtb_end:#x}");
     kernel_page_table.map_kernel_region_offset(
         K_SEG_DTB_BEG.into()..(K_SEG_DTB_BEG + (dtb_end - dtb_addr)).into(),
@@ -153,6 +176,40 @@ unsafe fn init_kernel_page_table() {
     KERNEL_PAGE_TABLE = Some(kernel_page_table);
 }
 
+/// Check that the guard pages of the kernel stacks are left unmapped, while
+/// the stacks themselves are mapped, and that the frame allocator manages all
+/// the free RAM found in the device tree.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    extern "C" {
+        fn _ekernel();
+    }
+    let page_table = kernel_page_table();
+    for hart_id in 0..MAX_HARTS {
+        let guard = arch::entry::kernel_stack_guard(hart_id);
+        let stack = arch::entry::kernel_stack(hart_id);
+        assert!(page_table
+            .find_leaf_pte(VirtAddr::from(guard.start).floor())
+            .is_none());
+        assert!(page_table
+            .find_leaf_pte(VirtAddr::from(stack.start).floor())
+            .is_some());
+        assert!(page_table
+            .find_leaf_pte(VirtAddr::from(stack.end - 1).floor())
+            .is_some());
+    }
+
+    memory_map::selftest();
+    let memory_map = BootMemoryMap::parse(config::mm::dtb_addr());
+    let kernel_end = round_up_to_page(_ekernel as usize - VIRT_RAM_OFFSET);
+    let frames: usize = memory_map
+        .free_frames(kernel_end)
+        .iter()
+        .map(|range| range.end - range.start)
+        .sum();
+    assert_eq!(frame::total_frames(), frames);
+}
+
 pub fn kernel_page_table() -> &'static PageTable {
     unsafe { KERNEL_PAGE_TABLE.as_ref().unwrap() }
 }
@@ -165,5 +222,6 @@ pub fn kernel_page_table_mut() -> &'static mut PageTable {
 }
 
 pub unsafe fn switch_kernel_page_table() {
+    tlb::set_active_token(kernel_page_table().token());
     kernel_page_table().switch();
 }
@@ -0,0 +1,311 @@
+//! Swap-out of anonymous pages to a swap file.
+//!
+//! A regular file is activated as the swap space by swapon(2). Its first page
+//! is the header written by mkswap(8), and each of the other pages is a slot
+//! for a page swapped out. The slots are read and written directly rather
+//! than through the page cache, which would take the memory being freed, and
+//! blocks are allocated for the holes of the file once it is activated, so
+//! that the filesystem never allocates blocks while memory is short.
+//!
+//! When a page fault of user space runs out of memory, private anonymous pages
+//! that are neither locked nor shared are swapped out to retry it. Pages are
+//! picked by the accessed bits in their PTEs as an approximate LRU: a page
+//! accessed since the last scan only has the bit cleared, and is taken by the
+//! next scan if it is not accessed again meanwhile. A page swapped out is
+//! unmapped, and its area keeps a `SwapEntry` in place of the page, from
+//! which the page fault handler reads it back in.
+//!
+//! The data of a page can only be written to the swap file once no hart can
+//! write the page through stale TLB entries, i.e. after the memory space is
+//! unlocked, so the page is kept by its entry as the swap cache until then,
+//! and is copied from there if it is faulted in again meanwhile.
+
+use alloc::{collections::BTreeSet, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use async_utils::block_on;
+use config::mm::{round_down_to_page, PAGE_SIZE};
+use page::Page;
+use sync::mutex::SpinNoIrqLock;
+use systype::{SysError, SysResult};
+use vfs_core::File;
+
+use crate::task::{Task, TASK_MANAGER};
+
+/// Pages swapped out at a time when a page fault runs out of memory.
+pub const SWAP_CLUSTER: usize = 32;
+
+/// Signature at the end of the header page, as written by mkswap(8).
+const SWAP_MAGIC: &[u8] = b"SWAPSPACE2";
+
+/// Pages read from the swap file, reported by /proc/vmstat.
+pub static PSWPIN: AtomicUsize = AtomicUsize::new(0);
+/// Pages written to the swap file, reported by /proc/vmstat.
+pub static PSWPOUT: AtomicUsize = AtomicUsize::new(0);
+
+/// The swap file activated, if any.
+static SWAP: SpinNoIrqLock<Option<Arc<SwapFile>>> = SpinNoIrqLock::new(None);
+
+struct Slots {
+    /// Whether each slot is in use, where slot 0 is the header.
+    used: Vec<bool>,
+    /// Number of slots in use.
+    nr_used: usize,
+    /// Slot where the search for a free slot starts.
+    next: usize,
+}
+
+pub struct SwapFile {
+    file: Arc<dyn File>,
+    slots: SpinNoIrqLock<Slots>,
+}
+
+impl SwapFile {
+    fn new(file: Arc<dyn File>, pages: usize) -> Self {
+        Self {
+            file,
+            slots: SpinNoIrqLock::new(Slots {
+                used: vec![false; pages],
+                nr_used: 0,
+                next: 1,
+            }),
+        }
+    }
+
+    /// Number of slots for pages, excluding the header.
+    pub fn pages(&self) -> usize {
+        self.slots.lock().used.len() - 1
+    }
+
+    /// Number of slots in use.
+    pub fn used_pages(&self) -> usize {
+        self.slots.lock().nr_used
+    }
+
+    fn alloc_slot(&self) -> Option<usize> {
+        let mut slots = self.slots.lock();
+        let next = slots.next;
+        let slot = (next..slots.used.len())
+            .chain(1..next)
+            .find(|&slot| !slots.used[slot])?;
+        slots.used[slot] = true;
+        slots.nr_used += 1;
+        slots.next = if slot + 1 == slots.used.len() {
+            1
+        } else {
+            slot + 1
+        };
+        Some(slot)
+    }
+
+    fn free_slot(&self, slot: usize) {
+        let mut slots = self.slots.lock();
+        debug_assert!(slots.used[slot]);
+        slots.used[slot] = false;
+        slots.nr_used -= 1;
+    }
+
+    fn write_page(&self, slot: usize, page: &Page) -> SysResult<()> {
+        let len = block_on(
+            self.file
+                .base_write_at(slot * PAGE_SIZE, page.bytes_array()),
+        )?;
+        if len != PAGE_SIZE {
+            return Err(SysError::EIO);
+        }
+        Ok(())
+    }
+
+    fn read_page(&self, slot: usize, page: &Page) -> SysResult<()> {
+        let len = block_on(self.file.base_read_at(slot * PAGE_SIZE, page.bytes_array()))?;
+        if len != PAGE_SIZE {
+            return Err(SysError::EIO);
+        }
+        Ok(())
+    }
+}
+
+/// A page swapped out to a slot of the swap file, which is freed when the
+/// entry is dropped.
+///
+/// An entry is shared by the areas of the processes forked after the page is
+/// swapped out, each of which reads the page into a page of its own.
+pub struct SwapEntry {
+    swap: Arc<SwapFile>,
+    slot: usize,
+    /// The page swapped out, kept until its data is written to the slot.
+    cached: SpinNoIrqLock<Option<Arc<Page>>>,
+}
+
+impl SwapEntry {
+    /// Read the page back into a new page. Return the page, and whether it is
+    /// read from the swap file rather than copied from the swap cache.
+    pub fn swap_in(&self) -> SysResult<(Arc<Page>, bool)> {
+        let page = Page::try_new()?;
+        if let Some(cached) = self.cached.lock().as_ref() {
+            page.copy_from_slice(cached.bytes_array());
+            return Ok((page, false));
+        }
+        self.swap.read_page(self.slot, &page)?;
+        PSWPIN.fetch_add(1, Ordering::Relaxed);
+        Ok((page, true))
+    }
+
+    /// Write the cached page to the slot, and drop it from the swap cache.
+    ///
+    /// The page is kept in memory if it can not be written.
+    fn write_out(&self) {
+        let Some(page) = self.cached.lock().clone() else {
+            return;
+        };
+        match self.swap.write_page(self.slot, &page) {
+            Ok(()) => {
+                PSWPOUT.fetch_add(1, Ordering::Relaxed);
+                *self.cached.lock() = None;
+            }
+            Err(e) => log::error!("[swap] failed to write slot {}: {e:?}", self.slot),
+        }
+    }
+}
+
+impl Drop for SwapEntry {
+    fn drop(&mut self) {
+        self.swap.free_slot(self.slot);
+    }
+}
+
+/// Take `page` to be swapped out, or return `None` if the swap space is full.
+pub fn new_entry(swap: &Arc<SwapFile>, page: Arc<Page>) -> Option<Arc<SwapEntry>> {
+    let slot = swap.alloc_slot()?;
+    Some(Arc::new(SwapEntry {
+        swap: swap.clone(),
+        slot,
+        cached: SpinNoIrqLock::new(Some(page)),
+    }))
+}
+
+/// Tasks of which the memory spaces are all different, one for each process.
+fn memory_space_owners() -> Vec<Arc<Task>> {
+    let mut visited = BTreeSet::new();
+    TASK_MANAGER
+        .tasks()
+        .into_iter()
+        .filter(|task| visited.insert(task.raw_mm_pointer()))
+        .collect()
+}
+
+/// Activate `file` as the swap space, which must be a regular file of at least
+/// two pages, and begin with the header written by mkswap(8).
+pub async fn swapon(file: Arc<dyn File>) -> SysResult<()> {
+    if !file.itype().is_file() {
+        return Err(SysError::EINVAL);
+    }
+    if SWAP.lock().is_some() {
+        return Err(SysError::EBUSY);
+    }
+    let size = round_down_to_page(file.size());
+    if size < 2 * PAGE_SIZE {
+        return Err(SysError::EINVAL);
+    }
+    // pages written through the page cache, e.g. the header, are written back
+    // and dropped, since the file is accessed directly from now on
+    file.writeback().await?;
+    if let Some(page_cache) = file.inode().page_cache() {
+        page_cache.truncate(0);
+    }
+    let mut header = vec![0; PAGE_SIZE];
+    file.base_read_at(0, &mut header).await?;
+    if !header.ends_with(SWAP_MAGIC) {
+        log::warn!("[swapon] {} has no swap signature", file.dentry().path());
+        return Err(SysError::EINVAL);
+    }
+    let zero = vec![0; PAGE_SIZE];
+    for offset in (PAGE_SIZE..size).step_by(PAGE_SIZE) {
+        if file.base_is_hole(offset).await? {
+            file.base_write_at(offset, &zero).await?;
+        }
+    }
+
+    let mut swap = SWAP.lock();
+    if swap.is_some() {
+        return Err(SysError::EBUSY);
+    }
+    log::info!(
+        "[swapon] {} activated with {} pages",
+        file.dentry().path(),
+        size / PAGE_SIZE - 1
+    );
+    *swap = Some(Arc::new(SwapFile::new(file, size / PAGE_SIZE)));
+    Ok(())
+}
+
+/// Deactivate the swap space if it is `file`, after reading all pages swapped
+/// out back into memory.
+///
+/// Fail with `ENOMEM` if there is no memory for them, in which case the swap
+/// space stays active.
+pub fn swapoff(file: &Arc<dyn File>) -> SysResult<()> {
+    let inode = Arc::as_ptr(&file.inode()) as *const ();
+    let swap = {
+        let mut swap = SWAP.lock();
+        match swap.as_ref() {
+            Some(s) if Arc::as_ptr(&s.file.inode()) as *const () == inode => swap.take().unwrap(),
+            _ => return Err(SysError::EINVAL),
+        }
+    };
+    // no page is swapped out once the swap space is taken, except by those
+    // who have taken it before
+    let ret = memory_space_owners()
+        .iter()
+        .try_for_each(|task| task.with_mut_memory_space(|m| m.swap_in_all()));
+    if let Err(e) = ret {
+        *SWAP.lock() = Some(swap);
+        return Err(e);
+    }
+    if swap.used_pages() != 0 {
+        *SWAP.lock() = Some(swap);
+        return Err(SysError::EBUSY);
+    }
+    log::info!("[swapoff] {} deactivated", file.dentry().path());
+    Ok(())
+}
+
+/// Swap out at most `nr` pages of user space, and return the number of pages
+/// swapped out.
+pub fn swap_out(nr: usize) -> usize {
+    let Some(swap) = SWAP.lock().clone() else {
+        return 0;
+    };
+    let tasks = memory_space_owners();
+    let mut entries = Vec::new();
+    // the first scan may only clear the accessed bits of pages in use
+    for _ in 0..2 {
+        for task in tasks.iter() {
+            if entries.len() >= nr {
+                break;
+            }
+            let want = nr - entries.len();
+            task.with_mut_memory_space(|m| m.swap_out(&swap, want, &mut entries));
+        }
+        if entries.len() >= nr {
+            break;
+        }
+    }
+    // the TLB shootdowns are waited for after each memory space is unlocked
+    for entry in entries.iter() {
+        entry.write_out();
+    }
+    log::info!("[swap_out] {} pages swapped out", entries.len());
+    entries.len()
+}
+
+/// Pages of the swap space in total and free.
+pub fn swap_stat() -> (usize, usize) {
+    match SWAP.lock().as_ref() {
+        Some(swap) => {
+            let total = swap.pages();
+            (total, total - swap.used_pages())
+        }
+        None => (0, 0),
+    }
+}
@@ -0,0 +1,174 @@
+//! TLB shootdown across harts.
+//!
+//! `sfence.vma` only flushes the TLB of the local hart, so when an entry of a
+//! user page table is revoked, downgraded or remapped, the other harts running
+//! on the same page table are asked by IPIs to flush it as well, and the
+//! requester waits until all of them have done so. Harts that have switched
+//! away from the page table may still cache its translations tagged with its
+//! ASID, which are flushed lazily when they switch back to it.
+//!
+//! The requests are posted with the memory space locked, but the memory space
+//! lock disables interrupts, so a target spinning on it could never take the
+//! IPI. Hence the requester waits by `wait_for_shootdown` only after the lock
+//! is released, and pages unmapped meanwhile are kept alive until then, since
+//! they may still be written through stale entries.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    hint::spin_loop,
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use arch::memory::{sfence_vma, sfence_vma_all, sfence_vma_asid, sfence_vma_vaddr};
+use config::{board::MAX_HARTS, mm::PAGE_SIZE};
+use memory::{asid, PageTable, VirtAddr};
+use page::Page;
+use sync::mutex::SpinNoIrqLock;
+
+use crate::processor::hart::local_hart;
+
+/// Ranges of more pages are flushed as a whole, which is cheaper than
+/// flushing them one by one.
+const FLUSH_ALL_PAGES: usize = 64;
+
+/// Requests for one hart to flush its TLB.
+struct Mailbox {
+    /// The hull of the ranges requested since the last flush.
+    range: SpinNoIrqLock<Option<Range<VirtAddr>>>,
+    /// Number of requests posted, which is increased along with `range`.
+    posted: AtomicUsize,
+    /// Number of requests served.
+    served: AtomicUsize,
+}
+
+impl Mailbox {
+    const fn new() -> Self {
+        Self {
+            range: SpinNoIrqLock::new(None),
+            posted: AtomicUsize::new(0),
+            served: AtomicUsize::new(0),
+        }
+    }
+}
+
+const MAILBOX_EACH: Mailbox = Mailbox::new();
+static MAILBOXES: [Mailbox; MAX_HARTS] = [MAILBOX_EACH; MAX_HARTS];
+
+const ACTIVE_TOKEN_EACH: AtomicUsize = AtomicUsize::new(0);
+/// Satp token of the page table each hart has switched to.
+static ACTIVE_TOKENS: [AtomicUsize; MAX_HARTS] = [ACTIVE_TOKEN_EACH; MAX_HARTS];
+
+/// Shootdowns issued by a hart and not waited for yet.
+pub struct PendingShootdown {
+    /// Number of the request posted to each hart that must be served.
+    tickets: [usize; MAX_HARTS],
+    /// Pages unmapped, which are freed once the shootdowns are done.
+    pages: Vec<Arc<Page>>,
+}
+
+impl PendingShootdown {
+    pub const fn new() -> Self {
+        Self {
+            tickets: [0; MAX_HARTS],
+            pages: Vec::new(),
+        }
+    }
+}
+
+/// The page table the local hart has switched to.
+pub fn active_token() -> usize {
+    ACTIVE_TOKENS[local_hart().hart_id()].load(Ordering::SeqCst)
+}
+
+/// Record that the local hart is switching to the page table of `token`.
+///
+/// It must be called before writing satp, and with the memory space locked if
+/// it is shared, so that a shootdown posted after it never misses this hart.
+pub fn set_active_token(token: usize) {
+    ACTIVE_TOKENS[local_hart().hart_id()].store(token, Ordering::SeqCst);
+}
+
+/// Flush `range` from the TLB of the local hart, only the translations tagged
+/// with `asid` if given.
+fn flush_local(range: &Range<VirtAddr>, asid: Option<usize>) {
+    let pages = (range.end.bits() - range.start.bits()).div_ceil(PAGE_SIZE);
+    let vaddrs = (range.start.bits()..range.end.bits()).step_by(PAGE_SIZE);
+    match asid {
+        Some(asid) if pages > FLUSH_ALL_PAGES => unsafe { sfence_vma_asid(asid) },
+        Some(asid) => vaddrs.for_each(|vaddr| unsafe { sfence_vma(vaddr, asid) }),
+        None if pages > FLUSH_ALL_PAGES => unsafe { sfence_vma_all() },
+        None => vaddrs.for_each(|vaddr| unsafe { sfence_vma_vaddr(vaddr) }),
+    }
+}
+
+/// Flush `range` of `page_table` from the TLB of every hart that may cache it.
+/// Harts running on it are asked by IPIs, which must be waited for by
+/// `wait_for_shootdown`, while the others flush it when switching back.
+pub fn shootdown(page_table: &PageTable, range: Range<VirtAddr>) {
+    let token = page_table.token();
+    let hart = local_hart();
+    if active_token() == token {
+        flush_local(&range, Some(asid::local_asid()));
+    }
+    let harts = page_table.asid().harts();
+    for (hart_id, active) in ACTIVE_TOKENS.iter().enumerate() {
+        if harts & (1 << hart_id) == 0 {
+            continue;
+        }
+        if active.load(Ordering::SeqCst) != token {
+            page_table.asid().mark_stale(hart_id);
+            continue;
+        }
+        if hart_id == hart.hart_id() {
+            continue;
+        }
+        let mailbox = &MAILBOXES[hart_id];
+        let ticket = {
+            let mut pending = mailbox.range.lock();
+            *pending = Some(match pending.take() {
+                Some(r) => r.start.min(range.start)..r.end.max(range.end),
+                None => range.clone(),
+            });
+            mailbox.posted.fetch_add(1, Ordering::SeqCst) + 1
+        };
+        hart.shootdown_mut().tickets[hart_id] = ticket;
+        arch::interrupts::send_ipi(hart_id);
+    }
+}
+
+/// Keep `pages` alive until the shootdowns issued by the local hart are done.
+pub fn free_after_shootdown(pages: impl IntoIterator<Item = Arc<Page>>) {
+    local_hart().shootdown_mut().pages.extend(pages);
+}
+
+/// Serve the requests posted to the local hart, on receiving an IPI or while
+/// waiting for other harts.
+pub fn handle_shootdown() {
+    let mailbox = &MAILBOXES[local_hart().hart_id()];
+    let (range, posted) = {
+        let mut pending = mailbox.range.lock();
+        (pending.take(), mailbox.posted.load(Ordering::SeqCst))
+    };
+    if let Some(range) = range {
+        // the page table may have been switched away from, so translations
+        // tagged with any ASID are flushed
+        flush_local(&range, None);
+        // an IPI taken right after the lock is released may have served more
+        mailbox.served.fetch_max(posted, Ordering::SeqCst);
+    }
+}
+
+/// Wait until the shootdowns issued by the local hart are done, which must not
+/// be called with the memory space locked.
+pub fn wait_for_shootdown() {
+    let pending = core::mem::replace(local_hart().shootdown_mut(), PendingShootdown::new());
+    for (hart_id, &ticket) in pending.tickets.iter().enumerate() {
+        while MAILBOXES[hart_id].served.load(Ordering::SeqCst) < ticket {
+            // the target may be waiting for this hart as well
+            handle_shootdown();
+            spin_loop();
+        }
+    }
+    drop(pending.pages);
+}
@@ -11,8 +11,10 @@ use core::{
     marker::PhantomData,
     mem,
     ops::{self, ControlFlow},
+    sync::atomic::AtomicU32,
 };
 
+use config::{fs::PATH_MAX, mm::U_SEG_END};
 use memory::VirtAddr;
 use net::{IpAddress, IpEndpoint, IpListenEndpoint};
 use riscv::register::scause;
@@ -272,10 +274,16 @@ impl<T: Clone + Copy + 'static, P: Read> UserPtr<T, P> {
     }
 
     pub fn into_slice(self, task: &Arc<Task>, n: usize) -> SysResult<UserSlice<T>> {
-        debug_assert!(self.not_null());
+        if self.is_null() {
+            return if n == 0 {
+                Ok(UserSlice::new(&mut []))
+            } else {
+                Err(SysError::EFAULT)
+            };
+        }
         task.just_ensure_user_area(
             VirtAddr::from(self.as_usize()),
-            size_of::<T>() * n,
+            array_size::<T>(n)?,
             PageFaultAccessType::RO,
         )?;
         let slice = unsafe { core::slice::from_raw_parts_mut(self.ptr, n) };
@@ -297,10 +305,15 @@ impl<T: Clone + Copy + 'static, P: Read> UserPtr<T, P> {
     }
 
     pub fn read_array(self, task: &Arc<Task>, n: usize) -> SysResult<Vec<T>> {
-        debug_assert!(self.not_null());
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        if self.is_null() {
+            return Err(SysError::EFAULT);
+        }
         task.just_ensure_user_area(
             VirtAddr::from(self.as_usize()),
-            size_of::<T>() * n,
+            array_size::<T>(n)?,
             PageFaultAccessType::RO,
         )?;
         let mut res = Vec::with_capacity(n);
@@ -314,19 +327,25 @@ impl<T: Clone + Copy + 'static, P: Read> UserPtr<T, P> {
     }
 
     /// Read a pointer vector (a.k.a 2d array) that ends with null, e.g. argv,
-    /// envp.
-    pub fn read_cvec(self, task: &Arc<Task>) -> SysResult<Vec<usize>> {
-        debug_assert!(self.not_null());
+    /// envp, which has at most `max` pointers besides the null, or `E2BIG` is
+    /// returned.
+    pub fn read_cvec(self, task: &Arc<Task>, max: usize) -> SysResult<Vec<usize>> {
+        // a misaligned pointer may cross a page boundary
+        if self.is_null() || self.as_usize() % size_of::<usize>() != 0 {
+            return Err(SysError::EFAULT);
+        }
         let mut vec = Vec::with_capacity(32);
+        let mut terminated = false;
         task.ensure_user_area(
             VirtAddr::from(self.as_usize()),
-            usize::MAX,
+            array_size::<usize>(max.saturating_add(1))?,
             PageFaultAccessType::RO,
             |beg, len| unsafe {
                 let mut ptr = beg.0 as *const usize;
-                for _ in 0..len {
+                for _ in 0..len / size_of::<usize>() {
                     let c = ptr.read();
                     if c == 0 {
+                        terminated = true;
                         return ControlFlow::Break(None);
                     }
                     vec.push(c);
@@ -335,41 +354,69 @@ impl<T: Clone + Copy + 'static, P: Read> UserPtr<T, P> {
                 ControlFlow::Continue(())
             },
         )?;
-        Ok(vec)
+        if terminated {
+            Ok(vec)
+        } else {
+            Err(SysError::E2BIG)
+        }
     }
 }
 
 impl<P: Read> UserPtr<u8, P> {
-    // TODO: set length limit to cstr
-    pub fn read_cstr(self, task: &Arc<Task>) -> SysResult<String> {
-        debug_assert!(self.not_null());
-        let mut str = String::with_capacity(32);
+    /// Read a string that ends with null, which takes at most `max` bytes
+    /// including the null, or `ENAMETOOLONG` is returned.
+    pub fn read_cstr_with_max(self, task: &Arc<Task>, max: usize) -> SysResult<String> {
+        let mut bytes = Vec::with_capacity(32);
+        self.read_cstr_into(task, &mut bytes, max)?;
+        bytes.pop();
+        Ok(bytes.into_iter().map(char::from).collect())
+    }
+
+    /// Like `read_cstr_with_max`, but append the bytes of the string to `buf`
+    /// including the null, which is left with part of the string on error.
+    pub fn read_cstr_into(self, task: &Arc<Task>, buf: &mut Vec<u8>, max: usize) -> SysResult<()> {
+        if self.is_null() {
+            return Err(SysError::EFAULT);
+        }
+        let mut terminated = false;
 
         task.ensure_user_area(
             VirtAddr::from(self.as_usize()),
-            usize::MAX,
+            max,
             PageFaultAccessType::RO,
             |beg, len| unsafe {
                 let mut ptr = beg.as_mut_ptr();
                 for _ in 0..len {
                     let c = ptr.read();
+                    buf.push(c);
                     if c == 0 {
+                        terminated = true;
                         return ControlFlow::Break(None);
                     }
-                    str.push(c as char);
                     ptr = ptr.offset(1);
                 }
                 ControlFlow::Continue(())
             },
         )?;
-        Ok(str)
+        if terminated {
+            Ok(())
+        } else {
+            Err(SysError::ENAMETOOLONG)
+        }
+    }
+
+    /// Read a path, which is limited to `PATH_MAX` bytes.
+    pub fn read_path(self, task: &Arc<Task>) -> SysResult<String> {
+        self.read_cstr_with_max(task, PATH_MAX)
     }
 }
 
 // TODO: should ref hold SumGuard?
 impl<T: Clone + Copy + 'static, P: Write> UserPtr<T, P> {
     pub fn into_mut(self, task: &Arc<Task>) -> SysResult<UserMut<T>> {
-        debug_assert!(self.not_null());
+        if self.is_null() {
+            return Err(SysError::EFAULT);
+        }
         task.just_ensure_user_area(
             VirtAddr::from(self.as_usize()),
             size_of::<T>(),
@@ -380,10 +427,16 @@ impl<T: Clone + Copy + 'static, P: Write> UserPtr<T, P> {
     }
 
     pub fn into_mut_slice(self, task: &Arc<Task>, n: usize) -> SysResult<UserSlice<T>> {
-        debug_assert!(self.not_null());
+        if self.is_null() {
+            return if n == 0 {
+                Ok(UserSlice::new(&mut []))
+            } else {
+                Err(SysError::EFAULT)
+            };
+        }
         task.just_ensure_user_area(
             VirtAddr::from(self.as_usize()),
-            size_of::<T>() * n,
+            array_size::<T>(n)?,
             PageFaultAccessType::RW,
         )?;
         // WARN: `core::slice::from_raw_parts_mut` does not accept null pointer even for
@@ -393,20 +446,26 @@ impl<T: Clone + Copy + 'static, P: Write> UserPtr<T, P> {
     }
 
     pub fn write(self, task: &Arc<Task>, val: T) -> SysResult<()> {
-        debug_assert!(self.not_null());
+        if self.is_null() {
+            return Err(SysError::EFAULT);
+        }
         if !Arc::ptr_eq(task, current_task_ref()) {
             unsafe { task.switch_page_table() };
         }
-        task.just_ensure_user_area(
+        let ret = task.just_ensure_user_area(
             VirtAddr::from(self.as_usize()),
             size_of::<T>(),
             PageFaultAccessType::RW,
-        )?;
-        unsafe { core::ptr::write(self.ptr, val) };
+        );
+        if ret.is_ok() {
+            unsafe { core::ptr::write(self.ptr, val) };
+        }
+        // NOTE: the page table of the current task must be switched back even if
+        // the address is invalid
         if !Arc::ptr_eq(task, current_task_ref()) {
             unsafe { current_task_ref().switch_page_table() };
         }
-        Ok(())
+        ret
     }
 
     pub fn write_unchecked(self, _task: &Arc<Task>, val: T) -> SysResult<()> {
@@ -416,10 +475,15 @@ impl<T: Clone + Copy + 'static, P: Write> UserPtr<T, P> {
     }
 
     pub fn write_array(self, task: &Arc<Task>, val: &[T]) -> SysResult<()> {
-        debug_assert!(self.not_null());
+        if val.is_empty() {
+            return Ok(());
+        }
+        if self.is_null() {
+            return Err(SysError::EFAULT);
+        }
         task.just_ensure_user_area(
             VirtAddr::from(self.as_usize()),
-            size_of::<T>() * val.len(),
+            array_size::<T>(val.len())?,
             PageFaultAccessType::RW,
         )?;
         unsafe {
@@ -435,7 +499,9 @@ impl<T: Clone + Copy + 'static, P: Write> UserPtr<T, P> {
 
 impl<P: Write> UserPtr<u8, P> {
     pub fn write_cstr(self, task: &Arc<Task>, val: &str) -> SysResult<()> {
-        debug_assert!(self.not_null());
+        if self.is_null() {
+            return Err(SysError::EFAULT);
+        }
 
         let mut str = val.as_bytes();
         let mut has_filled_zero = false;
@@ -489,6 +555,12 @@ impl<T: Clone + Copy + 'static, P: Policy> From<usize> for UserPtr<T, P> {
     }
 }
 
+/// Size in bytes of `n` elements of `T`, where an overflowing size can not be
+/// a valid user area either.
+fn array_size<T>(n: usize) -> SysResult<usize> {
+    size_of::<T>().checked_mul(n).ok_or(SysError::EFAULT)
+}
+
 impl Task {
     fn just_ensure_user_area(
         &self,
@@ -500,6 +572,11 @@ impl Task {
     }
 
     /// Ensure that the whole range is accessible, or return an error.
+    ///
+    /// The range must lie in user space, since kernel memory is accessible as
+    /// well while SUM is set. Pages are probed one by one before `f` is called
+    /// on them, and lazily allocated pages are faulted in, so that `f` never
+    /// touches a page which is not mapped.
     fn ensure_user_area(
         &self,
         begin: VirtAddr,
@@ -510,6 +587,10 @@ impl Task {
         if len == 0 {
             return Ok(());
         }
+        if unlikely(begin.bits() >= U_SEG_END || len > U_SEG_END - begin.bits()) {
+            log::warn!("[ensure_user_area] {begin:?} with len {len:#x} is out of user space");
+            return Err(SysError::EFAULT);
+        }
 
         unsafe { set_kernel_user_rw_trap() };
 
@@ -523,11 +604,15 @@ impl Task {
         let mut readable_len = 0;
         while readable_len < len {
             if test_fn(curr_vaddr.0) {
-                self.with_mut_memory_space(|m| m.handle_page_fault(curr_vaddr, access))?
+                let ret = self.handle_page_fault(curr_vaddr, access);
+                if let Err(e) = ret {
+                    unsafe { set_kernel_trap() };
+                    return Err(e);
+                }
             }
 
             let next_page_beg: VirtAddr = VirtAddr::from(curr_vaddr.floor().next());
-            let len = next_page_beg - curr_vaddr;
+            let len = (next_page_beg - curr_vaddr).min(len - readable_len);
 
             match f(curr_vaddr, len) {
                 ControlFlow::Continue(_) => {}
@@ -596,9 +681,17 @@ impl FutexAddr {
     pub fn check(&self, task: &Arc<Task>) -> SysResult<()> {
         task.just_ensure_user_area(self.addr, size_of::<VirtAddr>(), PageFaultAccessType::RO)
     }
+    /// Like `check`, but also makes sure the futex word can be written by the
+    /// kernel, e.g. copy-on-write pages are copied.
+    pub fn check_write(&self, task: &Arc<Task>) -> SysResult<()> {
+        task.just_ensure_user_area(self.addr, size_of::<u32>(), PageFaultAccessType::RW)
+    }
     pub fn read(&self) -> u32 {
         unsafe { atomic_load_acquire(self.addr.0 as *const u32) }
     }
+    pub fn word(&self) -> &AtomicU32 {
+        unsafe { &*(self.addr.0 as *const AtomicU32) }
+    }
 }
 
 impl From<usize> for FutexAddr {
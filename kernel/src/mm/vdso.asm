@@ -0,0 +1,98 @@
+    # Code of the vDSO, which is copied into the vdso image by `vdso::init`,
+    # so it must be position independent and refer to nothing else in kernel.
+    #
+    # Layout of `VdsoData` in the vvar page:
+    #   0:  seq
+    #   8:  clock_freq
+    #   16: realtime_sec
+    #   24: realtime_nsec
+    .equ VVAR, -0x10000000 # K_SEG_VDSO_BEG, i.e. 0xffff_ffff_f000_0000
+    .equ NSEC_PER_SEC, 1000000000
+    .equ USEC_PER_SEC, 1000000
+    .equ SYSCALL_CLOCK_GETTIME, 113
+
+    # Read the time since boot into \sec and \nsec, in microseconds as
+    # `get_time_duration` does, and the offset of CLOCK_REALTIME into \off_sec
+    # and \off_nsec. Retry while the kernel is updating the data.
+    #
+    # Clobber t0-t4.
+    .macro read_time sec, nsec, off_sec, off_nsec
+    li      t0, VVAR
+9:
+    lw      t1, 0(t0)
+    andi    t2, t1, 1
+    bnez    t2, 9b
+    fence   r, r
+    ld      t2, 8(t0)
+    ld      \off_sec, 16(t0)
+    ld      \off_nsec, 24(t0)
+    rdtime  t3
+    fence   r, r
+    lw      t4, 0(t0)
+    bne     t1, t4, 9b
+    li      t4, USEC_PER_SEC
+    divu    t2, t2, t4
+    divu    t3, t3, t2
+    divu    \sec, t3, t4
+    remu    \nsec, t3, t4
+    li      t4, 1000
+    mul     \nsec, \nsec, t4
+    .endm
+
+    # Add the offset to the time, carrying the nanoseconds over.
+    #
+    # Clobber t0.
+    .macro add_time sec, nsec, off_sec, off_nsec
+    add     \sec, \sec, \off_sec
+    add     \nsec, \nsec, \off_nsec
+    li      t0, NSEC_PER_SEC
+    bltu    \nsec, t0, 9f
+    sub     \nsec, \nsec, t0
+    addi    \sec, \sec, 1
+9:
+    .endm
+
+    .section .text.vdso
+    .global _svdso
+    .global _evdso
+    .global __vdso_clock_gettime
+    .global __vdso_gettimeofday
+_svdso:
+
+    # int __vdso_clock_gettime(clockid_t clockid, struct timespec *tp)
+    #
+    # Only CLOCK_REALTIME(0) and CLOCK_MONOTONIC(1) are served here, other
+    # clocks fall back to the syscall.
+__vdso_clock_gettime:
+    li      t0, 1
+    bgtu    a0, t0, 3f
+    beqz    a1, 2f
+    mv      t5, a0
+    read_time a2, a3, a4, a5
+    bnez    t5, 1f
+    add_time a2, a3, a4, a5
+1:
+    sd      a2, 0(a1)
+    sd      a3, 8(a1)
+2:
+    li      a0, 0
+    ret
+3:
+    li      a7, SYSCALL_CLOCK_GETTIME
+    ecall
+    ret
+
+    # int __vdso_gettimeofday(struct timeval *tv, struct timezone *tz)
+__vdso_gettimeofday:
+    beqz    a0, 1f
+    read_time a2, a3, a4, a5
+    add_time a2, a3, a4, a5
+    li      t0, 1000
+    divu    a3, a3, t0
+    sd      a2, 0(a0)
+    sd      a3, 8(a0)
+1:
+    li      a0, 0
+    ret
+
+_evdso:
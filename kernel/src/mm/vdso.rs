@@ -0,0 +1,310 @@
+//! vDSO serving clock_gettime(2) and gettimeofday(2) in user space.
+//!
+//! Like the signal-return trampoline, the vDSO is mapped with U flag in the
+//! kernel page table, which is shared by every user memory space. The vvar
+//! page holding `VdsoData` is followed by the vdso image, a minimal elf shared
+//! object built at boot around the code in `vdso.asm`, whose address is passed
+//! to user by AT_SYSINFO_EHDR.
+
+use alloc::sync::Arc;
+use core::{
+    arch::global_asm,
+    mem::size_of,
+    sync::atomic::{fence, AtomicU32, AtomicU64, Ordering},
+};
+
+use config::{
+    board::clock_freq,
+    mm::{K_SEG_VDSO_BEG, PAGE_SIZE},
+};
+use memory::{pte::PTEFlags, VirtAddr};
+use page::Page;
+use spin::Once;
+use sync::mutex::SpinNoIrqLock;
+use time::{clock_deviation, CLOCK_REALTIME};
+
+use super::kernel_page_table_mut;
+
+global_asm!(include_str!("vdso.asm"));
+
+/// Address of the elf header of the vdso image, following the vvar page.
+pub const VDSO_EHDR: usize = K_SEG_VDSO_BEG + PAGE_SIZE;
+
+/// Data read by the vDSO, which is protected by a seqlock. `seq` is odd while
+/// the kernel is updating the data, and the layout is known by `vdso.asm`.
+#[repr(C)]
+struct VdsoData {
+    seq: AtomicU32,
+    clock_freq: AtomicU64,
+    realtime_sec: AtomicU64,
+    realtime_nsec: AtomicU64,
+}
+
+static VVAR: Once<Arc<Page>> = Once::new();
+static VDSO: Once<Arc<Page>> = Once::new();
+
+/// Serialize the writers of `VdsoData`.
+static UPDATE_LOCK: SpinNoIrqLock<()> = SpinNoIrqLock::new(());
+
+fn vdso_data() -> &'static VdsoData {
+    let vvar = VVAR.get().unwrap();
+    unsafe { &*(vvar.bytes_array().as_ptr() as *const VdsoData) }
+}
+
+/// Publish the clock frequency and the offset of CLOCK_REALTIME, which should
+/// be called whenever either of them changes.
+pub fn update() {
+    let _guard = UPDATE_LOCK.lock();
+    let data = vdso_data();
+    let realtime = clock_deviation(CLOCK_REALTIME);
+    let seq = data.seq.load(Ordering::Relaxed);
+    data.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+    fence(Ordering::Release);
+    data.clock_freq
+        .store(clock_freq() as u64, Ordering::Relaxed);
+    data.realtime_sec
+        .store(realtime.as_secs(), Ordering::Relaxed);
+    data.realtime_nsec
+        .store(realtime.subsec_nanos() as u64, Ordering::Relaxed);
+    fence(Ordering::Release);
+    data.seq.store(seq.wrapping_add(2), Ordering::Relaxed);
+}
+
+/// Build the vdso image and map it together with the vvar page in the kernel
+/// page table, which should be done before any user memory space is created.
+pub fn init() {
+    let vvar = VVAR.call_once(|| {
+        let page = Page::new();
+        page.fill_zero();
+        page
+    });
+    let vdso = VDSO.call_once(|| {
+        let page = Page::new();
+        page.fill_zero();
+        build_image(page.bytes_array());
+        page
+    });
+    update();
+
+    let kernel_page_table = kernel_page_table_mut();
+    kernel_page_table.map(
+        VirtAddr::from(K_SEG_VDSO_BEG).floor(),
+        vvar.ppn(),
+        PTEFlags::U | PTEFlags::R,
+    );
+    kernel_page_table.map(
+        VirtAddr::from(VDSO_EHDR).floor(),
+        vdso.ppn(),
+        PTEFlags::U | PTEFlags::R | PTEFlags::X,
+    );
+    log::info!("[vdso] vvar at {K_SEG_VDSO_BEG:#x}, vdso at {VDSO_EHDR:#x}");
+}
+
+const ET_DYN: u16 = 3;
+const EM_RISCV: u16 = 243;
+/// RVC and double float ABI, the same as the user programs.
+const EF_RISCV: u32 = 0x5;
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PF_R: u32 = 4;
+const PF_X: u32 = 1;
+const STB_GLOBAL_STT_FUNC: u8 = 0x12;
+/// Section index of the symbols. There are no section headers in the image,
+/// but symbols with `SHN_UNDEF` are skipped by libc.
+const SHN_TEXT: u16 = 1;
+const DT_NULL: u64 = 0;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_STRSZ: u64 = 10;
+const DT_SYMENT: u64 = 11;
+const DT_SONAME: u64 = 14;
+
+#[repr(C)]
+struct Elf64Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[repr(C)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+#[repr(C)]
+struct Elf64Sym {
+    st_name: u32,
+    st_info: u8,
+    st_other: u8,
+    st_shndx: u16,
+    st_value: u64,
+    st_size: u64,
+}
+
+#[repr(C)]
+struct Elf64Dyn {
+    d_tag: u64,
+    d_val: u64,
+}
+
+/// Write `val` at `offset` of `image`, returning the offset following it.
+fn put<T>(image: &mut [u8], offset: usize, val: T) -> usize {
+    let end = offset + size_of::<T>();
+    assert!(end <= image.len(), "vdso image overflows");
+    unsafe { core::ptr::write_unaligned(image[offset..].as_mut_ptr() as *mut T, val) };
+    end
+}
+
+/// Lay out the elf header, program headers, hash table, symbols, strings,
+/// dynamic section and code in `image`, which is linked at address zero, so
+/// that offsets are also virtual addresses.
+fn build_image(image: &mut [u8]) {
+    extern "C" {
+        fn _svdso();
+        fn _evdso();
+        fn __vdso_clock_gettime();
+        fn __vdso_gettimeofday();
+    }
+    const SONAME: &str = "linux-vdso.so.1";
+    const PHNUM: usize = 2;
+    const CODE_OFFSET: usize = 0x400;
+
+    let code = _svdso as usize.._evdso as usize;
+    let symbols = [
+        ("__vdso_clock_gettime", __vdso_clock_gettime as usize),
+        ("__vdso_gettimeofday", __vdso_gettimeofday as usize),
+    ];
+    // index 0 is the null symbol
+    let nsyms = symbols.len() + 1;
+
+    // hash table with a single bucket chaining all symbols, so that hash values
+    // are never compared
+    let hash_offset = size_of::<Elf64Ehdr>() + PHNUM * size_of::<Elf64Phdr>();
+    let mut offset = put(image, hash_offset, 1u32);
+    offset = put(image, offset, nsyms as u32);
+    offset = put(image, offset, 1u32);
+    offset = put(image, offset, 0u32);
+    for i in 1..nsyms {
+        let next = if i + 1 < nsyms { i + 1 } else { 0 };
+        offset = put(image, offset, next as u32);
+    }
+
+    // strings, led by the empty one
+    let strtab_offset = offset;
+    offset += 1;
+    let mut put_str = |offset: &mut usize, s: &str| -> u32 {
+        let name = *offset - strtab_offset;
+        image[*offset..*offset + s.len()].copy_from_slice(s.as_bytes());
+        *offset += s.len() + 1;
+        name as u32
+    };
+    let soname = put_str(&mut offset, SONAME);
+    let names = symbols.map(|(name, _)| put_str(&mut offset, name));
+    let strsz = offset - strtab_offset;
+
+    let symtab_offset = offset.next_multiple_of(8);
+    offset = symtab_offset + size_of::<Elf64Sym>();
+    for ((_, addr), name) in symbols.iter().zip(names) {
+        offset = put(
+            image,
+            offset,
+            Elf64Sym {
+                st_name: name,
+                st_info: STB_GLOBAL_STT_FUNC,
+                st_other: 0,
+                st_shndx: SHN_TEXT,
+                st_value: (CODE_OFFSET + addr - code.start) as u64,
+                st_size: 0,
+            },
+        );
+    }
+
+    let dynamic_offset = offset;
+    for (d_tag, d_val) in [
+        (DT_HASH, hash_offset as u64),
+        (DT_STRTAB, strtab_offset as u64),
+        (DT_SYMTAB, symtab_offset as u64),
+        (DT_STRSZ, strsz as u64),
+        (DT_SYMENT, size_of::<Elf64Sym>() as u64),
+        (DT_SONAME, soname as u64),
+        (DT_NULL, 0),
+    ] {
+        offset = put(image, offset, Elf64Dyn { d_tag, d_val });
+    }
+    let dynamic_size = offset - dynamic_offset;
+
+    assert!(offset <= CODE_OFFSET && CODE_OFFSET + code.len() <= PAGE_SIZE);
+    let code_bytes = unsafe { core::slice::from_raw_parts(code.start as *const u8, code.len()) };
+    image[CODE_OFFSET..CODE_OFFSET + code.len()].copy_from_slice(code_bytes);
+
+    let mut e_ident = [0u8; 16];
+    // magic, 64-bit, little endian, current version
+    e_ident[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    let mut offset = put(
+        image,
+        0,
+        Elf64Ehdr {
+            e_ident,
+            e_type: ET_DYN,
+            e_machine: EM_RISCV,
+            e_version: 1,
+            e_entry: 0,
+            e_phoff: size_of::<Elf64Ehdr>() as u64,
+            e_shoff: 0,
+            e_flags: EF_RISCV,
+            e_ehsize: size_of::<Elf64Ehdr>() as u16,
+            e_phentsize: size_of::<Elf64Phdr>() as u16,
+            e_phnum: PHNUM as u16,
+            e_shentsize: 0,
+            e_shnum: 0,
+            e_shstrndx: 0,
+        },
+    );
+    offset = put(
+        image,
+        offset,
+        Elf64Phdr {
+            p_type: PT_LOAD,
+            p_flags: PF_R | PF_X,
+            p_offset: 0,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: PAGE_SIZE as u64,
+            p_memsz: PAGE_SIZE as u64,
+            p_align: PAGE_SIZE as u64,
+        },
+    );
+    put(
+        image,
+        offset,
+        Elf64Phdr {
+            p_type: PT_DYNAMIC,
+            p_flags: PF_R,
+            p_offset: dynamic_offset as u64,
+            p_vaddr: dynamic_offset as u64,
+            p_paddr: dynamic_offset as u64,
+            p_filesz: dynamic_size as u64,
+            p_memsz: dynamic_size as u64,
+            p_align: 8,
+        },
+    );
+}
@@ -1,4 +1,4 @@
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 
 use addr::SockAddr;
 use async_trait::async_trait;
@@ -29,7 +29,7 @@ impl Sock {
         match self {
             Sock::Tcp(tcp) => tcp.set_nonblocking(true),
             Sock::Udp(udp) => udp.set_nonblocking(true),
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(unix) => unix.set_nonblocking(),
         }
     }
 
@@ -54,15 +54,15 @@ impl Sock {
                 }
                 udp.bind(local_addr)
             }
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(_) => Err(SysError::EOPNOTSUPP),
         }
     }
 
-    pub fn listen(&self) -> SysResult<()> {
+    pub fn listen(&self, backlog: usize) -> SysResult<()> {
         match self {
-            Sock::Tcp(tcp) => tcp.listen(current_task().waker_ref().as_ref().unwrap()),
+            Sock::Tcp(tcp) => tcp.listen(current_task().waker_ref().as_ref().unwrap(), backlog),
             Sock::Udp(_udp) => Err(SysError::EOPNOTSUPP),
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(_) => Err(SysError::EOPNOTSUPP),
         }
     }
 
@@ -73,7 +73,7 @@ impl Sock {
                 Ok(new_tcp)
             }
             Sock::Udp(_udp) => Err(SysError::EOPNOTSUPP),
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(_) => Err(SysError::EOPNOTSUPP),
         }
     }
 
@@ -87,7 +87,7 @@ impl Sock {
                 let remote_addr = remote_addr.into_endpoint();
                 udp.connect(remote_addr)
             }
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(_) => Err(SysError::EOPNOTSUPP),
         }
     }
 
@@ -101,7 +101,7 @@ impl Sock {
                 let peer_addr = SockAddr::from_endpoint(udp.peer_addr()?);
                 Ok(peer_addr)
             }
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(unix) => Ok(unix.addr()),
         }
     }
 
@@ -115,7 +115,7 @@ impl Sock {
                 let local_addr = SockAddr::from_endpoint(udp.local_addr()?);
                 Ok(local_addr)
             }
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(unix) => Ok(unix.addr()),
         }
     }
     pub async fn sendto(&self, buf: &[u8], remote_addr: Option<SockAddr>) -> SysResult<usize> {
@@ -125,7 +125,7 @@ impl Sock {
                 Some(addr) => udp.send_to(buf, addr.into_endpoint()).await,
                 None => udp.send(buf).await,
             },
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(unix) => unix.send(buf, Vec::new()).await,
         }
     }
     pub async fn recvfrom(&self, buf: &mut [u8]) -> SysResult<(usize, SockAddr)> {
@@ -138,14 +138,19 @@ impl Sock {
                 let (len, endpoint) = udp.recv_from(buf).await?;
                 Ok((len, SockAddr::from_endpoint(endpoint)))
             }
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(unix) => {
+                // files passed along are closed, as they are not received by
+                // recvmsg
+                let len = unix.recv(buf).await?.len;
+                Ok((len, unix.addr()))
+            }
         }
     }
     pub async fn poll(&self) -> NetPollState {
         match self {
             Sock::Tcp(tcp) => tcp.poll().await,
             Sock::Udp(udp) => udp.poll().await,
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(unix) => unix.poll().await,
         }
     }
 
@@ -153,7 +158,7 @@ impl Sock {
         match self {
             Sock::Tcp(tcp) => tcp.shutdown(how),
             Sock::Udp(udp) => udp.shutdown(),
-            Sock::Unix(_) => unimplemented!(),
+            Sock::Unix(unix) => unix.shutdown(how),
         }
     }
 }
@@ -174,13 +179,19 @@ unsafe impl Send for Socket {}
 impl Socket {
     pub fn new(domain: SaFamily, types: SocketType, nonblock: bool) -> Self {
         let sk = match domain {
-            SaFamily::AF_UNIX => Sock::Unix(UnixSocket {}),
+            SaFamily::AF_UNIX => Sock::Unix(UnixSocket::new(types)),
             SaFamily::AF_INET | SaFamily::AF_INET6 => match types {
                 SocketType::STREAM => Sock::Tcp(TcpSocket::new_v4()),
                 SocketType::DGRAM => Sock::Udp(UdpSocket::new()),
                 _ => unimplemented!(),
             },
         };
+        Self::from_sock(types, sk, nonblock)
+    }
+
+    /// Make a socket of `sk`, which has been created, e.g. one of a pair by
+    /// `socketpair`.
+    pub fn from_sock(types: SocketType, sk: Sock, nonblock: bool) -> Self {
         let flags = if nonblock {
             sk.set_nonblocking();
             OpenFlags::O_RDWR | OpenFlags::O_NONBLOCK
@@ -195,6 +206,7 @@ impl Socket {
                 inode: Arc::<usize>::new_zeroed(),
                 pos: 0.into(),
                 flags: Mutex::new(flags),
+                read_ahead: Mutex::new(ReadAhead::default()),
             },
         }
     }
@@ -208,6 +220,7 @@ impl Socket {
                 inode: Arc::<usize>::new_zeroed(),
                 pos: 0.into(),
                 flags: Mutex::new(OpenFlags::O_RDWR),
+                read_ahead: Mutex::new(ReadAhead::default()),
             },
         }
     }
@@ -277,6 +290,10 @@ impl File for Socket {
         log::warn!("[Socket::File::ioctl] not supported now, return 0 instead");
         Ok(0)
     }
+
+    async fn seek(&self, _pos: SeekFrom) -> SyscallResult {
+        Err(SysError::ESPIPE)
+    }
 }
 
 /// sockfs是虚拟文件系统，所以在磁盘上不存在inode的表示，在内核中有struct
@@ -1 +1,360 @@
-pub struct UnixSocket {}
+//! Unix domain sockets, of which only connected pairs created by
+//! `socketpair` are supported for now.
+//!
+//! Each end of a pair receives messages from a `Channel` shared with its peer.
+//! A message may carry files passed by `SCM_RIGHTS`, which stay in flight
+//! until the message is received, or are closed when the message is discarded
+//! along with the receiving end. Note that a socket passed over itself is
+//! never discarded, since there is no garbage collection of in-flight sockets
+//! like Linux.
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    mem,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use async_utils::get_waker;
+use net::NetPollState;
+use sync::{mutex::SpinNoIrqLock, WaitQueue};
+use systype::{SysError, SysResult};
+use vfs_core::File;
+
+use super::{
+    addr::{SockAddr, SockAddrUn},
+    SaFamily, SocketType,
+};
+
+/// Bytes of data that can be queued on one end, the same as the buffer size
+/// reported by `getsockopt`.
+const UNIX_BUF_LEN: usize = 64 * 1024;
+
+/// A message queued on one end.
+struct UnixMessage {
+    data: Vec<u8>,
+    /// Offset of the data already read by stream receivers.
+    pos: usize,
+    /// Files in flight, which are installed into the fd table of the receiver.
+    files: Vec<Arc<dyn File>>,
+}
+
+impl UnixMessage {
+    fn remaining(&self) -> &[u8] {
+        &self.data[self.pos..]
+    }
+}
+
+struct ChannelInner {
+    messages: VecDeque<UnixMessage>,
+    /// Bytes of data in `messages` not read yet.
+    len: usize,
+    /// The receiving end is closed or shut down for reading, so that sending
+    /// fails with `EPIPE`.
+    recv_closed: bool,
+    /// The sending end is closed or shut down for writing, so that receiving
+    /// returns EOF once the messages are drained.
+    send_closed: bool,
+}
+
+/// Messages sent to one end of a pair.
+struct Channel {
+    inner: SpinNoIrqLock<ChannelInner>,
+    /// Tasks waiting for messages.
+    recv_queue: WaitQueue,
+    /// Tasks waiting for space to send.
+    send_queue: WaitQueue,
+}
+
+impl Channel {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: SpinNoIrqLock::new(ChannelInner {
+                messages: VecDeque::new(),
+                len: 0,
+                recv_closed: false,
+                send_closed: false,
+            }),
+            recv_queue: WaitQueue::new(),
+            send_queue: WaitQueue::new(),
+        })
+    }
+
+    fn readable(&self) -> bool {
+        let inner = self.inner.lock();
+        !inner.messages.is_empty() || inner.send_closed || inner.recv_closed
+    }
+
+    /// Whether a message of `len` bytes can be queued, where a stream message
+    /// can be split so that any space is enough.
+    fn writable(&self, len: usize, stream: bool) -> bool {
+        let inner = self.inner.lock();
+        let space = UNIX_BUF_LEN.saturating_sub(inner.len);
+        inner.recv_closed || inner.send_closed || space >= len || (stream && space > 0)
+    }
+
+    fn close_recv(&self) {
+        let messages = {
+            let mut inner = self.inner.lock();
+            inner.recv_closed = true;
+            inner.len = 0;
+            mem::take(&mut inner.messages)
+        };
+        // files in flight may be sockets which lock their own channels when
+        // dropped, so they are closed out of the lock
+        drop(messages);
+        self.recv_queue.wake_all();
+        self.send_queue.wake_all();
+    }
+
+    fn close_send(&self) {
+        self.inner.lock().send_closed = true;
+        self.recv_queue.wake_all();
+        self.send_queue.wake_all();
+    }
+}
+
+struct Connection {
+    /// Messages sent to this end.
+    rx: Arc<Channel>,
+    /// Messages sent to the peer.
+    tx: Arc<Channel>,
+}
+
+/// Data and files received by `UnixSocket::recv`.
+pub struct UnixRecv {
+    pub len: usize,
+    pub files: Vec<Arc<dyn File>>,
+    /// The rest of a datagram which does not fit in the buffer is discarded.
+    pub truncated: bool,
+}
+
+pub struct UnixSocket {
+    types: SocketType,
+    conn: Option<Connection>,
+    nonblock: AtomicBool,
+}
+
+impl UnixSocket {
+    /// An unconnected socket created by `socket`, which can not be bound or
+    /// connected yet.
+    pub fn new(types: SocketType) -> Self {
+        Self {
+            types,
+            conn: None,
+            nonblock: AtomicBool::new(false),
+        }
+    }
+
+    /// A pair of connected sockets.
+    pub fn new_pair(types: SocketType) -> (Self, Self) {
+        let (a, b) = (Channel::new(), Channel::new());
+        let new = |rx, tx| Self {
+            types,
+            conn: Some(Connection { rx, tx }),
+            nonblock: AtomicBool::new(false),
+        };
+        (new(a.clone(), b.clone()), new(b, a))
+    }
+
+    /// Address of the peer and the socket itself, which are always unnamed.
+    pub fn addr(&self) -> SockAddr {
+        SockAddr {
+            unix: SockAddrUn {
+                family: SaFamily::AF_UNIX.into(),
+                path: [0; 108],
+            },
+        }
+    }
+
+    pub fn set_nonblocking(&self) {
+        self.nonblock.store(true, Ordering::Relaxed);
+    }
+
+    fn is_stream(&self) -> bool {
+        self.types == SocketType::STREAM
+    }
+
+    fn conn(&self) -> SysResult<&Connection> {
+        self.conn.as_ref().ok_or(SysError::ENOTCONN)
+    }
+
+    /// Send `buf` with `files` attached. Stream data may be split into several
+    /// messages when the peer is short of space, where the files go with the
+    /// first one, while a datagram is always sent as a whole.
+    pub async fn send(&self, buf: &[u8], mut files: Vec<Arc<dyn File>>) -> SysResult<usize> {
+        let tx = &self.conn()?.tx;
+        let stream = self.is_stream();
+        if stream && buf.is_empty() {
+            return Ok(0);
+        }
+        if !stream && buf.len() > UNIX_BUF_LEN {
+            return Err(SysError::EMSGSIZE);
+        }
+        let mut sent = 0;
+        loop {
+            let remaining = buf.len() - sent;
+            if !tx.writable(remaining, stream) {
+                if self.nonblock.load(Ordering::Relaxed) {
+                    return if sent > 0 {
+                        Ok(sent)
+                    } else {
+                        Err(SysError::EAGAIN)
+                    };
+                }
+                tx.send_queue
+                    .wait_until(|| tx.writable(remaining, stream))
+                    .await;
+            }
+            let mut inner = tx.inner.lock();
+            if inner.recv_closed || inner.send_closed {
+                return Err(SysError::EPIPE);
+            }
+            let space = UNIX_BUF_LEN.saturating_sub(inner.len);
+            if space < remaining && (!stream || space == 0) {
+                // taken by other senders
+                continue;
+            }
+            let len = remaining.min(space);
+            inner.messages.push_back(UnixMessage {
+                data: buf[sent..sent + len].to_vec(),
+                pos: 0,
+                files: mem::take(&mut files),
+            });
+            inner.len += len;
+            drop(inner);
+            tx.recv_queue.wake_one();
+            sent += len;
+            if sent == buf.len() {
+                return Ok(sent);
+            }
+        }
+    }
+
+    /// Receive into `buf`. A stream receiver reads across messages, but stops
+    /// after a message carrying files, so that files are never received with
+    /// data sent after them, and before one if some data is already read.
+    pub async fn recv(&self, buf: &mut [u8]) -> SysResult<UnixRecv> {
+        let rx = &self.conn()?.rx;
+        loop {
+            if !rx.readable() {
+                if self.nonblock.load(Ordering::Relaxed) {
+                    return Err(SysError::EAGAIN);
+                }
+                rx.recv_queue.wait_until(|| rx.readable()).await;
+            }
+            let mut inner = rx.inner.lock();
+            if inner.messages.is_empty() {
+                if inner.send_closed || inner.recv_closed {
+                    return Ok(UnixRecv {
+                        len: 0,
+                        files: Vec::new(),
+                        truncated: false,
+                    });
+                }
+                // taken by other receivers
+                continue;
+            }
+            let ret = if self.is_stream() {
+                Self::recv_stream(&mut inner, buf)
+            } else {
+                Self::recv_datagram(&mut inner, buf)
+            };
+            drop(inner);
+            rx.send_queue.wake_all();
+            return Ok(ret);
+        }
+    }
+
+    fn recv_stream(inner: &mut ChannelInner, buf: &mut [u8]) -> UnixRecv {
+        let mut len = 0;
+        let mut files = Vec::new();
+        while let Some(message) = inner.messages.front_mut() {
+            if len > 0 && !message.files.is_empty() {
+                break;
+            }
+            let n = message.remaining().len().min(buf.len() - len);
+            buf[len..len + n].copy_from_slice(&message.remaining()[..n]);
+            message.pos += n;
+            len += n;
+            files = mem::take(&mut message.files);
+            if message.remaining().is_empty() {
+                inner.messages.pop_front();
+            }
+            if !files.is_empty() || len == buf.len() {
+                break;
+            }
+        }
+        inner.len -= len;
+        UnixRecv {
+            len,
+            files,
+            truncated: false,
+        }
+    }
+
+    fn recv_datagram(inner: &mut ChannelInner, buf: &mut [u8]) -> UnixRecv {
+        let message = inner.messages.pop_front().unwrap();
+        inner.len -= message.data.len();
+        let len = message.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&message.data[..len]);
+        UnixRecv {
+            len,
+            files: message.files,
+            truncated: len < message.data.len(),
+        }
+    }
+
+    pub async fn poll(&self) -> NetPollState {
+        let Some(conn) = self.conn.as_ref() else {
+            return NetPollState {
+                readable: false,
+                writable: false,
+                hangup: true,
+            };
+        };
+        let waker = get_waker().await;
+        let readable = conn.rx.readable();
+        let writable = conn.tx.writable(1, true);
+        if !readable {
+            conn.rx.recv_queue.register(&waker);
+        }
+        if !writable {
+            conn.tx.send_queue.register(&waker);
+        }
+        // the two channels are never locked together, since the peer may lock
+        // them in the other order
+        let peer_send_closed = conn.rx.inner.lock().send_closed;
+        let peer_recv_closed = conn.tx.inner.lock().recv_closed;
+        let hangup = peer_send_closed && peer_recv_closed;
+        NetPollState {
+            readable,
+            writable,
+            hangup,
+        }
+    }
+
+    pub fn shutdown(&self, how: u8) -> SysResult<()> {
+        let conn = self.conn()?;
+        match how {
+            0 => conn.rx.inner.lock().recv_closed = true,
+            1 => conn.tx.close_send(),
+            2 => {
+                conn.rx.inner.lock().recv_closed = true;
+                conn.tx.close_send();
+            }
+            _ => return Err(SysError::EINVAL),
+        }
+        conn.rx.recv_queue.wake_all();
+        conn.rx.send_queue.wake_all();
+        Ok(())
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.as_ref() {
+            conn.rx.close_recv();
+            conn.tx.close_send();
+        }
+    }
+}
@@ -10,7 +10,7 @@ use logging::LOG_INITIALIZED;
 use sbi_print::sbi_println;
 use sbi_rt::legacy::shutdown;
 
-use crate::processor::hart::local_hart;
+use crate::{processor::hart::local_hart, syscall::SyscallNo};
 
 static PANIC_CNT: AtomicUsize = AtomicUsize::new(0);
 
@@ -38,6 +38,8 @@ fn panic(info: &PanicInfo) -> ! {
         shutdown()
     }
 
+    // interrupts may never be taken again to drain the console
+    driver::serial::set_console_polling(true);
     println!("panic now!!!");
 
     // NOTE: message below is mostly printed in log, if these messages can not be
@@ -74,9 +76,122 @@ fn panic(info: &PanicInfo) -> ! {
         println!("Unknown panic: {:?}", info);
     }
 
+    if local_hart().has_task() {
+        dump_task();
+    }
+    sync::mutex::print_held_locks();
+
     log::error!("=============== BEGIN BACKTRACE ================");
     backtrace();
     log::error!("=============== END BACKTRACE ================");
 
+    #[cfg(feature = "recover-user")]
+    if local_hart().has_task() && local_hart().env().in_syscall() {
+        recover_user()
+    }
+
     shutdown()
 }
+
+const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Print the current task, its user registers and the syscalls it made
+/// recently.
+fn dump_task() {
+    let task = local_hart().task();
+    log::error!(
+        "Hart {} running task {} of process {} ({})",
+        local_hart().hart_id(),
+        task.tid(),
+        task.pid(),
+        task.elf_ref().dentry().name()
+    );
+    let cx = task.trap_context_mut();
+    log::error!("user sepc {:#018x}", cx.sepc);
+    for (names, regs) in REG_NAMES.chunks(4).zip(cx.user_x.chunks(4)) {
+        log::error!(
+            "{:>4} {:#018x} {:>4} {:#018x} {:>4} {:#018x} {:>4} {:#018x}",
+            names[0],
+            regs[0],
+            names[1],
+            regs[1],
+            names[2],
+            regs[2],
+            names[3],
+            regs[3]
+        );
+    }
+    for syscall_no in task.syscall_history_ref().iter() {
+        match SyscallNo::from_repr(syscall_no) {
+            Some(syscall) => log::error!("recent syscall {syscall_no} {syscall:?}"),
+            None => log::error!("recent syscall {syscall_no}"),
+        }
+    }
+}
+
+/// Recover from a panic in a syscall by killing the process, then run other
+/// tasks on a fresh stack.
+///
+/// Whatever the abandoned frames own is leaked, and the locks they hold are
+/// never released, so tasks contending for them later may hang.
+#[cfg(feature = "recover-user")]
+fn recover_user() -> ! {
+    use alloc::vec::Vec;
+
+    use signal::{Sig, SigDetails, SigInfo};
+
+    let hart = local_hart();
+    let task = hart.abandon_user_task();
+    log::error!(
+        "kill process {} to recover from the panic in its syscall",
+        task.pid()
+    );
+    let threads: Vec<_> = task.with_thread_group(|tg| tg.iter().collect());
+    for thread in threads.iter().filter(|t| t.tid() != task.tid()) {
+        thread.receive_siginfo(
+            SigInfo {
+                sig: Sig::SIGKILL,
+                code: SigInfo::KERNEL,
+                details: SigDetails::None,
+            },
+            true,
+        );
+    }
+    task.set_terminated();
+    task.set_exit_code(Sig::SIGKILL.raw() as i32 & 0x7F);
+    crate::task::spawn_exit_task(task);
+    PANIC_CNT.fetch_sub(1, Ordering::Relaxed);
+    driver::serial::set_console_polling(false);
+
+    let stack_top = arch::entry::kernel_stack(hart.hart_id()).end;
+    unsafe {
+        core::arch::asm!(
+            "mv sp, {stack_top}",
+            // end the backtraces here
+            "mv fp, zero",
+            "csrsi sstatus, 2",
+            "tail {run_tasks}",
+            stack_top = in(reg) stack_top,
+            run_tasks = sym crate::run_tasks,
+            options(noreturn)
+        )
+    }
+}
+
+/// Panic a few calls deep, for checking the functions printed in the
+/// backtrace.
+#[cfg(feature = "panic-test")]
+pub fn panic_test() -> ! {
+    #[inline(never)]
+    fn inner(depth: usize) -> ! {
+        if depth == 0 {
+            panic!("panic test");
+        }
+        inner(core::hint::black_box(depth - 1))
+    }
+    inner(3)
+}
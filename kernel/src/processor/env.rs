@@ -4,6 +4,7 @@ use arch::memory::sfence_vma_all;
 use riscv::register::{satp, sepc};
 
 use super::hart::local_hart;
+use crate::mm::tlb;
 
 /// use RAII to guard `sum` flag.
 pub struct SumGuard;
@@ -33,20 +34,27 @@ pub struct EnvContext {
     // For preempt and non preempt
     /// Permit supervisor user memory access
     sum_cnt: usize,
+    /// Whether the task is handling a syscall, so that a panic in it may be
+    /// recovered by killing the process.
+    in_syscall: bool,
 
     // For preempt only
     sstatus: usize,
     sepc: usize,
     satp: usize,
+    /// Page table recorded for TLB shootdowns along with `satp`.
+    active_token: usize,
 }
 
 impl EnvContext {
     pub const fn new() -> Self {
         Self {
             sum_cnt: 0,
+            in_syscall: false,
             sstatus: 0,
             sepc: 0,
             satp: 0,
+            active_token: 0,
         }
     }
 
@@ -74,6 +82,14 @@ impl EnvContext {
         }
     }
 
+    pub fn in_syscall(&self) -> bool {
+        self.in_syscall
+    }
+
+    pub fn set_in_syscall(&mut self, in_syscall: bool) {
+        self.in_syscall = in_syscall;
+    }
+
     pub fn change_env(&self, new: &Self) {
         unsafe { new.auto_sum() };
     }
@@ -82,12 +98,18 @@ impl EnvContext {
         self.sstatus = arch::sstatus::read().bits();
         self.sepc = sepc::read();
         self.satp = satp::read().bits();
+        self.active_token = tlb::active_token();
     }
 
     pub unsafe fn preempt_resume(&self) {
         arch::sstatus::write(self.sstatus);
         sepc::write(self.sepc);
+        tlb::set_active_token(self.active_token);
         satp::write(self.satp);
         sfence_vma_all();
+        // the preempting tasks may have handed out the ASID in satp to another
+        // page table after a rollover, so the TLB is flushed again on the next
+        // switch
+        memory::asid::invalidate_local();
     }
 }
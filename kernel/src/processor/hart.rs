@@ -1,12 +1,20 @@
 use alloc::sync::Arc;
+#[cfg(feature = "debug")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 use core::{arch::asm, sync::atomic::AtomicBool};
 
-use arch::interrupts::{disable_interrupt, enable_interrupt};
+use arch::interrupts::{disable_interrupt, enable_interrupt, wait_for_interrupt, InterruptGuard};
 use config::board::MAX_HARTS;
-use riscv::register::sstatus::{self, FS};
+use riscv::register::{
+    scounteren,
+    sstatus::{self, FS},
+};
 
 use super::env::EnvContext;
-use crate::{mm, task::Task};
+use crate::{
+    mm::{self, tlb::PendingShootdown},
+    task::{loadavg, Task},
+};
 
 const HART_EACH: Hart = Hart::new();
 pub static mut HARTS: [Hart; MAX_HARTS] = [HART_EACH; MAX_HARTS];
@@ -19,6 +27,11 @@ pub struct Hart {
     hart_id: usize,
     task: Option<Arc<Task>>,
     env: EnvContext,
+    /// Set when the time slice of the current task is used up, and the task
+    /// should yield before returning to user mode.
+    need_resched: bool,
+    /// TLB shootdowns issued by this hart and not waited for yet.
+    shootdown: PendingShootdown,
 }
 
 impl Hart {
@@ -27,6 +40,8 @@ impl Hart {
             hart_id: 0,
             task: None,
             env: EnvContext::new(),
+            need_resched: false,
+            shootdown: PendingShootdown::new(),
         }
     }
 
@@ -50,6 +65,26 @@ impl Hart {
         self.task.is_some()
     }
 
+    /// Consume one tick of the time slice of the current task on timer
+    /// interrupt.
+    pub fn tick(&mut self) {
+        loadavg::sample();
+        if let Some(task) = &self.task {
+            task.sched_attr().tick();
+            if task.time_stat().tick() {
+                self.need_resched = true;
+            }
+        }
+    }
+
+    pub fn take_need_resched(&mut self) -> bool {
+        core::mem::take(&mut self.need_resched)
+    }
+
+    pub fn shootdown_mut(&mut self) -> &mut PendingShootdown {
+        &mut self.shootdown
+    }
+
     pub fn env(&self) -> &EnvContext {
         &self.env
     }
@@ -75,6 +110,7 @@ impl Hart {
         unsafe { disable_interrupt() };
         unsafe { env.auto_sum() };
         self.set_task(Arc::clone(task));
+        self.need_resched = false;
         task.time_stat().record_switch_in();
         core::mem::swap(self.env_mut(), env);
         // NOTE: must switch page table even if it belongs to the same user in smp
@@ -99,6 +135,19 @@ impl Hart {
         unsafe { enable_interrupt() };
     }
 
+    /// Take the current task off the hart without switching back from it, as
+    /// its future has been abandoned by recovering from a panic.
+    #[cfg(feature = "recover-user")]
+    pub fn abandon_user_task(&mut self) -> Arc<Task> {
+        unsafe { disable_interrupt() };
+        unsafe { mm::switch_kernel_page_table() };
+        // the env of the executor is lost along with the future
+        self.env = EnvContext::new();
+        unsafe { self.env.auto_sum() };
+        self.need_resched = false;
+        self.task.take().unwrap()
+    }
+
     pub fn kernel_task_switch(&mut self, env: &mut EnvContext) {
         unsafe { disable_interrupt() };
         self.change_env(env);
@@ -162,10 +211,45 @@ pub fn local_hart_disable_preemptable() {
     }
 }
 
+/// Wait for interrupts when there are no tasks to run, until woken up by
+/// timers, devices or IPIs from other harts that have pushed some tasks.
+pub fn idle() {
+    // interrupts are disabled so that a pending one is handled after waking
+    // up instead of before waiting, otherwise it may be missed
+    let _guard = InterruptGuard::new();
+    if executor::enter_idle() {
+        wait_for_interrupt();
+        executor::leave_idle();
+    }
+}
+
 pub fn init(hart_id: usize) {
     unsafe {
         set_local_hart(hart_id);
         sstatus::set_fs(FS::Initial);
+        // Allow user to read the time CSR, on which the vDSO relies.
+        scounteren::set_tm();
+    }
+    #[cfg(feature = "debug")]
+    arch::entry::fill_local_stack();
+}
+
+/// Most bytes of a kernel stack ever used, over all harts.
+#[cfg(feature = "debug")]
+static STACK_HIGH_WATER: AtomicUsize = AtomicUsize::new(0);
+
+/// Scan the local kernel stack for how deep it has been used, and report a
+/// new high-water mark.
+#[cfg(feature = "debug")]
+pub fn check_stack_usage(tid: usize) {
+    use config::mm::{KERNEL_STACK_GUARD_SIZE, KERNEL_STACK_SIZE};
+
+    let used = arch::entry::local_stack_usage();
+    if STACK_HIGH_WATER.fetch_max(used, Ordering::Relaxed) < used {
+        log::warn!(
+            "[check_stack_usage] kernel stack high-water mark {used} of {} bytes, on exit of thread {tid}",
+            KERNEL_STACK_SIZE - KERNEL_STACK_GUARD_SIZE
+        );
     }
 }
 
@@ -287,6 +287,33 @@ pub enum SyscallNo {
     PKEY_FREE = 290,
 }
 
+impl SyscallNo {
+    /// Whether this syscall may be transparently restarted after being
+    /// interrupted by a signal whose handler was installed with `SA_RESTART`.
+    ///
+    /// Following Linux, syscalls that wait with a timeout or wait for signals
+    /// themselves always fail with `EINTR` regardless of `SA_RESTART`. See
+    /// signal(7) "Interruption of system calls and library functions by signal
+    /// handlers".
+    pub fn is_restartable(&self) -> bool {
+        !matches!(
+            self,
+            Self::NANOSLEEP
+                | Self::CLOCK_NANOSLEEP
+                | Self::RT_SIGSUSPEND
+                | Self::RT_SIGTIMEDWAIT
+                | Self::PPOLL
+                | Self::PSELECT6
+                | Self::EPOLL_PWAIT
+                | Self::IO_GETEVENTS
+                | Self::MSGRCV
+                | Self::MSGSND
+                | Self::SEMOP
+                | Self::SEMTIMEDOP
+        )
+    }
+}
+
 impl core::fmt::Display for SyscallNo {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:?}", self)
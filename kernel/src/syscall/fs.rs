@@ -1,4 +1,4 @@
-use alloc::{ffi::CString, sync::Arc, vec, vec::Vec};
+use alloc::{ffi::CString, string::String, sync::Arc, vec, vec::Vec};
 use core::{
     cmp, default,
     ops::{Deref, DerefMut},
@@ -6,16 +6,24 @@ use core::{
 
 use arch::time::get_time_duration;
 use async_utils::{Select2Futures, SelectOutput};
-use config::{board::BLOCK_SIZE, fs::PIPE_BUF_LEN};
+use config::{fs::PIPE_BUF_LEN, mm::PAGE_SIZE};
 use driver::BLOCK_DEVICE;
 use strum::FromRepr;
 use systype::{SysError, SyscallResult};
 use time::timespec::TimeSpec;
-use vfs::{fd_table::FdFlags, pipefs::new_pipe, simplefs::dentry, sys_root_dentry, FS_MANAGER};
+use vfs::{
+    devfs,
+    fd_table::FdFlags,
+    path_file::PathFile,
+    pipefs::{new_pipe, PipeInode},
+    probe::probe_fs_type,
+    simplefs::dentry,
+    sys_root_dentry, FS_MANAGER,
+};
 use vfs_core::{
-    is_absolute_path, split_parent_and_name, AtFd, Dentry, Inode, InodeMode, InodeType, MountFlags,
-    OpenFlags, Path, RenameFlags, SeekFrom, Stat, StatFs, AT_REMOVEDIR, AT_SYMLINK_FOLLOW,
-    AT_SYMLINK_NOFOLLOW,
+    is_absolute_path, split_parent_and_name, AccessMode, AtFd, Dentry, DentryState, Inode,
+    InodeMode, InodeType, MountFlags, OpenFlags, Path, RenameFlags, SeekFrom, Stat, StatFs,
+    AT_REMOVEDIR, AT_SYMLINK_FOLLOW, AT_SYMLINK_NOFOLLOW,
 };
 
 use super::Syscall;
@@ -43,10 +51,29 @@ pub enum FcntlOp {
     F_SETFD = 2,
     F_GETFL = 3,
     F_SETFL = 4,
+    F_ADD_SEALS = 1033,
+    F_GET_SEALS = 1034,
     #[default]
     F_UNIMPL,
 }
 
+bitflags! {
+    // Defined in <linux/memfd.h>
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MemfdFlags: u32 {
+        /// Set close-on-exec on the new file descriptor.
+        const MFD_CLOEXEC = 0x1;
+        /// Allow sealing operations on the file.
+        const MFD_ALLOW_SEALING = 0x2;
+        /// Back the file with huge pages.
+        const MFD_HUGETLB = 0x4;
+    }
+}
+
+/// Max length of the name given to memfd_create, without "memfd:" and the
+/// trailing nul.
+const MFD_NAME_MAX: usize = 249;
+
 // Defined in <bits/struct_stat.h>
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -92,7 +119,7 @@ impl Kstat {
         Kstat {
             st_dev: stat.st_dev,
             st_ino: stat.st_ino,
-            st_mode: stat.st_mode, // 0777 permission, we don't care about permission
+            st_mode: stat.st_mode,
             st_nlink: stat.st_nlink,
             st_uid: stat.st_uid,
             st_gid: stat.st_gid,
@@ -120,7 +147,7 @@ impl Syscall<'_> {
     /// file), and the file position is advanced by this number.
     pub async fn sys_read(&self, fd: usize, buf: UserWritePtr<u8>, count: usize) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
         log::info!("[sys_read] reading file {}", file.dentry().path());
         let mut buf = buf.into_mut_slice(&task, count)?;
 
@@ -140,7 +167,7 @@ impl Syscall<'_> {
 
     pub async fn sys_write(&self, fd: usize, buf: UserReadPtr<u8>, count: usize) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
         log::info!("[sys_write] writing file {}", file.dentry().path());
         let buf = buf.into_slice(&task, count)?;
         // log::info!("[sys_write] buf {buf:?}");
@@ -166,7 +193,7 @@ impl Syscall<'_> {
         offset: usize,
     ) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
         log::info!("[sys_pread64] reading file {}", file.dentry().path());
         let mut buf = buf.into_mut_slice(&task, count)?;
         let ret = file.read_at(offset, &mut buf).await?;
@@ -181,7 +208,7 @@ impl Syscall<'_> {
         offset: usize,
     ) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
         log::info!("[sys_pwrite64] writing file {}", file.dentry().path());
         let buf = buf.into_slice(&task, count)?;
         let ret = file.write_at(offset, &buf).await?;
@@ -216,30 +243,129 @@ impl Syscall<'_> {
         let task = self.task;
         let flags = OpenFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
         let mode = InodeMode::from_bits_truncate(mode);
-        let pathname = pathname.read_cstr(&task)?;
+        let pathname = pathname.read_path(&task)?;
         log::info!(
             "[sys_openat] dirfd: {dirfd}, pathname: {pathname}, flags: {flags:?}, mode: {mode:?}"
         );
         let dentry = task.at_helper(dirfd, &pathname, flags)?;
+        if flags.contains(OpenFlags::O_PATH) {
+            // Flags other than these are ignored.
+            let flags = flags.intersection(
+                OpenFlags::O_PATH
+                    | OpenFlags::O_CLOEXEC
+                    | OpenFlags::O_DIRECTORY
+                    | OpenFlags::O_NOFOLLOW,
+            );
+            let inode = dentry.inode()?;
+            if flags.contains(OpenFlags::O_DIRECTORY) && !inode.itype().is_dir() {
+                return Err(SysError::ENOTDIR);
+            }
+            let file = PathFile::new(dentry, inode, flags);
+            return task.with_mut_fd_table(|table| table.alloc(file, flags));
+        }
+        let mut created = false;
         if flags.contains(OpenFlags::O_CREAT) {
             // If pathname does not exist, create it as a regular file.
             if flags.contains(OpenFlags::O_EXCL) && !dentry.is_negetive() {
                 return Err(SysError::EEXIST);
             }
-            let parent = dentry.parent().expect("can not be root dentry");
-            parent.create(dentry.name(), InodeMode::FILE | mode)?;
+            if dentry.is_negetive() {
+                let parent = dentry.parent().expect("can not be root dentry");
+                task.create_helper(&parent, &dentry.name(), InodeMode::FILE | mode)?;
+                created = true;
+            }
         }
 
         let inode = dentry.inode()?;
         if flags.contains(OpenFlags::O_DIRECTORY) && !inode.itype().is_dir() {
             return Err(SysError::ENOTDIR);
         }
+        // The trailing symlink is not followed with `O_NOFOLLOW`, see `at_helper`.
+        if inode.itype().is_symlink() {
+            return Err(SysError::ELOOP);
+        }
+        // A newly created file can be opened in any mode, even if its mode does not
+        // allow.
+        if !created {
+            let mut access = AccessMode::empty();
+            if flags.readable() {
+                access |= AccessMode::READ;
+            }
+            if flags.writable() || flags.contains(OpenFlags::O_TRUNC) {
+                access |= AccessMode::WRITE;
+            }
+            task.with_cred(|cred| cred.check_access(&inode, access, false))?;
+        }
 
-        let file = dentry.open()?;
+        let file = if inode.itype().is_fifo() {
+            let pipe = inode
+                .downcast_arc::<PipeInode>()
+                .map_err(|_| SysError::ENXIO)?;
+            let (file, wait_peer) = pipe.open_fifo(dentry, flags)?;
+            if !flags.contains(OpenFlags::O_NONBLOCK) {
+                task.wait_interruptible(wait_peer, None).await?;
+            }
+            file
+        } else {
+            dentry.open()?
+        };
         file.set_flags(flags);
         task.with_mut_fd_table(|table| table.alloc(file, flags))
     }
 
+    /// The system call mknod() creates a filesystem node (file, device special
+    /// file, or named pipe) named pathname, with attributes specified by mode
+    /// and dev.
+    ///
+    /// The file type must be one of S_IFREG, S_IFCHR, S_IFBLK, S_IFIFO, or
+    /// S_IFSOCK. If the file type is S_IFCHR or S_IFBLK, then dev specifies the
+    /// major and minor numbers of the newly created device special file.
+    pub fn sys_mknodat(
+        &self,
+        dirfd: AtFd,
+        pathname: UserReadPtr<u8>,
+        mode: u32,
+        dev: u64,
+    ) -> SyscallResult {
+        let task = self.task;
+        let mut mode = InodeMode::from_bits_truncate(mode);
+        let pathname = pathname.read_path(&task)?;
+        log::info!(
+            "[sys_mknodat] dirfd: {dirfd}, pathname: {pathname}, mode: {mode:?}, dev: {dev:#x}"
+        );
+        let dentry = task.at_helper(dirfd, &pathname, OpenFlags::O_NOFOLLOW)?;
+        if !dentry.is_negetive() {
+            return Err(SysError::EEXIST);
+        }
+        let parent = dentry.parent().expect("can not be root dentry");
+        let name = &dentry.name();
+        // zero file type means a regular file
+        if (mode & InodeMode::TYPE_MASK).is_empty() {
+            mode |= InodeMode::FILE;
+        }
+        match mode.to_type() {
+            InodeType::File => {
+                task.create_helper(&parent, name, mode)?;
+            }
+            InodeType::Fifo => {
+                task.mknod_helper(&parent, mode, |_| {
+                    parent.mknod(name, PipeInode::new_fifo(parent.super_block()))
+                })?;
+            }
+            InodeType::CharDevice | InodeType::BlockDevice => {
+                if !task.with_cred(|cred| cred.is_privileged()) {
+                    return Err(SysError::EPERM);
+                }
+                let major = (((dev >> 32) & 0xfffff000) | ((dev >> 8) & 0xfff)) as u32;
+                let minor = (((dev >> 12) & 0xffffff00) | (dev & 0xff)) as u32;
+                task.mknod_helper(&parent, mode, |_| devfs::mknod(&parent, name, major, minor))?;
+            }
+            InodeType::Dir => return Err(SysError::EINVAL),
+            _ => return Err(SysError::EPERM),
+        }
+        Ok(0)
+    }
+
     /// close() closes a file descriptor, so that it no longer refers to any
     /// file and may be reused. Any record locks (see fcntl(2)) held on the
     /// file it was associated with, and owned by the process, are removed
@@ -264,14 +390,14 @@ impl Syscall<'_> {
     pub fn sys_mkdirat(&self, dirfd: AtFd, pathname: UserReadPtr<u8>, mode: u32) -> SyscallResult {
         let task = self.task;
         let mode = InodeMode::from_bits_truncate(mode);
-        let pathname = pathname.read_cstr(&task)?;
+        let pathname = pathname.read_path(&task)?;
         log::debug!("[sys_mkdirat] {mode:?}");
         let dentry = task.at_helper(dirfd, &pathname, OpenFlags::empty())?;
         if !dentry.is_negetive() {
             return Err(SysError::EEXIST);
         }
         let parent = dentry.parent().unwrap();
-        parent.create(dentry.name(), mode.union(InodeMode::DIR))?;
+        task.create_helper(&parent, &dentry.name(), mode.union(InodeMode::DIR))?;
         Ok(0)
     }
 
@@ -303,7 +429,14 @@ impl Syscall<'_> {
             return Err(SysError::EINVAL);
         }
         let task = self.task;
-        let abs_path = task.cwd().path();
+        // The path is built from the cwd dentry at call time, so it follows
+        // renames of the cwd or its ancestors.
+        let cwd = task.cwd();
+        if cwd.is_negetive() {
+            // the cwd has been removed
+            return Err(SysError::ENOENT);
+        }
+        let abs_path = cwd.path();
         let c_path_len = abs_path.len() + 1;
         if c_path_len > size {
             return Err(SysError::ERANGE);
@@ -323,7 +456,7 @@ impl Syscall<'_> {
     /// set to indicate the error.
     pub fn sys_chdir(&self, path: UserReadPtr<u8>) -> SyscallResult {
         let task = self.task;
-        let path = path.read_cstr(&task)?;
+        let path = path.read_path(&task)?;
         log::debug!("[sys_chdir] path {path}");
         let dentry = task.resolve_path(&path)?;
         if !dentry.inode()?.itype().is_dir() {
@@ -333,6 +466,18 @@ impl Syscall<'_> {
         Ok(0)
     }
 
+    /// fchdir() is identical to chdir(); the only difference is that the
+    /// directory is given as an open file descriptor.
+    pub fn sys_fchdir(&self, fd: usize) -> SyscallResult {
+        let task = self.task;
+        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        if !file.itype().is_dir() {
+            return Err(SysError::ENOTDIR);
+        }
+        task.set_cwd(file.dentry());
+        Ok(0)
+    }
+
     /// The dup() system call allocates a new file descriptor that refers to the
     /// same open file description as the descriptor oldfd. (For an explanation
     /// of open file descriptions, see open(2).) The new file descriptor
@@ -410,7 +555,7 @@ impl Syscall<'_> {
     ) -> SyscallResult {
         const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
         let task = self.task;
-        let path = pathname.read_cstr(&task)?;
+        let path = pathname.read_path(&task)?;
         let dentry = if flags == AT_SYMLINK_NOFOLLOW {
             task.at_helper(dirfd, &path, OpenFlags::O_NOFOLLOW)?
         } else {
@@ -430,42 +575,70 @@ impl Syscall<'_> {
         data: UserReadPtr<u8>,
     ) -> SyscallResult {
         let task = self.task;
-        let source = source.read_cstr(&task)?;
-        let target = target.read_cstr(&task)?;
-        let fstype = fstype.read_cstr(&task)?;
+        let source = source.read_path(&task)?;
+        let target = target.read_path(&task)?;
+        let fstype = fstype.read_path(&task)?;
         let flags = MountFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
+        let data = if data.is_null() {
+            String::new()
+        } else {
+            data.read_cstr_with_max(&task, PAGE_SIZE)?
+        };
         log::debug!(
         "[sys_mount] source:{source:?}, target:{target:?}, fstype:{fstype:?}, flags:{flags:?}, data:{data:?}",
     );
 
+        // the source is a loop device bound to an image file, while test suites
+        // mount devices not created, e.g. /dev/vda2, which are taken as the block
+        // device of the root
+        let source_device = || match task.resolve_path(&source) {
+            Ok(dentry) if !dentry.is_negetive() => {
+                dentry.inode().and_then(devfs::loop_dev::loop_block_device)
+            }
+            _ => Ok(BLOCK_DEVICE.get().unwrap().clone()),
+        };
+        // "auto" is resolved by probing the device as mount(8) does
+        let fstype = if fstype == "auto" {
+            String::from(probe_fs_type(&source_device()?).ok_or(SysError::EINVAL)?)
+        } else {
+            fstype
+        };
         // adding this code is because the fs_type in test code is vfat, which should be
         // turned into fat32
-        let fat32_type = FS_MANAGER.lock().get("fat32").unwrap().clone();
-        let fs_type = FS_MANAGER
-            .lock()
-            .get(&fstype)
-            .unwrap_or(&fat32_type.clone())
-            .clone();
+        let fs_type = {
+            let fs_manager = FS_MANAGER.lock();
+            fs_manager
+                .get(&fstype)
+                .or_else(|| fs_manager.get("fat32"))
+                .unwrap()
+                .clone()
+        };
         let _fs_root = match fs_type.name() {
-            name @ "fat32" => {
-                let dev = if name.eq("fat32") {
-                    // here should be getting device according to inode
-                    // it seems that device hasn't been associated with inode yet.
-                    // so here just return a virtio_block
-                    // let path = Path::new(sys_root_dentry(), sys_root_dentry(), &*source);
-                    // let dev = path.walk(InodeMode::BLOCK)?;
-                    // let dev_ino = dev.inode()?;
-                    // if dev_ino.itype() != InodeType::BlockDevice {
-                    //     return Err(SysError::EINVAL);
-                    // }
-                    Some(BLOCK_DEVICE.get().unwrap().clone())
-                } else {
-                    None
-                };
+            "fat32" | "ext4" => {
+                let dev = source_device()?;
                 let (parent, name) = split_parent_and_name(&target);
 
                 let parent = task.resolve_path(parent)?;
-                fs_type.mount(name.unwrap(), Some(parent), flags, dev)?
+                fs_type.mount(
+                    name.ok_or(SysError::EINVAL)?,
+                    Some(parent),
+                    flags,
+                    Some(dev),
+                    &data,
+                )?
+            }
+            "tmpfs" => {
+                let (parent, name) = split_parent_and_name(&target);
+                let parent = task.resolve_path(parent)?;
+                let fs_root = fs_type.mount(
+                    name.ok_or(SysError::EINVAL)?,
+                    Some(parent),
+                    flags,
+                    None,
+                    &data,
+                )?;
+                fs_root.set_state(DentryState::Sync);
+                fs_root
             }
             _ => return Err(SysError::EINVAL),
         };
@@ -475,21 +648,23 @@ impl Syscall<'_> {
 
     pub async fn sys_umount2(&self, target: UserReadPtr<u8>, flags: u32) -> SyscallResult {
         let task = self.task;
-        let mount_path = target.read_cstr(&task)?;
+        let mount_path = target.read_path(&task)?;
         let _flags = MountFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
         log::info!("[sys_umount2] umount path:{mount_path:?}");
+        let mount_root = task.resolve_path(&mount_path)?;
+        vfs::umount(mount_root).await?;
         Ok(0)
     }
 
     /// On success, the number of bytes read is returned. On end of directory, 0
     /// is returned. On error, -1 is returned, and errno is set to indicate
     /// the error.
-    pub fn sys_getdents64(&self, fd: usize, buf: usize, len: usize) -> SyscallResult {
+    pub async fn sys_getdents64(&self, fd: usize, buf: usize, len: usize) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
         // let mut writen_len = 0;
         let mut buf = UserWritePtr::<u8>::from(buf).into_mut_slice(&task, len)?;
-        file.read_dir(&mut buf)
+        file.read_dir(&mut buf).await
     }
 
     /// pipe() creates a pipe, a unidirectional data channel that can be used
@@ -548,21 +723,44 @@ impl Syscall<'_> {
         flags: i32,
     ) -> SyscallResult {
         let task = self.task;
-        let path = pathname.read_cstr(&task)?;
+        let path = pathname.read_path(&task)?;
         let dentry = task.at_helper(dirfd, &path, OpenFlags::O_NOFOLLOW)?;
         let parent = dentry.parent().expect("can not remove root directory");
-        let is_dir = dentry.inode()?.itype().is_dir();
+        let inode = dentry.inode()?;
+        let is_dir = inode.itype().is_dir();
         if flags == AT_REMOVEDIR && !is_dir {
             return Err(SysError::ENOTDIR);
         } else if flags != AT_REMOVEDIR && is_dir {
             return Err(SysError::EISDIR);
         }
-        parent.unlink(dentry.name()).map(|_| 0)
+        task.with_cred(|cred| cred.check_unlink(&parent.inode()?, &inode))?;
+        parent.unlink(&dentry.name()).map(|_| 0)
     }
 
     pub fn sys_ioctl(&self, fd: usize, cmd: usize, arg: usize) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
+        // the argument is a file descriptor, which the device can not resolve
+        if cmd == devfs::loop_dev::LOOP_SET_FD {
+            let backing = task.with_fd_table(|table| table.get_io_file(arg))?;
+            devfs::loop_dev::loop_set_fd(file, backing)?;
+            return Ok(0);
+        }
+        if matches!(
+            cmd,
+            devfs::tty::TIOCSCTTY | devfs::tty::TIOCNOTTY | devfs::tty::TIOCGSID
+        ) {
+            let inode = file.inode();
+            if !inode.is::<devfs::tty::TtyInode>() {
+                return Err(SysError::ENOTTY);
+            }
+            return self.tty_session_ioctl(inode, cmd, arg);
+        }
+        // the time is written by the kernel, which checks the buffer
+        if cmd == devfs::rtc::RTC_RD_TIME && file.inode().is::<devfs::rtc::RtcInode>() {
+            UserWritePtr::<devfs::rtc::RtcTime>::from(arg).write(task, devfs::rtc::rtc_time())?;
+            return Ok(0);
+        }
         within_sum(|| file.ioctl(cmd, arg))
     }
 
@@ -601,6 +799,8 @@ impl Syscall<'_> {
                 file.set_flags(flags.status());
                 Ok(0)
             }
+            // TODO: seals are not supported, as if no file allows sealing
+            FcntlOp::F_ADD_SEALS | FcntlOp::F_GET_SEALS => Err(SysError::EINVAL),
             _ => {
                 log::warn!("fcntl cmd: {op:?} not implemented");
                 Ok(0)
@@ -618,7 +818,7 @@ impl Syscall<'_> {
         iovcnt: usize,
     ) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|f| f.get_file(fd))?;
+        let file = task.with_fd_table(|f| f.get_io_file(fd))?;
         let mut offset = file.pos();
         let mut total_len = 0;
         let iovs = iov.into_slice(&task, iovcnt)?;
@@ -633,7 +833,7 @@ impl Syscall<'_> {
             total_len += write_len;
             offset += write_len;
         }
-        file.seek(SeekFrom::Current(total_len as i64))?;
+        file.set_pos(offset);
         Ok(total_len)
     }
 
@@ -647,7 +847,7 @@ impl Syscall<'_> {
         iovcnt: usize,
     ) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|f| f.get_file(fd))?;
+        let file = task.with_fd_table(|f| f.get_io_file(fd))?;
         let mut offset = file.pos();
         let mut total_len = 0;
         let iovs = iov.read_array(&task, iovcnt)?;
@@ -662,7 +862,7 @@ impl Syscall<'_> {
             total_len += write_len;
             offset += write_len;
         }
-        file.seek(SeekFrom::Current(total_len as i64))?;
+        file.set_pos(offset);
         Ok(total_len)
     }
 
@@ -707,8 +907,8 @@ impl Syscall<'_> {
             "[sys_sendfile] out_fd: {out_fd}, in_fd: {in_fd}, offset: {offset}, count: {count}"
         );
         let task = self.task;
-        let (in_file, out_file) =
-            task.with_fd_table(|table| Ok((table.get_file(in_fd)?, table.get_file(out_fd)?)))?;
+        let (in_file, out_file) = task
+            .with_fd_table(|table| Ok((table.get_io_file(in_fd)?, table.get_io_file(out_fd)?)))?;
         if !in_file.flags().readable() || !out_file.flags().writable() {
             return Err(SysError::EBADF);
         }
@@ -727,25 +927,29 @@ impl Syscall<'_> {
 
     /// access() checks whether the calling process can access the file
     /// pathname. If pathname is a symbolic link, it is dereferenced.
-    // TODO:
+    ///
+    /// The check is done using the calling process's real UID and GID, rather
+    /// than the effective IDs as is done when actually attempting an operation
+    /// (e.g., open(2)) on the file, unless `AT_EACCESS` is in flags.
     pub fn sys_faccessat(
         &self,
         dirfd: AtFd,
         pathname: UserReadPtr<u8>,
-        _mode: usize,
+        mode: usize,
         flags: i32,
     ) -> SyscallResult {
-        const AT_SYMLINK_NOFOLLOW: usize = 0x100;
-        const AT_EACCESS: usize = 0x200;
-        const AT_EMPTY_PATH: usize = 0x1000;
+        const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+        const AT_EACCESS: i32 = 0x200;
         let task = self.task;
-        let pathname = pathname.read_cstr(&task)?;
-        let dentry = if flags == AT_SYMLINK_NOFOLLOW as i32 {
+        let pathname = pathname.read_path(&task)?;
+        let access = AccessMode::from_bits(mode as u32).ok_or(SysError::EINVAL)?;
+        let dentry = if flags & AT_SYMLINK_NOFOLLOW != 0 {
             task.at_helper(dirfd, &pathname, OpenFlags::O_NOFOLLOW)?
         } else {
             task.at_helper(dirfd, &pathname, OpenFlags::empty())?
         };
-        dentry.open()?;
+        let inode = dentry.inode()?;
+        task.with_cred(|cred| cred.check_access(&inode, access, flags & AT_EACCESS == 0))?;
         Ok(0)
     }
 
@@ -757,13 +961,17 @@ impl Syscall<'_> {
     ///   bytes.
     /// + SEEK_END: The file offset is set to the size of the file plus offset
     ///   bytes.
+    /// + SEEK_DATA: The file offset is set to the next location greater than or
+    ///   equal to offset containing data.
+    /// + SEEK_HOLE: The file offset is set to the next hole greater than or
+    ///   equal to offset, or the end of the file if there is none.
     ///
     /// lseek() allows the file offset to be set beyond the end of the file (but
     /// this does not change the size of the file). If data is later written at
     /// this point, subsequent reads of the data in the gap (a "hole")
     /// return null bytes ('\0') until data is actually written into the
     /// gap.
-    pub fn sys_lseek(&self, fd: usize, offset: isize, whence: usize) -> SyscallResult {
+    pub async fn sys_lseek(&self, fd: usize, offset: isize, whence: usize) -> SyscallResult {
         #[derive(FromRepr)]
         #[repr(usize)]
         enum Whence {
@@ -774,23 +982,30 @@ impl Syscall<'_> {
             SeekHold = 4,
         }
         let task = self.task;
-        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
         let whence = Whence::from_repr(whence).ok_or(SysError::EINVAL)?;
 
         match whence {
-            Whence::SeekSet => file.seek(SeekFrom::Start(offset as u64)),
-            Whence::SeekCur => file.seek(SeekFrom::Current(offset as i64)),
-            Whence::SeekEnd => file.seek(SeekFrom::End(offset as i64)),
-            _ => todo!(),
+            Whence::SeekSet => file.seek(SeekFrom::Start(offset as u64)).await,
+            Whence::SeekCur => file.seek(SeekFrom::Current(offset as i64)).await,
+            Whence::SeekEnd => file.seek(SeekFrom::End(offset as i64)).await,
+            Whence::SeekData => file.seek(SeekFrom::Data(offset as u64)).await,
+            Whence::SeekHold => file.seek(SeekFrom::Hole(offset as u64)).await,
         }
     }
 
     /// umask() sets the calling process's file mode creation mask (umask) to
     /// mask & 0777 (i.e., only the file permission bits of mask are used),
     /// and returns the previous value of the mask.
-    // TODO:
-    pub fn sys_umask(&self, _mask: i32) -> SyscallResult {
-        Ok(0x777)
+    ///
+    /// The umask is used by open(2), mkdir(2), and other system calls that
+    /// create files to modify the permissions placed on newly created files or
+    /// directories. Specifically, permissions in the umask are turned off from
+    /// the mode argument.
+    pub fn sys_umask(&self, mask: u32) -> SyscallResult {
+        let mask = InodeMode::from_bits_truncate(mask & 0o777);
+        let old = self.task.set_umask(mask);
+        Ok(old.bits() as usize)
     }
 
     /// The utime() system call changes the access and modification times of the
@@ -819,7 +1034,7 @@ impl Syscall<'_> {
 
         let task = self.task;
         let inode = if pathname.not_null() {
-            let path = pathname.read_cstr(task)?;
+            let path = pathname.read_path(task)?;
             log::info!("[sys_utimensat] dirfd: {dirfd}, path: {path}");
             let flags = OpenFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
             let dentry = task.at_helper(dirfd, &path, flags)?;
@@ -862,7 +1077,7 @@ impl Syscall<'_> {
         Ok(0)
     }
 
-    pub fn sys_renameat2(
+    pub async fn sys_renameat2(
         &self,
         olddirfd: AtFd,
         oldpath: UserReadPtr<u8>,
@@ -872,36 +1087,45 @@ impl Syscall<'_> {
     ) -> SyscallResult {
         let task = self.task;
         let flags = RenameFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
-        let oldpath = oldpath.read_cstr(&task)?;
-        let newpath = newpath.read_cstr(&task)?;
+        let oldpath = oldpath.read_path(&task)?;
+        let newpath = newpath.read_path(&task)?;
         log::info!("[sys_renameat2] olddirfd:{olddirfd:?}, oldpath:{oldpath}, newdirfd:{newdirfd:?}, newpath:{newpath}, flags:{flags:?}");
 
         let old_dentry = task.at_helper(olddirfd, &oldpath, OpenFlags::O_NOFOLLOW)?;
         let new_dentry = task.at_helper(newdirfd, &newpath, OpenFlags::O_NOFOLLOW)?;
+        let old_parent = old_dentry.parent().ok_or(SysError::EBUSY)?;
+        let new_parent = new_dentry.parent().ok_or(SysError::EBUSY)?;
+        task.with_cred(|cred| {
+            cred.check_unlink(&old_parent.inode()?, &old_dentry.inode()?)?;
+            match new_dentry.inode() {
+                Ok(new_inode) => cred.check_unlink(&new_parent.inode()?, &new_inode),
+                Err(_) => cred.check_access(
+                    &new_parent.inode()?,
+                    AccessMode::WRITE | AccessMode::EXEC,
+                    false,
+                ),
+            }
+        })?;
 
         // TODO: currently don't care about `RENAME_WHITEOUT`
-        old_dentry.rename_to(&new_dentry, flags).map(|_| 0)
+        old_dentry.rename_to(&new_dentry, flags).await.map(|_| 0)
     }
 
     pub fn sys_statfs(&self, path: UserReadPtr<u8>, buf: UserWritePtr<StatFs>) -> SyscallResult {
         let task = self.task;
-        let _path = path.read_cstr(task)?;
-        let stfs = StatFs {
-            f_type: 0x2011BAB0 as i64,
-            f_bsize: BLOCK_SIZE as i64,
-            f_blocks: 1 << 27,
-            f_bfree: 1 << 26,
-            f_bavail: 1 << 20,
-            f_files: 1 << 10,
-            f_ffree: 1 << 9,
-            f_fsid: [0; 2],
-            f_namelen: 1 << 8,
-            f_frsize: 1 << 9,
-            f_flags: 1 << 1 as i64,
-            f_spare: [0; 4],
-        };
-        // TODO: find the target fs
-        buf.write(task, stfs)?;
+        let path = path.read_path(task)?;
+        let dentry = task.resolve_path(&path)?;
+        if dentry.is_negetive() {
+            return Err(SysError::ENOENT);
+        }
+        buf.write(task, dentry.super_block().stat_fs()?)?;
+        Ok(0)
+    }
+
+    pub fn sys_fstatfs(&self, fd: usize, buf: UserWritePtr<StatFs>) -> SyscallResult {
+        let task = self.task;
+        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        buf.write(task, file.super_block().stat_fs()?)?;
         Ok(0)
     }
 
@@ -913,7 +1137,7 @@ impl Syscall<'_> {
         bufsiz: usize,
     ) -> SyscallResult {
         let task = self.task;
-        let path = pathname.read_cstr(task)?;
+        let path = pathname.read_path(task)?;
         log::info!(
             "[sys_readlinkat] dirfd:{dirfd}, path:{path}, buf:{:x}, bufsiz: {bufsiz}",
             buf.as_usize()
@@ -929,17 +1153,107 @@ impl Syscall<'_> {
 
     pub async fn sys_ftruncate(&self, fd: usize, length: u64) -> SyscallResult {
         let task = self.task;
-        let file = task.with_fd_table(|table| table.get_file(fd))?;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
         log::warn!(
             "[sys_ftruncate] file path {}, length:{length}",
             file.dentry().path()
         );
-        file.inode().truncate(length as usize)
+        file.inode().truncate(length as usize).await
     }
 
-    /// Modify the permissions of a file or directory relative to a certain
-    /// directory or location
-    pub fn sys_fchmodat(&self) -> SyscallResult {
+    /// fchmod() changes the mode of the file referred to by the open file
+    /// descriptor fd.
+    pub fn sys_fchmod(&self, fd: usize, mode: u32) -> SyscallResult {
+        let task = self.task;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
+        self.chmod(&file.dentry(), mode)
+    }
+
+    /// fchmodat() changes the mode of the file specified by pathname relative
+    /// to dirfd, see `at_helper`.
+    pub fn sys_fchmodat(&self, dirfd: AtFd, pathname: UserReadPtr<u8>, mode: u32) -> SyscallResult {
+        let task = self.task;
+        let pathname = pathname.read_path(&task)?;
+        let dentry = task.at_helper(dirfd, &pathname, OpenFlags::empty())?;
+        self.chmod(&dentry, mode)
+    }
+
+    fn chmod(&self, dentry: &Arc<dyn Dentry>, mode: u32) -> SyscallResult {
+        let task = self.task;
+        let inode = dentry.inode()?;
+        let mode = InodeMode::from_bits_truncate(mode);
+        log::info!("[sys_fchmodat] path:{}, mode:{mode:?}", dentry.path());
+        let mode = task.with_cred(|cred| cred.check_chmod(&inode, mode))?;
+        dentry.set_mode(mode)?;
+        inode.meta().inner.lock().ctime = TimeSpec::from(get_time_duration());
+        Ok(0)
+    }
+
+    /// fchown() changes the ownership of the file referred to by the open file
+    /// descriptor fd. If the owner or group is specified as -1, then that ID is
+    /// not changed.
+    pub fn sys_fchown(&self, fd: usize, owner: u32, group: u32) -> SyscallResult {
+        let task = self.task;
+        let file = task.with_fd_table(|table| table.get_io_file(fd))?;
+        self.chown(&file.dentry(), owner, group)
+    }
+
+    /// fchownat() changes the ownership of the file specified by pathname
+    /// relative to dirfd. Symbolic links are not dereferenced with
+    /// `AT_SYMLINK_NOFOLLOW`, which is how lchown() is implemented, and the
+    /// file referred to by dirfd is changed if pathname is empty with
+    /// `AT_EMPTY_PATH`.
+    pub fn sys_fchownat(
+        &self,
+        dirfd: AtFd,
+        pathname: UserReadPtr<u8>,
+        owner: u32,
+        group: u32,
+        flags: i32,
+    ) -> SyscallResult {
+        const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+        const AT_EMPTY_PATH: i32 = 0x1000;
+        let task = self.task;
+        let pathname = pathname.read_path(&task)?;
+        let dentry = if pathname.is_empty() && flags & AT_EMPTY_PATH != 0 {
+            match dirfd {
+                AtFd::FdCwd => task.cwd(),
+                AtFd::Normal(fd) => task.with_fd_table(|table| table.get_file(fd))?.dentry(),
+            }
+        } else if flags & AT_SYMLINK_NOFOLLOW != 0 {
+            task.at_helper(dirfd, &pathname, OpenFlags::O_NOFOLLOW)?
+        } else {
+            task.at_helper(dirfd, &pathname, OpenFlags::empty())?
+        };
+        self.chown(&dentry, owner, group)
+    }
+
+    fn chown(&self, dentry: &Arc<dyn Dentry>, owner: u32, group: u32) -> SyscallResult {
+        let task = self.task;
+        let inode = dentry.inode()?;
+        log::info!(
+            "[sys_fchownat] path:{}, owner:{owner}, group:{group}",
+            dentry.path()
+        );
+        let (privileged, (uid, gid)) = task.with_cred(|cred| {
+            cred.check_chown(&inode, owner, group)
+                .map(|owner| (cred.user.fs == 0, owner))
+        })?;
+        dentry.set_owner(uid, gid)?;
+        // The set-user-ID bit is cleared when an unprivileged user changes the
+        // owner of an executable file, and so is the set-group-ID bit, unless
+        // the group execute bit is off, which marks mandatory locking instead.
+        let mode = inode.mode();
+        if !privileged && !inode.itype().is_dir() {
+            let mut clear = InodeMode::SET_UID;
+            if mode.contains(InodeMode::GROUP_EXEC) {
+                clear |= InodeMode::SET_GID;
+            }
+            if mode.intersects(clear) {
+                dentry.set_mode(mode - clear)?;
+            }
+        }
+        inode.meta().inner.lock().ctime = TimeSpec::from(get_time_duration());
         Ok(0)
     }
 
@@ -952,10 +1266,10 @@ impl Syscall<'_> {
         linkpath: UserReadPtr<u8>,
     ) -> SyscallResult {
         let task = self.task;
-        let linkpath = linkpath.read_cstr(task)?;
-        let target = target.read_cstr(task)?;
+        let linkpath = linkpath.read_path(task)?;
+        let target = target.read_path(task)?;
         let dentry = task.at_helper(newdirfd, &linkpath, OpenFlags::O_NOFOLLOW)?;
-        dentry.parent().unwrap().symlink(dentry.name(), &target)?;
+        dentry.parent().unwrap().symlink(&dentry.name(), &target)?;
         Ok(0)
     }
 
@@ -971,8 +1285,8 @@ impl Syscall<'_> {
     ) -> SyscallResult {
         let task = self.task;
         let flags = OpenFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
-        let oldpath = oldpath.read_cstr(task)?;
-        let newpath = newpath.read_cstr(task)?;
+        let oldpath = oldpath.read_path(task)?;
+        let newpath = newpath.read_path(task)?;
         let old_dentry = task.at_helper(olddirfd, &oldpath, flags)?;
         let new_dentry = task.at_helper(newdirfd, &newpath, flags)?;
         old_dentry.link(&new_dentry)?;
@@ -989,8 +1303,8 @@ impl Syscall<'_> {
         flags: usize,
     ) -> SyscallResult {
         let task = self.task;
-        let file_in = task.with_fd_table(|table| table.get_file(fd_in))?;
-        let file_out = task.with_fd_table(|table| table.get_file(fd_out))?;
+        let file_in = task.with_fd_table(|table| table.get_io_file(fd_in))?;
+        let file_out = task.with_fd_table(|table| table.get_io_file(fd_out))?;
         let file_in_type = file_in.inode().itype();
         let file_out_type = file_out.inode().itype();
 
@@ -1075,4 +1389,34 @@ impl Syscall<'_> {
 
         Ok(out_len)
     }
+
+    /// Write all dirty data of filesystems back to disks.
+    pub async fn sys_sync(&self) -> SyscallResult {
+        vfs::sync_all().await;
+        Ok(0)
+    }
+
+    /// memfd_create() creates an anonymous file and returns a file descriptor
+    /// that refers to it. The file behaves like a regular file, and so can be
+    /// modified, truncated, memory-mapped, and so on. However, unlike a
+    /// regular file, it lives in RAM and has a volatile backing storage.
+    pub fn sys_memfd_create(&self, name: UserReadPtr<u8>, flags: u32) -> SyscallResult {
+        let task = self.task;
+        let flags = MemfdFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
+        if flags.contains(MemfdFlags::MFD_HUGETLB) {
+            return Err(SysError::EINVAL);
+        }
+        let name = match name.read_cstr_with_max(&task, MFD_NAME_MAX + 1) {
+            Err(SysError::ENAMETOOLONG) => return Err(SysError::EINVAL),
+            name => name?,
+        };
+        log::info!("[sys_memfd_create] name: {name}, flags: {flags:?}");
+        let file = vfs::tmpfs::memfd_create(&name)?;
+        let mut open_flags = OpenFlags::O_RDWR;
+        if flags.contains(MemfdFlags::MFD_CLOEXEC) {
+            open_flags |= OpenFlags::O_CLOEXEC;
+        }
+        file.set_flags(open_flags);
+        task.with_mut_fd_table(|table| table.alloc(file, open_flags))
+    }
 }
@@ -1,13 +1,22 @@
+use alloc::sync::Arc;
+use core::{sync::atomic::Ordering, time::Duration};
+
+use arch::time::get_time_duration;
 use async_utils::suspend_now;
 use bitflags::Flags;
 use memory::VirtAddr;
+use page::Page;
 use systype::{SysError, SyscallResult};
-use time::timespec::TimeSpec;
+use time::{clock_deviation, timespec::TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME};
 
 use super::Syscall;
 use crate::{
-    ipc::futex::{futex_manager, FutexHashKey, FutexOp, FutexWaiter, RobustListHead},
+    ipc::futex::{
+        futex_manager, FutexHashKey, FutexOp, FutexWaiter, RobustListHead, FUTEX_BITSET_MATCH_ANY,
+        FUTEX_OWNER_DIED, FUTEX_TID_MASK, FUTEX_WAITERS,
+    },
     mm::{FutexAddr, UserReadPtr, UserWritePtr},
+    task::TASK_MANAGER,
 };
 
 impl Syscall<'_> {
@@ -39,15 +48,12 @@ impl Syscall<'_> {
         uaddr.check(&task)?;
         let is_private = futex_op.contains(FutexOp::Private);
         futex_op.remove(FutexOp::Private);
-        let key = if is_private {
-            FutexHashKey::Private {
-                mm: task.raw_mm_pointer(),
-                vaddr: uaddr.addr,
-            }
-        } else {
-            let paddr = VirtAddr::from(uaddr.raw()).to_paddr();
-            FutexHashKey::Shared { paddr }
-        };
+        let clock_realtime = futex_op.contains(FutexOp::ClockRealtime);
+        futex_op.remove(FutexOp::ClockRealtime);
+        if clock_realtime && futex_op != FutexOp::WaitBitset {
+            return Err(SysError::ENOSYS);
+        }
+        let key = self.futex_key(uaddr.addr, is_private);
         log::info!(
             "[sys_futex] {:?} uaddr:{:#x} key:{:?}",
             futex_op,
@@ -57,86 +63,326 @@ impl Syscall<'_> {
 
         match futex_op {
             FutexOp::Wait => {
-                let res = uaddr.read();
-                if res != val {
-                    log::info!(
-                        "[futex_wait] value in {} addr is {res} but expect {val}",
-                        uaddr.addr.0
-                    );
-                    return Err(SysError::EAGAIN);
-                }
-                futex_manager().add_waiter(
-                    &key,
-                    FutexWaiter {
-                        tid: task.tid(),
-                        waker: task.waker().clone().unwrap(),
-                    },
-                );
-                task.set_interruptable();
-                let wake_up_signal = !*task.sig_mask_ref();
-                task.set_wake_up_signal(wake_up_signal);
-                if timeout == 0 {
-                    suspend_now().await;
+                // the timeout of FUTEX_WAIT is relative and measured against
+                // CLOCK_MONOTONIC
+                let deadline = if timeout == 0 {
+                    None
                 } else {
-                    let timeout = UserReadPtr::<TimeSpec>::from(timeout as usize).read(&task)?;
-                    log::info!("[futex_wait] waiting for {:?}", timeout);
+                    let timeout = UserReadPtr::<TimeSpec>::from(timeout).read(&task)?;
                     if !timeout.is_valid() {
                         return Err(SysError::EINVAL);
                     }
-                    let rem = task.suspend_timeout(timeout.into()).await;
-                    if rem.is_zero() {
-                        futex_manager().remove_waiter(&key, task.tid());
-                    }
-                }
-                if task.with_sig_pending(|p| p.has_expect_signals(wake_up_signal)) {
-                    log::info!("[sys_futex] Woken by signal");
-                    futex_manager().remove_waiter(&key, task.tid());
-                    return Err(SysError::EINTR);
+                    Some(get_time_duration() + Duration::from(timeout))
+                };
+                self.futex_wait(&uaddr, key, val, deadline, FUTEX_BITSET_MATCH_ANY)
+                    .await
+            }
+            FutexOp::WaitBitset => {
+                if val3 == 0 {
+                    return Err(SysError::EINVAL);
                 }
-                log::info!("[sys_futex] I was woken");
-                task.set_running();
-                Ok(0)
+                // the timeout of FUTEX_WAIT_BITSET is absolute and measured
+                // against the clock selected by FUTEX_CLOCK_REALTIME
+                let deadline = if timeout == 0 {
+                    None
+                } else {
+                    let timeout = UserReadPtr::<TimeSpec>::from(timeout).read(&task)?;
+                    if !timeout.is_valid() {
+                        return Err(SysError::EINVAL);
+                    }
+                    let clockid = if clock_realtime {
+                        CLOCK_REALTIME
+                    } else {
+                        CLOCK_MONOTONIC
+                    };
+                    // the clock may have been adjusted by clock_settime(2), so
+                    // convert it back to the time of the timer
+                    let deviation = clock_deviation(clockid);
+                    Some(Duration::from(timeout).saturating_sub(deviation))
+                };
+                self.futex_wait(&uaddr, key, val, deadline, val3).await
             }
             FutexOp::Wake => {
-                let n_wake = futex_manager().wake(&key, val)?;
+                let n_wake = futex_manager().wake(&key, val, FUTEX_BITSET_MATCH_ANY)?;
                 return Ok(n_wake);
             }
-            FutexOp::Requeue => {
-                let n_wake = futex_manager().wake(&key, val)?;
-                let new_key = if is_private {
-                    FutexHashKey::Private {
-                        mm: task.raw_mm_pointer(),
-                        vaddr: uaddr2.into(),
-                    }
+            FutexOp::WakeBitset => {
+                if val3 == 0 {
+                    return Err(SysError::EINVAL);
+                }
+                let n_wake = futex_manager().wake(&key, val, val3)?;
+                Ok(n_wake)
+            }
+            FutexOp::LockPi => {
+                // the timeout of FUTEX_LOCK_PI is absolute and measured against
+                // CLOCK_REALTIME
+                let deadline = if timeout == 0 {
+                    None
                 } else {
-                    let paddr = VirtAddr::from(uaddr2).to_paddr();
-                    FutexHashKey::Shared { paddr }
+                    let timeout = UserReadPtr::<TimeSpec>::from(timeout).read(&task)?;
+                    if !timeout.is_valid() {
+                        return Err(SysError::EINVAL);
+                    }
+                    let deviation = clock_deviation(CLOCK_REALTIME);
+                    Some(Duration::from(timeout).saturating_sub(deviation))
                 };
-                futex_manager().requeue_waiters(key, new_key, timeout)?;
-                Ok(n_wake)
+                self.futex_lock_pi(&uaddr, key, deadline, false).await
+            }
+            FutexOp::TrylockPi => self.futex_lock_pi(&uaddr, key, None, true).await,
+            FutexOp::UnlockPi => self.futex_unlock_pi(&uaddr, key),
+            FutexOp::Requeue => {
+                self.futex_requeue(key, val, VirtAddr::from(uaddr2), is_private, timeout)
             }
             FutexOp::CmpRequeue => {
                 if uaddr.read() as u32 != val3 {
                     return Err(SysError::EAGAIN);
                 }
-                let n_wake = futex_manager().wake(&key, val)?;
-                let new_key = if is_private {
-                    FutexHashKey::Private {
-                        mm: task.raw_mm_pointer(),
-                        vaddr: uaddr2.into(),
-                    }
-                } else {
-                    let paddr = VirtAddr::from(uaddr2).to_paddr();
-                    FutexHashKey::Shared { paddr }
-                };
-                futex_manager().requeue_waiters(key, new_key, timeout)?;
-                Ok(n_wake)
+                self.futex_requeue(key, val, VirtAddr::from(uaddr2), is_private, timeout)
             }
 
             _ => panic!("unimplemented futexop {:?}", futex_op),
         }
     }
 
+    /// Futex words in private mappings are keyed by the address space and the
+    /// virtual address, while shared ones are keyed by the physical address, so
+    /// that the same page mapped at different addresses in different processes
+    /// matches.
+    fn futex_key(&self, addr: VirtAddr, is_private: bool) -> FutexHashKey {
+        if is_private {
+            FutexHashKey::Private {
+                mm: self.task.raw_mm_pointer(),
+                vaddr: addr,
+            }
+        } else {
+            FutexHashKey::Shared {
+                paddr: addr.to_paddr(),
+            }
+        }
+    }
+
+    /// Returns the page holding the futex word if `key` is shared, which
+    /// should be pinned while there are waiters on it.
+    fn futex_page(&self, key: &FutexHashKey, addr: VirtAddr) -> Option<Arc<Page>> {
+        match key {
+            FutexHashKey::Shared { .. } => self.task.with_memory_space(|m| m.get_page(addr)),
+            FutexHashKey::Private { .. } => None,
+        }
+    }
+
+    /// Wakes at most `val` waiters on `key` and moves at most `n_requeue` of
+    /// the remaining waiters to the futex at `uaddr2`.
+    fn futex_requeue(
+        &self,
+        key: FutexHashKey,
+        val: u32,
+        uaddr2: VirtAddr,
+        is_private: bool,
+        n_requeue: usize,
+    ) -> SyscallResult {
+        let new_key = self.futex_key(uaddr2, is_private);
+        let page = self.futex_page(&new_key, uaddr2);
+        let mut manager = futex_manager();
+        let n_wake = manager.wake(&key, val, FUTEX_BITSET_MATCH_ANY)?;
+        manager.requeue_waiters(key, new_key, n_requeue)?;
+        if let Some(page) = page {
+            manager.pin_page(&new_key, page);
+        }
+        Ok(n_wake)
+    }
+
+    /// Sleeps on the futex `key` if the futex word still contains `val`, until
+    /// woken by a wake operation whose bitset intersects with `bitset`, or
+    /// until the absolute `deadline` measured in the time of the timer.
+    async fn futex_wait(
+        &self,
+        uaddr: &FutexAddr,
+        key: FutexHashKey,
+        val: u32,
+        deadline: Option<Duration>,
+        bitset: u32,
+    ) -> SyscallResult {
+        let task = self.task;
+        let page = self.futex_page(&key, uaddr.addr);
+        // NOTE: the futex word is checked with the manager locked, or a waker which
+        // changes the word and then wakes, e.g. a thread exiting with
+        // clear_child_tid, may slip in before the waiter is added and be missed
+        let mut manager = futex_manager();
+        let res = uaddr.read();
+        if res != val {
+            log::info!(
+                "[futex_wait] value in {} addr is {res} but expect {val}",
+                uaddr.addr.0
+            );
+            return Err(SysError::EAGAIN);
+        }
+        let now = get_time_duration();
+        if deadline.is_some_and(|deadline| deadline <= now) {
+            log::info!("[futex_wait] deadline {:?} has passed", deadline);
+            return Err(SysError::ETIMEDOUT);
+        }
+        manager.add_waiter(
+            &key,
+            FutexWaiter {
+                tid: task.tid(),
+                waker: task.waker().clone().unwrap(),
+                bitset,
+            },
+        );
+        if let Some(page) = page {
+            manager.pin_page(&key, page);
+        }
+        drop(manager);
+        task.set_interruptable();
+        let wake_up_signal = !*task.sig_mask_ref();
+        task.set_wake_up_signal(wake_up_signal);
+        if let Some(deadline) = deadline {
+            log::info!("[futex_wait] waiting until {:?}", deadline);
+            let rem = task.suspend_timeout(deadline - now).await;
+            if rem.is_zero() {
+                futex_manager().remove_waiter(&key, task.tid());
+                task.set_running();
+                return Err(SysError::ETIMEDOUT);
+            }
+        } else {
+            suspend_now().await;
+        }
+        if task.with_sig_pending(|p| p.has_expect_signals(wake_up_signal)) {
+            log::info!("[sys_futex] Woken by signal");
+            futex_manager().remove_waiter(&key, task.tid());
+            task.set_running();
+            return Err(SysError::EINTR);
+        }
+        log::info!("[sys_futex] I was woken");
+        task.set_running();
+        Ok(0)
+    }
+
+    /// Acquires the priority-inheritance futex whose word holds the tid of the
+    /// owner. Unless `try_only` is set, blocks in the PI wait queue of `key`
+    /// while the futex is owned by another task, and the owner is boosted into
+    /// the prior queue until there are no waiters.
+    async fn futex_lock_pi(
+        &self,
+        uaddr: &FutexAddr,
+        key: FutexHashKey,
+        deadline: Option<Duration>,
+        try_only: bool,
+    ) -> SyscallResult {
+        let task = self.task;
+        uaddr.check_write(&task)?;
+        let tid = task.tid() as u32;
+        let word = uaddr.word();
+        let page = self.futex_page(&key, uaddr.addr);
+        loop {
+            let old = word.load(Ordering::Acquire);
+            let owner = old & FUTEX_TID_MASK;
+            if owner == tid {
+                return Err(SysError::EDEADLK);
+            }
+            // NOTE: look up the owner before locking the futex manager, the
+            // word is checked again by the cmpxchg below
+            let owner_task = match owner {
+                0 => None,
+                owner => TASK_MANAGER
+                    .get(owner as usize)
+                    .filter(|t| !t.is_terminated() && !t.is_zombie()),
+            };
+            let mut manager = futex_manager();
+            let Some(owner_task) = owner_task else {
+                // the futex is free, or its owner died without unlocking it
+                let mut new = tid | (old & FUTEX_OWNER_DIED);
+                if owner != 0 {
+                    new |= FUTEX_OWNER_DIED;
+                }
+                if manager.has_waiters(&key) {
+                    new |= FUTEX_WAITERS;
+                }
+                if word
+                    .compare_exchange(old, new, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    continue;
+                }
+                manager.set_pi_owner(&key, task.tid(), task.sched_attr().clone());
+                return Ok(0);
+            };
+            if try_only {
+                return Err(SysError::EAGAIN);
+            }
+            // the owner has to unlock it through FUTEX_UNLOCK_PI from now on
+            if word
+                .compare_exchange(
+                    old,
+                    old | FUTEX_WAITERS,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_err()
+            {
+                continue;
+            }
+            manager.add_waiter(
+                &key,
+                FutexWaiter {
+                    tid: task.tid(),
+                    waker: task.waker().clone().unwrap(),
+                    bitset: FUTEX_BITSET_MATCH_ANY,
+                },
+            );
+            if let Some(page) = page {
+                manager.pin_page(&key, page);
+            }
+            manager.set_pi_owner(&key, owner as usize, owner_task.sched_attr().clone());
+            break;
+        }
+
+        task.set_interruptable();
+        let wake_up_signal = !*task.sig_mask_ref();
+        task.set_wake_up_signal(wake_up_signal);
+        let ret = loop {
+            match deadline {
+                Some(deadline) => {
+                    let now = get_time_duration();
+                    if deadline > now {
+                        task.suspend_timeout(deadline - now).await;
+                    }
+                }
+                None => suspend_now().await,
+            }
+            let mut manager = futex_manager();
+            // the futex is handed over by the owner, which may happen right
+            // before timeout or signal
+            if word.load(Ordering::Acquire) & FUTEX_TID_MASK == tid {
+                manager.set_pi_owner(&key, task.tid(), task.sched_attr().clone());
+                break Ok(0);
+            }
+            if task.with_sig_pending(|p| p.has_expect_signals(wake_up_signal)) {
+                manager.remove_pi_waiter(&key, word, task.tid());
+                break Err(SysError::EINTR);
+            }
+            if deadline.is_some_and(|deadline| deadline <= get_time_duration()) {
+                manager.remove_pi_waiter(&key, word, task.tid());
+                break Err(SysError::ETIMEDOUT);
+            }
+        };
+        task.set_running();
+        ret
+    }
+
+    /// Releases the priority-inheritance futex owned by the caller, and hands
+    /// it over to the top waiter if there is any.
+    fn futex_unlock_pi(&self, uaddr: &FutexAddr, key: FutexHashKey) -> SyscallResult {
+        uaddr.check_write(&self.task)?;
+        let word = uaddr.word();
+        let mut manager = futex_manager();
+        if word.load(Ordering::Acquire) & FUTEX_TID_MASK != self.task.tid() as u32 {
+            return Err(SysError::EPERM);
+        }
+        manager.handover_pi(&key, word, 0);
+        Ok(0)
+    }
+
     /// actually this syscall has no actual effect
     pub fn sys_get_robust_list(
         &self,
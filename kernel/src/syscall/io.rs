@@ -4,21 +4,22 @@ use core::{
     mem::{self, size_of},
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
-use async_utils::{Select2Futures, SelectOutput};
+use arch::time::get_time_duration;
+use async_utils::{dyn_future, suspend_now, AnyFuture};
 use memory::VirtAddr;
 use signal::SigSet;
 use systype::{SysError, SyscallResult};
 use time::timespec::TimeSpec;
-use timer::timelimited_task::{TimeLimitedTaskFuture, TimeLimitedTaskOutput};
 use vfs::fd_table::Fd;
 use vfs_core::{File, PollEvents};
 
 use super::Syscall;
 use crate::{
     mm::{UserRdWrPtr, UserReadPtr, UserSlice},
-    task::signal::IntrBySignalFuture,
+    task::Task,
 };
 
 #[derive(Debug, Copy, Clone)]
@@ -78,7 +79,15 @@ impl FdSet {
 }
 
 pub struct PPollFuture {
-    polls: Vec<(PollEvents, Arc<dyn File>)>,
+    futures: AnyFuture<'static, PollEvents>,
+}
+
+impl PPollFuture {
+    fn new(polls: Vec<(PollEvents, Arc<dyn File>)>) -> Self {
+        Self {
+            futures: poll_futures(polls.into_iter()),
+        }
+    }
 }
 
 impl Future for PPollFuture {
@@ -87,29 +96,22 @@ impl Future for PPollFuture {
     /// Return vec of futures that are ready. Return `Poll::Pending` if
     /// no futures are ready.
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        let mut ret_vec = Vec::new();
-        for (i, (events, file)) in this.polls.iter().enumerate() {
-            let result = unsafe { Pin::new_unchecked(&mut file.poll(*events)).poll(cx) };
-            match result {
-                Poll::Pending => unreachable!(),
-                Poll::Ready(result) => {
-                    if !result.is_empty() {
-                        ret_vec.push((i, result))
-                    }
-                }
-            }
-        }
-        if ret_vec.len() > 0 {
-            Poll::Ready(ret_vec)
-        } else {
-            Poll::Pending
-        }
+        self.get_mut().futures.poll_all(cx)
     }
 }
 
 pub struct PSelectFuture {
-    polls: Vec<(Fd, PollEvents, Arc<dyn File>)>,
+    fds: Vec<Fd>,
+    futures: AnyFuture<'static, PollEvents>,
+}
+
+impl PSelectFuture {
+    fn new(polls: Vec<(Fd, PollEvents, Arc<dyn File>)>) -> Self {
+        Self {
+            fds: polls.iter().map(|(fd, ..)| *fd).collect(),
+            futures: poll_futures(polls.into_iter().map(|(_, events, file)| (events, file))),
+        }
+    }
 }
 
 impl Future for PSelectFuture {
@@ -118,23 +120,60 @@ impl Future for PSelectFuture {
     /// Return vec of futures that are ready. Return `Poll::Pending` if
     /// no futures are ready.
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        let mut ret_vec = Vec::with_capacity(this.polls.len());
-        for (fd, events, file) in this.polls.iter() {
-            let result = unsafe { Pin::new_unchecked(&mut file.poll(*events)).poll(cx) };
-            match result {
-                Poll::Pending => unreachable!(),
-                Poll::Ready(result) => {
-                    if !result.is_empty() {
-                        ret_vec.push((*fd, result))
-                    }
-                }
-            }
+        let this = self.get_mut();
+        this.futures.poll_all(cx).map(|ret_vec| {
+            ret_vec
+                .into_iter()
+                .map(|(i, events)| (this.fds[i], events))
+                .collect()
+        })
+    }
+}
+
+/// Create one future for each file, which are kept pinned across polls, so
+/// that every poll checks all files without allocating.
+fn poll_futures(
+    polls: impl Iterator<Item = (PollEvents, Arc<dyn File>)>,
+) -> AnyFuture<'static, PollEvents> {
+    let mut futures = AnyFuture::new();
+    for (events, file) in polls {
+        futures.push(dyn_future(wait_file(file, events)));
+    }
+    futures
+}
+
+/// Wait until any of `events` happens on `file`. Polling a file registers the
+/// waker and is never pending, so the future is suspended on empty events
+/// until it is woken up.
+async fn wait_file(file: Arc<dyn File>, events: PollEvents) -> PollEvents {
+    loop {
+        let ret = file.poll(events).await;
+        if !ret.is_empty() {
+            return ret;
         }
-        if ret_vec.len() > 0 {
-            Poll::Ready(ret_vec)
-        } else {
-            Poll::Pending
+        suspend_now().await;
+    }
+}
+
+/// Restores the signal mask replaced by `ppoll` and `pselect6` when dropped, so
+/// that it is restored on every return path, including faults on copying
+/// results back.
+struct SigMaskGuard<'a> {
+    task: &'a Arc<Task>,
+    old_mask: Option<SigSet>,
+}
+
+impl<'a> SigMaskGuard<'a> {
+    fn new(task: &'a Arc<Task>, new_mask: Option<SigSet>) -> Self {
+        let old_mask = new_mask.map(|mask| mem::replace(task.sig_mask(), mask));
+        Self { task, old_mask }
+    }
+}
+
+impl Drop for SigMaskGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mask) = self.old_mask.take() {
+            *self.task.sig_mask() = mask;
         }
     }
 }
@@ -152,11 +191,12 @@ impl Syscall<'_> {
         let task = self.task;
         let fds_va: VirtAddr = fds.as_usize().into();
         let mut poll_fds = fds.read_array(&task, nfds)?;
-        let timeout = if timeout.is_null() {
+        let timeout: Option<Duration> = if timeout.is_null() {
             None
         } else {
             Some(timeout.read(&task)?.into())
         };
+        let deadline = timeout.map(|timeout| get_time_duration() + timeout);
 
         let new_mask = if sigmask.is_null() {
             None
@@ -175,46 +215,19 @@ impl Syscall<'_> {
             polls.push((events, file));
         }
 
-        let old_mask = if let Some(mask) = new_mask {
-            Some(mem::replace(task.sig_mask(), mask))
-        } else {
-            None
+        let _mask_guard = SigMaskGuard::new(task, new_mask);
+        let poll_future = PPollFuture::new(polls);
+        let Some(ret_vec) = task.wait_interruptible(poll_future, deadline).await? else {
+            log::debug!("[sys_ppoll]: timeout");
+            return Ok(0);
         };
 
-        let poll_future = PPollFuture { polls };
-
-        let mut poll_fds_slice = unsafe { UserSlice::<PollFd>::new_unchecked(fds_va, nfds) };
-        task.set_interruptable();
-        task.set_wake_up_signal(!*task.sig_mask_ref());
-        let ret_vec = if let Some(timeout) = timeout {
-            match TimeLimitedTaskFuture::new(timeout, poll_future).await {
-                TimeLimitedTaskOutput::Ok(ret_vec) => ret_vec,
-                TimeLimitedTaskOutput::TimeOut => {
-                    log::debug!("[sys_ppoll]: timeout");
-                    return Ok(0);
-                }
-            }
-        } else {
-            let intr_future = IntrBySignalFuture {
-                task: task.clone(),
-                mask: *task.sig_mask_ref(),
-            };
-            match Select2Futures::new(poll_future, intr_future).await {
-                SelectOutput::Output1(ret_vec) => ret_vec,
-                SelectOutput::Output2(_) => return Err(SysError::EINTR),
-            }
-        };
-        task.set_running();
-
         let ret = ret_vec.len();
         for (i, result) in ret_vec {
             poll_fds[i].revents |= result
         }
+        let mut poll_fds_slice = unsafe { UserSlice::<PollFd>::new_unchecked(fds_va, nfds) };
         poll_fds_slice.copy_from_slice(&poll_fds);
-
-        if let Some(old_mask) = old_mask {
-            *task.sig_mask() = old_mask;
-        }
         Ok(ret)
     }
 
@@ -223,6 +236,8 @@ impl Syscall<'_> {
     /// of I/O operation (e.g., input possible). A file descriptor is considered
     /// ready if it is possible to perform a corresponding I/O operation (e.g.,
     /// read(2), or a sufficiently small write(2)) without blocking.
+    ///
+    /// Like Linux, `timeout` is updated with the remaining time on return.
     // TODO: execptfds is not used
     pub async fn sys_pselect6(
         &self,
@@ -230,7 +245,7 @@ impl Syscall<'_> {
         readfds: UserRdWrPtr<FdSet>,
         writefds: UserRdWrPtr<FdSet>,
         exceptfds: UserRdWrPtr<FdSet>,
-        timeout: UserReadPtr<TimeSpec>,
+        timeout: UserRdWrPtr<TimeSpec>,
         sigmask: UserReadPtr<SigSet>,
     ) -> SyscallResult {
         let task = self.task;
@@ -241,8 +256,11 @@ impl Syscall<'_> {
         let timeout = if timeout.is_null() {
             None
         } else {
-            Some(timeout.read(task)?.into())
+            Some(timeout.into_mut(task)?)
         };
+        let deadline = timeout
+            .as_ref()
+            .map(|timeout| get_time_duration() + Duration::from(**timeout));
         let new_mask = if sigmask.is_null() {
             None
         } else {
@@ -250,7 +268,6 @@ impl Syscall<'_> {
         };
 
         log::info!("[sys_pselect6] nfds:{nfds}, readfds:{readfds}, writefds:{writefds}, exceptfds:{exceptfds}, timeout:{timeout:?}, sigmask:{new_mask:?}");
-
         let mut readfds = if readfds.is_null() {
             None
         } else {
@@ -294,60 +311,25 @@ impl Syscall<'_> {
             }
         }
 
-        let old_mask = if let Some(mask) = new_mask {
-            Some(mem::replace(task.sig_mask(), mask))
-        } else {
-            None
-        };
-        task.set_interruptable();
-        task.set_wake_up_signal(!*task.sig_mask_ref());
-        let intr_future = IntrBySignalFuture {
-            task: task.clone(),
-            mask: *task.sig_mask_ref(),
-        };
-        let pselect_future = PSelectFuture { polls };
-        let ret_vec = if let Some(timeout) = timeout {
-            match Select2Futures::new(
-                TimeLimitedTaskFuture::new(timeout, pselect_future),
-                intr_future,
-            )
-            .await
-            {
-                SelectOutput::Output1(time_output) => match time_output {
-                    TimeLimitedTaskOutput::Ok(ret_vec) => ret_vec,
-                    TimeLimitedTaskOutput::TimeOut => {
-                        log::debug!("[sys_pselect6]: timeout");
-                        readfds.as_mut().map(|fds| fds.clear());
-                        writefds.as_mut().map(|fds| fds.clear());
-                        exceptfds.as_mut().map(|fds| fds.clear());
-                        task.set_running();
-                        // restore old signal mask
-                        if let Some(mask) = old_mask {
-                            *task.sig_mask() = mask;
-                        }
-                        return Ok(0);
-                    }
-                },
-                SelectOutput::Output2(_) => return Err(SysError::EINTR),
-            }
-        } else {
-            match Select2Futures::new(pselect_future, intr_future).await {
-                SelectOutput::Output1(ret_vec) => ret_vec,
-                SelectOutput::Output2(_) => return Err(SysError::EINTR),
-            }
-        };
+        let _mask_guard = SigMaskGuard::new(task, new_mask);
+        let pselect_future = PSelectFuture::new(polls);
+        let ret_vec = task.wait_interruptible(pselect_future, deadline).await;
+        if let (Some(mut timeout), Some(deadline)) = (timeout, deadline) {
+            let remaining = deadline.saturating_sub(get_time_duration());
+            *timeout = remaining.into();
+        }
         // NOTE: we can not clear before since EINTR will redo the syscall
+        let Some(ret_vec) = ret_vec? else {
+            log::debug!("[sys_pselect6]: timeout");
+            readfds.as_mut().map(|fds| fds.clear());
+            writefds.as_mut().map(|fds| fds.clear());
+            exceptfds.as_mut().map(|fds| fds.clear());
+            return Ok(0);
+        };
         readfds.as_mut().map(|fds| fds.clear());
         writefds.as_mut().map(|fds| fds.clear());
         exceptfds.as_mut().map(|fds| fds.clear());
 
-        task.set_running();
-
-        // restore old signal mask
-        if let Some(mask) = old_mask {
-            *task.sig_mask() = mask;
-        }
-
         let mut ret = 0;
         for (fd, events) in ret_vec {
             if events.contains(PollEvents::IN) || events.contains(PollEvents::HUP) {
@@ -0,0 +1,252 @@
+use core::{mem::size_of, time::Duration};
+
+use arch::time::get_time_duration;
+use systype::{SysError, SyscallResult};
+use time::timespec::TimeSpec;
+
+use super::Syscall;
+use crate::{
+    ipc::{
+        msg::{MsqIdDs, MSGMAX, MSG_MANAGER},
+        sem::{SemBuf, SemOpResult, SEMOPM, SEM_MANAGER},
+        IPC_RMID, IPC_SET, IPC_STAT,
+    },
+    mm::{UserReadPtr, UserWritePtr},
+};
+
+/// Set by glibc in `cmd` of `*ctl` syscalls to use the 64 bit structures,
+/// which are the only ones supported.
+const IPC_64: i32 = 0x100;
+
+// Commands of semctl
+const GETPID: i32 = 11;
+const GETVAL: i32 = 12;
+const GETALL: i32 = 13;
+const GETNCNT: i32 = 14;
+const GETZCNT: i32 = 15;
+const SETVAL: i32 = 16;
+const SETALL: i32 = 17;
+
+impl Syscall<'_> {
+    /// Returns the identifier of the System V semaphore set associated with
+    /// `key`, which is created with `nsems` semaphores if `IPC_CREAT` is in
+    /// `semflg` and there is none, or `key` is `IPC_PRIVATE`.
+    pub fn sys_semget(&self, key: usize, nsems: usize, semflg: i32) -> SyscallResult {
+        log::info!("[sys_semget] key:{key} nsems:{nsems} semflg:{semflg:#o}");
+        SEM_MANAGER.get_or_create(key as i32, nsems, semflg)
+    }
+
+    /// Performs operations in `sops` on the semaphore set atomically, i.e.
+    /// either all of them or none of them are performed.
+    pub async fn sys_semop(
+        &self,
+        semid: usize,
+        sops: UserReadPtr<SemBuf>,
+        nsops: usize,
+    ) -> SyscallResult {
+        self.semop(semid, sops, nsops, None).await
+    }
+
+    /// Like `semop`, but fails with `EAGAIN` if it has waited for `timeout`.
+    pub async fn sys_semtimedop(
+        &self,
+        semid: usize,
+        sops: UserReadPtr<SemBuf>,
+        nsops: usize,
+        timeout: UserReadPtr<TimeSpec>,
+    ) -> SyscallResult {
+        let timeout = if timeout.is_null() {
+            None
+        } else {
+            let timeout = timeout.read(self.task)?;
+            if !timeout.is_valid() {
+                return Err(SysError::EINVAL);
+            }
+            Some(timeout.into())
+        };
+        self.semop(semid, sops, nsops, timeout).await
+    }
+
+    async fn semop(
+        &self,
+        semid: usize,
+        sops: UserReadPtr<SemBuf>,
+        nsops: usize,
+        timeout: Option<Duration>,
+    ) -> SyscallResult {
+        let task = self.task;
+        if nsops == 0 {
+            return Err(SysError::EINVAL);
+        }
+        if nsops > SEMOPM {
+            return Err(SysError::E2BIG);
+        }
+        let sops = sops.read_array(task, nsops)?;
+        log::info!("[sys_semop] semid:{semid} sops:{sops:?}");
+        let set = SEM_MANAGER.get(semid)?;
+        let deadline = timeout.map(|timeout| get_time_duration() + timeout);
+        loop {
+            let seq = set.seq();
+            match set.try_op(&sops, task.pid())? {
+                SemOpResult::Done => return Ok(0),
+                SemOpResult::Blocked(num, wait_zero) => {
+                    set.count_waiter(num, wait_zero, true);
+                    let ret = task
+                        .wait_interruptible(set.wait_change(seq), deadline)
+                        .await;
+                    set.count_waiter(num, wait_zero, false);
+                    if ret?.is_none() {
+                        return Err(SysError::EAGAIN);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Performs the control operation `cmd` on the semaphore set, or the
+    /// semaphore `semnum` of it. `arg` is `union semun`, i.e. either an integer
+    /// or a pointer.
+    pub fn sys_semctl(&self, semid: usize, semnum: usize, cmd: i32, arg: usize) -> SyscallResult {
+        let task = self.task;
+        let cmd = cmd & !IPC_64;
+        log::info!("[sys_semctl] semid:{semid} semnum:{semnum} cmd:{cmd} arg:{arg:#x}");
+        if cmd == IPC_RMID {
+            SEM_MANAGER.remove(semid)?;
+            return Ok(0);
+        }
+        let set = SEM_MANAGER.get(semid)?;
+        match cmd {
+            IPC_STAT => {
+                UserWritePtr::from(arg).write(task, set.stat())?;
+                Ok(0)
+            }
+            IPC_SET => {
+                log::warn!("[sys_semctl] IPC_SET, do nothing");
+                Ok(0)
+            }
+            GETVAL => Ok(set.get_val(semnum)? as usize),
+            GETPID => Ok(set.get_pid(semnum)? as usize),
+            GETNCNT => set.get_ncnt(semnum),
+            GETZCNT => set.get_zcnt(semnum),
+            GETALL => {
+                UserWritePtr::<u16>::from(arg).write_array(task, &set.get_all())?;
+                Ok(0)
+            }
+            SETVAL => {
+                set.set_val(semnum, arg as i32, task.pid())?;
+                Ok(0)
+            }
+            SETALL => {
+                let vals = UserReadPtr::<u16>::from(arg).read_array(task, set.nsems())?;
+                set.set_all(&vals, task.pid())?;
+                Ok(0)
+            }
+            cmd => {
+                log::error!("[sys_semctl] unimplemented cmd {cmd}");
+                Err(SysError::EINVAL)
+            }
+        }
+    }
+
+    /// Returns the identifier of the System V message queue associated with
+    /// `key`, which is created if `IPC_CREAT` is in `msgflg` and there is
+    /// none, or `key` is `IPC_PRIVATE`.
+    pub fn sys_msgget(&self, key: usize, msgflg: i32) -> SyscallResult {
+        log::info!("[sys_msgget] key:{key} msgflg:{msgflg:#o}");
+        MSG_MANAGER.get_or_create(key as i32, msgflg)
+    }
+
+    /// Appends a message to the queue. `msgp` points to `struct msgbuf`, i.e.
+    /// a positive `long mtype` followed by `msgsz` bytes of text.
+    pub async fn sys_msgsnd(
+        &self,
+        msqid: usize,
+        msgp: usize,
+        msgsz: usize,
+        msgflg: i32,
+    ) -> SyscallResult {
+        let task = self.task;
+        log::info!("[sys_msgsnd] msqid:{msqid} msgp:{msgp:#x} msgsz:{msgsz} msgflg:{msgflg:#o}");
+        if msgsz > MSGMAX {
+            return Err(SysError::EINVAL);
+        }
+        let mtype = UserReadPtr::<isize>::from(msgp).read(task)?;
+        if mtype < 1 {
+            return Err(SysError::EINVAL);
+        }
+        let text = UserReadPtr::<u8>::from(msgp + size_of::<isize>()).read_array(task, msgsz)?;
+        let queue = MSG_MANAGER.get(msqid)?;
+        loop {
+            let seq = queue.seq();
+            if queue.try_send(mtype, &text, msgflg, task.pid())? {
+                return Ok(0);
+            }
+            task.wait_interruptible(queue.wait_change(seq), None)
+                .await?;
+        }
+    }
+
+    /// Takes a message from the queue and copies it into `msgp`, returning the
+    /// number of bytes of the text. `msgtyp` selects the message:
+    /// - 0: the first message.
+    /// - positive: the first message of type `msgtyp`, or not of it with
+    ///   `MSG_EXCEPT`.
+    /// - negative: the first message of the lowest type less than or equal to
+    ///   the absolute value of `msgtyp`.
+    pub async fn sys_msgrcv(
+        &self,
+        msqid: usize,
+        msgp: usize,
+        msgsz: usize,
+        msgtyp: isize,
+        msgflg: i32,
+    ) -> SyscallResult {
+        let task = self.task;
+        log::info!(
+            "[sys_msgrcv] msqid:{msqid} msgp:{msgp:#x} msgsz:{msgsz} msgtyp:{msgtyp} msgflg:{msgflg:#o}"
+        );
+        if (msgsz as isize) < 0 {
+            return Err(SysError::EINVAL);
+        }
+        let queue = MSG_MANAGER.get(msqid)?;
+        let (mtype, text) = loop {
+            let seq = queue.seq();
+            if let Some(msg) = queue.try_recv(msgtyp, msgsz, msgflg, task.pid())? {
+                break msg;
+            }
+            task.wait_interruptible(queue.wait_change(seq), None)
+                .await?;
+        };
+        UserWritePtr::<isize>::from(msgp).write(task, mtype)?;
+        UserWritePtr::<u8>::from(msgp + size_of::<isize>()).write_array(task, &text)?;
+        Ok(text.len())
+    }
+
+    /// Performs the control operation `cmd` on the message queue.
+    pub fn sys_msgctl(&self, msqid: usize, cmd: i32, buf: usize) -> SyscallResult {
+        let task = self.task;
+        let cmd = cmd & !IPC_64;
+        log::info!("[sys_msgctl] msqid:{msqid} cmd:{cmd} buf:{buf:#x}");
+        match cmd {
+            IPC_RMID => {
+                MSG_MANAGER.remove(msqid)?;
+                Ok(0)
+            }
+            IPC_STAT => {
+                let queue = MSG_MANAGER.get(msqid)?;
+                UserWritePtr::<MsqIdDs>::from(buf).write(task, queue.stat())?;
+                Ok(0)
+            }
+            IPC_SET => {
+                let ds = UserReadPtr::<MsqIdDs>::from(buf).read(task)?;
+                let queue = MSG_MANAGER.get(msqid)?;
+                queue.set(&ds);
+                Ok(0)
+            }
+            cmd => {
+                log::error!("[sys_msgctl] unimplemented cmd {cmd}");
+                Err(SysError::EINVAL)
+            }
+        }
+    }
+}
@@ -1,12 +1,24 @@
 //! Miscellaneous system calls
 
-use core::mem::size_of;
+use core::{mem::size_of, time::Duration};
 
 use arch::time::get_time_duration;
-use systype::SyscallResult;
+use config::{mm::PAGE_SIZE, process::INIT_PROC_PID};
+use driver::BLOCK_DEVICE;
+use memory::frame_stats;
+use signal::{
+    siginfo::{SigDetails, SigInfo},
+    sigset::Sig,
+};
+use systype::{SysError, SyscallResult};
+use timer::timelimited_task::ksleep_ms;
+use vfs::procfs::{hostname, set_hostname, HOST_NAME_MAX};
 
 use super::Syscall;
-use crate::mm::UserWritePtr;
+use crate::{
+    mm::{UserReadPtr, UserWritePtr},
+    task::{loadavg, shutdown_kernel_tasks, TASK_MANAGER},
+};
 
 // Defined in <sys/utsname.h>.
 #[derive(Debug, Clone, Copy)]
@@ -26,17 +38,37 @@ pub struct UtsName {
     pub domainname: [u8; 65],
 }
 
+/// Name of the kernel, which pretends to be Linux for compatibility of user
+/// programs.
+const SYSNAME: &str = "Linux";
+const RELEASE: &str = "5.19.0-42-generic";
+const VERSION: &str = concat!("#1 SMP Phoenix ", env!("GIT_HASH"));
+const MACHINE: &str = "riscv64";
+
+// Magic numbers and commands of reboot(2), defined in <linux/reboot.h>.
+const LINUX_REBOOT_MAGIC1: usize = 0xfee1dead;
+const LINUX_REBOOT_MAGIC2: [usize; 4] = [672274793, 85072278, 369367448, 537993216];
+const LINUX_REBOOT_CMD_CAD_OFF: usize = 0x00000000;
+const LINUX_REBOOT_CMD_CAD_ON: usize = 0x89abcdef;
+const LINUX_REBOOT_CMD_RESTART: usize = 0x01234567;
+const LINUX_REBOOT_CMD_HALT: usize = 0xcdef0123;
+const LINUX_REBOOT_CMD_POWER_OFF: usize = 0x4321fedc;
+
+/// Time for processes to exit after SIGTERM on reboot, before they are killed.
+const TERM_GRACE: Duration = Duration::from_secs(2);
+/// Time for processes to exit after SIGKILL on reboot.
+const KILL_GRACE: Duration = Duration::from_secs(1);
+/// Time for background kernel tasks to stop before the machine is powered off.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
 impl UtsName {
-    // TODO: Is the default value copied from Titanix correct?
-    pub fn default() -> Self {
+    pub fn new() -> Self {
         Self {
-            sysname: Self::from_str("Linux"),
-            nodename: Self::from_str("Linux"),
-            release: Self::from_str("5.19.0-42-generic"),
-            version: Self::from_str(
-                "#43~22.04.1-Ubuntu SMP PREEMPT_DYNAMIC Fri Apr 21 16:51:08 UTC 2",
-            ),
-            machine: Self::from_str("RISC-V SiFive Freedom U740 SoC"),
+            sysname: Self::from_str(SYSNAME),
+            nodename: Self::from_str(&hostname()),
+            release: Self::from_str(RELEASE),
+            version: Self::from_str(VERSION),
+            machine: Self::from_str(MACHINE),
             domainname: Self::from_str("localhost"),
         }
     }
@@ -47,6 +79,7 @@ impl UtsName {
         data
     }
 }
+
 pub const SYSINFO_SIZE: usize = size_of::<Sysinfo>();
 
 const _F_SIZE: usize = 20 - 2 * size_of::<u64>() - size_of::<u32>();
@@ -86,20 +119,23 @@ pub struct Sysinfo {
 
 impl Sysinfo {
     pub fn collect() -> Self {
+        let stats = frame_stats();
         Self {
             uptime: get_time_duration().as_secs() as i64,
-            loads: [0; 3],
-            totalram: 0,
-            freeram: 0,
+            loads: loadavg::loads(),
+            totalram: stats.total as u64,
+            freeram: stats.free as u64,
             sharedram: 0,
-            bufferram: 0,
+            bufferram: BLOCK_DEVICE
+                .get()
+                .map_or(0, |device| device.buffer_page_cnts()) as u64,
             totalswap: 0,
             freeswap: 0,
-            procs: 0,
+            procs: TASK_MANAGER.len() as u16,
             pad: 0,
             totalhigh: 0,
             freehigh: 0,
-            mem_uint: 0,
+            mem_uint: PAGE_SIZE as u32,
             _f: [0; _F_SIZE],
         }
     }
@@ -109,7 +145,21 @@ impl Syscall<'_> {
     /// uname() returns system information in the structure pointed to by buf.
     pub fn sys_uname(&self, buf: UserWritePtr<UtsName>) -> SyscallResult {
         let task = self.task;
-        buf.write(&task, UtsName::default())?;
+        buf.write(&task, UtsName::new())?;
+        Ok(0)
+    }
+
+    /// Sets the hostname to `len` bytes of `name`, which is not null
+    /// terminated. Only privileged callers may do it.
+    pub fn sys_sethostname(&self, name: UserReadPtr<u8>, len: usize) -> SyscallResult {
+        if !self.task.with_cred(|cred| cred.is_privileged()) {
+            return Err(SysError::EPERM);
+        }
+        if len > HOST_NAME_MAX {
+            return Err(SysError::EINVAL);
+        }
+        let name = name.read_array(self.task, len)?;
+        set_hostname(&name)?;
         Ok(0)
     }
 
@@ -144,4 +194,78 @@ impl Syscall<'_> {
         info.write(self.task, Sysinfo::collect())?;
         Ok(0)
     }
+
+    /// Restart, halt or power off the machine, where halting also powers
+    /// off. Ctrl-alt-del is ignored. Only privileged callers may do it.
+    ///
+    /// No more tasks can be created once it starts. Processes other than init
+    /// and the caller are asked to exit by SIGTERM, and killed by SIGKILL if
+    /// they are still alive after a grace period. Filesystems are synced and
+    /// background kernel tasks are stopped before the SBI is called.
+    pub async fn sys_reboot(
+        &self,
+        magic1: usize,
+        magic2: usize,
+        cmd: usize,
+        _arg: usize,
+    ) -> SyscallResult {
+        if !self.task.with_cred(|cred| cred.is_privileged()) {
+            return Err(SysError::EPERM);
+        }
+        if magic1 != LINUX_REBOOT_MAGIC1 || !LINUX_REBOOT_MAGIC2.contains(&magic2) {
+            return Err(SysError::EINVAL);
+        }
+        let cmd = cmd as u32 as usize;
+        match cmd {
+            LINUX_REBOOT_CMD_CAD_OFF | LINUX_REBOOT_CMD_CAD_ON => return Ok(0),
+            LINUX_REBOOT_CMD_RESTART | LINUX_REBOOT_CMD_HALT | LINUX_REBOOT_CMD_POWER_OFF => {}
+            _ => return Err(SysError::EINVAL),
+        }
+        log::info!("[sys_reboot] cmd {cmd:#x} by process {}", self.task.pid());
+
+        TASK_MANAGER.stop_admission();
+        if !self.signal_other_processes(Sig::SIGTERM, TERM_GRACE).await {
+            log::warn!("[sys_reboot] processes are alive after SIGTERM, kill them");
+            if !self.signal_other_processes(Sig::SIGKILL, KILL_GRACE).await {
+                log::error!("[sys_reboot] processes are alive after SIGKILL");
+            }
+        }
+        vfs::sync_all().await;
+        shutdown_kernel_tasks(SHUTDOWN_TIMEOUT).await;
+
+        if cmd == LINUX_REBOOT_CMD_RESTART {
+            let ret = sbi_rt::system_reset(sbi_rt::ColdReboot, sbi_rt::NoReason);
+            log::error!("[sys_reboot] failed to reset by SBI: {ret:?}, power off instead");
+        }
+        sbi_rt::legacy::shutdown()
+    }
+
+    /// Send `sig` to all processes but init and the caller, and wait at most
+    /// `grace` for them to exit. Return false if some are still alive.
+    async fn signal_other_processes(&self, sig: Sig, grace: Duration) -> bool {
+        let pid = self.task.pid();
+        let others = || {
+            TASK_MANAGER.processes().into_iter().filter(move |task| {
+                !task.is_zombie() && task.pid() != INIT_PROC_PID && task.pid() != pid
+            })
+        };
+        for task in others() {
+            task.receive_siginfo(
+                SigInfo {
+                    sig,
+                    code: SigInfo::USER,
+                    details: SigDetails::Kill { pid },
+                },
+                false,
+            );
+        }
+        let deadline = get_time_duration() + grace;
+        while others().next().is_some() {
+            if get_time_duration() >= deadline {
+                return false;
+            }
+            ksleep_ms(10).await;
+        }
+        true
+    }
 }
@@ -1,11 +1,13 @@
-use config::mm::{is_aligned_to_page, PAGE_MASK};
+use core::ops::Range;
+
+use config::mm::is_aligned_to_page;
 use memory::VirtAddr;
-use systype::{SysError, SyscallResult};
+use systype::{SysError, SysResult, SyscallResult};
 
 use super::Syscall;
 use crate::{
-    ipc::shm::{SharedMemory, SHARED_MEMORY_KEY_ALLOCATOR, SHARED_MEMORY_MANAGER},
-    mm::{memory_space::vm_area::MapPerm, UserWritePtr},
+    ipc::shm::{ShmIdDs, SHARED_MEMORY_MANAGER},
+    mm::{memory_space::vm_area::MapPerm, swap, UserReadPtr, UserWritePtr},
 };
 
 bitflags! {
@@ -28,6 +30,8 @@ bitflags! {
         const MAP_ANONYMOUS = 0x20;
         /// Don't check for reservations.
         const MAP_NORESERVE = 0x04000;
+        /// Populate (prefault) page tables.
+        const MAP_POPULATE = 0x08000;
     }
 }
 
@@ -66,7 +70,18 @@ impl Syscall<'_> {
     /// success. On failure, the system call returns the current break.
     pub fn sys_brk(&self, addr: VirtAddr) -> SyscallResult {
         let task = self.task;
-        let brk = task.with_mut_memory_space(|m| m.reset_heap_break(addr));
+        let privileged = task.with_cred(|cred| cred.is_privileged());
+        let brk = task.with_mut_memory_space(|m| {
+            let old_brk = m.get_heap_break();
+            if addr > old_brk && m.check_future_lock(addr - old_brk, privileged).is_err() {
+                return old_brk;
+            }
+            let brk = m.reset_heap_break(addr);
+            if brk > old_brk {
+                m.lock_if_future(old_brk);
+            }
+            brk
+        });
         Ok(brk.bits())
     }
 
@@ -126,11 +141,14 @@ impl Syscall<'_> {
             return Err(SysError::EINVAL);
         }
 
+        let privileged = task.with_cred(|cred| cred.is_privileged());
+        task.with_memory_space(|m| m.check_future_lock(length, privileged))?;
+
         if flags.contains(MmapFlags::MAP_FIXED) {
             task.with_mut_memory_space(|m| m.unmap(addr..(addr + length).round_up()))?;
         }
 
-        match flags.intersection(MmapFlags::MAP_TYPE_MASK) {
+        let start = match flags.intersection(MmapFlags::MAP_TYPE_MASK) {
             MmapFlags::MAP_SHARED => {
                 if flags.contains(MmapFlags::MAP_ANONYMOUS) {
                     // TODO: MAP_SHARED page fault should keep track of all vm areas
@@ -140,7 +158,7 @@ impl Syscall<'_> {
                     })?;
                     Ok(start_va.bits())
                 } else {
-                    let file = task.with_fd_table(|table| table.get_file(fd))?;
+                    let file = task.with_fd_table(|table| table.get_io_file(fd))?;
                     if offset + length > file.size() {
                         log::warn!("offset plus length is bigger than file size");
                     }
@@ -157,7 +175,7 @@ impl Syscall<'_> {
                     })?;
                     Ok(start_va.bits())
                 } else {
-                    let file = task.with_fd_table(|table| table.get_file(fd))?;
+                    let file = task.with_fd_table(|table| table.get_io_file(fd))?;
                     if offset + length > file.size() {
                         log::warn!("offset plus length is bigger than file size");
                     }
@@ -169,7 +187,9 @@ impl Syscall<'_> {
                 }
             }
             _ => Err(SysError::EINVAL),
-        }
+        }?;
+        task.with_mut_memory_space(|m| m.lock_if_future(VirtAddr::from(start)));
+        Ok(start)
     }
 
     /// The munmap() system call deletes the mappings for the specified address
@@ -215,52 +235,10 @@ impl Syscall<'_> {
     ///
     /// On success, a valid shared memory identifier is returned.
     pub fn sys_shmget(&self, key: usize, size: usize, shmflg: i32) -> SyscallResult {
-        bitflags! {
-            #[derive(Debug)]
-            struct ShmGetFlags: i32 {
-                /// Create a new segment. If this flag is not used, then shmget() will
-                /// find the segment associated with key and check to see if the user
-                /// has permission to access the segment.
-                const IPC_CREAT = 0o1000;
-                /// This flag is used with IPC_CREAT to ensure that this call creates
-                /// the segment.  If the segment already exists, the call fails.
-                const IPC_EXCL = 0o2000;
-            }
-        }
-        let shmflg = ShmGetFlags::from_bits_truncate(shmflg);
-        log::info!("[sys_shmget] {key} {size} {:?}", shmflg);
-
-        // Create a new shared memory. When it is specified, the shmflg is invalid
-        const IPC_PRIVATE: usize = 0;
-        let rounded_up_sz = (size + PAGE_MASK) & !PAGE_MASK;
-        if key == IPC_PRIVATE {
-            let new_key = SHARED_MEMORY_KEY_ALLOCATOR.lock().alloc();
-            let new_shm = SharedMemory::new(rounded_up_sz, self.task.pid());
-            SHARED_MEMORY_MANAGER.0.lock().insert(new_key, new_shm);
-            return Ok(new_key);
-        }
-        let mut shm_manager = SHARED_MEMORY_MANAGER.0.lock();
-        if let Some(shm) = shm_manager.get(&key) {
-            // IPC_CREAT and IPC_EXCL were specified in shmflg, but a shared memory segment
-            // already exists for key.
-            if shmflg.contains(ShmGetFlags::IPC_CREAT | ShmGetFlags::IPC_EXCL) {
-                return Err(SysError::EEXIST);
-            }
-            // A segment for the given key exists, but size is greater than the size of that
-            // segment.
-            if shm.size() < size {
-                return Err(SysError::EINVAL);
-            }
-            return Ok(key);
-        }
-        if shmflg.contains(ShmGetFlags::IPC_CREAT) {
-            let new_shm = SharedMemory::new(rounded_up_sz, self.task.pid());
-            shm_manager.insert(key, new_shm);
-            return Ok(key);
-        } else {
-            // No segment exists for the given key, and IPC_CREAT was not specified.
-            return Err(SysError::ENOENT);
-        }
+        log::info!("[sys_shmget] {key} {size} {shmflg:#o}");
+        // A new segment is always created for IPC_PRIVATE, where shmflg is ignored
+        // except for the permissions.
+        SHARED_MEMORY_MANAGER.get_or_create(key as i32, size, shmflg, self.task.pid())
     }
 
     /// After creating a shared memory, if a process wants to use it, it needs
@@ -304,22 +282,19 @@ impl Syscall<'_> {
         if shmflg.contains(ShmAtFlags::SHM_RDONLY) {
             map_perm.remove(MapPerm::W);
         }
-        let mut ret = 0;
-        if let Some(shm) = SHARED_MEMORY_MANAGER.0.lock().get_mut(&shmid) {
-            let task = self.task;
+        let task = self.task;
+        // Invalid shmid value results in EINVAL
+        let ret_addr = SHARED_MEMORY_MANAGER.with_shm(shmid, |shm| {
             let ret_addr = task.with_mut_memory_space(|m| {
                 m.attach_shm(shm.size(), shmaddr_aligned, map_perm, &mut shm.pages)
             });
-            task.with_mut_shm_ids(|ids| {
-                ids.insert(ret_addr, shmid);
-            });
-            ret = ret_addr.into();
-        } else {
-            // Invalid shmid value
-            return Err(SysError::EINVAL);
-        }
-        SHARED_MEMORY_MANAGER.attach(shmid, self.task.pid());
-        return Ok(ret);
+            shm.shmid_ds.attach(task.pid());
+            ret_addr
+        })?;
+        task.with_mut_shm_ids(|ids| {
+            ids.insert(ret_addr, shmid);
+        });
+        Ok(ret_addr.into())
     }
 
     /// When a process no longer uses a shared memory block, it should detach
@@ -363,18 +338,15 @@ impl Syscall<'_> {
         const IPC_STAT: i32 = 2;
         match cmd {
             IPC_STAT => {
-                let shm_manager = SHARED_MEMORY_MANAGER.0.lock();
-                if let Some(shm) = shm_manager.get(&shmid) {
-                    let buf = UserWritePtr::from(buf);
-                    buf.write(&self.task, shm.shmid_ds)?;
-                    Ok(0)
-                } else {
-                    // shmid is not a valid identifier
-                    Err(SysError::EINVAL)
-                }
+                // shmid is not a valid identifier results in EINVAL
+                let shmid_ds = SHARED_MEMORY_MANAGER.with_shm(shmid, |shm| shm.shmid_ds)?;
+                UserWritePtr::<ShmIdDs>::from(buf).write(&self.task, shmid_ds)?;
+                Ok(0)
             }
+            // Mark the segment to be destroyed. The segment will actually be destroyed
+            // only after the last process detaches it.
             IPC_RMID => {
-                log::warn!("[sys_shmctl] IPC_RMID, do nothing");
+                SHARED_MEMORY_MANAGER.remove(shmid)?;
                 Ok(0)
             }
             cmd => {
@@ -397,4 +369,141 @@ impl Syscall<'_> {
         task.with_mut_memory_space(|m| m.mprotect(new_range, perm))
             .map(|_| 0)
     }
+
+    /// The madvise() system call is used to give advice or directions to the
+    /// kernel about the address range beginning at address addr and with size
+    /// length bytes. `MADV_DONTNEED` and `MADV_FREE` release the pages in the
+    /// range, `MADV_HUGEPAGE` and `MADV_NOHUGEPAGE` turn on and off huge pages
+    /// for it, and other advice is ignored as long as the range is mapped.
+    pub fn sys_madvise(&self, addr: VirtAddr, len: usize, advice: usize) -> SyscallResult {
+        const MADV_DONTNEED: usize = 4;
+        const MADV_FREE: usize = 8;
+        const MADV_HUGEPAGE: usize = 14;
+        const MADV_NOHUGEPAGE: usize = 15;
+        let task = self.task;
+        if !addr.is_aligned() {
+            return Err(SysError::EINVAL);
+        }
+        let end = addr.bits().checked_add(len).ok_or(SysError::EINVAL)?;
+        let range = addr..VirtAddr::from(end).round_up();
+        log::info!("[sys_madvise] range: {range:?}, advice: {advice}");
+        match advice {
+            MADV_DONTNEED => task.with_mut_memory_space(|m| m.madvise_dontneed(range))?,
+            MADV_FREE => task.with_mut_memory_space(|m| m.madvise_free(range))?,
+            MADV_HUGEPAGE => task.with_mut_memory_space(|m| m.madvise_hugepage(range, true))?,
+            MADV_NOHUGEPAGE => task.with_mut_memory_space(|m| m.madvise_hugepage(range, false))?,
+            _ => task.with_memory_space(|m| m.check_mapped(range))?,
+        }
+        Ok(0)
+    }
+
+    /// Range of whole pages covering `len` bytes at `addr`, as mlock(2) takes.
+    fn mlock_range(addr: VirtAddr, len: usize) -> SysResult<Range<VirtAddr>> {
+        let end = addr.bits().checked_add(len).ok_or(SysError::EINVAL)?;
+        Ok(addr.round_down()..VirtAddr::from(end).round_up())
+    }
+
+    /// mlock() locks pages in the address range starting at addr and
+    /// continuing for len bytes. All pages that contain a part of the
+    /// specified address range are guaranteed to be resident in RAM when the
+    /// call returns successfully; the pages are guaranteed to stay in RAM
+    /// until later unlocked.
+    pub fn sys_mlock(&self, addr: VirtAddr, len: usize) -> SyscallResult {
+        self.sys_mlock2(addr, len, 0)
+    }
+
+    /// mlock2() also locks pages in the specified range, but only faults in
+    /// the pages when they are accessed if `MLOCK_ONFAULT` is given.
+    pub fn sys_mlock2(&self, addr: VirtAddr, len: usize, flags: u32) -> SyscallResult {
+        const MLOCK_ONFAULT: u32 = 0x1;
+        let task = self.task;
+        if flags & !MLOCK_ONFAULT != 0 {
+            return Err(SysError::EINVAL);
+        }
+        let range = Self::mlock_range(addr, len)?;
+        log::info!("[sys_mlock2] range: {range:?}, flags: {flags:#x}");
+        let privileged = task.with_cred(|cred| cred.is_privileged());
+        task.with_mut_memory_space(|m| m.mlock(range, flags & MLOCK_ONFAULT != 0, privileged))?;
+        Ok(0)
+    }
+
+    /// munlock() unlocks pages in the address range starting at addr and
+    /// continuing for len bytes. After this call, all pages that contain a
+    /// part of the specified memory range can be moved to external swap space
+    /// again by the kernel.
+    pub fn sys_munlock(&self, addr: VirtAddr, len: usize) -> SyscallResult {
+        let task = self.task;
+        let range = Self::mlock_range(addr, len)?;
+        log::info!("[sys_munlock] range: {range:?}");
+        task.with_mut_memory_space(|m| m.munlock(range))?;
+        Ok(0)
+    }
+
+    /// mlockall() locks all pages mapped into the address space of the calling
+    /// process, with `MCL_CURRENT`, and all pages which will become mapped in
+    /// the future, with `MCL_FUTURE`.
+    pub fn sys_mlockall(&self, flags: u32) -> SyscallResult {
+        const MCL_CURRENT: u32 = 0x1;
+        const MCL_FUTURE: u32 = 0x2;
+        const MCL_ONFAULT: u32 = 0x4;
+        let task = self.task;
+        if flags == 0
+            || flags & !(MCL_CURRENT | MCL_FUTURE | MCL_ONFAULT) != 0
+            || flags == MCL_ONFAULT
+        {
+            return Err(SysError::EINVAL);
+        }
+        log::info!("[sys_mlockall] flags: {flags:#x}");
+        let privileged = task.with_cred(|cred| cred.is_privileged());
+        task.with_mut_memory_space(|m| {
+            m.mlockall(
+                flags & MCL_CURRENT != 0,
+                flags & MCL_FUTURE != 0,
+                flags & MCL_ONFAULT != 0,
+                privileged,
+            )
+        })?;
+        Ok(0)
+    }
+
+    /// munlockall() unlocks all pages mapped into the address space of the
+    /// calling process.
+    pub fn sys_munlockall(&self) -> SyscallResult {
+        self.task.with_mut_memory_space(|m| m.munlockall());
+        Ok(0)
+    }
+
+    /// swapon() sets the swap area to the file specified by path. Only a
+    /// regular file prepared by mkswap(8) can be used, and there is one swap
+    /// area at most, so the priority in `swapflags` is ignored.
+    pub async fn sys_swapon(&self, path: UserReadPtr<u8>, swapflags: u32) -> SyscallResult {
+        /// `SWAP_FLAG_PREFER`, `SWAP_FLAG_DISCARD*` and the priority mask.
+        const SWAP_FLAGS_VALID: u32 = 0x7ffff;
+        let task = self.task;
+        if !task.with_cred(|cred| cred.is_privileged()) {
+            return Err(SysError::EPERM);
+        }
+        if swapflags & !SWAP_FLAGS_VALID != 0 {
+            return Err(SysError::EINVAL);
+        }
+        let path = path.read_path(&task)?;
+        log::info!("[sys_swapon] path: {path}, flags: {swapflags:#x}");
+        let file = task.resolve_path(&path)?.open()?;
+        swap::swapon(file).await?;
+        Ok(0)
+    }
+
+    /// swapoff() stops swapping to the file specified by path, after reading
+    /// the pages swapped out back into memory.
+    pub fn sys_swapoff(&self, path: UserReadPtr<u8>) -> SyscallResult {
+        let task = self.task;
+        if !task.with_cred(|cred| cred.is_privileged()) {
+            return Err(SysError::EPERM);
+        }
+        let path = path.read_path(&task)?;
+        log::info!("[sys_swapoff] path: {path}");
+        let file = task.resolve_path(&path)?.open()?;
+        swap::swapoff(&file)?;
+        Ok(0)
+    }
 }
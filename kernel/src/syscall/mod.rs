@@ -4,18 +4,25 @@ mod consts;
 mod fs;
 pub mod futex;
 mod io;
+mod ipc;
 mod misc;
 mod mm;
 mod net;
 mod process;
+mod ptrace;
 mod random;
 mod resource;
 mod sched;
 mod signal;
+pub mod stats;
+#[cfg(feature = "strace")]
+pub mod strace;
 mod time;
 
 use alloc::sync::Arc;
 
+use ::signal::{Sig, SigDetails, SigInfo};
+use arch::time::get_cycles;
 pub use consts::SyscallNo;
 pub use mm::MmapFlags;
 pub use process::CloneFlags;
@@ -26,8 +33,7 @@ use crate::task::Task;
 #[cfg(feature = "strace")]
 pub const STRACE_COLOR_CODE: logging::ColorCode = logging::ColorCode::BrightMagenta;
 
-/// Syscall trace.
-// TODO: syscall trace with exact args and return value
+/// Syscall trace, where the syscalls are decoded by `strace::decode_call`.
 #[cfg(feature = "strace")]
 #[macro_export]
 macro_rules! strace {
@@ -64,22 +70,18 @@ impl<'a> Syscall<'a> {
     /// Handle syscall exception with `syscall_id` and other arguments.
     pub async fn syscall(&self, syscall_no: usize, args: [usize; 6]) -> usize {
         use SyscallNo::*;
+        if !self.task.seccomp_ref().permits(syscall_no) {
+            return self.seccomp_kill(syscall_no);
+        }
         let Some(syscall_no) = SyscallNo::from_repr(syscall_no) else {
             log::error!("Syscall number not included: {syscall_no}");
             return -(SysError::ENOSYS as isize) as usize;
         };
         // Use STRACE=1 instead
         // log::debug!("[syscall] handle {syscall_no}");
-        strace!(
-            "{}, args: [{:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x}]",
-            syscall_no,
-            args[0],
-            args[1],
-            args[2],
-            args[3],
-            args[4],
-            args[5]
-        );
+        #[cfg(feature = "strace")]
+        let call = strace::decode_call(self.task, syscall_no, &args);
+        let start = stats::enabled().then(get_cycles);
         let result = match syscall_no {
             // Process
             EXIT => self.sys_exit(args[0] as _),
@@ -89,28 +91,56 @@ impl<'a> Syscall<'a> {
                     .await
             }
             SCHED_YIELD => self.sys_sched_yield().await,
-            CLONE => self.sys_clone(
-                args[0],
-                args[1].into(),
-                args[2].into(),
-                args[3].into(),
-                args[4].into(),
-            ),
+            CLONE => {
+                self.sys_clone(
+                    args[0],
+                    args[1].into(),
+                    args[2].into(),
+                    args[3].into(),
+                    args[4].into(),
+                )
+                .await
+            }
             WAIT4 => {
-                self.sys_wait4(args[0] as _, args[1].into(), args[2] as _, args[3])
+                self.sys_wait4(args[0] as _, args[1].into(), args[2] as _, args[3].into())
                     .await
             }
+            PTRACE => self.sys_ptrace(args[0], args[1], args[2], args[3]),
+            PRCTL => self.sys_prctl(args[0], args[1], args[2].into(), args[3]),
+            WAITID => {
+                self.sys_waitid(
+                    args[0] as _,
+                    args[1],
+                    args[2].into(),
+                    args[3] as _,
+                    args[4].into(),
+                )
+                .await
+            }
             GETTID => self.sys_gettid(),
             GETPID => self.sys_getpid(),
             GETPPID => self.sys_getppid(),
             GETPGID => self.sys_getpgid(args[0]),
+            GETSID => self.sys_getsid(args[0]),
             SET_TID_ADDRESS => self.sys_set_tid_address(args[0]),
-            GETUID => self.sys_getuid(),
-            GETEUID => self.sys_geteuid(),
+            UNSHARE => self.sys_unshare(args[0]),
             SETSID => self.sys_setsid(),
-            GETEGID => self.sys_do_nothing("getegid"),
             SETPGID => self.sys_setpgid(args[0], args[1]),
-            GETGID => self.sys_do_nothing("getgid"),
+            // Credentials
+            GETUID => self.sys_getuid(),
+            GETEUID => self.sys_geteuid(),
+            GETGID => self.sys_getgid(),
+            GETEGID => self.sys_getegid(),
+            GETRESUID => self.sys_getresuid(args[0].into(), args[1].into(), args[2].into()),
+            GETRESGID => self.sys_getresgid(args[0].into(), args[1].into(), args[2].into()),
+            SETUID => self.sys_setuid(args[0] as _),
+            SETGID => self.sys_setgid(args[0] as _),
+            SETREUID => self.sys_setreuid(args[0] as _, args[1] as _),
+            SETREGID => self.sys_setregid(args[0] as _, args[1] as _),
+            SETRESUID => self.sys_setresuid(args[0] as _, args[1] as _, args[2] as _),
+            SETRESGID => self.sys_setresgid(args[0] as _, args[1] as _, args[2] as _),
+            GETGROUPS => self.sys_getgroups(args[0], args[1].into()),
+            SETGROUPS => self.sys_setgroups(args[0], args[1].into()),
             // Memory
             BRK => self.sys_brk(args[0].into()),
             MMAP => self.sys_mmap(
@@ -124,13 +154,39 @@ impl<'a> Syscall<'a> {
             MUNMAP => self.sys_munmap(args[0].into(), args[1]),
             MPROTECT => self.sys_mprotect(args[0].into(), args[1], args[2] as _),
             MSYNC => self.sys_do_nothing("msync"),
+            MLOCK => self.sys_mlock(args[0].into(), args[1]),
+            MLOCK2 => self.sys_mlock2(args[0].into(), args[1], args[2] as _),
+            MUNLOCK => self.sys_munlock(args[0].into(), args[1]),
+            MLOCKALL => self.sys_mlockall(args[0] as _),
+            MUNLOCKALL => self.sys_munlockall(),
             MEMBARRIER => self.sys_do_nothing("membarrier"),
-            MADVISE => self.sys_do_nothing("madvise"),
+            MADVISE => self.sys_madvise(args[0].into(), args[1], args[2]),
+            SWAPON => self.sys_swapon(args[0].into(), args[1] as _).await,
+            SWAPOFF => self.sys_swapoff(args[0].into()),
             // Shared Memory
             SHMGET => self.sys_shmget(args[0], args[1], args[2] as _),
             SHMAT => self.sys_shmat(args[0], args[1].into(), args[2] as _),
             SHMDT => self.sys_shmdt(args[0].into()),
             SHMCTL => self.sys_shmctl(args[0], args[1] as _, args[2]),
+            // Semaphore
+            SEMGET => self.sys_semget(args[0], args[1], args[2] as _),
+            SEMOP => self.sys_semop(args[0], args[1].into(), args[2]).await,
+            SEMTIMEDOP => {
+                self.sys_semtimedop(args[0], args[1].into(), args[2], args[3].into())
+                    .await
+            }
+            SEMCTL => self.sys_semctl(args[0], args[1], args[2] as _, args[3]),
+            // Message queue
+            MSGGET => self.sys_msgget(args[0], args[1] as _),
+            MSGSND => {
+                self.sys_msgsnd(args[0], args[1], args[2], args[3] as _)
+                    .await
+            }
+            MSGRCV => {
+                self.sys_msgrcv(args[0], args[1], args[2], args[3] as _, args[4] as _)
+                    .await
+            }
+            MSGCTL => self.sys_msgctl(args[0], args[1] as _, args[2]),
             // File system
             READ => self.sys_read(args[0], args[1].into(), args[2]).await,
             WRITE => self.sys_write(args[0], args[1].into(), args[2]).await,
@@ -147,16 +203,18 @@ impl<'a> Syscall<'a> {
                     .await
             }
             CLOSE => self.sys_close(args[0]),
+            MKNODAT => self.sys_mknodat(args[0].into(), args[1].into(), args[2] as _, args[3] as _),
             MKDIRAT => self.sys_mkdirat(args[0].into(), args[1].into(), args[2] as _),
             GETCWD => self.sys_getcwd(args[0].into(), args[1]),
             CHDIR => self.sys_chdir(args[0].into()),
+            FCHDIR => self.sys_fchdir(args[0]),
             DUP => self.sys_dup(args[0]),
             DUP3 => self.sys_dup3(args[0], args[1], args[2] as _),
             FSTAT => self.sys_fstat(args[0], args[1].into()),
             FSTATAT => {
                 self.sys_fstatat(args[0].into(), args[1].into(), args[2].into(), args[3] as _)
             }
-            GETDENTS64 => self.sys_getdents64(args[0], args[1], args[2]),
+            GETDENTS64 => self.sys_getdents64(args[0], args[1], args[2]).await,
             UNLINKAT => self.sys_unlinkat(args[0].into(), args[1].into(), args[2] as _),
             MOUNT => {
                 self.sys_mount(
@@ -179,29 +237,42 @@ impl<'a> Syscall<'a> {
                     .await
             }
             FACCESSAT => self.sys_faccessat(args[0].into(), args[1].into(), args[2], args[3] as _),
-            LSEEK => self.sys_lseek(args[0], args[1] as _, args[2]),
+            LSEEK => self.sys_lseek(args[0], args[1] as _, args[2]).await,
             UMASK => self.sys_umask(args[0] as _),
             UTIMENSAT => {
                 self.sys_utimensat(args[0].into(), args[1].into(), args[2].into(), args[3] as _)
             }
-            RENAMEAT2 => self.sys_renameat2(
-                args[0].into(),
-                args[1].into(),
-                args[2].into(),
-                args[3].into(),
-                args[4] as _,
-            ),
+            RENAMEAT2 => {
+                self.sys_renameat2(
+                    args[0].into(),
+                    args[1].into(),
+                    args[2].into(),
+                    args[3].into(),
+                    args[4] as _,
+                )
+                .await
+            }
             STATFS => self.sys_statfs(args[0].into(), args[1].into()),
+            FSTATFS => self.sys_fstatfs(args[0], args[1].into()),
             READLINKAT => {
                 self.sys_readlinkat(args[0].into(), args[1].into(), args[2].into(), args[3])
                     .await
             }
-            SYNC => self.sys_do_nothing("sync"),
+            SYNC => self.sys_sync().await,
             FSYNC => self.sys_do_nothing("fsync"),
             FTRUNCATE => self.sys_ftruncate(args[0], args[1] as _).await,
-            FCHMODAT => self.sys_fchmodat(),
-            FCHOWNAT => self.sys_do_nothing("fchownat"),
+            FCHMOD => self.sys_fchmod(args[0], args[1] as _),
+            FCHMODAT => self.sys_fchmodat(args[0].into(), args[1].into(), args[2] as _),
+            FCHOWN => self.sys_fchown(args[0], args[1] as _, args[2] as _),
+            FCHOWNAT => self.sys_fchownat(
+                args[0].into(),
+                args[1].into(),
+                args[2] as _,
+                args[3] as _,
+                args[4] as _,
+            ),
             FALLOCATE => self.sys_do_nothing("fallocate"),
+            MEMFD_CREATE => self.sys_memfd_create(args[0].into(), args[1] as _),
             SYMLINKAT => self.sys_symlinkat(args[0].into(), args[1].into(), args[2].into()),
             LINKAT => self.sys_linkat(
                 args[0].into(),
@@ -282,10 +353,16 @@ impl<'a> Syscall<'a> {
             SET_ROBUST_LIST => self.sys_set_robust_list(args[0].into(), args[1]),
             // Schedule
             SCHED_SETSCHEDULER => self.sys_sched_setscheduler(),
-            SCHED_GETSCHEDULER => self.sys_sched_getscheduler(),
-            SCHED_GETPARAM => self.sys_sched_getparam(),
-            SCHED_SETAFFINITY => self.sys_sched_setaffinity(args[0], args[1], args[2].into()),
+            SCHED_GETSCHEDULER => self.sys_sched_getscheduler(args[0]),
+            SCHED_GETPARAM => self.sys_sched_getparam(args[0], args[1].into()),
+            SETPRIORITY => self.sys_setpriority(args[0], args[1], args[2] as i32),
+            GETPRIORITY => self.sys_getpriority(args[0], args[1]),
+            SCHED_SETAFFINITY => {
+                self.sys_sched_setaffinity(args[0], args[1], args[2].into())
+                    .await
+            }
             SCHED_GETAFFINITY => self.sys_sched_getaffinity(args[0], args[1], args[2].into()),
+            GETCPU => self.sys_getcpu(args[0].into(), args[1].into()),
             // Resource
             GETRUSAGE => self.sys_getrusage(args[0] as _, args[1].into()),
             PRLIMIT64 => self.sys_prlimit64(args[0], args[1] as _, args[2].into(), args[3].into()),
@@ -308,12 +385,15 @@ impl<'a> Syscall<'a> {
             SETSOCKOPT => self.sys_setsockopt(args[0], args[1], args[2], args[3], args[4]),
             GETSOCKOPT => self.sys_getsockopt(args[0], args[1], args[2], args[3], args[4]),
             SHUTDOWN => self.sys_shutdown(args[0], args[1]),
-            SOCKETPAIR => self.sys_socketpair(args[0], args[1], args[2], args[3].into()),
+            SOCKETPAIR => self.sys_socketpair(args[0], args[1] as _, args[2], args[3].into()),
             SENDMSG => self.sys_sendmsg(args[0], args[1].into(), args[2]).await,
+            RECVMSG => self.sys_recvmsg(args[0], args[1].into(), args[2]).await,
             // Miscellaneous
             UNAME => self.sys_uname(args[0].into()),
+            SETHOSTNAME => self.sys_sethostname(args[0].into(), args[1]),
             SYSLOG => self.sys_syslog(args[0], args[1].into(), args[2]),
             SYSINFO => self.sys_sysinfo(args[0].into()),
+            REBOOT => self.sys_reboot(args[0], args[1], args[2], args[3]).await,
             PERSONALITY => self.sys_do_nothing("personality"),
 
             // random
@@ -323,6 +403,14 @@ impl<'a> Syscall<'a> {
                 Ok(0)
             }
         };
+        if let Some(start) = start {
+            let cycles = get_cycles().saturating_sub(start);
+            stats::record(syscall_no, cycles as u64);
+        }
+        #[cfg(feature = "strace")]
+        if let Some(call) = call {
+            strace!("{} = {}", call, strace::decode_ret(syscall_no, &result));
+        }
         match result {
             Ok(ret) => {
                 log::info!("[syscall] {syscall_no} return val {ret:#x}");
@@ -335,6 +423,24 @@ impl<'a> Syscall<'a> {
         }
     }
 
+    /// Kill the process whose thread makes a syscall denied by its seccomp
+    /// filter, which is not executed.
+    fn seccomp_kill(&self, syscall_no: usize) -> usize {
+        log::warn!(
+            "[syscall] tid {} killed by seccomp for syscall {syscall_no}",
+            self.task.tid()
+        );
+        self.task.receive_siginfo(
+            SigInfo {
+                sig: Sig::SIGKILL,
+                code: SigInfo::KERNEL,
+                details: SigDetails::None,
+            },
+            true,
+        );
+        -(SysError::ENOSYS as isize) as usize
+    }
+
     fn sys_do_nothing(&self, name: &str) -> SyscallResult {
         log::warn!(
             "Not implemented syscall that specified to do nothing ({})",
@@ -1,13 +1,12 @@
-use alloc::{sync::Arc, vec::Vec};
-use core::intrinsics::unlikely;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{intrinsics::unlikely, mem::size_of, ptr};
 
 use addr::SockAddr;
 use log::info;
 use socket::*;
 use systype::{SysError, SysResult, SyscallResult};
-use vfs::pipefs::new_pipe;
-use vfs_core::OpenFlags;
-use virtio_drivers::PAGE_SIZE;
+use unix::UnixSocket;
+use vfs_core::{File, OpenFlags};
 
 use super::{fs::IoVec, Syscall};
 use crate::{
@@ -66,9 +65,9 @@ impl Syscall<'_> {
     /// Mark the stream socket referenced by the file descriptor `sockfd` as
     /// passive. This socket will be used later to accept connections from other
     /// (active) sockets
-    pub fn sys_listen(&self, sockfd: usize, _backlog: usize) -> SyscallResult {
+    pub fn sys_listen(&self, sockfd: usize, backlog: usize) -> SyscallResult {
         let socket = self.task.sockfd_lookup(sockfd)?;
-        socket.sk.listen()?;
+        socket.sk.listen(backlog)?;
         Ok(0)
     }
 
@@ -98,11 +97,7 @@ impl Syscall<'_> {
     pub async fn sys_accept(&self, sockfd: usize, addr: usize, addrlen: usize) -> SyscallResult {
         let task = self.task;
         let socket = task.sockfd_lookup(sockfd)?;
-
-        task.set_interruptable();
-        task.set_wake_up_signal(!*task.sig_mask_ref());
         let new_sk = socket.sk.accept().await?;
-        task.set_running();
 
         let peer_addr = new_sk.peer_addr()?;
         let peer_addr = SockAddr::from_endpoint(peer_addr);
@@ -153,7 +148,6 @@ impl Syscall<'_> {
         let task = self.task;
         let buf = buf.into_slice(&task, len)?;
         let socket = task.sockfd_lookup(sockfd)?;
-        task.set_interruptable();
         let bytes = match socket.types {
             SocketType::STREAM => {
                 if dest_addr != 0 {
@@ -171,7 +165,6 @@ impl Syscall<'_> {
             }
             _ => unimplemented!(),
         };
-        task.set_running();
         Ok(bytes)
     }
 
@@ -208,10 +201,8 @@ impl Syscall<'_> {
         );
         let mut temp = Vec::with_capacity(len);
         unsafe { temp.set_len(len) };
-        task.set_interruptable();
         // TODO: not sure if `len` is enough when call `socket.recvfrom`
         let (bytes, remote_addr) = socket.sk.recvfrom(&mut temp).await?;
-        task.set_running();
         let mut buf = buf.into_mut_slice(&task, bytes)?;
         buf[..bytes].copy_from_slice(&temp[..bytes]);
         task.write_sockaddr(src_addr, addrlen, remote_addr)?;
@@ -324,21 +315,47 @@ impl Syscall<'_> {
         Ok(0)
     }
 
+    /// Create a pair of connected unix domain sockets, whose file descriptors
+    /// are written to `sv`.
     pub fn sys_socketpair(
         &self,
-        _domain: usize,
-        _types: usize,
+        domain: usize,
+        types: i32,
         _protocol: usize,
         sv: UserWritePtr<[u32; 2]>,
     ) -> SyscallResult {
         let task = self.task;
-        let (pipe_read, pipe_write) = new_pipe(PAGE_SIZE);
-        let pipe = task.with_mut_fd_table(|table| {
-            let fd_read = table.alloc(pipe_read, OpenFlags::empty())?;
-            let fd_write = table.alloc(pipe_write, OpenFlags::empty())?;
-            Ok([fd_read as u32, fd_write as u32])
+        if SaFamily::try_from(domain as u16)? != SaFamily::AF_UNIX {
+            return Err(SysError::EOPNOTSUPP);
+        }
+        let mut types = types;
+        let mut flags = OpenFlags::empty();
+        if types & NONBLOCK != 0 {
+            types &= !NONBLOCK;
+            flags |= OpenFlags::O_NONBLOCK;
+        }
+        if types & CLOEXEC != 0 {
+            types &= !CLOEXEC;
+            flags |= OpenFlags::O_CLOEXEC;
+        }
+        let types = SocketType::try_from(types)?;
+        if !matches!(
+            types,
+            SocketType::STREAM | SocketType::DGRAM | SocketType::SEQPACKET
+        ) {
+            return Err(SysError::EOPNOTSUPP);
+        }
+        let nonblock = flags.contains(OpenFlags::O_NONBLOCK);
+        let (sk0, sk1) = UnixSocket::new_pair(types);
+        let socket0 = Arc::new(Socket::from_sock(types, Sock::Unix(sk0), nonblock));
+        let socket1 = Arc::new(Socket::from_sock(types, Sock::Unix(sk1), nonblock));
+        let fds = task.with_mut_fd_table(|table| {
+            let fd0 = table.alloc(socket0, flags)?;
+            let fd1 = table.alloc(socket1, flags)?;
+            Ok([fd0 as u32, fd1 as u32])
         })?;
-        sv.write(&task, pipe)?;
+        log::info!("[sys_socketpair] new socket pair {types:?} {flags:?} in fds {fds:?}");
+        sv.write(&task, fds)?;
         Ok(0)
     }
 }
@@ -381,7 +398,36 @@ pub struct CMsgHdr {
     type_: i32,
 }
 
+/// Type of the ancillary message at `SOL_SOCKET` level which passes file
+/// descriptors.
+const SCM_RIGHTS: i32 = 1;
+/// Max number of file descriptors passed in one `sendmsg`, the same as Linux.
+const SCM_MAX_FD: usize = 253;
+/// Set in `msg_flags` when some control data is discarded for lack of space.
+const MSG_CTRUNC: i32 = 0x8;
+/// Set in `msg_flags` when the tail of a datagram is discarded.
+const MSG_TRUNC: i32 = 0x20;
+/// Set close-on-exec flag on file descriptors received by `SCM_RIGHTS`.
+const MSG_CMSG_CLOEXEC: usize = 0x40000000;
+
+impl CMsgHdr {
+    /// Length of an ancillary message with `len` bytes of data, without
+    /// padding at the end.
+    const fn msg_len(len: usize) -> usize {
+        size_of::<Self>() + len
+    }
+
+    /// Length of an ancillary message with `len` bytes of data, including
+    /// padding to align the next one.
+    const fn space(len: usize) -> usize {
+        Self::msg_len(len).next_multiple_of(size_of::<usize>())
+    }
+}
+
 impl Syscall<'_> {
+    /// Send the data in `msg_iov`. For unix domain sockets, files referred to
+    /// by `SCM_RIGHTS` messages in `msg_control` are passed along, and the
+    /// data is sent as a single message.
     pub async fn sys_sendmsg(
         &self,
         sockfd: usize,
@@ -395,11 +441,31 @@ impl Syscall<'_> {
         let task = self.task;
         let socket = task.sockfd_lookup(sockfd)?;
         let message = msg.read(&task)?;
+        let iovs = UserReadPtr::<IoVec>::from(message.iov).read_array(&task, message.iovlen)?;
+        if let Sock::Unix(unix) = &socket.sk {
+            let files = if message.controllen != 0 {
+                let control = UserReadPtr::<u8>::from(message.control)
+                    .into_slice(&task, message.controllen)?;
+                task.scm_rights_files(&control)?
+            } else {
+                Vec::new()
+            };
+            let mut buf = Vec::new();
+            for iov in iovs.iter().filter(|iov| iov.len != 0) {
+                let data = UserReadPtr::<u8>::from(iov.base).into_slice(&task, iov.len)?;
+                buf.extend_from_slice(&data);
+            }
+            log::info!(
+                "[sys_sendmsg] send {} bytes with {} files",
+                buf.len(),
+                files.len()
+            );
+            return unix.send(&buf, files).await;
+        }
         if message.controllen != 0 {
             log::warn!("[sys_sendmsg] unsupport msg control");
         }
         let addr = task.read_sockaddr(message.name, message.namelen as _)?;
-        let iovs = UserReadPtr::<IoVec>::from(message.iov).read_array(&task, message.iovlen)?;
         let mut total_len = 0;
         for (i, iov) in iovs.iter().enumerate() {
             if unlikely(iov.len == 0) {
@@ -414,39 +480,115 @@ impl Syscall<'_> {
         Ok(total_len)
     }
 
-    /// 目前的实现是，如果Udp Socket收到多个不同Ip地址的数据报如ip1, ip1, ip2,
-    /// ip3, ip1，recvmsg
-    // pub async fn sys_recvmsg(
-    //     &self,
-    //     sockfd: usize,
-    //     msg: UserRdWrPtr<MsgHdr>,
-    //     flags: usize,
-    // ) -> SyscallResult {
-    //     if flags != 0 {
-    //         log::error!("[sys_sendmsg] unsupported flags {flags}");
-    //     }
-    //     // TODO: support flags
-    //     let task = self.task;
-    //     let socket = task.sockfd_lookup(sockfd)?;
-    //     let msg = msg.read(&task)?;
-    //     if msg.controllen != 0 {
-    //         log::warn!("[sys_sendmsg] unsupport msg control");
-    //     }
-    //     let addr = task.read_sockaddr(msg.name, msg.namelen as _)?;
-    //     let iovs = UserReadPtr::<IoVec>::from(msg.iov).read_array(&task,
-    // msg.iovlen)?;     let mut total_len = 0;
-    //     for (i, iov) in iovs.iter().enumerate() {
-    //         if unlikely(iov.len == 0) {
-    //             continue;
-    //         }
-    //         let ptr = UserWritePtr::<u8>::from(iov.base);
-    //         log::info!("[sys_recvmsg] iov #{i}, ptr: {ptr}, len: {}", iov.len);
-    //         let buf = ptr.into_mut_slice(&task, iov.len)?;
-    //         let send_len = socket.sk.sendto(&buf, Some(addr)).await?;
-    //         total_len += send_len;
-    //     }
-    //     Ok(total_len)
-    // }
+    /// Receive into `msg_iov`. For unix domain sockets, files passed along
+    /// are installed into the lowest free file descriptors, which are reported
+    /// by a `SCM_RIGHTS` message in `msg_control`. Files that do not fit in
+    /// `msg_control` are closed, and `MSG_CTRUNC` is set.
+    pub async fn sys_recvmsg(
+        &self,
+        sockfd: usize,
+        msg: UserRdWrPtr<MsgHdr>,
+        flags: usize,
+    ) -> SyscallResult {
+        if flags & !MSG_CMSG_CLOEXEC != 0 {
+            log::error!("[sys_recvmsg] unsupported flags {flags}");
+        }
+        let task = self.task;
+        let socket = task.sockfd_lookup(sockfd)?;
+        // `msg` is consumed by reading, and written back after receiving
+        let msg_addr = msg.as_usize();
+        let mut message = msg.read(&task)?;
+        let iovs = UserReadPtr::<IoVec>::from(message.iov).read_array(&task, message.iovlen)?;
+        let mut buf = vec![0; iovs.iter().map(|iov| iov.len).sum()];
+        message.flags = 0;
+        let (len, files) = match &socket.sk {
+            Sock::Unix(unix) => {
+                let recv = unix.recv(&mut buf).await?;
+                if recv.truncated {
+                    message.flags |= MSG_TRUNC;
+                }
+                // the peer is always unnamed
+                message.namelen = 0;
+                (recv.len, recv.files)
+            }
+            sk => {
+                let (len, addr) = sk.recvfrom(&mut buf).await?;
+                if message.name != 0 {
+                    // `msg_namelen` follows `msg_name`
+                    let namelen = msg_addr + size_of::<usize>();
+                    task.write_sockaddr(message.name, namelen, addr)?;
+                    message.namelen = UserReadPtr::<u32>::from(namelen).read(&task)?;
+                }
+                (len, Vec::new())
+            }
+        };
+
+        let mut copied = 0;
+        for iov in iovs.iter() {
+            if copied == len {
+                break;
+            }
+            let n = iov.len.min(len - copied);
+            if n == 0 {
+                continue;
+            }
+            let mut data = UserWritePtr::<u8>::from(iov.base).into_mut_slice(&task, n)?;
+            data.copy_from_slice(&buf[copied..copied + n]);
+            copied += n;
+        }
+
+        let fd_flags = if flags & MSG_CMSG_CLOEXEC != 0 {
+            OpenFlags::O_CLOEXEC
+        } else {
+            OpenFlags::empty()
+        };
+        self.put_scm_rights(&mut message, files, fd_flags)?;
+        UserWritePtr::<MsgHdr>::from(msg_addr).write(&task, message)?;
+        Ok(len)
+    }
+
+    /// Install `files` and write their file descriptors as a `SCM_RIGHTS`
+    /// message to the control buffer of `message`, whose `msg_controllen` is
+    /// updated to the length written.
+    fn put_scm_rights(
+        &self,
+        message: &mut MsgHdr,
+        files: Vec<Arc<dyn File>>,
+        flags: OpenFlags,
+    ) -> SysResult<()> {
+        let task = self.task;
+        let nfiles = files.len();
+        let capacity = message.controllen.saturating_sub(size_of::<CMsgHdr>()) / size_of::<i32>();
+        let mut fds: Vec<i32> = Vec::new();
+        for file in files.into_iter().take(capacity) {
+            match task.with_mut_fd_table(|table| table.alloc(file, flags)) {
+                Ok(fd) => fds.push(fd as i32),
+                Err(_) => break,
+            }
+        }
+        if fds.len() < nfiles {
+            log::warn!(
+                "[put_scm_rights] only {} of {nfiles} files are received",
+                fds.len()
+            );
+            message.flags |= MSG_CTRUNC;
+        }
+        if fds.is_empty() {
+            message.controllen = 0;
+            return Ok(());
+        }
+        let data_len = fds.len() * size_of::<i32>();
+        let cmsg = CMsgHdr {
+            len: CMsgHdr::msg_len(data_len),
+            level: SocketLevel::SOL_SOCKET as i32,
+            type_: SCM_RIGHTS,
+        };
+        UserWritePtr::<CMsgHdr>::from(message.control).write(&task, cmsg)?;
+        UserWritePtr::<i32>::from(message.control + size_of::<CMsgHdr>())
+            .write_array(&task, &fds)?;
+        message.controllen = CMsgHdr::space(data_len).min(message.controllen);
+        Ok(())
+    }
 
     pub fn sys_sendmmsg(&self, sockfd: usize) -> SyscallResult {
         Ok(0)
@@ -454,6 +596,47 @@ impl Syscall<'_> {
 }
 
 impl Task {
+    /// Look up the files referred to by `SCM_RIGHTS` messages in the ancillary
+    /// data `control` of `sendmsg`, where messages of other types are ignored.
+    fn scm_rights_files(&self, control: &[u8]) -> SysResult<Vec<Arc<dyn File>>> {
+        let mut fds = Vec::new();
+        let mut offset = 0;
+        while offset + size_of::<CMsgHdr>() <= control.len() {
+            let cmsg = unsafe { ptr::read_unaligned(control[offset..].as_ptr() as *const CMsgHdr) };
+            if cmsg.len < size_of::<CMsgHdr>() || cmsg.len > control.len() - offset {
+                return Err(SysError::EINVAL);
+            }
+            let data = &control[offset + size_of::<CMsgHdr>()..offset + cmsg.len];
+            if cmsg.level == SocketLevel::SOL_SOCKET as i32 && cmsg.type_ == SCM_RIGHTS {
+                fds.extend(
+                    data.chunks_exact(size_of::<i32>())
+                        .map(|fd| i32::from_ne_bytes(fd.try_into().unwrap())),
+                );
+                if fds.len() > SCM_MAX_FD {
+                    return Err(SysError::EINVAL);
+                }
+            } else {
+                log::warn!(
+                    "[scm_rights_files] ignore cmsg level {} type {}",
+                    cmsg.level,
+                    cmsg.type_
+                );
+            }
+            offset += CMsgHdr::space(cmsg.len - size_of::<CMsgHdr>());
+        }
+        self.with_fd_table(|table| {
+            fds.iter()
+                .map(|&fd| {
+                    if fd < 0 {
+                        Err(SysError::EBADF)
+                    } else {
+                        table.get_file(fd as usize)
+                    }
+                })
+                .collect()
+        })
+    }
+
     fn sockfd_lookup(&self, sockfd: usize) -> SysResult<Arc<Socket>> {
         self.with_fd_table(|table| table.get_file(sockfd))?
             .downcast_arc::<Socket>()
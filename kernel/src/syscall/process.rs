@@ -2,20 +2,64 @@
 
 use alloc::{
     string::{String, ToString},
+    sync::Arc,
+    vec,
     vec::Vec,
 };
 
-use async_utils::{suspend_now, yield_now};
+use async_utils::{suspend_now, yield_now, Select2Futures};
 use memory::VirtAddr;
-use signal::sigset::SigSet;
-use systype::{SysError, SysResult, SyscallResult};
+use signal::{
+    siginfo::{SigDetails, SigInfo},
+    sigset::{Sig, SigSet},
+};
+use systype::{Rusage, SysError, SysResult, SyscallResult};
+use vfs::devfs::tty::{TIOCGSID, TIOCNOTTY, TIOCSCTTY};
+use vfs_core::{AccessMode, File, Inode};
 
 use super::Syscall;
 use crate::{
     mm::{UserReadPtr, UserWritePtr},
-    task::{spawn_user_task, PGid, Pid, PROCESS_GROUP_MANAGER, TASK_MANAGER},
+    task::{
+        cred::NGROUPS_MAX,
+        exec_args::ExecArgs,
+        seccomp::{
+            Allowlist, Seccomp, SECCOMP_MODE_ALLOWLIST, SECCOMP_MODE_FILTER, SECCOMP_MODE_STRICT,
+        },
+        signal::{IntrBySignalFuture, StopEvent},
+        spawn_user_task, PGid, Pid, Task, VforkDone, PROCESS_GROUP_MANAGER, SESSION_MANAGER,
+        TASK_MANAGER,
+    },
 };
 
+/// Size of the head of a file read by execve(2) to look for `#!`.
+const BINPRM_BUF_SIZE: usize = 256;
+
+/// Max depth of script interpreters which are scripts themselves.
+const MAX_INTERP_DEPTH: usize = 4;
+
+/// Parse the `#!` line at the head of a script into the interpreter and its
+/// optional argument, which is the rest of the line as on Linux.
+///
+/// Return `None` if `head` does not start with `#!`.
+fn parse_shebang(head: &[u8]) -> SysResult<Option<(String, Option<String>)>> {
+    let Some(line) = head.strip_prefix(b"#!") else {
+        return Ok(None);
+    };
+    let line = line.split(|&c| c == b'\n').next().unwrap();
+    let line = core::str::from_utf8(line).map_err(|_| SysError::ENOEXEC)?;
+    let is_blank = |c: char| c == ' ' || c == '\t';
+    let line = line.trim_matches(is_blank);
+    let (interp, arg) = match line.split_once(is_blank) {
+        Some((interp, arg)) => (interp, Some(arg.trim_matches(is_blank))),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(SysError::ENOEXEC);
+    }
+    Ok(Some((interp.to_string(), arg.map(ToString::to_string))))
+}
+
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     /// Defined in <bits/sched.h>
@@ -30,6 +74,8 @@ bitflags! {
         const SIGHAND = 0x00000800;
         /// Set if a pidfd should be placed in parent.
         const PIDFD = 0x00001000;
+        /// Set if the parent wants the child to wake it up on mm_release.
+        const VFORK = 0x00004000;
         /// Set if we want to have the same parent as the cloner.
         const PARENT = 0x00008000;
         /// Set to add to same thread group.
@@ -73,11 +119,48 @@ bitflags! {
         const WNOHANG = 0x00000001;
         /// Report status of stopped children.
         const WUNTRACED = 0x00000002;
+        /// Report stopped child (same as WUNTRACED).
+        const WSTOPPED = 0x00000002;
+        /// Report dead child.
+        const WEXITED = 0x00000004;
         /// Report continued child.
         const WCONTINUED = 0x00000008;
+        /// Don't reap, just poll status.
+        const WNOWAIT = 0x01000000;
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+enum WaitFor {
+    // wait for any child process in the specific process group
+    PGid(PGid),
+    // wait for any child process
+    AnyChild,
+    // wait for any child process in the same process group of the calling process
+    AnyChildInGroup,
+    // wait for the child process with the specific pid
+    Pid(Pid),
+}
+
+/// `siginfo_t` filled by waitid(2) for SIGCHLD, see <bits/types/siginfo_t.h>.
+#[derive(Default, Clone, Copy)]
+#[repr(C)]
+pub struct WaitIdInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad0: i32,
+    pub si_pid: i32,
+    pub si_uid: u32,
+    pub si_status: i32,
+    _pad1: i32,
+    /// User time consumed, in clock ticks.
+    pub si_utime: i64,
+    /// System time consumed, in clock ticks.
+    pub si_stime: i64,
+    _pad: [u64; 10],
+}
+
 impl Syscall<'_> {
     /// _exit() system call terminates only the calling thread, and actions such
     /// as reparenting child processes or sending SIGCHLD to the parent
@@ -126,137 +209,264 @@ impl Syscall<'_> {
 
     /// NOTE: A thread can, and by default will, wait on children of other
     /// threads in the same thread group.
-    // TODO: More options and process group support.
     // PERF: use event bus to notify this task when child exits
     pub async fn sys_wait4(
         &self,
         pid: i32,
         wstatus: UserWritePtr<i32>,
         option: i32,
-        _rusage: usize,
+        rusage: UserWritePtr<Rusage>,
     ) -> SyscallResult {
         let task = self.task;
-        let option = WaitOptions::from_bits_truncate(option);
-        #[derive(Debug)]
-        enum WaitFor {
-            // wait for any child process in the specific process group
-            PGid(PGid),
-            // wait for any child process
-            AnyChild,
-            // wait for any child process in the same process group of the calling process
-            AnyChildInGroup,
-            // wait for the child process with the specific pid
-            Pid(Pid),
-        }
+        // wait4 always waits for terminated children
+        let option = WaitOptions::from_bits_truncate(option) | WaitOptions::WEXITED;
         let target = match pid {
             -1 => WaitFor::AnyChild,
             0 => WaitFor::AnyChildInGroup,
             p if p > 0 => WaitFor::Pid(p as Pid),
-            p => WaitFor::PGid(p as PGid),
+            p => WaitFor::PGid(-p as PGid),
         };
         log::info!("[sys_wait4] target: {target:?}, option: {option:?}");
 
-        let res_task = {
-            // 首先检查一遍等待的进程是否已经是zombie了
-            let children = task.children();
-            if children.is_empty() {
-                log::info!("[sys_wait4] fail: no child");
-                return Err(SysError::ECHILD);
+        let Some((child, event)) = self.do_wait(target, option).await? else {
+            return Ok(0);
+        };
+        let child_pid = child.pid();
+        if rusage.not_null() {
+            rusage.write(&task, child.get_process_rusage())?;
+        }
+        if let Some(event) = event {
+            // The child is stopped or continued, report it only once and do not reap it
+            if let StopEvent::Traced(_) = event {
+                child.ptrace_event(true);
+            } else {
+                child.with_mut_stop_event(|e| *e = None);
+            }
+            if wstatus.not_null() {
+                log::debug!("[sys_wait4] wstatus: {:#x}", event.wstatus());
+                wstatus.write(&task, event.wstatus())?;
+            }
+            return Ok(event.reported_id(&child));
+        }
+
+        if wstatus.not_null() {
+            // wstatus stores signal in the lowest 8 bits and exit code in higher 8 bits
+            // wstatus macros can be found in <bits/waitstatus.h>
+            let exit_code = child.exit_code();
+            log::debug!("[sys_wait4] wstatus: {exit_code:#x}");
+            wstatus.write(&task, exit_code)?;
+        }
+        self.reap_child(&child);
+        Ok(child_pid)
+    }
+
+    /// The waitid() system call provides more precise control over which child
+    /// state changes to wait for.
+    ///
+    /// - `idtype`: P_PID waits for the child whose process ID matches `id`,
+    ///   P_PGID waits for any child whose process group ID matches `id`, and
+    ///   P_ALL waits for any child, `id` is ignored.
+    /// - `options`: at least one of WEXITED, WSTOPPED and WCONTINUED must be
+    ///   specified. WNOWAIT leaves the child in a waitable state, so that a
+    ///   later wait call can be used to again retrieve the child status
+    ///   information.
+    ///
+    /// On success, returns 0. If WNOHANG was specified and there were no
+    /// children in a waitable state, returns 0 with `si_pid` zeroed.
+    pub async fn sys_waitid(
+        &self,
+        idtype: i32,
+        id: usize,
+        infop: UserWritePtr<WaitIdInfo>,
+        options: i32,
+        rusage: UserWritePtr<Rusage>,
+    ) -> SyscallResult {
+        const P_ALL: i32 = 0;
+        const P_PID: i32 = 1;
+        const P_PGID: i32 = 2;
+
+        let task = self.task;
+        let option = WaitOptions::from_bits(options).ok_or(SysError::EINVAL)?;
+        if !option
+            .intersects(WaitOptions::WEXITED | WaitOptions::WSTOPPED | WaitOptions::WCONTINUED)
+        {
+            return Err(SysError::EINVAL);
+        }
+        let target = match idtype {
+            P_ALL => WaitFor::AnyChild,
+            P_PID => WaitFor::Pid(id as Pid),
+            P_PGID if id == 0 => WaitFor::AnyChildInGroup,
+            P_PGID => WaitFor::PGid(id as PGid),
+            _ => return Err(SysError::EINVAL),
+        };
+        log::info!("[sys_waitid] target: {target:?}, option: {option:?}");
+
+        let Some((child, event)) = self.do_wait(target, option).await? else {
+            if infop.not_null() {
+                infop.write(&task, WaitIdInfo::default())?;
+            }
+            return Ok(0);
+        };
+        if rusage.not_null() {
+            rusage.write(&task, child.get_process_rusage())?;
+        }
+        let (code, status) = match event {
+            Some(StopEvent::Stopped(sig)) => (SigInfo::CLD_STOPPED, sig.raw() as i32),
+            Some(StopEvent::Continued) => (SigInfo::CLD_CONTINUED, Sig::SIGCONT.raw() as i32),
+            Some(StopEvent::Traced(sig)) => (SigInfo::CLD_TRAPPED, sig.raw() as i32),
+            None => {
+                let exit_code = child.exit_code();
+                if exit_code & 0x7F == 0 {
+                    (SigInfo::CLD_EXITED, (exit_code >> 8) & 0xFF)
+                } else if exit_code & 0x80 != 0 {
+                    (SigInfo::CLD_DUMPED, exit_code & 0x7F)
+                } else {
+                    (SigInfo::CLD_KILLED, exit_code & 0x7F)
+                }
+            }
+        };
+        if infop.not_null() {
+            let (utime, stime) = child.get_process_ustime();
+            let info = WaitIdInfo {
+                si_signo: Sig::SIGCHLD.raw() as i32,
+                si_code: code,
+                si_pid: event.map_or(child.pid(), |e| e.reported_id(&child)) as i32,
+                si_status: status,
+                si_utime: utime.as_millis() as i64 / 10,
+                si_stime: stime.as_millis() as i64 / 10,
+                ..Default::default()
+            };
+            infop.write(&task, info)?;
+        }
+        if option.contains(WaitOptions::WNOWAIT) {
+            return Ok(0);
+        }
+        match event {
+            Some(StopEvent::Traced(_)) => {
+                child.ptrace_event(true);
             }
+            Some(_) => child.with_mut_stop_event(|e| *e = None),
+            None => self.reap_child(&child),
+        }
+        Ok(0)
+    }
 
-            match target {
+    /// Wait until a child specified by `target` changes state as required by
+    /// `option`, and return the child with its state change. A `None` event
+    /// means the child has exited and become a zombie. Return `None` if
+    /// `WNOHANG` is specified and no child has changed state yet.
+    ///
+    /// Tracees of the caller are waited for like children, and their ptrace
+    /// stops are always reported, whether `WSTOPPED` is specified or not.
+    async fn do_wait(
+        &self,
+        target: WaitFor,
+        option: WaitOptions,
+    ) -> SysResult<Option<(Arc<Task>, Option<StopEvent>)>> {
+        let task = self.task;
+        let event_of = |child: &Arc<Task>| -> Option<Option<StopEvent>> {
+            if child.is_zombie() && child.with_thread_group(|tg| tg.len() == 1) {
+                return option.contains(WaitOptions::WEXITED).then_some(None);
+            }
+            let event = child.with_stop_event(|event| *event)?;
+            let wanted = match event {
+                StopEvent::Stopped(_) | StopEvent::Traced(_) => {
+                    option.contains(WaitOptions::WSTOPPED)
+                }
+                StopEvent::Continued => option.contains(WaitOptions::WCONTINUED),
+            };
+            wanted.then_some(Some(event))
+        };
+        let find_tracee = || -> Option<(Arc<Task>, Option<StopEvent>)> {
+            task.with_tracees(|tracees| {
+                tracees.values().filter_map(|t| t.upgrade()).find_map(|t| {
+                    let wanted = match target {
+                        WaitFor::AnyChild => true,
+                        WaitFor::Pid(pid) => t.tid() == pid,
+                        WaitFor::PGid(pgid) => t.pgid() == pgid,
+                        WaitFor::AnyChildInGroup => t.pgid() == task.pgid(),
+                    };
+                    let event = wanted.then(|| t.ptrace_event(false)).flatten()?;
+                    Some((t, Some(StopEvent::Traced(event))))
+                })
+            })
+        };
+        let find_child = || -> SysResult<Option<(Arc<Task>, Option<StopEvent>)>> {
+            if let Some(found) = find_tracee() {
+                return Ok(Some(found));
+            }
+            let children = task.children();
+            let traced = |pid: Pid| task.with_tracees(|tracees| tracees.contains_key(&pid));
+            let found = match target {
                 WaitFor::AnyChild => children
                     .values()
-                    .find(|c| c.is_zombie() && c.with_thread_group(|tg| tg.len() == 1)),
+                    .find_map(|c| event_of(c).map(|e| (c.clone(), e))),
+                WaitFor::Pid(pid) if !children.contains_key(&pid) && traced(pid) => None,
                 WaitFor::Pid(pid) => {
-                    if let Some(child) = children.get(&pid) {
-                        if child.is_zombie() && child.with_thread_group(|tg| tg.len() == 1) {
-                            Some(child)
-                        } else {
-                            None
-                        }
-                    } else {
-                        log::info!("[sys_wait4] fail: no child with pid {pid}");
+                    let Some(child) = children.get(&pid) else {
+                        log::info!("[do_wait] fail: no child with pid {pid}");
+                        return Err(SysError::ECHILD);
+                    };
+                    event_of(child).map(|e| (child.clone(), e))
+                }
+                WaitFor::PGid(_) | WaitFor::AnyChildInGroup => {
+                    let pgid = match target {
+                        WaitFor::PGid(pgid) => pgid,
+                        _ => task.pgid(),
+                    };
+                    let mut in_group = children.values().filter(|c| c.pgid() == pgid).peekable();
+                    if in_group.peek().is_none() {
+                        log::info!("[do_wait] fail: no child in process group {pgid}");
                         return Err(SysError::ECHILD);
                     }
+                    in_group.find_map(|c| event_of(c).map(|e| (c.clone(), e)))
                 }
-                WaitFor::PGid(_) => unimplemented!(),
-                WaitFor::AnyChildInGroup => unimplemented!(),
+            };
+            if found.is_none() && children.is_empty() && task.with_tracees(|t| t.is_empty()) {
+                log::info!("[do_wait] fail: no child");
+                return Err(SysError::ECHILD);
             }
-            .cloned()
+            Ok(found)
         };
 
-        if let Some(res_task) = res_task {
-            task.time_stat()
-                .update_child_time(res_task.time_stat().user_system_time());
-            if wstatus.not_null() {
-                // wstatus stores signal in the lowest 8 bits and exit code in higher 8 bits
-                // wstatus macros can be found in "bits/waitstatus.h"
-                let exit_code = res_task.exit_code();
-                log::debug!("[sys_wait4] wstatus: {exit_code:#x}");
-                wstatus.write(&task, exit_code)?;
+        loop {
+            if let Some(found) = find_child()? {
+                return Ok(Some(found));
             }
-            let tid = res_task.tid();
-            task.remove_child(tid);
-            TASK_MANAGER.remove(tid);
-            PROCESS_GROUP_MANAGER.remove(task);
-            return Ok(tid);
-        } else if option.contains(WaitOptions::WNOHANG) {
-            return Ok(0);
-        } else {
-            log::info!("[sys_wait4] waiting for sigchld");
-            // 如果等待的进程还不是zombie，那么本进程进行await，
-            // 直到等待的进程do_exit然后发送SIGCHLD信号唤醒自己
-            let (child_pid, exit_code, child_utime, child_stime) = loop {
-                task.set_interruptable();
-                task.set_wake_up_signal(!*task.sig_mask_ref() | SigSet::SIGCHLD);
-                suspend_now().await;
-                task.set_running();
-                let si = task.with_mut_sig_pending(|pending| pending.get_expect(SigSet::SIGCHLD));
-                if let Some(_info) = si {
-                    let children = task.children();
-                    let child = match target {
-                        WaitFor::AnyChild => children
-                            .values()
-                            .find(|c| c.is_zombie() && c.with_thread_group(|tg| tg.len() == 1)),
-                        WaitFor::Pid(pid) => {
-                            let child = children.get(&pid).unwrap();
-                            if child.is_zombie() && child.with_thread_group(|tg| tg.len() == 1) {
-                                Some(child)
-                            } else {
-                                None
-                            }
-                        }
-                        WaitFor::PGid(_) => unimplemented!(),
-                        WaitFor::AnyChildInGroup => unimplemented!(),
-                    };
-                    if let Some(child) = child {
-                        break (
-                            child.pid(),
-                            child.exit_code(),
-                            child.time_stat_ref().user_time(),
-                            child.time_stat_ref().sys_time(),
-                        );
-                    }
-                } else {
-                    return Err(SysError::EINTR);
-                }
-            };
-            task.time_stat()
-                .update_child_time((child_utime, child_stime));
-            if wstatus.not_null() {
-                // wstatus stores signal in the lowest 8 bits and exit code in higher 8 bits
-                // wstatus macros can be found in <bits/waitstatus.h>
-                log::trace!("[sys_wait4] wstatus: {:#x}", exit_code);
-                wstatus.write(&task, exit_code)?;
+            if option.contains(WaitOptions::WNOHANG) {
+                return Ok(None);
+            }
+            log::info!("[do_wait] waiting for sigchld");
+            // 如果等待的进程还没有状态变化，那么本进程进行await，
+            // 直到等待的进程do_exit或者被暂停/继续然后发送SIGCHLD信号唤醒自己
+            task.set_interruptable();
+            task.set_wake_up_signal(!*task.sig_mask_ref() | SigSet::SIGCHLD);
+            suspend_now().await;
+            task.set_running();
+            let si = task.with_mut_sig_pending(|pending| pending.get_expect(SigSet::SIGCHLD));
+            if si.is_none() {
+                return Err(SysError::EINTR);
             }
-            task.remove_child(child_pid);
-            TASK_MANAGER.remove(child_pid);
-            PROCESS_GROUP_MANAGER.remove(task);
-            return Ok(child_pid);
         }
     }
 
+    /// Release a zombie child after its exit status has been collected.
+    fn reap_child(&self, child: &Arc<Task>) {
+        let task = self.task;
+        let child_pid = child.pid();
+        // Times of the child include those of its waited-for descendants.
+        let (utime, stime) = child.get_process_ustime();
+        let (cutime, cstime) = child.time_stat_ref().child_user_system_time();
+        task.time_stat()
+            .update_child_time((utime + cutime, stime + cstime));
+        let usage = child.get_process_rusage();
+        let grandchildren = child.with_children_usage(|usage| *usage);
+        task.with_mut_children_usage(|u| u.accumulate(&usage, &grandchildren));
+        task.remove_child(child_pid);
+        TASK_MANAGER.remove(child_pid);
+        PROCESS_GROUP_MANAGER.remove(child);
+    }
+
     /// execve() executes the program referred to by pathname. This causes the
     /// program that is currently being run by the calling process to be
     /// replaced with a new program, with newly initialized stack, heap, and
@@ -272,44 +482,61 @@ impl Syscall<'_> {
         envp: UserReadPtr<usize>,
     ) -> SyscallResult {
         let task = self.task;
-        let mut path = path.read_cstr(&task)?;
+        let mut path = path.read_path(&task)?;
+        let mut args = ExecArgs::from_user(&task, argv, envp)?;
 
-        let read_2d_cstr = |ptr2d: UserReadPtr<usize>| -> SysResult<Vec<String>> {
-            // NOTE: On Linux, argv and envp can be specified as NULL.
-            if ptr2d.is_null() {
-                return Ok(Vec::new());
-            }
-            let ptr_vec: Vec<UserReadPtr<u8>> = ptr2d
-                .read_cvec(&task)?
-                .into_iter()
-                .map(UserReadPtr::from)
-                .collect();
-            let mut result = Vec::new();
-            for ptr in ptr_vec {
-                let str = ptr.read_cstr(&task)?;
-                result.push(str);
-            }
-            Ok(result)
-        };
-
-        let mut argv = read_2d_cstr(argv)?;
-        let envp = read_2d_cstr(envp)?;
-
-        log::info!("[sys_execve]: path: {path:?}, argv: {argv:?}, envp: {envp:?}",);
+        log::info!(
+            "[sys_execve]: path: {path:?}, argv: {:?}, envc: {}",
+            args.args_lossy(),
+            args.envc()
+        );
 
-        if path.ends_with(".sh") {
-            path = "/busybox".to_string();
-            argv.insert(0, "busybox".to_string());
-            argv.insert(1, "sh".to_string());
+        // The interpreter of a script may be a script itself, up to a limited depth.
+        let execfn = path.clone();
+        for _ in 0..=MAX_INTERP_DEPTH {
+            let dentry = task.resolve_path(&path)?;
+            task.with_cred(|cred| cred.check_access(&dentry.inode()?, AccessMode::EXEC, false))?;
+            let file = dentry.open()?;
+            let mut head = [0; BINPRM_BUF_SIZE];
+            let len = file.read_at(0, &mut head).await?;
+            match parse_shebang(&head[..len])? {
+                Some((interp, arg)) => {
+                    log::info!("[sys_execve]: script {path}, interp: {interp}, arg: {arg:?}");
+                    args.remove_arg0();
+                    let mut interp_args = vec![interp.as_str()];
+                    interp_args.extend(arg.as_deref());
+                    interp_args.push(&path);
+                    args.prepend_args(&interp_args)?;
+                    path = interp;
+                }
+                // Scripts of the test suites may have no `#!` line.
+                None if path.ends_with(".sh") => {
+                    path = "/busybox".to_string();
+                    args.prepend_args(&["busybox", "sh"])?;
+                }
+                None => {
+                    let elf_data = file.read_all().await?;
+                    task.do_execve(file, &elf_data, &execfn, args)?;
+                    // A tracee stops after a successful execve(2), so that the
+                    // tracer may look at the new program before it runs.
+                    if task.is_ptraced() {
+                        task.receive_siginfo(
+                            SigInfo {
+                                sig: Sig::SIGTRAP,
+                                code: SigInfo::KERNEL,
+                                details: SigDetails::None,
+                            },
+                            true,
+                        );
+                    }
+                    return Ok(0);
+                }
+            }
         }
-
-        let file = task.resolve_path(&path)?.open()?;
-        let elf_data = file.read_all().await?;
-        task.do_execve(file, &elf_data, argv, envp);
-        Ok(0)
+        Err(SysError::ELOOP)
     }
 
-    pub fn sys_clone(
+    pub async fn sys_clone(
         &self,
         flags: usize,
         stack: VirtAddr,
@@ -324,8 +551,12 @@ impl Syscall<'_> {
         log::info!(
             "[sys_clone] flags:{flags:?}, stack:{stack:#x}, tls:{tls:?}, parent_tid:{parent_tid:?}, child_tid:{child_tid:?}"
         );
+        // the system is going down
+        if !TASK_MANAGER.admits() {
+            return Err(SysError::EAGAIN);
+        }
         let task = self.task;
-        let new_task = task.do_clone(flags);
+        let new_task = task.do_clone(flags)?;
         new_task.trap_context_mut().set_user_a0(0);
         let new_tid = new_task.tid();
         log::info!("[sys_clone] clone a new thread, tid {new_tid}, clone flags {flags:?}",);
@@ -333,20 +564,50 @@ impl Syscall<'_> {
         if !stack.is_null() {
             new_task.trap_context_mut().set_user_sp(stack.bits());
         }
-        if flags.contains(CloneFlags::PARENT_SETTID) {
-            UserWritePtr::from(parent_tid.bits()).write(task, new_tid)?;
-        }
-        if flags.contains(CloneFlags::CHILD_SETTID) {
-            UserWritePtr::from(child_tid.bits()).write(&new_task, new_tid)?;
+        // NOTE: tids are stored as 32-bit `pid_t`, and failures of the stores are
+        // ignored as Linux does, for the child has been created
+        if flags.contains(CloneFlags::CHILD_SETTID) && !child_tid.is_null() {
+            // Stored in the memory of the child before it runs, which is a copy of
+            // the parent's one unless `CLONE_VM` is set.
             new_task.tid_address().set_child_tid = Some(child_tid.bits());
+            if let Err(e) = UserWritePtr::from(child_tid.bits()).write(&new_task, new_tid as u32) {
+                log::warn!("[sys_clone] can not store tid at child_tid {child_tid:?}: {e:?}");
+            }
         }
-        if flags.contains(CloneFlags::CHILD_CLEARTID) {
+        if flags.contains(CloneFlags::CHILD_CLEARTID) && !child_tid.is_null() {
             new_task.tid_address().clear_child_tid = Some(child_tid.bits());
         }
+        if flags.contains(CloneFlags::PARENT_SETTID) && !parent_tid.is_null() {
+            // Stored in the memory of the parent only, which is also the child's
+            // one if `CLONE_VM` is set.
+            if let Err(e) = UserWritePtr::from(parent_tid.bits()).write(task, new_tid as u32) {
+                log::warn!("[sys_clone] can not store tid at parent_tid {parent_tid:?}: {e:?}");
+            }
+        }
         if flags.contains(CloneFlags::SETTLS) {
             new_task.trap_context_mut().set_user_tp(tls.bits());
         }
-        spawn_user_task(new_task);
+        if flags.contains(CloneFlags::VFORK) {
+            // The child usually runs on the memory and even the stack of the parent
+            // by `CLONE_VM`, so the parent is suspended until the child execs or
+            // exits. Whatever the child does to the user memory, e.g. returning
+            // from the function calling vfork, only the parent process suffers.
+            let done = Arc::new(VforkDone::new());
+            new_task.set_vfork_done(done.clone());
+            spawn_user_task(new_task);
+            // Only SIGKILL ends the wait, other signals are handled once the
+            // child releases the memory.
+            task.set_interruptable();
+            task.set_wake_up_signal(SigSet::SIGKILL);
+            let intr_future = IntrBySignalFuture {
+                task: task.clone(),
+                mask: !SigSet::SIGKILL,
+            };
+            Select2Futures::new(done.wait(), intr_future).await;
+            task.set_running();
+        } else {
+            spawn_user_task(new_task);
+        }
         Ok(new_tid)
     }
 
@@ -355,6 +616,24 @@ impl Syscall<'_> {
         Ok(0)
     }
 
+    /// unshare() allows a process (or thread) to disassociate parts of its
+    /// execution context that are currently being shared with other processes
+    /// (or threads).
+    ///
+    /// Only `CLONE_FILES` and `CLONE_FS` take effect, while `CLONE_SYSVSEM` is
+    /// accepted since semaphore adjustments are never shared. Namespaces are
+    /// not supported.
+    pub fn sys_unshare(&self, flags: usize) -> SyscallResult {
+        let flags = CloneFlags::from_bits(flags as u64).ok_or(SysError::EINVAL)?;
+        log::info!("[sys_unshare] flags: {flags:?}");
+        if !(CloneFlags::FILES | CloneFlags::FS | CloneFlags::SYSVSEM).contains(flags) {
+            log::warn!("[sys_unshare] unsupported flags {flags:?}");
+            return Err(SysError::EINVAL);
+        }
+        self.task.unshare(flags);
+        Ok(0)
+    }
+
     /// The system call set_tid_address() sets the clear_child_tid value for the
     /// calling thread to tidptr.
     ///
@@ -373,7 +652,7 @@ impl Syscall<'_> {
     pub fn sys_set_tid_address(&self, tidptr: usize) -> SyscallResult {
         let task = self.task;
         log::info!("[sys_set_tid_address] tidptr:{tidptr:#x}");
-        task.tid_address().clear_child_tid = Some(tidptr);
+        task.tid_address().clear_child_tid = (tidptr != 0).then_some(tidptr);
         Ok(task.tid())
     }
 
@@ -399,37 +678,313 @@ impl Syscall<'_> {
     /// session (see setsid(2) and credentials(7)). In this case, the pgid
     /// specifies an existing process group to be joined and the session ID
     /// of that group must match the session ID of the joining process.
+    ///
+    /// The caller can only change the group of itself or a child in its
+    /// session which has not executed a program yet, and a session leader can
+    /// not change its group.
     pub fn sys_setpgid(&self, pid: usize, pgid: usize) -> SyscallResult {
+        let caller = self.task.leader();
+        if (pgid as isize) < 0 {
+            return Err(SysError::EINVAL);
+        }
         let target_task = if pid == 0 {
-            self.task.clone()
+            caller.clone()
         } else {
             TASK_MANAGER.get(pid).ok_or(SysError::ESRCH)?
         };
-
-        if pgid == 0 {
-            PROCESS_GROUP_MANAGER.add_group(&target_task);
-        } else {
-            if PROCESS_GROUP_MANAGER.get_group(pgid).is_none() {
-                PROCESS_GROUP_MANAGER.add_group(&target_task);
-            } else {
-                PROCESS_GROUP_MANAGER.add_process(pgid, &target_task);
+        if !target_task.is_leader() {
+            return Err(SysError::EINVAL);
+        }
+        let is_child = target_task
+            .parent()
+            .and_then(|parent| parent.upgrade())
+            .is_some_and(|parent| Arc::ptr_eq(&parent, &caller));
+        if is_child {
+            if target_task.sid() != caller.sid() {
+                return Err(SysError::EPERM);
+            }
+            if target_task.execed() {
+                return Err(SysError::EACCES);
             }
+        } else if !Arc::ptr_eq(&target_task, &caller) {
+            return Err(SysError::ESRCH);
         }
+        if target_task.is_session_leader() {
+            return Err(SysError::EPERM);
+        }
+
+        let pid = target_task.pid();
+        let pgid = if pgid == 0 { pid } else { pgid };
+        // A process can only form a group of its own pid, or join an existing
+        // one in the same session.
+        if pgid != pid && PROCESS_GROUP_MANAGER.session_of(pgid) != Some(caller.sid()) {
+            return Err(SysError::EPERM);
+        }
+        PROCESS_GROUP_MANAGER.add_process(pgid, &target_task);
         Ok(0)
     }
 
-    // TODO:
+    /// getsid() returns the session ID of the process specified by pid, or the
+    /// calling process if pid is zero.
+    pub fn sys_getsid(&self, pid: usize) -> SyscallResult {
+        let target_task = if pid == 0 {
+            self.task.clone()
+        } else {
+            TASK_MANAGER.get(pid).ok_or(SysError::ESRCH)?
+        };
+        Ok(target_task.sid())
+    }
+
     pub fn sys_getuid(&self) -> SyscallResult {
-        Ok(0)
+        Ok(self.task.with_cred(|cred| cred.user.real) as usize)
     }
 
-    // TODO:
     pub fn sys_geteuid(&self) -> SyscallResult {
+        Ok(self.task.with_cred(|cred| cred.user.effective) as usize)
+    }
+
+    pub fn sys_getgid(&self) -> SyscallResult {
+        Ok(self.task.with_cred(|cred| cred.group.real) as usize)
+    }
+
+    pub fn sys_getegid(&self) -> SyscallResult {
+        Ok(self.task.with_cred(|cred| cred.group.effective) as usize)
+    }
+
+    /// getresuid() returns the real UID, the effective UID, and the saved
+    /// set-user-ID of the calling process, in the arguments ruid, euid, and
+    /// suid, respectively.
+    pub fn sys_getresuid(
+        &self,
+        ruid: UserWritePtr<u32>,
+        euid: UserWritePtr<u32>,
+        suid: UserWritePtr<u32>,
+    ) -> SyscallResult {
+        let task = self.task;
+        let ids = task.with_cred(|cred| cred.user);
+        ruid.write(task, ids.real)?;
+        euid.write(task, ids.effective)?;
+        suid.write(task, ids.saved)?;
+        Ok(0)
+    }
+
+    pub fn sys_getresgid(
+        &self,
+        rgid: UserWritePtr<u32>,
+        egid: UserWritePtr<u32>,
+        sgid: UserWritePtr<u32>,
+    ) -> SyscallResult {
+        let task = self.task;
+        let ids = task.with_cred(|cred| cred.group);
+        rgid.write(task, ids.real)?;
+        egid.write(task, ids.effective)?;
+        sgid.write(task, ids.saved)?;
         Ok(0)
     }
 
+    /// setuid() sets the effective user ID of the calling process. If the
+    /// calling process is privileged, the real UID and saved set-user-ID are
+    /// also set.
+    pub fn sys_setuid(&self, uid: u32) -> SyscallResult {
+        self.task.with_mut_cred(|cred| {
+            let privileged = cred.is_privileged();
+            cred.user.set(uid, privileged)
+        })?;
+        Ok(0)
+    }
+
+    pub fn sys_setgid(&self, gid: u32) -> SyscallResult {
+        self.task.with_mut_cred(|cred| {
+            let privileged = cred.is_privileged();
+            cred.group.set(gid, privileged)
+        })?;
+        Ok(0)
+    }
+
+    /// setreuid() sets real and effective user IDs of the calling process,
+    /// where -1 leaves the ID unchanged.
+    pub fn sys_setreuid(&self, ruid: u32, euid: u32) -> SyscallResult {
+        self.task.with_mut_cred(|cred| {
+            let privileged = cred.is_privileged();
+            cred.user.set_re(ruid, euid, privileged)
+        })?;
+        Ok(0)
+    }
+
+    pub fn sys_setregid(&self, rgid: u32, egid: u32) -> SyscallResult {
+        self.task.with_mut_cred(|cred| {
+            let privileged = cred.is_privileged();
+            cred.group.set_re(rgid, egid, privileged)
+        })?;
+        Ok(0)
+    }
+
+    /// setresuid() sets the real user ID, the effective user ID, and the saved
+    /// set-user-ID of the calling process, where -1 leaves the ID unchanged.
+    pub fn sys_setresuid(&self, ruid: u32, euid: u32, suid: u32) -> SyscallResult {
+        self.task.with_mut_cred(|cred| {
+            let privileged = cred.is_privileged();
+            cred.user.set_res(ruid, euid, suid, privileged)
+        })?;
+        Ok(0)
+    }
+
+    pub fn sys_setresgid(&self, rgid: u32, egid: u32, sgid: u32) -> SyscallResult {
+        self.task.with_mut_cred(|cred| {
+            let privileged = cred.is_privileged();
+            cred.group.set_res(rgid, egid, sgid, privileged)
+        })?;
+        Ok(0)
+    }
+
+    /// getgroups() returns the supplementary group IDs of the calling process
+    /// in list. If size is zero, list is not modified, but the total number of
+    /// supplementary group IDs for the process is returned.
+    pub fn sys_getgroups(&self, size: usize, list: UserWritePtr<u32>) -> SyscallResult {
+        let task = self.task;
+        let groups = task.with_cred(|cred| cred.groups.clone());
+        if size == 0 {
+            return Ok(groups.len());
+        }
+        if size < groups.len() {
+            return Err(SysError::EINVAL);
+        }
+        list.write_array(task, &groups)?;
+        Ok(groups.len())
+    }
+
+    /// setgroups() sets the supplementary group IDs for the calling process,
+    /// which requires privilege.
+    pub fn sys_setgroups(&self, size: usize, list: UserReadPtr<u32>) -> SyscallResult {
+        let task = self.task;
+        if size > NGROUPS_MAX {
+            return Err(SysError::EINVAL);
+        }
+        let groups = if size == 0 {
+            Vec::new()
+        } else {
+            list.read_array(task, size)?
+        };
+        task.with_mut_cred(|cred| cred.set_groups(groups))?;
+        Ok(0)
+    }
+
+    /// setsid() creates a new session if the calling process is not a process
+    /// group leader. The calling process is the leader of the new session and
+    /// of a new process group in it, and has no controlling terminal.
     pub fn sys_setsid(&self) -> SyscallResult {
+        let process = self.task.leader();
+        let pid = process.pid();
+        if PROCESS_GROUP_MANAGER.contains(pid) {
+            return Err(SysError::EPERM);
+        }
+        process.set_sid(pid);
+        PROCESS_GROUP_MANAGER.add_group(&process);
+        Ok(pid)
+    }
+
+    /// prctl() manipulates various aspects of the behavior of the calling
+    /// thread or process. Only the seccomp options are supported, and others
+    /// do nothing.
+    ///
+    /// - `PR_GET_SECCOMP`: return the seccomp mode of the calling thread.
+    /// - `PR_SET_SECCOMP`: set the seccomp mode `arg2`, which can not be
+    ///   relaxed later. `SECCOMP_MODE_STRICT` permits only read(2), write(2),
+    ///   _exit(2) and sigreturn(2), while the Phoenix specific
+    ///   `SECCOMP_MODE_ALLOWLIST` permits syscalls in a bitmap of `arg4` u64
+    ///   words at `arg3`, where bit `n` permits the syscall numbered `n`.
+    /// - `PR_PANIC`: panic in the syscall on purpose, to test recovering from
+    ///   it. Phoenix specific, and only supported with `recover-user`.
+    ///
+    /// A thread making a syscall denied by the mode is killed by SIGKILL.
+    pub fn sys_prctl(
+        &self,
+        option: usize,
+        arg2: usize,
+        arg3: UserReadPtr<u64>,
+        arg4: usize,
+    ) -> SyscallResult {
+        const PR_GET_SECCOMP: usize = 21;
+        const PR_SET_SECCOMP: usize = 22;
+        #[cfg(feature = "recover-user")]
+        const PR_PANIC: usize = 0x5048_0000;
+
         let task = self.task;
-        Ok(task.pid())
+        match option {
+            PR_GET_SECCOMP => Ok(task.seccomp_ref().mode()),
+            PR_SET_SECCOMP => {
+                let new = match arg2 {
+                    SECCOMP_MODE_STRICT => Seccomp::Strict,
+                    SECCOMP_MODE_ALLOWLIST => {
+                        let mut bits: Allowlist = Default::default();
+                        if arg4 > bits.len() {
+                            return Err(SysError::EINVAL);
+                        }
+                        bits[..arg4].copy_from_slice(&arg3.read_array(task, arg4)?);
+                        Seccomp::Allowlist(Arc::new(bits))
+                    }
+                    SECCOMP_MODE_FILTER => {
+                        log::warn!("[sys_prctl] seccomp BPF filter is not supported");
+                        return Err(SysError::EINVAL);
+                    }
+                    _ => return Err(SysError::EINVAL),
+                };
+                task.seccomp().restrict(new)?;
+                log::info!(
+                    "[sys_prctl] tid {} seccomp mode {}",
+                    task.tid(),
+                    task.seccomp_ref().mode()
+                );
+                Ok(0)
+            }
+            #[cfg(feature = "recover-user")]
+            PR_PANIC => panic!("[sys_prctl] panic on purpose"),
+            _ => {
+                log::warn!("[sys_prctl] unsupported option {option}");
+                Ok(0)
+            }
+        }
+    }
+
+    /// Terminal ioctls on the session of the caller, which the terminal can
+    /// not resolve by itself.
+    pub fn tty_session_ioctl(&self, tty: Arc<dyn Inode>, cmd: usize, arg: usize) -> SyscallResult {
+        let process = self.task.leader();
+        let sid = process.sid();
+        let is_ctty = SESSION_MANAGER
+            .ctty(sid)
+            .is_some_and(|ctty| Arc::ptr_eq(&ctty, &tty));
+        match cmd {
+            TIOCSCTTY => {
+                if is_ctty {
+                    return Ok(0);
+                }
+                if !process.is_session_leader() {
+                    return Err(SysError::EPERM);
+                }
+                // A privileged process can take the terminal from another session
+                // by passing 1.
+                let steal = arg == 1 && process.with_cred(|cred| cred.is_privileged());
+                SESSION_MANAGER.set_ctty(sid, tty, steal)?;
+            }
+            TIOCNOTTY => {
+                if !is_ctty {
+                    return Err(SysError::ENOTTY);
+                }
+                // Only the session leader gives up the terminal of the session,
+                // while other processes have no terminal of their own to give up.
+                if process.is_session_leader() {
+                    SESSION_MANAGER.release(sid);
+                }
+            }
+            TIOCGSID => {
+                if SESSION_MANAGER.session_of(&tty) != Some(sid) {
+                    return Err(SysError::ENOTTY);
+                }
+                UserWritePtr::<u32>::from(arg).write(self.task, sid as u32)?;
+            }
+            _ => return Err(SysError::EINVAL),
+        }
+        Ok(0)
     }
 }
@@ -0,0 +1,143 @@
+use alloc::sync::Arc;
+use core::mem::size_of;
+
+use config::process::INIT_PROC_PID;
+use signal::{Sig, SigDetails, SigInfo};
+use systype::{SysError, SysResult, SyscallResult};
+
+use super::Syscall;
+use crate::{
+    mm::{UserReadPtr, UserWritePtr},
+    task::{Task, TASK_MANAGER},
+};
+
+// requests of ptrace(2)
+const PTRACE_TRACEME: usize = 0;
+const PTRACE_PEEKTEXT: usize = 1;
+const PTRACE_PEEKDATA: usize = 2;
+const PTRACE_POKETEXT: usize = 4;
+const PTRACE_POKEDATA: usize = 5;
+const PTRACE_CONT: usize = 7;
+const PTRACE_KILL: usize = 8;
+const PTRACE_GETREGS: usize = 12;
+const PTRACE_SETREGS: usize = 13;
+const PTRACE_ATTACH: usize = 16;
+const PTRACE_DETACH: usize = 17;
+const PTRACE_SYSCALL: usize = 24;
+
+impl Syscall<'_> {
+    /// Trace the thread `pid` as `request` asks. Only the requests of a minimal
+    /// debugger are supported, without any options.
+    ///
+    /// - `PTRACE_TRACEME`: be traced by the parent.
+    /// - `PTRACE_ATTACH`: trace `pid`, which is sent SIGSTOP to stop.
+    /// - `PTRACE_PEEKDATA`: read a word at `addr` of the tracee into `*data`.
+    /// - `PTRACE_POKEDATA`: write the word `data` at `addr` of the tracee.
+    /// - `PTRACE_GETREGS` and `PTRACE_SETREGS`: read or write the general
+    ///   purpose registers of the tracee at `data`.
+    /// - `PTRACE_CONT` and `PTRACE_SYSCALL`: resume the tracee with the signal
+    ///   `data` delivered, where `PTRACE_SYSCALL` also stops it at the next
+    ///   syscall entry or exit.
+    /// - `PTRACE_DETACH`: stop tracing and resume the tracee with the signal
+    ///   `data` delivered.
+    /// - `PTRACE_KILL`: kill the tracee.
+    ///
+    /// All requests but the first two need the tracee to be in a ptrace stop,
+    /// otherwise fail with ESRCH.
+    pub fn sys_ptrace(
+        &self,
+        request: usize,
+        pid: usize,
+        addr: usize,
+        data: usize,
+    ) -> SyscallResult {
+        let task = self.task;
+        log::info!("[sys_ptrace] request {request}, pid {pid}, addr {addr:#x}, data {data:#x}");
+        match request {
+            PTRACE_TRACEME => {
+                let parent = task
+                    .parent()
+                    .and_then(|p| p.upgrade())
+                    .ok_or(SysError::EPERM)?;
+                task.ptrace_attach(&parent)?;
+                return Ok(0);
+            }
+            PTRACE_ATTACH => {
+                let tracee = TASK_MANAGER.get(pid).ok_or(SysError::ESRCH)?;
+                if tracee.pid() == task.pid()
+                    || tracee.pid() == INIT_PROC_PID
+                    || !task.with_cred(|cred| tracee.with_cred(|target| cred.can_trace(target)))
+                {
+                    return Err(SysError::EPERM);
+                }
+                tracee.ptrace_attach(task)?;
+                tracee.receive_siginfo(
+                    SigInfo {
+                        sig: Sig::SIGSTOP,
+                        code: SigInfo::USER,
+                        details: SigDetails::Kill { pid: task.pid() },
+                    },
+                    true,
+                );
+                return Ok(0);
+            }
+            _ => {}
+        }
+
+        let tracee = self.stopped_tracee(pid)?;
+        match request {
+            PTRACE_PEEKTEXT | PTRACE_PEEKDATA => {
+                let mut word = [0; size_of::<usize>()];
+                tracee.ptrace_read(addr, &mut word)?;
+                UserWritePtr::<usize>::from(data).write(task, usize::from_ne_bytes(word))?;
+            }
+            PTRACE_POKETEXT | PTRACE_POKEDATA => tracee.ptrace_write(addr, &data.to_ne_bytes())?,
+            PTRACE_GETREGS => {
+                let regs = tracee.trap_context_mut().user_regs();
+                UserWritePtr::<[usize; 32]>::from(data).write(task, regs)?;
+            }
+            PTRACE_SETREGS => {
+                let regs = UserReadPtr::<[usize; 32]>::from(data).read(task)?;
+                tracee.trap_context_mut().set_user_regs(&regs);
+            }
+            PTRACE_CONT | PTRACE_SYSCALL => {
+                tracee.ptrace_resume(resume_sig(data)?, request == PTRACE_SYSCALL)
+            }
+            PTRACE_DETACH => tracee.ptrace_detach(resume_sig(data)?),
+            PTRACE_KILL => tracee.receive_siginfo(
+                SigInfo {
+                    sig: Sig::SIGKILL,
+                    code: SigInfo::KERNEL,
+                    details: SigDetails::None,
+                },
+                true,
+            ),
+            _ => {
+                log::warn!("[sys_ptrace] unsupported request {request}");
+                return Err(SysError::EIO);
+            }
+        }
+        Ok(0)
+    }
+
+    /// The thread `pid` traced by the caller, which must be in a ptrace stop.
+    fn stopped_tracee(&self, pid: usize) -> SysResult<Arc<Task>> {
+        let tracee = TASK_MANAGER.get(pid).ok_or(SysError::ESRCH)?;
+        if !tracee.is_ptraced_by(self.task) || !tracee.is_traced() {
+            return Err(SysError::ESRCH);
+        }
+        Ok(tracee)
+    }
+}
+
+/// The signal passed in `data` to resume a tracee with, where zero means none.
+fn resume_sig(data: usize) -> SysResult<Option<Sig>> {
+    if data == 0 {
+        return Ok(None);
+    }
+    let sig = Sig::from_i32(data as i32);
+    if !sig.is_valid() {
+        return Err(SysError::EIO);
+    }
+    Ok(Some(sig))
+}
@@ -1,5 +1,5 @@
+use driver::random::get_random_bytes;
 use systype::{SysError, SyscallResult};
-use vfs::devfs::urandom::RNG;
 
 use super::Syscall;
 use crate::mm::UserWritePtr;
@@ -20,7 +20,7 @@ impl Syscall<'_> {
     ) -> SyscallResult {
         let task = self.task;
         let mut buf = buf.into_mut_slice(&task, buflen)?;
-        unsafe { RNG.fill_buf(&mut buf) };
+        get_random_bytes(&mut buf);
         Ok(buf.len())
     }
 }
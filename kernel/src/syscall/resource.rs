@@ -71,30 +71,24 @@ impl Syscall<'_> {
         const RUSAGE_SELF: i32 = 0;
         const RUSAGE_CHILDREN: i32 = -1;
         const RUSAGE_THREAD: i32 = 1;
-        let mut ret = Rusage::default();
-        match who {
-            RUSAGE_SELF => {
-                let (total_utime, total_stime) = task.get_process_ustime();
-                ret.utime = total_utime.into();
-                ret.stime = total_stime.into();
-                usage.write(&task, ret)?;
-            }
-            RUSAGE_CHILDREN => {
-                log::error!("rusage children not implemented");
-                let (total_utime, total_stime) = task.get_process_ustime();
-                ret.utime = total_utime.into();
-                ret.stime = total_stime.into();
-                usage.write(&task, ret)?;
-            }
+        let ret = match who {
+            RUSAGE_SELF => task.get_process_rusage(),
+            RUSAGE_CHILDREN => task.get_children_rusage(),
             RUSAGE_THREAD => {
-                log::error!("rusage thread not implemented");
-                let (total_utime, total_stime) = task.get_process_ustime();
-                ret.utime = total_utime.into();
-                ret.stime = total_stime.into();
-                usage.write(&task, ret)?;
+                // Page faults are only counted per process
+                let (utime, stime) = task.time_stat_ref().user_system_time();
+                let (nvcsw, nivcsw) = task.time_stat_ref().context_switches();
+                Rusage {
+                    utime: utime.into(),
+                    stime: stime.into(),
+                    nvcsw,
+                    nivcsw,
+                    ..task.get_process_rusage()
+                }
             }
             _ => return Err(SysError::EINVAL),
-        }
+        };
+        usage.write(&task, ret)?;
         Ok(0)
     }
 
@@ -123,6 +117,8 @@ impl Syscall<'_> {
                     rlim_max: USER_STACK_SIZE,
                 },
                 NOFILE => task.with_fd_table(|table| table.rlimit()),
+                MEMLOCK => task.with_memory_space(|m| m.memlock_limit()),
+                CORE => task.with_memory_space(|m| m.core_limit()),
                 r => {
                     log::warn!("[sys_prlimit64] get old_limit : unimplemented {r:?}");
                     RLimit {
@@ -140,6 +136,12 @@ impl Syscall<'_> {
                 NOFILE => {
                     task.with_mut_fd_table(|table| table.set_rlimit(limit));
                 }
+                MEMLOCK => {
+                    task.with_mut_memory_space(|m| m.set_memlock_limit(limit));
+                }
+                CORE => {
+                    task.with_mut_memory_space(|m| m.set_core_limit(limit));
+                }
                 r => {
                     log::warn!("[sys_prlimit64] set new_limit : unimplemented {r:?}");
                 }
@@ -1,69 +1,211 @@
+use alloc::{sync::Arc, vec, vec::Vec};
 use core::intrinsics::size_of;
 
-use systype::{SysError, SyscallResult};
+use async_utils::yield_now;
+use config::board;
+use executor::{NICE_MAX, NICE_MIN};
+use systype::{SysError, SysResult, SyscallResult};
 
 use super::Syscall;
 use crate::{
     mm::{UserReadPtr, UserWritePtr},
-    task::{resource::CpuMask, TASK_MANAGER},
+    processor::hart::local_hart,
+    task::{resource::CpuMask, Task, PROCESS_GROUP_MANAGER, TASK_MANAGER},
 };
 
+/// The standard round-robin time-sharing policy
+const SCHED_OTHER: usize = 0;
+
+// which of setpriority(2) and getpriority(2)
+const PRIO_PROCESS: usize = 0;
+const PRIO_PGRP: usize = 1;
+const PRIO_USER: usize = 2;
+
 impl Syscall<'_> {
     pub fn sys_sched_setscheduler(&self) -> SyscallResult {
         log::warn!("[sys_sched_setscheduler] unimplemented");
         Ok(0)
     }
 
-    pub fn sys_sched_getscheduler(&self) -> SyscallResult {
-        log::warn!("[sys_sched_getscheduler] unimplemented");
+    /// Get the scheduling policy of the thread `pid`, or the calling thread if
+    /// `pid` is zero. Only SCHED_OTHER is supported.
+    pub fn sys_sched_getscheduler(&self, pid: usize) -> SyscallResult {
+        if pid != 0 && TASK_MANAGER.get(pid).is_none() {
+            return Err(SysError::ESRCH);
+        }
+        Ok(SCHED_OTHER)
+    }
+
+    /// Get the static priority of the thread `pid`, which is always zero for
+    /// SCHED_OTHER. The dynamic priority is set by nice values instead.
+    pub fn sys_sched_getparam(&self, pid: usize, param: UserWritePtr<i32>) -> SyscallResult {
+        if pid != 0 && TASK_MANAGER.get(pid).is_none() {
+            return Err(SysError::ESRCH);
+        }
+        param.write(&self.task, 0)?;
         Ok(0)
     }
 
-    pub fn sys_sched_getparam(&self) -> SyscallResult {
-        log::warn!("[sys_sched_getparam] unimplemented");
+    /// Set the nice value of the processes specified by `which` and `who`.
+    /// Nice values are clamped to `NICE_MIN..=NICE_MAX`, and lower nice gets
+    /// more cpu time.
+    ///
+    /// - `PRIO_PROCESS`: the process `who`, or the calling process if zero.
+    /// - `PRIO_PGRP`: processes in the process group `who`, or that of the
+    ///   calling process if zero.
+    /// - `PRIO_USER`: processes owned by the user `who`, or the calling user if
+    ///   zero.
+    ///
+    /// Fail with `EPERM` if the caller may not renice some of the processes,
+    /// or `EACCES` if it lowers the nice value of some of them without
+    /// privilege, in which case none of them is changed.
+    pub fn sys_setpriority(&self, which: usize, who: usize, prio: i32) -> SyscallResult {
+        let nice = prio.clamp(NICE_MIN, NICE_MAX);
+        let cred = self.task.with_cred(|cred| cred.clone());
+        let processes = self.priority_targets(which, who)?;
+        for process in processes.iter() {
+            if !process.with_cred(|target| cred.can_renice(target)) {
+                return Err(SysError::EPERM);
+            }
+            if nice < process.sched_attr().nice() && !cred.is_privileged() {
+                return Err(SysError::EACCES);
+            }
+        }
+        for process in processes {
+            log::info!("[sys_setpriority] set nice of {} to {nice}", process.pid());
+            process.with_thread_group(|tg| {
+                for thread in tg.iter() {
+                    thread.sched_attr().set_nice(nice);
+                }
+            });
+        }
         Ok(0)
     }
 
-    pub fn sys_sched_setaffinity(
+    /// Get the highest priority of the processes specified by `which` and
+    /// `who`, see `sys_setpriority`. Return `20 - nice` like the raw syscall of
+    /// Linux to avoid negative values, which is converted back by libc.
+    pub fn sys_getpriority(&self, which: usize, who: usize) -> SyscallResult {
+        let nice = self
+            .priority_targets(which, who)?
+            .iter()
+            .map(|process| process.sched_attr().nice())
+            .min()
+            .unwrap();
+        Ok((20 - nice) as usize)
+    }
+
+    fn priority_targets(&self, which: usize, who: usize) -> SysResult<Vec<Arc<Task>>> {
+        let task = self.task;
+        let processes: Vec<Arc<Task>> = match which {
+            PRIO_PROCESS => {
+                let process = if who == 0 {
+                    task.leader()
+                } else {
+                    TASK_MANAGER.get(who).ok_or(SysError::ESRCH)?.leader()
+                };
+                vec![process]
+            }
+            PRIO_PGRP => {
+                let pgid = if who == 0 { task.pgid() } else { who };
+                PROCESS_GROUP_MANAGER.processes_in_group(pgid)
+            }
+            PRIO_USER => {
+                let uid = if who == 0 {
+                    task.with_cred(|cred| cred.user.real)
+                } else {
+                    who as u32
+                };
+                TASK_MANAGER
+                    .processes()
+                    .into_iter()
+                    .filter(|process| process.with_cred(|cred| cred.user.real == uid))
+                    .collect()
+            }
+            _ => return Err(SysError::EINVAL),
+        };
+        if processes.is_empty() {
+            return Err(SysError::ESRCH);
+        }
+        Ok(processes)
+    }
+
+    /// Set the cpu affinity of the thread `pid`, or the calling thread if `pid`
+    /// is zero. The thread will only run on harts in the mask from now on.
+    ///
+    /// Like renicing, this needs the caller to be allowed to change the nice
+    /// value of the thread, otherwise EPERM is returned.
+    pub async fn sys_sched_setaffinity(
         &self,
         pid: usize,
         cpusetsize: usize,
         mask: UserReadPtr<CpuMask>,
     ) -> SyscallResult {
-        log::warn!("[sys_sched_setaffinity]");
         if cpusetsize < size_of::<CpuMask>() {
             return Err(SysError::EINVAL);
         }
-        if let Some(task) = TASK_MANAGER.get(pid) {
-            if !task.is_leader() {
-                return Err(SysError::ESRCH);
-            }
-            let mask = mask.read(&self.task)?;
-            *task.cpus_allowed() = mask;
+        let task = if pid == 0 {
+            self.task.clone()
         } else {
-            return Err(SysError::ESRCH);
+            TASK_MANAGER.get(pid).ok_or(SysError::ESRCH)?
+        };
+        let cred = self.task.with_cred(|cred| cred.clone());
+        if !task.with_cred(|target| cred.can_renice(target)) {
+            return Err(SysError::EPERM);
+        }
+        let mask = mask.read(&self.task)?.bits();
+        log::info!(
+            "[sys_sched_setaffinity] set affinity of {} to {mask:#x}",
+            task.tid()
+        );
+        if mask & online_harts() == 0 {
+            return Err(SysError::EINVAL);
+        }
+        task.sched_attr().set_affinity(mask);
+        // migrate to an allowed hart at once
+        if !task.sched_attr().allows(local_hart().hart_id()) && task.tid() == self.task.tid() {
+            yield_now().await;
         }
         Ok(0)
     }
 
+    /// Get the effective cpu affinity of the thread `pid`, or the calling
+    /// thread if `pid` is zero, i.e. the harts that it may run on. Return the
+    /// size of the mask written like the raw syscall of Linux.
     pub fn sys_sched_getaffinity(
         &self,
         pid: usize,
         cpusetsize: usize,
         mask: UserWritePtr<CpuMask>,
     ) -> SyscallResult {
-        log::warn!("[sys_sched_getaffinity]");
         if cpusetsize < size_of::<CpuMask>() {
             return Err(SysError::EINVAL);
         }
-        if let Some(task) = TASK_MANAGER.get(pid) {
-            if !task.is_leader() {
-                return Err(SysError::ESRCH);
-            }
-            mask.write(&self.task, *task.cpus_allowed())?;
+        let task = if pid == 0 {
+            self.task.clone()
         } else {
-            return Err(SysError::ESRCH);
+            TASK_MANAGER.get(pid).ok_or(SysError::ESRCH)?
+        };
+        let affinity = task.sched_attr().affinity() & online_harts();
+        mask.write(&self.task, CpuMask::from_bits_truncate(affinity))?;
+        Ok(size_of::<CpuMask>())
+    }
+
+    /// Get the hart the calling thread is running on and its NUMA node, which
+    /// is always zero. Either pointer may be NULL, and the result may be stale
+    /// as soon as it returns unless the thread is pinned to one hart.
+    pub fn sys_getcpu(&self, cpu: UserWritePtr<u32>, node: UserWritePtr<u32>) -> SyscallResult {
+        if !cpu.is_null() {
+            cpu.write(&self.task, local_hart().hart_id() as u32)?;
+        }
+        if !node.is_null() {
+            node.write(&self.task, 0)?;
         }
         Ok(0)
     }
 }
+
+/// Bitmask of harts that are started.
+fn online_harts() -> usize {
+    (1 << board::harts()) - 1
+}
@@ -1,3 +1,4 @@
+use alloc::{sync::Arc, vec::Vec};
 use core::mem;
 
 use async_utils::suspend_now;
@@ -16,7 +17,7 @@ use crate::{
     mm::{UserReadPtr, UserWritePtr},
     task::{
         signal::{SigAction, SIG_DFL, SIG_IGN},
-        PROCESS_GROUP_MANAGER, TASK_MANAGER,
+        Task, PROCESS_GROUP_MANAGER, TASK_MANAGER,
     },
 };
 
@@ -143,97 +144,67 @@ impl Syscall<'_> {
     ///   process has permission to send signals, except for process 1 (init)
     /// - If pid < -1, then sig is sent to every process in the process group
     ///   whose ID is -pid.
-    /// - If sig is 0, linux will perform permission check but our system skips
+    /// - If sig is 0, no signal is sent, but the existence and permission
+    ///   checks are still performed.
     ///
     /// **RETURN VALUE** :On success (at least one signal was sent), zero is
     /// returned. On error, -1 is returned, and errno is set appropriately
     pub fn sys_kill(&self, pid: isize, signum: i32) -> SyscallResult {
-        if signum == 0 {
-            // log::warn!("signum is zero, currently skip the permission check");
-            // our system has no permission check
-            return Ok(0);
-        }
         let sig = Sig::from_i32(signum);
         if !sig.is_valid() {
             return Err(SysError::EINVAL);
         }
-        // log::debug!("[sys_kill] signal {sig:?}");
-        match pid {
-            0 => {
-                // 进程组
-                let pgid = self.task.pgid();
-                for task in PROCESS_GROUP_MANAGER
-                    .get_group(pgid)
-                    .unwrap()
-                    .into_iter()
-                    .map(|t| t.upgrade().unwrap())
-                {
-                    task.receive_siginfo(
-                        SigInfo {
-                            sig,
-                            code: SigInfo::USER,
-                            details: SigDetails::Kill { pid: pgid },
-                        },
-                        false,
-                    );
-                }
-            }
+        let caller = self.task.pid();
+        let processes = match pid {
+            0 => PROCESS_GROUP_MANAGER.processes_in_group(self.task.pgid()),
             -1 => {
-                TASK_MANAGER.for_each(|task| {
-                    if task.pid() != INIT_PROC_PID && task.is_leader() && sig.raw() != 0 {
-                        task.receive_siginfo(
-                            SigInfo {
-                                sig,
-                                code: SigInfo::USER,
-                                details: SigDetails::Kill { pid: task.pid() },
-                            },
-                            false,
-                        );
+                let mut processes = Vec::new();
+                TASK_MANAGER.for_each(|pid, process| {
+                    if pid != INIT_PROC_PID && pid != caller {
+                        processes.push(process.clone());
                     }
                     Ok(())
                 })?;
+                processes
             }
-            _ if pid > 0 => {
-                if let Some(task) = TASK_MANAGER.get(pid as usize) {
-                    if task.is_leader() {
-                        task.receive_siginfo(
-                            SigInfo {
-                                sig,
-                                code: SigInfo::USER,
-                                details: SigDetails::Kill { pid: task.pid() },
-                            },
-                            false,
-                        );
-                    } else {
-                        // sys_kill is sent to process not thread
-                        return Err(SysError::ESRCH);
-                    }
-                } else {
-                    return Err(SysError::ESRCH);
-                }
+            // sys_kill is sent to process not thread
+            _ if pid > 0 => TASK_MANAGER.find(pid as usize).into_iter().collect(),
+            _ => PROCESS_GROUP_MANAGER.processes_in_group(pid.unsigned_abs()),
+        };
+        self.kill_processes(processes, sig)
+    }
+
+    /// Send `sig` to each of `processes` that the caller has permission to
+    /// signal. Fail with `ESRCH` if there is no process, or `EPERM` if none of
+    /// them can be signaled.
+    fn kill_processes(&self, processes: Vec<Arc<Task>>, sig: Sig) -> SyscallResult {
+        if processes.is_empty() {
+            return Err(SysError::ESRCH);
+        }
+        let cred = self.task.with_cred(|cred| cred.clone());
+        let caller = self.task.pid();
+        let mut permitted = false;
+        for process in processes {
+            if !process.with_cred(|target| cred.can_signal(target)) {
+                continue;
             }
-            _ => {
-                // pid < -1
-                // sig is sent to every process in the process group whose ID is -pid.
-                let pgid = -pid as usize;
-                for task in PROCESS_GROUP_MANAGER
-                    .get_group(pgid)
-                    .ok_or_else(|| SysError::ESRCH)?
-                    .into_iter()
-                    .filter_map(|t| t.upgrade())
-                {
-                    task.receive_siginfo(
-                        SigInfo {
-                            sig,
-                            code: SigInfo::USER,
-                            details: SigDetails::Kill { pid: pgid },
-                        },
-                        false,
-                    );
-                }
+            permitted = true;
+            if sig.raw() != 0 {
+                process.receive_siginfo(
+                    SigInfo {
+                        sig,
+                        code: SigInfo::USER,
+                        details: SigDetails::Kill { pid: caller },
+                    },
+                    false,
+                );
             }
         }
-        Ok(0)
+        if permitted {
+            Ok(0)
+        } else {
+            Err(SysError::EPERM)
+        }
     }
 
     /// sends the signal sigum to the thread with the thread ID tid in the
@@ -250,22 +221,20 @@ impl Syscall<'_> {
         if !task.is_leader() {
             return Err(SysError::ESRCH);
         }
-        task.with_mut_thread_group(|tg| -> SyscallResult {
-            for thread in tg.iter() {
-                if thread.tid() == tid as usize {
-                    thread.receive_siginfo(
-                        SigInfo {
-                            sig,
-                            code: SigInfo::TKILL,
-                            details: SigDetails::Kill { pid: task.pid() },
-                        },
-                        true,
-                    );
-                    return Ok(0);
-                }
-            }
-            return Err(SysError::ESRCH);
-        })
+        // NOTE: signal should be sent without holding the thread group lock, since
+        // receiving some signals will access the whole thread group
+        let thread = task
+            .with_thread_group(|tg| tg.iter().find(|t| t.tid() == tid as usize))
+            .ok_or(SysError::ESRCH)?;
+        thread.receive_siginfo(
+            SigInfo {
+                sig,
+                code: SigInfo::TKILL,
+                details: SigDetails::Kill { pid: task.pid() },
+            },
+            true,
+        );
+        Ok(0)
     }
 
     /// An obsolete predecessor to tgkill(). It allows only the target thread ID
@@ -0,0 +1,108 @@
+//! Latency statistics of syscalls in the manner of the function profiler of
+//! ftrace, which is read from /proc/syscall_stats and switched on by writing 1
+//! to /proc/sys/kernel/syscall_stats_enable.
+//!
+//! Each hart counts the syscalls it has handled on its own, and the counts are
+//! merged on read. Time is measured in cycles, so a syscall which has migrated
+//! to another hart before returning may be timed inexactly.
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use config::board::MAX_HARTS;
+
+use super::SyscallNo;
+use crate::processor::hart::local_hart;
+
+/// Syscall numbers counted, which covers all the syscalls.
+const SYSCALL_STATS_SLOTS: usize = 512;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+struct SyscallStat {
+    count: AtomicU64,
+    /// Cycles spent in total.
+    total: AtomicU64,
+    /// Cycles spent by the slowest call.
+    max: AtomicU64,
+}
+
+impl SyscallStat {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+const STAT_EACH: SyscallStat = SyscallStat::new();
+const HART_STATS_EACH: [SyscallStat; SYSCALL_STATS_SLOTS] = [STAT_EACH; SYSCALL_STATS_SLOTS];
+static STATS: [[SyscallStat; SYSCALL_STATS_SLOTS]; MAX_HARTS] = [HART_STATS_EACH; MAX_HARTS];
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Count a call of `syscall_no` which has spent `cycles` on this hart.
+pub fn record(syscall_no: SyscallNo, cycles: u64) {
+    let stat = &STATS[local_hart().hart_id()][syscall_no as usize];
+    stat.count.fetch_add(1, Ordering::Relaxed);
+    stat.total.fetch_add(cycles, Ordering::Relaxed);
+    stat.max.fetch_max(cycles, Ordering::Relaxed);
+}
+
+/// Clear the statistics of all harts.
+pub fn reset() {
+    for stat in STATS.iter().flatten() {
+        stat.count.store(0, Ordering::Relaxed);
+        stat.total.store(0, Ordering::Relaxed);
+        stat.max.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The statistics of the syscalls that have been called, sorted by the total
+/// time spent in descending order.
+pub fn serialize() -> String {
+    let mut stats: Vec<(SyscallNo, u64, u64, u64)> = (0..SYSCALL_STATS_SLOTS)
+        .filter_map(|no| {
+            let (mut count, mut total, mut max) = (0, 0, 0);
+            for hart_stats in STATS.iter() {
+                let stat = &hart_stats[no];
+                count += stat.count.load(Ordering::Relaxed);
+                total += stat.total.load(Ordering::Relaxed);
+                max = max.max(stat.max.load(Ordering::Relaxed));
+            }
+            let syscall_no = SyscallNo::from_repr(no)?;
+            (count > 0).then_some((syscall_no, count, total, max))
+        })
+        .collect();
+    stats.sort_by(|a, b| b.2.cmp(&a.2));
+
+    let mut info = String::new();
+    let _ = writeln!(
+        info,
+        "{:<24} {:>10} {:>16} {:>12} {:>12}",
+        "syscall", "count", "total(cycles)", "avg", "max"
+    );
+    for (syscall_no, count, total, max) in stats {
+        let _ = writeln!(
+            info,
+            "{:<24} {:>10} {:>16} {:>12} {:>12}",
+            syscall_no,
+            count,
+            total,
+            total / count,
+            max
+        );
+    }
+    info
+}
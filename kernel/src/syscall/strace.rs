@@ -0,0 +1,363 @@
+//! Decoding of syscalls for the `strace` feature, which prints each syscall
+//! with its arguments and return value in the manner of strace(1), e.g.
+//! `openat(AT_FDCWD, "/etc/passwd", O_RDONLY|O_CLOEXEC) = 3`.
+//!
+//! The verbosity is set by `strace=<level>` in the bootargs:
+//!
+//! - 0: nothing is printed.
+//! - 1: the arguments are printed in raw hex.
+//! - 2: the arguments are decoded, with strings truncated to 32 bytes, which is
+//!   the default.
+//! - 3: like 2, but strings are truncated to 256 bytes.
+//!
+//! User memory is read by the checked `UserPtr` reads, so that an argument
+//! pointing to bad memory is printed as the address rather than faulting.
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::{
+    fmt::{self, Write},
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bitflags::Flags;
+use signal::{Sig, SigSet};
+use systype::{SysError, SyscallResult};
+use time::timespec::TimeSpec;
+use vfs_core::{AtFd, OpenFlags};
+
+use super::{mm::MmapProt, CloneFlags, MmapFlags, SyscallNo};
+use crate::{mm::UserReadPtr, task::Task};
+
+const STRACE_OFF: usize = 0;
+const STRACE_RAW: usize = 1;
+const STRACE_DECODE: usize = 2;
+const STRACE_VERBOSE: usize = 3;
+
+static LEVEL: AtomicUsize = AtomicUsize::new(STRACE_DECODE);
+
+/// Elements of an array of strings printed at most, e.g. of argv.
+const MAX_ARRAY_LEN: usize = 32;
+
+/// Set the verbosity by `strace=<level>` in the bootargs.
+pub fn init() {
+    let level = config::cmdline()
+        .get("strace")
+        .and_then(|level| level.parse::<usize>().ok());
+    if let Some(level) = level {
+        LEVEL.store(level.min(STRACE_VERBOSE), Ordering::Relaxed);
+    }
+}
+
+/// Kinds of syscall arguments, which are decoded differently.
+#[derive(Clone, Copy)]
+enum Arg {
+    /// Signed integer.
+    Int,
+    /// Unsigned integer in hex, e.g. an address.
+    Hex,
+    Fd,
+    /// Directory of the *at syscalls, which may be `AT_FDCWD`.
+    DirFd,
+    /// Null-terminated string, e.g. a path.
+    Str,
+    /// Buffer of bytes, whose length is the next argument.
+    Buf,
+    /// Null-terminated array of strings, e.g. argv.
+    StrArray,
+    /// Permission bits of a new file.
+    Mode,
+    OpenFlags,
+    MmapProt,
+    MmapFlags,
+    /// Flags of clone(2), whose low byte is the exit signal.
+    CloneFlags,
+    Sig,
+    /// Pointer to a `struct timespec`.
+    TimeSpec,
+    /// Pointer to a socket address, whose length is the next argument.
+    SockAddr,
+}
+
+/// Kinds of the arguments of the syscalls decoded, where the others are
+/// printed in raw hex.
+fn signature(syscall_no: SyscallNo) -> Option<&'static [Arg]> {
+    use Arg::*;
+    use SyscallNo::*;
+    let args: &[Arg] = match syscall_no {
+        GETPID | GETPPID | GETTID | GETUID | GETEUID | GETGID | GETEGID | SETSID | SCHED_YIELD
+        | SYNC => &[],
+        EXIT | EXIT_GROUP | UMASK => &[Int],
+        CLOSE | DUP | FSYNC | FDATASYNC | FCHDIR => &[Fd],
+        DUP3 => &[Fd, Fd, OpenFlags],
+        PIPE2 => &[Hex, OpenFlags],
+        OPENAT => &[DirFd, Str, OpenFlags, Mode],
+        READ | GETDENTS64 => &[Fd, Hex, Int],
+        WRITE => &[Fd, Buf, Int],
+        READV | WRITEV => &[Fd, Hex, Int],
+        PREAD64 => &[Fd, Hex, Int, Int],
+        PWRITE64 => &[Fd, Buf, Int, Int],
+        LSEEK => &[Fd, Int, Int],
+        IOCTL | FCNTL => &[Fd, Hex, Hex],
+        SENDFILE => &[Fd, Fd, Hex, Int],
+        FSTAT | FSTATFS => &[Fd, Hex],
+        FSTATAT => &[DirFd, Str, Hex, Hex],
+        STATFS => &[Str, Hex],
+        FACCESSAT => &[DirFd, Str, Int, Hex],
+        READLINKAT => &[DirFd, Str, Hex, Int],
+        MKDIRAT => &[DirFd, Str, Mode],
+        UNLINKAT => &[DirFd, Str, Hex],
+        SYMLINKAT => &[Str, DirFd, Str],
+        LINKAT | RENAMEAT2 => &[DirFd, Str, DirFd, Str, Hex],
+        FCHMODAT => &[DirFd, Str, Mode],
+        UTIMENSAT => &[DirFd, Str, Hex, Hex],
+        CHDIR | CHROOT => &[Str],
+        GETCWD => &[Hex, Int],
+        MOUNT => &[Str, Str, Str, Hex, Hex],
+        UMOUNT2 => &[Str, Hex],
+        EXECVE => &[Str, StrArray, Hex],
+        CLONE => &[CloneFlags, Hex, Hex, Hex, Hex],
+        WAIT4 => &[Int, Hex, Hex, Hex],
+        KILL | TKILL => &[Int, Sig],
+        TGKILL => &[Int, Int, Sig],
+        RT_SIGACTION => &[Sig, Hex, Hex, Int],
+        RT_SIGPROCMASK => &[Int, Hex, Hex, Int],
+        SET_TID_ADDRESS | BRK | UNAME => &[Hex],
+        MMAP => &[Hex, Int, MmapProt, MmapFlags, Fd, Hex],
+        MUNMAP => &[Hex, Int],
+        MPROTECT => &[Hex, Int, MmapProt],
+        NANOSLEEP => &[TimeSpec, Hex],
+        CLOCK_GETTIME => &[Int, Hex],
+        CLOCK_NANOSLEEP => &[Int, Hex, TimeSpec, Hex],
+        PPOLL => &[Hex, Int, TimeSpec, Hex, Int],
+        FUTEX => &[Hex, Int, Int, Hex, Hex, Int],
+        SOCKET => &[Int, Int, Int],
+        BIND | CONNECT => &[Fd, SockAddr, Int],
+        LISTEN => &[Fd, Int],
+        ACCEPT => &[Fd, Hex, Hex],
+        ACCEPT4 => &[Fd, Hex, Hex, Hex],
+        SENDTO => &[Fd, Buf, Int, Hex, SockAddr, Int],
+        RECVFROM => &[Fd, Hex, Int, Hex, Hex, Hex],
+        GETRANDOM => &[Hex, Int, Hex],
+        PRLIMIT64 => &[Int, Int, Hex, Hex],
+        _ => return None,
+    };
+    Some(args)
+}
+
+/// The syscall with its arguments, e.g. `close(3)`, or `None` if syscalls
+/// are not to be printed.
+pub fn decode_call(task: &Arc<Task>, syscall_no: SyscallNo, args: &[usize; 6]) -> Option<String> {
+    let level = LEVEL.load(Ordering::Relaxed);
+    if level == STRACE_OFF {
+        return None;
+    }
+    let mut call = format!("{syscall_no}(").to_lowercase();
+    match signature(syscall_no).filter(|_| level > STRACE_RAW) {
+        Some(kinds) => {
+            let max_str = if level >= STRACE_VERBOSE { 256 } else { 32 };
+            for (i, &kind) in kinds.iter().enumerate() {
+                if i > 0 {
+                    call += ", ";
+                }
+                let next = args.get(i + 1).copied().unwrap_or(0);
+                let _ = decode_arg(&mut call, task, kind, args[i], next, max_str);
+            }
+        }
+        None => {
+            let _ = write!(
+                call,
+                "{:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x}",
+                args[0], args[1], args[2], args[3], args[4], args[5]
+            );
+        }
+    }
+    call.push(')');
+    Some(call)
+}
+
+/// The return value, or the name and description of the error.
+pub fn decode_ret(syscall_no: SyscallNo, result: &SyscallResult) -> String {
+    match result {
+        Ok(ret) if matches!(syscall_no, SyscallNo::BRK | SyscallNo::MMAP) => format!("{ret:#x}"),
+        Ok(ret) => format!("{}", *ret as isize),
+        Err(err) => format!("-1 {err:?} ({})", err.as_str()),
+    }
+}
+
+fn decode_arg(
+    out: &mut String,
+    task: &Arc<Task>,
+    kind: Arg,
+    arg: usize,
+    next: usize,
+    max_str: usize,
+) -> fmt::Result {
+    match kind {
+        Arg::Int => write!(out, "{}", arg as isize),
+        Arg::Hex => write!(out, "{arg:#x}"),
+        Arg::Fd => write!(out, "{}", arg as i32),
+        Arg::DirFd => write!(out, "{}", AtFd::from(arg as i32 as isize)),
+        Arg::Mode => write!(out, "0{:o}", arg as u32),
+        Arg::Str => decode_str(out, task, arg, max_str),
+        Arg::Buf => {
+            let len = next.min(max_str);
+            match UserReadPtr::<u8>::from(arg).read_array(task, len) {
+                Ok(bytes) => quote(out, &bytes, next > max_str),
+                Err(_) => write!(out, "{arg:#x}"),
+            }
+        }
+        Arg::StrArray => decode_str_array(out, task, arg, max_str),
+        Arg::OpenFlags => {
+            let flags = OpenFlags::from_bits_retain(arg as i32);
+            let access = match (flags & OpenFlags::O_ACCMODE).bits() {
+                0 => "O_RDONLY",
+                1 => "O_WRONLY",
+                _ => "O_RDWR",
+            };
+            let rest = flags.difference(OpenFlags::O_ACCMODE);
+            if rest.is_empty() {
+                write!(out, "{access}")
+            } else {
+                write!(out, "{access}|{}", flag_names(rest, ""))
+            }
+        }
+        Arg::MmapProt if arg == 0 => write!(out, "PROT_NONE"),
+        Arg::MmapProt => write!(
+            out,
+            "{}",
+            flag_names(MmapProt::from_bits_retain(arg as i32), "")
+        ),
+        Arg::MmapFlags => write!(
+            out,
+            "{}",
+            flag_names(MmapFlags::from_bits_retain(arg as i32), "")
+        ),
+        Arg::CloneFlags => {
+            let flags = CloneFlags::from_bits_retain(arg as u64 & !0xff);
+            let exit_sig = Sig::from_i32((arg & 0xff) as i32);
+            match (flags.is_empty(), exit_sig.raw()) {
+                (_, 0) => write!(out, "{}", flag_names(flags, "CLONE_")),
+                (true, _) => write!(out, "{}", SigName(exit_sig)),
+                (false, _) => write!(out, "{}|{}", flag_names(flags, "CLONE_"), SigName(exit_sig)),
+            }
+        }
+        Arg::Sig => write!(out, "{}", SigName(Sig::from_i32(arg as i32))),
+        Arg::TimeSpec if arg == 0 => write!(out, "NULL"),
+        Arg::TimeSpec => match UserReadPtr::<TimeSpec>::from(arg).read(task) {
+            Ok(ts) => write!(out, "{{tv_sec={}, tv_nsec={}}}", ts.tv_sec, ts.tv_nsec),
+            Err(_) => write!(out, "{arg:#x}"),
+        },
+        Arg::SockAddr if arg == 0 => write!(out, "NULL"),
+        Arg::SockAddr => match task.read_sockaddr(arg, next) {
+            Ok(addr) => write!(out, "{{{addr}}}"),
+            Err(_) => write!(out, "{arg:#x}"),
+        },
+    }
+}
+
+/// Print the string at `ptr` quoted, truncated to `max` bytes.
+fn decode_str(out: &mut String, task: &Arc<Task>, ptr: usize, max: usize) -> fmt::Result {
+    if ptr == 0 {
+        return write!(out, "NULL");
+    }
+    let mut bytes = Vec::with_capacity(max + 1);
+    match UserReadPtr::<u8>::from(ptr).read_cstr_into(task, &mut bytes, max + 1) {
+        Ok(()) => {
+            bytes.pop();
+            quote(out, &bytes, false)
+        }
+        Err(SysError::ENAMETOOLONG) => quote(out, &bytes[..max], true),
+        Err(_) => write!(out, "{ptr:#x}"),
+    }
+}
+
+/// Print the null-terminated array of strings at `ptr`, e.g. `["ls", "-l"]`.
+fn decode_str_array(out: &mut String, task: &Arc<Task>, ptr: usize, max: usize) -> fmt::Result {
+    if ptr == 0 {
+        return write!(out, "NULL");
+    }
+    out.push('[');
+    for i in 0..=MAX_ARRAY_LEN {
+        let elem = match UserReadPtr::<usize>::from(ptr + i * size_of::<usize>()).read(task) {
+            Ok(0) => break,
+            Ok(elem) => elem,
+            Err(_) => return write!(out, "{ptr:#x}]"),
+        };
+        if i > 0 {
+            out.push_str(", ");
+        }
+        if i == MAX_ARRAY_LEN {
+            out.push_str("...");
+            break;
+        }
+        decode_str(out, task, elem, max)?;
+    }
+    out.push(']');
+    Ok(())
+}
+
+/// Print `bytes` in double quotes with the special characters escaped, and
+/// followed by `...` if `truncated`.
+fn quote(out: &mut String, bytes: &[u8], truncated: bool) -> fmt::Result {
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => write!(out, "\\x{b:02x}")?,
+        }
+    }
+    out.push('"');
+    if truncated {
+        out.push_str("...");
+    }
+    Ok(())
+}
+
+/// Names of the flags set in `flags` joined by `|`, with the bits unknown in
+/// hex, e.g. `O_CREAT|O_CLOEXEC`.
+fn flag_names<F: Flags>(flags: F, prefix: &str) -> String
+where
+    F::Bits: fmt::LowerHex,
+{
+    let mut s = String::new();
+    let mut names = flags.iter_names();
+    for (name, _) in names.by_ref() {
+        if !s.is_empty() {
+            s.push('|');
+        }
+        s.push_str(prefix);
+        s.push_str(name);
+    }
+    let rest = names.remaining();
+    if !rest.is_empty() {
+        if !s.is_empty() {
+            s.push('|');
+        }
+        let _ = write!(s, "{:#x}", rest.bits());
+    }
+    if s.is_empty() {
+        s.push('0');
+    }
+    s
+}
+
+/// Name of a signal, e.g. `SIGCHLD`, or its number if it is not valid.
+struct SigName(Sig);
+
+impl fmt::Display for SigName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sig = self.0;
+        if sig.raw() != 0 && sig.is_valid() {
+            if let Some((name, _)) = SigSet::from(sig).iter_names().next() {
+                return write!(f, "{name}");
+            }
+        }
+        write!(f, "{}", sig.raw() as i32)
+    }
+}
@@ -1,23 +1,114 @@
 use alloc::{boxed::Box, sync::Arc};
-use core::time::Duration;
+use core::{future, time::Duration};
 
-use arch::time::{get_time_duration, get_time_ms, get_time_us};
-use systype::{SysError, SyscallResult};
+use arch::time::{get_time_duration, get_time_ms};
+use sync::WaitQueue;
+use systype::{SysError, SysResult, SyscallResult};
 use time::{
+    clock_deviation,
     timespec::TimeSpec,
     timeval::{ITimerVal, TimeVal},
     tms::TMS,
     CLOCK_DEVIATION, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME,
-    CLOCK_THREAD_CPUTIME_ID,
+    CLOCK_THREAD_CPUTIME_ID, ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL,
 };
 use timer::{Timer, TIMER_MANAGER};
 
 use super::Syscall;
 use crate::{
-    mm::{UserReadPtr, UserWritePtr},
-    task::signal::{alloc_timer_id, RealITimer},
+    mm::{vdso, UserReadPtr, UserWritePtr},
+    task::{
+        signal::{alloc_timer_id, RealITimer},
+        Task, TASK_MANAGER,
+    },
 };
 
+/// Tasks sleeping until an absolute time of a settable clock, woken when the
+/// clock is set.
+static CLOCK_SET_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Sleep for `req` unless interrupted by a signal, in which case the remaining
+/// time is written into `rem` if it is not null.
+async fn sleep_relative(
+    task: &Arc<Task>,
+    req: Duration,
+    rem: UserWritePtr<TimeSpec>,
+) -> SyscallResult {
+    let deadline = get_time_duration() + req;
+    match task
+        .wait_interruptible(future::pending::<()>(), Some(deadline))
+        .await
+    {
+        Ok(_) => Ok(0),
+        Err(e) => {
+            if rem.not_null() {
+                let remain = deadline.saturating_sub(get_time_duration());
+                rem.write(task, remain.into())?;
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Dynamic cpu clock ids made by `clock_getcpuclockid(3)` and
+/// `pthread_getcpuclockid(3)` are `(~pid << 3) | flags`, where the lowest two
+/// bits of flags select the kind of cpu time.
+const CPUCLOCK_PROF: i32 = 0;
+const CPUCLOCK_VIRT: i32 = 1;
+const CPUCLOCK_SCHED: i32 = 2;
+const CPUCLOCK_CLOCK_MASK: i32 = 3;
+/// The clock measures a thread instead of a process.
+const CPUCLOCK_PERTHREAD_MASK: i32 = 4;
+
+/// Cpu time measured by `clockid`, which is either `CLOCK_PROCESS_CPUTIME_ID`,
+/// `CLOCK_THREAD_CPUTIME_ID` or a dynamic cpu clock id.
+fn cpu_clock_time(task: &Arc<Task>, clockid: usize) -> SysResult<Duration> {
+    match clockid {
+        CLOCK_PROCESS_CPUTIME_ID => return Ok(task.get_process_cputime()),
+        CLOCK_THREAD_CPUTIME_ID => return Ok(task.time_stat().cpu_time()),
+        _ => {}
+    }
+    let clockid = clockid as i32;
+    if clockid >= 0 {
+        return Err(SysError::EINVAL);
+    }
+    let pid = !(clockid >> 3) as usize;
+    let target = if pid == 0 {
+        task.clone()
+    } else {
+        TASK_MANAGER.get(pid).ok_or(SysError::EINVAL)?
+    };
+    let thread_time = |thread: &Arc<Task>| match clockid & CPUCLOCK_CLOCK_MASK {
+        CPUCLOCK_PROF | CPUCLOCK_SCHED => Ok(thread.time_stat().cpu_time()),
+        CPUCLOCK_VIRT => Ok(thread.time_stat().user_time()),
+        _ => Err(SysError::EINVAL),
+    };
+    if clockid & CPUCLOCK_PERTHREAD_MASK != 0 {
+        // only threads in the same process can be measured
+        if target.pid() != task.pid() {
+            return Err(SysError::EINVAL);
+        }
+        thread_time(&target)
+    } else {
+        if !target.is_leader() {
+            return Err(SysError::EINVAL);
+        }
+        target.with_thread_group(|tg| {
+            tg.iter().try_fold(
+                Duration::ZERO,
+                |acc, thread| Ok(acc + thread_time(&thread)?),
+            )
+        })
+    }
+}
+
+/// Whether `clockid` is one of the cpu clocks, which can not be set.
+fn is_cpu_clock(clockid: usize) -> bool {
+    clockid == CLOCK_PROCESS_CPUTIME_ID
+        || clockid == CLOCK_THREAD_CPUTIME_ID
+        || (clockid as i32) < 0
+}
+
 impl Syscall<'_> {
     /// Retrieves the current time of day.
     ///
@@ -37,7 +128,8 @@ impl Syscall<'_> {
     pub fn sys_gettimeofday(&self, tv: UserWritePtr<TimeVal>, _tz: usize) -> SyscallResult {
         let task = self.task;
         if tv.not_null() {
-            tv.write(&task, TimeVal::from_usec(get_time_us()))?;
+            let now = clock_deviation(CLOCK_REALTIME) + get_time_duration();
+            tv.write(&task, now.into())?;
         }
         Ok(0)
     }
@@ -70,15 +162,10 @@ impl Syscall<'_> {
             return Ok(0);
         }
         let req = req.read(&task)?;
-        let remain = task.suspend_timeout(req.into()).await;
-        if remain.is_zero() {
-            Ok(0)
-        } else {
-            if rem.not_null() {
-                rem.write(&task, remain.into())?;
-            }
-            Err(SysError::EINTR)
+        if !req.is_valid() {
+            return Err(SysError::EINVAL);
         }
+        sleep_relative(task, req.into(), rem).await
     }
 
     /// retrieve the time of the specified clock clockid
@@ -90,35 +177,33 @@ impl Syscall<'_> {
         match clockid {
             CLOCK_REALTIME | CLOCK_MONOTONIC => {
                 let current = get_time_duration();
-                tp.write(
-                    &task,
-                    (unsafe { CLOCK_DEVIATION }[clockid] + current).into(),
-                )?;
-            }
-            CLOCK_PROCESS_CPUTIME_ID => {
-                let cpu_time = task.get_process_cputime();
-                tp.write(&task, cpu_time.into())?;
-            }
-            CLOCK_THREAD_CPUTIME_ID => {
-                tp.write(&task, task.time_stat().cpu_time().into())?;
+                tp.write(&task, (clock_deviation(clockid) + current).into())?;
             }
             5 => {
                 log::warn!("[sys_clock_gettime] unsupported clockid{}", clockid);
                 return Err(SysError::EINTR);
             }
+            _ if is_cpu_clock(clockid) => {
+                let cpu_time = cpu_clock_time(task, clockid)?;
+                tp.write(&task, cpu_time.into())?;
+            }
             _ => {
                 log::error!("[sys_clock_gettime] unsupported clockid{}", clockid);
-                return Err(SysError::EINTR);
+                return Err(SysError::EINVAL);
             }
         }
         Ok(0)
     }
 
     pub fn sys_clock_settime(&self, clockid: usize, tp: UserReadPtr<TimeSpec>) -> SyscallResult {
-        if clockid == CLOCK_PROCESS_CPUTIME_ID
-            || clockid == CLOCK_THREAD_CPUTIME_ID
-            || clockid == CLOCK_MONOTONIC
-        {
+        if is_cpu_clock(clockid) {
+            log::error!(
+                "[sys_clock_settime] cpu clock {} can not be set",
+                clockid as i32
+            );
+            return Err(SysError::EPERM);
+        }
+        if clockid == CLOCK_MONOTONIC {
             log::error!("[sys_clock_settime] The clockid {} specified in a call to clock_settime() is not a settable clock.", clockid);
             return Err(SysError::EINVAL);
         }
@@ -133,9 +218,9 @@ impl Syscall<'_> {
                     log::error!("[sys_clock_settime] attempted to set the time to a value less than the current value of the CLOCK_MONOTONIC clock.");
                     return Err(SysError::EINVAL);
                 }
-                unsafe {
-                    CLOCK_DEVIATION[clockid] = Duration::from(tp) - get_time_duration();
-                }
+                set_realtime(tp.into());
+                vdso::update();
+                CLOCK_SET_QUEUE.wake_all();
             }
             _ => {
                 log::error!("[sys_clock_gettime] unsupported clockid{}", clockid);
@@ -146,15 +231,22 @@ impl Syscall<'_> {
     }
 
     /// finds the resolution (precision) of the specified clock clockid
-    pub fn sys_clock_getres(&self, _clockid: usize, res: UserWritePtr<TimeSpec>) -> SyscallResult {
+    pub fn sys_clock_getres(&self, clockid: usize, res: UserWritePtr<TimeSpec>) -> SyscallResult {
+        let task = self.task;
+        if is_cpu_clock(clockid) {
+            // check that the measured task exists
+            cpu_clock_time(task, clockid)?;
+        }
         if res.is_null() {
             return Ok(0);
         }
-        let task = self.task;
         res.write(&task, Duration::from_nanos(1).into())?;
         Ok(0)
     }
 
+    /// Like `nanosleep`, but the sleep is measured against `clockid`. With
+    /// `TIMER_ABSTIME` in `flags`, `t` is an absolute time of the clock, and
+    /// `rem` is never written.
     pub async fn sys_clock_nanosleep(
         &self,
         clockid: usize,
@@ -165,34 +257,34 @@ impl Syscall<'_> {
         /// for clock_nanosleep
         pub const TIMER_ABSTIME: usize = 1;
         let task = self.task;
-        match clockid {
-            // FIXME: what is CLOCK_MONOTONIC
-            CLOCK_REALTIME | CLOCK_MONOTONIC => {
-                let ts = t.read(task)?;
-                let req: Duration = ts.into();
-                let remain = if flags == TIMER_ABSTIME {
-                    let current = get_time_duration();
-                    // request time is absolutely
-                    if req.le(&current) {
-                        return Ok(0);
-                    }
-                    let sleep = req - current;
-                    task.suspend_timeout(sleep).await
-                } else {
-                    task.suspend_timeout(req).await
-                };
-                if remain.is_zero() {
-                    Ok(0)
-                } else {
-                    if rem.not_null() {
-                        rem.write(&task, remain.into())?;
-                    }
-                    Err(SysError::EINTR)
-                }
+        if clockid != CLOCK_REALTIME && clockid != CLOCK_MONOTONIC {
+            log::error!("[sys_clock_nanosleep] unsupported clockid {}", clockid);
+            return Err(SysError::EINVAL);
+        }
+        let ts = t.read(task)?;
+        if !ts.is_valid() {
+            return Err(SysError::EINVAL);
+        }
+        if flags & TIMER_ABSTIME == 0 {
+            return sleep_relative(task, ts.into(), rem).await;
+        }
+        let expire: Duration = ts.into();
+        loop {
+            // the clock may be adjusted by clock_settime(2) while sleeping, so
+            // convert the request to the time of the timer every time it is
+            // changed
+            let deviation = clock_deviation(clockid);
+            let deadline = expire.saturating_sub(deviation);
+            if deadline <= get_time_duration() {
+                return Ok(0);
             }
-            _ => {
-                log::error!("[sys_clock_nanosleep] unsupported clockid {}", clockid);
-                return Err(SysError::EINVAL);
+            let clock_set = CLOCK_SET_QUEUE.wait_until(|| clock_deviation(clockid) != deviation);
+            if task
+                .wait_interruptible(clock_set, Some(deadline))
+                .await?
+                .is_none()
+            {
+                return Ok(0);
             }
         }
     }
@@ -200,16 +292,23 @@ impl Syscall<'_> {
     /// Kernel provides a timer mechanism called itimer for implementing
     /// interval timers. Interval timer allows processes to receive signals
     /// after a specified time interval
+    ///
+    /// - `ITIMER_REAL`: decrements in real time, and delivers SIGALRM upon
+    ///   expiration.
+    /// - `ITIMER_VIRTUAL`: decrements only when the process is executing in
+    ///   user mode, and delivers SIGVTALRM upon expiration.
+    /// - `ITIMER_PROF`: decrements both when the process executes in user mode
+    ///   and when the system is executing on behalf of the process, and
+    ///   delivers SIGPROF upon expiration.
     pub fn sys_setitimer(
         &self,
         which: usize,
         new_value: UserReadPtr<ITimerVal>,
         old_value: UserWritePtr<ITimerVal>,
     ) -> SyscallResult {
-        if which > 2 {
+        if which > ITIMER_PROF {
             return Err(SysError::EINVAL);
         }
-        debug_assert!(which == CLOCK_REALTIME);
         let task = self.task;
         let new = new_value.read(&task)?;
 
@@ -217,16 +316,18 @@ impl Syscall<'_> {
             return Err(SysError::EINVAL);
         }
 
+        // `next_expire` of each itimer is measured in its own clock
+        let now = self.itimer_clock(which);
         let timer_id = alloc_timer_id();
         let (old, next_expire) = task.with_mut_itimers(|itimers| {
-            // only supports real itimer now
             let itimer = &mut itimers[which];
             let old = ITimerVal {
                 it_interval: itimer.interval.into(),
-                it_value: itimer
-                    .next_expire
-                    .saturating_sub(get_time_duration())
-                    .into(),
+                it_value: if itimer.is_armed() {
+                    itimer.next_expire.saturating_sub(now).into()
+                } else {
+                    Duration::ZERO.into()
+                },
             };
 
             if new.it_value.is_zero() {
@@ -237,13 +338,14 @@ impl Syscall<'_> {
                 (old, itimer.next_expire)
             } else {
                 itimer.interval = new.it_interval.into();
-                itimer.next_expire = get_time_duration() + new.it_value.into();
+                itimer.next_expire = now + new.it_value.into();
                 itimer.id = timer_id;
                 (old, itimer.next_expire)
             }
         });
 
-        if !new.it_value.is_zero() {
+        // Virtual and prof itimers are checked against cpu time in trap handler
+        if which == ITIMER_REAL && !new.it_value.is_zero() {
             let timer = Timer::new(
                 next_expire,
                 Box::new(RealITimer {
@@ -254,7 +356,7 @@ impl Syscall<'_> {
             TIMER_MANAGER.add_timer(timer);
         }
 
-        log::info!("[sys_setitimer] new ITimerVal {new} old ITimerVal {old}");
+        log::info!("[sys_setitimer] which {which} new ITimerVal {new} old ITimerVal {old}");
         if old_value.not_null() {
             old_value.write(&task, old)?;
         }
@@ -266,23 +368,35 @@ impl Syscall<'_> {
         which: usize,
         curr_value: UserWritePtr<ITimerVal>,
     ) -> SyscallResult {
-        if which > 2 {
+        if which > ITIMER_PROF {
             return Err(SysError::EINVAL);
         }
         if curr_value.not_null() {
             let task = self.task;
+            let now = self.itimer_clock(which);
             let itimerval = task.with_itimers(|itimers| {
                 let itimer = &itimers[which];
                 ITimerVal {
                     it_interval: itimer.interval.into(),
-                    it_value: itimer
-                        .next_expire
-                        .saturating_sub(get_time_duration())
-                        .into(),
+                    it_value: if itimer.is_armed() {
+                        itimer.next_expire.saturating_sub(now).into()
+                    } else {
+                        Duration::ZERO.into()
+                    },
                 }
             });
             curr_value.write(&task, itimerval)?;
         }
         Ok(0)
     }
+
+    /// Current time of the clock that itimer `which` counts down against.
+    fn itimer_clock(&self, which: usize) -> Duration {
+        match which {
+            ITIMER_REAL => get_time_duration(),
+            ITIMER_VIRTUAL => self.task.get_process_utime(),
+            ITIMER_PROF => self.task.get_process_cputime(),
+            _ => unreachable!(),
+        }
+    }
 }
@@ -1,6 +1,9 @@
 use alloc::vec::Vec;
 
-use config::mm::PAGE_SIZE;
+use config::{board::hwcap, mm::PAGE_SIZE};
+
+use super::cred::Credentials;
+use crate::mm::vdso::VDSO_EHDR;
 
 /// end of vector
 pub const AT_NULL: usize = 0;
@@ -62,7 +65,6 @@ pub const AT_EXECFN: usize = 31;
 #[allow(unused)]
 pub const AT_SYSINFO: usize = 32;
 /// address of a page containing the vDSO
-#[allow(unused)]
 pub const AT_SYSINFO_EHDR: usize = 33;
 
 /// Auxiliary header
@@ -81,6 +83,9 @@ impl AuxHeader {
     }
 }
 
+/// Entries known once the elf is parsed. Those depending on the credentials
+/// and the strings pushed on the stack are added by `push_cred_auxv` and
+/// `init_stack`.
 pub fn generate_early_auxv(
     ph_entry_size: usize,
     ph_count: usize,
@@ -97,13 +102,20 @@ pub fn generate_early_auxv(
     push!(AT_PAGESZ, PAGE_SIZE);
     push!(AT_FLAGS, 0);
     push!(AT_ENTRY, entry_point);
-    push!(AT_UID, 0);
-    push!(AT_EUID, 0);
-    push!(AT_GID, 0);
-    push!(AT_EGID, 0);
-    push!(AT_PLATFORM, 0);
-    push!(AT_HWCAP, 0);
+    push!(AT_HWCAP, hwcap());
     push!(AT_CLKTCK, 100);
-    push!(AT_SECURE, 0);
+    push!(AT_SYSINFO_EHDR, VDSO_EHDR);
     auxv
 }
+
+/// Push the ids of the new program, which should be called after the
+/// set-user-ID and set-group-ID bits take effect.
+pub fn push_cred_auxv(auxv: &mut Vec<AuxHeader>, cred: &Credentials) {
+    auxv.push(AuxHeader::new(AT_UID, cred.user.real as usize));
+    auxv.push(AuxHeader::new(AT_EUID, cred.user.effective as usize));
+    auxv.push(AuxHeader::new(AT_GID, cred.group.real as usize));
+    auxv.push(AuxHeader::new(AT_EGID, cred.group.effective as usize));
+    // Secure mode is only needed when the ids have been changed by exec.
+    let secure = cred.user.real != cred.user.effective || cred.group.real != cred.group.effective;
+    auxv.push(AuxHeader::new(AT_SECURE, secure as usize));
+}
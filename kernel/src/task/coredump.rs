@@ -0,0 +1,306 @@
+//! Core dumps of processes terminated by signals, see core(5).
+//!
+//! The core file is an ELF file of type `ET_CORE` as Linux writes, with a
+//! `PT_NOTE` segment describing the process, followed by a `PT_LOAD` segment
+//! for each area of its memory space, so that it can be examined by gdb along
+//! with the executable.
+
+use alloc::{format, sync::Arc, vec, vec::Vec};
+use core::{cmp, ops::Range, time::Duration};
+
+use config::mm::PAGE_SIZE;
+use memory::VirtAddr;
+use page::Page;
+use signal::Sig;
+use systype::{SysError, SysResult};
+use vfs_core::{AccessMode, AtFd, InodeMode, OpenFlags};
+
+use super::Task;
+use crate::mm::memory_space::vm_area::MapPerm;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+
+/// Size of `struct elf_prstatus` on riscv64.
+const PRSTATUS_SIZE: usize = 376;
+/// Size of `struct elf_prpsinfo` on riscv64.
+const PRPSINFO_SIZE: usize = 136;
+
+/// Writer of the little-endian fields of the headers and notes.
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    fn u16(&mut self, v: u16) -> &mut Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u32(&mut self, v: u32) -> &mut Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u64(&mut self, v: u64) -> &mut Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    /// Append a NUL terminated string truncated to fit in `len` bytes, and pad
+    /// it with zeros.
+    fn cstr(&mut self, s: &str, len: usize) -> &mut Self {
+        let n = cmp::min(s.len(), len - 1);
+        self.bytes(&s.as_bytes()[..n]);
+        self.0.resize(self.0.len() + len - n, 0);
+        self
+    }
+
+    /// `struct timeval`
+    fn timeval(&mut self, d: Duration) -> &mut Self {
+        self.u64(d.as_secs()).u64(d.subsec_micros() as u64)
+    }
+
+    fn align(&mut self, align: usize) -> &mut Self {
+        let len = self.0.len().next_multiple_of(align);
+        self.0.resize(len, 0);
+        self
+    }
+
+    /// Append an ELF note of the kernel, named "CORE".
+    fn note(&mut self, ntype: u32, desc: &[u8]) -> &mut Self {
+        const NAME: &[u8] = b"CORE\0";
+        self.u32(NAME.len() as u32)
+            .u32(desc.len() as u32)
+            .u32(ntype)
+            .bytes(NAME)
+            .align(4)
+            .bytes(desc)
+            .align(4)
+    }
+}
+
+/// A `PT_LOAD` segment for an area of the memory space.
+struct Segment {
+    range: Range<VirtAddr>,
+    perm: MapPerm,
+    /// Pages of the area, where those not resident are dumped as zeros.
+    pages: Vec<Option<Arc<Page>>>,
+    offset: usize,
+    /// Bytes dumped to the file, which is less than the area if the dump is
+    /// cut by `RLIMIT_CORE`.
+    file_size: usize,
+}
+
+impl Segment {
+    fn flags(&self) -> u32 {
+        [(MapPerm::R, PF_R), (MapPerm::W, PF_W), (MapPerm::X, PF_X)]
+            .into_iter()
+            .filter(|(perm, _)| self.perm.contains(*perm))
+            .fold(0, |flags, (_, flag)| flags | flag)
+    }
+}
+
+/// Dump core of the process of `task` terminated by `sig` to `core.<pid>` in
+/// its working directory, if `RLIMIT_CORE` allows. Then the core dump flag is
+/// set in the wait status reported to the parent.
+pub async fn do_coredump(task: &Arc<Task>, sig: Sig) {
+    let limit = task.with_memory_space(|m| m.core_limit()).rlim_cur;
+    // Not even the headers can be dumped in less than a page.
+    if limit < PAGE_SIZE {
+        return;
+    }
+    match dump(task, sig, limit).await {
+        Ok(size) => {
+            log::info!(
+                "[do_coredump] process {} dumped core of {size} bytes",
+                task.pid()
+            );
+            let leader = task.leader();
+            leader.set_exit_code(leader.exit_code() | 0x80);
+        }
+        Err(e) => log::warn!(
+            "[do_coredump] process {} dump core failed: {e:?}",
+            task.pid()
+        ),
+    }
+}
+
+/// Write the core file and return its size.
+async fn dump(task: &Arc<Task>, sig: Sig, limit: usize) -> SysResult<usize> {
+    let mut segments: Vec<Segment> = task.with_memory_space(|m| {
+        m.areas()
+            .iter()
+            .map(|(range, area)| Segment {
+                range: range.clone(),
+                perm: area.perm(),
+                pages: area
+                    .range_vpn()
+                    .map(|vpn| area.pages.get(&vpn).cloned())
+                    .collect(),
+                offset: 0,
+                file_size: 0,
+            })
+            .collect()
+    });
+    let notes = notes(task, sig);
+
+    let phnum = segments.len() + 1;
+    let notes_offset = ELF_HEADER_SIZE + phnum * PROGRAM_HEADER_SIZE;
+    let mut offset = (notes_offset + notes.len()).next_multiple_of(PAGE_SIZE);
+    if offset > limit {
+        return Err(SysError::EFBIG);
+    }
+    for seg in segments.iter_mut() {
+        let size = seg.range.end.bits() - seg.range.start.bits();
+        seg.offset = offset;
+        seg.file_size = cmp::min(size, (limit - offset) / PAGE_SIZE * PAGE_SIZE);
+        offset += seg.file_size;
+    }
+
+    let mut w = Writer::new();
+    // e_ident: ELFCLASS64, ELFDATA2LSB, EV_CURRENT, ELFOSABI_NONE
+    w.bytes(b"\x7fELF").bytes(&[2, 1, 1, 0]).align(16);
+    w.u16(ET_CORE)
+        .u16(EM_RISCV)
+        .u32(1)
+        .u64(0)
+        .u64(ELF_HEADER_SIZE as u64)
+        .u64(0)
+        .u32(0)
+        .u16(ELF_HEADER_SIZE as u16)
+        .u16(PROGRAM_HEADER_SIZE as u16)
+        .u16(phnum as u16)
+        .u16(0)
+        .u16(0)
+        .u16(0);
+    w.u32(PT_NOTE)
+        .u32(0)
+        .u64(notes_offset as u64)
+        .u64(0)
+        .u64(0)
+        .u64(notes.len() as u64)
+        .u64(0)
+        .u64(4);
+    for seg in segments.iter() {
+        let size = seg.range.end.bits() - seg.range.start.bits();
+        w.u32(PT_LOAD)
+            .u32(seg.flags())
+            .u64(seg.offset as u64)
+            .u64(seg.range.start.bits() as u64)
+            .u64(0)
+            .u64(seg.file_size as u64)
+            .u64(size as u64)
+            .u64(PAGE_SIZE as u64);
+    }
+    debug_assert_eq!(w.0.len(), notes_offset);
+    w.bytes(&notes);
+
+    let name = format!("core.{}", task.pid());
+    let dentry = task.at_helper(AtFd::FdCwd, &name, OpenFlags::O_NOFOLLOW)?;
+    if dentry.is_negetive() {
+        let parent = dentry.parent().expect("can not be root dentry");
+        task.create_helper(
+            &parent,
+            &name,
+            InodeMode::FILE | InodeMode::OWNER_READ | InodeMode::OWNER_WRITE,
+        )?;
+    } else {
+        let inode = dentry.inode()?;
+        if !inode.itype().is_file() {
+            return Err(SysError::EEXIST);
+        }
+        task.with_cred(|cred| cred.check_access(&inode, AccessMode::WRITE, false))?;
+    }
+    let file = dentry.open()?;
+    file.inode().truncate(0).await?;
+    file.write_at(0, &w.0).await?;
+
+    let zeros = vec![0; PAGE_SIZE];
+    for seg in segments.iter() {
+        let n = seg.file_size / PAGE_SIZE;
+        for (i, page) in seg.pages.iter().take(n).enumerate() {
+            let data = page
+                .as_ref()
+                .map_or(&zeros[..], |page| &*page.bytes_array());
+            file.write_at(seg.offset + i * PAGE_SIZE, data).await?;
+        }
+    }
+    Ok(offset)
+}
+
+/// Notes of the process, i.e. `NT_PRSTATUS` of the dumping thread and
+/// `NT_PRPSINFO`.
+fn notes(task: &Arc<Task>, sig: Sig) -> Vec<u8> {
+    let ppid = task
+        .parent()
+        .and_then(|p| p.upgrade())
+        .map_or(0, |p| p.pid());
+    let ids = [task.pid(), ppid, task.pgid(), task.sid()].map(|id| id as u32);
+
+    let mut status = Writer::new();
+    // pr_info, i.e. si_signo, si_code and si_errno, and pr_cursig
+    status
+        .u32(sig.raw() as u32)
+        .u32(0)
+        .u32(0)
+        .u16(sig.raw() as u16)
+        .align(8);
+    status
+        .u64(task.with_sig_pending(|pending| pending.bitmap.bits()))
+        .u64(task.sig_mask_ref().bits());
+    for id in ids {
+        status.u32(id);
+    }
+    let (utime, stime) = task.get_process_ustime();
+    let children = task.get_children_rusage();
+    status
+        .timeval(utime)
+        .timeval(stime)
+        .timeval(children.utime.into())
+        .timeval(children.stime.into());
+    // pr_reg, i.e. pc and x1 to x31
+    for reg in task.trap_context_mut().user_regs() {
+        status.u64(reg as u64);
+    }
+    // pr_fpvalid
+    status.u32(0).align(8);
+    debug_assert_eq!(status.0.len(), PRSTATUS_SIZE);
+
+    let mut psinfo = Writer::new();
+    // pr_state, pr_sname, pr_zomb and pr_nice, then pr_flag
+    psinfo.bytes(&[0, b'R', 0, 0]).align(8).u64(0);
+    let (uid, gid) = task.with_cred(|cred| (cred.user.real, cred.group.real));
+    psinfo.u32(uid).u32(gid);
+    for id in ids {
+        psinfo.u32(id);
+    }
+    psinfo
+        .cstr(&task.elf_ref().dentry().name(), 16)
+        .cstr(&task.args_ref().join(" "), 80);
+    debug_assert_eq!(psinfo.0.len(), PRPSINFO_SIZE);
+
+    let mut w = Writer::new();
+    w.note(NT_PRSTATUS, &status.0).note(NT_PRPSINFO, &psinfo.0);
+    w.0
+}
@@ -0,0 +1,263 @@
+//! Credentials of processes, i.e. user and group IDs used for permission
+//! checks.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use systype::{SysError, SysResult};
+use vfs_core::{AccessMode, Dentry, Inode, InodeMode};
+
+/// Max number of supplementary groups.
+pub const NGROUPS_MAX: usize = 65536;
+
+/// An ID of -1 passed to `setre*id` and `setres*id` means unchanged.
+pub const ID_UNCHANGED: u32 = u32::MAX;
+
+/// A set of user IDs or group IDs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ids {
+    /// Real ID, the one of the owner of the process.
+    pub real: u32,
+    /// Effective ID, used for most permission checks.
+    pub effective: u32,
+    /// Saved set ID, which an unprivileged process can switch its effective ID
+    /// back to.
+    pub saved: u32,
+    /// Filesystem ID, used for permission checks of file access, which follows
+    /// the effective ID.
+    pub fs: u32,
+}
+
+impl Ids {
+    fn contains(&self, id: u32) -> bool {
+        id == self.real || id == self.effective || id == self.saved
+    }
+
+    /// `setuid` and `setgid`. A privileged process sets all IDs, while an
+    /// unprivileged one can only set the effective ID to its real or saved set
+    /// ID.
+    pub fn set(&mut self, id: u32, privileged: bool) -> SysResult<()> {
+        if id == ID_UNCHANGED {
+            return Err(SysError::EINVAL);
+        }
+        if privileged {
+            self.real = id;
+            self.saved = id;
+        } else if id != self.real && id != self.saved {
+            return Err(SysError::EPERM);
+        }
+        self.effective = id;
+        self.fs = id;
+        Ok(())
+    }
+
+    /// `setreuid` and `setregid`. An unprivileged process can only set the real
+    /// ID to its real or effective ID, and the effective ID to any of its real,
+    /// effective or saved set ID.
+    pub fn set_re(&mut self, real: u32, effective: u32, privileged: bool) -> SysResult<()> {
+        if !privileged
+            && ((real != ID_UNCHANGED && real != self.real && real != self.effective)
+                || (effective != ID_UNCHANGED && !self.contains(effective)))
+        {
+            return Err(SysError::EPERM);
+        }
+        let old_real = self.real;
+        if real != ID_UNCHANGED {
+            self.real = real;
+        }
+        if effective != ID_UNCHANGED {
+            self.effective = effective;
+        }
+        // The saved set ID follows the effective ID if the real ID is set, or the
+        // effective ID is set to a value other than the previous real ID.
+        if real != ID_UNCHANGED || (effective != ID_UNCHANGED && effective != old_real) {
+            self.saved = self.effective;
+        }
+        self.fs = self.effective;
+        Ok(())
+    }
+
+    /// `setresuid` and `setresgid`. An unprivileged process can only set each
+    /// ID to any of its real, effective or saved set ID.
+    pub fn set_res(
+        &mut self,
+        real: u32,
+        effective: u32,
+        saved: u32,
+        privileged: bool,
+    ) -> SysResult<()> {
+        if !privileged
+            && [real, effective, saved]
+                .into_iter()
+                .any(|id| id != ID_UNCHANGED && !self.contains(id))
+        {
+            return Err(SysError::EPERM);
+        }
+        if real != ID_UNCHANGED {
+            self.real = real;
+        }
+        if effective != ID_UNCHANGED {
+            self.effective = effective;
+        }
+        if saved != ID_UNCHANGED {
+            self.saved = saved;
+        }
+        self.fs = self.effective;
+        Ok(())
+    }
+}
+
+/// Credentials of a process, shared by its threads.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub user: Ids,
+    pub group: Ids,
+    /// Supplementary group IDs.
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// Credentials of root, where all IDs are 0.
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Whether the process can change its IDs and groups arbitrarily, which
+    /// Linux grants by `CAP_SETUID` and `CAP_SETGID`, i.e. the effective user
+    /// ID is root.
+    pub fn is_privileged(&self) -> bool {
+        self.user.effective == 0
+    }
+
+    /// Whether the process can send a signal to a process of `target`, which
+    /// needs its real or effective user ID to be the real or saved set user
+    /// ID of the target, unless it is privileged.
+    pub fn can_signal(&self, target: &Credentials) -> bool {
+        self.is_privileged()
+            || [self.user.real, self.user.effective]
+                .into_iter()
+                .any(|uid| uid == target.user.real || uid == target.user.saved)
+    }
+
+    /// Whether the process can change the nice value of a process of `target`,
+    /// which needs its effective user ID to be the real or effective user ID
+    /// of the target, unless it is privileged.
+    pub fn can_renice(&self, target: &Credentials) -> bool {
+        self.is_privileged()
+            || self.user.effective == target.user.real
+            || self.user.effective == target.user.effective
+    }
+
+    /// Whether the process can trace a process of `target` by ptrace(2), which
+    /// needs all the user and group IDs of the target to be the real ones of
+    /// the caller, unless it is privileged.
+    pub fn can_trace(&self, target: &Credentials) -> bool {
+        let same = |ids: &Ids, real: u32| {
+            [ids.real, ids.effective, ids.saved]
+                .into_iter()
+                .all(|id| id == real)
+        };
+        self.is_privileged()
+            || (same(&target.user, self.user.real) && same(&target.group, self.group.real))
+    }
+
+    pub fn set_groups(&mut self, groups: Vec<u32>) -> SysResult<()> {
+        if !self.is_privileged() {
+            return Err(SysError::EPERM);
+        }
+        self.groups = groups;
+        Ok(())
+    }
+
+    /// Check whether the process can `access` the inode with its filesystem
+    /// IDs, or real IDs if `real` is set, as `access` does by default.
+    pub fn check_access(
+        &self,
+        inode: &Arc<dyn Inode>,
+        access: AccessMode,
+        real: bool,
+    ) -> SysResult<()> {
+        let (uid, gid) = if real {
+            (self.user.real, self.group.real)
+        } else {
+            (self.user.fs, self.group.fs)
+        };
+        inode.check_access(uid, |id| id == gid || self.groups.contains(&id), access)
+    }
+
+    /// Whether the process can remove or rename the `child` in directory
+    /// `dir`, which needs write and search permission of `dir`, and ownership
+    /// of either of them if `dir` is sticky.
+    pub fn check_unlink(&self, dir: &Arc<dyn Inode>, child: &Arc<dyn Inode>) -> SysResult<()> {
+        self.check_access(dir, AccessMode::WRITE | AccessMode::EXEC, false)?;
+        let uid = self.user.fs;
+        if dir.mode().contains(InodeMode::STICKY)
+            && uid != 0
+            && uid != dir.owner().0
+            && uid != child.owner().0
+        {
+            return Err(SysError::EPERM);
+        }
+        Ok(())
+    }
+
+    /// Make a newly created inode owned by the process.
+    pub fn own(&self, dentry: &Arc<dyn Dentry>) -> SysResult<()> {
+        dentry.set_owner(self.user.fs, self.group.fs)
+    }
+
+    fn in_group(&self, gid: u32) -> bool {
+        gid == self.group.fs || self.groups.contains(&gid)
+    }
+
+    /// Check whether the process can change the mode of `inode` to `mode`,
+    /// which needs ownership of it, and return the mode to set. The
+    /// set-group-ID bit is cleared silently if an unprivileged process is not
+    /// in the group of a file.
+    pub fn check_chmod(&self, inode: &Arc<dyn Inode>, mode: InodeMode) -> SysResult<InodeMode> {
+        let (uid, gid) = inode.owner();
+        if self.user.fs != 0 && self.user.fs != uid {
+            return Err(SysError::EPERM);
+        }
+        if self.user.fs != 0 && !inode.itype().is_dir() && !self.in_group(gid) {
+            return Ok(mode - InodeMode::SET_GID);
+        }
+        Ok(mode)
+    }
+
+    /// Check whether the process can change the owner of `inode` to `uid` and
+    /// `gid`, where `ID_UNCHANGED` keeps the old one, and return the owner to
+    /// set. Only a privileged process can change the user, while the owner
+    /// can change the group to any group it is in.
+    pub fn check_chown(&self, inode: &Arc<dyn Inode>, uid: u32, gid: u32) -> SysResult<(u32, u32)> {
+        let (old_uid, old_gid) = inode.owner();
+        let uid = if uid == ID_UNCHANGED { old_uid } else { uid };
+        let gid = if gid == ID_UNCHANGED { old_gid } else { gid };
+        if self.user.fs != 0
+            && (uid != old_uid
+                || (gid != old_gid && (self.user.fs != old_uid || !self.in_group(gid))))
+        {
+            return Err(SysError::EPERM);
+        }
+        Ok((uid, gid))
+    }
+
+    /// Update IDs when the process executes `elf`, whose set-user-ID and
+    /// set-group-ID bits make the effective IDs the owner of it. The saved set
+    /// IDs are copied from the effective IDs.
+    pub fn exec(&mut self, elf: &Arc<dyn Inode>) {
+        let mode = elf.mode();
+        let (uid, gid) = elf.owner();
+        if mode.contains(InodeMode::SET_UID) {
+            self.user.effective = uid;
+        }
+        // Without group execute bit, the set-group-ID bit marks mandatory
+        // locking instead.
+        if mode.contains(InodeMode::SET_GID | InodeMode::GROUP_EXEC) {
+            self.group.effective = gid;
+        }
+        for ids in [&mut self.user, &mut self.group] {
+            ids.saved = ids.effective;
+            ids.fs = ids.effective;
+        }
+    }
+}
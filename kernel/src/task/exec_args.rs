@@ -0,0 +1,133 @@
+//! Arguments and environment of execve.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::mem::size_of;
+
+use config::process::{ARG_MAX, MAX_ARG_STRLEN};
+use systype::{SysError, SysResult};
+
+use super::Task;
+use crate::mm::UserReadPtr;
+
+/// Arguments and environment of a new program, which are pushed onto its
+/// stack by `init_stack`.
+///
+/// They are copied from the old memory space before it is replaced, so they
+/// are kept in one buffer rather than a string each. Like Linux, the strings
+/// along with their pointers on the stack take at most `ARG_MAX` bytes, and a
+/// string takes at most `MAX_ARG_STRLEN` bytes, otherwise `E2BIG` is returned
+/// before copying any further, so that huge or numerous strings can not
+/// exhaust the kernel heap.
+pub struct ExecArgs {
+    /// Strings of argv followed by those of envp, each ending with null.
+    strings: Vec<u8>,
+    argc: usize,
+    envc: usize,
+}
+
+impl ExecArgs {
+    /// Arguments and environment given by the kernel, e.g. of the init process.
+    pub fn from_strs(argv: &[&str], envp: &[&str]) -> Self {
+        let mut strings = Vec::new();
+        for s in argv.iter().chain(envp) {
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+        }
+        Self {
+            strings,
+            argc: argv.len(),
+            envc: envp.len(),
+        }
+    }
+
+    /// Copy `argv` and `envp` from user space, which are arrays of pointers
+    /// ending with null, or NULL as allowed by Linux.
+    pub fn from_user(
+        task: &Arc<Task>,
+        argv: UserReadPtr<usize>,
+        envp: UserReadPtr<usize>,
+    ) -> SysResult<Self> {
+        let mut args = Self::from_strs(&[], &[]);
+        for ptr in args.read_ptrs(task, argv)? {
+            args.push_user(task, ptr.into())?;
+            args.argc += 1;
+        }
+        for ptr in args.read_ptrs(task, envp)? {
+            args.push_user(task, ptr.into())?;
+            args.envc += 1;
+        }
+        Ok(args)
+    }
+
+    /// Bytes taken on the stack by the strings and their pointers.
+    fn size(&self) -> usize {
+        self.strings.len() + (self.argc + self.envc) * size_of::<usize>()
+    }
+
+    /// Read an array of pointers, which are no more than the space left.
+    fn read_ptrs(&self, task: &Arc<Task>, ptrs: UserReadPtr<usize>) -> SysResult<Vec<usize>> {
+        if ptrs.is_null() {
+            return Ok(Vec::new());
+        }
+        ptrs.read_cvec(
+            task,
+            ARG_MAX.saturating_sub(self.size()) / size_of::<usize>(),
+        )
+    }
+
+    /// Append a string from user space, which is limited by the space left
+    /// along with its pointer.
+    fn push_user(&mut self, task: &Arc<Task>, ptr: UserReadPtr<u8>) -> SysResult<()> {
+        let max = ARG_MAX
+            .saturating_sub(self.size() + size_of::<usize>())
+            .min(MAX_ARG_STRLEN);
+        ptr.read_cstr_into(task, &mut self.strings, max)
+            .map_err(|e| match e {
+                SysError::ENAMETOOLONG => SysError::E2BIG,
+                e => e,
+            })
+    }
+
+    /// Remove argv[0], e.g. which is replaced by the interpreter of a script.
+    pub fn remove_arg0(&mut self) {
+        if self.argc > 0 {
+            let len = self.strings.iter().position(|&c| c == 0).unwrap() + 1;
+            self.strings.drain(..len);
+            self.argc -= 1;
+        }
+    }
+
+    /// Insert `args` before the arguments.
+    pub fn prepend_args(&mut self, args: &[&str]) -> SysResult<()> {
+        let len: usize = args.iter().map(|s| s.len() + 1 + size_of::<usize>()).sum();
+        if self.size() + len > ARG_MAX {
+            return Err(SysError::E2BIG);
+        }
+        let head = args.iter().flat_map(|s| s.bytes().chain([0]));
+        self.strings.splice(..0, head);
+        self.argc += args.len();
+        Ok(())
+    }
+
+    pub fn argc(&self) -> usize {
+        self.argc
+    }
+
+    pub fn envc(&self) -> usize {
+        self.envc
+    }
+
+    /// Strings of argv followed by those of envp, each ending with null.
+    pub fn strings(&self) -> &[u8] {
+        &self.strings
+    }
+
+    /// Arguments converted lossily, e.g. to show in the process tree.
+    pub fn args_lossy(&self) -> Vec<String> {
+        self.strings
+            .split(|&c| c == 0)
+            .take(self.argc)
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect()
+    }
+}
@@ -0,0 +1,285 @@
+//! Handles of kernel tasks, and background kernel tasks that are cancelled
+//! cooperatively on shutdown.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use arch::time::get_time_duration;
+use executor::TaskHandle;
+use sync::{mutex::SpinNoIrqLock, WaitQueue};
+use timer::timeout::{timeout, timeout_at, TimedOut};
+
+use super::spawn_kernel_task;
+
+/// Handle of a kernel task, which resolves to the output of the task when
+/// awaited. Unlike `async_task::Task`, dropping the handle detaches the task
+/// instead of cancelling it.
+pub struct JoinHandle<T> {
+    task: Option<TaskHandle<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub(super) fn new(task: TaskHandle<T>) -> Self {
+        Self { task: Some(task) }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.as_ref().unwrap().is_finished()
+    }
+
+    /// Cancel the task, which is dropped at the await point it is pending on
+    /// without running any further. Return the output if it has finished
+    /// already.
+    pub async fn abort(mut self) -> Option<T> {
+        self.task.take().unwrap().cancel().await
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(self.get_mut().task.as_mut().unwrap()).poll(cx)
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.detach();
+        }
+    }
+}
+
+struct CancelState {
+    cancelled: AtomicBool,
+    waiters: WaitQueue,
+}
+
+/// Token to ask a long-running kernel task to stop, which should be checked
+/// by the task between its rounds of work, so that it stops in a consistent
+/// state rather than wherever it is pending.
+#[derive(Clone)]
+pub struct CancelToken(Arc<CancelState>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self(Arc::new(CancelState {
+            cancelled: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+        }))
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Release);
+        self.0.waiters.wake_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        self.0.waiters.wait_until(|| self.is_cancelled()).await
+    }
+
+    /// Sleep for `duration` unless the token is cancelled meanwhile. Return
+    /// false if it is cancelled, in which case the task should stop.
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        timeout(duration, self.cancelled()).await == Err(TimedOut) && !self.is_cancelled()
+    }
+}
+
+struct BackgroundTask {
+    name: &'static str,
+    token: CancelToken,
+    handle: JoinHandle<()>,
+}
+
+/// Background kernel tasks that are running, which are stopped by
+/// `shutdown_kernel_tasks`.
+static BACKGROUND_TASKS: SpinNoIrqLock<Vec<BackgroundTask>> = SpinNoIrqLock::new(Vec::new());
+
+/// Spawn a long-running kernel task, e.g. a periodic service, which is given a
+/// token to observe cancellation on shutdown.
+pub fn spawn_background_task<F, Fut>(name: &'static str, f: F)
+where
+    F: FnOnce(CancelToken) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let token = CancelToken::new();
+    let handle = spawn_kernel_task(f(token.clone()));
+    let mut tasks = BACKGROUND_TASKS.lock();
+    tasks.retain(|task| !task.handle.is_finished());
+    tasks.push(BackgroundTask {
+        name,
+        token,
+        handle,
+    });
+}
+
+/// Cancel all background tasks and wait for them to stop. Tasks that are still
+/// running after `limit` are aborted.
+pub async fn shutdown_kernel_tasks(limit: Duration) {
+    let tasks = mem::take(&mut *BACKGROUND_TASKS.lock());
+    for task in tasks.iter() {
+        task.token.cancel();
+    }
+    let deadline = get_time_duration() + limit;
+    for BackgroundTask {
+        name, mut handle, ..
+    } in tasks
+    {
+        if timeout_at(deadline, &mut handle).await.is_err() {
+            log::warn!("[shutdown_kernel_tasks] {name} does not stop in time, abort it");
+            handle.abort().await;
+        } else {
+            log::info!("[shutdown_kernel_tasks] {name} stopped");
+        }
+    }
+}
+
+/// Check that a kernel task observes cancellation while it is sleeping, that
+/// aborting a task which never finishes works, and that `block_on` completes a
+/// future which waits for another task.
+#[cfg(feature = "selftest")]
+pub async fn selftest() {
+    let token = CancelToken::new();
+    let handle = spawn_kernel_task({
+        let token = token.clone();
+        async move { !token.sleep(Duration::from_secs(10)).await }
+    });
+    timer::timelimited_task::ksleep_ms(10).await;
+    token.cancel();
+    let observed = timeout(Duration::from_secs(1), handle).await;
+    assert_eq!(observed, Ok(true), "cancellation is not observed");
+
+    let handle = spawn_kernel_task(core::future::pending::<()>());
+    assert!(handle.abort().await.is_none());
+
+    // the task is run in place or woken by the timer while blocking on it
+    let handle = spawn_kernel_task(async {
+        async_utils::yield_now().await;
+        timer::timelimited_task::ksleep_ms(10).await;
+        42
+    });
+    assert_eq!(async_utils::block_on(handle), 42);
+    log::info!("[kernel_task] selftest passed");
+}
+
+/// Read blocks with more requests in flight than the virtqueue takes at once,
+/// some of whose waiters are dropped early, and check that every read gives
+/// the same data as a read on its own. Then write a block back unchanged and
+/// read it again.
+#[cfg(feature = "selftest")]
+pub async fn blk_selftest() {
+    use config::board::BLOCK_SIZE;
+
+    const BLOCKS: usize = 16;
+    const READERS: usize = 64;
+    let Some(dev) = driver::BLOCK_DEVICE.get().cloned() else {
+        log::warn!("[blk] no block device, selftest skipped");
+        return;
+    };
+    let expected: Vec<_> = (0..BLOCKS)
+        .map(|block_id| {
+            let mut buf = [0u8; BLOCK_SIZE];
+            dev.base_read_blocks(block_id, &mut buf);
+            buf
+        })
+        .collect();
+    let expected = Arc::new(expected);
+
+    let handles: Vec<_> = (0..READERS)
+        .map(|i| {
+            let dev = dev.clone();
+            let expected = expected.clone();
+            spawn_kernel_task(async move {
+                let mut buf = [0u8; BLOCK_SIZE];
+                if i % 8 == 0 {
+                    // abandon a request, which must not disturb the others
+                    let _ =
+                        timeout(Duration::ZERO, dev.read_blocks_async(i % BLOCKS, &mut buf)).await;
+                }
+                dev.read_blocks_async(i % BLOCKS, &mut buf).await;
+                buf == expected[i % BLOCKS]
+            })
+        })
+        .collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert!(handle.await, "concurrent read {i} gives wrong data");
+    }
+
+    let block_id = BLOCKS - 1;
+    dev.write_blocks_async(block_id, &expected[block_id]).await;
+    let mut buf = [0u8; BLOCK_SIZE];
+    dev.read_blocks_async(block_id, &mut buf).await;
+    assert!(buf == expected[block_id], "block written back is changed");
+    log::info!("[blk] selftest passed");
+}
+
+/// Hold a `SpinNoIrqLock` for too long, then across an await, on purpose, both
+/// of which must be reported by the lock diagnostics of a debug build.
+#[cfg(feature = "lock-debug")]
+pub async fn lock_debug_test() {
+    static LOCK: SpinNoIrqLock<usize> = SpinNoIrqLock::new(0);
+    {
+        let mut guard = LOCK.lock();
+        let begin = get_time_duration();
+        while get_time_duration() - begin < Duration::from_millis(100) {
+            core::hint::spin_loop();
+        }
+        *guard += 1;
+    }
+    let mut guard = unsafe { LOCK.sent_lock() };
+    async_utils::yield_now().await;
+    *guard += 1;
+    log::info!("[kernel_task] lock debug test done");
+}
+
+/// Measure random reads of single blocks from the block device, with 1, 4 and
+/// 8 reads kept in flight by as many kernel tasks, which only scales if the
+/// device completes requests by interrupts and takes more than one at once.
+#[cfg(feature = "blk-bench")]
+pub async fn blk_bench() {
+    use config::board::BLOCK_SIZE;
+
+    const READS: usize = 4096;
+    let dev = driver::BLOCK_DEVICE.get().unwrap().clone();
+    let blocks = dev.size() as usize / BLOCK_SIZE;
+    for depth in [1, 4, 8] {
+        let begin = get_time_duration();
+        let handles: Vec<_> = (0..depth)
+            .map(|i| {
+                let dev = dev.clone();
+                spawn_kernel_task(async move {
+                    let mut buf = [0u8; BLOCK_SIZE];
+                    let mut seed = 0x9e3779b97f4a7c15usize ^ (i + 1);
+                    for _ in 0..READS / depth {
+                        // xorshift
+                        seed ^= seed << 13;
+                        seed ^= seed >> 7;
+                        seed ^= seed << 17;
+                        dev.read_blocks_async(seed % blocks, &mut buf).await;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.await;
+        }
+        let usec = (get_time_duration() - begin).as_micros().max(1) as usize;
+        log::warn!(
+            "[blk_bench] depth {depth}: {} IOPS",
+            READS * 1_000_000 / usec
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Load averages, computed the same way as Linux
+//!
+//! The number of runnable tasks is sampled every `LOAD_FREQ_MS` from the timer
+//! tick, and decayed exponentially into 1, 5 and 15 minute averages, which are
+//! fixed point numbers with `FSHIFT` bits of fraction.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use arch::time::get_time_ms;
+
+const FSHIFT: usize = 11;
+const FIXED_1: usize = 1 << FSHIFT;
+/// 1 / exp(5sec / 1min), 1 / exp(5sec / 5min), 1 / exp(5sec / 15min) as fixed
+/// point.
+const EXP: [usize; 3] = [1884, 2014, 2037];
+
+const LOAD_FREQ_MS: usize = 5000;
+
+/// `loads` of `struct sysinfo` have 16 bits of fraction.
+const SI_LOAD_SHIFT: usize = 16;
+
+static AVENRUN: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+static NEXT_SAMPLE_MS: AtomicUsize = AtomicUsize::new(LOAD_FREQ_MS);
+
+fn calc_load(load: usize, exp: usize, active: usize) -> usize {
+    let mut newload = load * exp + active * (FIXED_1 - exp);
+    if active >= load {
+        newload += FIXED_1 - 1;
+    }
+    newload / FIXED_1
+}
+
+/// Called on every timer tick of every hart, only one of which samples when
+/// it is time.
+pub fn sample() {
+    let now = get_time_ms();
+    let next = NEXT_SAMPLE_MS.load(Ordering::Relaxed);
+    if now < next
+        || NEXT_SAMPLE_MS
+            .compare_exchange(
+                next,
+                now + LOAD_FREQ_MS,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_err()
+    {
+        return;
+    }
+    let active = executor::nr_running() * FIXED_1;
+    for (avenrun, exp) in AVENRUN.iter().zip(EXP) {
+        let load = avenrun.load(Ordering::Relaxed);
+        avenrun.store(calc_load(load, exp, active), Ordering::Relaxed);
+    }
+}
+
+/// 1, 5 and 15 minute load averages in the format of `struct sysinfo`.
+pub fn loads() -> [u64; 3] {
+    AVENRUN
+        .each_ref()
+        .map(|avenrun| (avenrun.load(Ordering::Relaxed) << (SI_LOAD_SHIFT - FSHIFT)) as u64)
+}
@@ -3,33 +3,47 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
+use core::{
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use config::process::INIT_PROC_PID;
 use hashbrown::HashMap;
 use spin::Lazy;
 use sync::mutex::SpinNoIrqLock;
-use systype::SysResult;
+use systype::{SysError, SysResult};
+use vfs_core::Inode;
 
-use super::{task::Task, PGid, Tid};
+use super::{task::Task, PGid, Pid, Tid};
 
 pub static TASK_MANAGER: Lazy<TaskManager> = Lazy::new(TaskManager::new);
 
 pub static PROCESS_GROUP_MANAGER: ProcessGroupManager = ProcessGroupManager::new();
 
+pub static SESSION_MANAGER: SessionManager = SessionManager::new();
+
 /// Tid -> Task
-pub struct TaskManager(SpinNoIrqLock<HashMap<Tid, Weak<Task>>>);
+pub struct TaskManager {
+    tasks: SpinNoIrqLock<HashMap<Tid, Weak<Task>>>,
+    /// Set when the system is going down, after which no task can be created.
+    admission_stopped: AtomicBool,
+}
 
 impl TaskManager {
     pub fn new() -> Self {
-        Self(SpinNoIrqLock::new(HashMap::new()))
+        Self {
+            tasks: SpinNoIrqLock::new(HashMap::new()),
+            admission_stopped: AtomicBool::new(false),
+        }
     }
 
     pub fn add(&self, task: &Arc<Task>) {
-        self.0.lock().insert(task.tid(), Arc::downgrade(task));
+        self.tasks.lock().insert(task.tid(), Arc::downgrade(task));
     }
 
     pub fn remove(&self, tid: Tid) {
-        self.0.lock().remove(&tid);
+        self.tasks.lock().remove(&tid);
     }
 
     /// Get the init process.
@@ -38,33 +52,62 @@ impl TaskManager {
     }
 
     pub fn get(&self, tid: Tid) -> Option<Arc<Task>> {
-        match self.0.lock().get(&tid) {
+        match self.tasks.lock().get(&tid) {
             Some(task) => task.upgrade(),
             None => None,
         }
     }
 
+    /// Find the process, i.e. the thread group leader, whose pid is `pid`.
+    pub fn find(&self, pid: Pid) -> Option<Arc<Task>> {
+        self.get(pid).filter(|task| task.is_leader())
+    }
+
+    /// All tasks alive. Tasks are only held weakly, so the ones dropped but
+    /// not removed yet are skipped.
     pub fn tasks(&self) -> Vec<Arc<Task>> {
-        self.0
+        self.tasks
             .lock()
             .values()
-            .map(|t| t.upgrade().unwrap())
+            .filter_map(|t| t.upgrade())
             .collect()
     }
 
-    pub fn for_each(&self, f: impl Fn(&Arc<Task>) -> SysResult<()>) -> SysResult<()> {
-        for task in self.0.lock().values() {
-            f(&task.upgrade().unwrap())?
+    /// All processes alive, i.e. the thread group leaders.
+    pub fn processes(&self) -> Vec<Arc<Task>> {
+        let mut processes = self.tasks();
+        processes.retain(|task| task.is_leader());
+        processes
+    }
+
+    /// Call `f` with each process and its pid. The processes are collected
+    /// under the lock and `f` is called without it, so `f` may create or reap
+    /// tasks.
+    pub fn for_each(&self, mut f: impl FnMut(Pid, &Arc<Task>) -> SysResult<()>) -> SysResult<()> {
+        for process in self.processes() {
+            f(process.tid(), &process)?
         }
         Ok(())
     }
 
     pub fn len(&self) -> usize {
-        self.0.lock().len()
+        self.tasks.lock().len()
+    }
+
+    /// Refuse to create tasks from now on, which is done on shutdown.
+    pub fn stop_admission(&self) {
+        self.admission_stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn admits(&self) -> bool {
+        !self.admission_stopped.load(Ordering::SeqCst)
     }
 }
 
 /// PGid -> Process group
+///
+/// Processes are held weakly, and a process is removed from its group when it
+/// is reaped or joins another group. Empty groups are removed.
 // TODO: process group should be created by shell forking, but how do we
 // recognize a shell? may be by sid, which will introduce session in extra.
 pub struct ProcessGroupManager(SpinNoIrqLock<BTreeMap<PGid, Vec<Weak<Task>>>>);
@@ -74,34 +117,117 @@ impl ProcessGroupManager {
         Self(SpinNoIrqLock::new(BTreeMap::new()))
     }
 
+    /// Make `group_leader` the leader of a new group, whose pgid is its pid.
     pub fn add_group(&self, group_leader: &Arc<Task>) {
-        let pgid = group_leader.tid();
-        group_leader.set_pgid(pgid);
-        let mut group = Vec::new();
-        group.push(Arc::downgrade(group_leader));
-        self.0.lock().insert(pgid, group);
+        self.add_process(group_leader.tid(), group_leader);
     }
 
+    /// Move `process` to the group `pgid`, which is created if there is none.
     pub fn add_process(&self, pgid: PGid, process: &Arc<Task>) {
         if !process.is_leader() {
             log::warn!("[ProcessGroupManager::add_process] try adding task that is not a process");
             return;
         }
+        let mut groups = self.0.lock();
+        Self::leave(&mut groups, process);
         process.set_pgid(pgid);
-        let mut inner = self.0.lock();
-        let vec = inner.get_mut(&pgid).unwrap();
-        vec.push(Arc::downgrade(process));
+        groups
+            .entry(pgid)
+            .or_default()
+            .push(Arc::downgrade(process));
     }
 
     pub fn get_group(&self, pgid: PGid) -> Option<Vec<Weak<Task>>> {
         self.0.lock().get(&pgid).cloned()
     }
 
+    pub fn contains(&self, pgid: PGid) -> bool {
+        self.0.lock().contains_key(&pgid)
+    }
+
+    /// Session of the group `pgid`, which is shared by all its processes.
+    pub fn session_of(&self, pgid: PGid) -> Option<Pid> {
+        self.processes_in_group(pgid)
+            .first()
+            .map(|process| process.sid())
+    }
+
+    /// Processes alive in the group `pgid`, which is empty if there is no such
+    /// group.
+    pub fn processes_in_group(&self, pgid: PGid) -> Vec<Arc<Task>> {
+        self.get_group(pgid)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|process| process.upgrade())
+            .collect()
+    }
+
     pub fn remove(&self, process: &Arc<Task>) {
+        Self::leave(&mut self.0.lock(), process)
+    }
+
+    /// Remove `process` from its group, along with the processes dropped. The
+    /// processes are not upgraded, so none of them is dropped under the lock.
+    fn leave(groups: &mut BTreeMap<PGid, Vec<Weak<Task>>>, process: &Arc<Task>) {
+        let pgid = process.pgid();
+        let Some(group) = groups.get_mut(&pgid) else {
+            return;
+        };
+        group.retain(|p| p.strong_count() > 0 && !ptr::eq(p.as_ptr(), Arc::as_ptr(process)));
+        if group.is_empty() {
+            groups.remove(&pgid);
+        }
+    }
+}
+
+/// Sid -> Controlling terminal of the session
+///
+/// A session is created by `setsid` without a controlling terminal, and its
+/// leader acquires one by `TIOCSCTTY`. The terminal is released when the leader
+/// exits or gives it up by `TIOCNOTTY`.
+pub struct SessionManager(SpinNoIrqLock<BTreeMap<Pid, Arc<dyn Inode>>>);
+
+impl SessionManager {
+    pub const fn new() -> Self {
+        Self(SpinNoIrqLock::new(BTreeMap::new()))
+    }
+
+    pub fn ctty(&self, sid: Pid) -> Option<Arc<dyn Inode>> {
+        self.0.lock().get(&sid).cloned()
+    }
+
+    /// Session of which `tty` is the controlling terminal.
+    pub fn session_of(&self, tty: &Arc<dyn Inode>) -> Option<Pid> {
         self.0
             .lock()
-            .get_mut(&process.pgid())
-            .unwrap()
-            .retain(|task| task.upgrade().map_or(false, |t| !Arc::ptr_eq(process, &t)))
+            .iter()
+            .find(|(_, ctty)| Arc::ptr_eq(ctty, tty))
+            .map(|(&sid, _)| sid)
+    }
+
+    /// Make `tty` the controlling terminal of the session `sid`, which must
+    /// have none. A terminal controlling another session is taken from it only
+    /// if `steal` is set.
+    pub fn set_ctty(&self, sid: Pid, tty: Arc<dyn Inode>, steal: bool) -> SysResult<()> {
+        let mut sessions = self.0.lock();
+        if sessions.contains_key(&sid) {
+            return Err(SysError::EPERM);
+        }
+        let owner = sessions
+            .iter()
+            .find(|(_, ctty)| Arc::ptr_eq(ctty, &tty))
+            .map(|(&sid, _)| sid);
+        if let Some(owner) = owner {
+            if !steal {
+                return Err(SysError::EPERM);
+            }
+            sessions.remove(&owner);
+        }
+        sessions.insert(sid, tty);
+        Ok(())
+    }
+
+    pub fn release(&self, sid: Pid) {
+        self.0.lock().remove(&sid);
     }
 }
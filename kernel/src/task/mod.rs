@@ -1,53 +1,79 @@
 pub mod aux;
+mod coredump;
+pub mod cred;
+pub mod exec_args;
+mod kernel_task;
+pub mod loadavg;
 mod manager;
+pub mod ptrace;
 pub mod resource;
 mod schedule;
+pub mod seccomp;
 pub mod signal;
 pub mod task;
 mod tid;
 
-use alloc::{string::ToString, vec, vec::Vec};
-
 use async_utils::block_on;
 use config::process::USER_STACK_SIZE;
-pub use manager::{PROCESS_GROUP_MANAGER, TASK_MANAGER};
+#[cfg(feature = "blk-bench")]
+pub use kernel_task::blk_bench;
+#[cfg(feature = "lock-debug")]
+pub use kernel_task::lock_debug_test;
+#[cfg(feature = "selftest")]
+pub use kernel_task::{blk_selftest, selftest};
+pub use kernel_task::{shutdown_kernel_tasks, spawn_background_task, CancelToken, JoinHandle};
+pub use manager::{PROCESS_GROUP_MANAGER, SESSION_MANAGER, TASK_MANAGER};
+#[cfg(feature = "recover-user")]
+pub use schedule::spawn_exit_task;
 pub use schedule::{spawn_kernel_task, spawn_user_task};
-pub use task::Task;
+pub use task::{Task, VforkDone};
 pub use tid::{PGid, Pid, Tid, TID_ALLOCATOR};
 use vfs::sys_root_dentry;
 use vfs_core::{OpenFlags, Path};
 
+use self::{
+    aux::{push_cred_auxv, AuxHeader, AT_BASE},
+    cred::Credentials,
+    exec_args::ExecArgs,
+};
 use crate::{
-    mm::memory_space::{init_stack, MemorySpace},
+    mm::memory_space::{init_stack, parse_elf, MemorySpace},
     processor::env::within_sum,
     trap::TrapContext,
 };
 
+/// Spawn the first user program, which is given by `init=<path>` in the
+/// bootargs.
 pub fn spawn_init_proc() {
     #[cfg(not(feature = "final2"))]
-    let init_proc_path = "/init_proc";
+    let default_path = "/init_proc";
     #[cfg(feature = "final2")]
-    let init_proc_path = "/final_tests";
-    let args = vec![init_proc_path.to_string()];
-    let envp = Vec::new();
+    let default_path = "/final_tests";
+    let init_proc_path = config::cmdline().get("init").unwrap_or(default_path);
+    let args = ExecArgs::from_strs(&[init_proc_path], &[]);
 
     let file = Path::new(sys_root_dentry(), sys_root_dentry(), init_proc_path)
         .walk(OpenFlags::empty())
-        .unwrap()
+        .unwrap_or_else(|e| panic!("No init found at {init_proc_path}: {e:?}"))
         .open()
         .unwrap();
     let elf_data = block_on(async { file.read_all().await }).unwrap();
 
     let mut memory_space = MemorySpace::new_user();
     unsafe { memory_space.switch_page_table() };
-    let (entry, auxv) = memory_space.parse_and_map_elf(file.clone(), &elf_data);
-    let sp_init = memory_space.alloc_stack_lazily(USER_STACK_SIZE);
-    let (sp, _argc, _argv, _envp) = within_sum(|| init_stack(sp_init, args.clone(), envp, auxv));
+    let (entry, mut auxv) = memory_space
+        .parse_and_map_elf(file.clone(), &elf_data)
+        .unwrap();
+    auxv.push(AuxHeader::new(AT_BASE, 0));
+    push_cred_auxv(&mut auxv, &Credentials::root());
+    let stack_perm = MemorySpace::elf_stack_perm(&parse_elf(&elf_data).unwrap());
+    let sp_init = memory_space.alloc_stack_lazily(USER_STACK_SIZE, stack_perm);
+    let (sp, _argc, _argv, _envp) = within_sum(|| init_stack(sp_init, init_proc_path, &args, auxv));
     memory_space.alloc_heap_lazily();
 
     let trap_context = TrapContext::new(entry, sp);
 
-    let task = Task::new_init(memory_space, trap_context, file, args);
+    let task = Task::new_init(memory_space, trap_context, file, args.args_lossy());
     schedule::spawn_user_task(task);
 }
 
@@ -89,6 +115,39 @@ macro_rules! generate_with_methods {
     };
 }
 
+/// Like `generate_with_methods`, but for fields of `Replaceable<T>`, whose
+/// shared part may be replaced, e.g. by unshare(2). The shared part is only
+/// cloned under the outer lock, so a replacement never races with its users.
+#[macro_export]
+macro_rules! generate_shared_with_methods {
+    ($($name:ident : $ty:ty),+) => {
+        paste::paste! {
+            $(
+                #[allow(unused)]
+                pub fn $name(&self) -> Shared<$ty> {
+                    self.$name.lock().clone()
+                }
+                /// Replace the shared part and return the old one, which should
+                /// be dropped without holding any lock.
+                #[allow(unused)]
+                pub fn [<replace_ $name>](&self, new: Shared<$ty>) -> Shared<$ty> {
+                    core::mem::replace(&mut *self.$name.lock(), new)
+                }
+                #[allow(unused)]
+                pub fn [<with_ $name>]<T>(&self, f: impl FnOnce(&$ty) -> T) -> T {
+                    log::trace!("with_{}", stringify!($name));
+                    f(&self.$name().lock())
+                }
+                #[allow(unused)]
+                pub fn [<with_mut_ $name>]<T>(&self, f: impl FnOnce(&mut $ty) -> T) -> T {
+                    log::trace!("with_mut_{}", stringify!($name));
+                    f(&mut self.$name().lock())
+                }
+            )+
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! generate_accessors {
     ($($field_name:ident : $field_type:ty),+) => {
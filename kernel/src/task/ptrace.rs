@@ -0,0 +1,231 @@
+//! Process tracing by ptrace(2).
+//!
+//! A tracee is a thread, traced by the process which attaches to it. It stops
+//! before a signal is delivered to it, and at syscall entry and exit if the
+//! tracer asks, where the tracer finds it by `wait4`, and may read and write
+//! its memory and registers before resuming it.
+
+use alloc::sync::{Arc, Weak};
+use core::cmp;
+
+use async_utils::suspend_now;
+use config::mm::PAGE_SIZE;
+use memory::VirtAddr;
+use signal::{Sig, SigDetails, SigInfo};
+use systype::{SysError, SysResult};
+
+use super::{task::TaskState, Task};
+use crate::mm::PageFaultAccessType;
+
+/// Tracing state of a tracee.
+pub struct Ptrace {
+    /// Leader of the tracer process.
+    tracer: Weak<Task>,
+    /// Stop at syscall entry and exit, set by `PTRACE_SYSCALL` and cleared by
+    /// `PTRACE_CONT`.
+    trace_syscall: bool,
+    /// Signal of the stop which has not been reported by `wait4` yet.
+    event: Option<Sig>,
+    /// Signal the tracer resumes the tracee with, which is delivered instead
+    /// of the one it stopped for.
+    resume_sig: Option<Sig>,
+}
+
+impl Task {
+    /// Whether the task is traced by any process.
+    pub fn is_ptraced(&self) -> bool {
+        self.with_ptrace(|ptrace| ptrace.is_some())
+    }
+
+    /// Whether the task is traced by the process of `tracer`.
+    pub fn is_ptraced_by(&self, tracer: &Arc<Task>) -> bool {
+        self.with_ptrace(|ptrace| {
+            ptrace
+                .as_ref()
+                .is_some_and(|p| p.tracer.as_ptr() == Arc::as_ptr(&tracer.leader()))
+        })
+    }
+
+    /// Signal of the stop for the tracer to be reported by `wait4`, if the
+    /// task is in a ptrace stop. The stop is reported only once if `consume`.
+    pub fn ptrace_event(&self, consume: bool) -> Option<Sig> {
+        if !self.is_traced() {
+            return None;
+        }
+        self.with_mut_ptrace(|ptrace| {
+            let ptrace = ptrace.as_mut()?;
+            if consume {
+                ptrace.event.take()
+            } else {
+                ptrace.event
+            }
+        })
+    }
+
+    /// Start to be traced by the process of `tracer`.
+    pub fn ptrace_attach(self: &Arc<Self>, tracer: &Arc<Task>) -> SysResult<()> {
+        let tracer = tracer.leader();
+        self.with_mut_ptrace(|ptrace| {
+            if ptrace.is_some() {
+                return Err(SysError::EPERM);
+            }
+            *ptrace = Some(Ptrace {
+                tracer: Arc::downgrade(&tracer),
+                trace_syscall: false,
+                event: None,
+                resume_sig: None,
+            });
+            Ok(())
+        })?;
+        tracer.with_mut_tracees(|tracees| tracees.insert(self.tid(), Arc::downgrade(self)));
+        Ok(())
+    }
+
+    /// Stop being traced, and be resumed if it is in a ptrace stop, with `sig`
+    /// delivered.
+    pub fn ptrace_detach(self: &Arc<Self>, sig: Option<Sig>) {
+        let Some(ptrace) = self.with_mut_ptrace(|ptrace| ptrace.take()) else {
+            return;
+        };
+        if let Some(tracer) = ptrace.tracer.upgrade() {
+            tracer.with_mut_tracees(|tracees| tracees.remove(&self.tid()));
+        }
+        if let Some(sig) = sig {
+            self.receive_siginfo(
+                SigInfo {
+                    sig,
+                    code: SigInfo::KERNEL,
+                    details: SigDetails::None,
+                },
+                true,
+            );
+        }
+        self.ptrace_wake();
+    }
+
+    /// Detach all the tracees of the process, when it exits.
+    pub fn ptrace_detach_all(&self) {
+        let tracees = self.with_mut_tracees(core::mem::take);
+        for tracee in tracees.values().filter_map(|t| t.upgrade()) {
+            tracee.ptrace_detach(None);
+        }
+    }
+
+    /// Resume the task from a ptrace stop with `sig` delivered instead of the
+    /// signal it stopped for, and stop at syscalls from now on if
+    /// `trace_syscall`.
+    pub fn ptrace_resume(&self, sig: Option<Sig>, trace_syscall: bool) {
+        self.with_mut_ptrace(|ptrace| {
+            if let Some(ptrace) = ptrace {
+                ptrace.trace_syscall = trace_syscall;
+                ptrace.event = None;
+                ptrace.resume_sig = sig;
+            }
+        });
+        self.ptrace_wake();
+    }
+
+    fn ptrace_wake(&self) {
+        let resumed = self.with_mut_state(|state| {
+            let traced = *state == TaskState::Traced;
+            if traced {
+                *state = TaskState::Running;
+            }
+            traced
+        });
+        if resumed {
+            self.waker_ref().as_ref().unwrap().wake_by_ref();
+        }
+    }
+
+    /// Read the memory of the task at `va` into `buf`, as the tracer does.
+    /// Pages are faulted in first.
+    pub fn ptrace_read(&self, va: usize, buf: &mut [u8]) -> SysResult<()> {
+        self.ptrace_access(va, buf.len(), PageFaultAccessType::RO, |bytes, off| {
+            buf[off..off + bytes.len()].copy_from_slice(bytes)
+        })
+    }
+
+    /// Write `buf` to the memory of the task at `va`, as the tracer does.
+    /// Pages are faulted in first, and copied on write if they are shared.
+    ///
+    /// NOTE: unlike Linux, areas that are not writable, e.g. the text, can not
+    /// be written even by the tracer.
+    pub fn ptrace_write(&self, va: usize, buf: &[u8]) -> SysResult<()> {
+        self.ptrace_access(va, buf.len(), PageFaultAccessType::RW, |bytes, off| {
+            bytes.copy_from_slice(&buf[off..off + bytes.len()])
+        })
+    }
+
+    /// Access `len` bytes of the memory of the task at `va`, where `f` is
+    /// called on the bytes in each page with their offset from `va`.
+    fn ptrace_access(
+        &self,
+        va: usize,
+        len: usize,
+        access: PageFaultAccessType,
+        mut f: impl FnMut(&mut [u8], usize),
+    ) -> SysResult<()> {
+        let mut off = 0;
+        while off < len {
+            let addr = VirtAddr::from(va.checked_add(off).ok_or(SysError::EIO)?);
+            let in_page = addr.page_offset();
+            let n = cmp::min(PAGE_SIZE - in_page, len - off);
+            self.handle_page_fault(addr, access)
+                .map_err(|_| SysError::EIO)?;
+            let page = self
+                .with_memory_space(|m| m.get_page(addr))
+                .ok_or(SysError::EIO)?;
+            f(page.bytes_array_range(in_page..in_page + n), off);
+            off += n;
+        }
+        Ok(())
+    }
+}
+
+/// Stop the tracee `task` for `sig`, which is reported to its tracer, until it
+/// is resumed by the tracer or killed. Returns the signal it is resumed with.
+pub async fn ptrace_stop(task: &Arc<Task>, sig: Sig) -> Option<Sig> {
+    let tracer = task.with_mut_ptrace(|ptrace| {
+        let ptrace = ptrace.as_mut()?;
+        ptrace.event = Some(sig);
+        ptrace.resume_sig = None;
+        ptrace.tracer.upgrade()
+    });
+    let Some(tracer) = tracer else {
+        return Some(sig);
+    };
+    // NOTE: the task must not stop once it is killed, or it will never wake up
+    let stopped = task.with_mut_state(|state| {
+        let alive = *state != TaskState::Terminated;
+        if alive {
+            *state = TaskState::Traced;
+        }
+        alive
+    });
+    if !stopped {
+        return None;
+    }
+    log::info!("[ptrace_stop] tid {} stopped for {sig:?}", task.tid());
+    tracer.receive_siginfo(
+        SigInfo {
+            sig: Sig::SIGCHLD,
+            code: SigInfo::CLD_TRAPPED,
+            details: SigDetails::None,
+        },
+        false,
+    );
+    while task.is_traced() {
+        suspend_now().await;
+    }
+    task.with_mut_ptrace(|ptrace| ptrace.as_mut().and_then(|p| p.resume_sig.take()))
+}
+
+/// Stop at syscall entry or exit for the tracer, if it asks by
+/// `PTRACE_SYSCALL`.
+pub async fn ptrace_syscall_stop(task: &Arc<Task>) {
+    if task.with_ptrace(|ptrace| ptrace.as_ref().is_some_and(|p| p.trace_syscall)) {
+        // Signals can not be injected at syscall stops.
+        ptrace_stop(task, Sig::SIGTRAP).await;
+    }
+}
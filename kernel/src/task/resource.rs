@@ -1,6 +1,9 @@
-use core::time::Duration;
+use alloc::{format, string::String, sync::Arc};
+use core::{cmp, time::Duration};
 
-use super::Task;
+use systype::Rusage;
+
+use super::{task::TaskState, Task};
 
 impl Task {
     pub fn get_process_ustime(&self) -> (Duration, Duration) {
@@ -23,6 +26,119 @@ impl Task {
         })
     }
 
+    /// Resource usage of the process, which is reported to its parent by
+    /// wait4(2) and waitid(2).
+    pub fn get_process_rusage(&self) -> Rusage {
+        let (utime, stime) = self.get_process_ustime();
+        let (nvcsw, nivcsw) = self.get_process_context_switches();
+        let stat = self.with_memory_space(|m| m.stat());
+        Rusage {
+            utime: utime.into(),
+            stime: stime.into(),
+            maxrss: stat.max_rss_kb(),
+            minflt: stat.minflt,
+            majflt: stat.majflt,
+            nvcsw,
+            nivcsw,
+            ..Default::default()
+        }
+    }
+
+    /// Resource usage of all children of the process that have terminated and
+    /// been waited for, including their waited-for descendants.
+    pub fn get_children_rusage(&self) -> Rusage {
+        let (cutime, cstime) = self.with_thread_group(|tg| -> (Duration, Duration) {
+            tg.iter()
+                .map(|thread| thread.time_stat().child_user_system_time())
+                .reduce(|(acc_utime, acc_stime), (utime, stime)| {
+                    (acc_utime + utime, acc_stime + stime)
+                })
+                .unwrap()
+        });
+        let usage = self.with_children_usage(|usage| *usage);
+        Rusage {
+            utime: cutime.into(),
+            stime: cstime.into(),
+            maxrss: usage.maxrss,
+            minflt: usage.minflt,
+            majflt: usage.majflt,
+            nvcsw: usage.nvcsw,
+            nivcsw: usage.nivcsw,
+            ..Default::default()
+        }
+    }
+
+    /// Status information of the process in the format of /proc/[pid]/stat,
+    /// see proc(5). Fields that are not supported are filled with zero.
+    pub fn proc_stat(self: &Arc<Self>) -> String {
+        /// Clock ticks per second, i.e. sysconf(_SC_CLK_TCK)
+        const CLK_TCK: u128 = 100;
+        let ticks = |d: Duration| d.as_millis() * CLK_TCK / 1000;
+
+        let leader = self.leader();
+        let state = match leader.state() {
+            TaskState::Running => 'R',
+            TaskState::Interruptable => 'S',
+            TaskState::UnInterruptable => 'D',
+            TaskState::Stopped => 'T',
+            TaskState::Traced => 't',
+            TaskState::Zombie | TaskState::Terminated => 'Z',
+        };
+        let ppid = self
+            .parent()
+            .and_then(|p| p.upgrade())
+            .map_or(0, |p| p.pid());
+        let usage = self.get_process_rusage();
+        let children = self.get_children_rusage();
+        let (utime, stime) = self.get_process_ustime();
+        let (cutime, cstime): (Duration, Duration) = (children.utime.into(), children.stime.into());
+        let num_threads = self.with_thread_group(|tg| tg.len());
+        let (vsize, rss) = self.with_memory_space(|m| {
+            let vsize: usize = m
+                .areas()
+                .iter()
+                .map(|(range, _)| range.end.bits() - range.start.bits())
+                .sum();
+            (vsize, m.resident_pages())
+        });
+        let mut stat = format!(
+            "{} ({}) {} {} {} 0 0 0 0 {} {} {} {} {} {} {} {} 20 0 {} 0 0 {} {}",
+            self.pid(),
+            self.elf_ref().dentry().name(),
+            state,
+            ppid,
+            self.pgid(),
+            usage.minflt,
+            children.minflt,
+            usage.majflt,
+            children.majflt,
+            ticks(utime),
+            ticks(stime),
+            ticks(cutime),
+            ticks(cstime),
+            num_threads,
+            vsize,
+            rss,
+        );
+        // The remaining fields from rsslim to exit_code (the 25th to the 52nd)
+        for _ in 25..=52 {
+            stat.push_str(" 0");
+        }
+        stat.push('\n');
+        stat
+    }
+
+    pub fn get_process_context_switches(&self) -> (usize, usize) {
+        self.with_thread_group(|tg| -> (usize, usize) {
+            tg.iter()
+                .map(|thread| thread.time_stat().context_switches())
+                .reduce(|(acc_nvcsw, acc_nivcsw), (nvcsw, nivcsw)| {
+                    (acc_nvcsw + nvcsw, acc_nivcsw + nivcsw)
+                })
+                .unwrap()
+        })
+    }
+
     pub fn get_process_cputime(&self) -> Duration {
         self.with_thread_group(|tg| -> Duration {
             tg.iter()
@@ -33,6 +149,29 @@ impl Task {
     }
 }
 
+/// Resource usage accumulated from waited-for children, reported by
+/// getrusage(2) with RUSAGE_CHILDREN.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChildrenUsage {
+    /// Resident set size of the largest child in kilobytes.
+    pub maxrss: usize,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+impl ChildrenUsage {
+    /// Add the usage of a reaped child and those of its own children.
+    pub fn accumulate(&mut self, child: &Rusage, grandchildren: &ChildrenUsage) {
+        self.maxrss = cmp::max(self.maxrss, cmp::max(child.maxrss, grandchildren.maxrss));
+        self.minflt += child.minflt + grandchildren.minflt;
+        self.majflt += child.majflt + grandchildren.majflt;
+        self.nvcsw += child.nvcsw + grandchildren.nvcsw;
+        self.nivcsw += child.nivcsw + grandchildren.nivcsw;
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy)]
     #[repr(C)]
@@ -7,13 +7,14 @@ use core::{
 };
 
 use arch::time::get_time_duration;
-use async_utils::{get_waker, suspend_now};
-use timer::{Timer, TIMER_MANAGER};
+use async_utils::{get_waker, suspend_now, yield_now, Select2Futures, SelectOutput};
+use systype::{SysError, SysResult};
+use timer::timeout::{timeout_at, TimedOut};
 
-use super::Task;
+use super::{coredump::do_coredump, JoinHandle, Task};
 use crate::{
     processor::{env::EnvContext, hart},
-    task::{signal::*, task::TaskState::*},
+    task::signal::*,
     trap,
 };
 
@@ -49,12 +50,12 @@ impl<F: Future + Send + 'static> Future for UserTaskFuture<F> {
     }
 }
 
-pub struct KernelTaskFuture<F: Future<Output = ()> + Send + 'static> {
+pub struct KernelTaskFuture<F: Future + Send + 'static> {
     env: EnvContext,
     future: F,
 }
 
-impl<F: Future<Output = ()> + Send + 'static> KernelTaskFuture<F> {
+impl<F: Future + Send + 'static> KernelTaskFuture<F> {
     pub fn new(future: F) -> Self {
         Self {
             env: EnvContext::new(),
@@ -63,7 +64,7 @@ impl<F: Future<Output = ()> + Send + 'static> KernelTaskFuture<F> {
     }
 }
 
-impl<F: Future<Output = ()> + Send + 'static> Future for KernelTaskFuture<F> {
+impl<F: Future + Send + 'static> Future for KernelTaskFuture<F> {
     type Output = F::Output;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -79,51 +80,86 @@ impl<F: Future<Output = ()> + Send + 'static> Future for KernelTaskFuture<F> {
 pub async fn task_loop(task: Arc<Task>) {
     *task.waker() = Some(get_waker().await);
     loop {
-        match task.state() {
-            Terminated => break,
-            Stopped => suspend_now().await,
-            _ => {}
+        if wait_if_stopped(&task).await {
+            break;
         }
 
+        preempt_if_needed(&task).await;
+
         trap::user_trap::trap_return(&task);
 
         // task may be set to terminated by other task, e.g. execve will kill other
         // tasks in the same thread group
-        match task.state() {
-            Terminated => break,
-            Stopped => suspend_now().await,
-            _ => {}
+        if wait_if_stopped(&task).await {
+            break;
         }
 
         let intr = trap::user_trap::trap_handler(&task).await;
 
-        match task.state() {
-            Terminated => break,
-            Stopped => suspend_now().await,
-            _ => {}
+        if wait_if_stopped(&task).await {
+            break;
+        }
+        // The core is dumped before the process exits, so that it is complete
+        // once the parent is notified.
+        if let Some(sig) = do_signal(&task, intr).await.expect("do signal error") {
+            do_coredump(&task, sig).await;
         }
-        do_signal(&task, intr).expect("do signal error");
     }
 
     log::debug!("thread {} terminated", task.tid());
     task.do_exit();
 }
 
+/// Yield the task before returning to user mode if its time slice is used up,
+/// so that a task that never blocks can not monopolize the hart.
+async fn preempt_if_needed(task: &Arc<Task>) {
+    if hart::local_hart().take_need_resched() && executor::has_task() {
+        log::info!("time slice used up, yield now");
+        task.time_stat().record_preempt();
+        yield_now().await;
+    }
+}
+
+/// Park the task while it is stopped, until it is continued by SIGCONT or
+/// terminated by SIGKILL. Return true if the task has been terminated.
+async fn wait_if_stopped(task: &Arc<Task>) -> bool {
+    while task.is_stopped() {
+        suspend_now().await;
+    }
+    task.is_terminated()
+}
+
 /// Spawn a new async user task
 pub fn spawn_user_task(user_task: Arc<Task>) {
+    let attr = user_task.sched_attr().clone();
     let future = UserTaskFuture::new(user_task.clone(), task_loop(user_task));
-    let (runnable, task) = executor::spawn(future);
+    let (runnable, task) = executor::spawn_with_attr(future, attr);
+    runnable.schedule();
+    task.detach();
+}
+
+/// Spawn a task only to exit `task`, whose own future has been abandoned by
+/// recovering from a panic in its syscall.
+#[cfg(feature = "recover-user")]
+pub fn spawn_exit_task(task: Arc<Task>) {
+    let attr = task.sched_attr().clone();
+    let future = UserTaskFuture::new(task.clone(), async move { task.do_exit() });
+    let (runnable, task) = executor::spawn_with_attr(future, attr);
     runnable.schedule();
     task.detach();
 }
 
 /// Spawn a new async kernel task (used for doing some kernel init work or timed
-/// tasks)
-pub fn spawn_kernel_task<F: Future<Output = ()> + Send + 'static>(kernel_task: F) {
+/// tasks). The task keeps running if the handle returned is dropped.
+pub fn spawn_kernel_task<F>(kernel_task: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
     let future = KernelTaskFuture::new(kernel_task);
     let (runnable, task) = executor::spawn(future);
     runnable.schedule();
-    task.detach();
+    JoinHandle::new(task)
 }
 
 impl Task {
@@ -131,11 +167,7 @@ impl Task {
     /// 0，说明就是超时了，大于 0 才是因事件唤醒
     pub async fn suspend_timeout(&self, limit: Duration) -> Duration {
         let expire = get_time_duration() + limit;
-        TIMER_MANAGER.add_timer(Timer::new_waker_timer(
-            expire,
-            self.waker().clone().unwrap(),
-        ));
-        suspend_now().await;
+        let _ = timeout_at(expire, suspend_now()).await;
         let now = get_time_duration();
         if expire > now {
             expire - now
@@ -143,4 +175,36 @@ impl Task {
             Duration::ZERO
         }
     }
+
+    /// Wait until `future` is ready, `deadline` is reached, or the task is
+    /// interrupted by signals that are not blocked. Only one timer is armed for
+    /// the deadline, so the task sleeps until something happens. Return `None`
+    /// on timeout.
+    pub async fn wait_interruptible<F: Future>(
+        self: &Arc<Self>,
+        future: F,
+        deadline: Option<Duration>,
+    ) -> SysResult<Option<F::Output>> {
+        self.set_interruptable();
+        self.set_wake_up_signal(!*self.sig_mask_ref());
+        let intr_future = IntrBySignalFuture {
+            task: self.clone(),
+            mask: *self.sig_mask_ref(),
+        };
+        let ret = match deadline {
+            Some(deadline) => {
+                match Select2Futures::new(timeout_at(deadline, future), intr_future).await {
+                    SelectOutput::Output1(Ok(ret)) => Ok(Some(ret)),
+                    SelectOutput::Output1(Err(TimedOut)) => Ok(None),
+                    SelectOutput::Output2(_) => Err(SysError::EINTR),
+                }
+            }
+            None => match Select2Futures::new(future, intr_future).await {
+                SelectOutput::Output1(ret) => Ok(Some(ret)),
+                SelectOutput::Output2(_) => Err(SysError::EINTR),
+            },
+        };
+        self.set_running();
+        ret
+    }
 }
@@ -0,0 +1,86 @@
+//! Syscall filtering in the manner of seccomp(2), which confines a thread to a
+//! small set of syscalls, e.g. a program under fuzzing.
+//!
+//! A filter is inherited across clone(2) and kept across execve(2), and it can
+//! only be made stricter once it is set.
+
+use alloc::sync::Arc;
+
+use systype::{SysError, SysResult};
+
+use crate::syscall::SyscallNo;
+
+/// Syscall numbers an allowlist can permit, which covers all the syscalls.
+const SECCOMP_ALLOWLIST_BITS: usize = 512;
+
+const ALLOWLIST_WORDS: usize = SECCOMP_ALLOWLIST_BITS / 64;
+
+// modes of PR_GET_SECCOMP and PR_SET_SECCOMP
+pub const SECCOMP_MODE_DISABLED: usize = 0;
+pub const SECCOMP_MODE_STRICT: usize = 1;
+/// BPF filters are not supported.
+pub const SECCOMP_MODE_FILTER: usize = 2;
+/// Phoenix specific mode, where only syscalls in a bitmap are permitted.
+pub const SECCOMP_MODE_ALLOWLIST: usize = 3;
+
+/// Bitmap of permitted syscall numbers.
+pub type Allowlist = [u64; ALLOWLIST_WORDS];
+
+#[derive(Clone, Default)]
+pub enum Seccomp {
+    #[default]
+    Disabled,
+    /// Only read(2), write(2), _exit(2) and sigreturn(2) are permitted.
+    Strict,
+    /// Only syscalls in the bitmap are permitted.
+    Allowlist(Arc<Allowlist>),
+}
+
+impl Seccomp {
+    pub fn mode(&self) -> usize {
+        match self {
+            Seccomp::Disabled => SECCOMP_MODE_DISABLED,
+            Seccomp::Strict => SECCOMP_MODE_STRICT,
+            Seccomp::Allowlist(_) => SECCOMP_MODE_ALLOWLIST,
+        }
+    }
+
+    /// Whether the syscall of `syscall_no` is permitted. The thread is killed
+    /// if it makes a syscall which is not.
+    pub fn permits(&self, syscall_no: usize) -> bool {
+        match self {
+            Seccomp::Disabled => true,
+            Seccomp::Strict => [
+                SyscallNo::READ,
+                SyscallNo::WRITE,
+                SyscallNo::EXIT,
+                SyscallNo::RT_SIGRETURN,
+            ]
+            .into_iter()
+            .any(|no| no as usize == syscall_no),
+            Seccomp::Allowlist(bits) => {
+                syscall_no < SECCOMP_ALLOWLIST_BITS
+                    && bits[syscall_no / 64] & (1 << (syscall_no % 64)) != 0
+            }
+        }
+    }
+
+    /// Set the filter to `new`, which is combined with the current one so that
+    /// nothing denied now can be permitted. Fails with `EINVAL` if the filter
+    /// is to be disabled.
+    pub fn restrict(&mut self, new: Seccomp) -> SysResult<()> {
+        *self = match (&*self, new) {
+            (_, Seccomp::Disabled) => return Err(SysError::EINVAL),
+            (Seccomp::Strict, _) | (_, Seccomp::Strict) => Seccomp::Strict,
+            (Seccomp::Disabled, new) => new,
+            (Seccomp::Allowlist(old), Seccomp::Allowlist(new)) => {
+                let mut bits = *new;
+                for (bit, old) in bits.iter_mut().zip(old.iter()) {
+                    *bit &= old;
+                }
+                Seccomp::Allowlist(Arc::new(bits))
+            }
+        };
+        Ok(())
+    }
+}
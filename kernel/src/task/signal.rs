@@ -14,10 +14,11 @@ use core::{
 use arch::time::get_time_duration;
 use signal::*;
 use systype::SysResult;
+use time::{ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL};
 use timer::{Timer, TimerEvent};
 
-use super::Task;
-use crate::mm::UserWritePtr;
+use super::{ptrace::ptrace_stop, Task, Tid};
+use crate::{mm::UserWritePtr, syscall::SyscallNo};
 
 #[derive(Clone, Copy, Default)]
 #[repr(C)]
@@ -42,7 +43,7 @@ impl From<Action> for SigAction {
     fn from(action: Action) -> Self {
         let sa_handler = match action.atype {
             ActionType::Ignore => SIG_IGN,
-            ActionType::Kill | ActionType::Stop | ActionType::Cont => SIG_DFL,
+            ActionType::Kill | ActionType::Core | ActionType::Stop | ActionType::Cont => SIG_DFL,
             ActionType::User { entry } => entry.into(),
         };
         Self {
@@ -62,6 +63,7 @@ impl Task {
     /// A thread-directed signal is targeted at
     /// (i.e., delivered to) a specific thread.
     pub fn receive_siginfo(&self, si: SigInfo, thread_directed: bool) {
+        self.prepare_signal(si.sig);
         match thread_directed {
             false => {
                 debug_assert!(self.is_leader());
@@ -87,6 +89,65 @@ impl Task {
             true => self.recv(si),
         }
     }
+    /// Some signals take effect on the whole process when they are generated
+    /// rather than delivered, since a stopped process will not handle any
+    /// signal until it is continued.
+    fn prepare_signal(&self, sig: Sig) {
+        if sig == Sig::SIGCONT {
+            // When SIGCONT is generated, the process is continued even if SIGCONT is
+            // blocked or ignored, and all pending stop signals are discarded.
+            let stopped = self.with_thread_group(|tg| {
+                let mut stopped = None;
+                for t in tg.iter() {
+                    t.with_mut_sig_pending(|pending| pending.remove_expect(SigSet::STOP_MASK));
+                    if t.is_stopped() {
+                        // NOTE: set running before waking up, otherwise the task may park
+                        // itself again
+                        t.set_running();
+                        t.waker_ref().as_ref().unwrap().wake_by_ref();
+                        stopped = Some(t);
+                    }
+                }
+                stopped
+            });
+            if let Some(task) = stopped {
+                log::warn!("[prepare_signal] process {} continued", task.pid());
+                task.leader()
+                    .with_mut_stop_event(|event| *event = Some(StopEvent::Continued));
+                task.notify_parent(SigInfo::CLD_CONTINUED, sig);
+            }
+        } else if sig.is_stop() {
+            // When a stop signal is generated, any pending SIGCONT is discarded.
+            self.with_thread_group(|tg| {
+                for t in tg.iter() {
+                    t.with_mut_sig_pending(|pending| pending.remove_expect(SigSet::SIGCONT));
+                }
+            });
+        } else if sig == Sig::SIGKILL {
+            // SIGKILL must terminate a stopped process, which will never handle it.
+            // So must it terminate a thread stopped for its tracer.
+            let stopped_leader = self.with_thread_group(|tg| {
+                if !tg.iter().any(|t| t.is_stopped() || t.is_traced()) {
+                    return None;
+                }
+                for t in tg.iter() {
+                    let was_stopped = t.is_stopped() || t.is_traced();
+                    if !t.is_zombie() {
+                        t.set_terminated();
+                    }
+                    if was_stopped {
+                        t.waker_ref().as_ref().unwrap().wake_by_ref();
+                    }
+                }
+                tg.iter().find(|t| t.is_leader())
+            });
+            if let Some(leader) = stopped_leader {
+                log::warn!("[prepare_signal] stopped process {} killed", leader.pid());
+                leader.set_exit_code(sig.raw() as i32 & 0x7F);
+            }
+        }
+    }
+
     fn recv(&self, si: SigInfo) {
         log::info!(
             "[Task::recv] tid {} recv {si:?} {:?}",
@@ -142,25 +203,64 @@ extern "C" {
 /// Signal dispositions and actions are process-wide: if an unhandled signal is
 /// delivered to a thread, then it will affect (terminate, stop, continue, be
 /// ignored in) all members of the thread group.
-pub fn do_signal(task: &Arc<Task>, mut intr: bool) -> SysResult<()> {
+///
+/// A traced thread stops for its tracer before a signal other than SIGKILL is
+/// delivered, and the signal the tracer resumes it with is delivered instead.
+///
+/// Returns the signal to dump core for if the process is terminated by one,
+/// see `coredump::do_coredump`.
+pub async fn do_signal(task: &Arc<Task>, mut intr: bool) -> SysResult<Option<Sig>> {
     let old_mask = *task.sig_mask();
     let cx = task.trap_context_mut();
+    let mut core_sig = None;
+    let mut ignored = false;
 
-    while let Some(si) = task.with_mut_sig_pending(|pending| pending.dequeue_signal(&old_mask)) {
+    while let Some(mut si) = task.with_mut_sig_pending(|pending| pending.dequeue_signal(&old_mask))
+    {
+        if si.sig != Sig::SIGKILL && task.is_ptraced() {
+            let resume_sig = ptrace_stop(task, si.sig).await;
+            if task.is_terminated() {
+                break;
+            }
+            match resume_sig {
+                // The signal is suppressed by the tracer.
+                None => continue,
+                Some(sig) if sig != si.sig => {
+                    si = SigInfo {
+                        sig,
+                        code: SigInfo::USER,
+                        details: SigDetails::None,
+                    }
+                }
+                Some(_) => {}
+            }
+        }
         let action = task.with_sig_handlers(|handlers| handlers.get(si.sig));
         log::info!("[do signal] Handling signal: {:?} {:?}", si, action);
-        if intr && action.flags.contains(SigActionFlag::SA_RESTART) {
-            cx.sepc -= 4;
-            cx.restore_last_user_a0();
+        // A syscall interrupted by a signal will be restarted only if the signal
+        // is caught by a user handler installed with SA_RESTART, and the syscall
+        // is restartable. Otherwise, EINTR is left in a0 and seen by user.
+        if intr
+            && matches!(action.atype, ActionType::User { .. })
+            && action.flags.contains(SigActionFlag::SA_RESTART)
+            && SyscallNo::from_repr(cx.syscall_no()).is_some_and(|no| no.is_restartable())
+        {
+            cx.restart_syscall();
             log::info!("[do_signal] restart syscall");
             intr = false;
         }
         match action.atype {
-            ActionType::Ignore => {}
+            ActionType::Ignore => ignored = true,
             ActionType::Kill => terminate(task, si.sig),
+            ActionType::Core => {
+                terminate(task, si.sig);
+                core_sig = Some(si.sig);
+            }
             ActionType::Stop => stop(task, si.sig),
-            ActionType::Cont => cont(task, si.sig),
+            // The process has been continued when SIGCONT was generated.
+            ActionType::Cont => ignored = true,
             ActionType::User { entry } => {
+                ignored = false;
                 // The signal being delivered is also added to the signal mask, unless
                 // SA_NODEFER was specified when registering the handler.
                 if !action.flags.contains(SigActionFlag::SA_NODEFER) {
@@ -248,7 +348,14 @@ pub fn do_signal(task: &Arc<Task>, mut intr: bool) -> SysResult<()> {
             }
         }
     }
-    Ok(())
+    // Linux never interrupts a syscall for a signal that is ignored, so if only
+    // ignored signals arrived, the syscall is executed again as if nothing
+    // happened instead of failing with EINTR.
+    if intr && ignored && !task.is_terminated() {
+        cx.restart_syscall();
+        log::info!("[do_signal] restart syscall interrupted by ignored signals");
+    }
+    Ok(core_sig)
 }
 
 /// terminate the process
@@ -260,30 +367,55 @@ fn terminate(task: &Arc<Task>, sig: Sig) {
         }
     });
     // 将信号放入低7位 (第8位是core dump标志,在gdb调试崩溃程序中用到)
-    task.set_exit_code(sig.raw() as i32 & 0x7F);
+    task.leader().set_exit_code(sig.raw() as i32 & 0x7F);
 }
 
+/// stop all the threads of the process until SIGCONT or SIGKILL arrives, see
+/// `prepare_signal`
 fn stop(task: &Arc<Task>, sig: Sig) {
     log::warn!("[do_signal] task stopped!");
-    task.with_mut_thread_group(|tg| {
+    task.with_thread_group(|tg| {
         for t in tg.iter() {
-            t.set_stopped();
-            t.set_wake_up_signal(SigSet::SIGCONT);
+            // A thread stopped for its tracer stays so until the tracer resumes it.
+            if !t.is_terminated() && !t.is_zombie() && !t.is_traced() {
+                t.set_stopped();
+            }
         }
     });
+    task.leader()
+        .with_mut_stop_event(|event| *event = Some(StopEvent::Stopped(sig)));
     task.notify_parent(SigInfo::CLD_STOPPED, sig);
 }
 
-/// continue the process if it is currently stopped
-fn cont(task: &Arc<Task>, sig: Sig) {
-    log::warn!("[do_signal] task continue");
-    task.with_mut_thread_group(|tg| {
-        for t in tg.iter() {
-            t.set_running();
-            t.wake();
+/// Stop or continue state change of a process which has not been reported to
+/// its parent by `wait4` yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopEvent {
+    Stopped(Sig),
+    Continued,
+    /// A ptrace stop of a tracee for the signal, which is reported to its
+    /// tracer, see `ptrace::ptrace_stop`.
+    Traced(Sig),
+}
+
+impl StopEvent {
+    /// wstatus encoding in <bits/waitstatus.h>: 0x7f in the lowest 8 bits with
+    /// the stop signal in higher 8 bits for stopped, and 0xffff for continued.
+    pub fn wstatus(&self) -> i32 {
+        match self {
+            StopEvent::Stopped(sig) | StopEvent::Traced(sig) => ((sig.raw() as i32) << 8) | 0x7F,
+            StopEvent::Continued => 0xFFFF,
         }
-    });
-    task.notify_parent(SigInfo::CLD_CONTINUED, sig);
+    }
+
+    /// The id reported by `wait4` for the event of `task`, which is the tid for
+    /// a ptrace stop since tracees are threads.
+    pub fn reported_id(&self, task: &Task) -> Tid {
+        match self {
+            StopEvent::Traced(_) => task.tid(),
+            _ => task.pid(),
+        }
+    }
 }
 
 static TIMER_ID_ALLOCATOR: AtomicUsize = AtomicUsize::new(1);
@@ -305,6 +437,59 @@ impl ITimer {
         next_expire: Duration::ZERO,
         id: 0,
     };
+
+    pub fn is_armed(&self) -> bool {
+        !self.next_expire.is_zero()
+    }
+
+    /// Check a cpu time based itimer with `now` measured in the same clock as
+    /// `next_expire`. Return true if the timer expires, in which case it will
+    /// be rearmed from `interval` or disarmed.
+    pub fn check_expire(&mut self, now: Duration) -> bool {
+        if !self.is_armed() || now < self.next_expire {
+            return false;
+        }
+        self.next_expire = if self.interval.is_zero() {
+            Duration::ZERO
+        } else {
+            now + self.interval
+        };
+        true
+    }
+}
+
+impl Task {
+    /// `ITIMER_VIRTUAL` and `ITIMER_PROF` count down against the user cpu time
+    /// and the user plus system cpu time of the whole process respectively.
+    /// Their `next_expire` are kept in the corresponding cpu time clock, so we
+    /// only need to compare them with the current cpu time here.
+    pub fn update_itimers(self: &Arc<Self>) {
+        let armed = self.with_itimers(|itimers| {
+            itimers[ITIMER_VIRTUAL].is_armed() || itimers[ITIMER_PROF].is_armed()
+        });
+        if !armed {
+            return;
+        }
+        let (utime, stime) = self.get_process_ustime();
+        let expired = self.with_mut_itimers(|itimers| {
+            [
+                (ITIMER_VIRTUAL, utime, Sig::SIGVTALRM),
+                (ITIMER_PROF, utime + stime, Sig::SIGPROF),
+            ]
+            .map(|(which, now, sig)| itimers[which].check_expire(now).then_some(sig))
+        });
+        for sig in expired.into_iter().flatten() {
+            log::info!("[update_itimers] itimer expired, send {sig:?}");
+            self.leader().receive_siginfo(
+                SigInfo {
+                    sig,
+                    code: SigInfo::KERNEL,
+                    details: SigDetails::None,
+                },
+                false,
+            );
+        }
+    }
 }
 
 #[derive(Default, Debug)]
@@ -318,7 +503,7 @@ impl TimerEvent for RealITimer {
     fn callback(self: Box<Self>) -> Option<Timer> {
         self.task.upgrade().and_then(|task| {
             task.with_mut_itimers(|itimers| {
-                let real = &mut itimers[0];
+                let real = &mut itimers[ITIMER_REAL];
 
                 if real.id != self.id {
                     // incarnation check fails
@@ -339,10 +524,7 @@ impl TimerEvent for RealITimer {
                 }
 
                 real.next_expire = get_time_duration() + real.interval;
-                Some(Timer {
-                    expire: real.next_expire,
-                    data: self,
-                })
+                Some(Timer::new(real.next_expire, self))
             })
         })
     }
@@ -9,16 +9,16 @@ use alloc::{
 use core::{
     cell::SyncUnsafeCell,
     ops::DerefMut,
-    sync::atomic::{AtomicI32, AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering},
     task::Waker,
 };
 
-use arch::memory::sfence_vma_all;
 use async_utils::block_on;
 use config::{
     mm::DL_INTERP_OFFSET,
     process::{INIT_PROC_PID, USER_STACK_SIZE},
 };
+use executor::SchedAttr;
 use memory::VirtAddr;
 use signal::{
     action::{SigHandlers, SigPending},
@@ -26,31 +26,44 @@ use signal::{
     signal_stack::SignalStack,
     sigset::{Sig, SigSet},
 };
-use sync::mutex::SpinNoIrqLock;
+use sync::{mutex::SpinNoIrqLock, WaitQueue};
 use systype::{SysError, SysResult};
 use time::stat::TaskTimeStat;
 use vfs::{fd_table::FdTable, sys_root_dentry};
 use vfs_core::{
-    is_absolute_path, split_path, AtFd, Dentry, File, InodeMode, InodeType, OpenFlags, Path,
+    is_absolute_path, split_path, AccessMode, AtFd, Dentry, File, InodeMode, InodeType, OpenFlags,
+    Path,
 };
 
 use super::{
-    resource::CpuMask,
-    signal::ITimer,
+    cred::Credentials,
+    ptrace::Ptrace,
+    resource::ChildrenUsage,
+    seccomp::Seccomp,
+    signal::{ITimer, StopEvent},
     tid::{Pid, Tid, TidHandle},
-    PGid, PROCESS_GROUP_MANAGER,
+    PGid, PROCESS_GROUP_MANAGER, SESSION_MANAGER,
 };
 use crate::{
-    generate_accessors, generate_atomic_accessors, generate_state_methods, generate_with_methods,
+    generate_accessors, generate_atomic_accessors, generate_shared_with_methods,
+    generate_state_methods, generate_with_methods,
     ipc::{
-        futex::{futex_manager, FutexHashKey, RobustListHead},
+        futex::{
+            exit_pi_futexes, futex_manager, FutexHashKey, RobustListHead, FUTEX_BITSET_MATCH_ANY,
+        },
+        sem::SEM_MANAGER,
         shm::SHARED_MEMORY_MANAGER,
     },
-    mm::{memory_space::init_stack, MemorySpace, UserWritePtr},
+    mm::{
+        memory_space::{init_stack, parse_elf},
+        swap::{self, SWAP_CLUSTER},
+        tlb, MemorySpace, PageFaultAccessType, UserWritePtr,
+    },
     processor::env::within_sum,
     syscall::CloneFlags,
     task::{
-        aux::{AuxHeader, AT_BASE},
+        aux::{push_cred_auxv, AuxHeader, AT_BASE},
+        exec_args::ExecArgs,
         manager::TASK_MANAGER,
         tid::{alloc_tid, TidAddress},
     },
@@ -58,6 +71,12 @@ use crate::{
 };
 
 type Shared<T> = Arc<SpinNoIrqLock<T>>;
+/// A `Shared` that may be replaced by another one while other tasks use it,
+/// e.g. on execve(2) or unshare(2).
+type Replaceable<T> = SpinNoIrqLock<Shared<T>>;
+
+/// Default file mode creation mask, i.e. 022.
+const DEFAULT_UMASK: InodeMode = InodeMode::GROUP_WRITE.union(InodeMode::OTHER_WRITE);
 
 fn new_shared<T>(data: T) -> Shared<T> {
     Arc::new(SpinNoIrqLock::new(data))
@@ -80,8 +99,9 @@ pub struct Task {
     /// Indicates if the task is a zombie. Protected by a spin lock due to
     /// potential access by other tasks.
     state: SpinNoIrqLock<TaskState>,
-    /// The address space of the process.
-    memory_space: Shared<MemorySpace>,
+    /// The address space of the process. It may be shared with another process
+    /// by `CLONE_VM`, e.g. a vfork child, which gets its own one on execve.
+    memory_space: Replaceable<MemorySpace>,
     /// Map of start address of shared memory areas to their keys in the shared
     /// memory manager.
     shm_ids: Shared<BTreeMap<VirtAddr, usize>>,
@@ -92,18 +112,26 @@ pub struct Task {
     // will be automatically dropped by previous two structs. However, it should be treated with
     // great care to drop task in `children`.
     children: Shared<BTreeMap<Tid, Arc<Task>>>,
+    /// Tracees of the process, which are traced by ptrace(2) and reported to it
+    /// by `wait4` when they stop, whether they are its children or not.
+    tracees: Shared<BTreeMap<Tid, Weak<Task>>>,
+    /// Tracing state of the task if it is traced by ptrace(2).
+    ptrace: SpinNoIrqLock<Option<Ptrace>>,
     /// Exit code of the current process.
     exit_code: AtomicI32,
+    /// Stop or continue event of the process to be reported by `wait4`. Only
+    /// meaningful for the leader.
+    stop_event: SpinNoIrqLock<Option<StopEvent>>,
     /// Trap context for the task.
     trap_context: SyncUnsafeCell<TrapContext>,
     /// Waker to add the task back to the scheduler.
     waker: SyncUnsafeCell<Option<Waker>>,
     /// Thread group containing this task.
     thread_group: Shared<ThreadGroup>,
-    /// File descriptor table.
-    fd_table: Shared<FdTable>,
-    /// Current working directory dentry.
-    cwd: Shared<Arc<dyn Dentry>>,
+    /// File descriptor table, shared by `CLONE_FILES`.
+    fd_table: Replaceable<FdTable>,
+    /// Root, current working directory and umask, shared by `CLONE_FS`.
+    fs: Replaceable<FsContext>,
     /// Pending signals for the task.
     sig_pending: SpinNoIrqLock<SigPending>,
     /// Signal handlers.
@@ -114,18 +142,35 @@ pub struct Task {
     sig_stack: SyncUnsafeCell<Option<SignalStack>>,
     /// Pointer to the user context for signal handling.
     sig_ucontext_ptr: AtomicUsize,
+    /// Scheduling attributes shared with the executor, e.g. the boost for
+    /// owning contended priority-inheritance futexes and the cpu affinity.
+    sched_attr: Arc<SchedAttr>,
     /// Statistics for task execution times.
     time_stat: SyncUnsafeCell<TaskTimeStat>,
+    /// Syscalls made most recently, printed on panic.
+    syscall_history: SyncUnsafeCell<SyscallHistory>,
+    /// Syscall filter of the thread, which can only be changed by itself.
+    seccomp: SyncUnsafeCell<Seccomp>,
     /// Interval timers for the task.
     itimers: Shared<[ITimer; 3]>,
+    /// Resource usage of waited-for children of the process.
+    children_usage: Shared<ChildrenUsage>,
     /// Futexes used by the task.
     robust: Shared<RobustListHead>,
     /// Address of the task's thread ID.
     tid_address: SyncUnsafeCell<TidAddress>,
-    /// Mask of CPUs allowed for the task.
-    cpus_allowed: SyncUnsafeCell<CpuMask>,
+    /// Completion on which the parent waits after `CLONE_VFORK`, until the task
+    /// execs or exits.
+    vfork_done: SpinNoIrqLock<Option<Arc<VforkDone>>>,
     /// Process group ID of the task.
     pgid: Shared<PGid>,
+    /// Session ID of the process, i.e. the pid of the session leader.
+    sid: Shared<Pid>,
+    /// Set once the process executes a program, after which its parent can
+    /// not change its process group.
+    execed: AtomicBool,
+    /// User and group IDs of the process.
+    cred: Shared<Credentials>,
     /// ELF file the task executes.
     elf: SyncUnsafeCell<Arc<dyn File>>,
     /// Command-line arguments for the task.
@@ -160,6 +205,9 @@ pub enum TaskState {
     /// The task has been stopped, usually due to receiving a stop signal (e.g.,
     /// SIGSTOP). It can be resumed with a continue signal (e.g., SIGCONT).
     Stopped,
+    /// The task has been stopped for its tracer, see ptrace(2). It can only be
+    /// resumed by the tracer, or killed by SIGKILL.
+    Traced,
     /// The task is waiting for an event, such as the completion of an I/O
     /// operation or the release of a resource. In this state, the task can
     /// be interrupted by signals. If a signal is sent to the task, it will be
@@ -181,6 +229,7 @@ impl Task {
         Running,
         Zombie,
         Stopped,
+        Traced,
         Terminated,
         Interruptable,
         UnInterruptable
@@ -191,22 +240,27 @@ impl Task {
         sig_mask: SigSet,
         sig_stack: Option<SignalStack>,
         time_stat: TaskTimeStat,
-        cpus_allowed: CpuMask,
+        syscall_history: SyscallHistory,
+        seccomp: Seccomp,
         elf: Arc<dyn File>,
         args: Vec<String>
     );
-    generate_atomic_accessors!(exit_code: i32, sig_ucontext_ptr: usize);
+    generate_atomic_accessors!(exit_code: i32, sig_ucontext_ptr: usize, execed: bool);
+    generate_shared_with_methods!(fd_table: FdTable, fs: FsContext);
     generate_with_methods!(
-        fd_table: FdTable,
         children: BTreeMap<Tid, Arc<Task>>,
-        memory_space: MemorySpace,
         thread_group: ThreadGroup,
         sig_pending: SigPending,
         robust: RobustListHead,
         sig_handlers: SigHandlers,
         state: TaskState,
+        stop_event: Option<StopEvent>,
+        tracees: BTreeMap<Tid, Weak<Task>>,
+        ptrace: Option<Ptrace>,
         shm_ids: BTreeMap<VirtAddr, usize>,
-        itimers: [ITimer;3]
+        itimers: [ITimer;3],
+        children_usage: ChildrenUsage,
+        cred: Credentials
     );
 
     pub fn new_init(
@@ -215,7 +269,7 @@ impl Task {
         elf_file: Arc<dyn File>,
         args: Vec<String>,
     ) -> Arc<Self> {
-        let tid = alloc_tid();
+        let tid = alloc_tid().expect("no tid for the init proc");
         let pgid = tid.0;
         let task = Arc::new(Self {
             tid,
@@ -224,25 +278,35 @@ impl Task {
             state: SpinNoIrqLock::new(TaskState::Running),
             parent: new_shared(None),
             children: new_shared(BTreeMap::new()),
+            tracees: new_shared(BTreeMap::new()),
+            ptrace: SpinNoIrqLock::new(None),
             exit_code: AtomicI32::new(0),
+            stop_event: SpinNoIrqLock::new(None),
             trap_context: SyncUnsafeCell::new(trap_context),
-            memory_space: new_shared(memory_space),
+            memory_space: SpinNoIrqLock::new(new_shared(memory_space)),
             waker: SyncUnsafeCell::new(None),
             thread_group: new_shared(ThreadGroup::new()),
-            fd_table: new_shared(FdTable::new()),
-            cwd: new_shared(sys_root_dentry()),
+            fd_table: SpinNoIrqLock::new(new_shared(FdTable::new())),
+            fs: SpinNoIrqLock::new(new_shared(FsContext::new())),
             sig_pending: SpinNoIrqLock::new(SigPending::new()),
             sig_mask: SyncUnsafeCell::new(SigSet::empty()),
             sig_handlers: new_shared(SigHandlers::new()),
             sig_stack: SyncUnsafeCell::new(None),
             time_stat: SyncUnsafeCell::new(TaskTimeStat::new()),
+            syscall_history: SyncUnsafeCell::new(SyscallHistory::new()),
+            seccomp: SyncUnsafeCell::new(Seccomp::Disabled),
             sig_ucontext_ptr: AtomicUsize::new(0),
+            sched_attr: Arc::new(SchedAttr::new()),
             itimers: new_shared([ITimer::ZERO; 3]),
+            children_usage: new_shared(ChildrenUsage::default()),
             robust: new_shared(RobustListHead::default()),
             tid_address: SyncUnsafeCell::new(TidAddress::new()),
-            cpus_allowed: SyncUnsafeCell::new(CpuMask::CPU_ALL),
+            vfork_done: SpinNoIrqLock::new(None),
             shm_ids: new_shared(BTreeMap::new()),
             pgid: new_shared(pgid),
+            sid: new_shared(pgid),
+            execed: AtomicBool::new(false),
+            cred: new_shared(Credentials::root()),
             elf: SyncUnsafeCell::new(elf_file),
             args: SyncUnsafeCell::new(args),
         });
@@ -312,6 +376,20 @@ impl Task {
         *self.pgid.lock() = pgid
     }
 
+    pub fn sid(&self) -> Pid {
+        *self.sid.lock()
+    }
+
+    pub fn set_sid(&self, sid: Pid) {
+        *self.sid.lock() = sid
+    }
+
+    /// Whether the task is the leader of its session, which is created by it
+    /// calling `setsid`.
+    pub fn is_session_leader(self: &Arc<Self>) -> bool {
+        self.sid() == self.pid()
+    }
+
     pub fn ppid(&self) -> Pid {
         self.parent()
             .expect("Call ppid without a parent")
@@ -331,24 +409,91 @@ impl Task {
         waker.as_ref().unwrap().wake_by_ref();
     }
 
+    pub fn root(&self) -> Arc<dyn Dentry> {
+        self.with_fs(|fs| fs.root.clone())
+    }
+
     pub fn cwd(&self) -> Arc<dyn Dentry> {
-        self.cwd.lock().clone()
+        self.with_fs(|fs| fs.cwd.clone())
     }
 
     pub fn set_cwd(&self, dentry: Arc<dyn Dentry>) {
-        *self.cwd.lock() = dentry;
+        self.with_mut_fs(|fs| fs.cwd = dentry);
+    }
+
+    pub fn umask(&self) -> InodeMode {
+        self.with_fs(|fs| fs.umask)
+    }
+
+    /// Set the umask and return the previous one.
+    pub fn set_umask(&self, umask: InodeMode) -> InodeMode {
+        self.with_mut_fs(|fs| core::mem::replace(&mut fs.umask, umask))
+    }
+
+    /// Stop sharing the fd table or the fs context selected by `flags` with
+    /// other tasks, by taking a copy of it.
+    pub fn unshare(&self, flags: CloneFlags) {
+        if flags.contains(CloneFlags::FILES) {
+            let fd_table = self.with_fd_table(|table| table.clone());
+            drop(self.replace_fd_table(new_shared(fd_table)));
+        }
+        if flags.contains(CloneFlags::FS) {
+            let fs = self.with_fs(|fs| fs.clone());
+            drop(self.replace_fs(new_shared(fs)));
+        }
+    }
+
+    pub fn memory_space(&self) -> Shared<MemorySpace> {
+        self.memory_space.lock().clone()
+    }
+
+    pub fn with_memory_space<T>(&self, f: impl FnOnce(&MemorySpace) -> T) -> T {
+        log::trace!("with_memory_space");
+        f(&self.memory_space().lock())
+    }
+
+    /// Like `with_memory_space`, but also waits for the TLB shootdowns issued
+    /// by `f`, which can only be done once the memory space is unlocked.
+    pub fn with_mut_memory_space<T>(&self, f: impl FnOnce(&mut MemorySpace) -> T) -> T {
+        log::trace!("with_mut_memory_space");
+        let ret = f(&mut self.memory_space().lock());
+        tlb::wait_for_shootdown();
+        ret
+    }
+
+    /// Handle a page fault at `va`. If it runs out of memory, pages are
+    /// swapped out to retry it, as long as there are pages to swap out.
+    pub fn handle_page_fault(
+        &self,
+        va: VirtAddr,
+        access_type: PageFaultAccessType,
+    ) -> SysResult<()> {
+        loop {
+            match self.with_mut_memory_space(|m| m.handle_page_fault(va, access_type)) {
+                Err(SysError::ENOMEM) if swap::swap_out(SWAP_CLUSTER) > 0 => {}
+                ret => return ret,
+            }
+        }
     }
 
     pub unsafe fn switch_page_table(&self) {
-        self.memory_space.lock().switch_page_table()
+        self.memory_space().lock().switch_page_table()
+    }
+
+    pub fn sched_attr(&self) -> &Arc<SchedAttr> {
+        &self.sched_attr
+    }
+
+    pub fn set_vfork_done(&self, done: Arc<VforkDone>) {
+        *self.vfork_done.lock() = Some(done);
     }
 
     pub fn raw_mm_pointer(&self) -> usize {
-        Arc::as_ptr(&self.memory_space) as usize
+        Arc::as_ptr(&*self.memory_space.lock()) as usize
     }
 
-    pub fn do_clone(self: &Arc<Self>, flags: CloneFlags) -> Arc<Self> {
-        let tid = alloc_tid();
+    pub fn do_clone(self: &Arc<Self>, flags: CloneFlags) -> SysResult<Arc<Self>> {
+        let tid = alloc_tid()?;
         let trap_context = SyncUnsafeCell::new(*self.trap_context_mut());
         let state = SpinNoIrqLock::new(self.state());
 
@@ -356,12 +501,19 @@ impl Task {
         let is_leader;
         let parent;
         let children;
+        let tracees;
         let thread_group;
-        let cwd;
         let itimers;
+        let children_usage;
         let robust;
         let shm_ids;
         let pgid;
+        let sid;
+        let cred;
+        // The child inherits the cpu affinity and the nice value of its parent.
+        let sched_attr = Arc::new(SchedAttr::new());
+        sched_attr.set_affinity(self.sched_attr.affinity());
+        sched_attr.set_nice(self.sched_attr.nice());
         let sig_handlers = if flags.contains(CloneFlags::SIGHAND) {
             self.sig_handlers.clone()
         } else {
@@ -372,72 +524,92 @@ impl Task {
             leader = Some(Arc::downgrade(self));
             parent = self.parent.clone();
             children = self.children.clone();
+            tracees = self.tracees.clone();
             thread_group = self.thread_group.clone();
             itimers = self.itimers.clone();
-            cwd = self.cwd.clone();
+            children_usage = self.children_usage.clone();
             robust = self.robust.clone();
             shm_ids = self.shm_ids.clone();
             pgid = self.pgid.clone();
+            sid = self.sid.clone();
+            cred = self.cred.clone();
         } else {
             is_leader = true;
             leader = None;
             parent = new_shared(Some(Arc::downgrade(self)));
             children = new_shared(BTreeMap::new());
+            tracees = new_shared(BTreeMap::new());
             thread_group = new_shared(ThreadGroup::new());
             itimers = new_shared([ITimer::ZERO; 3]);
-            cwd = new_shared(self.cwd());
+            children_usage = new_shared(ChildrenUsage::default());
             robust = new_shared(RobustListHead::default());
             shm_ids = new_shared(BTreeMap::clone(&self.shm_ids.lock()));
             for (_, shm_id) in shm_ids.lock().iter() {
                 SHARED_MEMORY_MANAGER.attach(*shm_id, tid.0);
             }
             pgid = new_shared(self.pgid());
+            sid = new_shared(self.sid());
+            cred = new_shared(self.with_cred(|cred| cred.clone()));
         }
 
         let memory_space;
         if flags.contains(CloneFlags::VM) {
-            memory_space = self.memory_space.clone();
+            memory_space = self.memory_space();
         } else {
             memory_space =
                 new_shared(self.with_mut_memory_space(|m| MemorySpace::from_user_lazily(m)));
-            // TODO: avoid flushing global entries like kernel mappings
-            unsafe { sfence_vma_all() };
         }
 
         let fd_table = if flags.contains(CloneFlags::FILES) {
-            self.fd_table.clone()
+            self.fd_table()
+        } else {
+            new_shared(self.with_fd_table(|table| table.clone()))
+        };
+        let fs = if flags.contains(CloneFlags::FS) {
+            self.fs()
         } else {
-            new_shared(self.fd_table.lock().clone())
+            new_shared(self.with_fs(|fs| fs.clone()))
         };
 
         let new = Arc::new(Self {
             tid,
             leader,
             is_leader,
-            cwd,
             state,
             parent,
             children,
+            tracees,
+            // Tracing is not inherited by clone(2).
+            ptrace: SpinNoIrqLock::new(None),
             exit_code: AtomicI32::new(0),
+            stop_event: SpinNoIrqLock::new(None),
             trap_context,
-            memory_space,
+            memory_space: SpinNoIrqLock::new(memory_space),
             waker: SyncUnsafeCell::new(None),
             thread_group,
-            fd_table,
+            fd_table: SpinNoIrqLock::new(fd_table),
+            fs: SpinNoIrqLock::new(fs),
             sig_pending: SpinNoIrqLock::new(SigPending::new()),
             // A child created via fork(2) inherits a copy of its parent's signal mask;
             sig_mask: SyncUnsafeCell::new(self.sig_mask_ref().clone()),
             sig_handlers,
             sig_stack: SyncUnsafeCell::new(None),
             time_stat: SyncUnsafeCell::new(TaskTimeStat::new()),
+            syscall_history: SyncUnsafeCell::new(SyscallHistory::new()),
+            seccomp: SyncUnsafeCell::new(self.seccomp_ref().clone()),
             sig_ucontext_ptr: AtomicUsize::new(0),
+            sched_attr,
             itimers,
+            children_usage,
             robust,
             tid_address: SyncUnsafeCell::new(TidAddress::new()),
-            cpus_allowed: SyncUnsafeCell::new(CpuMask::CPU_ALL),
+            vfork_done: SpinNoIrqLock::new(None),
             // After a fork(2), the child inherits the attached shared memory segments.
             shm_ids,
             pgid,
+            sid,
+            execed: AtomicBool::new(false),
+            cred,
             elf: SyncUnsafeCell::new(self.elf_ref().clone()),
             args: SyncUnsafeCell::new(self.args_ref().clone()),
         });
@@ -452,63 +624,89 @@ impl Task {
         }
 
         TASK_MANAGER.add(&new);
-        new
+        Ok(new)
     }
 
     pub fn do_execve(
         self: &Arc<Self>,
         elf_file: Arc<dyn File>,
         elf_data: &[u8],
-        argv: Vec<String>,
-        envp: Vec<String>,
-    ) {
+        execfn: &str,
+        args: ExecArgs,
+    ) -> SysResult<()> {
+        // NOTE: the new memory space is built before touching the task, so that the
+        // caller is left intact if the elf turns out to be invalid
         log::debug!("[Task::do_execve] parsing elf");
         let mut memory_space = MemorySpace::new_user();
-        let (mut entry, mut auxv) = memory_space.parse_and_map_elf(elf_file.clone(), elf_data);
+        let (mut entry, mut auxv) = memory_space.parse_and_map_elf(elf_file.clone(), elf_data)?;
 
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        if let Some(interp_entry_point) = memory_space.load_dl_interp_if_needed(&elf) {
+        let elf = parse_elf(elf_data)?;
+        if let Some(interp_entry_point) = memory_space.load_dl_interp_if_needed(&elf)? {
             auxv.push(AuxHeader::new(AT_BASE, DL_INTERP_OFFSET));
             entry = interp_entry_point;
         } else {
             auxv.push(AuxHeader::new(AT_BASE, 0));
         }
+        let stack_perm = MemorySpace::elf_stack_perm(&elf);
 
         // NOTE: should do termination before switching page table, so that other
         // threads will trap in by page fault and be handled by `do_exit`
         log::debug!("[Task::do_execve] terminating all threads except the leader");
-        let pid = self.with_thread_group(|tg| {
+        let (pid, others) = self.with_thread_group(|tg| {
             let mut pid = 0;
+            let mut others = Vec::new();
             for t in tg.iter() {
                 if !t.is_leader() {
-                    t.set_terminated();
+                    others.push(t);
                 } else {
                     pid = t.tid();
                 }
             }
-            pid
+            (pid, others)
         });
+        // Tid addresses belong to the old memory space, so they are released here
+        // rather than when the threads exit.
+        for t in others {
+            t.release_tid_address();
+            t.set_terminated();
+        }
+        self.release_tid_address();
+        *self.tid_address() = TidAddress::new();
 
         log::debug!("[Task::do_execve] changing memory space");
         // NOTE: need to switch to new page table first before dropping old page table,
         // otherwise, there will be a vacuum period without page table which will cause
         // random errors in smp situation
         unsafe { memory_space.switch_page_table() };
-        self.with_mut_memory_space(|m| *m = memory_space);
+        self.with_memory_space(|m| memory_space.inherit_stat(m));
+        // NOTE: the old memory space may be shared with another process, e.g. the
+        // parent of vfork, so it is replaced rather than overwritten. Other users
+        // of the old one, e.g. procfs, keep it alive until they are done.
+        let old = core::mem::replace(&mut *self.memory_space.lock(), new_shared(memory_space));
+        drop(old);
+        self.complete_vfork();
+        self.leader().set_execed(true);
 
         // alloc stack, and push argv, envp and auxv
         log::debug!("[Task::do_execve] allocing stack");
-        let sp_init = self.with_mut_memory_space(|m| m.alloc_stack_lazily(USER_STACK_SIZE));
+        let sp_init =
+            self.with_mut_memory_space(|m| m.alloc_stack_lazily(USER_STACK_SIZE, stack_perm));
 
+        // The set-user-ID and set-group-ID bits take effect.
+        self.with_mut_cred(|cred| {
+            cred.exec(&elf_file.inode());
+            push_cred_auxv(&mut auxv, cred);
+        });
         *self.elf() = elf_file;
-        *self.args() = argv.clone();
+        *self.args() = args.args_lossy();
 
-        let (sp, argc, argv, envp) = within_sum(|| init_stack(sp_init, argv, envp, auxv));
+        let (sp, argc, argv, envp) = within_sum(|| init_stack(sp_init, execfn, &args, auxv));
 
         // alloc heap
         self.with_mut_memory_space(|m| m.alloc_heap_lazily());
 
-        // close fd on exec
+        // close fd on exec, in a table of its own like Linux
+        self.unshare(CloneFlags::FILES);
         self.with_mut_fd_table(|table| table.do_close_on_exec());
 
         // init trap context
@@ -530,6 +728,43 @@ impl Task {
             }
             *ids = BTreeMap::new();
         });
+        Ok(())
+    }
+
+    /// Store zero at the clear_child_tid address and wake one waiter of the
+    /// futex there, on which pthread_join() waits, when the thread exits or
+    /// execs. Like Linux, it is done only if the memory is shared with other
+    /// threads, and an address which can not be written is ignored.
+    fn release_tid_address(self: &Arc<Self>) {
+        let Some(address) = self.tid_address().clear_child_tid.take() else {
+            return;
+        };
+        if Arc::strong_count(&*self.memory_space.lock()) <= 1 {
+            return;
+        }
+        log::info!("[release_tid_address] clear_child_tid: {address:#x}");
+        if let Err(e) = UserWritePtr::from(address).write(self, 0u32) {
+            log::warn!("[release_tid_address] can not clear tid at {address:#x}: {e:?}");
+            return;
+        }
+        // The waiter may wait on either a private or a shared futex.
+        let mut manager = futex_manager();
+        let key = FutexHashKey::Shared {
+            paddr: VirtAddr::from(address).to_paddr(),
+        };
+        let _ = manager.wake(&key, 1, FUTEX_BITSET_MATCH_ANY);
+        let key = FutexHashKey::Private {
+            mm: self.raw_mm_pointer(),
+            vaddr: address.into(),
+        };
+        let _ = manager.wake(&key, 1, FUTEX_BITSET_MATCH_ANY);
+    }
+
+    /// Wake up the parent blocked in vfork, if any.
+    fn complete_vfork(&self) {
+        if let Some(done) = self.vfork_done.lock().take() {
+            done.complete();
+        }
     }
 
     // NOTE: After all of the threads in a thread group is terminated, the parent
@@ -538,6 +773,8 @@ impl Task {
     // instead, call `set_terminated`
     pub fn do_exit(self: &Arc<Self>) {
         log::info!("thread {} do exit", self.tid());
+        #[cfg(feature = "debug")]
+        crate::processor::hart::check_stack_usage(self.tid());
         assert_ne!(
             self.tid(),
             INIT_PROC_PID,
@@ -545,21 +782,13 @@ impl Task {
             self.trap_context_mut().sepc
         );
 
-        if let Some(address) = self.tid_address_ref().clear_child_tid {
-            log::info!("[do_exit] clear_child_tid: {:x}", address);
-            UserWritePtr::from(address)
-                .write(self, 0)
-                .expect("tid address write error");
-            let key = FutexHashKey::Shared {
-                paddr: VirtAddr::from(address).to_paddr(),
-            };
-            let _ = futex_manager().wake(&key, 1);
-            let key = FutexHashKey::Private {
-                mm: self.raw_mm_pointer(),
-                vaddr: address.into(),
-            };
-            let _ = futex_manager().wake(&key, 1);
-        }
+        self.release_tid_address();
+        self.complete_vfork();
+        self.ptrace_detach(None);
+        // The files are closed once no task shares the fd table.
+        drop(self.replace_fd_table(new_shared(FdTable::empty())));
+
+        exit_pi_futexes(self.tid());
 
         let mut tg = self.thread_group.lock();
 
@@ -583,9 +812,13 @@ impl Task {
             tg.remove(self);
             TASK_MANAGER.remove(self.tid());
         }
+        // NOTE: init may be waiting with its children locked, and checking our
+        // thread group, while we lock its children below.
+        drop(tg);
 
         // exit the process, e.g. reparent all children, and send SIGCHLD to parent
         log::info!("[Task::do_exit] exit the whole process");
+        self.ptrace_detach_all();
 
         log::debug!("[Task::do_exit] reparent children to init");
         debug_assert_ne!(self.tid(), INIT_PROC_PID);
@@ -594,33 +827,26 @@ impl Task {
                 return;
             }
             let init_proc = TASK_MANAGER.init_proc();
+            // NOTE: init must find the children before they can notify it, or it may
+            // miss a zombie when woken up.
+            init_proc.children.lock().extend(children.clone());
+            let mut has_zombie = false;
             for c in children.values() {
                 log::debug!(
                     "[Task::do_eixt] reparent child process pid {} to init",
                     c.pid()
                 );
-                if c.is_zombie() {
-                    // NOTE: self has not called wait to clear zombie children, we need to notify
-                    // init to clear these zombie children.
-                    init_proc.receive_siginfo(
-                        SigInfo {
-                            sig: Sig::SIGCHLD,
-                            code: SigInfo::CLD_EXITED,
-                            details: SigDetails::None,
-                        },
-                        false,
-                    )
-                }
+                // NOTE: the parent is changed before checking whether the child is a
+                // zombie, while an exiting child becomes a zombie before finding its
+                // parent, so that init is notified by either of us.
                 *c.parent.lock() = Some(Arc::downgrade(&init_proc));
+                has_zombie |= c.is_zombie();
             }
-            init_proc.children.lock().extend(children.clone());
             children.clear();
-        });
-
-        // NOTE: leader will be removed by parent calling `sys_wait4`
-        if let Some(parent) = self.parent() {
-            if let Some(parent) = parent.upgrade() {
-                parent.receive_siginfo(
+            if has_zombie {
+                // NOTE: self has not called wait to clear zombie children, we need to notify
+                // init to clear these zombie children.
+                init_proc.receive_siginfo(
                     SigInfo {
                         sig: Sig::SIGCHLD,
                         code: SigInfo::CLD_EXITED,
@@ -628,28 +854,52 @@ impl Task {
                     },
                     false,
                 )
-            } else {
-                log::error!("no arc parent");
             }
+        });
+
+        // The controlling terminal of the session is released when its leader
+        // exits.
+        if self.is_session_leader() {
+            SESSION_MANAGER.release(self.sid());
         }
 
         // Upon _exit(2), all attached shared memory segments are detached from the
         // process.
-        self.with_shm_ids(|ids| {
+        self.with_mut_shm_ids(|ids| {
             for (_, shm_id) in ids.iter() {
                 SHARED_MEMORY_MANAGER.detach(*shm_id, self.pid());
             }
+            ids.clear();
         });
+        // Adjustments of semaphores by SEM_UNDO are applied on exit.
+        SEM_MANAGER.exit(self.pid());
 
         // TODO: drop most resources here instead of wait4 function parent
         // called
-        self.with_mut_fd_table(|table| table.clear());
 
+        // NOTE: the process becomes a zombie before finding its parent, see the
+        // reparenting above.
         if self.is_leader() {
             self.set_zombie();
         } else {
             self.leader().set_zombie();
         }
+
+        // NOTE: leader will be removed by parent calling `sys_wait4`
+        if let Some(parent) = self.parent() {
+            if let Some(parent) = parent.upgrade() {
+                parent.receive_siginfo(
+                    SigInfo {
+                        sig: Sig::SIGCHLD,
+                        code: SigInfo::CLD_EXITED,
+                        details: SigDetails::None,
+                    },
+                    false,
+                )
+            } else {
+                log::error!("no arc parent");
+            }
+        }
         // When the task is not leader, which means its is not a process, it
         // will get dropped when hart leaves this task.
     }
@@ -669,16 +919,16 @@ impl Task {
     pub fn at_helper(&self, fd: AtFd, path: &str, flags: OpenFlags) -> SysResult<Arc<dyn Dentry>> {
         log::info!("[at_helper] fd: {fd}, path: {path}");
         let path = if is_absolute_path(path) {
-            Path::new(sys_root_dentry(), sys_root_dentry(), path)
+            Path::new(self.root(), self.root(), path)
         } else {
             match fd {
                 AtFd::FdCwd => {
                     log::info!("[at_helper] cwd: {}", self.cwd().path());
-                    Path::new(sys_root_dentry(), self.cwd(), path)
+                    Path::new(self.root(), self.cwd(), path)
                 }
                 AtFd::Normal(fd) => {
                     let file = self.with_fd_table(|table| table.get_file(fd))?;
-                    Path::new(sys_root_dentry(), file.dentry(), path)
+                    Path::new(self.root(), file.dentry(), path)
                 }
             }
         };
@@ -697,12 +947,124 @@ impl Task {
         Path::resolve_dentry(dentry)
     }
 
+    /// Create `name` in directory `parent` with `mode` masked by the umask,
+    /// which needs write and search permission of `parent`. The new inode is
+    /// owned by the task.
+    pub fn create_helper(
+        &self,
+        parent: &Arc<dyn Dentry>,
+        name: &str,
+        mode: InodeMode,
+    ) -> SysResult<Arc<dyn Dentry>> {
+        self.mknod_helper(parent, mode, |mode| parent.create(name, mode))
+    }
+
+    /// Like `create_helper`, but the inode is made by `mknod` with the masked
+    /// mode, for special files that the filesystem does not create.
+    pub fn mknod_helper(
+        &self,
+        parent: &Arc<dyn Dentry>,
+        mode: InodeMode,
+        mknod: impl FnOnce(InodeMode) -> SysResult<Arc<dyn Dentry>>,
+    ) -> SysResult<Arc<dyn Dentry>> {
+        let mode = mode - self.umask();
+        let cred = self.with_cred(|cred| cred.clone());
+        cred.check_access(
+            &parent.inode()?,
+            AccessMode::WRITE | AccessMode::EXEC,
+            false,
+        )?;
+        let dentry = mknod(mode)?;
+        dentry.set_mode(mode)?;
+        cred.own(&dentry)?;
+        Ok(dentry)
+    }
+
     /// Given a path, absolute or relative, will find.
     pub fn resolve_path_nofollow(&self, path: &str) -> SysResult<Arc<dyn Dentry>> {
         self.at_helper(AtFd::FdCwd, path, OpenFlags::O_NOFOLLOW)
     }
 }
 
+/// Completion of vfork, on which the parent waits until the child stops using
+/// its memory by execve or exit.
+pub struct VforkDone {
+    done: AtomicBool,
+    queue: WaitQueue,
+}
+
+impl VforkDone {
+    pub fn new() -> Self {
+        Self {
+            done: AtomicBool::new(false),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    pub fn complete(&self) {
+        self.done.store(true, Ordering::Release);
+        self.queue.wake_all();
+    }
+
+    pub async fn wait(&self) {
+        self.queue
+            .wait_until(|| self.done.load(Ordering::Acquire))
+            .await
+    }
+}
+
+/// Number of syscalls kept in `SyscallHistory`.
+const SYSCALL_HISTORY_LEN: usize = 8;
+
+/// Ring buffer of the numbers of the syscalls made most recently by a task.
+pub struct SyscallHistory {
+    syscalls: [usize; SYSCALL_HISTORY_LEN],
+    /// Number of syscalls recorded ever.
+    count: usize,
+}
+
+impl SyscallHistory {
+    pub const fn new() -> Self {
+        Self {
+            syscalls: [0; SYSCALL_HISTORY_LEN],
+            count: 0,
+        }
+    }
+
+    pub fn record(&mut self, syscall_no: usize) {
+        self.syscalls[self.count % SYSCALL_HISTORY_LEN] = syscall_no;
+        self.count += 1;
+    }
+
+    /// The syscalls kept, from the oldest to the latest.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let start = self.count.saturating_sub(SYSCALL_HISTORY_LEN);
+        (start..self.count).map(|i| self.syscalls[i % SYSCALL_HISTORY_LEN])
+    }
+}
+
+/// Filesystem information of tasks sharing it by `CLONE_FS`, like `fs_struct`
+/// of Linux.
+#[derive(Clone)]
+pub struct FsContext {
+    /// Root directory where absolute paths are resolved.
+    pub root: Arc<dyn Dentry>,
+    /// Current working directory.
+    pub cwd: Arc<dyn Dentry>,
+    /// File mode creation mask.
+    pub umask: InodeMode,
+}
+
+impl FsContext {
+    pub fn new() -> Self {
+        Self {
+            root: sys_root_dentry(),
+            cwd: sys_root_dentry(),
+            umask: DEFAULT_UMASK,
+        }
+    }
+}
+
 /// Hold a group of threads which belongs to the same process.
 pub struct ThreadGroup {
     members: BTreeMap<Tid, Weak<Task>>,
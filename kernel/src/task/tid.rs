@@ -1,9 +1,10 @@
-use config::process::INIT_PROC_PID;
+use config::process::{INIT_PROC_PID, PID_MAX};
 use recycle_allocator::RecycleAllocator;
 use sync::mutex::SpinNoIrqLock;
+use systype::{SysError, SysResult};
 
 pub static TID_ALLOCATOR: SpinNoIrqLock<RecycleAllocator> =
-    SpinNoIrqLock::new(RecycleAllocator::new(INIT_PROC_PID));
+    SpinNoIrqLock::new(RecycleAllocator::with_max(INIT_PROC_PID, PID_MAX));
 
 pub type Tid = usize;
 pub type Pid = Tid;
@@ -18,17 +19,23 @@ impl Drop for TidHandle {
     }
 }
 
-pub fn alloc_tid() -> TidHandle {
-    TidHandle(TID_ALLOCATOR.lock().alloc())
+/// Allocate a tid, or fail with `EAGAIN` if all tids are in use.
+pub fn alloc_tid() -> SysResult<TidHandle> {
+    TID_ALLOCATOR
+        .lock()
+        .try_alloc()
+        .map(TidHandle)
+        .ok_or(SysError::EAGAIN)
 }
 
 /// Tid address which may be set by `set_tid_address` syscall.
 pub struct TidAddress {
-    /// When set, when spawning a new thread, the kernel sets the thread's tid
-    /// to this address.
+    /// Set by `CLONE_CHILD_SETTID`, where the kernel stores the tid of the new
+    /// thread before it runs.
     pub set_child_tid: Option<usize>,
-    /// When set, when the thread exits, the kernel sets the thread's tid to
-    /// this address, and wake up a futex waiting on this address.
+    /// Set by `CLONE_CHILD_CLEARTID` or `set_tid_address`. When the thread
+    /// exits or execs, the kernel stores zero to this address, and wakes up a
+    /// futex waiting on this address.
     pub clear_child_tid: Option<usize>,
 }
 
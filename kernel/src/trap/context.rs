@@ -29,7 +29,9 @@ pub struct TrapContext {
     /// Float regs
     pub user_fx: UserFloatContext,
 
-    pub last_a0: usize,
+    /// Original syscall arguments `a0`-`a5`, stashed on syscall entry so that
+    /// the syscall can be restarted after a signal handler returns.
+    pub last_args: [usize; 6],
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -179,7 +181,7 @@ impl TrapContext {
             // We will give the right kernel tp in `__return_to_user`
             kernel_tp: 0,
             user_fx: UserFloatContext::new(),
-            last_a0: 0,
+            last_args: [0; 6],
         };
         cx.set_user_sp(sp);
         cx
@@ -237,12 +239,21 @@ impl TrapContext {
         self.user_x[4] = val;
     }
 
-    pub fn save_last_user_a0(&mut self) {
-        self.last_a0 = self.user_x[10];
+    pub fn save_last_user_args(&mut self) {
+        self.last_args = self.syscall_args();
     }
 
-    pub fn restore_last_user_a0(&mut self) {
-        self.user_x[10] = self.last_a0;
+    pub fn restore_last_user_args(&mut self) {
+        // a0-a5 == x10-x15
+        self.user_x[10..16].copy_from_slice(&self.last_args);
+    }
+
+    /// Rewind `sepc` to the `ecall` instruction and restore the original
+    /// arguments, so that the interrupted syscall will be executed again when
+    /// returning to user space.
+    pub fn restart_syscall(&mut self) {
+        self.sepc -= 4;
+        self.restore_last_user_args();
     }
 
     /// Set entry point
@@ -253,4 +264,18 @@ impl TrapContext {
     pub fn set_user_pc_to_next(&mut self) {
         self.sepc += 4;
     }
+
+    /// General purpose registers of user in `struct user_regs_struct`, i.e. pc
+    /// followed by x1 to x31.
+    pub fn user_regs(&self) -> [usize; 32] {
+        let mut regs = self.user_x;
+        regs[0] = self.sepc;
+        regs
+    }
+
+    /// Set general purpose registers of user from `struct user_regs_struct`.
+    pub fn set_user_regs(&mut self, regs: &[usize; 32]) {
+        self.sepc = regs[0];
+        self.user_x[1..].copy_from_slice(&regs[1..]);
+    }
 }
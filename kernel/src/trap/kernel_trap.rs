@@ -1,5 +1,7 @@
 //! Trap from kernel.
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use arch::{
     interrupts::set_trap_handler_vector,
     time::{get_time_duration, set_next_timer_irq, set_timer_irq},
@@ -9,11 +11,12 @@ use riscv::register::{
     scause::{self, Exception, Interrupt, Scause, Trap},
     sepc, sstatus, stval, stvec,
 };
+use sbi_print::sbi_println;
 use signal::{Sig, SigDetails, SigInfo};
 use timer::TIMER_MANAGER;
 
 use crate::{
-    mm::PageFaultAccessType,
+    mm::{tlb, PageFaultAccessType},
     processor::hart::{
         current_task_ref, local_hart, local_hart_disable_preemptable,
         local_hart_enable_preemptable, local_hart_preemptable,
@@ -41,14 +44,25 @@ pub fn kernel_trap_handler() {
     let cause = scause.cause();
     match scause.cause() {
         Trap::Interrupt(i) => match i {
+            Interrupt::SupervisorSoft => {
+                // woken up from idle by another hart, which has pushed some tasks,
+                // or asked to flush the TLB
+                arch::interrupts::clear_ipi();
+                tlb::handle_shootdown();
+            }
             Interrupt::SupervisorExternal => {
                 log::info!("[kernel] receive externel interrupt");
-                driver::get_device_manager_mut().handle_irq();
+                executor::enter_irq();
+                driver::get_device_manager().handle_irq(local_hart().hart_id());
+                executor::leave_irq();
             }
             Interrupt::SupervisorTimer => {
                 // log::error!("[kernel_trap] receive timer interrupt");
+                executor::enter_irq();
                 TIMER_MANAGER.check();
+                executor::leave_irq();
                 unsafe { set_next_timer_irq() };
+                local_hart().tick();
                 #[cfg(feature = "preempt")]
                 {
                     use crate::processor::hart::local_hart;
@@ -84,9 +98,8 @@ pub fn kernel_trap_handler() {
                     _ => unreachable!(),
                 };
 
-                let result = current_task_ref().with_mut_memory_space(|m| {
-                    m.handle_page_fault(VirtAddr::from(stval), access_type)
-                });
+                let result =
+                    current_task_ref().handle_page_fault(VirtAddr::from(stval), access_type);
                 if let Err(_e) = result {
                     log::warn!(
                         "[trap_handler] encounter page fault, addr {stval:#x}, instruction {sepc:#x} scause {cause:?}",
@@ -99,7 +112,7 @@ pub fn kernel_trap_handler() {
                             code: SigInfo::KERNEL,
                             details: SigDetails::None,
                         },
-                        false,
+                        true,
                     );
                 }
             }
@@ -108,6 +121,41 @@ pub fn kernel_trap_handler() {
     }
 }
 
+/// Called on the emergency stack by the trap entry when the kernel stack has
+/// overflowed into its guard page, with `sp` at the trap.
+///
+/// The message and backtrace are printed by SBI before panicking, since the
+/// overflow may happen with the logger locked.
+#[no_mangle]
+extern "C" fn kernel_stack_overflow(sp: usize) -> ! {
+    static REPORTED: AtomicBool = AtomicBool::new(false);
+    if REPORTED.swap(true, Ordering::SeqCst) {
+        // faulted again while reporting, even on the emergency stack
+        sbi_println!("kernel stack overflow again, sp {sp:#x}");
+        sbi_rt::legacy::shutdown();
+    }
+    let hart = local_hart();
+    let (stval, sepc) = (stval::read(), sepc::read());
+    match arch::entry::kernel_stack_owner(sp) {
+        Some(owner) if hart.has_task() => sbi_println!(
+            "kernel stack overflow in task {} on hart {owner}, sp {sp:#x}, sepc {sepc:#x}, stval {stval:#x}",
+            hart.task().tid()
+        ),
+        Some(owner) => sbi_println!(
+            "kernel stack overflow in kernel task on hart {owner}, sp {sp:#x}, sepc {sepc:#x}, stval {stval:#x}"
+        ),
+        None => sbi_println!(
+            "kernel stack overflow outside kernel stacks, sp {sp:#x}, sepc {sepc:#x}, stval {stval:#x}"
+        ),
+    }
+    backtrace::backtrace();
+    if hart.has_task() {
+        panic!("kernel stack overflow in task {}", hart.task().tid());
+    } else {
+        panic!("kernel stack overflow in kernel task");
+    }
+}
+
 extern "C" {
     fn __user_rw_trap_vector();
 }
@@ -7,9 +7,21 @@ pub mod user_trap;
 use core::arch::global_asm;
 
 use arch::interrupts::set_trap_handler;
+use config::{
+    board::MAX_HARTS,
+    mm::{KERNEL_EMERGENCY_STACK_SIZE, KERNEL_STACK_SIZE, PAGE_SIZE_BITS},
+};
 pub use context::TrapContext;
 
-global_asm!(include_str!("trap.asm"));
+global_asm!(
+    include_str!("trap.asm"),
+    boot_stack = sym arch::entry::BOOT_STACK,
+    emergency_stack = sym arch::entry::EMERGENCY_STACK,
+    stack_bits = const KERNEL_STACK_SIZE.trailing_zeros(),
+    page_size_bits = const PAGE_SIZE_BITS,
+    emergency_stack_size = const KERNEL_EMERGENCY_STACK_SIZE,
+    max_harts = const MAX_HARTS,
+);
 
 extern "C" {
     fn __trap_from_user();
@@ -115,6 +115,16 @@ __return_to_user:
 
 # kernel -> kernel
 __trap_from_kernel:
+    # A kernel stack overflow faults in the guard page at the bottom of the
+    # stack, where nothing can be saved, so it is told by the page sp is in
+    # within the stack before the stack is touched. The stacks are aligned to
+    # their size, and sp - 1 is checked since sp is at the top when empty.
+    csrw sscratch, t0
+    addi t0, sp, -1
+    slli t0, t0, 64 - {stack_bits}
+    srli t0, t0, 64 - {stack_bits} + {page_size_bits}
+    beqz t0, __kernel_stack_overflow
+    csrr t0, sscratch
     # only need to save caller-saved regs
     # note that we don't save sepc & stvec here
     addi sp, sp, -17*8
@@ -154,6 +164,24 @@ __trap_from_kernel:
     addi sp, sp, 17*8
     sret
 
+# Report the overflow on the emergency stack of the hart owning the kernel
+# stack, which never returns.
+__kernel_stack_overflow:
+    mv a0, sp
+    la t0, {boot_stack}
+    sub t0, sp, t0
+    srli t0, t0, {stack_bits}
+    li t1, {max_harts}
+    bltu t0, t1, 1f
+    li t0, 0
+1:
+    addi t0, t0, 1
+    li t1, {emergency_stack_size}
+    mul t0, t0, t1
+    la sp, {emergency_stack}
+    add sp, sp, t0
+    call kernel_stack_overflow
+
 # arg: (user_ptr)
 # return: (usize, usize)
 # if a0 == 0, which means no exception happens
@@ -6,7 +6,6 @@ use arch::{
     interrupts::{disable_interrupt, enable_interrupt},
     time::{get_time_duration, set_next_timer_irq},
 };
-use async_utils::yield_now;
 use memory::VirtAddr;
 use riscv::register::{
     scause::{self, Exception, Interrupt, Trap},
@@ -19,7 +18,13 @@ use systype::SysError;
 use timer::TIMER_MANAGER;
 
 use super::{set_kernel_trap, TrapContext};
-use crate::{mm::PageFaultAccessType, syscall::Syscall, task::Task, trap::set_user_trap};
+use crate::{
+    mm::{tlb, PageFaultAccessType},
+    processor::hart::local_hart,
+    syscall::Syscall,
+    task::{ptrace::ptrace_syscall_stop, Task},
+    trap::set_user_trap,
+};
 
 /// handle an interrupt, exception, or system call from user space
 /// return if it is syscall and has been interrupted
@@ -36,23 +41,32 @@ pub async fn trap_handler(task: &Arc<Task>) -> bool {
     log::trace!("[trap_handler] sepc:{sepc:#x}, stval:{stval:#x}");
     unsafe { enable_interrupt() };
 
-    if task.time_stat_ref().need_schedule() && executor::has_task() {
-        log::info!("time slice used up, yield now");
-        yield_now().await;
-    }
+    task.update_itimers();
 
     match cause {
         Trap::Exception(e) => {
             match e {
                 Exception::UserEnvCall => {
-                    let syscall_no = cx.syscall_no();
                     cx.set_user_pc_to_next();
+                    // the tracer may change the syscall and its arguments at
+                    // the syscall entry stop
+                    ptrace_syscall_stop(task).await;
+                    if task.is_terminated() {
+                        return false;
+                    }
+                    cx.save_last_user_args();
+                    let syscall_no = cx.syscall_no();
+                    task.syscall_history().record(syscall_no);
+                    // the env is swapped along with the task, so the flag is
+                    // only seen on the hart polling the syscall
+                    local_hart().env_mut().set_in_syscall(true);
                     // get system call return value
                     let ret = Syscall::new(task)
                         .syscall(syscall_no, cx.syscall_args())
                         .await;
-                    cx.save_last_user_a0();
+                    local_hart().env_mut().set_in_syscall(false);
                     cx.set_user_a0(ret);
+                    ptrace_syscall_stop(task).await;
                     if ret == -(SysError::EINTR as isize) as usize {
                         return true;
                     }
@@ -78,10 +92,24 @@ pub async fn trap_handler(task: &Arc<Task>) -> bool {
                     // 6. dynamic link
                     // 7. illegal page fault
 
-                    let result = task.with_mut_memory_space(|m| {
-                        m.handle_page_fault(VirtAddr::from(stval), access_type)
-                    });
-                    if let Err(_e) = result {
+                    let result = task.handle_page_fault(VirtAddr::from(stval), access_type);
+                    if let Err(SysError::ENOMEM) = result {
+                        // the fault can not be resolved by retrying, and the
+                        // signal handler may fault again, so the process is
+                        // killed to give its memory back
+                        log::warn!(
+                            "[trap_handler] out of memory at addr {stval:#x}, send SIGKILL to process {}",
+                            task.pid()
+                        );
+                        task.receive_siginfo(
+                            SigInfo {
+                                sig: Sig::SIGKILL,
+                                code: SigInfo::KERNEL,
+                                details: SigDetails::None,
+                            },
+                            false,
+                        );
+                    } else if let Err(_e) = result {
                         log::warn!(
                             "[trap_handler] encounter page fault, addr {stval:#x}, instruction {sepc:#x} scause {cause:?}",
                         );
@@ -89,13 +117,14 @@ pub async fn trap_handler(task: &Arc<Task>) -> bool {
                         log::warn!("{:x?}", task.trap_context_mut());
                         // task.with_memory_space(|m| m.print_all());
                         log::warn!("bad memory access, send SIGSEGV to task");
+                        // delivered to the faulting thread, which may not be the leader
                         task.receive_siginfo(
                             SigInfo {
                                 sig: Sig::SIGSEGV,
                                 code: SigInfo::KERNEL,
                                 details: SigDetails::None,
                             },
-                            false,
+                            true,
                         );
                     }
                 }
@@ -117,15 +146,21 @@ pub async fn trap_handler(task: &Arc<Task>) -> bool {
                     // likely not triggered in user mode but rather be triggered in supervisor mode,
                     // which will cause user program running on the cpu for a quite long time.
                     log::trace!("[trap_handler] timer interrupt, sepc {sepc:#x}");
+                    executor::enter_irq();
                     TIMER_MANAGER.check();
+                    executor::leave_irq();
                     unsafe { set_next_timer_irq() };
-                    if executor::has_task() {
-                        yield_now().await;
-                    }
+                    local_hart().tick();
+                }
+                Interrupt::SupervisorSoft => {
+                    arch::interrupts::clear_ipi();
+                    tlb::handle_shootdown();
                 }
                 Interrupt::SupervisorExternal => {
                     log::info!("[kernel] receive externel interrupt");
-                    driver::get_device_manager_mut().handle_irq();
+                    executor::enter_irq();
+                    driver::get_device_manager().handle_irq(local_hart().hart_id());
+                    executor::leave_irq();
                 }
                 _ => {
                     panic!(
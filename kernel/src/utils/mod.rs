@@ -1,7 +1,7 @@
 use alloc::{format, sync::Arc};
+use core::time::Duration;
 
 use config::process::INIT_PROC_PID;
-use timer::timelimited_task::{ksleep_ms, ksleep_s};
 
 use crate::task::{self, Task, TASK_MANAGER};
 
@@ -26,28 +26,37 @@ pub fn exam_hash(buf: &[u8]) -> usize {
     h
 }
 
-pub fn spawn_timer_tasks<F>(f: F, interval_secs: usize)
+/// Run `f` every `interval_secs` seconds in a background task named `name`,
+/// until it is stopped on shutdown.
+pub fn spawn_timer_tasks<F>(name: &'static str, f: F, interval_secs: usize)
 where
     F: FnOnce() + Send + Copy + 'static,
 {
-    task::spawn_kernel_task(async move {
+    task::spawn_background_task(name, move |token| async move {
         let f = f;
         loop {
             f();
-            ksleep_s(interval_secs).await;
+            if !token.sleep(Duration::from_secs(interval_secs as u64)).await {
+                break;
+            }
         }
     });
 }
 
-pub fn spawn_timer_tasks_ms<F>(f: F, interval_millisecs: usize)
+pub fn spawn_timer_tasks_ms<F>(name: &'static str, f: F, interval_millisecs: usize)
 where
     F: FnOnce() + Send + Copy + 'static,
 {
-    task::spawn_kernel_task(async move {
+    task::spawn_background_task(name, move |token| async move {
         let f = f;
         loop {
             f();
-            ksleep_ms(interval_millisecs).await;
+            if !token
+                .sleep(Duration::from_millis(interval_millisecs as u64))
+                .await
+            {
+                break;
+            }
         }
     });
 }
@@ -20,14 +20,17 @@ pub enum DeviceType {
     Char,
     Net,
     Display,
+    Rng,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(usize)]
 pub enum DeviceMajor {
     Serial = 4,
+    Loop = 7,
     Block = 8,
     Net = 9,
+    Misc = 10,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -109,6 +112,22 @@ pub trait CharDevice: Device {
     async fn poll_out(&self) -> bool;
 }
 
+/// I/O statistics of a block device, counted in requests to the device and in
+/// sectors of 512 bytes as in /proc/diskstats.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiskStats {
+    pub reads: usize,
+    /// Reads merged into others before issued to the device.
+    pub reads_merged: usize,
+    pub sectors_read: usize,
+    pub writes: usize,
+    /// Writes merged into others before issued to the device.
+    pub writes_merged: usize,
+    pub sectors_written: usize,
+    pub in_flight: usize,
+}
+
+#[async_trait]
 pub trait BlockDevice: Device {
     fn size(&self) -> u64;
 
@@ -116,6 +135,11 @@ pub trait BlockDevice: Device {
 
     fn buffer_head_cnts(&self) -> usize;
 
+    /// Number of pages caching pure block data.
+    fn buffer_page_cnts(&self) -> usize {
+        0
+    }
+
     fn remove_buffer_page(&self, block_id: usize);
 
     /// Read data form block to buffer
@@ -129,6 +153,40 @@ pub trait BlockDevice: Device {
 
     /// Write data from buffer to block
     fn write_block(&self, block_id: usize, buf: &[u8]);
+
+    /// Write blocks cached by the device and dirty back to the disk.
+    fn sync(&self) {}
+
+    /// Read data from blocks to buffer, letting other tasks run until it is
+    /// done if the device completes requests by interrupts.
+    async fn read_blocks_async(&self, block_id: usize, buf: &mut [u8]) {
+        self.base_read_blocks(block_id, buf)
+    }
+
+    /// Write data from buffer to blocks, letting other tasks run until it is
+    /// done if the device completes requests by interrupts.
+    async fn write_blocks_async(&self, block_id: usize, buf: &[u8]) {
+        self.base_write_blocks(block_id, buf)
+    }
+
+    /// Start writing data from buffer to blocks without waiting for it, whose
+    /// completion is waited for by `wait_writes`.
+    fn submit_write_blocks(&self, block_id: usize, buf: &[u8]) {
+        self.base_write_blocks(block_id, buf)
+    }
+
+    /// Wait until all writes submitted are done.
+    fn wait_writes(&self) {}
+
+    /// Hold back requests to be merged with the ones following, until
+    /// `unplug`. Plugs may be nested.
+    fn plug(&self) {}
+
+    fn unplug(&self) {}
+
+    fn disk_stats(&self) -> DiskStats {
+        DiskStats::default()
+    }
 }
 
 impl_downcast!(sync BlockDevice);
@@ -5,28 +5,84 @@
 
 extern crate alloc;
 
-use alloc::collections::VecDeque;
-use core::future::Future;
+mod sched_attr;
 
-use async_task::{Runnable, ScheduleInfo, Task, WithInfo};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    future::Future,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use async_task::{Builder, ScheduleInfo, Task, WithInfo};
+use config::board::MAX_HARTS;
+use crate_interface::call_interface;
+pub use sched_attr::{SchedAttr, NICE_MAX, NICE_MIN};
 use sync::mutex::SpinNoIrqLock;
 
-static TASK_QUEUE: TaskQueue = TaskQueue::new();
+pub type Runnable = async_task::Runnable<Arc<SchedAttr>>;
+/// Handle of a spawned task, which cancels the task when dropped unless it is
+/// detached.
+pub type TaskHandle<T> = Task<T, Arc<SchedAttr>>;
+
+#[crate_interface::def_interface]
+pub trait ExecutorIf: Send + Sync {
+    /// Id of the hart running the executor.
+    fn hart_id() -> usize;
+    /// Wake up the hart that is waiting for interrupts.
+    fn send_ipi(hart_id: usize);
+}
+
+fn local_hart_id() -> usize {
+    call_interface!(ExecutorIf::hart_id())
+}
+
+/// The injector is polled first once every `INJECTOR_INTERVAL` fetches, so
+/// that tasks in it will not be starved by a busy hart.
+const INJECTOR_INTERVAL: usize = 61;
+
+const HART_QUEUE_EACH: HartQueue = HartQueue::new();
+static HART_QUEUES: [HartQueue; MAX_HARTS] = [HART_QUEUE_EACH; MAX_HARTS];
+
+/// Bitmask of harts that are running the executor.
+static ACTIVE_HARTS: AtomicUsize = AtomicUsize::new(0);
+/// Bitmask of harts that are idle, i.e. waiting for interrupts since there are
+/// no tasks to run.
+static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
 
-struct TaskQueue {
+/// Shared queue for tasks woken up in interrupt context, which can be fetched
+/// by any hart.
+static INJECTOR: SpinNoIrqLock<VecDeque<Runnable>> = SpinNoIrqLock::new(VecDeque::new());
+
+/// Local task queues of a hart.
+struct HartQueue {
     normal: SpinNoIrqLock<VecDeque<Runnable>>,
     prior: SpinNoIrqLock<VecDeque<Runnable>>,
+    /// Set while the hart is handling an interrupt.
+    in_irq: AtomicBool,
+    /// Number of tasks fetched from all queues.
+    fetched: AtomicUsize,
+    /// Number of tasks stolen from other harts.
+    stolen: AtomicUsize,
+    /// The least virtual runtime of tasks run from the normal queue.
+    min_vruntime: AtomicU64,
 }
 
-impl TaskQueue {
+impl HartQueue {
     pub const fn new() -> Self {
         Self {
             normal: SpinNoIrqLock::new(VecDeque::new()),
             prior: SpinNoIrqLock::new(VecDeque::new()),
+            in_irq: AtomicBool::new(false),
+            fetched: AtomicUsize::new(0),
+            stolen: AtomicUsize::new(0),
+            min_vruntime: AtomicU64::new(0),
         }
     }
 
     pub fn push_normal(&self, runnable: Runnable) {
+        runnable
+            .metadata()
+            .place(self.min_vruntime.load(Ordering::Relaxed));
         self.normal.lock().push_back(runnable);
     }
 
@@ -34,10 +90,6 @@ impl TaskQueue {
         self.prior.lock().push_back(runnable);
     }
 
-    pub fn fetch_normal(&self) -> Option<Runnable> {
-        self.normal.lock().pop_front()
-    }
-
     pub fn fetch_prior(&self) -> Option<Runnable> {
         self.prior.lock().pop_front()
     }
@@ -46,7 +98,53 @@ impl TaskQueue {
         self.prior
             .lock()
             .pop_front()
-            .or_else(|| self.normal.lock().pop_front())
+            .or_else(|| self.fetch_normal())
+    }
+
+    /// Fetch the task with the least virtual runtime in the normal queue.
+    pub fn fetch_normal(&self) -> Option<Runnable> {
+        let mut normal = self.normal.lock();
+        let (i, _) = normal
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, runnable)| runnable.metadata().vruntime())?;
+        let runnable = normal.remove(i).unwrap();
+        self.min_vruntime
+            .fetch_max(runnable.metadata().vruntime(), Ordering::Relaxed);
+        Some(runnable)
+    }
+
+    /// Takes half of the tasks in the queues that are allowed to run on the
+    /// hart, normal tasks first.
+    pub fn steal(&self, hart_id: usize) -> Vec<Runnable> {
+        for queue in [&self.normal, &self.prior] {
+            let mut queue = queue.lock();
+            let n = (queue.len() + 1) / 2;
+            let mut stolen = Vec::new();
+            let mut i = 0;
+            while i < queue.len() && stolen.len() < n {
+                if queue[i].metadata().allows(hart_id) {
+                    stolen.push(queue.remove(i).unwrap());
+                } else {
+                    i += 1;
+                }
+            }
+            if !stolen.is_empty() {
+                return stolen;
+            }
+        }
+        Vec::new()
+    }
+
+    /// Whether there are tasks in the queues that are allowed to run on the
+    /// hart.
+    pub fn has_allowed(&self, hart_id: usize) -> bool {
+        [&self.normal, &self.prior].into_iter().any(|queue| {
+            queue
+                .lock()
+                .iter()
+                .any(|runnable| runnable.metadata().allows(hart_id))
+        })
     }
 
     pub fn len(&self) -> usize {
@@ -62,53 +160,250 @@ impl TaskQueue {
     }
 }
 
+fn local_queue() -> &'static HartQueue {
+    &HART_QUEUES[local_hart_id()]
+}
+
+/// Push a woken task into the queues of the local hart, or the injector if the
+/// hart is handling an interrupt. The task is pushed into the queues of another
+/// hart directly if it is not allowed to run on the local hart.
+fn push_task(runnable: Runnable, prior: bool) {
+    let attr = runnable.metadata();
+    let prior = prior || attr.is_boosted();
+    let local = local_hart_id();
+    let hart_id = if attr.allows(local) {
+        local
+    } else {
+        allowed_hart(attr)
+    };
+    let queue = &HART_QUEUES[hart_id];
+    let active = ACTIVE_HARTS.load(Ordering::Relaxed);
+    if hart_id == local
+        && queue.in_irq.load(Ordering::Relaxed)
+        && attr.affinity() & active == active
+    {
+        INJECTOR.lock().push_back(runnable);
+    } else if prior {
+        queue.push_prior(runnable);
+    } else {
+        queue.push_normal(runnable);
+    }
+    if hart_id == local {
+        wake_idle_hart();
+    } else {
+        wake_hart(hart_id);
+    }
+}
+
+/// Find a hart that the task is allowed to run on, preferring an idle one.
+fn allowed_hart(attr: &SchedAttr) -> usize {
+    let active = ACTIVE_HARTS.load(Ordering::Relaxed);
+    let idle = IDLE_HARTS.load(Ordering::Relaxed);
+    let affinity = attr.affinity() & active;
+    if affinity & idle != 0 {
+        (affinity & idle).trailing_zeros() as usize
+    } else if affinity != 0 {
+        affinity.trailing_zeros() as usize
+    } else {
+        // no allowed hart is running yet
+        attr.affinity().trailing_zeros() as usize % MAX_HARTS
+    }
+}
+
+/// Wake up one idle hart, if there is any, to steal the task just pushed.
+fn wake_idle_hart() {
+    let idle = IDLE_HARTS.load(Ordering::Relaxed) & !(1 << local_hart_id());
+    if idle == 0 {
+        return;
+    }
+    wake_hart(idle.trailing_zeros() as usize);
+}
+
+/// Wake up the hart if it is idle.
+fn wake_hart(hart_id: usize) {
+    if IDLE_HARTS.fetch_and(!(1 << hart_id), Ordering::AcqRel) & (1 << hart_id) != 0 {
+        call_interface!(ExecutorIf::send_ipi(hart_id));
+    }
+}
+
+/// Fetch a task for the local hart.
+fn fetch() -> Option<Runnable> {
+    let hart_id = local_hart_id();
+    let queue = &HART_QUEUES[hart_id];
+    let runnable = fetch_from(hart_id, queue);
+    if runnable.is_some() {
+        queue.fetched.fetch_add(1, Ordering::Relaxed);
+    }
+    runnable
+}
+
+/// Tasks are fetched from the local queues, then the injector, and stolen from
+/// other harts at last.
+fn fetch_from(hart_id: usize, queue: &HartQueue) -> Option<Runnable> {
+    loop {
+        let runnable = fetch_any(hart_id, queue)?;
+        if runnable.metadata().allows(hart_id) {
+            return Some(runnable);
+        }
+        // the affinity of the task has been changed after it was pushed
+        let target = &HART_QUEUES[allowed_hart(runnable.metadata())];
+        target.push_normal(runnable);
+    }
+}
+
+fn fetch_any(hart_id: usize, queue: &HartQueue) -> Option<Runnable> {
+    if queue.fetched.load(Ordering::Relaxed) % INJECTOR_INTERVAL == 0 {
+        if let Some(runnable) = INJECTOR.lock().pop_front() {
+            return Some(runnable);
+        }
+    }
+    if let Some(runnable) = queue.fetch() {
+        return Some(runnable);
+    }
+    if let Some(runnable) = INJECTOR.lock().pop_front() {
+        return Some(runnable);
+    }
+    for i in 1..MAX_HARTS {
+        let victim = &HART_QUEUES[(hart_id + i) % MAX_HARTS];
+        let mut stolen = victim.steal(hart_id).into_iter();
+        if let Some(runnable) = stolen.next() {
+            queue.stolen.fetch_add(stolen.len() + 1, Ordering::Relaxed);
+            queue.normal.lock().extend(stolen);
+            return Some(runnable);
+        }
+    }
+    None
+}
+
 /// Add a task into task queue
-pub fn spawn<F>(future: F) -> (Runnable, Task<F::Output>)
+pub fn spawn<F>(future: F) -> (Runnable, TaskHandle<F::Output>)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    spawn_with_attr(future, Arc::new(SchedAttr::new()))
+}
+
+/// Add a task into task queue, which is scheduled according to `attr`
+pub fn spawn_with_attr<F>(future: F, attr: Arc<SchedAttr>) -> (Runnable, TaskHandle<F::Output>)
 where
     F: Future + Send + 'static,
     F::Output: Send + 'static,
 {
     let schedule = move |runnable: Runnable, info: ScheduleInfo| {
-        if info.woken_while_running {
-            // i.e `yield_now()`
-            TASK_QUEUE.push_normal(runnable);
-        } else {
-            // i.e. woken up by some signal
-            TASK_QUEUE.push_prior(runnable);
-        }
+        // `woken_while_running` i.e. `yield_now()`, otherwise woken up by some
+        // signal
+        push_task(runnable, !info.woken_while_running);
     };
-    async_task::spawn(future, WithInfo(schedule))
+    Builder::new()
+        .metadata(attr)
+        .spawn(move |_| future, WithInfo(schedule))
+}
+
+/// Poll the task until it is suspended, when it must not hold any spin lock.
+fn run(task: Runnable) {
+    task.run();
+    sync::mutex::check_suspend();
 }
 
 pub fn run_until_idle() -> usize {
+    ACTIVE_HARTS.fetch_or(1 << local_hart_id(), Ordering::Relaxed);
     let mut len = 0;
-    while let Some(task) = TASK_QUEUE.fetch() {
-        task.run();
+    while let Some(task) = fetch() {
+        run(task);
         len += 1
     }
     len
 }
 
 pub fn run_one() {
-    if let Some(task) = TASK_QUEUE.fetch() {
-        task.run();
+    if let Some(task) = fetch() {
+        run(task);
     }
 }
 
 pub fn run_prior_until_idle() {
-    while let Some(task) = TASK_QUEUE.fetch_prior() {
-        task.run();
+    while let Some(task) = local_queue().fetch_prior() {
+        run(task);
     }
 }
 
+/// Mark the local hart as idle so that it will be woken up by an IPI when
+/// tasks are pushed by other harts. Return false if there are tasks that can be
+/// fetched, in which case the hart should not wait for interrupts.
+///
+/// Interrupts should be disabled to avoid missing the IPI.
+pub fn enter_idle() -> bool {
+    IDLE_HARTS.fetch_or(1 << local_hart_id(), Ordering::AcqRel);
+    if has_fetchable_task() {
+        leave_idle();
+        return false;
+    }
+    true
+}
+
+pub fn leave_idle() {
+    IDLE_HARTS.fetch_and(!(1 << local_hart_id()), Ordering::AcqRel);
+}
+
+/// Whether all other harts running the executor are idle.
+pub fn other_harts_idle() -> bool {
+    let active = ACTIVE_HARTS.load(Ordering::Relaxed) & !(1 << local_hart_id());
+    IDLE_HARTS.load(Ordering::Relaxed) & active == active
+}
+
+/// Tasks woken up by the local hart meanwhile are pushed into the injector
+/// instead of its own queues.
+pub fn enter_irq() {
+    local_queue().in_irq.store(true, Ordering::Relaxed);
+}
+
+pub fn leave_irq() {
+    local_queue().in_irq.store(false, Ordering::Relaxed);
+}
+
+pub fn in_irq() -> bool {
+    local_queue().in_irq.load(Ordering::Relaxed)
+}
+
+/// Whether there are tasks that can be fetched by the local hart without
+/// stealing.
 pub fn has_task() -> bool {
-    TASK_QUEUE.len() >= 1
+    local_queue().len() >= 1 || !INJECTOR.lock().is_empty()
+}
+
+/// Whether there are tasks that the local hart can fetch, i.e. those in its
+/// own queues or the injector, and those of other harts that it is allowed to
+/// steal. Tasks that only other harts may run are not counted.
+fn has_fetchable_task() -> bool {
+    let hart_id = local_hart_id();
+    has_task()
+        || HART_QUEUES
+            .iter()
+            .enumerate()
+            .any(|(i, queue)| i != hart_id && queue.has_allowed(hart_id))
 }
 
 pub fn has_prior_task() -> bool {
-    TASK_QUEUE.prior_len() >= 1
+    local_queue().prior_len() >= 1
 }
 
 pub fn task_len() -> usize {
-    TASK_QUEUE.len()
+    HART_QUEUES.iter().map(|queue| queue.len()).sum::<usize>() + INJECTOR.lock().len()
+}
+
+/// Number of tasks that are runnable, i.e. waiting in queues or running on
+/// harts that are not idle.
+pub fn nr_running() -> usize {
+    let busy = ACTIVE_HARTS.load(Ordering::Relaxed) & !IDLE_HARTS.load(Ordering::Relaxed);
+    task_len() + busy.count_ones() as usize
+}
+
+/// Number of tasks fetched and stolen by the hart.
+pub fn queue_stats(hart_id: usize) -> (usize, usize) {
+    let queue = &HART_QUEUES[hart_id];
+    (
+        queue.fetched.load(Ordering::Relaxed),
+        queue.stolen.load(Ordering::Relaxed),
+    )
 }
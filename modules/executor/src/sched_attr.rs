@@ -0,0 +1,97 @@
+use core::sync::atomic::{AtomicI32, AtomicU64, AtomicUsize, Ordering};
+
+/// Weight of nice 0.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// Weights of nice -20..=19, where each nice level differs by about 10% of cpu
+/// time, the same as Linux.
+const NICE_TO_WEIGHT: [u64; 40] = [
+    // -20
+    88761, 71755, 56483, 46273, 36291, // -15
+    29154, 23254, 18705, 14949, 11916, // -10
+    9548, 7620, 6100, 4904, 3906, // -5
+    3121, 2501, 1991, 1586, 1277, // 0
+    1024, 820, 655, 526, 423, // 5
+    335, 272, 215, 172, 137, // 10
+    110, 87, 70, 56, 45, // 15
+    36, 29, 23, 18, 15,
+];
+
+pub const NICE_MIN: i32 = -20;
+pub const NICE_MAX: i32 = 19;
+
+/// Scheduling attributes of a task, shared between the task and the executor.
+pub struct SchedAttr {
+    /// The task is always scheduled into the prior queue while it is nonzero,
+    /// e.g. it owns priority-inheritance locks that others are waiting for.
+    boost: AtomicUsize,
+    /// Bitmask of harts that the task is allowed to run on.
+    affinity: AtomicUsize,
+    /// Nice value in `NICE_MIN..=NICE_MAX`, lower nice gets more cpu time.
+    nice: AtomicI32,
+    /// Cpu time consumed by the task weighted by its nice. Among tasks in the
+    /// normal queue, the one with the least virtual runtime runs first.
+    vruntime: AtomicU64,
+}
+
+impl SchedAttr {
+    pub const fn new() -> Self {
+        Self {
+            boost: AtomicUsize::new(0),
+            affinity: AtomicUsize::new(usize::MAX),
+            nice: AtomicI32::new(0),
+            vruntime: AtomicU64::new(0),
+        }
+    }
+
+    pub fn boost(&self) {
+        self.boost.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn unboost(&self) {
+        self.boost.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn is_boosted(&self) -> bool {
+        self.boost.load(Ordering::Relaxed) != 0
+    }
+
+    pub fn affinity(&self) -> usize {
+        self.affinity.load(Ordering::Relaxed)
+    }
+
+    pub fn set_affinity(&self, affinity: usize) {
+        self.affinity.store(affinity, Ordering::Relaxed);
+    }
+
+    pub fn allows(&self, hart_id: usize) -> bool {
+        self.affinity() & (1 << hart_id) != 0
+    }
+
+    pub fn nice(&self) -> i32 {
+        self.nice.load(Ordering::Relaxed)
+    }
+
+    pub fn set_nice(&self, nice: i32) {
+        self.nice
+            .store(nice.clamp(NICE_MIN, NICE_MAX), Ordering::Relaxed);
+    }
+
+    pub fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    /// Charge one tick of cpu time to the task.
+    pub fn tick(&self) {
+        let weight = NICE_TO_WEIGHT[(self.nice() - NICE_MIN) as usize];
+        self.vruntime
+            .fetch_add(NICE_0_WEIGHT * NICE_0_WEIGHT / weight, Ordering::Relaxed);
+    }
+
+    /// Catch up with `min_vruntime` of the queue that the task is pushed into,
+    /// so that a task that has slept for a long time will not monopolize the
+    /// hart.
+    pub fn place(&self, min_vruntime: u64) {
+        self.vruntime.fetch_max(min_vruntime, Ordering::Relaxed);
+    }
+}
@@ -1,21 +1,38 @@
-use alloc::{ffi::CString, sync::Arc, vec, vec::Vec};
+use alloc::{ffi::CString, format, sync::Arc, vec, vec::Vec};
 use core::fmt::Error;
 
 use lwext4_rust::{
-    bindings::EEXIST, lwext4_check_inode_exist, lwext4_link, lwext4_mvdir, lwext4_mvfile,
-    lwext4_readlink, lwext4_rmdir, lwext4_rmfile, lwext4_symlink, InodeTypes,
+    bindings::{ext4_mknod, EEXIST},
+    lwext4_check_inode_exist, lwext4_link, lwext4_mvdir, lwext4_mvfile, lwext4_readlink,
+    lwext4_rmdir, lwext4_rmfile, lwext4_symlink, InodeTypes,
 };
 use systype::{SysError, SysResult};
 use vfs_core::{
-    Dentry, DentryMeta, DentryState, File, FileSystemType, FileSystemTypeMeta, Inode, InodeMode,
-    InodeType, MountFlags, OpenFlags, Path, RenameFlags, StatFs, SuperBlock, SuperBlockMeta,
+    new_fifo_inode, Dentry, DentryMeta, DentryState, File, FileSystemType, FileSystemTypeMeta,
+    Inode, InodeMode, InodeType, MountFlags, OpenFlags, Path, RenameFlags, StatFs, SuperBlock,
+    SuperBlockMeta,
 };
 
 use crate::{
-    file::Ext4FileFile, inode::Ext4FileInode, readlink, Ext4DirFile, Ext4DirInode, Ext4LinkFile,
-    Ext4LinkInode, LwExt4Dir, LwExt4File,
+    ext4_result, file::Ext4FileFile, inode::Ext4FileInode, load_attr, readlink, store_attr,
+    Ext4DirFile, Ext4DirInode, Ext4LinkFile, Ext4LinkInode, LwExt4Dir, LwExt4File,
 };
 
+/// Temporary names tried by `RENAME_EXCHANGE` before giving up.
+const MAX_EXCHANGE_TRIES: usize = 16;
+
+/// Whether anything exists at `path`, which lwext4 only checks per file type.
+fn path_exists(path: &str) -> bool {
+    [
+        InodeTypes::EXT4_DE_DIR,
+        InodeTypes::EXT4_DE_REG_FILE,
+        InodeTypes::EXT4_DE_SYMLINK,
+        InodeTypes::EXT4_DE_FIFO,
+    ]
+    .into_iter()
+    .any(|itype| lwext4_check_inode_exist(path, itype))
+}
+
 pub struct Ext4Dentry {
     meta: DentryMeta,
 }
@@ -77,29 +94,34 @@ impl Dentry for Ext4Dentry {
         let path = sub_dentry.path();
         if lwext4_check_inode_exist(&path, InodeTypes::EXT4_DE_DIR) {
             let new_file = LwExt4Dir::open(&path).map_err(SysError::from_i32)?;
-            sub_dentry.set_inode(Ext4DirInode::new(sb, new_file))
+            let new_inode: Arc<dyn Inode> = Ext4DirInode::new(sb, new_file);
+            load_attr(&path, &new_inode)?;
+            sub_dentry.set_inode(new_inode)
         } else if lwext4_check_inode_exist(&path, InodeTypes::EXT4_DE_REG_FILE) {
             let new_file =
                 LwExt4File::open(&path, OpenFlags::empty().bits()).map_err(SysError::from_i32)?;
-            sub_dentry.set_inode(Ext4FileInode::new(sb, new_file))
+            let new_inode: Arc<dyn Inode> = Ext4FileInode::new(sb, new_file);
+            load_attr(&path, &new_inode)?;
+            sub_dentry.set_inode(new_inode)
         } else if lwext4_check_inode_exist(&path, InodeTypes::EXT4_DE_SYMLINK) {
             let target = readlink(&sub_dentry.path())?;
             let sub_inode = Ext4LinkInode::new(target.to_str().unwrap(), sb);
             sub_dentry.set_inode(sub_inode)
+        } else if lwext4_check_inode_exist(&path, InodeTypes::EXT4_DE_FIFO) {
+            let new_inode = new_fifo_inode(sb);
+            load_attr(&path, &new_inode)?;
+            sub_dentry.set_inode(new_inode)
         }
         Ok(sub_dentry)
     }
 
     fn base_create(self: Arc<Self>, name: &str, mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
         let sb = self.super_block();
-        let inode = self
-            .inode()?
-            .downcast_arc::<Ext4DirInode>()
-            .unwrap_or_else(|_| unreachable!());
         let sub_dentry = self.into_dyn().get_child_or_create(name);
         let path = sub_dentry.path();
         log::debug!("[Ext4Dentry::base_create] path:{path}, mode:{mode:?}");
-        let mut dir = inode.dir.lock();
+        // lwext4 creates entries by path, so the directory handle, which is
+        // locked while `Ext4DirFile::base_load_dir` walks it, is not needed
         let new_inode: Arc<dyn Inode> = match mode.to_type() {
             InodeType::Dir => {
                 let new_dir = LwExt4Dir::create(&path).map_err(SysError::from_i32)?;
@@ -124,53 +146,84 @@ impl Dentry for Ext4Dentry {
         let path = sub_dentry.path();
         match sub_dentry.inode()?.itype() {
             InodeType::Dir => lwext4_rmdir(&path).map_err(SysError::from_i32),
-            InodeType::File | InodeType::SymLink => {
+            InodeType::File | InodeType::SymLink | InodeType::Fifo => {
                 lwext4_rmfile(&path).map_err(SysError::from_i32)
             }
             _ => todo!(),
         }
     }
 
+    fn base_mknod(self: Arc<Self>, name: &str, mode: InodeMode) -> SysResult<()> {
+        let itype = match mode.to_type() {
+            InodeType::Fifo => InodeTypes::EXT4_DE_FIFO,
+            _ => return Err(SysError::EPERM),
+        };
+        let path = self.into_dyn().get_child_or_create(name).path();
+        log::debug!("[Ext4Dentry::base_mknod] path:{path}, mode:{mode:?}");
+        let c_path = CString::new(path).map_err(|_| SysError::EINVAL)?;
+        unsafe { ext4_result(ext4_mknod(c_path.as_ptr(), itype as i32, 0)) }
+    }
+
     fn base_new_child(self: Arc<Self>, name: &str) -> Arc<dyn Dentry> {
         Self::new(name, self.super_block(), Some(self))
     }
 
+    fn base_write_attr(self: Arc<Self>) -> SysResult<()> {
+        let inode = self.inode()?;
+        // lwext4 does not follow symlinks, whose mode is always 0777 anyway
+        if inode.itype().is_symlink() {
+            return Ok(());
+        }
+        store_attr(&self.path(), &inode)
+    }
+
     fn base_rename_to(self: Arc<Self>, new: Arc<dyn Dentry>, flags: RenameFlags) -> SysResult<()> {
-        // TODO: lwext4_rust does not support RENAME_EXCHANGE, it remove old path when
-        // renaming
-        let old_itype = self.inode()?.itype();
-        if !new.is_negetive() {
-            let new_itype = new.inode()?.itype();
-            if new_itype != old_itype {
-                return match (old_itype, new_itype) {
-                    (InodeType::File, InodeType::Dir) => Err(SysError::EISDIR),
-                    (InodeType::Dir, InodeType::File) => Err(SysError::ENOTDIR),
-                    _ => unimplemented!(),
-                };
+        fn mv(from: &str, to: &str, itype: InodeType) -> SysResult<()> {
+            match itype {
+                InodeType::Dir => lwext4_mvdir(from, to),
+                _ => lwext4_mvfile(from, to),
             }
-            match new_itype {
-                InodeType::Dir => lwext4_rmdir(&new.path()),
-                InodeType::File => lwext4_rmfile(&new.path()),
-                InodeType::SymLink => todo!(),
-                _ => todo!(),
-            };
+            .map(|_| ())
+            .map_err(SysError::from_i32)
         }
-        match old_itype {
-            InodeType::Dir => {
-                lwext4_mvdir(&self.path(), &new.path());
+
+        let old_path = self.path();
+        let new_path = new.path();
+        let old_inode = self.inode()?;
+        if flags.contains(RenameFlags::RENAME_EXCHANGE) {
+            // lwext4 can not exchange two paths, so move the old one aside to an
+            // unused name first, and undo the moves done if a later one fails.
+            let new_inode = new.inode()?;
+            let tmp_path = (0..MAX_EXCHANGE_TRIES)
+                .map(|i| format!("{old_path}.exchange-{}-{i}", old_inode.ino()))
+                .find(|path| !path_exists(path))
+                .ok_or(SysError::EEXIST)?;
+            mv(&old_path, &tmp_path, old_inode.itype())?;
+            if let Err(e) = mv(&new_path, &old_path, new_inode.itype()) {
+                let _ = mv(&tmp_path, &old_path, old_inode.itype());
+                return Err(e);
             }
-            InodeType::File => {
-                lwext4_mvfile(&self.path(), &new.path());
+            if let Err(e) = mv(&tmp_path, &new_path, old_inode.itype()) {
+                let _ = mv(&old_path, &new_path, new_inode.itype())
+                    .and_then(|_| mv(&tmp_path, &old_path, old_inode.itype()));
+                return Err(e);
             }
-            InodeType::SymLink => todo!(),
-            _ => unimplemented!(),
+            self.set_inode(new_inode);
+            new.set_inode(old_inode);
+            return Ok(());
         }
-        new.set_inode(self.inode()?);
-        if flags.contains(RenameFlags::RENAME_EXCHANGE) {
-            self.set_inode(new.inode()?);
-        } else {
-            self.clear_inode();
+        // lwext4 removes the old path when renaming, but does not replace the new
+        // one, whose type and emptiness are checked by the caller.
+        if !new.is_negetive() {
+            match new.inode()?.itype() {
+                InodeType::Dir => lwext4_rmdir(&new_path),
+                _ => lwext4_rmfile(&new_path),
+            }
+            .map_err(SysError::from_i32)?;
         }
+        mv(&old_path, &new_path, old_inode.itype())?;
+        new.set_inode(old_inode);
+        self.clear_inode();
         Ok(())
     }
 
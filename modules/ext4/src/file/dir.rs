@@ -14,16 +14,20 @@ use lwext4_rust::{
     lwext4_readlink, InodeTypes,
 };
 use systype::{SysError, SysResult, SyscallResult};
-use vfs_core::{DirEntry, File, FileMeta, Inode, InodeType, OpenFlags};
+use vfs_core::{
+    new_fifo_inode, DentryState, DirEntry, File, FileMeta, Inode, InodeType, OpenFlags,
+};
 
 use crate::{
-    dentry::Ext4Dentry, inode::Ext4FileInode, map_ext4_type, readlink, Ext4DirInode, Ext4LinkInode,
-    LwExt4Dir, LwExt4File, Shared,
+    dentry::Ext4Dentry,
+    inode::{DirHandle, Ext4FileInode},
+    load_attr, map_ext4_type, readlink, AsyncShared, Ext4DirInode, Ext4LinkInode, LwExt4Dir,
+    LwExt4File,
 };
 
 pub struct Ext4DirFile {
     meta: FileMeta,
-    dir: Shared<LwExt4Dir>,
+    dir: AsyncShared<DirHandle>,
 }
 
 unsafe impl Send for Ext4DirFile {}
@@ -61,8 +65,8 @@ impl File for Ext4DirFile {
     }
 
     /// Load all dentry and inodes in a directory. Will not advance dir offset.
-    fn base_load_dir(&self) -> SysResult<()> {
-        let mut dir = self.dir.lock();
+    async fn base_load_dir(&self) -> SysResult<()> {
+        let mut dir = self.dir.lock().await;
         let iters = dir.lwext4_dir_entries(&self.dentry().path()).unwrap();
 
         // skip "." and ".."
@@ -82,13 +86,19 @@ impl File for Ext4DirFile {
             } else if InodeTypes::from(dirent.type_ as usize) == InodeTypes::EXT4_DE_DIR {
                 let ext4_dir = LwExt4Dir::open(&(sub_dentry.path())).map_err(SysError::from_i32)?;
                 Ext4DirInode::new(self.super_block(), ext4_dir).clone()
+            } else if InodeTypes::from(dirent.type_ as usize) == InodeTypes::EXT4_DE_FIFO {
+                new_fifo_inode(self.super_block())
             } else {
                 let target = readlink(&sub_dentry.path())?;
                 Ext4LinkInode::new(target.to_str().unwrap(), self.super_block()).clone()
             };
             if sub_dentry.is_negetive() {
+                if !new_inode.itype().is_symlink() {
+                    load_attr(&sub_dentry.path(), &new_inode)?;
+                }
                 sub_dentry.set_inode(new_inode);
             }
+            sub_dentry.set_state(DentryState::Sync);
         }
 
         Ok(())
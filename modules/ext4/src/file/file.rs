@@ -8,6 +8,7 @@ use alloc::{
 use core::{cmp, iter::zip};
 
 use async_trait::async_trait;
+use config::{board::BLOCK_SIZE, mm::PAGE_SIZE};
 use lwext4_rust::{
     bindings::{O_RDONLY, O_RDWR, SEEK_SET},
     lwext4_readlink, InodeTypes,
@@ -16,13 +17,14 @@ use systype::{SysError, SysResult, SyscallResult};
 use vfs_core::{DirEntry, File, FileMeta, Inode, InodeType, OpenFlags};
 
 use crate::{
-    dentry::Ext4Dentry, inode::Ext4FileInode, map_ext4_type, Ext4DirInode, Ext4LinkInode,
-    LwExt4Dir, LwExt4File, Shared,
+    dentry::Ext4Dentry,
+    inode::{Ext4FileInode, FileHandle},
+    map_ext4_type, AsyncShared, Ext4DirInode, Ext4LinkInode, LwExt4Dir, LwExt4File,
 };
 
 pub struct Ext4FileFile {
     meta: FileMeta,
-    file: Shared<LwExt4File>,
+    file: AsyncShared<FileHandle>,
 }
 
 unsafe impl Send for Ext4FileFile {}
@@ -46,7 +48,7 @@ impl File for Ext4FileFile {
     async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
         match self.itype() {
             InodeType::File => {
-                let mut file = self.file.lock();
+                let mut file = self.file.lock().await;
                 file.seek(offset as i64, SEEK_SET)
                     .map_err(SysError::from_i32)?;
                 file.read(buf).map_err(SysError::from_i32)
@@ -58,7 +60,23 @@ impl File for Ext4FileFile {
     async fn base_write_at(&self, offset: usize, buf: &[u8]) -> SyscallResult {
         match self.itype() {
             InodeType::File => {
-                let mut file = self.file.lock();
+                let mut file = self.file.lock().await;
+                // lwext4 can not seek beyond the end, so a gap left by the page
+                // cache is filled block by block with zeros
+                let size = file.size() as usize;
+                if offset > size {
+                    let zeros = [0u8; BLOCK_SIZE];
+                    file.seek(size as i64, SEEK_SET)
+                        .map_err(SysError::from_i32)?;
+                    let mut pos = size;
+                    while pos < offset {
+                        let len = cmp::min(BLOCK_SIZE - pos % BLOCK_SIZE, offset - pos);
+                        match file.write(&zeros[..len]).map_err(SysError::from_i32)? {
+                            0 => return Err(SysError::ENOSPC),
+                            n => pos += n,
+                        }
+                    }
+                }
                 file.seek(offset as i64, SEEK_SET)
                     .map_err(SysError::from_i32)?;
                 file.write(buf).map_err(SysError::from_i32)
@@ -71,12 +89,31 @@ impl File for Ext4FileFile {
         todo!()
     }
 
+    async fn base_is_hole(&self, offset_aligned: usize) -> SysResult<bool> {
+        let inode = self.inode();
+        // pages cached may not be written back and allocated yet
+        if inode
+            .page_cache()
+            .is_some_and(|page_cache| page_cache.get_page(offset_aligned).is_some())
+        {
+            return Ok(false);
+        }
+        let end = cmp::min(offset_aligned + PAGE_SIZE, self.size());
+        for offset in (offset_aligned..end).step_by(BLOCK_SIZE) {
+            // block index zero means no block is mapped
+            if inode.get_blk_idx(offset).await? != 0 {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
         Err(SysError::ENOTDIR)
     }
 
     /// Load all dentry and inodes in a directory. Will not advance dir offset.
-    fn base_load_dir(&self) -> SysResult<()> {
+    async fn base_load_dir(&self) -> SysResult<()> {
         Err(SysError::ENOTDIR)
     }
 }
@@ -54,7 +54,7 @@ impl File for Ext4LinkFile {
     }
 
     /// Load all dentry and inodes in a directory. Will not advance dir offset.
-    fn base_load_dir(&self) -> SysResult<()> {
+    async fn base_load_dir(&self) -> SysResult<()> {
         Err(SysError::ENOTDIR)
     }
 
@@ -1,14 +1,20 @@
-use alloc::sync::Arc;
+use alloc::{ffi::CString, sync::Arc};
 
 use device_core::BlockDevice;
-use lwext4_rust::{Ext4BlockWrapper, InodeTypes};
+use lwext4_rust::{
+    bindings::{ext4_cache_flush, ext4_mount_point_stats, ext4_mount_stats},
+    Ext4BlockWrapper, InodeTypes,
+};
 use systype::{SysError, SysResult};
 use vfs_core::{
-    Dentry, FileSystemType, FileSystemTypeMeta, InodeType, MountFlags, OpenFlags, StatFs,
+    Dentry, FileSystemType, FileSystemTypeMeta, Inode, InodeType, MountFlags, OpenFlags, StatFs,
     SuperBlock, SuperBlockMeta,
 };
 
-use crate::{disk::Disk, Ext4Dentry, Ext4DirInode, Ext4FileInode, LwExt4Dir, LwExt4File};
+use crate::{
+    disk::Disk, ext4_result, load_attr, Ext4Dentry, Ext4DirInode, Ext4FileInode, LwExt4Dir,
+    LwExt4File,
+};
 
 pub struct Ext4FsType {
     meta: FileSystemTypeMeta,
@@ -33,11 +39,19 @@ impl FileSystemType for Ext4FsType {
         parent: Option<Arc<dyn Dentry>>,
         _flags: MountFlags,
         dev: Option<Arc<dyn BlockDevice>>,
+        _data: &str,
     ) -> SysResult<Arc<dyn Dentry>> {
         debug_assert!(dev.is_some());
+        // lwext4 has a single mount point at "/", which the root filesystem
+        // takes, see `Ext4SuperBlock::new`
+        if !self.meta.supers.lock().is_empty() {
+            log::warn!("[Ext4FsType::base_mount] only one ext4 filesystem can be mounted");
+            return Err(SysError::EBUSY);
+        }
         let sb = Ext4SuperBlock::new(SuperBlockMeta::new(dev, self.clone()));
         let mut root_ext4_dir = LwExt4Dir::open("/").map_err(SysError::from_i32)?;
-        let root_inode = Ext4DirInode::new(sb.clone(), root_ext4_dir);
+        let root_inode: Arc<dyn Inode> = Ext4DirInode::new(sb.clone(), root_ext4_dir);
+        load_attr("/", &root_inode)?;
         let root_dentry = Ext4Dentry::new(name, sb.clone(), parent.clone()).into_dyn();
         root_dentry.set_inode(root_inode);
         if let Some(parent) = parent {
@@ -53,9 +67,16 @@ impl FileSystemType for Ext4FsType {
     }
 }
 
+const EXT4_SUPER_MAGIC: i64 = 0xef53;
+
+/// Mount point of the filesystem registered by `Ext4BlockWrapper::new`.
+const LWEXT4_MOUNT_POINT: &str = "/";
+
 pub struct Ext4SuperBlock {
     meta: SuperBlockMeta,
     inner: Ext4BlockWrapper<Disk>,
+    /// Mount point in lwext4, which queries of this filesystem are made on.
+    mount_point: CString,
 }
 
 unsafe impl Send for Ext4SuperBlock {}
@@ -67,7 +88,11 @@ impl Ext4SuperBlock {
         let disk = Disk::new(blk_dev);
         let inner =
             Ext4BlockWrapper::<Disk>::new(disk).expect("failed to initialize EXT4 filesystem");
-        Arc::new(Self { meta, inner })
+        Arc::new(Self {
+            meta,
+            inner,
+            mount_point: CString::new(LWEXT4_MOUNT_POINT).unwrap(),
+        })
     }
 }
 
@@ -77,10 +102,28 @@ impl SuperBlock for Ext4SuperBlock {
     }
 
     fn stat_fs(&self) -> SysResult<StatFs> {
-        todo!()
+        let mount_point = self.mount_point.as_ptr();
+        let mut stats: ext4_mount_stats = unsafe { core::mem::zeroed() };
+        unsafe { ext4_result(ext4_mount_point_stats(mount_point, &mut stats))? };
+        Ok(StatFs {
+            f_type: EXT4_SUPER_MAGIC,
+            f_bsize: stats.block_size as i64,
+            f_blocks: stats.blocks_count,
+            f_bfree: stats.free_blocks_count,
+            f_bavail: stats.free_blocks_count,
+            f_files: stats.inodes_count as u64,
+            f_ffree: stats.free_inodes_count as u64,
+            f_fsid: [0; 2],
+            f_namelen: 255,
+            f_frsize: stats.block_size as isize,
+            f_flags: 0,
+            f_spare: [0; 4],
+        })
     }
 
+    /// Write the blocks cached by lwext4 to the device. File data cached in
+    /// page caches should be written back before.
     fn sync_fs(&self, _wait: isize) -> systype::SysResult<()> {
-        todo!()
+        unsafe { ext4_result(ext4_cache_flush(self.mount_point.as_ptr())) }
     }
 }
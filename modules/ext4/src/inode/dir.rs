@@ -1,17 +1,38 @@
-use alloc::sync::Arc;
+use alloc::{boxed::Box, sync::Arc};
+use core::ops::{Deref, DerefMut};
 
+use async_trait::async_trait;
 use lwext4_rust::{
     bindings::{ext4_flink, O_RDONLY, SEEK_CUR, SEEK_SET},
     InodeTypes,
 };
+use sync::mutex::AsyncMutex;
 use systype::{SysError, SysResult};
 use vfs_core::{Inode, InodeMeta, InodeMode, InodeType, Stat, SuperBlock};
 
-use crate::{map_ext4_err, map_ext4_type, LwExt4Dir, LwExt4File, Mutex, Shared};
+use crate::{map_ext4_err, map_ext4_type, AsyncShared, LwExt4Dir, LwExt4File};
 
 pub struct Ext4DirInode {
     meta: InodeMeta,
-    pub(crate) dir: Shared<LwExt4Dir>,
+    pub(crate) dir: AsyncShared<DirHandle>,
+}
+
+/// Lwext4 directory, which is sent between harts like `FileHandle`.
+pub(crate) struct DirHandle(LwExt4Dir);
+
+unsafe impl Send for DirHandle {}
+
+impl Deref for DirHandle {
+    type Target = LwExt4Dir;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for DirHandle {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
 }
 
 unsafe impl Send for Ext4DirInode {}
@@ -21,12 +42,13 @@ impl Ext4DirInode {
     pub fn new(super_block: Arc<dyn SuperBlock>, dir: LwExt4Dir) -> Arc<Self> {
         let inode = Arc::new(Self {
             meta: InodeMeta::new(InodeMode::from_type(InodeType::Dir), super_block.clone(), 0),
-            dir: Arc::new(Mutex::new(dir)),
+            dir: Arc::new(AsyncMutex::new(DirHandle(dir))),
         });
         inode
     }
 }
 
+#[async_trait]
 impl Inode for Ext4DirInode {
     fn meta(&self) -> &InodeMeta {
         &self.meta
@@ -34,15 +56,15 @@ impl Inode for Ext4DirInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: inner.nlink as _,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -56,11 +78,11 @@ impl Inode for Ext4DirInode {
         })
     }
 
-    fn base_truncate(&self, len: usize) -> SysResult<()> {
+    async fn base_truncate(&self, len: usize) -> SysResult<()> {
         Err(SysError::EINVAL)
     }
 
-    fn base_get_blk_idx(&self, offset: usize) -> SysResult<usize> {
+    async fn base_get_blk_idx(&self, offset: usize) -> SysResult<usize> {
         Err(SysError::EINVAL)
     }
 }
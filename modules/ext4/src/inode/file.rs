@@ -1,17 +1,40 @@
-use alloc::sync::Arc;
+use alloc::{boxed::Box, sync::Arc};
+use core::ops::{Deref, DerefMut};
 
+use async_trait::async_trait;
 use lwext4_rust::{
     bindings::{ext4_flink, O_RDONLY, SEEK_CUR, SEEK_SET},
     InodeTypes,
 };
+use sync::mutex::AsyncMutex;
 use systype::{SysError, SysResult};
 use vfs_core::{Inode, InodeMeta, InodeMode, InodeType, Stat, SuperBlock};
 
-use crate::{map_ext4_err, map_ext4_type, LwExt4Dir, LwExt4File, Mutex, Shared};
+use crate::{map_ext4_err, map_ext4_type, AsyncShared, LwExt4Dir, LwExt4File};
 
 pub struct Ext4FileInode {
     meta: InodeMeta,
-    pub(crate) file: Shared<LwExt4File>,
+    pub(crate) file: AsyncShared<FileHandle>,
+}
+
+/// Lwext4 file, which holds raw pointers of the C library but is only accessed
+/// with its lock held, so it can be sent between harts while the lock is
+/// awaited.
+pub(crate) struct FileHandle(LwExt4File);
+
+unsafe impl Send for FileHandle {}
+
+impl Deref for FileHandle {
+    type Target = LwExt4File;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for FileHandle {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
 }
 
 unsafe impl Send for Ext4FileInode {}
@@ -28,12 +51,13 @@ impl Ext4FileInode {
                 super_block.clone(),
                 size,
             ),
-            file: Arc::new(Mutex::new(file)),
+            file: Arc::new(AsyncMutex::new(FileHandle(file))),
         });
         inode
     }
 }
 
+#[async_trait]
 impl Inode for Ext4FileInode {
     fn meta(&self) -> &InodeMeta {
         &self.meta
@@ -41,15 +65,15 @@ impl Inode for Ext4FileInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: inner.nlink as _,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -63,13 +87,13 @@ impl Inode for Ext4FileInode {
         })
     }
 
-    fn base_truncate(&self, len: usize) -> SysResult<()> {
-        self.file.lock().truncate(len as u64);
+    async fn base_truncate(&self, len: usize) -> SysResult<()> {
+        self.file.lock().await.truncate(len as u64);
         Ok(())
     }
 
-    fn base_get_blk_idx(&self, offset: usize) -> SysResult<usize> {
-        let mut file = self.file.lock();
+    async fn base_get_blk_idx(&self, offset: usize) -> SysResult<usize> {
+        let mut file = self.file.lock().await;
         let origin_offset = file.tell();
         file.seek(offset as i64, SEEK_SET);
         let blk_idx = file.file_get_blk_idx().unwrap();
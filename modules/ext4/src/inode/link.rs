@@ -1,5 +1,6 @@
-use alloc::sync::Arc;
+use alloc::{boxed::Box, sync::Arc};
 
+use async_trait::async_trait;
 use config::board::BLOCK_MASK;
 use lwext4_rust::{
     bindings::{ext4_flink, O_RDONLY, SEEK_CUR, SEEK_SET},
@@ -30,6 +31,7 @@ impl Ext4LinkInode {
     }
 }
 
+#[async_trait]
 impl Inode for Ext4LinkInode {
     fn meta(&self) -> &InodeMeta {
         &self.meta
@@ -37,15 +39,15 @@ impl Inode for Ext4LinkInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: inner.nlink as _,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -59,11 +61,11 @@ impl Inode for Ext4LinkInode {
         })
     }
 
-    fn base_truncate(&self, len: usize) -> SysResult<()> {
+    async fn base_truncate(&self, len: usize) -> SysResult<()> {
         Err(SysError::EINVAL)
     }
 
-    fn base_get_blk_idx(&self, offset: usize) -> SysResult<usize> {
+    async fn base_get_blk_idx(&self, offset: usize) -> SysResult<usize> {
         Err(SysError::EINVAL)
     }
 }
@@ -3,11 +3,14 @@
 
 use alloc::{ffi::CString, string::String, sync::Arc, vec};
 
-use lwext4_rust::lwext4_readlink;
+use lwext4_rust::{
+    bindings::{ext4_mode_get, ext4_mode_set, ext4_owner_get, ext4_owner_set},
+    lwext4_readlink,
+};
 pub(crate) use lwext4_rust::{Ext4Dir as LwExt4Dir, Ext4File as LwExt4File, InodeTypes};
-use sync::mutex::SpinNoIrqLock;
+use sync::mutex::{AsyncMutex, SpinNoIrqLock};
 use systype::{SysError, SysResult};
-use vfs_core::{Inode, InodeType};
+use vfs_core::{Inode, InodeMode, InodeType};
 
 extern crate alloc;
 
@@ -25,6 +28,10 @@ pub use inode::*;
 type Mutex<T> = SpinNoIrqLock<T>;
 type Shared<T> = Arc<Mutex<T>>;
 
+/// Lwext4 files and directories are locked by `AsyncMutex` since they are held
+/// across disk io.
+type AsyncShared<T> = Arc<AsyncMutex<T>>;
+
 fn new_shared<T>(val: T) -> Shared<T> {
     Arc::new(Mutex::new(val))
 }
@@ -41,6 +48,7 @@ pub fn map_ext4_type(value: InodeTypes) -> InodeType {
         InodeTypes::EXT4_DE_REG_FILE => InodeType::File,
         InodeTypes::EXT4_DE_DIR => InodeType::Dir,
         InodeTypes::EXT4_DE_SYMLINK => InodeType::SymLink,
+        InodeTypes::EXT4_DE_FIFO => InodeType::Fifo,
         other => unimplemented!("{:?}", other),
     }
 }
@@ -51,3 +59,35 @@ pub(crate) fn readlink(path: &str) -> SysResult<CString> {
     path_buf.truncate(len + 1);
     CString::from_vec_with_nul(path_buf).map_err(|_| SysError::EINVAL)
 }
+
+fn ext4_result(ret: i32) -> SysResult<()> {
+    match ret {
+        0 => Ok(()),
+        err => Err(SysError::from_i32(err)),
+    }
+}
+
+/// Read the mode and owner of the inode at `path` from disk into `inode`, which
+/// is created with default ones.
+pub(crate) fn load_attr(path: &str, inode: &Arc<dyn Inode>) -> SysResult<()> {
+    let c_path = CString::new(path).map_err(|_| SysError::EINVAL)?;
+    let (mut mode, mut uid, mut gid) = (0, 0, 0);
+    unsafe {
+        ext4_result(ext4_mode_get(c_path.as_ptr(), &mut mode))?;
+        ext4_result(ext4_owner_get(c_path.as_ptr(), &mut uid, &mut gid))?;
+    }
+    inode.set_mode(InodeMode::from_bits_truncate(mode));
+    inode.set_owner(uid, gid);
+    Ok(())
+}
+
+/// Write the mode and owner of `inode` to the inode at `path` on disk.
+pub(crate) fn store_attr(path: &str, inode: &Arc<dyn Inode>) -> SysResult<()> {
+    let c_path = CString::new(path).map_err(|_| SysError::EINVAL)?;
+    let (uid, gid) = inode.owner();
+    unsafe {
+        // lwext4 sets the whole mode, including the file type
+        ext4_result(ext4_mode_set(c_path.as_ptr(), inode.mode().bits()))?;
+        ext4_result(ext4_owner_set(c_path.as_ptr(), uid, gid))
+    }
+}
@@ -1,7 +1,7 @@
 use alloc::sync::Arc;
 
 use systype::{SysError, SysResult};
-use vfs_core::{Dentry, DentryMeta, Inode, InodeType, SuperBlock};
+use vfs_core::{Dentry, DentryMeta, Inode, InodeMode, InodeType, SuperBlock};
 
 use crate::{
     as_sys_err,
@@ -124,6 +124,11 @@ impl Dentry for FatDentry {
         Ok(())
     }
 
+    /// FAT has no special files.
+    fn base_mknod(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<()> {
+        Err(SysError::EPERM)
+    }
+
     fn base_new_child(self: Arc<Self>, name: &str) -> Arc<dyn Dentry> {
         Self::new(name, self.super_block(), Some(self))
     }
@@ -2,7 +2,7 @@ use alloc::{boxed::Box, sync::Arc};
 
 use async_trait::async_trait;
 use systype::{SysError, SysResult, SyscallResult};
-use vfs_core::{DirEntry, File, FileMeta, Inode, SeekFrom};
+use vfs_core::{DirEntry, File, FileMeta, Inode};
 
 use crate::{
     dentry::FatDentry,
@@ -53,7 +53,7 @@ impl File for FatDirFile {
             return Err(SysError::EIO);
         };
         let name = entry.file_name();
-        self.seek(SeekFrom::Current(1))?;
+        self.set_pos(self.pos() + 1);
         let sub_dentry = self.dentry().get_child_or_create(&name);
         let new_inode: Arc<dyn Inode> = if entry.is_dir() {
             let new_dir = entry.to_dir();
@@ -73,7 +73,7 @@ impl File for FatDirFile {
         Ok(Some(entry))
     }
 
-    fn base_load_dir(&self) -> SysResult<()> {
+    async fn base_load_dir(&self) -> SysResult<()> {
         let mut iter = self.dir.lock().iter();
         while let Some(entry) = iter.next() {
             let Ok(entry) = entry else {
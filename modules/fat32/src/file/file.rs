@@ -70,6 +70,10 @@ impl File for FatFileFile {
                 }
                 file.write_all(buf).map_err(as_sys_err)?;
                 if offset + buf.len() > size {
+                    // the size in the directory entry is only written by a
+                    // flush, which may not happen before the filesystem is
+                    // unmounted since the file is held by the inode
+                    file.flush().map_err(as_sys_err)?;
                     let new_size = offset + buf.len();
                     self.inode().set_size(new_size);
                 }
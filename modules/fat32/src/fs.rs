@@ -1,6 +1,7 @@
 use alloc::sync::Arc;
 
 use device_core::BlockDevice;
+use systype::{SysError, SysResult};
 use vfs_core::{Dentry, FileSystemType, FileSystemTypeMeta, StatFs, SuperBlock, SuperBlockMeta};
 
 use crate::{as_sys_err, dentry::FatDentry, inode::dir::FatDirInode, DiskCursor, FatFs};
@@ -28,9 +29,10 @@ impl FileSystemType for FatFsType {
         parent: Option<Arc<dyn Dentry>>,
         _flags: vfs_core::MountFlags,
         dev: Option<Arc<dyn BlockDevice>>,
+        _data: &str,
     ) -> systype::SysResult<Arc<dyn vfs_core::Dentry>> {
         debug_assert!(dev.is_some());
-        let sb = FatSuperBlock::new(SuperBlockMeta::new(dev, self.clone()));
+        let sb = FatSuperBlock::new(SuperBlockMeta::new(dev, self.clone()))?;
         let root_inode = FatDirInode::new(sb.clone(), sb.fs.root_dir());
         let root_dentry = FatDentry::new(name, sb.clone(), parent.clone()).into_dyn();
         root_dentry.set_inode(root_inode);
@@ -53,22 +55,25 @@ pub struct FatSuperBlock {
 }
 
 impl FatSuperBlock {
-    pub fn new(meta: SuperBlockMeta) -> Arc<Self> {
+    /// Fails if there is no FAT filesystem on the device.
+    pub fn new(meta: SuperBlockMeta) -> SysResult<Arc<Self>> {
         let blk_dev = meta.device.as_ref().unwrap().clone();
-        Arc::new(Self {
+        let fs = FatFs::new(
+            DiskCursor {
+                sector: 0,
+                offset: 0,
+                blk_dev,
+            },
+            fatfs::FsOptions::new(),
+        )
+        .map_err(|err| {
+            log::warn!("[FatSuperBlock::new] no FAT filesystem: {err:?}");
+            SysError::EINVAL
+        })?;
+        Ok(Arc::new(Self {
             meta,
-            fs: Arc::new(
-                FatFs::new(
-                    DiskCursor {
-                        sector: 0,
-                        offset: 0,
-                        blk_dev,
-                    },
-                    fatfs::FsOptions::new(),
-                )
-                .unwrap(),
-            ),
-        })
+            fs: Arc::new(fs),
+        }))
     }
 }
 
@@ -101,7 +106,9 @@ impl SuperBlock for FatSuperBlock {
         })
     }
 
+    /// Blocks are written to the device as soon as files are written, see
+    /// `FatFileFile::base_write_at`, so there is nothing to write here.
     fn sync_fs(&self, _wait: isize) -> systype::SysResult<()> {
-        todo!()
+        Ok(())
     }
 }
@@ -27,15 +27,15 @@ impl Inode for FatDirInode {
 
     fn get_attr(&self) -> systype::SysResult<vfs_core::Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -146,7 +146,11 @@ impl fatfs::Seek for DiskCursor {
                 self.set_position(i as usize);
                 Ok(i)
             }
-            fatfs::SeekFrom::End(_) => unreachable!(),
+            fatfs::SeekFrom::End(i) => {
+                let new_pos = (self.blk_dev.size() as i64) + i;
+                self.set_position(new_pos as usize);
+                Ok(new_pos as u64)
+            }
             fatfs::SeekFrom::Current(i) => {
                 let new_pos = (self.get_position() as i64) + i;
                 // log::debug!("Seek, current {new_pos}",);
@@ -1,27 +1,71 @@
 #![no_std]
 #![no_main]
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::{
+    cmp,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
 use crate_interface::call_interface;
 use log::{Level, LevelFilter, Log, Metadata, Record};
 
 pub static mut LOG_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+/// Level of the logs printed, by `log=<level>` in the bootargs or `LOG` at
+/// build time, unless the module is given its own level.
+static LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
 pub fn init() {
     static LOGGER: SimpleLogger = SimpleLogger;
     log::set_logger(&LOGGER).unwrap();
-    log::set_max_level(match option_env!("LOG") {
-        Some("error") => LevelFilter::Error,
-        Some("warn") => LevelFilter::Warn,
-        Some("info") => LevelFilter::Info,
-        Some("debug") => LevelFilter::Debug,
-        Some("trace") => LevelFilter::Trace,
-        _ => LevelFilter::Off,
-    });
+    let level = config::cmdline()
+        .get("log")
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(match option_env!("LOG") {
+            Some("error") => LevelFilter::Error,
+            Some("warn") => LevelFilter::Warn,
+            Some("info") => LevelFilter::Info,
+            Some("debug") => LevelFilter::Debug,
+            Some("trace") => LevelFilter::Trace,
+            _ => LevelFilter::Off,
+        });
+    LEVEL.store(level as usize, Ordering::Relaxed);
+    // a module may print logs the others do not
+    let max_level = module_levels()
+        .map(|(_, level)| level)
+        .fold(level, cmp::max);
+    log::set_max_level(max_level);
     unsafe { LOG_INITIALIZED.store(true, Ordering::SeqCst) };
 }
 
+/// Levels given by `log.<module>=<level>` in the bootargs, e.g.
+/// `log.kernel::syscall=info`, where the module is a prefix of the targets of
+/// logs.
+fn module_levels() -> impl Iterator<Item = (&'static str, LevelFilter)> {
+    config::cmdline().iter().filter_map(|(key, value)| {
+        let module = key.strip_prefix("log.")?;
+        Some((module, value.parse().ok()?))
+    })
+}
+
+/// Level of the logs of `target`, which is given by the longest module that
+/// `target` is in.
+fn level_of(target: &str) -> LevelFilter {
+    module_levels()
+        .filter(|(module, _)| {
+            target
+                .strip_prefix(module)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+        .max_by_key(|(module, _)| module.len())
+        .map(|(_, level)| level)
+        .unwrap_or_else(|| {
+            LevelFilter::iter()
+                .nth(LEVEL.load(Ordering::Relaxed))
+                .unwrap()
+        })
+}
+
 /// Add escape sequence to print with color in linux console
 // #[macro_export]
 // macro_rules! with_color {
@@ -45,8 +89,8 @@ pub trait LogIf: Send + Sync {
 struct SimpleLogger;
 
 impl Log for SimpleLogger {
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= level_of(metadata.target())
     }
     fn log(&self, record: &Record) {
         if !self.enabled(record.metadata()) {
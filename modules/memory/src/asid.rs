@@ -0,0 +1,187 @@
+//! Address space identifiers, which tag the TLB entries of each page table, so
+//! that switching page tables needs no flush.
+//!
+//! ASIDs are handed out in generations. An ASID is never recycled within a
+//! generation, since any hart may still cache translations tagged with it,
+//! and once a generation runs out, the next one starts over, where every hart
+//! flushes its whole TLB before using any ASID of the new generation. A page
+//! table whose ASID is from an old generation gets a new one when switched to.
+//!
+//! On hardware that ignores ASIDs, every page table uses zero, and the TLB is
+//! flushed on every switch as if there were no ASIDs.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use config::board::MAX_HARTS;
+use crate_interface::call_interface;
+use riscv::register::satp;
+use sync::mutex::SpinNoIrqLock;
+
+/// Offset of the ASID field in satp.
+const SATP_ASID_SHIFT: usize = 44;
+/// Width of the ASID field in satp on sv39, of which the hardware may
+/// implement fewer bits.
+const SATP_ASID_BITS: usize = 16;
+
+/// Number of ASID bits implemented by the hardware.
+static ASID_BITS: AtomicUsize = AtomicUsize::new(0);
+
+#[crate_interface::def_interface]
+pub trait AsidIf {
+    fn hart_id() -> usize;
+}
+
+struct AsidAllocator {
+    generation: usize,
+    /// The next ASID to hand out in this generation, from one, since zero is
+    /// what every page table uses before ASIDs are probed.
+    next: usize,
+}
+
+static ALLOCATOR: SpinNoIrqLock<AsidAllocator> = SpinNoIrqLock::new(AsidAllocator {
+    generation: 1,
+    next: 1,
+});
+/// The generation of `ALLOCATOR`, which is read without the lock when the
+/// ASID assigned is still valid.
+static GENERATION: AtomicUsize = AtomicUsize::new(1);
+
+const HART_GENERATION_EACH: AtomicUsize = AtomicUsize::new(0);
+/// Generation whose ASIDs each hart has flushed its TLB for. Zero means that
+/// the TLB may hold anything, e.g. after satp is written behind the allocator.
+static HART_GENERATIONS: [AtomicUsize; MAX_HARTS] = [HART_GENERATION_EACH; MAX_HARTS];
+
+/// Probe the ASID bits implemented by the hardware, which are read back as
+/// ones after writing ones to them.
+pub fn init() {
+    let mask = (1 << SATP_ASID_BITS) - 1;
+    let bits = unsafe {
+        let old = satp::read().bits();
+        satp::write(old | (mask << SATP_ASID_SHIFT));
+        let probed = (satp::read().bits() >> SATP_ASID_SHIFT) & mask;
+        satp::write(old);
+        core::arch::riscv64::sfence_vma_all();
+        probed.count_ones() as usize
+    };
+    ASID_BITS.store(bits, Ordering::Relaxed);
+    log::info!("[asid] {bits} ASID bits supported");
+}
+
+/// Tell that the TLB of the local hart may hold translations tagged with any
+/// ASID, so that it is flushed as a whole on the next switch.
+pub fn invalidate_local() {
+    HART_GENERATIONS[call_interface!(AsidIf::hart_id())].store(0, Ordering::SeqCst);
+}
+
+/// How the TLB must be flushed when switching to a page table.
+enum Flush {
+    None,
+    Asid(usize),
+    All,
+}
+
+/// The ASID of a page table, along with the harts that may cache its
+/// translations.
+pub struct Asid {
+    /// Generation in the high bits and the ASID in the low bits, or zero if
+    /// none is assigned yet.
+    context: AtomicUsize,
+    /// Harts that have switched to the page table, whose TLB may still hold
+    /// its translations even after switching away.
+    harts: AtomicUsize,
+    /// Harts that must flush the ASID before switching to the page table
+    /// again, since the translations cached by them have been changed.
+    stale: AtomicUsize,
+}
+
+impl Asid {
+    pub const fn new() -> Self {
+        Self {
+            context: AtomicUsize::new(0),
+            harts: AtomicUsize::new(0),
+            stale: AtomicUsize::new(0),
+        }
+    }
+
+    /// Bitmask of harts that may cache translations of the page table.
+    pub fn harts(&self) -> usize {
+        self.harts.load(Ordering::SeqCst)
+    }
+
+    /// Have the hart flush the translations of the page table before it
+    /// switches to it again, instead of flushing them right away.
+    pub fn mark_stale(&self, hart_id: usize) {
+        self.stale.fetch_or(1 << hart_id, Ordering::SeqCst);
+    }
+
+    /// Get an ASID of the current generation, which is allocated if the one
+    /// assigned is from an old generation.
+    fn current(&self) -> usize {
+        let context = self.context.load(Ordering::SeqCst);
+        if context >> SATP_ASID_BITS == GENERATION.load(Ordering::SeqCst) {
+            return context;
+        }
+        let mut allocator = ALLOCATOR.lock();
+        // may be allocated by another hart meanwhile
+        let context = self.context.load(Ordering::SeqCst);
+        if context >> SATP_ASID_BITS == allocator.generation {
+            return context;
+        }
+        if allocator.next >= 1 << ASID_BITS.load(Ordering::Relaxed) {
+            allocator.generation += 1;
+            allocator.next = 1;
+            GENERATION.store(allocator.generation, Ordering::SeqCst);
+            log::info!("[asid] rollover to generation {}", allocator.generation);
+        }
+        let context = (allocator.generation << SATP_ASID_BITS) | allocator.next;
+        allocator.next += 1;
+        self.context.store(context, Ordering::SeqCst);
+        context
+    }
+
+    /// Get the ASID to switch to the page table on the local hart, and how the
+    /// TLB must be flushed.
+    fn activate(&self) -> (usize, Flush) {
+        let hart_id = call_interface!(AsidIf::hart_id());
+        let bit = 1 << hart_id;
+        self.harts.fetch_or(bit, Ordering::SeqCst);
+        let stale = self.stale.fetch_and(!bit, Ordering::SeqCst) & bit != 0;
+        if ASID_BITS.load(Ordering::Relaxed) == 0 {
+            return (0, Flush::All);
+        }
+        let context = self.current();
+        let (generation, asid) = (
+            context >> SATP_ASID_BITS,
+            context & ((1 << SATP_ASID_BITS) - 1),
+        );
+        let flushed = HART_GENERATIONS[hart_id].swap(generation, Ordering::SeqCst);
+        let flush = if flushed != generation {
+            Flush::All
+        } else if stale {
+            Flush::Asid(asid)
+        } else {
+            Flush::None
+        };
+        (asid, flush)
+    }
+
+    /// Write satp with `token` and the ASID, and flush the TLB in need.
+    ///
+    /// # Safety
+    ///
+    /// `token` must be of the page table owning this ASID.
+    pub unsafe fn switch(&self, token: usize) {
+        let (asid, flush) = self.activate();
+        satp::write(token | (asid << SATP_ASID_SHIFT));
+        match flush {
+            Flush::None => {}
+            Flush::Asid(asid) => core::arch::riscv64::sfence_vma_asid(asid),
+            Flush::All => core::arch::riscv64::sfence_vma_all(),
+        }
+    }
+}
+
+/// The ASID the local hart is running with.
+pub fn local_asid() -> usize {
+    (satp::read().bits() >> SATP_ASID_SHIFT) & ((1 << SATP_ASID_BITS) - 1)
+}
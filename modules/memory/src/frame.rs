@@ -3,9 +3,10 @@
 
 use alloc::vec::Vec;
 use core::{
-    cell::SyncUnsafeCell,
+    cmp,
     fmt::{self, Debug, Formatter},
     ops::Range,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
 use bitmap_allocator::BitAlloc;
@@ -46,106 +47,217 @@ impl Drop for FrameTracker {
     }
 }
 
+/// Frames kept for the kernel, which fallible allocations never take, so that
+/// page tables and kernel buffers can still be allocated when user space has
+/// used up the memory.
+const MIN_RESERVED_FRAMES: usize = 256;
+
 struct FrameAllocator {
-    range_ppn: SyncUnsafeCell<Range<PhysPageNum>>,
+    /// Number of frames managed.
+    total: AtomicUsize,
     allocator: SpinNoIrqLock<bitmap_allocator::BitAlloc16M>,
+    /// Number of frames allocated.
+    allocated: AtomicUsize,
+    /// Free frames below which fallible allocations fail.
+    min_watermark: AtomicUsize,
+    /// Free frames below which caches are shrunk before allocating.
+    low_watermark: AtomicUsize,
+    /// Number of allocations failed.
+    failures: AtomicUsize,
+    /// Set while the caches are being shrunk, so that only one hart does it.
+    reclaiming: AtomicBool,
 }
 
 impl FrameAllocator {
-    fn init(&self, range_ppn: Range<PhysPageNum>) {
-        unsafe { *self.range_ppn.get() = range_ppn };
+    fn init(&self, total: usize) {
+        let min = cmp::max(total / 64, MIN_RESERVED_FRAMES);
+        self.min_watermark.store(min, Ordering::Relaxed);
+        self.low_watermark.store(min * 2, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    fn free(&self) -> usize {
+        self.total
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.allocated.load(Ordering::Relaxed))
+    }
+
+    /// Shrink the caches by `FrameReleaseIf`, unless another hart is doing so.
+    fn reclaim(&self) {
+        if self.reclaiming.swap(true, Ordering::Acquire) {
+            return;
+        }
+        call_interface!(FrameReleaseIf::release_frames());
+        self.reclaiming.store(false, Ordering::Release);
+    }
+
+    fn alloc_contiguous(&self, size: usize, align_log2: usize, reserved: bool) -> Option<usize> {
+        let mut allocator = self.allocator.lock();
+        // frames are counted under the lock, so the reserve is never taken
+        if !reserved && self.free() < size + self.min_watermark.load(Ordering::Relaxed) {
+            return None;
+        }
+        let ret = if size == 1 {
+            allocator.alloc()
+        } else {
+            allocator.alloc_contiguous(size, align_log2)
+        };
+        if ret.is_some() {
+            self.allocated.fetch_add(size, Ordering::Relaxed);
+        }
+        ret
     }
 
-    fn range_ppn(&self) -> Range<PhysPageNum> {
-        unsafe { &*self.range_ppn.get() }.clone()
+    /// Allocate `size` contiguous frames, where `reserved` tells whether the
+    /// frames kept for the kernel may be taken.
+    ///
+    /// The caches are shrunk first if free frames are below the low
+    /// watermark, and once more if the allocation fails.
+    fn alloc_with_reclaim(&self, size: usize, reserved: bool) -> Option<PhysPageNum> {
+        if self.free() < size + self.low_watermark.load(Ordering::Relaxed) {
+            self.reclaim();
+        }
+        let ret = self.alloc_contiguous(size, 0, reserved).or_else(|| {
+            self.reclaim();
+            self.alloc_contiguous(size, 0, reserved)
+        });
+        match ret {
+            Some(idx) => Some(idx.into()),
+            None => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                log::warn!(
+                    "[frame] failed to allocate {size} frames, {} frames free",
+                    self.free()
+                );
+                None
+            }
+        }
+    }
+
+    fn dealloc(&self, idx: usize) {
+        self.allocator.lock().dealloc(idx);
+        self.allocated.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
 static FRAME_ALLOCATOR: FrameAllocator = FrameAllocator {
-    range_ppn: SyncUnsafeCell::new(PhysPageNum::ZERO..PhysPageNum::ZERO),
+    total: AtomicUsize::new(0),
     allocator: SpinNoIrqLock::new(bitmap_allocator::BitAlloc16M::DEFAULT),
+    allocated: AtomicUsize::new(0),
+    min_watermark: AtomicUsize::new(0),
+    low_watermark: AtomicUsize::new(0),
+    failures: AtomicUsize::new(0),
+    reclaiming: AtomicBool::new(false),
 };
 
-/// Initiate the frame allocator, using `VPNRange`
-pub fn init_frame_allocator(start: PhysPageNum, end: PhysPageNum) {
-    FRAME_ALLOCATOR
-        .allocator
-        .lock()
-        .insert(0..(end.0 - start.0));
-    FRAME_ALLOCATOR.init(start..end);
+/// Initiate the frame allocator with the ranges of usable frames, which may
+/// have holes between them, e.g. for firmware and the device tree.
+///
+/// Frames are indexed by their PPNs in the bitmap, so that frames allocated
+/// with an alignment are aligned physically as well.
+pub fn init_frame_allocator(ranges: &[Range<PhysPageNum>]) {
+    let mut allocator = FRAME_ALLOCATOR.allocator.lock();
+    for range in ranges {
+        allocator.insert(range.start.0..range.end.0);
+        log::info!(
+            "frame allocator takes [{:#x}, {:#x})",
+            PhysAddr::from(range.start),
+            PhysAddr::from(range.end)
+        );
+    }
+    FRAME_ALLOCATOR.init(ranges.iter().map(|range| range.end - range.start).sum());
+    log::info!("frame allocator init finshed, {} frames", total_frames());
+}
 
-    log::info!(
-        "frame allocator init finshed, start {:#x}, end {:#x}",
-        PhysAddr::from(start),
-        PhysAddr::from(end)
-    );
+/// Try to allocate a frame, which fails rather than taking the frames kept
+/// for the kernel. Used for memory allocated on behalf of user space.
+pub fn try_alloc_frame_tracker() -> Option<FrameTracker> {
+    FRAME_ALLOCATOR
+        .alloc_with_reclaim(1, false)
+        .map(FrameTracker::new)
 }
 
-/// Allocate a frame
+/// Allocate a frame for the kernel, which may take the frames kept for it.
 pub fn alloc_frame_tracker() -> FrameTracker {
-    let ret = FRAME_ALLOCATOR
-        .allocator
-        .lock()
-        .alloc()
-        .map(|u| FrameTracker::new(FRAME_ALLOCATOR.range_ppn().start + u));
-    if let Some(ret) = ret {
-        ret
-    } else {
-        call_interface!(FrameReleaseIf::release_frames());
-        FRAME_ALLOCATOR
-            .allocator
-            .lock()
-            .alloc()
-            .map(|u| FrameTracker::new(FRAME_ALLOCATOR.range_ppn().start + u))
-            .expect("frame space not enough")
-    }
+    FRAME_ALLOCATOR
+        .alloc_with_reclaim(1, true)
+        .map(FrameTracker::new)
+        .expect("frame space not enough")
 }
 
 /// Allocate contiguous frames
 pub fn alloc_frame_trackers(size: usize) -> Vec<FrameTracker> {
-    if let Some(first_frame) = FRAME_ALLOCATOR.allocator.lock().alloc_contiguous(size, 0) {
-        (first_frame..first_frame + size)
-            .map(|u| FrameTracker::new(FRAME_ALLOCATOR.range_ppn().start + u))
-            .collect()
-    } else {
-        call_interface!(FrameReleaseIf::release_frames());
-        let first_frame = FRAME_ALLOCATOR
-            .allocator
-            .lock()
-            .alloc_contiguous(size, 0)
-            .unwrap();
-        (first_frame..first_frame + size)
-            .map(|u| FrameTracker::new(FRAME_ALLOCATOR.range_ppn().start + u))
-            .collect()
-    }
+    let first_ppn = FRAME_ALLOCATOR
+        .alloc_with_reclaim(size, true)
+        .expect("frame space not enough");
+    (0..size)
+        .map(|i| FrameTracker::new(first_ppn + i))
+        .collect()
+}
+
+/// Try to allocate `size` contiguous frames aligned to `size`, which must be a
+/// power of two, on behalf of user space.
+///
+/// Unlike other allocations, the caches are not shrunk for it, nor is a
+/// failure counted, since the caller falls back to single frames.
+pub fn try_alloc_aligned_frame_trackers(size: usize) -> Option<Vec<FrameTracker>> {
+    debug_assert!(size.is_power_of_two());
+    let first_ppn: PhysPageNum = FRAME_ALLOCATOR
+        .alloc_contiguous(size, size.trailing_zeros() as usize, false)?
+        .into();
+    Some(
+        (0..size)
+            .map(|i| FrameTracker::new(first_ppn + i))
+            .collect(),
+    )
 }
 
 /// Allocate contiguous frames
 pub fn alloc_frames(size: usize) -> PhysAddr {
-    if let Some(first_frame) = FRAME_ALLOCATOR.allocator.lock().alloc_contiguous(size, 0) {
-        let ppn = FRAME_ALLOCATOR.range_ppn().start + first_frame;
-        ppn.to_paddr()
-    } else {
-        call_interface!(FrameReleaseIf::release_frames());
-        let ppn = FRAME_ALLOCATOR.range_ppn().start
-            + FRAME_ALLOCATOR
-                .allocator
-                .lock()
-                .alloc_contiguous(size, 0)
-                .unwrap();
-        ppn.to_paddr()
-    }
+    FRAME_ALLOCATOR
+        .alloc_with_reclaim(size, true)
+        .expect("frame space not enough")
+        .to_paddr()
 }
 
 /// Deallocate a frame
 pub fn dealloc_frame(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR
-        .allocator
-        .lock()
-        .dealloc(ppn - FRAME_ALLOCATOR.range_ppn().start);
+    FRAME_ALLOCATOR.dealloc(ppn.0);
+}
+
+/// Statistics of the frame allocator, in frames.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    /// Frames managed by the allocator.
+    pub total: usize,
+    /// Frames that can be allocated.
+    pub free: usize,
+    /// Free frames below which allocations for user space fail.
+    pub min_watermark: usize,
+    /// Free frames below which caches are shrunk before allocating.
+    pub low_watermark: usize,
+    /// Number of allocations failed since boot.
+    pub failures: usize,
+}
+
+pub fn frame_stats() -> FrameStats {
+    FrameStats {
+        total: total_frames(),
+        free: FRAME_ALLOCATOR.free(),
+        min_watermark: FRAME_ALLOCATOR.min_watermark.load(Ordering::Relaxed),
+        low_watermark: FRAME_ALLOCATOR.low_watermark.load(Ordering::Relaxed),
+        failures: FRAME_ALLOCATOR.failures.load(Ordering::Relaxed),
+    }
+}
+
+/// Number of frames managed by the allocator.
+pub fn total_frames() -> usize {
+    FRAME_ALLOCATOR.total.load(Ordering::Relaxed)
 }
 
 #[crate_interface::def_interface]
 pub trait FrameReleaseIf {
+    /// Shrink the caches to free frames, when free frames are low or an
+    /// allocation fails.
     fn release_frames();
 }
@@ -1,8 +1,17 @@
 //! The global allocator
+//!
+//! The heap is wrapped to count the bytes and objects allocated, in total and
+//! per size class, which are read from /proc/sys/kernel/slabinfo and printed
+//! when the heap is exhausted. With the `heap-tracking` feature, the callers
+//! of the live allocations are recorded as well to hunt for leaks.
+
+use alloc::vec::Vec;
 use core::{
     self,
     alloc::{GlobalAlloc, Layout},
+    cmp,
     ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use buddy_system_allocator::Heap as BuddyHeap;
@@ -19,31 +28,192 @@ type GlobalHeap = LockedLinkedHeap;
 
 /// heap allocator instance
 #[global_allocator]
-static HEAP_ALLOCATOR: GlobalHeap = GlobalHeap::empty();
+static HEAP_ALLOCATOR: TrackedHeap = TrackedHeap::empty();
 
 /// heap space
 #[link_section = ".bss.heap"]
 static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
-/// Panic when heap allocation error occurs.
-#[alloc_error_handler]
-pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
-    log::error!("heap alloc error");
+/// Size of the smallest size class.
+const MIN_CLASS_SIZE: usize = 8;
+/// Number of size classes, which are powers of two from `MIN_CLASS_SIZE`,
+/// except that the last one holds all larger allocations.
+pub const SIZE_CLASSES: usize = 11;
+
+/// Size class of an allocation, by its size rounded up to a power of two.
+fn size_class(layout: &Layout) -> usize {
+    let size = cmp::max(layout.size(), layout.align()).next_power_of_two();
+    let class = size.trailing_zeros() as usize;
+    class
+        .saturating_sub(MIN_CLASS_SIZE.trailing_zeros() as usize)
+        .min(SIZE_CLASSES - 1)
+}
+
+/// Objects allocated from one size class.
+#[derive(Debug, Clone, Copy)]
+pub struct SizeClassStats {
+    /// Largest size of the objects, or `None` for the last class.
+    pub size: Option<usize>,
+    /// Objects allocated and not freed yet.
+    pub live: usize,
+    /// Objects allocated since boot.
+    pub allocs: usize,
+}
+
+/// Statistics of the kernel heap.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Size of the heap in bytes.
+    pub total: usize,
+    /// Bytes allocated and not freed yet.
+    pub current: usize,
+    /// Most bytes ever allocated at once.
+    pub peak: usize,
+    /// Number of allocations since boot.
+    pub allocs: usize,
+    /// Number of frees since boot.
+    pub frees: usize,
+    /// Number of allocations failed.
+    pub failures: usize,
+    pub classes: [SizeClassStats; SIZE_CLASSES],
+}
 
-    let inner = HEAP_ALLOCATOR.0.lock();
-    let alloc_user = inner.stats_alloc_user();
-    let alloc_actual = inner.stats_alloc_actual();
-    let total_bytes = inner.stats_total_bytes();
+const COUNTER_EACH: AtomicUsize = AtomicUsize::new(0);
+
+struct HeapCounters {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+    allocs: AtomicUsize,
+    frees: AtomicUsize,
+    failures: AtomicUsize,
+    class_live: [AtomicUsize; SIZE_CLASSES],
+    class_allocs: [AtomicUsize; SIZE_CLASSES],
+}
+
+impl HeapCounters {
+    const fn new() -> Self {
+        Self {
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            allocs: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            class_live: [COUNTER_EACH; SIZE_CLASSES],
+            class_allocs: [COUNTER_EACH; SIZE_CLASSES],
+        }
+    }
+
+    fn on_alloc(&self, layout: &Layout) {
+        let class = size_class(layout);
+        let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        self.peak.fetch_max(current, Ordering::Relaxed);
+        self.allocs.fetch_add(1, Ordering::Relaxed);
+        self.class_live[class].fetch_add(1, Ordering::Relaxed);
+        self.class_allocs[class].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_dealloc(&self, layout: &Layout) {
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+        self.frees.fetch_add(1, Ordering::Relaxed);
+        self.class_live[size_class(layout)].fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HeapStats {
+        let classes = core::array::from_fn(|class| SizeClassStats {
+            size: (class < SIZE_CLASSES - 1).then_some(MIN_CLASS_SIZE << class),
+            live: self.class_live[class].load(Ordering::Relaxed),
+            allocs: self.class_allocs[class].load(Ordering::Relaxed),
+        });
+        HeapStats {
+            total: KERNEL_HEAP_SIZE,
+            current: self.current.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            allocs: self.allocs.load(Ordering::Relaxed),
+            frees: self.frees.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            classes,
+        }
+    }
+}
+
+/// The heap along with its counters.
+struct TrackedHeap {
+    heap: GlobalHeap,
+    counters: HeapCounters,
+}
+
+impl TrackedHeap {
+    const fn empty() -> Self {
+        Self {
+            heap: GlobalHeap::empty(),
+            counters: HeapCounters::new(),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for TrackedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.heap.alloc(layout);
+        if ptr.is_null() {
+            self.counters.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.counters.on_alloc(&layout);
+            #[cfg(feature = "heap-tracking")]
+            tracking::record(ptr as usize, layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "heap-tracking")]
+        tracking::forget(ptr as usize);
+        self.counters.on_dealloc(&layout);
+        self.heap.dealloc(ptr, layout)
+    }
+}
 
+/// Statistics of the kernel heap.
+pub fn heap_stats() -> HeapStats {
+    HEAP_ALLOCATOR.counters.snapshot()
+}
+
+/// Print the heap statistics and a backtrace, then panic when heap
+/// allocation error occurs.
+///
+/// Nothing is allocated here, since the heap is exhausted.
+#[alloc_error_handler]
+pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
+    let stats = heap_stats();
     sbi_println!(
-        "Heap allocation error, layout = {:?}, alloc_user: {}, alloc_actual: {}, total_bytes: {}",
+        "Heap allocation error, layout = {:?}, current: {}, peak: {}, total: {}, allocs: {}, frees: {}, failures: {}",
         layout,
-        alloc_user,
-        alloc_actual,
-        total_bytes
+        stats.current,
+        stats.peak,
+        stats.total,
+        stats.allocs,
+        stats.frees,
+        stats.failures
     );
+    for class in stats.classes.iter() {
+        match class.size {
+            Some(size) => sbi_println!(
+                "  size <= {:6}: {:8} live, {:10} allocs",
+                size,
+                class.live,
+                class.allocs
+            ),
+            None => sbi_println!(
+                "  larger:         {:8} live, {:10} allocs",
+                class.live,
+                class.allocs
+            ),
+        }
+    }
+    #[cfg(feature = "heap-tracking")]
+    tracking::print_live();
+    backtrace::backtrace();
 
-    panic!();
+    panic!("heap exhausted");
 }
 
 struct LockedBuddyHeap(SpinNoIrqLock<BuddyHeap<32>>);
@@ -111,7 +281,7 @@ unsafe impl GlobalAlloc for LockedLinkedHeap {
 pub fn init_heap_allocator() {
     unsafe {
         let start = HEAP_SPACE.as_ptr() as usize;
-        HEAP_ALLOCATOR.init(start, KERNEL_HEAP_SIZE);
+        HEAP_ALLOCATOR.heap.init(start, KERNEL_HEAP_SIZE);
         log::info!(
             "[kernel] heap start {:#x}, end {:#x}",
             start,
@@ -119,3 +289,152 @@ pub fn init_heap_allocator() {
         );
     }
 }
+
+/// An allocation recorded by the `heap-tracking` feature.
+#[derive(Debug, Clone, Copy)]
+pub struct LiveAllocation {
+    pub ptr: usize,
+    pub size: usize,
+    /// Return addresses of the innermost frames above the allocator, which
+    /// can be resolved by addr2line.
+    pub callers: [usize; CALLER_DEPTH],
+}
+
+/// Number of return addresses recorded for each allocation.
+pub const CALLER_DEPTH: usize = 4;
+
+/// The allocations recorded by the `heap-tracking` feature and not freed yet,
+/// which is empty without the feature.
+pub fn live_allocations() -> Vec<LiveAllocation> {
+    #[cfg(feature = "heap-tracking")]
+    let live = tracking::snapshot();
+    #[cfg(not(feature = "heap-tracking"))]
+    let live = Vec::new();
+    live
+}
+
+#[cfg(feature = "heap-tracking")]
+mod tracking {
+    use super::*;
+
+    /// Allocations that can be recorded at once, beyond which they are only
+    /// counted.
+    const TRACKED_ALLOCATIONS: usize = 4096;
+    /// Frames of `callers`, `TrackedHeap::alloc` and the `__rust_alloc` shim,
+    /// which are skipped.
+    const SKIPPED_FRAMES: usize = 3;
+
+    const EMPTY: LiveAllocation = LiveAllocation {
+        ptr: 0,
+        size: 0,
+        callers: [0; CALLER_DEPTH],
+    };
+
+    static TABLE: SpinNoIrqLock<[LiveAllocation; TRACKED_ALLOCATIONS]> =
+        SpinNoIrqLock::new([EMPTY; TRACKED_ALLOCATIONS]);
+    /// Allocations not recorded since the table is full.
+    static UNTRACKED: AtomicUsize = AtomicUsize::new(0);
+
+    /// Walk the frame pointers for the return addresses of the callers.
+    #[inline(never)]
+    fn callers() -> [usize; CALLER_DEPTH] {
+        extern "C" {
+            fn _stext();
+            fn _etext();
+        }
+        let text = _stext as usize..=_etext as usize;
+        let mut callers = [0; CALLER_DEPTH];
+        let mut fp = arch::register::fp();
+        for depth in 0..SKIPPED_FRAMES + CALLER_DEPTH {
+            if fp == 0 {
+                break;
+            }
+            let ra = unsafe { *(fp as *const usize).offset(-1) };
+            if !text.contains(&ra) {
+                break;
+            }
+            if depth >= SKIPPED_FRAMES {
+                callers[depth - SKIPPED_FRAMES] = ra;
+            }
+            fp = unsafe { *(fp as *const usize).offset(-2) };
+        }
+        callers
+    }
+
+    /// Slot to start probing from for `ptr`.
+    fn slot(ptr: usize) -> usize {
+        (ptr / MIN_CLASS_SIZE) % TRACKED_ALLOCATIONS
+    }
+
+    #[inline(always)]
+    pub fn record(ptr: usize, size: usize) {
+        let callers = callers();
+        let mut table = TABLE.lock();
+        let start = slot(ptr);
+        let free = (0..TRACKED_ALLOCATIONS)
+            .map(|i| (start + i) % TRACKED_ALLOCATIONS)
+            .find(|&i| table[i].ptr == 0);
+        match free {
+            Some(i) => table[i] = LiveAllocation { ptr, size, callers },
+            None => {
+                UNTRACKED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn forget(ptr: usize) {
+        let mut table = TABLE.lock();
+        let start = slot(ptr);
+        // slots may be freed behind an entry, so the probing never stops early
+        if let Some(i) = (0..TRACKED_ALLOCATIONS)
+            .map(|i| (start + i) % TRACKED_ALLOCATIONS)
+            .find(|&i| table[i].ptr == ptr)
+        {
+            table[i] = EMPTY;
+        }
+    }
+
+    pub fn snapshot() -> Vec<LiveAllocation> {
+        // allocated before locking the table, which is locked on allocation
+        let mut live = Vec::with_capacity(TRACKED_ALLOCATIONS);
+        let table = TABLE.lock();
+        live.extend(table.iter().filter(|a| a.ptr != 0));
+        live
+    }
+
+    /// Print the allocations recorded, without allocating.
+    pub fn print_live() {
+        let table = TABLE.lock();
+        for a in table.iter().filter(|a| a.ptr != 0) {
+            sbi_println!("  {:#x} {:8} bytes from {:x?}", a.ptr, a.size, a.callers);
+        }
+        sbi_println!(
+            "  {} allocations not recorded",
+            UNTRACKED.load(Ordering::Relaxed)
+        );
+    }
+}
+
+/// Check that boxes leaked in a loop are counted as live in their size class,
+/// while boxes dropped are not.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    use alloc::boxed::Box;
+
+    const LEAKS: usize = 64;
+    let layout = Layout::new::<[u64; 4]>();
+    let class = size_class(&layout);
+    let before = heap_stats();
+    for i in 0..LEAKS {
+        Box::leak(Box::new([i as u64; 4]));
+        drop(Box::new([i as u64; 4]));
+    }
+    let after = heap_stats();
+    assert_eq!(after.classes[class].size, Some(32));
+    // interrupt handlers may allocate meanwhile, so only the increase is
+    // checked
+    assert!(after.classes[class].live >= before.classes[class].live + LEAKS);
+    assert!(after.classes[class].allocs >= before.classes[class].allocs + 2 * LEAKS);
+    assert!(after.current >= before.current + LEAKS * layout.size());
+    assert!(after.peak >= after.current);
+}
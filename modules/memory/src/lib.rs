@@ -10,6 +10,7 @@
 extern crate alloc;
 
 pub mod address;
+pub mod asid;
 pub mod frame;
 pub mod heap;
 pub mod page_table;
@@ -3,21 +3,19 @@
 use alloc::{vec, vec::Vec};
 use core::{iter::zip, ops::Range};
 
-use config::mm::{PAGE_SIZE, VIRT_RAM_OFFSET};
-use riscv::register::satp;
+use config::mm::{PAGE_SIZE, PAGE_TABLE_LEVEL_NUM, PTES_PER_PAGE, VIRT_RAM_OFFSET};
 
 use crate::{
     address::{PhysPageNum, VirtAddr, VirtPageNum},
+    asid::Asid,
     frame::{alloc_frame_tracker, FrameTracker},
     pte::PTEFlags,
     PageTableEntry, PhysAddr,
 };
 
-/// Write `page_table_token` into satp and sfence.vma
-pub unsafe fn switch_page_table(page_table_token: usize) {
-    satp::write(page_table_token);
-    core::arch::riscv64::sfence_vma_all();
-}
+/// Pages mapped by a leaf pte of each level, i.e. 1 GiB, 2 MiB and 4 KiB.
+const LEVEL_PAGES: [usize; PAGE_TABLE_LEVEL_NUM] =
+    [PTES_PER_PAGE * PTES_PER_PAGE, PTES_PER_PAGE, 1];
 
 /// # Safety
 ///
@@ -28,6 +26,7 @@ pub struct PageTable {
     root_ppn: PhysPageNum,
     /// Frames hold all internal pages
     frames: Vec<FrameTracker>,
+    asid: Asid,
 }
 
 impl PageTable {
@@ -38,6 +37,7 @@ impl PageTable {
         PageTable {
             root_ppn: root_frame.ppn,
             frames: vec![root_frame],
+            asid: Asid::new(),
         }
     }
 
@@ -45,6 +45,10 @@ impl PageTable {
         self.root_ppn
     }
 
+    pub fn asid(&self) -> &Asid {
+        &self.asid
+    }
+
     /// Create a page table that inherits kernel page table by shallow copying
     /// the ptes from root page table.
     ///
@@ -67,26 +71,30 @@ impl PageTable {
         PageTable {
             root_ppn: root_frame.ppn,
             frames: vec![root_frame],
+            asid: Asid::new(),
         }
     }
 
     pub fn vaddr_to_paddr(&self, vaddr: VirtAddr) -> PhysAddr {
-        let leaf_pte = self.find_leaf_pte(vaddr.floor()).unwrap();
-        let paddr = leaf_pte.ppn().to_paddr() + vaddr.page_offset();
-        paddr
+        let vpn = vaddr.floor();
+        let (pte, level) = self.find_pte(vpn).unwrap();
+        let ppn = pte.ppn() + (vpn.0 & (LEVEL_PAGES[level] - 1));
+        ppn.to_paddr() + vaddr.page_offset()
     }
 
-    /// Switch to this pagetable
+    /// Switch to this pagetable, with its ASID written into satp, so that the
+    /// TLB is flushed only in need.
     pub unsafe fn switch(&self) {
-        switch_page_table(self.token());
+        self.asid.switch(self.token());
     }
 
-    /// Find the leaf pte and will create page table in need.
-    fn find_leaf_pte_create(&mut self, vpn: VirtPageNum) -> &mut PageTableEntry {
+    /// Find the pte of `level` and will create page table in need.
+    fn find_pte_create(&mut self, vpn: VirtPageNum, level: usize) -> &mut PageTableEntry {
         let idxs = vpn.indices();
         let mut ppn = self.root_ppn;
-        for &idx in &idxs[..2] {
+        for &idx in &idxs[..level] {
             let pte = ppn.pte(idx);
+            debug_assert!(!pte.is_leaf(), "vpn {vpn:?} is mapped by a huge page");
             if !pte.is_valid() {
                 let frame = alloc_frame_tracker();
                 frame.fill_zero();
@@ -95,15 +103,19 @@ impl PageTable {
             }
             ppn = pte.ppn();
         }
-        // log::error!("{idxs:?}");
-        // log::error!("{:?}", ppn.pte_array());
-        return ppn.pte(idxs[2]);
+        return ppn.pte(idxs[level]);
+    }
+
+    /// Find the leaf pte and will create page table in need.
+    fn find_leaf_pte_create(&mut self, vpn: VirtPageNum) -> &mut PageTableEntry {
+        self.find_pte_create(vpn, PAGE_TABLE_LEVEL_NUM - 1)
     }
 
-    /// Find the leaf pte.
+    /// Find the leaf pte mapping `vpn` along with its level, which is the last
+    /// one for a base page.
     ///
     /// Return `None` if the leaf pte is not valid.
-    pub fn find_leaf_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    pub fn find_pte(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
         let idxs = vpn.indices();
         let mut ppn = self.root_ppn;
         for (i, idx) in idxs.into_iter().enumerate() {
@@ -111,14 +123,24 @@ impl PageTable {
             if !pte.is_valid() {
                 return None;
             }
-            if i == 2 {
-                return Some(pte);
+            if i == PAGE_TABLE_LEVEL_NUM - 1 || pte.is_leaf() {
+                return Some((pte, i));
             }
             ppn = pte.ppn();
         }
         return None;
     }
 
+    /// Find the leaf pte of a base page.
+    ///
+    /// Return `None` if the leaf pte is not valid, or `vpn` is mapped by a
+    /// huge page.
+    pub fn find_leaf_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte(vpn)
+            .filter(|(_, level)| *level == PAGE_TABLE_LEVEL_NUM - 1)
+            .map(|(pte, _)| pte)
+    }
+
     /// Map `VirtPageNum` to `PhysPageNum` with `PTEFlags`.
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
         let pte = self.find_leaf_pte_create(vpn);
@@ -143,10 +165,64 @@ impl PageTable {
         *pte = PageTableEntry::empty();
     }
 
+    /// Map a huge page of `HUGE_PAGE_PAGES` pages at `vpn` to the frames at
+    /// `ppn`, both of which are aligned to it.
+    ///
+    /// Pages must not be mapped in the range, though the page table of the
+    /// last level may remain, which is kept until the page table is dropped.
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        debug_assert!(vpn.0 % LEVEL_PAGES[1] == 0 && ppn.0 % LEVEL_PAGES[1] == 0);
+        let pte = self.find_pte_create(vpn, 1);
+        debug_assert!(!pte.is_leaf(), "vpn {vpn:?} is mapped before mapping");
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V | PTEFlags::D | PTEFlags::A);
+    }
+
+    /// Unmap the huge page at `vpn`.
+    pub fn unmap_huge(&mut self, vpn: VirtPageNum) {
+        let (pte, level) = self.find_pte(vpn).expect("leaf pte is not valid");
+        debug_assert!(level == 1, "vpn {vpn:?} is not mapped by a huge page");
+        *pte = PageTableEntry::empty();
+    }
+
+    /// Split the huge page mapping `vpn`, if any, into base pages of the same
+    /// frames and flags. Return whether it is split, in which case the TLB
+    /// should be flushed for the range.
+    pub fn split_huge(&mut self, vpn: VirtPageNum) -> bool {
+        let Some((pte, 1)) = self.find_pte(vpn) else {
+            return false;
+        };
+        let frame = alloc_frame_tracker();
+        let flags = pte.flags();
+        for (i, leaf_pte) in frame.ppn.pte_array().iter_mut().enumerate() {
+            *leaf_pte = PageTableEntry::new(pte.ppn() + i, flags);
+        }
+        *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
+        self.frames.push(frame);
+        true
+    }
+
+    /// Map a region of the kernel at its physical address plus
+    /// `VIRT_RAM_OFFSET`, with the largest leaf ptes that the alignment allows,
+    /// which saves TLB entries for the physical memory.
     pub fn map_kernel_region(&mut self, range_va: Range<VirtAddr>, flags: PTEFlags) {
         let range_vpn = range_va.start.floor()..range_va.end.floor();
-        for vpn in range_vpn {
-            self.map(vpn, vpn.to_ppn(), flags);
+        let mut vpn = range_vpn.start;
+        while vpn < range_vpn.end {
+            let level = (0..PAGE_TABLE_LEVEL_NUM)
+                .find(|&level| {
+                    let pages = LEVEL_PAGES[level];
+                    vpn.0 % pages == 0
+                        && vpn.to_ppn().0 % pages == 0
+                        && range_vpn.end - vpn >= pages
+                })
+                .unwrap();
+            let pte = self.find_pte_create(vpn, level);
+            debug_assert!(!pte.is_valid(), "vpn {vpn:?} is mapped before mapping");
+            *pte = PageTableEntry::new(
+                vpn.to_ppn(),
+                flags | PTEFlags::V | PTEFlags::D | PTEFlags::A,
+            );
+            vpn += LEVEL_PAGES[level];
         }
     }
 
@@ -190,7 +266,7 @@ impl PageTable {
         }
     }
 
-    /// Satp token with sv39 enabled
+    /// Satp token with sv39 enabled, without the ASID.
     pub fn token(&self) -> usize {
         8usize << 60 | self.root_ppn.0
     }
@@ -59,6 +59,15 @@ impl PageTableEntry {
         self.flags().contains(PTEFlags::V)
     }
 
+    /// Check PTE is a leaf, i.e. maps a page rather than points to the page
+    /// table of the next level
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid()
+            && self
+                .flags()
+                .intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X)
+    }
+
     /// Check PTE readable
     pub fn readable(&self) -> bool {
         self.flags().contains(PTEFlags::R)
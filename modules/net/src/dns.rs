@@ -1,13 +1,14 @@
 use alloc::vec::Vec;
-use axerrno::{ax_err_type, AxError, AxResult};
 use core::net::IpAddr;
 
-use smoltcp::iface::SocketHandle;
-use smoltcp::socket::dns::{self, GetQueryResultError, StartQueryError};
-use smoltcp::wire::DnsQueryType;
+use axerrno::{ax_err_type, AxError, AxResult};
+use smoltcp::{
+    iface::SocketHandle,
+    socket::dns::{self, GetQueryResultError, StartQueryError},
+    wire::DnsQueryType,
+};
 
-use super::addr::into_core_ipaddr;
-use super::{SocketSetWrapper, ETH0, SOCKET_SET};
+use super::{addr::into_core_ipaddr, SocketSetWrapper, ETH0, SOCKET_SET};
 
 /// A DNS socket.
 struct DnsSocket {
@@ -35,10 +36,11 @@ impl DnsSocket {
     pub fn query(&self, name: &str, query_type: DnsQueryType) -> AxResult<Vec<IpAddr>> {
         // let local_addr = self.local_addr.unwrap_or_else(f);
         let handle = self.handle.ok_or_else(|| ax_err_type!(InvalidInput))?;
-        let iface = &ETH0.iface;
+        // the interface is locked before the sockets
+        let mut iface = ETH0.iface.lock();
         let query_handle = SOCKET_SET
             .with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
-                socket.start_query(iface.lock().context(), name, query_type)
+                socket.start_query(iface.context(), name, query_type)
             })
             .map_err(|e| match e {
                 StartQueryError::NoFreeSlot => {
@@ -51,6 +53,7 @@ impl DnsSocket {
                     ax_err_type!(InvalidInput, "socket query() failed: too long name")
                 }
             })?;
+        drop(iface);
         loop {
             SOCKET_SET.poll_interfaces();
             match SOCKET_SET.with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
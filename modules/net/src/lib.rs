@@ -5,13 +5,14 @@
 #![feature(new_uninit)]
 
 extern crate alloc;
-use alloc::{boxed::Box, vec, vec::Vec};
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
 use core::{cell::RefCell, future::Future, ops::DerefMut, panic, time::Duration};
 
 use arch::time::{get_time_duration, get_time_us};
 use crate_interface::call_interface;
 use device_core::{error::DevError, NetBufPtrOps, NetDevice};
 use listen_table::*;
+use lock_order::{LockRank, RankedMutex};
 use log::*;
 pub use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint, Ipv4Address, Ipv6Address};
 use smoltcp::{
@@ -22,12 +23,16 @@ use smoltcp::{
     wire::{EthernetAddress, HardwareAddress, IpCidr},
 };
 use spin::{Lazy, Once};
+pub use stats::{NetCounters, ProtoCounters};
+use stats::{NetStats, Proto, RxError};
 use sync::mutex::SpinNoIrqLock;
-use timer::{Timer, TimerEvent, TIMER_MANAGER};
+use timer::{Timer, TimerEvent, TimerHandle, TIMER_MANAGER};
 pub mod addr;
 pub mod bench;
 pub mod listen_table;
+mod lock_order;
 pub mod portmap;
+mod stats;
 pub mod tcp;
 pub mod udp;
 
@@ -42,7 +47,8 @@ macro_rules! env_or_default {
     };
 }
 
-/// Defined in makefile
+/// Defined in makefile, which can be overridden by `ip=` and `gw=` in the
+/// bootargs.
 const IP: &str = env_or_default!("Phoenix_IP");
 const GATEWAY: &str = env_or_default!("Phoenix_GW");
 const DNS_SEVER: &str = "8.8.8.8";
@@ -56,6 +62,8 @@ pub const TCP_RX_BUF_LEN: usize = 64 * 1024;
 pub const TCP_TX_BUF_LEN: usize = 64 * 1024;
 const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
+/// Connections in the SYN queue of a listener, as the upper bound of the
+/// backlog of listen().
 const LISTEN_QUEUE_SIZE: usize = 512;
 
 static LISTEN_TABLE: Lazy<ListenTable> = Lazy::new(ListenTable::new);
@@ -67,13 +75,16 @@ static ETH0: Once<InterfaceWrapper> = Once::new();
 /// and operate these sockets, including polling socket status, processing data
 /// transmission and reception, etc. It is similar to `FdTable` and
 /// `SocketHandle` is similar to `fd`
-struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
+struct SocketSetWrapper<'a>(RankedMutex<SocketSet<'a>>);
 
 /// A wrapper for network devices, providing interior mutability for
 /// `NetDevice`.
 struct DeviceWrapper {
     /// The inner network device wrapped in a `RefCell` for interior mutability.
     inner: RefCell<Box<dyn NetDevice>>,
+    /// Counters of the interface, updated as packets are received and
+    /// transmitted.
+    stats: Arc<NetStats>,
 }
 
 /// A wrapper for network interfaces, containing device and interface details
@@ -84,15 +95,20 @@ struct InterfaceWrapper {
     /// The Ethernet address of the network interface.
     ether_addr: EthernetAddress,
     /// The device wrapper protected by a `Mutex` to ensure thread-safe access.
-    dev: Mutex<DeviceWrapper>,
+    dev: RankedMutex<DeviceWrapper>,
     /// The network interface protected by a `Mutex` to ensure thread-safe
     /// access.
-    iface: Mutex<Interface>,
+    iface: RankedMutex<Interface>,
+    /// The timer to poll the interface later, which is superseded when the
+    /// interface is checked again.
+    poll_timer: Mutex<Option<TimerHandle>>,
+    /// Counters of the interface, shared with the device.
+    stats: Arc<NetStats>,
 }
 
 impl<'a> SocketSetWrapper<'a> {
     fn new() -> Self {
-        Self(Mutex::new(SocketSet::new(vec![])))
+        Self(RankedMutex::new(LockRank::Sockets, SocketSet::new(vec![])))
     }
 
     /// return a `tcp::Socket` defined in `smoltcp`
@@ -177,6 +193,11 @@ impl<'a> SocketSetWrapper<'a> {
         f(socket)
     }
 
+    /// Access the whole set, e.g. to take the listen table while it is locked.
+    pub fn with_sockets<R>(&self, f: impl FnOnce(&mut SocketSet<'a>) -> R) -> R {
+        f(&mut self.0.lock())
+    }
+
     pub fn poll_interfaces(&self) -> smoltcp::time::Instant {
         ETH0.get().unwrap().poll(&self.0)
     }
@@ -206,13 +227,19 @@ impl InterfaceWrapper {
         };
         config.random_seed = RANDOM_SEED;
 
-        let mut dev = DeviceWrapper::new(dev);
-        let iface = Mutex::new(Interface::new(config, &mut dev, Self::current_time()));
+        let stats = Arc::new(NetStats::default());
+        let mut dev = DeviceWrapper::new(dev, stats.clone());
+        let iface = RankedMutex::new(
+            LockRank::Iface,
+            Interface::new(config, &mut dev, Self::current_time()),
+        );
         Self {
             name,
             ether_addr,
-            dev: Mutex::new(dev),
+            dev: RankedMutex::new(LockRank::Dev, dev),
             iface,
+            poll_timer: Mutex::new(None),
+            stats,
         }
     }
 
@@ -253,9 +280,9 @@ impl InterfaceWrapper {
     /// protocol stack status.
     ///
     /// return what time it should poll next
-    pub fn poll(&self, sockets: &Mutex<SocketSet>) -> SmolInstant {
-        let mut dev = self.dev.lock();
+    pub fn poll(&self, sockets: &RankedMutex<SocketSet>) -> SmolInstant {
         let mut iface = self.iface.lock();
+        let mut dev = self.dev.lock();
         let mut sockets = sockets.lock();
         let timestamp = Self::current_time();
         let result = iface.poll(timestamp, dev.deref_mut(), &mut sockets);
@@ -292,41 +319,43 @@ impl InterfaceWrapper {
     //     }
     // }
 
-    pub fn check_poll(&self, timestamp: SmolInstant, sockets: &Mutex<SocketSet>) {
+    pub fn check_poll(&self, timestamp: SmolInstant, sockets: &RankedMutex<SocketSet>) {
+        // the device is taken before the sockets even if not polled, following
+        // the lock order
         let mut iface = self.iface.lock();
+        let mut dev = self.dev.lock();
         let mut sockets = sockets.lock();
-        match iface
+        let next_poll = match iface
             .poll_delay(timestamp, &mut sockets)
             .map(Self::dur_to_duration)
         {
             Some(Duration::ZERO) => {
-                iface.poll(
-                    Self::current_time(),
-                    self.dev.lock().deref_mut(),
-                    &mut sockets,
-                );
+                iface.poll(Self::current_time(), dev.deref_mut(), &mut sockets);
+                None
             }
             Some(delay) => {
                 let next_poll = delay + Self::ins_to_duration(timestamp);
                 let current = get_time_duration();
                 if next_poll < current {
-                    iface.poll(
-                        Self::current_time(),
-                        self.dev.lock().deref_mut(),
-                        &mut sockets,
-                    );
+                    iface.poll(Self::current_time(), dev.deref_mut(), &mut sockets);
+                    None
                 } else {
-                    let timer = Timer::new(next_poll, Box::new(PollTimer {}));
-                    TIMER_MANAGER.add_timer(timer);
+                    Some(next_poll)
                 }
             }
-            None => {
-                let timer = Timer::new(
-                    get_time_duration() + Duration::from_millis(2),
-                    Box::new(PollTimer {}),
-                );
-                TIMER_MANAGER.add_timer(timer);
-            }
+            None => Some(get_time_duration() + Duration::from_millis(2)),
+        };
+        // `TIMER_MANAGER` polls the interface with its lock held
+        drop(sockets);
+        drop(dev);
+        drop(iface);
+        let mut poll_timer = self.poll_timer.lock();
+        if let Some(timer) = poll_timer.take() {
+            timer.cancel();
+        }
+        if let Some(next_poll) = next_poll {
+            let timer = Timer::new(next_poll, Box::new(PollTimer {}));
+            *poll_timer = Some(TIMER_MANAGER.add_timer(timer));
         }
     }
 
@@ -368,9 +397,10 @@ impl TimerEvent for PollTimer {
 }
 
 impl DeviceWrapper {
-    fn new(inner: Box<dyn NetDevice>) -> Self {
+    fn new(inner: Box<dyn NetDevice>, stats: Arc<NetStats>) -> Self {
         Self {
             inner: RefCell::new(inner),
+            stats,
         }
     }
 }
@@ -397,11 +427,16 @@ impl Device for DeviceWrapper {
             Err(err) => {
                 if !matches!(err, DevError::Again) {
                     warn!("receive failed: {:?}", err);
+                    self.stats.rx_dev_error();
                 }
                 return None;
             }
         };
-        Some((NetRxToken(&self.inner, rx_buf), NetTxToken(&self.inner)))
+        self.stats.rx_packet(rx_buf.packet_len());
+        Some((
+            NetRxToken(&self.inner, rx_buf, &self.stats),
+            NetTxToken(&self.inner, &self.stats),
+        ))
     }
 
     fn transmit(&mut self, _timestamp: smoltcp::time::Instant) -> Option<Self::TxToken<'_>> {
@@ -411,7 +446,7 @@ impl Device for DeviceWrapper {
             return None;
         }
         if dev.can_transmit() {
-            Some(NetTxToken(&self.inner))
+            Some(NetTxToken(&self.inner, &self.stats))
         } else {
             None
         }
@@ -422,14 +457,20 @@ impl Device for DeviceWrapper {
     }
 }
 
-struct NetRxToken<'a>(&'a RefCell<Box<dyn NetDevice>>, Box<dyn NetBufPtrOps>);
-struct NetTxToken<'a>(&'a RefCell<Box<dyn NetDevice>>);
+struct NetRxToken<'a>(
+    &'a RefCell<Box<dyn NetDevice>>,
+    Box<dyn NetBufPtrOps>,
+    &'a NetStats,
+);
+struct NetTxToken<'a>(&'a RefCell<Box<dyn NetDevice>>, &'a NetStats);
 
 impl<'a> RxToken for NetRxToken<'a> {
     fn preprocess(&self, sockets: &mut SocketSet<'_>) {
-        let medium = self.0.borrow().capabilities().medium;
-        let is_ethernet = medium == Medium::Ethernet;
-        snoop_tcp_packet(self.1.packet(), sockets, is_ethernet).ok();
+        let caps = self.0.borrow().capabilities();
+        if let Err(err) = snoop_tcp_packet(self.1.packet(), sockets, &caps, self.2) {
+            warn!("[RxToken::preprocess] malformed packet: {err:?}");
+            self.2.rx_error(err);
+        }
     }
 
     /// 此方法接收数据包，然后以原始数据包字节作为参数调用给定的闭包f。
@@ -466,37 +507,83 @@ impl<'a> TxToken for NetTxToken<'a> {
             len,
             // tx_buf.packet()
         );
-        dev.transmit(tx_buf).unwrap();
+        match dev.transmit(tx_buf) {
+            Ok(()) => self.1.tx_packet(len),
+            Err(err) => {
+                warn!("transmit failed: {:?}", err);
+                self.1.tx_error();
+            }
+        }
         ret
     }
 }
 
+/// Validate a received packet, which smoltcp would drop silently if it is
+/// malformed, and create a socket for the first SYN to a listened port, as the
+/// later accept() returns.
 fn snoop_tcp_packet(
     buf: &[u8],
     sockets: &mut SocketSet<'_>,
-    is_ethernet: bool,
-) -> Result<(), smoltcp::wire::Error> {
-    use smoltcp::wire::{EthernetFrame, IpProtocol, Ipv4Packet, TcpPacket};
-
-    // let ether_frame = EthernetFrame::new_checked(buf)?;
-    // let ipv4_packet = Ipv4Packet::new_checked(ether_frame.payload())?;
-    let ipv4_packet = if is_ethernet {
-        let ether_frame = EthernetFrame::new_checked(buf)?;
-        Ipv4Packet::new_checked(ether_frame.payload())?
+    caps: &DeviceCapabilities,
+    stats: &NetStats,
+) -> Result<(), RxError> {
+    use smoltcp::wire::{
+        EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, TcpPacket, UdpPacket,
+    };
+
+    let buf = if caps.medium == Medium::Ethernet {
+        let ether_frame =
+            EthernetFrame::new_checked(buf).map_err(|_| RxError::Length(Proto::Link))?;
+        match ether_frame.ethertype() {
+            EthernetProtocol::Ipv4 => ether_frame.payload(),
+            EthernetProtocol::Arp | EthernetProtocol::Ipv6 => return Ok(()),
+            _ => return Err(RxError::UnknownProto),
+        }
     } else {
-        Ipv4Packet::new_checked(buf)?
+        match buf.first().map(|byte| byte >> 4) {
+            Some(4) => buf,
+            Some(6) => return Ok(()),
+            Some(_) => return Err(RxError::UnknownProto),
+            None => return Err(RxError::Length(Proto::Ip)),
+        }
     };
-    if ipv4_packet.next_header() == IpProtocol::Tcp {
-        let tcp_packet = TcpPacket::new_checked(ipv4_packet.payload())?;
-        let src_addr = (ipv4_packet.src_addr(), tcp_packet.src_port()).into();
-        let dst_addr = (ipv4_packet.dst_addr(), tcp_packet.dst_port()).into();
-        let is_first = tcp_packet.syn() && !tcp_packet.ack();
-        if is_first {
-            // create a socket for the first incoming TCP packet, as the later accept()
-            // returns.
-            info!("[snoop_tcp_packet] receive TCP");
-            LISTEN_TABLE.incoming_tcp_packet(src_addr, dst_addr, sockets);
+    stats.rx_proto(Proto::Ip);
+    let ipv4_packet = Ipv4Packet::new_checked(buf).map_err(|_| RxError::Length(Proto::Ip))?;
+    if caps.checksum.ipv4.rx() && !ipv4_packet.verify_checksum() {
+        return Err(RxError::Checksum(Proto::Ip));
+    }
+    let src_ip = IpAddress::Ipv4(ipv4_packet.src_addr());
+    let dst_ip = IpAddress::Ipv4(ipv4_packet.dst_addr());
+    match ipv4_packet.next_header() {
+        IpProtocol::Tcp => {
+            stats.rx_proto(Proto::Tcp);
+            let tcp_packet = TcpPacket::new_checked(ipv4_packet.payload())
+                .map_err(|_| RxError::Length(Proto::Tcp))?;
+            if caps.checksum.tcp.rx() && !tcp_packet.verify_checksum(&src_ip, &dst_ip) {
+                return Err(RxError::Checksum(Proto::Tcp));
+            }
+            let is_first = tcp_packet.syn() && !tcp_packet.ack();
+            if is_first {
+                // create a socket for the first incoming TCP packet, as the later accept()
+                // returns.
+                info!("[snoop_tcp_packet] receive TCP");
+                let src_addr = (src_ip, tcp_packet.src_port()).into();
+                let dst_addr = (dst_ip, tcp_packet.dst_port()).into();
+                if !LISTEN_TABLE.incoming_tcp_packet(src_addr, dst_addr, sockets) {
+                    stats.rx_no_socket();
+                }
+            }
+        }
+        IpProtocol::Udp => {
+            stats.rx_proto(Proto::Udp);
+            let udp_packet = UdpPacket::new_checked(ipv4_packet.payload())
+                .map_err(|_| RxError::Length(Proto::Udp))?;
+            if caps.checksum.udp.rx() && !udp_packet.verify_checksum(&src_ip, &dst_ip) {
+                return Err(RxError::Checksum(Proto::Udp));
+            }
         }
+        IpProtocol::Icmp | IpProtocol::Igmp => {}
+        _ => return Err(RxError::UnknownProto),
     }
     Ok(())
 }
@@ -513,23 +600,54 @@ pub struct NetPollState {
 
 /// Benchmark raw socket transmit bandwidth.
 pub fn bench_transmit() {
-    ETH0.get().unwrap().dev.lock().bench_transmit_bandwidth();
+    let eth0 = ETH0.get().unwrap();
+    eth0.dev.lock().bench_transmit_bandwidth();
+    info!("[bench_transmit] {}: {}", eth0.name, eth0.stats.counters());
 }
 
 /// Benchmark raw socket receive bandwidth.
 pub fn bench_receive() {
-    ETH0.get().unwrap().dev.lock().bench_receive_bandwidth();
+    let eth0 = ETH0.get().unwrap();
+    eth0.dev.lock().bench_receive_bandwidth();
+    info!("[bench_receive] {}: {}", eth0.name, eth0.stats.counters());
+}
+
+/// Name and counters of the network interface, if it is initialized.
+pub fn interface_stats() -> Option<(&'static str, NetCounters)> {
+    ETH0.get().map(|eth0| (eth0.name, eth0.stats.counters()))
 }
 
 #[crate_interface::def_interface]
 pub trait HasSignalIf: Send + Sync {
+    /// Whether the current task has pending signals that are not blocked.
     fn has_signal() -> bool;
+    /// Let unblocked signals wake the current task when it is suspended.
+    fn set_interruptable();
+    /// Undo `set_interruptable`.
+    fn set_running();
 }
 
 pub(crate) fn has_signal() -> bool {
     call_interface!(HasSignalIf::has_signal())
 }
 
+/// Keeps the current task interruptable while a blocking socket operation is
+/// waiting, so that a signal sent to it will wake the wait up.
+pub(crate) struct InterruptGuard;
+
+impl InterruptGuard {
+    pub(crate) fn new() -> Self {
+        call_interface!(HasSignalIf::set_interruptable());
+        Self
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        call_interface!(HasSignalIf::set_running());
+    }
+}
+
 // 下面是来自系统调用的how flag
 pub const SHUT_RD: u8 = 0;
 pub const SHUT_WR: u8 = 1;
@@ -547,28 +665,105 @@ pub fn init_network(net_dev: Box<dyn NetDevice>, is_loopback: bool) {
     let ether_addr = EthernetAddress(net_dev.mac_address().0);
     let eth0 = InterfaceWrapper::new("eth0", net_dev, ether_addr);
 
-    // let ip = IP.parse().expect("invalid IP address");
-
-    // let gateway = GATEWAY.parse().expect("invalid gateway IP address");
-    let gateway = GATEWAY.parse().unwrap();
+    let cmdline = config::cmdline();
+    let gateway = cmdline
+        .get("gw")
+        .unwrap_or(GATEWAY)
+        .parse()
+        .expect("invalid gateway IP address");
     let ip;
     let ip_addrs = if is_loopback {
         ip = "127.0.0.1".parse().unwrap();
         vec![IpCidr::new(ip, 8)]
     } else {
-        ip = IP.parse().expect("invalid IP address");
-        vec![
-            IpCidr::new(IP.parse().unwrap(), 8),
-            IpCidr::new(ip, IP_PREFIX),
-        ]
+        ip = cmdline
+            .get("ip")
+            .unwrap_or(IP)
+            .parse()
+            .expect("invalid IP address");
+        vec![IpCidr::new(ip, 8), IpCidr::new(ip, IP_PREFIX)]
     };
     eth0.setup_ip_addr(ip_addrs);
     eth0.setup_gateway(gateway);
 
     ETH0.call_once(|| eth0);
+    TIMER_MANAGER.add_timer(Timer::new(
+        get_time_duration() + SYN_REAP_INTERVAL,
+        Box::new(SynReaper),
+    ));
 
     info!("created net interface {:?}:", ETH0.get().unwrap().name());
     info!("  ether:    {}", ETH0.get().unwrap().ethernet_address());
     info!("  ip:       {}/{}", ip, IP_PREFIX);
     info!("  gateway:  {}", gateway);
 }
+
+/// Check that frames with a bad IPv4 checksum or a truncated IPv4 header are
+/// counted as receive errors, and a valid UDP datagram is not, by injecting
+/// them through the loopback device.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    use smoltcp::wire::{IpProtocol, Ipv4Packet, UdpPacket};
+
+    const IPV4_HEADER_LEN: usize = 20;
+    const UDP_LEN: usize = 8 + 4;
+
+    let Some(eth0) = ETH0.get() else {
+        return;
+    };
+    if eth0.dev.lock().capabilities().medium != Medium::Ip {
+        info!("[net] selftest skipped: not a loopback device");
+        return;
+    }
+    let inject = |packet: &[u8]| {
+        {
+            let dev = eth0.dev.lock();
+            let mut dev = dev.inner.borrow_mut();
+            let mut tx_buf = dev.alloc_tx_buffer(packet.len()).unwrap();
+            tx_buf.packet_mut().copy_from_slice(packet);
+            dev.transmit(tx_buf).unwrap();
+        }
+        poll_interfaces();
+    };
+
+    let loopback = IpAddress::v4(127, 0, 0, 1);
+    let mut packet = [0u8; IPV4_HEADER_LEN + UDP_LEN];
+    {
+        let mut udp = UdpPacket::new_unchecked(&mut packet[IPV4_HEADER_LEN..]);
+        udp.set_src_port(9);
+        udp.set_dst_port(9);
+        udp.set_len(UDP_LEN as u16);
+        udp.payload_mut().copy_from_slice(b"ping");
+        udp.fill_checksum(&loopback, &loopback);
+    }
+    let mut ip = Ipv4Packet::new_unchecked(&mut packet[..]);
+    ip.set_version(4);
+    ip.set_header_len(IPV4_HEADER_LEN as u8);
+    ip.set_total_len((IPV4_HEADER_LEN + UDP_LEN) as u16);
+    ip.set_hop_limit(64);
+    ip.set_next_header(IpProtocol::Udp);
+    ip.set_src_addr(Ipv4Address::new(127, 0, 0, 1));
+    ip.set_dst_addr(Ipv4Address::new(127, 0, 0, 1));
+    ip.fill_checksum();
+
+    let before = eth0.stats.counters();
+    inject(&packet);
+    let valid = eth0.stats.counters();
+    assert!(valid.udp.received > before.udp.received);
+    assert_eq!(valid.rx_errors, before.rx_errors);
+
+    let mut corrupted = packet;
+    // the hop limit, which is covered by the header checksum
+    corrupted[8] ^= 0xff;
+    inject(&corrupted);
+    let after = eth0.stats.counters();
+    assert!(after.rx_csum_errors > valid.rx_csum_errors);
+    assert!(after.ip.csum_errors > valid.ip.csum_errors);
+
+    let mut truncated = packet;
+    Ipv4Packet::new_unchecked(&mut truncated[..]).set_total_len(64);
+    inject(&truncated);
+    let last = eth0.stats.counters();
+    assert!(last.rx_length_errors > after.rx_length_errors);
+    assert!(last.rx_errors >= valid.rx_errors + 2);
+}
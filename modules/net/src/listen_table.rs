@@ -1,9 +1,12 @@
-use alloc::{boxed::Box, collections::VecDeque};
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
 use core::{
     ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
     task::Waker,
+    time::Duration,
 };
 
+use arch::time::get_time_duration;
 use log::*;
 use smoltcp::{
     iface::{SocketHandle, SocketSet},
@@ -11,12 +14,54 @@ use smoltcp::{
     wire::{IpAddress, IpEndpoint, IpListenEndpoint},
 };
 use systype::{SysError, SysResult};
+use timer::{Timer, TimerEvent};
 
-use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, SOCKET_SET};
-use crate::Mutex;
+use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, LISTEN_TABLE, SOCKET_SET};
+use crate::lock_order::{LockRank, RankedMutex};
 
 const PORT_NUM: usize = 65536;
 
+/// Embryonic connections, i.e. those that have not completed the handshake, in
+/// the SYN queue of each listener.
+const SYN_BACKLOG: usize = 32;
+/// Embryonic connections of all listeners. Each of them holds the buffers of a
+/// TCP socket, so this bounds the memory taken by a SYN flood.
+const MAX_EMBRYONIC: usize = 64;
+/// Time for an embryonic connection to complete the handshake before it is
+/// dropped.
+const SYN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Interval at which the SYN queues are aged.
+pub(crate) const SYN_REAP_INTERVAL: Duration = Duration::from_secs(1);
+/// Sockets of dropped embryonic connections kept for the next SYNs, so that a
+/// SYN flood does not allocate their buffers over and over.
+const MAX_SPARE_SOCKETS: usize = 16;
+
+/// Number of embryonic connections of all listeners.
+static EMBRYONIC: AtomicUsize = AtomicUsize::new(0);
+/// Handles of the sockets kept for reuse, which stay closed in the socket set.
+static SPARE_SOCKETS: RankedMutex<Vec<SocketHandle>> =
+    RankedMutex::new(LockRank::SpareSockets, Vec::new());
+
+/// A connection in the SYN queue.
+struct SynQueueEntry {
+    handle: SocketHandle,
+    /// Time when the first SYN arrived.
+    created: Duration,
+    /// Whether the connection is counted in `EMBRYONIC`, until it is found
+    /// connected.
+    embryonic: bool,
+}
+
+impl SynQueueEntry {
+    /// Stop counting the connection as embryonic.
+    fn settle(&mut self) {
+        if self.embryonic {
+            self.embryonic = false;
+            EMBRYONIC.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
 /// An entry in the listen table, representing a specific listening endpoint.
 ///
 /// This struct holds the information related to a specific listening IP address
@@ -25,22 +70,55 @@ const PORT_NUM: usize = 65536;
 struct ListenTableEntry {
     /// The IP address and port being listened on.
     listen_endpoint: IpListenEndpoint,
-    /// The SYN queue holding incoming TCP connection handles.
-    syn_queue: VecDeque<SocketHandle>,
+    /// The SYN queue holding incoming TCP connections, both embryonic and
+    /// connected.
+    syn_queue: VecDeque<SynQueueEntry>,
+    /// Connected ones in the SYN queue allowed, i.e. the backlog of listen().
+    backlog: usize,
     /// The waker used to wake up the listening socket when a new connection
     /// arrives.
     waker: Waker,
 }
 
 impl ListenTableEntry {
-    pub fn new(listen_endpoint: IpListenEndpoint, waker: &Waker) -> Self {
+    pub fn new(listen_endpoint: IpListenEndpoint, waker: &Waker, backlog: usize) -> Self {
         Self {
             listen_endpoint,
-            syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            syn_queue: VecDeque::new(),
+            backlog: backlog.clamp(1, LISTEN_QUEUE_SIZE),
             waker: waker.clone(),
         }
     }
 
+    /// Settle the connections that have become connected, and drop the
+    /// embryonic ones that have not completed the handshake within
+    /// `SYN_TIMEOUT`. Return the number of embryonic connections left.
+    fn reap(&mut self, sockets: &mut SocketSet<'_>, now: Duration) -> usize {
+        let port = self.listen_endpoint.port;
+        let mut embryonic = 0;
+        self.syn_queue.retain_mut(|conn| {
+            if !conn.embryonic {
+                return true;
+            }
+            if is_connected(sockets, conn.handle) {
+                conn.settle();
+                return true;
+            }
+            if now.saturating_sub(conn.created) < SYN_TIMEOUT {
+                embryonic += 1;
+                return true;
+            }
+            debug!(
+                "TCP socket {}: handshake timed out on port {}",
+                conn.handle, port
+            );
+            conn.settle();
+            recycle_socket(sockets, conn.handle);
+            false
+        });
+        embryonic
+    }
+
     #[inline]
     /// Linux内核有一个特殊的机制，叫做 IPv4-mapped IPv6
     /// addresses，允许IPv6套接字接收IPv4连接
@@ -75,10 +153,13 @@ impl ListenTableEntry {
     }
 }
 
+/// Must not be dropped with the listen table locked, as the socket set is
+/// locked to remove the sockets in the SYN queue.
 impl Drop for ListenTableEntry {
     fn drop(&mut self) {
-        for &handle in &self.syn_queue {
-            SOCKET_SET.remove(handle);
+        for conn in self.syn_queue.iter_mut() {
+            conn.settle();
+            SOCKET_SET.remove(conn.handle);
         }
     }
 }
@@ -93,7 +174,10 @@ impl Drop for ListenTableEntry {
 pub struct ListenTable {
     /// An array of Mutexes, each protecting an optional ListenTableEntry for a
     /// specific port.
-    tcp: Box<[Mutex<Option<Box<ListenTableEntry>>>]>,
+    tcp: Box<[RankedMutex<Option<Box<ListenTableEntry>>>]>,
+    /// Bitmap of the ports listened on, so that packets to other ports are
+    /// passed without taking the locks of their entries.
+    listening: Box<[AtomicU64]>,
 }
 
 impl ListenTable {
@@ -101,23 +185,49 @@ impl ListenTable {
         let tcp = unsafe {
             let mut buf = Box::new_uninit_slice(PORT_NUM);
             for i in 0..PORT_NUM {
-                buf[i].write(Mutex::new(None));
+                buf[i].write(RankedMutex::new(LockRank::ListenTable, None));
             }
             buf.assume_init()
         };
-        Self { tcp }
+        let listening = (0..PORT_NUM / 64).map(|_| AtomicU64::new(0)).collect();
+        Self { tcp, listening }
+    }
+
+    fn is_listening(&self, port: u16) -> bool {
+        let port = port as usize;
+        self.listening[port / 64].load(Ordering::Relaxed) & (1 << (port % 64)) != 0
+    }
+
+    fn set_listening(&self, port: u16, listening: bool) {
+        let port = port as usize;
+        let bit = 1 << (port % 64);
+        if listening {
+            self.listening[port / 64].fetch_or(bit, Ordering::Relaxed);
+        } else {
+            self.listening[port / 64].fetch_and(!bit, Ordering::Relaxed);
+        }
     }
 
     pub fn can_listen(&self, port: u16) -> bool {
         self.tcp[port as usize].lock().is_none()
     }
 
-    pub fn listen(&self, listen_endpoint: IpListenEndpoint, waker: &Waker) -> SysResult<()> {
+    pub fn listen(
+        &self,
+        listen_endpoint: IpListenEndpoint,
+        waker: &Waker,
+        backlog: usize,
+    ) -> SysResult<()> {
         let port = listen_endpoint.port;
         assert_ne!(port, 0);
         let mut entry = self.tcp[port as usize].lock();
         if entry.is_none() {
-            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint, waker)));
+            *entry = Some(Box::new(ListenTableEntry::new(
+                listen_endpoint,
+                waker,
+                backlog,
+            )));
+            self.set_listening(port, true);
             Ok(())
         } else {
             warn!("socket listen() failed");
@@ -127,33 +237,54 @@ impl ListenTable {
 
     pub fn unlisten(&self, port: u16) {
         info!("TCP socket unlisten on {}", port);
-        if let Some(entry) = self.tcp[port as usize].lock().take() {
+        // the entry is dropped after the listen table is unlocked
+        let entry = {
+            let mut entry = self.tcp[port as usize].lock();
+            self.set_listening(port, false);
+            entry.take()
+        };
+        if let Some(entry) = entry {
             entry.wake()
         }
         // *self.tcp[port as usize].lock() = None;
     }
 
     pub fn can_accept(&self, port: u16) -> bool {
-        if let Some(entry) = self.tcp[port as usize].lock().deref() {
-            entry.syn_queue.iter().any(|&handle| is_connected(handle))
-        } else {
-            // 因为在listen函数调用时已经将port设为监听状态了，这里应该不会查不到？？
-            error!("socket accept() failed: not listen. I think this wouldn't happen !!!");
-            false
-            // Err(SysError::EINVAL)
-        }
+        // the sockets are locked before the listen table
+        SOCKET_SET.with_sockets(|sockets| {
+            if let Some(entry) = self.tcp[port as usize].lock().deref() {
+                entry
+                    .syn_queue
+                    .iter()
+                    .any(|conn| is_connected(sockets, conn.handle))
+            } else {
+                // 因为在listen函数调用时已经将port设为监听状态了，这里应该不会查不到？？
+                error!("socket accept() failed: not listen. I think this wouldn't happen !!!");
+                false
+                // Err(SysError::EINVAL)
+            }
+        })
     }
 
     /// 检查端口上的SYN队列，找到已经建立连接的句柄，并将其从队列中取出，
     /// 返回给调用者。
     pub fn accept(&self, port: u16) -> SysResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
+        SOCKET_SET.with_sockets(|sockets| self.accept_locked(sockets, port))
+    }
+
+    fn accept_locked(
+        &self,
+        sockets: &SocketSet<'_>,
+        port: u16,
+    ) -> SysResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
         if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
             let syn_queue = &mut entry.syn_queue;
             let (idx, addr_tuple) = syn_queue
                 .iter()
                 .enumerate()
-                .find_map(|(idx, &handle)| {
-                    is_connected(handle).then(|| (idx, get_addr_tuple(handle)))
+                .find_map(|(idx, conn)| {
+                    is_connected(sockets, conn.handle)
+                        .then(|| (idx, get_addr_tuple(sockets, conn.handle)))
                 })
                 .ok_or(SysError::EAGAIN)?; // wait for connection
 
@@ -171,63 +302,136 @@ impl ListenTable {
                     syn_queue.len()
                 );
             }
-            let handle = syn_queue.swap_remove_front(idx).unwrap();
-            Ok((handle, addr_tuple))
+            let mut conn = syn_queue.swap_remove_front(idx).unwrap();
+            conn.settle();
+            Ok((conn.handle, addr_tuple))
         } else {
             warn!("socket accept() failed: not listen");
             Err(SysError::EINVAL)
         }
     }
 
+    /// Prepare a socket for the first SYN of a connection. Return false if no
+    /// socket listens on its address and port.
     pub fn incoming_tcp_packet(
         &self,
         src: IpEndpoint,
         dst: IpEndpoint,
         sockets: &mut SocketSet<'_>,
-    ) {
-        if let Some(entry) = self.tcp[dst.port as usize].lock().deref_mut() {
-            if !entry.can_accept(dst.addr) {
-                // not listening on this address
-                warn!(
-                    "[ListenTable::incoming_tcp_packet] not listening on address {}",
-                    dst.addr
-                );
-                return;
-            }
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
-                // SYN queue is full, drop the packet
-                warn!("SYN queue overflow!");
-                return;
-            }
-            entry.waker.wake_by_ref();
+    ) -> bool {
+        if !self.is_listening(dst.port) {
+            return false;
+        }
+        let mut guard = self.tcp[dst.port as usize].lock();
+        let Some(entry) = guard.deref_mut() else {
+            return false;
+        };
+        if !entry.can_accept(dst.addr) {
+            // not listening on this address
+            warn!(
+                "[ListenTable::incoming_tcp_packet] not listening on address {}",
+                dst.addr
+            );
+            return false;
+        }
+        let embryonic = entry.reap(sockets, get_time_duration());
+        if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE
+            || entry.syn_queue.len() - embryonic >= entry.backlog
+        {
+            // SYN queue is full, drop the packet
+            warn!("SYN queue overflow!");
+            return true;
+        }
+        if embryonic >= SYN_BACKLOG || EMBRYONIC.load(Ordering::Relaxed) >= MAX_EMBRYONIC {
+            // too many half-open connections, e.g. under a SYN flood
+            warn!(
+                "[ListenTable::incoming_tcp_packet] drop SYN from {} to port {}: too many embryonic connections",
+                src, dst.port
+            );
+            return true;
+        }
+        entry.waker.wake_by_ref();
+        info!(
+            "[ListenTable::incoming_tcp_packet] wake the socket who listens port {}",
+            dst.port
+        );
+        let handle = SPARE_SOCKETS
+            .lock()
+            .pop()
+            .unwrap_or_else(|| sockets.add(SocketSetWrapper::new_tcp_socket()));
+        if sockets
+            .get_mut::<tcp::Socket>(handle)
+            .listen(entry.listen_endpoint)
+            .is_ok()
+        {
             info!(
-                "[ListenTable::incoming_tcp_packet] wake the socket who listens port {}",
-                dst.port
+                "TCP socket {}: prepare for connection {} -> {}",
+                handle, src, entry.listen_endpoint
             );
-            let mut socket = SocketSetWrapper::new_tcp_socket();
-            if socket.listen(entry.listen_endpoint).is_ok() {
-                let handle = sockets.add(socket);
-                info!(
-                    "TCP socket {}: prepare for connection {} -> {}",
-                    handle, src, entry.listen_endpoint
-                );
-                entry.syn_queue.push_back(handle);
+            EMBRYONIC.fetch_add(1, Ordering::Relaxed);
+            entry.syn_queue.push_back(SynQueueEntry {
+                handle,
+                created: get_time_duration(),
+                embryonic: true,
+            });
+        } else {
+            recycle_socket(sockets, handle);
+        }
+        true
+    }
+
+    /// Age the SYN queues of all listeners.
+    fn reap(&self, sockets: &mut SocketSet<'_>) {
+        let now = get_time_duration();
+        for (i, word) in self.listening.iter().enumerate() {
+            let mut bits = word.load(Ordering::Relaxed);
+            while bits != 0 {
+                let port = i * 64 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                if let Some(entry) = self.tcp[port].lock().deref_mut() {
+                    entry.reap(sockets, now);
+                }
             }
         }
     }
 }
 
-fn is_connected(handle: SocketHandle) -> bool {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
-        !matches!(socket.state(), State::Listen | State::SynReceived)
-    })
+/// Periodic timer to age the SYN queues, so that embryonic connections are
+/// dropped even if no more SYNs arrive.
+pub(crate) struct SynReaper;
+
+impl TimerEvent for SynReaper {
+    fn callback(self: Box<Self>) -> Option<Timer> {
+        if EMBRYONIC.load(Ordering::Relaxed) != 0 {
+            // the sockets are locked before the listen table
+            SOCKET_SET.with_sockets(|sockets| LISTEN_TABLE.reap(sockets));
+        }
+        Some(Timer::new(get_time_duration() + SYN_REAP_INTERVAL, self))
+    }
+}
+
+/// Abort the socket of a dropped embryonic connection, and keep it for reuse
+/// if there are not enough spare ones.
+fn recycle_socket(sockets: &mut SocketSet<'_>, handle: SocketHandle) {
+    sockets.get_mut::<tcp::Socket>(handle).abort();
+    let mut spare = SPARE_SOCKETS.lock();
+    if spare.len() < MAX_SPARE_SOCKETS {
+        spare.push(handle);
+    } else {
+        drop(spare);
+        sockets.remove(handle);
+    }
+}
+
+fn is_connected(sockets: &SocketSet<'_>, handle: SocketHandle) -> bool {
+    let socket = sockets.get::<tcp::Socket>(handle);
+    !matches!(socket.state(), State::Listen | State::SynReceived)
 }
 
-fn get_addr_tuple(handle: SocketHandle) -> (IpEndpoint, IpEndpoint) {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
-        (
-            socket.local_endpoint().unwrap(),
-            socket.remote_endpoint().unwrap(),
-        )
-    })
+fn get_addr_tuple(sockets: &SocketSet<'_>, handle: SocketHandle) -> (IpEndpoint, IpEndpoint) {
+    let socket = sockets.get::<tcp::Socket>(handle);
+    (
+        socket.local_endpoint().unwrap(),
+        socket.remote_endpoint().unwrap(),
+    )
 }
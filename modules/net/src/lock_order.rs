@@ -0,0 +1,110 @@
+//! Lock hierarchy of the network stack.
+//!
+//! The locks are always taken in the order of `LockRank`, i.e. the interface,
+//! then the device, then the socket set, then an entry of the listen table,
+//! then the spare sockets of the listen table, skipping any of them. Polling
+//! the interface takes the first three, and `RxToken::preprocess` takes the
+//! listen table while they are held.
+//!
+//! In debug builds, each hart records the ranks it holds, and taking a lock
+//! not ranked higher than all of them panics, before it could deadlock.
+
+use core::ops::{Deref, DerefMut};
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(debug_assertions)]
+use config::board::MAX_HARTS;
+use sync::mutex::{spin_mutex::MutexGuard, SpinNoIrq};
+
+use crate::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LockRank {
+    Iface = 0,
+    Dev = 1,
+    Sockets = 2,
+    ListenTable = 3,
+    SpareSockets = 4,
+}
+
+#[cfg(debug_assertions)]
+const HELD_RANKS_EACH: AtomicU8 = AtomicU8::new(0);
+/// Ranks held by each hart, as bit masks. Only modified by the hart itself
+/// with interrupts disabled by the locks.
+#[cfg(debug_assertions)]
+static HELD_RANKS: [AtomicU8; MAX_HARTS] = [HELD_RANKS_EACH; MAX_HARTS];
+
+#[cfg(debug_assertions)]
+fn local_held_ranks() -> Option<&'static AtomicU8> {
+    arch::entry::kernel_stack_owner(arch::register::sp()).map(|hart_id| &HELD_RANKS[hart_id])
+}
+
+/// A `SpinNoIrqLock` with a rank in the lock hierarchy.
+pub(crate) struct RankedMutex<T> {
+    rank: LockRank,
+    inner: Mutex<T>,
+}
+
+pub(crate) struct RankedMutexGuard<'a, T> {
+    #[cfg(debug_assertions)]
+    rank: LockRank,
+    inner: MutexGuard<'a, T, SpinNoIrq>,
+}
+
+impl<T> RankedMutex<T> {
+    pub const fn new(rank: LockRank, data: T) -> Self {
+        Self {
+            rank,
+            inner: Mutex::new(data),
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> RankedMutexGuard<'_, T> {
+        #[cfg(debug_assertions)]
+        if let Some(held) = local_held_ranks() {
+            let held = held.load(Ordering::Relaxed);
+            assert!(
+                held >> self.rank as u8 == 0,
+                "lock order violated: taking {:?} while holding ranks {:#06b}",
+                self.rank,
+                held
+            );
+        }
+        let inner = self.inner.lock();
+        // interrupts are disabled from now on
+        #[cfg(debug_assertions)]
+        if let Some(held) = local_held_ranks() {
+            held.fetch_or(1 << self.rank as u8, Ordering::Relaxed);
+        }
+        RankedMutexGuard {
+            #[cfg(debug_assertions)]
+            rank: self.rank,
+            inner,
+        }
+    }
+}
+
+impl<'a, T> Drop for RankedMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        if let Some(held) = local_held_ranks() {
+            held.fetch_and(!(1 << self.rank as u8), Ordering::Relaxed);
+        }
+    }
+}
+
+impl<'a, T> Deref for RankedMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T> DerefMut for RankedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,238 @@
+//! Statistics of the network interface.
+//!
+//! Packets are counted as the device receives and transmits them, and each
+//! received IPv4 packet is validated before smoltcp processes it, since smoltcp
+//! drops malformed packets silently. The counters are read from /proc/net/dev
+//! and /proc/net/snmp.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use arch::time::get_time_us;
+use log::*;
+
+/// Receive errors in a second above which a warning is logged, at most once a
+/// second.
+const RX_ERROR_RATE_THRESHOLD: usize = 100;
+const RX_ERROR_WINDOW_US: usize = 1_000_000;
+
+/// Protocol layer at which a received packet is found malformed.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Proto {
+    Link,
+    Ip,
+    Tcp,
+    Udp,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum RxError {
+    Checksum(Proto),
+    /// The packet is shorter than its headers or than the length in them.
+    Length(Proto),
+    /// Neither an IP protocol nor an ether type handled.
+    UnknownProto,
+}
+
+/// Counters of a protocol, as in /proc/net/snmp.
+#[derive(Default)]
+struct ProtoStats {
+    received: AtomicUsize,
+    errors: AtomicUsize,
+    csum_errors: AtomicUsize,
+}
+
+impl ProtoStats {
+    fn counters(&self) -> ProtoCounters {
+        ProtoCounters {
+            received: self.received.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            csum_errors: self.csum_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counters of an interface, shared by the interface and its device.
+#[derive(Default)]
+pub(crate) struct NetStats {
+    rx_packets: AtomicUsize,
+    rx_bytes: AtomicUsize,
+    rx_errors: AtomicUsize,
+    rx_csum_errors: AtomicUsize,
+    rx_length_errors: AtomicUsize,
+    rx_unknown_proto: AtomicUsize,
+    rx_no_socket: AtomicUsize,
+    tx_packets: AtomicUsize,
+    tx_bytes: AtomicUsize,
+    tx_errors: AtomicUsize,
+    ip: ProtoStats,
+    ip_unknown_protos: AtomicUsize,
+    tcp: ProtoStats,
+    udp: ProtoStats,
+    /// Start of the window in which the receive errors are counted for the
+    /// warning, in microseconds.
+    error_window_start: AtomicUsize,
+    error_window_count: AtomicUsize,
+}
+
+impl NetStats {
+    pub fn rx_packet(&self, len: usize) {
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        self.rx_bytes.fetch_add(len, Ordering::Relaxed);
+    }
+
+    pub fn tx_packet(&self, len: usize) {
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        self.tx_bytes.fetch_add(len, Ordering::Relaxed);
+    }
+
+    pub fn tx_error(&self) {
+        self.tx_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A packet of `proto` is received, before it is validated.
+    pub fn rx_proto(&self, proto: Proto) {
+        if let Some(stats) = self.proto(proto) {
+            stats.received.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A SYN is received for a port that no socket listens on.
+    pub fn rx_no_socket(&self) {
+        self.rx_no_socket.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The device fails to receive a packet.
+    pub fn rx_dev_error(&self) {
+        self.rx_errors.fetch_add(1, Ordering::Relaxed);
+        self.check_error_rate();
+    }
+
+    pub fn rx_error(&self, err: RxError) {
+        self.rx_errors.fetch_add(1, Ordering::Relaxed);
+        match err {
+            RxError::Checksum(proto) => {
+                self.rx_csum_errors.fetch_add(1, Ordering::Relaxed);
+                if let Some(stats) = self.proto(proto) {
+                    stats.errors.fetch_add(1, Ordering::Relaxed);
+                    stats.csum_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            RxError::Length(proto) => {
+                self.rx_length_errors.fetch_add(1, Ordering::Relaxed);
+                if let Some(stats) = self.proto(proto) {
+                    stats.errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            RxError::UnknownProto => {
+                self.rx_unknown_proto.fetch_add(1, Ordering::Relaxed);
+                self.ip_unknown_protos.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.check_error_rate();
+    }
+
+    fn proto(&self, proto: Proto) -> Option<&ProtoStats> {
+        match proto {
+            Proto::Link => None,
+            Proto::Ip => Some(&self.ip),
+            Proto::Tcp => Some(&self.tcp),
+            Proto::Udp => Some(&self.udp),
+        }
+    }
+
+    /// Warn once the receive errors in the current window reach the
+    /// threshold.
+    fn check_error_rate(&self) {
+        let now = get_time_us();
+        let start = self.error_window_start.load(Ordering::Relaxed);
+        if now.saturating_sub(start) >= RX_ERROR_WINDOW_US
+            && self
+                .error_window_start
+                .compare_exchange(start, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.error_window_count.store(0, Ordering::Relaxed);
+        }
+        if self.error_window_count.fetch_add(1, Ordering::Relaxed) + 1 == RX_ERROR_RATE_THRESHOLD {
+            warn!(
+                "[net] {} receive errors within a second: {} in total, {} checksum, {} length, {} unknown protocol",
+                RX_ERROR_RATE_THRESHOLD,
+                self.rx_errors.load(Ordering::Relaxed),
+                self.rx_csum_errors.load(Ordering::Relaxed),
+                self.rx_length_errors.load(Ordering::Relaxed),
+                self.rx_unknown_proto.load(Ordering::Relaxed),
+            );
+        }
+    }
+
+    pub fn counters(&self) -> NetCounters {
+        NetCounters {
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            rx_errors: self.rx_errors.load(Ordering::Relaxed),
+            rx_csum_errors: self.rx_csum_errors.load(Ordering::Relaxed),
+            rx_length_errors: self.rx_length_errors.load(Ordering::Relaxed),
+            rx_unknown_proto: self.rx_unknown_proto.load(Ordering::Relaxed),
+            rx_no_socket: self.rx_no_socket.load(Ordering::Relaxed),
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+            tx_errors: self.tx_errors.load(Ordering::Relaxed),
+            ip: self.ip.counters(),
+            ip_unknown_protos: self.ip_unknown_protos.load(Ordering::Relaxed),
+            tcp: self.tcp.counters(),
+            udp: self.udp.counters(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProtoCounters {
+    /// Packets received, including the malformed ones.
+    pub received: usize,
+    /// Malformed packets, including those with bad checksums.
+    pub errors: usize,
+    pub csum_errors: usize,
+}
+
+/// A snapshot of the counters of an interface.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NetCounters {
+    pub rx_packets: usize,
+    pub rx_bytes: usize,
+    /// Packets dropped as malformed or failed to be received by the device.
+    pub rx_errors: usize,
+    pub rx_csum_errors: usize,
+    pub rx_length_errors: usize,
+    pub rx_unknown_proto: usize,
+    /// SYNs dropped as no socket listens on their ports.
+    pub rx_no_socket: usize,
+    pub tx_packets: usize,
+    pub tx_bytes: usize,
+    pub tx_errors: usize,
+    pub ip: ProtoCounters,
+    pub ip_unknown_protos: usize,
+    pub tcp: ProtoCounters,
+    pub udp: ProtoCounters,
+}
+
+impl fmt::Display for NetCounters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rx {} packets {} bytes, {} errors ({} checksum, {} length, {} unknown protocol), {} dropped; tx {} packets {} bytes, {} errors",
+            self.rx_packets,
+            self.rx_bytes,
+            self.rx_errors,
+            self.rx_csum_errors,
+            self.rx_length_errors,
+            self.rx_unknown_proto,
+            self.rx_no_socket,
+            self.tx_packets,
+            self.tx_bytes,
+            self.tx_errors
+        )
+    }
+}
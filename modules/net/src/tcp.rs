@@ -10,7 +10,7 @@ use async_utils::{get_waker, suspend_now, yield_now};
 use log::*;
 use smoltcp::{
     iface::SocketHandle,
-    socket::tcp::{self, ConnectError, State},
+    socket::tcp::{self, State},
     wire::{IpAddress, IpEndpoint, IpListenEndpoint},
 };
 use systype::*;
@@ -21,8 +21,8 @@ use super::{
     SocketSetWrapper, ETH0, LISTEN_TABLE, SOCKET_SET,
 };
 use crate::{
-    addr::UNSPECIFIED_IPV4, has_signal, Mutex, NetPollState, RCV_SHUTDOWN, SEND_SHUTDOWN,
-    SHUTDOWN_MASK, SHUT_RD, SHUT_RDWR, SHUT_WR, TCP_RX_BUF_LEN, TCP_TX_BUF_LEN,
+    addr::UNSPECIFIED_IPV4, has_signal, InterruptGuard, Mutex, NetPollState, RCV_SHUTDOWN,
+    SEND_SHUTDOWN, SHUTDOWN_MASK, SHUT_RD, SHUT_RDWR, SHUT_WR, TCP_RX_BUF_LEN, TCP_TX_BUF_LEN,
 };
 
 // State transitions:
@@ -157,25 +157,15 @@ impl TcpSocket {
 
             // TODO: check remote addr unreachable
             let bound_endpoint = self.bound_endpoint()?;
-            let iface = &ETH0.get().unwrap().iface;
+            // the interface is locked before the sockets
+            let mut iface = ETH0.get().unwrap().iface.lock();
             let (local_endpoint, remote_endpoint) = SOCKET_SET
                 .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
                     socket
-                        .connect(iface.lock().context(), remote_addr, bound_endpoint)
-                        .or_else(|e| match e {
-                            // When attempting to perform an operation, the socket is in an
-                            // invalid state. Such as attempting to call the connection operation
-                            // again on an already connected socket, or performing
-                            // the operation on a closed socket
-                            ConnectError::InvalidState => {
-                                warn!("[TcpSocket::connect] failed: InvalidState");
-                                Err(SysError::EBADF)
-                            }
-                            // The target address or port attempting to connect is unreachable
-                            ConnectError::Unaddressable => {
-                                warn!("[TcpSocket::connect] failed: Unaddressable");
-                                Err(SysError::EADDRNOTAVAIL)
-                            }
+                        .connect(iface.context(), remote_addr, bound_endpoint)
+                        .map_err(|e| {
+                            warn!("[TcpSocket::connect] failed: {e:?}");
+                            SysError::from(e)
                         })?;
                     Ok((
                         socket.local_endpoint().unwrap(),
@@ -255,17 +245,18 @@ impl TcpSocket {
         })
     }
 
-    /// Starts listening on the bound address and port.
+    /// Starts listening on the bound address and port, with at most `backlog`
+    /// connections waiting to be accepted.
     ///
     /// It's must be called after [`bind`](Self::bind) and before
     /// [`accept`](Self::accept).
-    pub fn listen(&self, waker: &Waker) -> SysResult<()> {
+    pub fn listen(&self, waker: &Waker, backlog: usize) -> SysResult<()> {
         self.update_state(STATE_CLOSED, STATE_LISTENING, || {
             let bound_endpoint = self.bound_endpoint()?;
             unsafe {
                 (*self.local_addr.get()).port = bound_endpoint.port;
             }
-            LISTEN_TABLE.listen(bound_endpoint, waker)?;
+            LISTEN_TABLE.listen(bound_endpoint, waker, backlog)?;
             info!("[TcpSocket::listen] listening on {bound_endpoint:?}");
             Ok(())
         })
@@ -379,9 +370,9 @@ impl TcpSocket {
                 } else if socket.recv_queue() > 0 {
                     // data available
                     // TODO: use socket.recv(|buf| {...})
-                    let len = socket.recv_slice(buf).map_err(|_| {
-                        warn!("socket recv() failed, badstate");
-                        SysError::EBADF
+                    let len = socket.recv_slice(buf).map_err(|e| {
+                        warn!("socket recv() failed: {e:?}");
+                        SysError::from(e)
                     })?;
                     Ok(len)
                 } else {
@@ -422,9 +413,8 @@ impl TcpSocket {
                     // connected, and the tx buffer is not full
                     // TODO: use socket.send(|buf| {...})
                     let len = socket.send_slice(buf).map_err(|e| {
-                        error!("socket recv() failed: bad state, {e:?}");
-                        // TODO: Not sure what error should it be
-                        SysError::EBADF
+                        error!("socket send() failed: {e:?}");
+                        SysError::from(e)
                     })?;
                     Ok(len)
                 } else {
@@ -723,6 +713,7 @@ impl TcpSocket {
         if self.is_nonblocking() {
             f()
         } else {
+            let _guard = InterruptGuard::new();
             loop {
                 let timestamp = SOCKET_SET.poll_interfaces();
                 let ret = f();
@@ -730,11 +721,11 @@ impl TcpSocket {
                 match ret {
                     Ok(t) => return Ok(t),
                     Err(SysError::EAGAIN) => {
-                        suspend_now().await;
                         if has_signal() {
                             warn!("[TcpSocket::block_on] has signal");
                             return Err(SysError::EINTR);
                         }
+                        suspend_now().await;
                     }
                     Err(e) => return Err(e),
                 }
@@ -750,6 +741,7 @@ impl TcpSocket {
         if self.is_nonblocking() {
             f().await
         } else {
+            let _guard = InterruptGuard::new();
             loop {
                 let timestamp = SOCKET_SET.poll_interfaces();
                 let ret = f().await;
@@ -757,11 +749,11 @@ impl TcpSocket {
                 match ret {
                     Ok(t) => return Ok(t),
                     Err(SysError::EAGAIN) => {
-                        suspend_now().await;
                         if has_signal() {
                             warn!("[TcpSocket::block_on_async] has signal");
                             return Err(SysError::EINTR);
                         }
+                        suspend_now().await;
                     }
                     Err(e) => return Err(e),
                 }
@@ -8,7 +8,7 @@ use async_utils::{get_waker, suspend_now, yield_now};
 use log::{debug, error, info, warn};
 use smoltcp::{
     iface::SocketHandle,
-    socket::udp::{self, BindError, SendError},
+    socket::udp::{self, SendError},
     wire::{IpAddress, IpEndpoint, IpListenEndpoint},
 };
 use spin::RwLock;
@@ -24,7 +24,7 @@ use crate::{
     },
     has_signal,
     portmap::PORT_MAP,
-    Mutex, NetPollState,
+    InterruptGuard, Mutex, NetPollState,
 };
 
 /// A UDP socket that provides POSIX-like APIs.
@@ -144,11 +144,8 @@ impl UdpSocket {
         // };
         SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
             socket.bind(bound_addr).map_err(|e| {
-                warn!("socket bind() failed");
-                match e {
-                    BindError::InvalidState => SysError::EEXIST,
-                    BindError::Unaddressable => SysError::EINVAL,
-                }
+                warn!("socket bind() failed: {e:?}");
+                SysError::from(e)
             })
         })?;
 
@@ -315,19 +312,13 @@ impl UdpSocket {
             .block_on(|| {
                 SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
                     if socket.can_send() {
-                        socket
-                            .send_slice(buf, remote_endpoint)
-                            .map_err(|e| match e {
-                                SendError::BufferFull => {
-                                    warn!("socket send() failed, {e:?}");
-                                    socket.register_send_waker(&waker);
-                                    SysError::EAGAIN
-                                }
-                                SendError::Unaddressable => {
-                                    warn!("socket send() failed, {e:?}");
-                                    SysError::ECONNREFUSED
-                                }
-                            })?;
+                        socket.send_slice(buf, remote_endpoint).map_err(|e| {
+                            warn!("socket send() failed, {e:?}");
+                            if matches!(e, SendError::BufferFull) {
+                                socket.register_send_waker(&waker);
+                            }
+                            SysError::from(e)
+                        })?;
                         Ok(buf.len())
                     } else {
                         // tx buffer is full
@@ -385,6 +376,7 @@ impl UdpSocket {
         if self.is_nonblocking() {
             f()
         } else {
+            let _guard = InterruptGuard::new();
             loop {
                 let timestamp = SOCKET_SET.poll_interfaces();
                 let ret = f();
@@ -392,11 +384,11 @@ impl UdpSocket {
                 match ret {
                     Ok(t) => return Ok(t),
                     Err(SysError::EAGAIN) => {
-                        suspend_now().await;
                         if has_signal() {
                             warn!("[UdpSocket::block_on] has signal");
                             return Err(SysError::EINTR);
                         }
+                        suspend_now().await;
                     }
                     Err(e) => return Err(e),
                 }
@@ -63,10 +63,22 @@ impl BufferCache {
         if buffer_head.has_cached() {
             buffer_head.write_block(buf)
         } else {
-            self.device().base_write_blocks(block_id, buf)
+            // waited for by `sync`, and by later requests to the same block
+            self.device().submit_write_blocks(block_id, buf)
         }
     }
 
+    /// Write dirty blocks of the pages caching pure block data back to disk.
+    pub fn sync(&self) {
+        let device = self.device();
+        device.plug();
+        for (_, page) in self.pages.iter() {
+            page.start_flush();
+        }
+        device.unplug();
+        device.wait_writes();
+    }
+
     pub fn get_buffer_head_from_disk(&mut self, block_id: usize) -> Arc<BufferHead> {
         let device = self.device();
         if let Some(buffer_head) = self.buffer_heads.get_mut(&block_id).cloned() {
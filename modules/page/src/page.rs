@@ -8,8 +8,9 @@ use config::{
 use device_core::BlockDevice;
 use enum_as_inner::EnumAsInner;
 use intrusive_collections::LinkedList;
-use memory::{alloc_frame_tracker, FrameTracker, PhysPageNum};
+use memory::{alloc_frame_tracker, try_alloc_frame_tracker, FrameTracker, PhysPageNum};
 use sync::mutex::SpinNoIrqLock;
+use systype::{SysError, SysResult};
 
 use crate::{
     buffer_cache::{BufferHead, BufferHeadAdapter},
@@ -85,7 +86,8 @@ impl Drop for Page {
 }
 
 impl Page {
-    /// Create a `Page` by allocating a frame.
+    /// Create a `Page` by allocating a frame, which may take the frames kept
+    /// for the kernel.
     pub fn new() -> Arc<Self> {
         let frame = alloc_frame_tracker();
         Arc::new(Self {
@@ -94,6 +96,25 @@ impl Page {
         })
     }
 
+    /// Try to create a `Page` on behalf of user space, which fails with
+    /// `ENOMEM` when out of memory.
+    pub fn try_new() -> SysResult<Arc<Self>> {
+        let frame = try_alloc_frame_tracker().ok_or(SysError::ENOMEM)?;
+        Ok(Arc::new(Self {
+            frame,
+            kind: PageKind::Normal,
+        }))
+    }
+
+    /// Create a normal `Page` of a frame allocated by the caller, who should
+    /// clean the frame.
+    pub fn from_frame(frame: FrameTracker) -> Arc<Self> {
+        Arc::new(Self {
+            frame,
+            kind: PageKind::Normal,
+        })
+    }
+
     pub fn new_file(block_device: &Arc<dyn BlockDevice>) -> Arc<Self> {
         let frame = alloc_frame_tracker();
         Arc::new(Self {
@@ -176,7 +197,9 @@ impl Page {
         inner.buffer_head_cnts
     }
 
-    pub fn flush(&self) {
+    /// Start writing the dirty buffers back to disk, whose completion is
+    /// waited for by `wait_writes` of the device.
+    pub fn start_flush(&self) {
         let inner = match &self.kind {
             PageKind::Normal => unreachable!(),
             PageKind::FileCache(inner) => inner.lock(),
@@ -186,9 +209,36 @@ impl Page {
         let device = inner.device.upgrade().unwrap();
         for buffer_head in inner.buffer_heads.iter() {
             if buffer_head.bstate() == BufferState::Dirty {
-                let block_id = buffer_head.block_id();
-                device.base_write_blocks(buffer_head.block_id(), &buffer_head.bytes_array());
+                device.submit_write_blocks(buffer_head.block_id(), &buffer_head.bytes_array());
+                buffer_head.set_bstate(BufferState::Sync);
             }
         }
     }
+
+    pub fn flush(&self) {
+        self.start_flush();
+        self.block_device().wait_writes();
+    }
+
+    pub fn block_device(&self) -> Arc<dyn BlockDevice> {
+        let inner = match &self.kind {
+            PageKind::Normal => unreachable!(),
+            PageKind::FileCache(inner) => inner.lock(),
+            PageKind::BlockCache(inner) => inner.lock(),
+        };
+        inner.device.upgrade().unwrap()
+    }
+}
+
+/// Write the dirty buffers of `pages` back to disk, plugging the device so that
+/// adjacent ones are merged into fewer requests.
+pub fn flush_pages<'a>(pages: impl IntoIterator<Item = &'a Arc<Page>>) {
+    let mut pages = pages.into_iter().peekable();
+    let Some(device) = pages.peek().map(|page| page.block_device()) else {
+        return;
+    };
+    device.plug();
+    pages.for_each(|page| page.start_flush());
+    device.unplug();
+    device.wait_writes();
 }
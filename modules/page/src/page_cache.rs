@@ -1,20 +1,34 @@
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use config::mm::is_aligned_to_page;
-use hashbrown::HashMap;
-use sync::mutex::SpinNoIrqLock;
+use hashbrown::{HashMap, HashSet};
+use sync::mutex::{AsyncMutex, SpinNoIrqLock};
 
-use crate::Page;
+use crate::{flush_pages, Page};
 
 pub struct PageCache {
     /// Map from aligned file offset to page cache.
     pages: SpinNoIrqLock<HashMap<usize, Arc<Page>>>,
+    /// Held while filling pages from disk, so that a page missed by several
+    /// tasks is only read once, and others wait without spinning.
+    fill_lock: AsyncMutex<()>,
+    /// Offsets of pages read ahead and not accessed yet.
+    read_ahead: SpinNoIrqLock<HashSet<usize>>,
+    /// Number of accesses to pages read ahead.
+    read_ahead_hits: AtomicUsize,
+    /// Offsets of pages written and not written back to the filesystem yet.
+    dirty: SpinNoIrqLock<HashSet<usize>>,
 }
 
 impl PageCache {
     pub fn new() -> Self {
         Self {
             pages: SpinNoIrqLock::new(HashMap::new()),
+            fill_lock: AsyncMutex::new(()),
+            read_ahead: SpinNoIrqLock::new(HashSet::new()),
+            read_ahead_hits: AtomicUsize::new(0),
+            dirty: SpinNoIrqLock::new(HashSet::new()),
         }
     }
 
@@ -28,13 +42,71 @@ impl PageCache {
         self.pages.lock().insert(offset_aligned, page);
     }
 
+    /// Insert a page which is read before being requested.
+    pub fn insert_read_ahead_page(&self, offset_aligned: usize, page: Arc<Page>) {
+        self.insert_page(offset_aligned, page);
+        self.read_ahead.lock().insert(offset_aligned);
+    }
+
+    /// Account an access to the cached page at `offset_aligned`.
+    pub fn mark_accessed(&self, offset_aligned: usize) {
+        if self.read_ahead.lock().remove(&offset_aligned) {
+            self.read_ahead_hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn mark_dirty(&self, offset_aligned: usize) {
+        debug_assert!(is_aligned_to_page(offset_aligned));
+        self.dirty.lock().insert(offset_aligned);
+    }
+
+    /// Take the dirty pages in the order of offsets, which are clean from now
+    /// on.
+    pub fn take_dirty(&self) -> Vec<(usize, Arc<Page>)> {
+        let offsets = core::mem::take(&mut *self.dirty.lock());
+        let pages = self.pages.lock();
+        let mut dirty: Vec<_> = offsets
+            .into_iter()
+            .filter_map(|offset| pages.get(&offset).map(|page| (offset, page.clone())))
+            .collect();
+        dirty.sort_unstable_by_key(|(offset, _)| *offset);
+        dirty
+    }
+
+    pub fn read_ahead_hits(&self) -> usize {
+        self.read_ahead_hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of pages cached.
+    pub fn len(&self) -> usize {
+        self.pages.lock().len()
+    }
+
+    /// Drop pages from `offset_aligned` to the end, and return the number of
+    /// pages dropped.
+    pub fn truncate(&self, offset_aligned: usize) -> usize {
+        debug_assert!(is_aligned_to_page(offset_aligned));
+        let mut pages = self.pages.lock();
+        let len = pages.len();
+        pages.retain(|&offset, _| offset < offset_aligned);
+        self.read_ahead
+            .lock()
+            .retain(|&offset| offset < offset_aligned);
+        self.dirty.lock().retain(|&offset| offset < offset_aligned);
+        len - pages.len()
+    }
+
+    pub fn fill_lock(&self) -> &AsyncMutex<()> {
+        &self.fill_lock
+    }
+
     pub fn clear(&self) {
-        self.pages.lock().clear()
+        self.pages.lock().clear();
+        self.read_ahead.lock().clear();
+        self.dirty.lock().clear();
     }
 
     pub fn flush(&self) {
-        for page in self.pages.lock().values() {
-            page.flush()
-        }
+        flush_pages(self.pages.lock().values());
     }
 }
@@ -13,9 +13,13 @@ use crate::{
 pub enum ActionType {
     Ignore,
     Kill,
+    /// Terminate the process and dump core, see core(5).
+    Core,
     Stop,
     Cont,
-    User { entry: usize },
+    User {
+        entry: usize,
+    },
 }
 
 impl ActionType {
@@ -24,6 +28,16 @@ impl ActionType {
             Sig::SIGCHLD | Sig::SIGURG | Sig::SIGWINCH => ActionType::Ignore,
             Sig::SIGSTOP | Sig::SIGTSTP | Sig::SIGTTIN | Sig::SIGTTOU => ActionType::Stop,
             Sig::SIGCONT => ActionType::Cont,
+            Sig::SIGQUIT
+            | Sig::SIGILL
+            | Sig::SIGTRAP
+            | Sig::SIGABRT
+            | Sig::SIGBUS
+            | Sig::SIGFPE
+            | Sig::SIGSEGV
+            | Sig::SIGXCPU
+            | Sig::SIGXFSZ
+            | Sig::SIGSYS => ActionType::Core,
             _ => ActionType::Kill,
         }
     }
@@ -157,6 +171,15 @@ impl SigPending {
         !(expect & self.bitmap).is_empty()
     }
 
+    /// Discard all pending signals in `expect`.
+    pub fn remove_expect(&mut self, expect: SigSet) {
+        if (self.bitmap & expect).is_empty() {
+            return;
+        }
+        self.queue.retain(|si| !expect.contain_signal(si.sig));
+        self.bitmap.remove(expect);
+    }
+
     // #[inline]
     // pub fn has_expect_sigset(&self, expect: SigSet) -> Option<SigInfo> {
     //     let x = self.bitmap & expect;
@@ -200,7 +223,9 @@ impl SigHandlers {
         debug_assert!(!sig.is_kill_or_stop());
         self.actions[sig.index()] = new;
         match new.atype {
-            ActionType::User { .. } | ActionType::Kill => self.bitmap.add_signal(sig),
+            ActionType::User { .. } | ActionType::Kill | ActionType::Core => {
+                self.bitmap.add_signal(sig)
+            }
             _ => self.bitmap.remove_signal(sig),
         }
     }
@@ -63,6 +63,14 @@ impl Sig {
     pub fn is_kill_or_stop(&self) -> bool {
         matches!(*self, Sig::SIGKILL | Sig::SIGSTOP)
     }
+
+    /// Signals whose default action is to stop the process.
+    pub fn is_stop(&self) -> bool {
+        matches!(
+            *self,
+            Sig::SIGSTOP | Sig::SIGTSTP | Sig::SIGTTIN | Sig::SIGTTOU
+        )
+    }
 }
 
 impl fmt::Display for Sig {
@@ -158,6 +166,9 @@ bitflags! {
         const SYNCHRONOUS_MASK = SigSet::SIGSEGV.bits() | SigSet::SIGBUS.bits()
         | SigSet::SIGILL.bits() | SigSet::SIGTRAP.bits() | SigSet::SIGFPE.bits() | SigSet::SIGSYS.bits();
         // const SYNCHRONOUS_MASK = (1<<3) | (1<<4) | (1<<6) | (1<<7) | (1<<10) | (1<<30) ;
+        // 默认动作为暂停进程的信号
+        const STOP_MASK = SigSet::SIGSTOP.bits() | SigSet::SIGTSTP.bits()
+        | SigSet::SIGTTIN.bits() | SigSet::SIGTTOU.bits();
     }
 }
 
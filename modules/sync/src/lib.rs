@@ -9,3 +9,8 @@
 extern crate alloc;
 
 pub mod mutex;
+mod wait_queue;
+
+#[cfg(feature = "selftest")]
+pub use wait_queue::selftest;
+pub use wait_queue::{WaitQueue, WaitUntil};
@@ -0,0 +1,185 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    marker::PhantomPinned,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::SpinNoIrqLock;
+use crate::wait_queue::{WaitList, WaitNode};
+
+struct MutexState {
+    locked: bool,
+    waiters: WaitList,
+    /// Waker of the task holding the lock, to detect double lock.
+    #[cfg(all(feature = "lock-debug", debug_assertions))]
+    owner: Option<core::task::Waker>,
+}
+
+/// A mutex whose `lock` parks the task instead of spinning, so it can be held
+/// across `await` and long operations.
+///
+/// The lock is handed over to waiters in FIFO order, i.e. when unlocked with
+/// waiters, the first waiter gets the lock directly instead of racing with
+/// tasks that come later.
+pub struct AsyncMutex<T: ?Sized> {
+    state: SpinNoIrqLock<MutexState>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for AsyncMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for AsyncMutex<T> {}
+
+impl<T> AsyncMutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: SpinNoIrqLock::new(MutexState {
+                locked: false,
+                waiters: WaitList::new(),
+                #[cfg(all(feature = "lock-debug", debug_assertions))]
+                owner: None,
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> AsyncMutex<T> {
+    /// Acquire the lock, waiting behind the tasks that come earlier.
+    pub fn lock(&self) -> AsyncMutexLock<'_, T> {
+        AsyncMutexLock {
+            mutex: self,
+            node: UnsafeCell::new(WaitNode::new(false)),
+            acquired: false,
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Acquire the lock if it is free and no one is waiting for it.
+    pub fn try_lock(&self) -> Option<AsyncMutexGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.locked || !state.waiters.is_empty() {
+            return None;
+        }
+        state.locked = true;
+        Some(AsyncMutexGuard { mutex: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Hand the lock over to the first waiter, or release it if there is none.
+    fn unlock(&self) {
+        let mut state = self.state.lock();
+        #[cfg(all(feature = "lock-debug", debug_assertions))]
+        {
+            state.owner = None;
+        }
+        match unsafe { state.waiters.wake_front() } {
+            Some(waker) => {
+                drop(state);
+                waker.wake();
+            }
+            None => state.locked = false,
+        }
+    }
+}
+
+/// Future returned by `AsyncMutex::lock`.
+pub struct AsyncMutexLock<'a, T: ?Sized> {
+    mutex: &'a AsyncMutex<T>,
+    node: UnsafeCell<WaitNode>,
+    acquired: bool,
+    _pinned: PhantomPinned,
+}
+
+unsafe impl<T: ?Sized + Send> Send for AsyncMutexLock<'_, T> {}
+
+impl<'a, T: ?Sized> Future for AsyncMutexLock<'a, T> {
+    type Output = AsyncMutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let node = this.node.get();
+        let mut state = this.mutex.state.lock();
+        unsafe {
+            // handed over by the previous owner
+            if !(*node).woken {
+                if (*node).linked {
+                    (*node).set_waker(cx.waker());
+                    return Poll::Pending;
+                }
+                if state.locked {
+                    #[cfg(all(feature = "lock-debug", debug_assertions))]
+                    if state
+                        .owner
+                        .as_ref()
+                        .is_some_and(|owner| owner.will_wake(cx.waker()))
+                    {
+                        panic!("[AsyncMutex] double lock by the same task");
+                    }
+                    (*node).set_waker(cx.waker());
+                    state.waiters.push_back(node);
+                    return Poll::Pending;
+                }
+                state.locked = true;
+            }
+        }
+        #[cfg(all(feature = "lock-debug", debug_assertions))]
+        {
+            state.owner = Some(cx.waker().clone());
+        }
+        this.acquired = true;
+        Poll::Ready(AsyncMutexGuard { mutex: this.mutex })
+    }
+}
+
+impl<T: ?Sized> Drop for AsyncMutexLock<'_, T> {
+    fn drop(&mut self) {
+        if self.acquired {
+            return;
+        }
+        let node = self.node.get();
+        let mut state = self.mutex.state.lock();
+        let woken = unsafe {
+            if (*node).linked {
+                state.waiters.remove(node);
+            }
+            (*node).woken
+        };
+        drop(state);
+        if woken {
+            // the lock has been handed over to this cancelled waiter
+            self.mutex.unlock();
+        }
+    }
+}
+
+pub struct AsyncMutexGuard<'a, T: ?Sized> {
+    mutex: &'a AsyncMutex<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for AsyncMutexGuard<'_, T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for AsyncMutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for AsyncMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for AsyncMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AsyncMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
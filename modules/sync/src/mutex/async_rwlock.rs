@@ -0,0 +1,260 @@
+use alloc::vec::Vec;
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    marker::PhantomPinned,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::SpinNoIrqLock;
+use crate::wait_queue::{WaitList, WaitNode};
+
+struct RwLockState {
+    readers: usize,
+    writer: bool,
+    waiters: WaitList,
+}
+
+impl RwLockState {
+    /// Hand the lock over to the first waiting writer, or all readers at the
+    /// front of the queue.
+    fn grant(&mut self) -> Vec<core::task::Waker> {
+        let mut wakers = Vec::new();
+        if self.writer || self.readers > 0 {
+            return wakers;
+        }
+        if self.waiters.front().is_some_and(|node| node.exclusive) {
+            self.writer = true;
+            wakers.extend(unsafe { self.waiters.wake_front() });
+            return wakers;
+        }
+        while self.waiters.front().is_some_and(|node| !node.exclusive) {
+            self.readers += 1;
+            wakers.extend(unsafe { self.waiters.wake_front() });
+        }
+        wakers
+    }
+}
+
+/// A readers-writer lock whose lock methods park the task instead of spinning.
+///
+/// Waiters are granted in FIFO order, so readers coming after a waiting writer
+/// will wait behind it, and writers will not be starved.
+pub struct AsyncRwLock<T: ?Sized> {
+    state: SpinNoIrqLock<RwLockState>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for AsyncRwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for AsyncRwLock<T> {}
+
+impl<T> AsyncRwLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: SpinNoIrqLock::new(RwLockState {
+                readers: 0,
+                writer: false,
+                waiters: WaitList::new(),
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> AsyncRwLock<T> {
+    pub fn read(&self) -> AsyncRwLockAcquire<'_, T, false> {
+        AsyncRwLockAcquire::new(self)
+    }
+
+    pub fn write(&self) -> AsyncRwLockAcquire<'_, T, true> {
+        AsyncRwLockAcquire::new(self)
+    }
+
+    pub fn try_read(&self) -> Option<AsyncRwLockReadGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.writer || !state.waiters.is_empty() {
+            return None;
+        }
+        state.readers += 1;
+        Some(AsyncRwLockReadGuard { lock: self })
+    }
+
+    pub fn try_write(&self) -> Option<AsyncRwLockWriteGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.writer || state.readers > 0 || !state.waiters.is_empty() {
+            return None;
+        }
+        state.writer = true;
+        Some(AsyncRwLockWriteGuard { lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock(&self, exclusive: bool) {
+        let mut state = self.state.lock();
+        if exclusive {
+            state.writer = false;
+        } else {
+            state.readers -= 1;
+        }
+        let wakers = state.grant();
+        drop(state);
+        wakers.into_iter().for_each(|waker| waker.wake());
+    }
+}
+
+/// Future returned by `AsyncRwLock::read` and `AsyncRwLock::write`.
+pub struct AsyncRwLockAcquire<'a, T: ?Sized, const EXCLUSIVE: bool> {
+    lock: &'a AsyncRwLock<T>,
+    node: UnsafeCell<WaitNode>,
+    acquired: bool,
+    _pinned: PhantomPinned,
+}
+
+unsafe impl<T: ?Sized + Send + Sync, const EXCLUSIVE: bool> Send
+    for AsyncRwLockAcquire<'_, T, EXCLUSIVE>
+{
+}
+
+impl<'a, T: ?Sized, const EXCLUSIVE: bool> AsyncRwLockAcquire<'a, T, EXCLUSIVE> {
+    fn new(lock: &'a AsyncRwLock<T>) -> Self {
+        let mut node = WaitNode::new(false);
+        node.exclusive = EXCLUSIVE;
+        Self {
+            lock,
+            node: UnsafeCell::new(node),
+            acquired: false,
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Poll for the lock, return true if it is acquired.
+    fn poll_acquire(self: Pin<&mut Self>, cx: &mut Context<'_>) -> bool {
+        let this = unsafe { self.get_unchecked_mut() };
+        let node = this.node.get();
+        let mut state = this.lock.state.lock();
+        unsafe {
+            // granted by `RwLockState::grant`
+            if !(*node).woken {
+                if (*node).linked {
+                    (*node).set_waker(cx.waker());
+                    return false;
+                }
+                let free =
+                    !state.writer && (!EXCLUSIVE || state.readers == 0) && state.waiters.is_empty();
+                if !free {
+                    (*node).set_waker(cx.waker());
+                    state.waiters.push_back(node);
+                    return false;
+                }
+                if EXCLUSIVE {
+                    state.writer = true;
+                } else {
+                    state.readers += 1;
+                }
+            }
+        }
+        this.acquired = true;
+        true
+    }
+}
+
+impl<'a, T: ?Sized> Future for AsyncRwLockAcquire<'a, T, false> {
+    type Output = AsyncRwLockReadGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.as_mut().poll_acquire(cx) {
+            Poll::Ready(AsyncRwLockReadGuard { lock: self.lock })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Future for AsyncRwLockAcquire<'a, T, true> {
+    type Output = AsyncRwLockWriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.as_mut().poll_acquire(cx) {
+            Poll::Ready(AsyncRwLockWriteGuard { lock: self.lock })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<T: ?Sized, const EXCLUSIVE: bool> Drop for AsyncRwLockAcquire<'_, T, EXCLUSIVE> {
+    fn drop(&mut self) {
+        if self.acquired {
+            return;
+        }
+        let node = self.node.get();
+        let mut state = self.lock.state.lock();
+        let woken = unsafe {
+            if (*node).linked {
+                state.waiters.remove(node);
+            }
+            (*node).woken
+        };
+        if woken {
+            // the lock has been granted to this cancelled waiter
+            drop(state);
+            self.lock.unlock(EXCLUSIVE);
+        } else {
+            // a removed writer may have been blocking readers behind it
+            let wakers = state.grant();
+            drop(state);
+            wakers.into_iter().for_each(|waker| waker.wake());
+        }
+    }
+}
+
+pub struct AsyncRwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a AsyncRwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Send for AsyncRwLockReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for AsyncRwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for AsyncRwLockReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AsyncRwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock(false);
+    }
+}
+
+pub struct AsyncRwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a AsyncRwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for AsyncRwLockWriteGuard<'_, T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for AsyncRwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for AsyncRwLockWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for AsyncRwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AsyncRwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock(true);
+    }
+}
@@ -0,0 +1,174 @@
+//! Diagnostics for `SpinNoIrqLock`, with the `lock-debug` feature in debug
+//! builds.
+//!
+//! Each hart records the locks it holds, and reports a lock held longer than
+//! `HOLD_WARN_US`, or still held when the executor gets back control from a
+//! task, i.e. held across a suspension point, which leaves interrupts disabled
+//! on the hart. The hart is told by the kernel stack `sp` points into, so that
+//! locks taken before the hart is set up are tracked as well.
+
+/// Report the locks held by the local hart after polling a task, which have
+/// been held across a suspension point.
+pub fn check_suspend() {
+    #[cfg(all(feature = "lock-debug", debug_assertions))]
+    imp::check_suspend();
+}
+
+/// Print the locks held by each hart, e.g. on panic.
+pub fn print_held_locks() {
+    #[cfg(all(feature = "lock-debug", debug_assertions))]
+    imp::print_held_locks();
+}
+
+#[cfg(all(feature = "lock-debug", debug_assertions))]
+pub(super) use imp::{acquire, release};
+
+#[cfg(all(feature = "lock-debug", debug_assertions))]
+mod imp {
+    use core::{cell::UnsafeCell, panic::Location};
+
+    use arch::{entry::kernel_stack_owner, register, time::get_time_us};
+    use config::board::MAX_HARTS;
+
+    /// Locks held longer are reported when released.
+    const HOLD_WARN_US: usize = 50_000;
+    /// Most locks recorded for one hart, beyond which they are only counted.
+    const MAX_HELD: usize = 16;
+
+    #[derive(Clone, Copy)]
+    struct HeldLock {
+        /// Address of the lock.
+        lock: usize,
+        /// Where the lock is taken.
+        location: &'static Location<'static>,
+        since_us: usize,
+    }
+
+    struct HartLocks {
+        held: [Option<HeldLock>; MAX_HELD],
+        /// Number of locks held, which may exceed `MAX_HELD`.
+        depth: usize,
+        /// Set while reporting, during which the locks taken by logging are not
+        /// reported again.
+        reporting: bool,
+    }
+
+    impl HartLocks {
+        const fn new() -> Self {
+            Self {
+                held: [None; MAX_HELD],
+                depth: 0,
+                reporting: false,
+            }
+        }
+
+        fn held(&self) -> impl Iterator<Item = &HeldLock> {
+            self.held.iter().flatten()
+        }
+
+        fn clear(&mut self) {
+            self.held = [None; MAX_HELD];
+            self.depth = 0;
+        }
+    }
+
+    /// Locks held by each hart, only modified by the hart itself with
+    /// interrupts disabled, and read racily by others when printing.
+    struct AllHarts(UnsafeCell<[HartLocks; MAX_HARTS]>);
+
+    unsafe impl Sync for AllHarts {}
+
+    const HART_LOCKS_EACH: HartLocks = HartLocks::new();
+    static HART_LOCKS: AllHarts = AllHarts(UnsafeCell::new([HART_LOCKS_EACH; MAX_HARTS]));
+
+    fn local_locks() -> Option<&'static mut HartLocks> {
+        let hart_id = kernel_stack_owner(register::sp())?;
+        Some(unsafe { &mut (*HART_LOCKS.0.get())[hart_id] })
+    }
+
+    pub fn acquire(lock: usize, location: &'static Location<'static>) {
+        let Some(locks) = local_locks() else {
+            return;
+        };
+        if locks.depth < MAX_HELD {
+            locks.held[locks.depth] = Some(HeldLock {
+                lock,
+                location,
+                since_us: get_time_us(),
+            });
+        }
+        locks.depth += 1;
+    }
+
+    pub fn release(lock: usize) {
+        let Some(locks) = local_locks() else {
+            return;
+        };
+        let len = locks.depth.min(MAX_HELD);
+        let Some(i) = (0..len)
+            .rev()
+            .find(|&i| locks.held[i].is_some_and(|held| held.lock == lock))
+        else {
+            // taken on another hart, or not recorded
+            if locks.depth > MAX_HELD {
+                locks.depth -= 1;
+            }
+            return;
+        };
+        let held = locks.held[i].unwrap();
+        locks.held.copy_within(i + 1..len, i);
+        locks.held[len - 1] = None;
+        locks.depth -= 1;
+
+        let held_us = get_time_us() - held.since_us;
+        if held_us > HOLD_WARN_US && !locks.reporting {
+            locks.reporting = true;
+            log::warn!(
+                "[lock_debug] SpinNoIrqLock {:#x} taken at {} held for {} us",
+                held.lock,
+                held.location,
+                held_us
+            );
+            backtrace::backtrace();
+            locks.reporting = false;
+        }
+    }
+
+    pub fn check_suspend() {
+        let Some(locks) = local_locks() else {
+            return;
+        };
+        if locks.depth == 0 || locks.reporting {
+            return;
+        }
+        locks.reporting = true;
+        log::warn!(
+            "[lock_debug] task suspended with {} SpinNoIrqLock held, interrupts are left disabled",
+            locks.depth
+        );
+        for held in locks.held() {
+            log::warn!("[lock_debug] {:#x} taken at {}", held.lock, held.location);
+        }
+        // reported once, and released on whichever hart the task resumes
+        locks.clear();
+        locks.reporting = false;
+    }
+
+    pub fn print_held_locks() {
+        let harts = unsafe { &*HART_LOCKS.0.get() };
+        for (hart_id, locks) in harts.iter().enumerate() {
+            if locks.depth == 0 {
+                continue;
+            }
+            log::error!("hart {hart_id} holds {} SpinNoIrqLock", locks.depth);
+            for held in locks.held() {
+                log::error!(
+                    "{:#x} taken at {} for {} us",
+                    held.lock,
+                    held.location,
+                    get_time_us() - held.since_us
+                );
+            }
+        }
+    }
+}
@@ -1,6 +1,15 @@
+pub use self::{
+    async_mutex::{AsyncMutex, AsyncMutexGuard},
+    async_rwlock::{AsyncRwLock, AsyncRwLockReadGuard, AsyncRwLockWriteGuard},
+    lock_debug::{check_suspend, print_held_locks},
+    spin_mutex::local_held_locks,
+};
 use self::{interrupts::InterruptGuard, sleep_mutex::SleepMutex, spin_mutex::SpinMutex};
 
+mod async_mutex;
+mod async_rwlock;
 mod interrupts;
+mod lock_debug;
 pub mod sleep_mutex;
 /// SpinMutex
 pub mod spin_mutex;
@@ -13,6 +22,9 @@ pub type SleepLock<T> = SleepMutex<T, SpinNoIrq>;
 
 /// Low-level support for mutex(spinlock, sleeplock, etc)
 pub trait MutexSupport {
+    /// Whether interrupts are disabled while locked, in which case the lock is
+    /// tracked with the `lock-debug` feature.
+    const NO_IRQ: bool = false;
     /// Guard data
     type GuardData;
     /// Called before lock() & try_lock()
@@ -36,6 +48,7 @@ impl MutexSupport for Spin {
 pub struct SpinNoIrq;
 
 impl MutexSupport for SpinNoIrq {
+    const NO_IRQ: bool = true;
     type GuardData = InterruptGuard;
     #[inline(always)]
     fn before_lock() -> Self::GuardData {
@@ -2,16 +2,39 @@ use core::{
     cell::UnsafeCell,
     marker::PhantomData,
     ops::{Deref, DerefMut},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
+use arch::{entry::kernel_stack_owner, register};
 use async_utils::SendWrapper;
+use config::board::MAX_HARTS;
 
+#[cfg(all(feature = "lock-debug", debug_assertions))]
+use super::lock_debug;
 use super::MutexSupport;
 
 pub struct MutexGuard<'a, T: ?Sized, S: MutexSupport> {
     mutex: &'a SpinMutex<T, S>,
     support_guard: S::GuardData,
+    /// Hart whose held lock count is increased by this guard.
+    hart_id: Option<usize>,
+}
+
+const HELD_EACH: AtomicUsize = AtomicUsize::new(0);
+/// Number of spin locks held by each hart.
+static HELD_LOCKS: [AtomicUsize; MAX_HARTS] = [HELD_EACH; MAX_HARTS];
+
+/// Number of spin locks held by the local hart, which must not switch to
+/// other tasks while holding any.
+pub fn local_held_locks() -> usize {
+    kernel_stack_owner(register::sp())
+        .map_or(0, |hart_id| HELD_LOCKS[hart_id].load(Ordering::Relaxed))
+}
+
+fn hold_local() -> Option<usize> {
+    let hart_id = kernel_stack_owner(register::sp())?;
+    HELD_LOCKS[hart_id].fetch_add(1, Ordering::Relaxed);
+    Some(hart_id)
 }
 
 /// `SpinMutex` can include different `MutexSupport` type
@@ -54,6 +77,7 @@ impl<T, S: MutexSupport> SpinMutex<T, S> {
     /// Note that the locked data cannot step over `await`,
     /// i.e. cannot be sent between thread.
     #[inline(always)]
+    #[cfg_attr(all(feature = "lock-debug", debug_assertions), track_caller)]
     pub fn lock(&self) -> MutexGuard<T, S> {
         let support_guard = S::before_lock();
         loop {
@@ -66,10 +90,34 @@ impl<T, S: MutexSupport> SpinMutex<T, S> {
                 break;
             }
         }
+        #[cfg(all(feature = "lock-debug", debug_assertions))]
+        if S::NO_IRQ {
+            lock_debug::acquire(self.addr(), core::panic::Location::caller());
+        }
         MutexGuard {
             mutex: self,
             support_guard,
+            hart_id: hold_local(),
+        }
+    }
+
+    /// Lock the mutex if it is unlocked, or return `None` at once otherwise.
+    #[inline(always)]
+    #[cfg_attr(all(feature = "lock-debug", debug_assertions), track_caller)]
+    pub fn try_lock(&self) -> Option<MutexGuard<T, S>> {
+        let support_guard = S::before_lock();
+        self.lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        #[cfg(all(feature = "lock-debug", debug_assertions))]
+        if S::NO_IRQ {
+            lock_debug::acquire(self.addr(), core::panic::Location::caller());
         }
+        Some(MutexGuard {
+            mutex: self,
+            support_guard,
+            hart_id: hold_local(),
+        })
     }
 
     /// # Safety
@@ -78,6 +126,7 @@ impl<T, S: MutexSupport> SpinMutex<T, S> {
     /// You should ensure that context switch won't happen during
     /// the locked data's lifetime.
     #[inline(always)]
+    #[cfg_attr(all(feature = "lock-debug", debug_assertions), track_caller)]
     pub unsafe fn sent_lock(&self) -> impl DerefMut<Target = T> + '_ {
         SendWrapper::new(self.lock())
     }
@@ -88,6 +137,13 @@ impl<T, S: MutexSupport> SpinMutex<T, S> {
     }
 }
 
+impl<T: ?Sized, S: MutexSupport> SpinMutex<T, S> {
+    #[cfg(all(feature = "lock-debug", debug_assertions))]
+    fn addr(&self) -> usize {
+        self as *const Self as *const u8 as usize
+    }
+}
+
 impl<'a, T: ?Sized, S: MutexSupport> Deref for MutexGuard<'a, T, S> {
     type Target = T;
     #[inline(always)]
@@ -108,6 +164,13 @@ impl<'a, T: ?Sized, S: MutexSupport> Drop for MutexGuard<'a, T, S> {
     /// from.
     #[inline(always)]
     fn drop(&mut self) {
+        #[cfg(all(feature = "lock-debug", debug_assertions))]
+        if S::NO_IRQ {
+            lock_debug::release(self.mutex.addr());
+        }
+        if let Some(hart_id) = self.hart_id {
+            HELD_LOCKS[hart_id].fetch_sub(1, Ordering::Relaxed);
+        }
         self.mutex.lock.store(false, Ordering::Release);
         S::after_unlock(&mut self.support_guard);
     }
@@ -0,0 +1,329 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    marker::PhantomPinned,
+    pin::Pin,
+    ptr,
+    task::{Context, Poll, Waker},
+};
+
+use crate::mutex::SpinNoIrqLock;
+
+/// A waiter linked in a `WaitQueue`. Nodes of `WaitUntil` futures live in the
+/// pinned futures, while nodes added by `WaitQueue::register` are allocated
+/// on heap and owned by the queue.
+pub(crate) struct WaitNode {
+    prev: *mut WaitNode,
+    next: *mut WaitNode,
+    pub(crate) waker: Option<Waker>,
+    pub(crate) linked: bool,
+    /// Set when the node is unlinked by a wake up.
+    pub(crate) woken: bool,
+    /// Allocated by `WaitQueue::register` and freed once unlinked.
+    owned: bool,
+    /// Waiting for exclusive access, used by `AsyncRwLock`.
+    pub(crate) exclusive: bool,
+}
+
+impl WaitNode {
+    pub(crate) const fn new(owned: bool) -> Self {
+        Self {
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+            waker: None,
+            linked: false,
+            woken: false,
+            owned,
+            exclusive: false,
+        }
+    }
+
+    /// Refresh the waker if it will not wake the task polling now.
+    pub(crate) fn set_waker(&mut self, waker: &Waker) {
+        if !self.waker.as_ref().is_some_and(|w| w.will_wake(waker)) {
+            self.waker = Some(waker.clone());
+        }
+    }
+}
+
+/// Intrusive doubly-linked list of waiters. Nodes are only accessed with the
+/// list locked.
+pub(crate) struct WaitList {
+    head: *mut WaitNode,
+    tail: *mut WaitNode,
+}
+
+unsafe impl Send for WaitList {}
+
+impl WaitList {
+    pub(crate) const fn new() -> Self {
+        Self {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    pub(crate) fn front(&self) -> Option<&WaitNode> {
+        unsafe { self.head.as_ref() }
+    }
+
+    pub(crate) unsafe fn push_back(&mut self, node: *mut WaitNode) {
+        (*node).prev = self.tail;
+        (*node).next = ptr::null_mut();
+        if self.tail.is_null() {
+            self.head = node;
+        } else {
+            (*self.tail).next = node;
+        }
+        self.tail = node;
+        (*node).linked = true;
+    }
+
+    pub(crate) unsafe fn remove(&mut self, node: *mut WaitNode) {
+        let (prev, next) = ((*node).prev, (*node).next);
+        if prev.is_null() {
+            self.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        if next.is_null() {
+            self.tail = prev;
+        } else {
+            (*next).prev = prev;
+        }
+        (*node).prev = ptr::null_mut();
+        (*node).next = ptr::null_mut();
+        (*node).linked = false;
+    }
+
+    /// Unlink the first waiter and take its waker.
+    pub(crate) unsafe fn wake_front(&mut self) -> Option<Waker> {
+        let node = self.head;
+        if node.is_null() {
+            return None;
+        }
+        self.remove(node);
+        (*node).woken = true;
+        let waker = (*node).waker.take();
+        if (*node).owned {
+            drop(Box::from_raw(node));
+        }
+        waker
+    }
+}
+
+/// A queue of tasks waiting for some condition, e.g. data to read.
+///
+/// Waiters are unlinked from the queue when their futures are dropped, so a
+/// cancelled wait will neither leak an entry nor swallow a `wake_one`.
+pub struct WaitQueue {
+    list: SpinNoIrqLock<WaitList>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            list: SpinNoIrqLock::new(WaitList::new()),
+        }
+    }
+
+    /// Wait until `cond` returns true. `cond` is checked after the waiter is
+    /// linked, so that a wake up after the condition is changed will never be
+    /// missed.
+    pub fn wait_until<F: FnMut() -> bool>(&self, cond: F) -> WaitUntil<'_, F> {
+        WaitUntil {
+            queue: self,
+            cond,
+            node: UnsafeCell::new(WaitNode::new(false)),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Register a waker to be woken once, used by poll methods that return
+    /// before the event happens. A waker that will wake the same task as a
+    /// registered one is ignored.
+    pub fn register(&self, waker: &Waker) {
+        let mut list = self.list.lock();
+        let mut node = list.head;
+        while !node.is_null() {
+            unsafe {
+                if (*node).owned && (*node).waker.as_ref().is_some_and(|w| w.will_wake(waker)) {
+                    return;
+                }
+                node = (*node).next;
+            }
+        }
+        let node = Box::into_raw(Box::new(WaitNode::new(true)));
+        unsafe {
+            (*node).waker = Some(waker.clone());
+            list.push_back(node);
+        }
+    }
+
+    /// Wake up the first waiter. Return false if there is no waiter.
+    pub fn wake_one(&self) -> bool {
+        let waker = unsafe { self.list.lock().wake_front() };
+        match waker {
+            Some(waker) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wake up all waiters, return the number of them.
+    pub fn wake_all(&self) -> usize {
+        let mut wakers = Vec::new();
+        let mut list = self.list.lock();
+        while let Some(waker) = unsafe { list.wake_front() } {
+            wakers.push(waker);
+        }
+        drop(list);
+        let n = wakers.len();
+        wakers.into_iter().for_each(Waker::wake);
+        n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.lock().is_empty()
+    }
+}
+
+impl Drop for WaitQueue {
+    fn drop(&mut self) {
+        // only nodes owned by the queue can be left, since `WaitUntil` borrows
+        // the queue
+        let mut list = self.list.lock();
+        while unsafe { list.wake_front() }.is_some() {}
+    }
+}
+
+/// Future returned by `WaitQueue::wait_until`, whose node is linked into the
+/// queue while it is pending.
+pub struct WaitUntil<'a, F: FnMut() -> bool> {
+    queue: &'a WaitQueue,
+    cond: F,
+    node: UnsafeCell<WaitNode>,
+    _pinned: PhantomPinned,
+}
+
+unsafe impl<F: FnMut() -> bool + Send> Send for WaitUntil<'_, F> {}
+
+impl<F: FnMut() -> bool> Future for WaitUntil<'_, F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let node = this.node.get();
+        let mut list = this.queue.list.lock();
+        unsafe {
+            (*node).set_waker(cx.waker());
+            if !(*node).linked {
+                (*node).woken = false;
+                list.push_back(node);
+            }
+        }
+        drop(list);
+        if (this.cond)() {
+            let mut list = this.queue.list.lock();
+            unsafe {
+                if (*node).linked {
+                    list.remove(node);
+                }
+                // the wake up is consumed
+                (*node).woken = false;
+            }
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+impl<F: FnMut() -> bool> Drop for WaitUntil<'_, F> {
+    fn drop(&mut self) {
+        let node = self.node.get();
+        let mut list = self.queue.list.lock();
+        let woken = unsafe {
+            if (*node).linked {
+                list.remove(node);
+            }
+            (*node).woken
+        };
+        drop(list);
+        if woken {
+            // pass the wake up to another waiter since this one is cancelled
+            self.queue.wake_one();
+        }
+    }
+}
+
+/// Poll `WaitUntil` futures by hand with counting wakers, and check that a
+/// cancelled waiter is unlinked from the queue and passes on the wake up it
+/// has taken, so that neither a dangling node nor a lost wake up is left.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    use alloc::{sync::Arc, task::Wake};
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let counters = [
+        Arc::new(CountingWaker(AtomicUsize::new(0))),
+        Arc::new(CountingWaker(AtomicUsize::new(0))),
+    ];
+    let wakers = counters.clone().map(Waker::from);
+    let woken = |i: usize| counters[i].0.load(Ordering::Relaxed);
+    let ready = AtomicBool::new(false);
+    let queue = WaitQueue::new();
+    let cond = || ready.load(Ordering::Relaxed);
+    let poll = |future: Pin<&mut WaitUntil<'_, _>>, i: usize| {
+        future.poll(&mut Context::from_waker(&wakers[i]))
+    };
+
+    // a dropped waiter is unlinked, so wake_all only wakes the other one
+    let mut first = Box::pin(queue.wait_until(cond));
+    let mut second = Box::pin(queue.wait_until(cond));
+    assert!(poll(first.as_mut(), 0).is_pending());
+    assert!(poll(second.as_mut(), 1).is_pending());
+    drop(first);
+    assert_eq!(queue.wake_all(), 1);
+    assert_eq!((woken(0), woken(1)), (0, 1));
+    ready.store(true, Ordering::Relaxed);
+    assert!(poll(second.as_mut(), 1).is_ready());
+    drop(second);
+    assert!(queue.is_empty());
+
+    // a waiter dropped after taking a wake up passes it to the next one
+    ready.store(false, Ordering::Relaxed);
+    let mut first = Box::pin(queue.wait_until(cond));
+    let mut second = Box::pin(queue.wait_until(cond));
+    assert!(poll(first.as_mut(), 0).is_pending());
+    assert!(poll(second.as_mut(), 1).is_pending());
+    assert!(queue.wake_one());
+    assert_eq!((woken(0), woken(1)), (1, 1));
+    drop(first);
+    assert_eq!((woken(0), woken(1)), (1, 2));
+    drop(second);
+    assert!(queue.is_empty());
+    assert!(!queue.wake_one());
+
+    // a waker is registered once, and nodes left are freed with the queue
+    queue.register(&wakers[0]);
+    queue.register(&wakers[0]);
+    assert_eq!(queue.wake_all(), 1);
+    assert_eq!(woken(0), 2);
+    queue.register(&wakers[1]);
+    drop(queue);
+}
@@ -102,28 +102,89 @@ pub enum SysError {
     ENOTEMPTY = 39,
     /// Too many symbolic links encountered
     ELOOP = 40,
+    /// No message of desired type
+    ENOMSG = 42,
+    /// Identifier removed
+    EIDRM = 43,
+    /// No data available
+    ENODATA = 61,
+    /// Timer expired
+    ETIME = 62,
+    /// Value too large for defined data type
+    EOVERFLOW = 75,
+    /// Illegal byte sequence
+    EILSEQ = 84,
     /// Socket operation on non-socket
     ENOTSOCK = 88,
+    /// Destination address required
+    EDESTADDRREQ = 89,
+    /// Message too long
+    EMSGSIZE = 90,
+    /// Protocol wrong type for socket
+    EPROTOTYPE = 91,
+    /// Protocol not available
+    ENOPROTOOPT = 92,
+    /// Protocol not supported
+    EPROTONOSUPPORT = 93,
+    /// Socket type not supported
+    ESOCKTNOSUPPORT = 94,
     /// Unsupported
     EOPNOTSUPP = 95,
+    /// Protocol family not supported
+    EPFNOSUPPORT = 96,
+    /// Address family not supported by protocol
+    EAFNOSUPPORT = 97,
     /// Socket address is already in use
     EADDRINUSE = 98,
     /// Address not available
     EADDRNOTAVAIL = 99,
+    /// Network is down
+    ENETDOWN = 100,
+    /// Network is unreachable
+    ENETUNREACH = 101,
+    /// Network dropped connection because of reset
+    ENETRESET = 102,
+    /// Software caused connection abort
+    ECONNABORTED = 103,
     /// Connection reset
     ECONNRESET = 104,
+    /// No buffer space available
+    ENOBUFS = 105,
     /// Transport endpoint is already connected
     EISCONN = 106,
     /// The socket is not connected
     ENOTCONN = 107,
+    /// Cannot send after transport endpoint shutdown
+    ESHUTDOWN = 108,
+    /// Connection timed out
+    ETIMEDOUT = 110,
     /// Connection refused
     ECONNREFUSED = 111,
+    /// Host is down
+    EHOSTDOWN = 112,
+    /// No route to host
+    EHOSTUNREACH = 113,
+    /// Operation already in progress
+    EALREADY = 114,
     /// The socket is nonblocking and the connection cannot be completed
     /// immediately.(connect.2)
     EINPROGRESS = 115,
+    /// Stale file handle
+    ESTALE = 116,
+    /// Quota exceeded
+    EDQUOT = 122,
+    /// Operation canceled
+    ECANCELED = 125,
 }
 
 impl SysError {
+    /// The same as `EOPNOTSUPP` on Linux.
+    pub const ENOTSUP: Self = Self::EOPNOTSUPP;
+    /// The same as `EAGAIN`.
+    pub const EWOULDBLOCK: Self = Self::EAGAIN;
+    /// The same as `EDEADLK`.
+    pub const EDEADLOCK: Self = Self::EDEADLK;
+
     /// Returns the error description.
     pub const fn as_str(&self) -> &'static str {
         use self::SysError::*;
@@ -168,20 +229,49 @@ impl SysError {
             ENOSYS => "Invalid system call number",
             ENOTEMPTY => "Directory not empty",
             ELOOP => "Too many symbolic links encountered",
+            ENOMSG => "No message of desired type",
+            EIDRM => "Identifier removed",
+            ENODATA => "No data available",
+            ETIME => "Timer expired",
+            EOVERFLOW => "Value too large for defined data type",
+            EILSEQ => "Illegal byte sequence",
             ENOTSOCK => "Socket operation on non-socket",
-            ENOTCONN => "Transport endpoint is not connected",
+            EDESTADDRREQ => "Destination address required",
+            EMSGSIZE => "Message too long",
+            EPROTOTYPE => "Protocol wrong type for socket",
+            ENOPROTOOPT => "Protocol not available",
+            EPROTONOSUPPORT => "Protocol not supported",
+            ESOCKTNOSUPPORT => "Socket type not supported",
             EOPNOTSUPP => "Unsupported Error",
-            EADDRNOTAVAIL => "Address not available",
+            EPFNOSUPPORT => "Protocol family not supported",
+            EAFNOSUPPORT => "Address family not supported by protocol",
             EADDRINUSE => "Address already in use",
-            EISCONN => "Transport endpoint is already connected",
+            EADDRNOTAVAIL => "Address not available",
+            ENETDOWN => "Network is down",
+            ENETUNREACH => "Network is unreachable",
+            ENETRESET => "Network dropped connection because of reset",
+            ECONNABORTED => "Software caused connection abort",
             ECONNRESET => "Connection reset",
+            ENOBUFS => "No buffer space available",
+            EISCONN => "Transport endpoint is already connected",
+            ENOTCONN => "Transport endpoint is not connected",
+            ESHUTDOWN => "Cannot send after transport endpoint shutdown",
+            ETIMEDOUT => "Connection timed out",
             ECONNREFUSED => "Connection refused",
+            EHOSTDOWN => "Host is down",
+            EHOSTUNREACH => "No route to host",
+            EALREADY => "Operation already in progress",
             EINPROGRESS => "Operation now in progress",
+            ESTALE => "Stale file handle",
+            EDQUOT => "Quota exceeded",
+            ECANCELED => "Operation canceled",
         }
     }
 
+    /// Converts an error code, e.g. one returned by lwext4, where unknown codes
+    /// are reported as `EIO`.
     pub fn from_i32(value: i32) -> Self {
-        Self::from_repr(value).unwrap()
+        Self::from_repr(value).unwrap_or(Self::EIO)
     }
 
     /// Returns the error code value in `i32`.
@@ -190,6 +280,109 @@ impl SysError {
     }
 }
 
+#[cfg(feature = "smoltcp")]
+mod smoltcp_impls {
+    use smoltcp::{
+        socket::{tcp, udp},
+        wire,
+    };
+
+    use super::SysError;
+
+    impl From<tcp::RecvError> for SysError {
+        /// `Finished` is returned only after the peer closes and the data is
+        /// drained, which should be checked beforehand to return EOF.
+        fn from(err: tcp::RecvError) -> Self {
+            match err {
+                tcp::RecvError::InvalidState => Self::ENOTCONN,
+                tcp::RecvError::Finished => Self::ENOTCONN,
+            }
+        }
+    }
+
+    impl From<tcp::SendError> for SysError {
+        fn from(err: tcp::SendError) -> Self {
+            match err {
+                tcp::SendError::InvalidState => Self::EPIPE,
+            }
+        }
+    }
+
+    impl From<tcp::ConnectError> for SysError {
+        fn from(err: tcp::ConnectError) -> Self {
+            match err {
+                tcp::ConnectError::InvalidState => Self::EISCONN,
+                tcp::ConnectError::Unaddressable => Self::EADDRNOTAVAIL,
+            }
+        }
+    }
+
+    impl From<tcp::ListenError> for SysError {
+        fn from(err: tcp::ListenError) -> Self {
+            match err {
+                tcp::ListenError::InvalidState => Self::EINVAL,
+                tcp::ListenError::Unaddressable => Self::EINVAL,
+            }
+        }
+    }
+
+    impl From<udp::BindError> for SysError {
+        fn from(err: udp::BindError) -> Self {
+            match err {
+                udp::BindError::InvalidState => Self::EINVAL,
+                udp::BindError::Unaddressable => Self::EINVAL,
+            }
+        }
+    }
+
+    impl From<udp::SendError> for SysError {
+        fn from(err: udp::SendError) -> Self {
+            match err {
+                udp::SendError::Unaddressable => Self::EINVAL,
+                udp::SendError::BufferFull => Self::EAGAIN,
+            }
+        }
+    }
+
+    impl From<wire::Error> for SysError {
+        /// Malformed packet or address.
+        fn from(_: wire::Error) -> Self {
+            Self::EINVAL
+        }
+    }
+}
+
+/// Check that every error code converts to the variant with the same code and
+/// back, and that the aliases and unknown codes convert as expected.
+#[cfg(feature = "selftest")]
+pub fn selftest() {
+    // beyond the largest code on Linux
+    const MAX_ERRNO: i32 = 133;
+    let mut variants = 0;
+    for code in 0..=MAX_ERRNO {
+        if let Some(err) = SysError::from_repr(code) {
+            assert_eq!(err.code(), code, "{err:?}");
+            assert_eq!(SysError::from_i32(code), err);
+            assert!(!err.as_str().is_empty(), "{err:?}");
+            variants += 1;
+        } else {
+            assert_eq!(SysError::from_i32(code), SysError::EIO, "{code}");
+        }
+    }
+    for (alias, code) in [
+        (SysError::ENOTSUP, 95),
+        (SysError::EWOULDBLOCK, 11),
+        (SysError::EDEADLOCK, 35),
+    ] {
+        assert_eq!(alias.code(), code);
+        assert_eq!(SysError::from_i32(code), alias);
+    }
+    for code in [-1, i32::MIN, MAX_ERRNO + 1, i32::MAX] {
+        assert_eq!(SysError::from_i32(code), SysError::EIO);
+    }
+    assert!(variants > 0);
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 #[repr(C)]
 pub struct Rusage {
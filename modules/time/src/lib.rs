@@ -3,6 +3,8 @@
 
 use core::time::Duration;
 
+use arch::time::get_time_duration;
+
 pub mod stat;
 pub mod timespec;
 pub mod timeval;
@@ -19,4 +21,29 @@ pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
 /// 用于测量调用线程消耗的CPU时间
 pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
 
+// itimer which
+/// 以真实（墙上时钟）时间递减，到期时发送SIGALRM
+pub const ITIMER_REAL: usize = 0;
+/// 以进程消耗的用户态CPU时间递减，到期时发送SIGVTALRM
+pub const ITIMER_VIRTUAL: usize = 1;
+/// 以进程消耗的用户态和内核态CPU时间递减，到期时发送SIGPROF
+pub const ITIMER_PROF: usize = 2;
+
+/// 可设置时钟相对于计时器时间的偏差，只有CLOCK_REALTIME可以被设置
 pub static mut CLOCK_DEVIATION: [Duration; SUPPORT_CLOCK] = [Duration::ZERO; SUPPORT_CLOCK];
+
+/// Deviation of the clock `clockid` from the time of the timer, which is zero
+/// for clocks that can not be set.
+pub fn clock_deviation(clockid: usize) -> Duration {
+    unsafe {
+        CLOCK_DEVIATION
+            .get(clockid)
+            .copied()
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+/// Set CLOCK_REALTIME to `now`, the wall-clock time since the Epoch.
+pub fn set_realtime(now: Duration) {
+    unsafe { CLOCK_DEVIATION[CLOCK_REALTIME] = now.saturating_sub(get_time_duration()) }
+}
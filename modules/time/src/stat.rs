@@ -1,7 +1,7 @@
 use core::time::Duration;
 
 use arch::time::get_time_duration;
-use config::time::TIME_SLICE_DUATION;
+use config::time::TIME_SLICE_TICKS;
 
 ///                                -user-          --user--
 /// ---kernel---(switch)---kernel--      --kernel--        ------(switch)
@@ -14,10 +14,18 @@ pub struct TaskTimeStat {
     // task_start: Duration,
     system_time_start: Duration,
     user_time_start: Duration,
-    schedule_time_start: Duration,
+    /// Number of timer interrupts left in the current time slice.
+    slice_ticks: usize,
 
     child_user_time: Duration,
     child_system_stime: Duration,
+
+    /// Number of voluntary context switches, i.e. the task suspends itself.
+    voluntary_switches: usize,
+    /// Number of involuntary context switches, i.e. the task is preempted.
+    involuntary_switches: usize,
+    /// Whether the next switch out is caused by preemption.
+    preempted: bool,
 }
 
 impl TaskTimeStat {
@@ -31,7 +39,10 @@ impl TaskTimeStat {
             child_system_stime: Duration::ZERO,
             system_time_start: Duration::ZERO,
             user_time_start: Duration::ZERO,
-            schedule_time_start: Duration::ZERO,
+            slice_ticks: TIME_SLICE_TICKS,
+            voluntary_switches: 0,
+            involuntary_switches: 0,
+            preempted: false,
         }
     }
 
@@ -58,6 +69,11 @@ impl TaskTimeStat {
         self.user_time + self.system_time
     }
 
+    /// return the voluntary and involuntary context switch counts
+    pub fn context_switches(&self) -> (usize, usize) {
+        (self.voluntary_switches, self.involuntary_switches)
+    }
+
     pub fn update_child_time(&mut self, (utime, stime): (Duration, Duration)) {
         self.child_user_time += utime;
         self.child_system_stime += stime;
@@ -67,12 +83,25 @@ impl TaskTimeStat {
         let current_time = get_time_duration();
 
         self.system_time_start = current_time;
-        self.schedule_time_start = current_time;
+        self.slice_ticks = TIME_SLICE_TICKS;
     }
 
     pub fn record_switch_out(&mut self) {
         let stime_slice = get_time_duration() - self.system_time_start;
         self.system_time += stime_slice;
+
+        if self.preempted {
+            self.involuntary_switches += 1;
+            self.preempted = false;
+        } else {
+            self.voluntary_switches += 1;
+        }
+    }
+
+    /// Mark that the task is going to be switched out because of preemption,
+    /// e.g. its time slice is used up.
+    pub fn record_preempt(&mut self) {
+        self.preempted = true;
     }
 
     pub fn record_trap(&mut self) {
@@ -87,13 +116,16 @@ impl TaskTimeStat {
     pub fn record_trap_return(&mut self) {
         let current_time = get_time_duration();
 
-        let stime_slice = current_time - self.user_time_start;
+        let stime_slice = current_time - self.system_time_start;
         self.system_time += stime_slice;
 
         self.user_time_start = current_time;
     }
 
-    pub fn need_schedule(&self) -> bool {
-        get_time_duration() - self.schedule_time_start >= TIME_SLICE_DUATION
+    /// Consume one tick of the time slice on timer interrupt. Return true if
+    /// the time slice is used up.
+    pub fn tick(&mut self) -> bool {
+        self.slice_ticks = self.slice_ticks.saturating_sub(1);
+        self.slice_ticks == 0
     }
 }
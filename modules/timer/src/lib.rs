@@ -1,14 +1,28 @@
 #![no_std]
 #![no_main]
-use core::{cmp::Reverse, task::Waker, time::Duration};
+use core::{
+    cmp::Reverse,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+    task::Waker,
+    time::Duration,
+};
 extern crate alloc;
-use alloc::{boxed::Box, collections::BinaryHeap};
+use alloc::{boxed::Box, collections::BinaryHeap, sync::Arc};
 
 use arch::time::get_time_duration;
+use config::board::MAX_HARTS;
+use crate_interface::call_interface;
 use spin::Lazy;
 use sync::mutex::SpinNoIrqLock;
 
 pub mod timelimited_task;
+pub mod timeout;
+
+#[crate_interface::def_interface]
+pub trait TimerIf: Send + Sync {
+    /// Id of the hart adding or checking timers.
+    fn hart_id() -> usize;
+}
 
 /// A trait that defines the event to be triggered when a timer expires.
 /// The TimerEvent trait requires a callback method to be implemented,
@@ -33,11 +47,66 @@ pub struct Timer {
     /// A boxed dynamic trait object that implements the TimerEvent trait.
     /// This allows different types of events to be associated with the timer.
     pub data: Box<dyn TimerEvent>,
+
+    /// Shared with the `TimerHandle` to cancel the timer.
+    state: Arc<TimerState>,
+}
+
+const TIMER_ARMED: u8 = 0;
+const TIMER_FIRED: u8 = 1;
+const TIMER_CANCELLED: u8 = 2;
+
+struct TimerState {
+    state: AtomicU8,
+    /// The hart whose queue the timer is in.
+    hart_id: AtomicUsize,
+}
+
+/// Handle of a timer added into `TIMER_MANAGER`, which can be used to cancel
+/// the timer, e.g. when the timed wait completes before the timer expires.
+#[derive(Clone)]
+pub struct TimerHandle(Arc<TimerState>);
+
+impl TimerHandle {
+    /// Cancel the timer so that its event will never be triggered. Return
+    /// false if the timer has already fired or been cancelled.
+    pub fn cancel(&self) -> bool {
+        let queue = &TIMER_MANAGER.queues[self.0.hart_id.load(Ordering::Relaxed)];
+        let mut queue = queue.lock();
+        if self
+            .0
+            .state
+            .compare_exchange(
+                TIMER_ARMED,
+                TIMER_CANCELLED,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return false;
+        }
+        TIMER_MANAGER.cancelled.fetch_add(1, Ordering::Relaxed);
+        queue.cancelled += 1;
+        queue.purge_if_needed();
+        true
+    }
+
+    pub fn is_fired(&self) -> bool {
+        self.0.state.load(Ordering::Acquire) == TIMER_FIRED
+    }
 }
 
 impl Timer {
     pub fn new(expire: Duration, data: Box<dyn TimerEvent>) -> Self {
-        Self { expire, data }
+        Self {
+            expire,
+            data,
+            state: Arc::new(TimerState {
+                state: AtomicU8::new(TIMER_ARMED),
+                hart_id: AtomicUsize::new(0),
+            }),
+        }
     }
 
     pub fn new_waker_timer(expire: Duration, waker: Waker) -> Self {
@@ -51,10 +120,19 @@ impl Timer {
             }
         }
 
-        Self {
-            expire,
-            data: Box::new(WakerData { waker }),
-        }
+        Self::new(expire, Box::new(WakerData { waker }))
+    }
+
+    fn handle(&self) -> TimerHandle {
+        TimerHandle(self.state.clone())
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.state.state.load(Ordering::Acquire) == TIMER_CANCELLED
+    }
+
+    fn fire(&self) {
+        self.state.state.store(TIMER_FIRED, Ordering::Release);
     }
 
     fn callback(self) -> Option<Timer> {
@@ -82,50 +160,145 @@ impl PartialEq for Timer {
     }
 }
 
+/// Cancelled timers are left in the queue and skipped when they are popped,
+/// until they make up more than half of the queue and there are at least
+/// `PURGE_THRESHOLD` of them, so that cancellation is amortized O(1) while
+/// memory is bounded by twice the armed timers.
+const PURGE_THRESHOLD: usize = 64;
+
+/// A min-heap of timers ordered by expiration time.
+struct TimerQueue {
+    timers: BinaryHeap<Reverse<Timer>>,
+    /// Number of cancelled timers still in the heap.
+    cancelled: usize,
+}
+
+impl TimerQueue {
+    const fn new() -> Self {
+        Self {
+            timers: BinaryHeap::new(),
+            cancelled: 0,
+        }
+    }
+
+    fn armed(&self) -> usize {
+        self.timers.len() - self.cancelled
+    }
+
+    fn purge_if_needed(&mut self) {
+        if self.cancelled >= PURGE_THRESHOLD && self.cancelled * 2 > self.timers.len() {
+            self.timers.retain(|timer| !timer.0.is_cancelled());
+            self.cancelled = 0;
+        }
+    }
+}
+
+/// Counters of timers since boot.
+#[derive(Debug, Clone, Copy)]
+pub struct TimerStats {
+    pub armed: usize,
+    pub fired: usize,
+    pub cancelled: usize,
+    /// Timers in the queues now, including the cancelled ones not purged yet.
+    pub queued: usize,
+}
+
 /// `TimerManager` is responsible for managing all the timers in the system.
-/// It uses a thread-safe lock to protect a priority queue (binary heap) that
-/// stores the timers. The timers are stored in a `BinaryHeap` with their
-/// expiration times wrapped in `Reverse` to create a min-heap, ensuring that
-/// the timer with the earliest expiration time is at the top.
+/// Each hart has its own queue of timers, so that harts checking timers on
+/// timer interrupts will not contend for a global lock. A timer is added into
+/// the queue of the local hart and fired by the same hart.
 pub struct TimerManager {
-    /// A priority queue to store the timers. The queue is protected by a spin
-    /// lock to ensure thread-safe access. The timers are wrapped in
-    /// `Reverse` to maintain a min-heap.
-    timers: SpinNoIrqLock<BinaryHeap<Reverse<Timer>>>,
+    queues: [SpinNoIrqLock<TimerQueue>; MAX_HARTS],
+    armed: AtomicUsize,
+    fired: AtomicUsize,
+    cancelled: AtomicUsize,
 }
 
 impl TimerManager {
     fn new() -> Self {
         Self {
-            timers: SpinNoIrqLock::new(BinaryHeap::new()),
+            queues: core::array::from_fn(|_| SpinNoIrqLock::new(TimerQueue::new())),
+            armed: AtomicUsize::new(0),
+            fired: AtomicUsize::new(0),
+            cancelled: AtomicUsize::new(0),
         }
     }
 
-    pub fn add_timer(&self, timer: Timer) {
+    pub fn add_timer(&self, timer: Timer) -> TimerHandle {
         log::debug!("add new timer, next expiration {:?}", timer.expire);
-        self.timers.lock().push(Reverse(timer));
+        let hart_id = call_interface!(TimerIf::hart_id());
+        let handle = timer.handle();
+        let mut queue = self.queues[hart_id].lock();
+        self.push(hart_id, &mut queue, timer);
+        handle
+    }
+
+    fn push(&self, hart_id: usize, queue: &mut TimerQueue, timer: Timer) {
+        timer.state.hart_id.store(hart_id, Ordering::Relaxed);
+        self.armed.fetch_add(1, Ordering::Relaxed);
+        queue.timers.push(Reverse(timer));
     }
 
+    /// Whether there are no pending timers.
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|queue| queue.lock().armed() == 0)
+    }
+
+    pub fn stats(&self) -> TimerStats {
+        TimerStats {
+            armed: self.armed.load(Ordering::Relaxed),
+            fired: self.fired.load(Ordering::Relaxed),
+            cancelled: self.cancelled.load(Ordering::Relaxed),
+            queued: self
+                .queues
+                .iter()
+                .map(|queue| queue.lock().timers.len())
+                .sum(),
+        }
+    }
+
+    /// Fire expired timers in the queue of the local hart.
+    ///
+    /// Each expired timer is popped with the queue locked, and its callback
+    /// runs after the lock is released, so that callbacks may add or cancel
+    /// timers.
     pub fn check(&self) {
-        let mut timers = self.timers.lock();
+        let hart_id = call_interface!(TimerIf::hart_id());
+        while let Some(timer) = self.pop_expired(hart_id) {
+            if let Some(new_timer) = timer.callback() {
+                let mut queue = self.queues[hart_id].lock();
+                self.push(hart_id, &mut queue, new_timer);
+            }
+        }
+    }
 
-        while let Some(timer) = timers.peek() {
+    /// Pop the first expired timer in the queue of `hart_id`, skipping the
+    /// cancelled ones, and mark it fired.
+    fn pop_expired(&self, hart_id: usize) -> Option<Timer> {
+        let mut queue = self.queues[hart_id].lock();
+        while let Some(timer) = queue.timers.peek() {
+            if timer.0.is_cancelled() {
+                queue.timers.pop();
+                queue.cancelled -= 1;
+                continue;
+            }
             let current_time = get_time_duration();
-            if current_time >= timer.0.expire {
-                log::trace!("timers len {}", timers.len());
-                log::info!(
-                    "[Timer Manager] there is a timer expired, current:{:?}, expire:{:?}",
-                    current_time,
-                    timer.0.expire
-                );
-                let timer = timers.pop().unwrap().0;
-                if let Some(new_timer) = timer.callback() {
-                    timers.push(Reverse(new_timer));
-                }
-            } else {
-                break;
+            if current_time < timer.0.expire {
+                return None;
             }
+            log::trace!("timers len {}", queue.timers.len());
+            log::info!(
+                "[Timer Manager] there is a timer expired, current:{:?}, expire:{:?}",
+                current_time,
+                timer.0.expire
+            );
+            let timer = queue.timers.pop().unwrap().0;
+            // cancellation also holds the lock, so it can not happen here
+            timer.fire();
+            self.fired.fetch_add(1, Ordering::Relaxed);
+            return Some(timer);
         }
+        None
     }
 }
 
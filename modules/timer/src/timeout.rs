@@ -0,0 +1,84 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use arch::time::get_time_duration;
+
+use crate::{Timer, TimerHandle, TIMER_MANAGER};
+
+/// Error returned by `TimeoutFuture` when the deadline has passed before the
+/// inner future completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedOut;
+
+/// Races a future against a deadline. A waker timer is armed on the first poll
+/// that the inner future is pending, and it is cancelled once the inner future
+/// completes or the `TimeoutFuture` is dropped, so that no stale wakeup is
+/// left in `TIMER_MANAGER`.
+pub struct TimeoutFuture<F: Future> {
+    deadline: Duration,
+    future: F,
+    timer: Option<TimerHandle>,
+}
+
+impl<F: Future> TimeoutFuture<F> {
+    pub fn new(deadline: Duration, future: F) -> Self {
+        Self {
+            deadline,
+            future,
+            timer: None,
+        }
+    }
+
+    fn cancel_timer(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            timer.cancel();
+        }
+    }
+}
+
+impl<F: Future> Future for TimeoutFuture<F> {
+    type Output = Result<F::Output, TimedOut>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let ret = unsafe { Pin::new_unchecked(&mut this.future).poll(cx) };
+        match ret {
+            Poll::Ready(ret) => {
+                this.cancel_timer();
+                Poll::Ready(Ok(ret))
+            }
+            Poll::Pending if get_time_duration() >= this.deadline => {
+                log::info!("[TimeoutFuture] time out");
+                this.cancel_timer();
+                Poll::Ready(Err(TimedOut))
+            }
+            Poll::Pending => {
+                if this.timer.is_none() {
+                    let timer = Timer::new_waker_timer(this.deadline, cx.waker().clone());
+                    this.timer = Some(TIMER_MANAGER.add_timer(timer));
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<F: Future> Drop for TimeoutFuture<F> {
+    fn drop(&mut self) {
+        self.cancel_timer();
+    }
+}
+
+/// Run `future` for at most `limit`.
+pub fn timeout<F: Future>(limit: Duration, future: F) -> TimeoutFuture<F> {
+    TimeoutFuture::new(get_time_duration() + limit, future)
+}
+
+/// Run `future` until `deadline`, which is measured by `get_time_duration`.
+pub fn timeout_at<F: Future>(deadline: Duration, future: F) -> TimeoutFuture<F> {
+    TimeoutFuture::new(deadline, future)
+}
@@ -0,0 +1,140 @@
+//! Accounting and reclaim of the dentry cache.
+//!
+//! A dentry stays in the children map of its parent once looked up, and so do
+//! negative dentries of files that do not exist, so that later lookups need
+//! not ask the filesystem again. Looked up dentries are also kept in an LRU
+//! list, whose least recently used entries are evicted from the tree when the
+//! list grows over `DCACHE_CAPACITY` or when frames run out.
+
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use config::fs::DCACHE_CAPACITY;
+use lru::LruCache;
+use spin::Lazy;
+
+use crate::{dentry::RENAME_LOCK, Dentry, InodeState, Mutex};
+
+static HITS: AtomicUsize = AtomicUsize::new(0);
+static MISSES: AtomicUsize = AtomicUsize::new(0);
+static EVICTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Recently looked up dentries, keyed by their addresses. Weak references are
+/// kept so that the list itself does not pin dentries.
+static LRU: Lazy<Mutex<LruCache<usize, Weak<dyn Dentry>>>> =
+    Lazy::new(|| Mutex::new(LruCache::unbounded()));
+
+#[derive(Debug, Clone, Copy)]
+pub struct DcacheStat {
+    /// Number of dentries in the LRU list.
+    pub nr_dentry: usize,
+    /// Lookups answered by the cache.
+    pub hits: usize,
+    /// Lookups passed to the filesystem.
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+pub fn dcache_stat() -> DcacheStat {
+    DcacheStat {
+        nr_dentry: LRU.lock().len(),
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        evictions: EVICTIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Account a lookup which results in `dentry`, and move it to the head of the
+/// LRU list.
+pub(crate) fn record_lookup(dentry: &Arc<dyn Dentry>, hit: bool) {
+    if hit {
+        HITS.fetch_add(1, Ordering::Relaxed);
+    } else {
+        MISSES.fetch_add(1, Ordering::Relaxed);
+    }
+    let key = Arc::as_ptr(dentry) as *const () as usize;
+    let len = {
+        let mut lru = LRU.lock();
+        lru.put(key, Arc::downgrade(dentry));
+        lru.len()
+    };
+    if len > DCACHE_CAPACITY {
+        dcache_shrink(len - DCACHE_CAPACITY);
+    }
+}
+
+/// Evict at most `nr` least recently used dentries and return the number
+/// evicted.
+///
+/// Dentries that can not be evicted, e.g. ones still in use, are dropped from
+/// the LRU list on the way, and will be put back by their next lookup.
+pub fn dcache_shrink(nr: usize) -> usize {
+    let mut evicted = 0;
+    while evicted < nr {
+        let Some((_, dentry)) = LRU.lock().pop_lru() else {
+            break;
+        };
+        if dentry.upgrade().is_some_and(try_evict) {
+            evicted += 1;
+        }
+    }
+    EVICTIONS.fetch_add(evicted, Ordering::Relaxed);
+    evicted
+}
+
+/// Remove `dentry` from its parent if it is referenced by the tree only and
+/// can be looked up from the filesystem again, which drops its inode and the
+/// page cache with it.
+fn try_evict(dentry: Arc<dyn Dentry>) -> bool {
+    let Some(parent) = dentry.parent() else {
+        return false;
+    };
+    // Root of a mounted filesystem
+    if !Arc::ptr_eq(&dentry.super_block(), &parent.super_block()) {
+        return false;
+    }
+    if let Ok(inode) = dentry.inode() {
+        // Files in memory only, e.g. files in tmpfs and FIFOs, would be lost.
+        let itype = inode.itype();
+        if dentry.super_block().meta().device.is_none()
+            || !(itype.is_file() || itype.is_dir() || itype.is_symlink())
+        {
+            return false;
+        }
+        // Flushing dirty pages may need frames, which is not done here since
+        // we may be called for lack of frames.
+        if inode.state() == InodeState::Dirty || Arc::strong_count(&inode) > 2 {
+            return false;
+        }
+    }
+
+    // The children maps of the parent and the dentry are locked together,
+    // which is only done under the rename lock. Eviction is skipped while a
+    // rename is in progress, which may itself be looking up and evicting.
+    let Some(_rename) = RENAME_LOCK.try_lock() else {
+        return false;
+    };
+    let name = dentry.name_string();
+    let mut children = parent.meta().children.lock();
+    // Held by the children map of the parent and us only. Checked with the map
+    // locked, so that no lookup can take it meanwhile.
+    if Arc::strong_count(&dentry) > 2 || !dentry.meta().children.lock().is_empty() {
+        return false;
+    }
+    match children.get(&name) {
+        Some(child) if Arc::ptr_eq(child, &dentry) => {
+            children.remove(&name);
+        }
+        _ => return false,
+    }
+    drop(children);
+
+    log::trace!("[dcache] evict {name} in path {}", parent.path());
+    if !dentry.is_negetive() {
+        // The directory should be read from disk again to list the child.
+        if let Ok(parent_inode) = parent.inode() {
+            parent_inode.set_state(InodeState::UnInit);
+        }
+    }
+    true
+}
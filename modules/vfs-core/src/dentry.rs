@@ -8,14 +8,23 @@ use core::{default, fmt::Error, mem::MaybeUninit, str::FromStr};
 use sync::mutex::spin_mutex::SpinMutex;
 use systype::{SysError, SysResult, SyscallResult};
 
-use crate::{inode::Inode, File, InodeMode, InodeState, InodeType, Mutex, RenameFlags, SuperBlock};
+use crate::{
+    dcache::record_lookup, inode::Inode, File, InodeMode, InodeState, InodeType, Mutex,
+    RenameFlags, SuperBlock,
+};
+
+/// Held during renames, see `Dentry::rename_to`, and by dcache eviction.
+///
+/// Children maps of two dentries are only locked together with it held, so
+/// that no two of them are taken in opposite orders.
+pub(crate) static RENAME_LOCK: Mutex<()> = Mutex::new(());
 
 pub struct DentryMeta {
-    /// Name of this file or directory.
-    pub name: String,
+    /// Name of this file or directory, which is changed by rename.
+    pub name: Mutex<String>,
     pub super_block: Weak<dyn SuperBlock>,
-    /// Parent dentry. `None` if root dentry.
-    pub parent: Option<Weak<dyn Dentry>>,
+    /// Parent dentry. `None` if root dentry. It is changed by rename.
+    pub parent: Mutex<Option<Weak<dyn Dentry>>>,
 
     /// Inode it points to. May be `None`, which is called negative dentry.
     pub inode: Mutex<Option<Arc<dyn Inode>>>,
@@ -35,10 +44,10 @@ impl DentryMeta {
         let super_block = Arc::downgrade(&super_block);
         let inode = Mutex::new(None);
         Self {
-            name: name.to_string(),
+            name: Mutex::new(name.to_string()),
             super_block,
             inode,
-            parent: parent.map(|p| Arc::downgrade(&p)),
+            parent: Mutex::new(parent.map(|p| Arc::downgrade(&p))),
             children: Mutex::new(BTreeMap::new()),
             state: Mutex::new(DentryState::UnInit),
         }
@@ -90,11 +99,24 @@ pub trait Dentry: Send + Sync {
         Err(SysError::EINVAL)
     }
 
+    /// Create the node of the special file `name` with the file type of
+    /// `mode`, e.g. a FIFO, whose inode is made by the caller, see
+    /// `Dentry::mknod`. Filesystems in memory keep it in the dentry cache only.
+    fn base_mknod(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<()> {
+        Ok(())
+    }
+
     /// Create a negetive child dentry with `name`.
     fn base_new_child(self: Arc<Self>, _name: &str) -> Arc<dyn Dentry> {
         todo!()
     }
 
+    /// Write the mode and owner of the inode back to disk after they are
+    /// changed. Filesystems in memory keep them in the inode only.
+    fn base_write_attr(self: Arc<Self>) -> SysResult<()> {
+        Ok(())
+    }
+
     fn inode(&self) -> SysResult<Arc<dyn Inode>> {
         self.meta()
             .inode
@@ -109,15 +131,19 @@ pub trait Dentry: Send + Sync {
     }
 
     fn name_string(&self) -> String {
-        self.meta().name.clone()
+        self.meta().name.lock().clone()
     }
 
-    fn name(&self) -> &str {
-        &self.meta().name
+    fn name(&self) -> String {
+        self.name_string()
     }
 
     fn parent(&self) -> Option<Arc<dyn Dentry>> {
-        self.meta().parent.as_ref().map(|p| p.upgrade().unwrap())
+        self.meta()
+            .parent
+            .lock()
+            .as_ref()
+            .map(|p| p.upgrade().unwrap())
     }
 
     fn children(&self) -> BTreeMap<String, Arc<dyn Dentry>> {
@@ -160,9 +186,9 @@ pub trait Dentry: Send + Sync {
         if let Some(p) = self.parent() {
             let p_path = p.path();
             if p_path == "/" {
-                p_path + self.name()
+                p_path + &self.name()
             } else {
-                p_path + "/" + self.name()
+                p_path + "/" + &self.name()
             }
         } else {
             String::from("/")
@@ -193,10 +219,14 @@ impl dyn Dentry {
                 "[Dentry::lookup] lookup {name} not in cache in path {}",
                 self.path()
             );
+            // The child is left negative if the file does not exist, which is
+            // cached as well.
             self.clone().base_lookup(name)?;
             child.set_state(DentryState::Sync);
+            record_lookup(&child, false);
             return Ok(child);
         }
+        record_lookup(&child, true);
         Ok(child)
     }
 
@@ -207,7 +237,28 @@ impl dyn Dentry {
         let child = self.get_child_or_create(name);
         if child.is_negetive() {
             self.clone().base_create(name, mode)?;
+            child.set_state(DentryState::Sync);
+        }
+        Ok(child)
+    }
+
+    /// Make the negative child `name` point to `inode` of a special file, which
+    /// is created by the caller instead of the filesystem, e.g. FIFOs whose
+    /// data is kept in memory. The node itself is stored by the filesystem.
+    pub fn mknod(
+        self: &Arc<Self>,
+        name: &str,
+        inode: Arc<dyn Inode>,
+    ) -> SysResult<Arc<dyn Dentry>> {
+        if !self.inode()?.itype().is_dir() {
+            return Err(SysError::ENOTDIR);
+        }
+        let child = self.get_child_or_create(name);
+        if !child.is_negetive() {
+            return Err(SysError::EEXIST);
         }
+        self.clone().base_mknod(name, inode.mode())?;
+        child.set_inode(inode);
         Ok(child)
     }
 
@@ -216,29 +267,100 @@ impl dyn Dentry {
             return Err(SysError::ENOTDIR);
         }
         let sub_dentry = self.get_child(name).ok_or(SysError::ENOENT)?;
-        sub_dentry.inode()?.set_state(InodeState::Removed);
+        let sub_inode = sub_dentry.inode()?;
+        sub_inode.set_state(InodeState::Removed);
         self.clone().base_unlink(name)?;
         sub_dentry.clear_inode();
         Ok(())
     }
 
-    pub fn rename_to(self: &Arc<Self>, new: &Arc<Self>, flags: RenameFlags) -> SysResult<()> {
+    pub async fn rename_to(self: &Arc<Self>, new: &Arc<Self>, flags: RenameFlags) -> SysResult<()> {
         if flags.contains(RenameFlags::RENAME_EXCHANGE)
             && (flags.contains(RenameFlags::RENAME_NOREPLACE)
                 || flags.contains(RenameFlags::RENAME_WHITEOUT))
         {
             return Err(SysError::EINVAL);
         }
-        if new.is_descendant_of(self) {
+        let old_inode = self.inode()?;
+        if !Arc::ptr_eq(&self.super_block(), &new.super_block()) {
+            return Err(SysError::EXDEV);
+        }
+        if Arc::ptr_eq(self, new) {
+            return Ok(());
+        }
+        // A directory can not be moved into itself, and neither can its
+        // ancestor when exchanging.
+        if new.is_descendant_of(self)
+            || (flags.contains(RenameFlags::RENAME_EXCHANGE) && self.is_descendant_of(new))
+        {
             return Err(SysError::EINVAL);
         }
 
         if new.is_negetive() && flags.contains(RenameFlags::RENAME_EXCHANGE) {
             return Err(SysError::ENOENT);
-        } else if flags.contains(RenameFlags::RENAME_NOREPLACE) {
+        } else if !new.is_negetive() && flags.contains(RenameFlags::RENAME_NOREPLACE) {
             return Err(SysError::EEXIST);
         }
-        self.clone().base_rename_to(new.clone(), flags)
+        if !new.is_negetive() && !flags.contains(RenameFlags::RENAME_EXCHANGE) {
+            let new_inode = new.inode()?;
+            match (old_inode.itype().is_dir(), new_inode.itype().is_dir()) {
+                (true, false) => return Err(SysError::ENOTDIR),
+                (false, true) => return Err(SysError::EISDIR),
+                (true, true) => {
+                    // children on disk may not be in the cache yet
+                    new.open()?.load_dir().await?;
+                    if new.children().values().any(|child| !child.is_negetive()) {
+                        return Err(SysError::ENOTEMPTY);
+                    }
+                }
+                (false, false) => {}
+            }
+        }
+
+        // Renames are serialized, so that the children maps of both parents can
+        // be locked together.
+        let _guard = RENAME_LOCK.lock();
+        self.clone().base_rename_to(new.clone(), flags)?;
+        // Filesystems move inodes between the two dentries. Swap the dentries
+        // back to their inodes and move them in the tree instead, so that
+        // references to the renamed dentry, e.g. cwd of tasks, and paths of its
+        // children follow it.
+        self.swap_with(new);
+        Ok(())
+    }
+
+    /// Swap inodes, names and parents of two dentries, which are not roots.
+    ///
+    /// Children maps of the parents are locked during the swap, so lookups see
+    /// either both names before the swap or both after.
+    fn swap_with(self: &Arc<Self>, other: &Arc<Self>) {
+        fn swap<T: Default>(a: &Mutex<T>, b: &Mutex<T>) {
+            let a_val = core::mem::take(&mut *a.lock());
+            let b_val = core::mem::replace(&mut *b.lock(), a_val);
+            *a.lock() = b_val;
+        }
+        let self_parent = self.parent().expect("can not rename root dentry");
+        let other_parent = other.parent().expect("can not rename root dentry");
+        let self_name = self.name_string();
+        let other_name = other.name_string();
+
+        let mut self_children = self_parent.meta().children.lock();
+        let mut other_children = if Arc::ptr_eq(&self_parent, &other_parent) {
+            None
+        } else {
+            Some(other_parent.meta().children.lock())
+        };
+
+        let (self_meta, other_meta) = (self.meta(), other.meta());
+        swap(&self_meta.inode, &other_meta.inode);
+        swap(&self_meta.name, &other_meta.name);
+        swap(&self_meta.parent, &other_meta.parent);
+
+        self_children.insert(self_name, other.clone());
+        other_children
+            .as_deref_mut()
+            .unwrap_or(&mut self_children)
+            .insert(other_name, self.clone());
     }
 
     pub fn symlink(self: &Arc<Self>, name: &str, target: &str) -> SysResult<()> {
@@ -265,6 +387,17 @@ impl dyn Dentry {
         }
     }
 
+    /// Change the permission bits of the inode, see `Inode::set_mode`.
+    pub fn set_mode(self: &Arc<Self>, mode: InodeMode) -> SysResult<()> {
+        self.inode()?.set_mode(mode);
+        self.clone().base_write_attr()
+    }
+
+    pub fn set_owner(self: &Arc<Self>, uid: u32, gid: u32) -> SysResult<()> {
+        self.inode()?.set_owner(uid, gid);
+        self.clone().base_write_attr()
+    }
+
     /// Create a negetive child dentry with `name`.
     pub fn new_child(self: &Arc<Self>, name: &str) -> Arc<dyn Dentry> {
         let child = self.clone().base_new_child(name);
@@ -279,10 +412,11 @@ impl dyn Dentry {
         })
     }
 
+    /// Whether `dir` is a proper ancestor of the dentry.
     pub fn is_descendant_of(self: &Arc<Self>, dir: &Arc<Self>) -> bool {
         let mut parent_opt = self.parent();
         while let Some(parent) = parent_opt {
-            if Arc::ptr_eq(self, dir) {
+            if Arc::ptr_eq(&parent, dir) {
                 return true;
             }
             parent_opt = parent.parent();
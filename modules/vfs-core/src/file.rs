@@ -1,6 +1,8 @@
 use alloc::{boxed::Box, ffi::CString, string::String, sync::Arc, vec, vec::Vec};
 use core::{
     cmp,
+    future::Future,
+    pin::Pin,
     sync::atomic::{AtomicUsize, Ordering},
     usize,
 };
@@ -8,6 +10,7 @@ use core::{
 use async_trait::async_trait;
 use config::{
     board::BLOCK_SIZE,
+    fs::{READ_AHEAD_INIT_PAGES, READ_AHEAD_MAX_PAGES},
     mm::{
         align_offset_to_page, block_page_id, round_down_to_page, round_up_to_page,
         MAX_BUFFERS_PER_PAGE, PAGE_MASK, PAGE_SIZE,
@@ -24,6 +27,12 @@ use crate::{
     SuperBlock,
 };
 
+/// Interface for running the read ahead of a file in the background.
+#[crate_interface::def_interface]
+pub trait ReadAheadIf {
+    fn spawn_read_ahead(future: Pin<Box<dyn Future<Output = ()> + Send>>);
+}
+
 pub struct FileMeta {
     /// Dentry which pointes to this file.
     pub dentry: Arc<dyn Dentry>,
@@ -33,6 +42,7 @@ pub struct FileMeta {
     /// WARN: may cause trouble if this is not locked with other things.
     pub pos: AtomicUsize,
     pub flags: Mutex<OpenFlags>,
+    pub read_ahead: Mutex<ReadAhead>,
 }
 
 impl FileMeta {
@@ -42,10 +52,38 @@ impl FileMeta {
             inode,
             pos: 0.into(),
             flags: Mutex::new(OpenFlags::empty()),
+            read_ahead: Mutex::new(ReadAhead::default()),
         }
     }
 }
 
+/// Read pattern of an opened file, which decides how many pages are read from
+/// disk at once when the page cache misses.
+#[derive(Default)]
+pub struct ReadAhead {
+    /// Offset right after the last read, where a sequential read begins.
+    prev_end: usize,
+    /// Pages to read on a miss, zero for random reads.
+    window: usize,
+}
+
+impl ReadAhead {
+    /// Account a read of `len` bytes at `offset`, and return the window for
+    /// it. The window grows on every sequential read up to
+    /// `READ_AHEAD_MAX_PAGES`, and is reset by seeks.
+    fn update(&mut self, offset: usize, len: usize) -> usize {
+        self.window = if offset != self.prev_end {
+            0
+        } else if self.window == 0 {
+            READ_AHEAD_INIT_PAGES
+        } else {
+            cmp::min(self.window * 2, READ_AHEAD_MAX_PAGES)
+        };
+        self.prev_end = offset + len;
+        self.window
+    }
+}
+
 #[async_trait]
 pub trait File: Send + Sync + DowncastSync {
     fn meta(&self) -> &FileMeta;
@@ -67,7 +105,7 @@ pub trait File: Send + Sync + DowncastSync {
     }
 
     /// Load all dentry and inodes in a directory. Will not advance dir offset.
-    fn base_load_dir(&self) -> SysResult<()> {
+    async fn base_load_dir(&self) -> SysResult<()> {
         todo!()
     }
 
@@ -82,6 +120,11 @@ pub trait File: Send + Sync + DowncastSync {
 
         let inode = self.inode();
         let page_cache = inode.page_cache().unwrap();
+        let _fill = page_cache.fill_lock().lock().await;
+        // filled by others while waiting for the lock
+        if let Some(page) = page_cache.get_page(offset_aligned) {
+            return Ok(Some(page));
+        }
 
         let device = inode.super_block().device();
         let page = Page::new_file(&device);
@@ -89,6 +132,8 @@ pub trait File: Send + Sync + DowncastSync {
         let len = self
             .base_read_at(offset_aligned, page.bytes_array())
             .await?;
+        // bytes beyond the data on disk, e.g. in a hole, read as zeros
+        page.bytes_array()[len..].fill(0);
 
         // let virtio_blk = device
         //     .downcast_arc::<VirtIoBlkDev>()
@@ -113,6 +158,63 @@ pub trait File: Send + Sync + DowncastSync {
         Ok(Some(page))
     }
 
+    /// Read `nr_demand` pages requested and `nr_ahead` pages after them from
+    /// `offset_aligned` into page cache with one request to the filesystem,
+    /// stopping at the first page cached or EOF. At most
+    /// `READ_AHEAD_MAX_PAGES` are read at once, so that the buffer stays small.
+    ///
+    /// Returns the first page.
+    async fn read_pages_at(
+        &self,
+        offset_aligned: usize,
+        nr_demand: usize,
+        nr_ahead: usize,
+    ) -> SysResult<Option<Arc<Page>>> {
+        let nr = cmp::min(nr_demand + nr_ahead, READ_AHEAD_MAX_PAGES);
+        if nr_ahead == 0 && nr <= 1 {
+            return self.read_page_at(offset_aligned).await;
+        }
+        let size = self.size();
+        if offset_aligned >= size {
+            log::warn!("[File::read_pages_at] reach end of file");
+            return Ok(None);
+        }
+
+        let inode = self.inode();
+        let page_cache = inode.page_cache().unwrap();
+        let _fill = page_cache.fill_lock().lock().await;
+        if let Some(page) = page_cache.get_page(offset_aligned) {
+            return Ok(Some(page));
+        }
+
+        let nr = (1..nr)
+            .map(|i| offset_aligned + i * PAGE_SIZE)
+            .take_while(|&offset| offset < size && page_cache.get_page(offset).is_none())
+            .count()
+            + 1;
+        log::debug!("[File::read_pages_at] read {nr} pages at offset {offset_aligned}");
+        let mut buf = vec![0u8; nr * PAGE_SIZE];
+        let len = self.base_read_at(offset_aligned, &mut buf).await?;
+
+        let device = inode.super_block().device();
+        let mut first = None;
+        for (i, bytes) in buf[..len].chunks(PAGE_SIZE).enumerate() {
+            let page = Page::new_file(&device);
+            page.bytes_array()[..bytes.len()].copy_from_slice(bytes);
+            page.bytes_array()[bytes.len()..].fill(0);
+            let offset = offset_aligned + i * PAGE_SIZE;
+            if i == 0 {
+                first = Some(page.clone());
+            }
+            if i < nr_demand {
+                page_cache.insert_page(offset, page);
+            } else {
+                page_cache.insert_read_ahead_page(offset, page);
+            }
+        }
+        Ok(first)
+    }
+
     /// Read at an `offset`, and will fill `buf` until `buf` is full or eof is
     /// reached. Will not advance offset.
     ///
@@ -132,6 +234,7 @@ pub trait File: Send + Sync + DowncastSync {
             return Ok(count);
         };
 
+        let mut nr_ahead = self.meta().read_ahead.lock().update(offset, buf.len());
         let mut buf_it = buf;
         let mut offset_it = offset;
 
@@ -139,12 +242,23 @@ pub trait File: Send + Sync + DowncastSync {
         while !buf_it.is_empty() && offset_it < self.size() {
             let (offset_aligned, offset_in_page) = align_offset_to_page(offset_it);
             let page = if let Some(page) = page_cache.get_page(offset_aligned) {
-                page
-            } else if let Some(page) = self.read_page_at(offset_aligned).await? {
+                page_cache.mark_accessed(offset_aligned);
                 page
             } else {
-                // no page means EOF
-                break;
+                let nr_demand = cmp::min(
+                    (round_up_to_page(offset_it + buf_it.len()) - offset_aligned) / PAGE_SIZE,
+                    READ_AHEAD_MAX_PAGES,
+                );
+                let Some(page) = self.read_pages_at(offset_aligned, nr_demand, 0).await? else {
+                    // no page means EOF
+                    break;
+                };
+                // the window is read once per read(2) in the background
+                if nr_ahead != 0 {
+                    self.spawn_read_ahead(offset_aligned + nr_demand * PAGE_SIZE, nr_ahead);
+                    nr_ahead = 0;
+                }
+                page
             };
             let len = (buf_it.len())
                 .min(PAGE_SIZE - offset_in_page)
@@ -159,6 +273,26 @@ pub trait File: Send + Sync + DowncastSync {
         Ok(offset_it - offset)
     }
 
+    /// Read `nr_ahead` pages from `offset_aligned` into page cache in the
+    /// background, through another file opened on the same dentry.
+    fn spawn_read_ahead(&self, offset_aligned: usize, nr_ahead: usize) {
+        if offset_aligned >= self.size() {
+            return;
+        }
+        let file = match self.dentry().open() {
+            Ok(file) => file,
+            Err(e) => {
+                log::warn!("[File::spawn_read_ahead] can not open file: {e:?}");
+                return;
+            }
+        };
+        crate_interface::call_interface!(ReadAheadIf::spawn_read_ahead(Box::pin(async move {
+            if let Err(e) = file.read_pages_at(offset_aligned, 0, nr_ahead).await {
+                log::warn!("[File::spawn_read_ahead] read ahead failed: {e:?}");
+            }
+        })));
+    }
+
     async fn get_page_at(&self, offset_aligned: usize) -> SysResult<Option<Arc<Page>>> {
         let inode = self.inode();
         let page_cache = inode.page_cache().unwrap();
@@ -195,8 +329,16 @@ pub trait File: Send + Sync + DowncastSync {
             return Ok(count);
         };
 
-        if offset > self.size() {
-            todo!("offset greater than size, will create hole");
+        // Writing beyond the end leaves a hole in between, which is never
+        // written and reads as zeros. The stale bytes after the old end in its
+        // cached page are zeroed since they become part of the file.
+        let old_size = self.size();
+        let (old_aligned, old_in_page) = align_offset_to_page(old_size);
+        if offset > old_size && old_in_page != 0 {
+            if let Some(page) = page_cache.get_page(old_aligned) {
+                let end = cmp::min(PAGE_SIZE, offset - old_aligned);
+                page.bytes_array_range(old_in_page..end).fill(0);
+            }
         }
 
         let device = self.super_block().device();
@@ -212,24 +354,26 @@ pub trait File: Send + Sync + DowncastSync {
             } else {
                 log::info!("[File::write_at] create new page");
                 let page = Page::new_file(&device);
+                page.fill_zero();
                 page_cache.insert_page(offset_aligned, page.clone());
                 page
             };
             let len = (buf_it.len()).min(PAGE_SIZE - offset_in_page);
             page.bytes_array_range(offset_in_page..offset_in_page + len)
                 .copy_from_slice(&buf_it[0..len]);
+            page_cache.mark_dirty(offset_aligned);
             log::trace!("[File::write] write count {len}, buf len {}", buf_it.len());
             offset_it += len;
             buf_it = &buf_it[len..];
         }
-        if offset_it > self.size() {
+        if offset_it > old_size {
             log::warn!(
-                "[File::write_at] write beyond file, offset_it:{offset_it}, size:{}",
-                self.size()
+                "[File::write_at] write beyond file, offset_it:{offset_it}, size:{old_size}"
             );
-            self.base_write_at(self.size(), &buf[self.size() - offset..])
+            // the filesystem extends the file sparsely if it can
+            let disk_start = cmp::max(old_size, offset);
+            self.base_write_at(disk_start, &buf[disk_start - offset..])
                 .await?;
-            let old_size = self.size();
             let new_size = offset_it;
             // let virtio_blk = device
             //     .downcast_arc::<VirtIoBlkDev>()
@@ -296,6 +440,13 @@ pub trait File: Send + Sync + DowncastSync {
         self.meta().inode.itype()
     }
 
+    /// Whether the page at `offset_aligned` is a hole, i.e. no data is
+    /// allocated for it. Used by `SEEK_DATA` and `SEEK_HOLE`, and files that
+    /// can not tell always report data.
+    async fn base_is_hole(&self, _offset_aligned: usize) -> SysResult<bool> {
+        Ok(false)
+    }
+
     /// Called when the VFS needs to move the file position index.
     ///
     /// Return the result offset.
@@ -304,33 +455,42 @@ pub trait File: Send + Sync + DowncastSync {
     /// this does not change the size of the file). If data is later written at
     /// this point, subsequent reads of the data in the gap (a "hole") return
     /// null bytes ('\0') until data is actually written into the gap.
-    // TODO: On Linux, using lseek() on a terminal device fails with the error
-    // ESPIPE. However, many function will use this Seek.
-    fn seek(&self, pos: SeekFrom) -> SyscallResult {
-        let mut res_pos = self.pos();
-        match pos {
-            SeekFrom::Current(off) => {
-                if off < 0 {
-                    if res_pos as i64 - off.abs() < 0 {
-                        return Err(SysError::EINVAL);
-                    }
-                    res_pos -= off.abs() as usize;
-                } else {
-                    res_pos += off as usize;
+    ///
+    /// Files which are not seekable, e.g. pipes, sockets and terminals, should
+    /// override this to return `ESPIPE`.
+    async fn seek(&self, pos: SeekFrom) -> SyscallResult {
+        let size = self.size();
+        let res_pos = match pos {
+            SeekFrom::Start(off) => i64::try_from(off).map_err(|_| SysError::EINVAL)?,
+            SeekFrom::Current(off) => (self.pos() as i64)
+                .checked_add(off)
+                .ok_or(SysError::EINVAL)?,
+            SeekFrom::End(off) => (size as i64).checked_add(off).ok_or(SysError::EINVAL)?,
+            SeekFrom::Data(off) | SeekFrom::Hole(off) => {
+                let off = off as usize;
+                if off >= size {
+                    return Err(SysError::ENXIO);
                 }
-            }
-            SeekFrom::Start(off) => {
-                res_pos = off as usize;
-            }
-            SeekFrom::End(off) => {
-                let size = self.size();
-                if off < 0 {
-                    res_pos = size - off.abs() as usize;
-                } else {
-                    res_pos = size + off as usize;
+                let want_hole = matches!(pos, SeekFrom::Hole(_));
+                let mut offset_aligned = round_down_to_page(off);
+                loop {
+                    if offset_aligned >= size {
+                        if want_hole {
+                            break size as i64;
+                        }
+                        return Err(SysError::ENXIO);
+                    }
+                    if self.base_is_hole(offset_aligned).await? == want_hole {
+                        break offset_aligned.max(off) as i64;
+                    }
+                    offset_aligned += PAGE_SIZE;
                 }
             }
+        };
+        if res_pos < 0 {
+            return Err(SysError::EINVAL);
         }
+        let res_pos = res_pos as usize;
         self.set_pos(res_pos);
         Ok(res_pos)
     }
@@ -393,17 +553,17 @@ impl dyn File {
         self.base_poll(events).await
     }
 
-    pub fn load_dir(&self) -> SysResult<()> {
+    pub async fn load_dir(&self) -> SysResult<()> {
         let inode = self.inode();
         if inode.state() == InodeState::UnInit {
-            self.base_load_dir()?;
+            self.base_load_dir().await?;
             inode.set_state(InodeState::Sync)
         }
         Ok(())
     }
 
-    pub fn read_dir(&self, buf: &mut [u8]) -> SyscallResult {
-        self.load_dir()?;
+    pub async fn read_dir(&self, buf: &mut [u8]) -> SyscallResult {
+        self.load_dir().await?;
 
         #[derive(Debug, Clone, Copy)]
         #[repr(C)]
@@ -422,7 +582,7 @@ impl dyn File {
         let mut buf_it = buf;
         for dentry in self.dentry().children().values().skip(self.pos()) {
             if dentry.is_negetive() {
-                self.seek(SeekFrom::Current(1))?;
+                self.set_pos(self.pos() + 1);
                 continue;
             }
             // align to 8 bytes
@@ -441,7 +601,7 @@ impl dyn File {
                 break;
             }
 
-            self.seek(SeekFrom::Current(1))?;
+            self.set_pos(self.pos() + 1);
             let ptr = buf_it.as_mut_ptr() as *mut LinuxDirent64;
             unsafe {
                 ptr.copy_from_nonoverlapping(&linux_dirent, 1);
@@ -455,6 +615,34 @@ impl dyn File {
         Ok(writen_len)
     }
 
+    /// Write the dirty pages in the page cache back to the filesystem, which
+    /// are only written by `write_at` before.
+    pub async fn writeback(&self) -> SysResult<()> {
+        let inode = self.inode();
+        let Some(page_cache) = inode.page_cache() else {
+            return Ok(());
+        };
+        // set before taking the pages, so that a write meanwhile dirties the
+        // inode again
+        inode.set_state(InodeState::Sync);
+        for (offset_aligned, page) in page_cache.take_dirty() {
+            let size = self.size();
+            if offset_aligned >= size {
+                continue;
+            }
+            let len = cmp::min(PAGE_SIZE, size - offset_aligned);
+            if let Err(err) = self
+                .base_write_at(offset_aligned, page.bytes_array_range(0..len))
+                .await
+            {
+                page_cache.mark_dirty(offset_aligned);
+                inode.set_state(InodeState::Dirty);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
     /// Read all data from this file synchronously.
     pub async fn read_all(&self) -> SysResult<Vec<u8>> {
         log::info!("[File::read_all] file size {}", self.size());
@@ -28,7 +28,9 @@ impl FileSystemTypeMeta {
 pub trait FileSystemType: Send + Sync {
     fn meta(&self) -> &FileSystemTypeMeta;
 
-    /// Call when a new instance of this filesystem should be mounted.
+    /// Call when a new instance of this filesystem should be mounted. `data` is
+    /// the data argument of mount(2), which is a comma separated list of
+    /// options for most filesystems.
     // NOTE: `self` cannot be `&Arc<Self>` for object safety
     // https://doc.rust-lang.org/reference/items/traits.html#object-safety
     fn base_mount(
@@ -37,6 +39,7 @@ pub trait FileSystemType: Send + Sync {
         parent: Option<Arc<dyn Dentry>>,
         flags: MountFlags,
         dev: Option<Arc<dyn BlockDevice>>,
+        data: &str,
     ) -> SysResult<Arc<dyn Dentry>>;
 
     /// Call when an instance of this filesystem should be shut down.
@@ -65,8 +68,9 @@ impl dyn FileSystemType {
         parent: Option<Arc<dyn Dentry>>,
         flags: MountFlags,
         dev: Option<Arc<dyn BlockDevice>>,
+        data: &str,
     ) -> SysResult<Arc<dyn Dentry>> {
-        self.clone().base_mount(name, parent, flags, dev)
+        self.clone().base_mount(name, parent, flags, dev, data)
     }
 
     pub fn get_sb(&self, abs_mount_path: &str) -> SysResult<Arc<dyn SuperBlock>> {
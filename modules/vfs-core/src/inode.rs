@@ -1,10 +1,14 @@
-use alloc::sync::{Arc, Weak};
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
 use core::mem::MaybeUninit;
 
+use async_trait::async_trait;
 use device_core::DevId;
 use downcast_rs::{impl_downcast, DowncastSync};
 use page::PageCache;
-use systype::{SysResult, SyscallResult};
+use systype::{SysError, SysResult, SyscallResult};
 use time::timespec::TimeSpec;
 
 use crate::{alloc_ino, Mutex, Stat, SuperBlock};
@@ -32,6 +36,13 @@ pub struct InodeMetaInner {
     pub mtime: TimeSpec,
     /// Last status change time.
     pub ctime: TimeSpec,
+    /// Mode of inode, whose permission bits can be changed, unlike
+    /// `InodeMeta::mode`.
+    pub mode: InodeMode,
+    /// User ID of owner.
+    pub uid: u32,
+    /// Group ID of owner.
+    pub gid: u32,
     ///
     pub state: InodeState,
 }
@@ -73,6 +84,9 @@ impl InodeMeta {
                 atime: TimeSpec::default(),
                 mtime: TimeSpec::default(),
                 ctime: TimeSpec::default(),
+                mode,
+                uid: 0,
+                gid: 0,
                 state: InodeState::UnInit,
                 nlink: 1,
             }),
@@ -80,18 +94,31 @@ impl InodeMeta {
     }
 }
 
+/// Interface for creating inodes of FIFOs, which are pipes implemented out of
+/// this crate, when filesystems find them on disk.
+#[crate_interface::def_interface]
+pub trait FifoIf {
+    fn new_fifo(super_block: Arc<dyn SuperBlock>) -> Arc<dyn Inode>;
+}
+
+/// Create the inode of a FIFO in `super_block`, see `FifoIf`.
+pub fn new_fifo_inode(super_block: Arc<dyn SuperBlock>) -> Arc<dyn Inode> {
+    crate_interface::call_interface!(FifoIf::new_fifo(super_block))
+}
+
+#[async_trait]
 pub trait Inode: Send + Sync + DowncastSync {
     fn meta(&self) -> &InodeMeta;
 
     fn get_attr(&self) -> SysResult<Stat>;
 
-    fn base_truncate(&self, len: usize) -> SysResult<()> {
+    async fn base_truncate(&self, len: usize) -> SysResult<()> {
         todo!()
     }
 
     /// Calculates the block index on the underlying block device for a given
     /// file offset.
-    fn base_get_blk_idx(&self, offset: usize) -> SysResult<usize> {
+    async fn base_get_blk_idx(&self, offset: usize) -> SysResult<usize> {
         todo!()
     }
 
@@ -134,21 +161,85 @@ impl dyn Inode {
         }
     }
 
-    pub fn truncate(&self, len: usize) -> SyscallResult {
+    pub async fn truncate(&self, len: usize) -> SyscallResult {
         log::info!(
             "[Inode::truncate] len:{len:#x}, origin size:{:#x}",
             self.size()
         );
-        self.base_truncate(len).map(|_| 0)
+        self.base_truncate(len).await.map(|_| 0)
     }
 
-    pub fn get_blk_idx(&self, offset: usize) -> SysResult<usize> {
-        self.base_get_blk_idx(offset)
+    pub async fn get_blk_idx(&self, offset: usize) -> SysResult<usize> {
+        self.base_get_blk_idx(offset).await
     }
 
     pub fn super_block(&self) -> Arc<dyn SuperBlock> {
         self.meta().super_block.upgrade().unwrap()
     }
+
+    /// Mode including permission bits.
+    pub fn mode(&self) -> InodeMode {
+        self.meta().inner.lock().mode
+    }
+
+    /// Change permission bits, setuid, setgid and sticky bits, where the file
+    /// type is kept. It only changes the inode in memory, see
+    /// `Dentry::set_mode` for filesystems on disk.
+    pub fn set_mode(&self, perm: InodeMode) {
+        let mut inner = self.meta().inner.lock();
+        inner.mode = (inner.mode & InodeMode::TYPE_MASK) | (perm - InodeMode::TYPE_MASK);
+    }
+
+    pub fn owner(&self) -> (u32, u32) {
+        let inner = self.meta().inner.lock();
+        (inner.uid, inner.gid)
+    }
+
+    /// Change the owner in memory, see `Dentry::set_owner` for filesystems on
+    /// disk.
+    pub fn set_owner(&self, uid: u32, gid: u32) {
+        let mut inner = self.meta().inner.lock();
+        inner.uid = uid;
+        inner.gid = gid;
+    }
+
+    /// Check whether a process can `access` the inode, where `uid` and
+    /// `in_group` are its user ID and groups used for permission checks.
+    ///
+    /// Root can read and write anything, but can only execute a file if any
+    /// execute bit is set.
+    pub fn check_access(
+        &self,
+        uid: u32,
+        in_group: impl Fn(u32) -> bool,
+        access: AccessMode,
+    ) -> SysResult<()> {
+        let inner = self.meta().inner.lock();
+        let mode = inner.mode.bits();
+        if uid == 0 {
+            let exec_mask =
+                (InodeMode::OWNER_EXEC | InodeMode::GROUP_EXEC | InodeMode::OTHER_EXEC).bits();
+            if !access.contains(AccessMode::EXEC)
+                || inner.mode.to_type().is_dir()
+                || mode & exec_mask != 0
+            {
+                return Ok(());
+            }
+            return Err(SysError::EACCES);
+        }
+        let granted = if uid == inner.uid {
+            mode >> 6
+        } else if in_group(inner.gid) {
+            mode >> 3
+        } else {
+            mode
+        };
+        if AccessMode::from_bits_truncate(granted).contains(access) {
+            Ok(())
+        } else {
+            Err(SysError::EACCES)
+        }
+    }
 }
 
 impl_downcast!(sync Inode);
@@ -162,6 +253,17 @@ pub enum InodeState {
     Removed,
 }
 
+bitflags::bitflags! {
+    /// Access to check permission for, the same as bits of `mode` of
+    /// `access`.
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    pub struct AccessMode: u32 {
+        const READ = 4;
+        const WRITE = 2;
+        const EXEC = 1;
+    }
+}
+
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, Eq, PartialEq)]
     pub struct InodeMode: u32 {
@@ -2,6 +2,7 @@
 #![no_main]
 #![feature(new_uninit)]
 
+mod dcache;
 mod dentry;
 mod file;
 mod file_system_type;
@@ -30,6 +31,7 @@ pub fn arc_zero() -> Arc<core::mem::MaybeUninit<usize>> {
     Arc::<usize>::new_zeroed()
 }
 
+pub use dcache::{dcache_shrink, dcache_stat, DcacheStat};
 pub use dentry::*;
 pub use file::*;
 pub use file_system_type::*;
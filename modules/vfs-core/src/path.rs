@@ -48,8 +48,11 @@ impl Path {
         log::debug!("[Path::walk] {:?}", split_path(path));
         for p in split_path(path) {
             match p {
+                // ".." of the root is the root itself
                 ".." => {
-                    dentry = dentry.parent().ok_or(SysError::ENOENT)?;
+                    if !Arc::ptr_eq(&dentry, &self.root) {
+                        dentry = dentry.parent().unwrap_or(dentry);
+                    }
                 }
                 // NOTE: lookup will only create negative dentry in non-negetive dir dentry
                 name => {
@@ -41,6 +41,24 @@ pub trait SuperBlock: Send + Sync {
     /// superblock.
     fn sync_fs(&self, wait: isize) -> SysResult<()>;
 
+    /// Reserve `nr` pages for file data, which fails with `ENOSPC` when the
+    /// filesystem is full. Only filesystems in memory with a size limit, i.e.
+    /// tmpfs, keep account of it.
+    fn alloc_pages(&self, _nr: usize) -> SysResult<()> {
+        Ok(())
+    }
+
+    /// Give back `nr` pages reserved by `alloc_pages`.
+    fn free_pages(&self, _nr: usize) {}
+
+    /// Reserve an inode, see `alloc_pages`.
+    fn alloc_inode(&self) -> SysResult<()> {
+        Ok(())
+    }
+
+    /// Give back an inode reserved by `alloc_inode`.
+    fn free_inode(&self) {}
+
     fn set_root_dentry(&self, root_dentry: Arc<dyn Dentry>) {
         self.meta().root_dentry.call_once(|| root_dentry);
     }
@@ -2,6 +2,7 @@ use alloc::string::String;
 use core::fmt::Display;
 
 use bitflags::Flags;
+use config::mm::PAGE_SIZE;
 use time::timespec::TimeSpec;
 
 use crate::InodeType;
@@ -100,6 +101,19 @@ pub struct StatFs {
     pub f_spare: [isize; 4],
 }
 
+impl StatFs {
+    /// Statistics of a pseudo filesystem, which has no blocks or inode limits.
+    pub fn pseudo(f_type: i64) -> Self {
+        Self {
+            f_type,
+            f_bsize: PAGE_SIZE as i64,
+            f_namelen: 255,
+            f_frsize: PAGE_SIZE as isize,
+            ..Default::default()
+        }
+    }
+}
+
 /// Directory entry.
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -238,6 +252,14 @@ pub enum SeekFrom {
     /// It is possible to seek beyond the end of an object, but it's an error to
     /// seek before byte 0.
     Current(i64),
+
+    /// Sets the offset to the next location containing data at or after the
+    /// specified number of bytes.
+    Data(u64),
+
+    /// Sets the offset to the next hole at or after the specified number of
+    /// bytes. The end of the object counts as a hole.
+    Hole(u64),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -0,0 +1,666 @@
+//! Loop devices, which make regular files available as block devices, so that
+//! filesystem images can be mounted.
+//!
+//! Free devices are found or added by `/dev/loop-control`, and a file is bound
+//! to `/dev/loopN` by `LOOP_SET_FD`. Blocks of a bound device are read and
+//! written through the page cache of the file, from `lo_offset` of it and up
+//! to `lo_sizelimit` bytes if that is set.
+
+use alloc::{boxed::Box, collections::BTreeMap, format, sync::Arc};
+
+use async_trait::async_trait;
+use async_utils::block_on;
+use config::board::BLOCK_SIZE;
+use device_core::{BlockDevice, DevId, Device, DeviceMajor, DeviceMeta, DeviceType};
+use spin::Once;
+use strum::FromRepr;
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{
+    Dentry, DentryMeta, DentryState, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode,
+    InodeType, Stat, SuperBlock,
+};
+
+use crate::Mutex;
+
+/// Devices added when devfs is initialized, as `max_loop` of Linux defaults
+/// to.
+const NR_LOOP_INIT: usize = 8;
+/// Minor of /dev/loop-control under the misc major.
+pub const LOOP_CTRL_MINOR: u32 = 237;
+const LO_NAME_SIZE: usize = 64;
+const LO_KEY_SIZE: usize = 32;
+
+/// Bind a file to the device, `_IO(0x4C, 0x00)`. The argument is a file
+/// descriptor, which is resolved by `sys_ioctl` and passed to `loop_set_fd`.
+pub const LOOP_SET_FD: usize = 0x4c00;
+
+/// Defined in <linux/loop.h>
+#[derive(FromRepr, Debug)]
+#[repr(usize)]
+#[allow(non_camel_case_types)]
+enum LoopIoctlCmd {
+    LOOP_CLR_FD = 0x4c01,
+    LOOP_SET_STATUS64 = 0x4c04,
+    LOOP_GET_STATUS64 = 0x4c05,
+}
+
+/// Defined in <linux/loop.h>
+#[derive(FromRepr, Debug)]
+#[repr(usize)]
+#[allow(non_camel_case_types)]
+enum LoopCtlIoctlCmd {
+    LOOP_CTL_ADD = 0x4c80,
+    LOOP_CTL_REMOVE = 0x4c81,
+    LOOP_CTL_GET_FREE = 0x4c82,
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct LoopFlags: u32 {
+        const READ_ONLY = 1;
+        const AUTOCLEAR = 4;
+        const PARTSCAN = 8;
+        const DIRECT_IO = 16;
+    }
+}
+
+impl LoopFlags {
+    /// Flags that `LOOP_SET_STATUS64` can change.
+    const SETTABLE: Self = Self::AUTOCLEAR.union(Self::PARTSCAN);
+}
+
+/// Status of a loop device, the same as `struct loop_info64`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct LoopInfo64 {
+    lo_device: u64,
+    lo_inode: u64,
+    lo_rdevice: u64,
+    lo_offset: u64,
+    lo_sizelimit: u64,
+    lo_number: u32,
+    lo_encrypt_type: u32,
+    lo_encrypt_key_size: u32,
+    lo_flags: u32,
+    lo_file_name: [u8; LO_NAME_SIZE],
+    lo_crypt_name: [u8; LO_NAME_SIZE],
+    lo_encrypt_key: [u8; LO_KEY_SIZE],
+    lo_init: [u64; 2],
+}
+
+struct LoopState {
+    /// The file bound.
+    file: Option<Arc<dyn File>>,
+    offset: u64,
+    /// Size of the device in bytes at most, or no limit if zero.
+    sizelimit: u64,
+    flags: LoopFlags,
+    file_name: [u8; LO_NAME_SIZE],
+}
+
+pub struct LoopDevice {
+    meta: DeviceMeta,
+    state: Mutex<LoopState>,
+}
+
+impl LoopDevice {
+    fn new(number: usize) -> Arc<Self> {
+        let meta = DeviceMeta {
+            dev_id: DevId {
+                major: DeviceMajor::Loop,
+                minor: number,
+            },
+            name: format!("loop{number}"),
+            mmio_base: 0,
+            mmio_size: 0,
+            irq_no: None,
+            dtype: DeviceType::Block,
+        };
+        Arc::new(Self {
+            meta,
+            state: Mutex::new(LoopState {
+                file: None,
+                offset: 0,
+                sizelimit: 0,
+                flags: LoopFlags::empty(),
+                file_name: [0; LO_NAME_SIZE],
+            }),
+        })
+    }
+
+    fn number(&self) -> usize {
+        self.meta.dev_id.minor
+    }
+
+    fn is_bound(&self) -> bool {
+        self.state.lock().file.is_some()
+    }
+
+    /// The file bound, the offset into it and whether it is read only. The
+    /// state is not locked while the file is read or written.
+    fn backing(&self) -> Option<(Arc<dyn File>, usize, bool)> {
+        let state = self.state.lock();
+        let file = state.file.clone()?;
+        Some((
+            file,
+            state.offset as usize,
+            state.flags.contains(LoopFlags::READ_ONLY),
+        ))
+    }
+
+    fn set_fd(&self, file: Arc<dyn File>) -> SysResult<()> {
+        if !matches!(file.itype(), InodeType::File | InodeType::BlockDevice) {
+            return Err(SysError::EINVAL);
+        }
+        let mut state = self.state.lock();
+        if state.file.is_some() {
+            return Err(SysError::EBUSY);
+        }
+        let path = file.dentry().path();
+        let len = path.len().min(LO_NAME_SIZE - 1);
+        state.file_name = [0; LO_NAME_SIZE];
+        state.file_name[..len].copy_from_slice(&path.as_bytes()[..len]);
+        state.flags = if file.flags().writable() {
+            LoopFlags::empty()
+        } else {
+            LoopFlags::READ_ONLY
+        };
+        state.offset = 0;
+        state.sizelimit = 0;
+        state.file = Some(file);
+        log::info!("[loop] {} bound to {path}", self.meta.name);
+        Ok(())
+    }
+
+    fn clr_fd(&self) -> SysResult<()> {
+        let file = self.state.lock().file.take().ok_or(SysError::ENXIO)?;
+        // blocks written are dirty in the page cache of the file
+        if let Err(err) = block_on(async { file.writeback().await }) {
+            log::error!("[loop] failed to write back {}: {err:?}", self.meta.name);
+        }
+        Ok(())
+    }
+
+    fn get_status(&self) -> SysResult<LoopInfo64> {
+        let state = self.state.lock();
+        let file = state.file.as_ref().ok_or(SysError::ENXIO)?;
+        let inode = file.inode();
+        Ok(LoopInfo64 {
+            lo_device: 0,
+            lo_inode: inode.ino() as u64,
+            lo_rdevice: 0,
+            lo_offset: state.offset,
+            lo_sizelimit: state.sizelimit,
+            lo_number: self.number() as u32,
+            lo_encrypt_type: 0,
+            lo_encrypt_key_size: 0,
+            lo_flags: state.flags.bits(),
+            lo_file_name: state.file_name,
+            lo_crypt_name: [0; LO_NAME_SIZE],
+            lo_encrypt_key: [0; LO_KEY_SIZE],
+            lo_init: [0; 2],
+        })
+    }
+
+    fn set_status(&self, info: &LoopInfo64) -> SysResult<()> {
+        let mut state = self.state.lock();
+        if state.file.is_none() {
+            return Err(SysError::ENXIO);
+        }
+        state.offset = info.lo_offset;
+        state.sizelimit = info.lo_sizelimit;
+        let flags = LoopFlags::from_bits_truncate(info.lo_flags) & LoopFlags::SETTABLE;
+        state.flags = (state.flags - LoopFlags::SETTABLE) | flags;
+        Ok(())
+    }
+}
+
+impl Device for LoopDevice {
+    fn meta(&self) -> &DeviceMeta {
+        &self.meta
+    }
+
+    fn init(&self) {}
+
+    fn handle_irq(&self) {}
+
+    fn as_blk(self: Arc<Self>) -> Option<Arc<dyn BlockDevice>> {
+        Some(self)
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn size(&self) -> u64 {
+        let state = self.state.lock();
+        let Some(file) = state.file.as_ref() else {
+            return 0;
+        };
+        let size = (file.size() as u64).saturating_sub(state.offset);
+        match state.sizelimit {
+            0 => size,
+            limit => size.min(limit),
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn buffer_head_cnts(&self) -> usize {
+        0
+    }
+
+    fn remove_buffer_page(&self, _block_id: usize) {}
+
+    fn base_read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        let Some((file, offset, _)) = self.backing() else {
+            log::error!("[loop] read from {}, which is not bound", self.meta.name);
+            buf.fill(0);
+            return;
+        };
+        let pos = offset + block_id * BLOCK_SIZE;
+        // blocks beyond the end of the file read as zeros
+        match block_on(async { file.read_at(pos, buf).await }) {
+            Ok(len) => buf[len..].fill(0),
+            Err(err) => {
+                log::error!("[loop] failed to read {}: {err:?}", self.meta.name);
+                buf.fill(0);
+            }
+        }
+    }
+
+    fn base_write_blocks(&self, block_id: usize, buf: &[u8]) {
+        let Some((file, offset, read_only)) = self.backing() else {
+            log::error!("[loop] write to {}, which is not bound", self.meta.name);
+            return;
+        };
+        if read_only {
+            log::error!("[loop] write to {}, which is read only", self.meta.name);
+            return;
+        }
+        let pos = offset + block_id * BLOCK_SIZE;
+        if let Err(err) = block_on(async { file.write_at(pos, buf).await }) {
+            log::error!("[loop] failed to write {}: {err:?}", self.meta.name);
+        }
+    }
+
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.base_read_blocks(block_id, buf)
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.base_write_blocks(block_id, buf)
+    }
+
+    fn sync(&self) {
+        if let Some((file, ..)) = self.backing() {
+            if let Err(err) = block_on(async { file.writeback().await }) {
+                log::error!("[loop] failed to write back {}: {err:?}", self.meta.name);
+            }
+        }
+    }
+}
+
+/// Loop devices by their numbers.
+static LOOP_DEVICES: Mutex<BTreeMap<usize, Arc<LoopDevice>>> = Mutex::new(BTreeMap::new());
+
+/// Directory of devfs, where nodes of the loop devices are created.
+static DEV_DIR: Once<Arc<dyn Dentry>> = Once::new();
+
+/// Create /dev/loop-control and the first loop devices in devfs directory
+/// `dev_dir`.
+pub fn init_loop(dev_dir: Arc<dyn Dentry>) -> SysResult<()> {
+    let sb = dev_dir.super_block();
+    let ctl_dentry = LoopCtlDentry::new("loop-control", sb.clone(), Some(dev_dir.clone()));
+    dev_dir.insert(ctl_dentry.clone());
+    ctl_dentry.set_inode(LoopCtlInode::new(sb));
+    DEV_DIR.call_once(|| dev_dir);
+    for number in 0..NR_LOOP_INIT {
+        loop_add(Some(number))?;
+    }
+    Ok(())
+}
+
+/// Add loop device `number`, or the first one not added if `None`, and create
+/// its node in devfs.
+fn loop_add(number: Option<usize>) -> SysResult<usize> {
+    let dev_dir = DEV_DIR.get().unwrap();
+    let sb = dev_dir.super_block();
+    let mut devices = LOOP_DEVICES.lock();
+    let number = match number {
+        Some(number) if devices.contains_key(&number) => return Err(SysError::EEXIST),
+        Some(number) => number,
+        None => (0..).find(|number| !devices.contains_key(number)).unwrap(),
+    };
+    let device = LoopDevice::new(number);
+    devices.insert(number, device.clone());
+    let dentry = LoopDentry::new(&device.meta.name, sb.clone(), Some(dev_dir.clone()));
+    dentry.set_inode(LoopInode::new(sb, device));
+    dentry.set_state(DentryState::Sync);
+    dev_dir.insert(dentry);
+    Ok(number)
+}
+
+fn loop_remove(number: usize) -> SysResult<usize> {
+    let mut devices = LOOP_DEVICES.lock();
+    let device = devices.get(&number).ok_or(SysError::ENODEV)?;
+    if device.is_bound() {
+        return Err(SysError::EBUSY);
+    }
+    let device = devices.remove(&number).unwrap();
+    DEV_DIR.get().unwrap().remove_child(&device.meta.name);
+    Ok(number)
+}
+
+/// Number of the first loop device not bound, which is added if all are.
+fn loop_get_free() -> SysResult<usize> {
+    let free = LOOP_DEVICES
+        .lock()
+        .values()
+        .find(|device| !device.is_bound())
+        .map(|device| device.number());
+    match free {
+        Some(number) => Ok(number),
+        None => loop_add(None),
+    }
+}
+
+/// Loop device `number` if it is added.
+pub fn loop_device(number: usize) -> Option<Arc<LoopDevice>> {
+    LOOP_DEVICES.lock().get(&number).cloned()
+}
+
+/// Bind `backing` to the loop device opened as `file`, for `LOOP_SET_FD`.
+pub fn loop_set_fd(file: Arc<dyn File>, backing: Arc<dyn File>) -> SysResult<()> {
+    let file = file
+        .downcast_arc::<LoopFile>()
+        .map_err(|_| SysError::ENOTTY)?;
+    file.device.set_fd(backing)
+}
+
+/// Block device of the loop device of `inode`, which has to be bound.
+pub fn loop_block_device(inode: Arc<dyn Inode>) -> SysResult<Arc<dyn BlockDevice>> {
+    let inode = inode
+        .downcast_arc::<LoopInode>()
+        .map_err(|_| SysError::ENOTBLK)?;
+    if !inode.device.is_bound() {
+        return Err(SysError::ENXIO);
+    }
+    Ok(inode.device.clone())
+}
+
+pub struct LoopDentry {
+    meta: DentryMeta,
+}
+
+impl LoopDentry {
+    pub fn new(
+        name: &str,
+        super_block: Arc<dyn SuperBlock>,
+        parent: Option<Arc<dyn Dentry>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new(name, super_block, parent),
+        })
+    }
+}
+
+impl Dentry for LoopDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        let inode = self
+            .inode()?
+            .downcast_arc::<LoopInode>()
+            .unwrap_or_else(|_| unreachable!());
+        Ok(Arc::new(LoopFile {
+            meta: FileMeta::new(self.clone(), inode.clone()),
+            device: inode.device.clone(),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct LoopInode {
+    meta: InodeMeta,
+    device: Arc<LoopDevice>,
+}
+
+impl LoopInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>, device: Arc<LoopDevice>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::BLOCK, super_block, 0),
+            device,
+        })
+    }
+}
+
+impl Inode for LoopInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: makedev(DeviceMajor::Loop as usize, self.device.number()),
+            __pad: 0,
+            st_size: 0,
+            st_blksize: BLOCK_SIZE as u32,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct LoopFile {
+    meta: FileMeta,
+    device: Arc<LoopDevice>,
+}
+
+#[async_trait]
+impl File for LoopFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let (file, base, _) = self.device.backing().ok_or(SysError::ENXIO)?;
+        let size = self.device.size() as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min(size - offset);
+        file.read_at(base + offset, &mut buf[..len]).await
+    }
+
+    async fn base_write_at(&self, offset: usize, buf: &[u8]) -> SyscallResult {
+        let (file, base, read_only) = self.device.backing().ok_or(SysError::ENXIO)?;
+        if read_only {
+            return Err(SysError::EPERM);
+        }
+        let size = self.device.size() as usize;
+        if offset >= size {
+            return Err(SysError::ENOSPC);
+        }
+        let len = buf.len().min(size - offset);
+        file.write_at(base + offset, &buf[..len]).await
+    }
+
+    /// Size of the device rather than of the inode, e.g. for `SEEK_END`.
+    fn size(&self) -> usize {
+        self.device.size() as usize
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> SyscallResult {
+        let Some(cmd) = LoopIoctlCmd::from_repr(cmd) else {
+            log::error!("[LoopFile::ioctl] cmd {cmd:#x} not included");
+            return Err(SysError::EINVAL);
+        };
+        match cmd {
+            LoopIoctlCmd::LOOP_CLR_FD => self.device.clr_fd()?,
+            LoopIoctlCmd::LOOP_SET_STATUS64 => {
+                let info = unsafe { &*(arg as *const LoopInfo64) };
+                self.device.set_status(info)?
+            }
+            LoopIoctlCmd::LOOP_GET_STATUS64 => {
+                let info = self.device.get_status()?;
+                unsafe { *(arg as *mut LoopInfo64) = info }
+            }
+        }
+        Ok(0)
+    }
+}
+
+pub struct LoopCtlDentry {
+    meta: DentryMeta,
+}
+
+impl LoopCtlDentry {
+    pub fn new(
+        name: &str,
+        super_block: Arc<dyn SuperBlock>,
+        parent: Option<Arc<dyn Dentry>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new(name, super_block, parent),
+        })
+    }
+}
+
+impl Dentry for LoopCtlDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(LoopCtlFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct LoopCtlInode {
+    meta: InodeMeta,
+}
+
+impl LoopCtlInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::CHAR, super_block, 0),
+        })
+    }
+}
+
+impl Inode for LoopCtlInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: makedev(DeviceMajor::Misc as usize, LOOP_CTRL_MINOR as usize),
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct LoopCtlFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for LoopCtlFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, _offset: usize, _buf: &mut [u8]) -> SyscallResult {
+        Err(SysError::EINVAL)
+    }
+
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SyscallResult {
+        Err(SysError::EINVAL)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> SyscallResult {
+        let Some(cmd) = LoopCtlIoctlCmd::from_repr(cmd) else {
+            log::error!("[LoopCtlFile::ioctl] cmd {cmd:#x} not included");
+            return Err(SysError::EINVAL);
+        };
+        match cmd {
+            // a negative number asks for any free one
+            LoopCtlIoctlCmd::LOOP_CTL_ADD => loop_add((arg as isize >= 0).then_some(arg)),
+            LoopCtlIoctlCmd::LOOP_CTL_REMOVE => loop_remove(arg),
+            LoopCtlIoctlCmd::LOOP_CTL_GET_FREE => loop_get_free(),
+        }
+    }
+}
@@ -1,22 +1,31 @@
-use alloc::sync::Arc;
-
-use device_core::BlockDevice;
-use systype::SysResult;
-use vfs_core::{Dentry, FileSystemType, FileSystemTypeMeta, InodeMode, SuperBlock, SuperBlockMeta};
+use alloc::{format, sync::Arc};
+
+use device_core::{BlockDevice, Device};
+use driver::serial::{self, CONSOLE};
+use systype::{SysError, SysResult};
+use vfs_core::{
+    Dentry, DentryState, FileSystemType, FileSystemTypeMeta, Inode, InodeMode, StatFs, SuperBlock,
+    SuperBlockMeta,
+};
 
 use self::{
     cpu_dma_latency::{CpuDmaLatencyDentry, CpuDmaLatencyInode},
+    loop_dev::{init_loop, loop_device, LoopCtlDentry, LoopCtlInode, LoopDentry, LoopInode},
     null::{NullDentry, NullInode},
     rtc::{RtcDentry, RtcInode},
     tty::{TtyDentry, TtyFile, TtyInode, TTY},
     urandom::{UrandomDentry, UrandomInode},
     zero::{ZeroDentry, ZeroInode},
 };
-use crate::simplefs::{dentry::SimpleDentry, inode::SimpleDirInode};
+use crate::{
+    simplefs::{dentry::SimpleDentry, inode::SimpleDirInode},
+    tmpfs::TMPFS_MAGIC,
+};
 
 mod cpu_dma_latency;
+pub mod loop_dev;
 mod null;
-mod rtc;
+pub mod rtc;
 pub mod tty;
 pub mod urandom;
 mod zero;
@@ -50,23 +59,112 @@ pub fn init_devfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
     let urandom_inode = UrandomInode::new(sb.clone());
     urandom_dentry.set_inode(urandom_inode);
 
+    // the standard streams of init stay on the first serial port, while
+    // kernel printing may be moved to another one by `console=`
     let tty_dentry = TtyDentry::new("tty", sb.clone(), Some(root_dentry.clone()));
     root_dentry.insert(tty_dentry.clone());
-    let tty_inode = TtyInode::new(sb.clone());
+    let tty_inode = TtyInode::new(sb.clone(), serial::serial(0).unwrap());
     tty_dentry.set_inode(tty_inode);
     let tty_file = TtyFile::new(tty_dentry.clone(), tty_dentry.inode()?);
     TTY.call_once(|| tty_file);
 
-    // TODO: POSIX shm operations are not implemented yet. The code below is work
-    // around to pass libc test pthread_cancel_points.
-    let shm_dentry = SimpleDentry::new("shm", sb.clone(), Some(root_dentry.clone()));
-    root_dentry.insert(shm_dentry.clone());
-    let shm_inode = SimpleDirInode::new(InodeMode::DIR, sb.clone(), 0);
-    shm_dentry.set_inode(shm_inode);
+    let console_dentry = TtyDentry::new("console", sb.clone(), Some(root_dentry.clone()));
+    root_dentry.insert(console_dentry.clone());
+    let console_inode = TtyInode::new(sb.clone(), CONSOLE.get().unwrap().clone());
+    console_dentry.set_inode(console_inode);
+
+    for port in serial::serials() {
+        let name = format!("ttyS{}", port.dev_id().minor);
+        let ttys_dentry = TtyDentry::new(&name, sb.clone(), Some(root_dentry.clone()));
+        root_dentry.insert(ttys_dentry.clone());
+        let ttys_inode = TtyInode::new(sb.clone(), port);
+        ttys_dentry.set_inode(ttys_inode);
+    }
+
+    init_loop(root_dentry.clone())?;
 
     Ok(())
 }
 
+/// Minor of ttyS0 under major 4, as Linux numbers it.
+const TTYS_MINOR_BASE: u32 = 64;
+
+/// Device number encoded as Linux does in `st_rdev`.
+fn makedev(major: usize, minor: usize) -> u64 {
+    (((major & 0xfff) << 8) | (minor & 0xff) | ((minor & !0xff) << 12)) as u64
+}
+
+/// Create a device node `name` in directory `parent` of devfs for the device
+/// numbered `major` and `minor`, as Linux numbers them. Only devices created by
+/// `init_devfs` can be made, other filesystems can not hold device nodes.
+pub fn mknod(
+    parent: &Arc<dyn Dentry>,
+    name: &str,
+    major: u32,
+    minor: u32,
+) -> SysResult<Arc<dyn Dentry>> {
+    let sb = parent.super_block();
+    if sb.fs_type().name() != "devfs" {
+        return Err(SysError::EPERM);
+    }
+    if parent
+        .get_child(name)
+        .is_some_and(|child| !child.is_negetive())
+    {
+        return Err(SysError::EEXIST);
+    }
+    let parent = Some(parent.clone());
+    let (dentry, inode): (Arc<dyn Dentry>, Arc<dyn Inode>) = match (major, minor) {
+        (1, 3) => (
+            NullDentry::new(name, sb.clone(), parent),
+            NullInode::new(sb),
+        ),
+        (1, 5) => (
+            ZeroDentry::new(name, sb.clone(), parent),
+            ZeroInode::new(sb),
+        ),
+        (1, 9) => (
+            UrandomDentry::new(name, sb.clone(), parent),
+            UrandomInode::new(sb),
+        ),
+        (4, minor @ TTYS_MINOR_BASE..) => {
+            let serial =
+                serial::serial((minor - TTYS_MINOR_BASE) as usize).ok_or(SysError::ENXIO)?;
+            (
+                TtyDentry::new(name, sb.clone(), parent),
+                TtyInode::new(sb, serial),
+            )
+        }
+        (5, 0) => (
+            TtyDentry::new(name, sb.clone(), parent),
+            TtyInode::new(sb, serial::serial(0).unwrap()),
+        ),
+        (5, 1) => (
+            TtyDentry::new(name, sb.clone(), parent),
+            TtyInode::new(sb, CONSOLE.get().unwrap().clone()),
+        ),
+        (7, minor) => {
+            let device = loop_device(minor as usize).ok_or(SysError::ENXIO)?;
+            (
+                LoopDentry::new(name, sb.clone(), parent),
+                LoopInode::new(sb, device),
+            )
+        }
+        (10, loop_dev::LOOP_CTRL_MINOR) => (
+            LoopCtlDentry::new(name, sb.clone(), parent),
+            LoopCtlInode::new(sb),
+        ),
+        _ => {
+            log::warn!("[devfs::mknod] unsupported device {major}:{minor}");
+            return Err(SysError::EINVAL);
+        }
+    };
+    dentry.set_inode(inode);
+    dentry.parent().unwrap().insert(dentry.clone());
+    dentry.set_state(DentryState::Sync);
+    Ok(dentry)
+}
+
 pub struct DevFsType {
     meta: FileSystemTypeMeta,
 }
@@ -90,6 +188,7 @@ impl FileSystemType for DevFsType {
         parent: Option<Arc<dyn Dentry>>,
         _flags: vfs_core::MountFlags,
         dev: Option<alloc::sync::Arc<dyn BlockDevice>>,
+        _data: &str,
     ) -> systype::SysResult<alloc::sync::Arc<dyn vfs_core::Dentry>> {
         let sb = DevSuperBlock::new(dev, self.clone());
         let mount_dentry = SimpleDentry::new(name, sb.clone(), parent.clone());
@@ -128,7 +227,8 @@ impl SuperBlock for DevSuperBlock {
     }
 
     fn stat_fs(&self) -> systype::SysResult<vfs_core::StatFs> {
-        todo!()
+        // devtmpfs on Linux
+        Ok(StatFs::pseudo(TMPFS_MAGIC))
     }
 
     fn sync_fs(&self, _wait: isize) -> systype::SysResult<()> {
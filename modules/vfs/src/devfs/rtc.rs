@@ -67,15 +67,15 @@ impl Inode for RtcInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -119,21 +119,73 @@ impl File for RtcFile {
         todo!()
     }
 
-    fn ioctl(&self, _cmd: usize, arg: usize) -> SyscallResult {
-        unsafe {
-            *(arg as *mut RtcTime) = RtcTime::default();
-        }
-        Ok(0)
+    fn ioctl(&self, cmd: usize, _arg: usize) -> SyscallResult {
+        log::error!("[RtcFile::ioctl] cmd {cmd:#x} not included");
+        Err(SysError::EINVAL)
     }
 }
 
-#[derive(Default)]
+/// Read the RTC time, `_IOR('p', 0x09, struct rtc_time)` defined in
+/// <linux/rtc.h>. The time of `rtc_time` is written to the buffer of the
+/// caller by `sys_ioctl`, which can check the buffer.
+pub const RTC_RD_TIME: usize = 0x80247009;
+
+/// Time of the RTC, which reads as the Epoch without a RTC.
+pub fn rtc_time() -> RtcTime {
+    let now = driver::rtc_time().unwrap_or_default();
+    RtcTime::from_unix_secs(now.as_secs())
+}
+
+/// Broken-down time, the same as `struct tm`.
+#[derive(Default, Clone, Copy)]
 #[repr(C)]
 pub struct RtcTime {
     tm_sec: i32,
     tm_min: i32,
     tm_hour: i32,
     tm_mday: i32,
+    /// Months since January, in 0..=11.
     tm_mon: i32,
+    /// Years since 1900.
     tm_year: i32,
+    /// Days since Sunday, in 0..=6.
+    tm_wday: i32,
+    /// Days since January 1, in 0..=365.
+    tm_yday: i32,
+    tm_isdst: i32,
+}
+
+impl RtcTime {
+    /// Convert seconds since the Epoch to UTC time.
+    fn from_unix_secs(secs: u64) -> Self {
+        let days = (secs / 86400) as i64;
+        let secs_of_day = (secs % 86400) as i32;
+        // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let mday = doy - (153 * mp + 2) / 5 + 1;
+        let mon = if mp < 10 { mp + 2 } else { mp - 10 };
+        let year = yoe + era * 400 + if mon < 2 { 1 } else { 0 };
+        let is_leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+        const DAYS_BEFORE_MONTH: [i32; 12] =
+            [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let yday = DAYS_BEFORE_MONTH[mon as usize] + mday as i32 - 1
+            + if is_leap && mon >= 2 { 1 } else { 0 };
+        Self {
+            tm_sec: secs_of_day % 60,
+            tm_min: secs_of_day / 60 % 60,
+            tm_hour: secs_of_day / 3600,
+            tm_mday: mday as i32,
+            tm_mon: mon as i32,
+            tm_year: year as i32 - 1900,
+            // 1970-01-01 is Thursday
+            tm_wday: (days + 4).rem_euclid(7) as i32,
+            tm_yday: yday,
+            tm_isdst: 0,
+        }
+    }
 }
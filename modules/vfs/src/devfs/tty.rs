@@ -1,17 +1,19 @@
 use alloc::{boxed::Box, sync::Arc};
 
 use async_trait::async_trait;
-use device_core::{CharDevice, DeviceMajor};
-use driver::{get_device_manager, serial::Serial};
+use device_core::CharDevice;
+use driver::serial::Serial;
 use spin::Once;
 use strum::FromRepr;
 use sync::mutex::{SleepLock, SpinNoIrqLock};
 use systype::{SysError, SysResult, SyscallResult};
 use vfs_core::{
-    Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, PollEvents, Stat,
-    SuperBlock,
+    Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, PollEvents,
+    SeekFrom, Stat, SuperBlock,
 };
 
+use super::{makedev, TTYS_MINOR_BASE};
+
 pub struct TtyDentry {
     meta: DentryMeta,
 }
@@ -56,20 +58,14 @@ pub struct TtyInode {
 }
 
 impl TtyInode {
-    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+    /// Create an inode of the serial port `serial`.
+    pub fn new(super_block: Arc<dyn SuperBlock>, serial: Arc<Serial>) -> Arc<Self> {
         let mut meta = InodeMeta::new(InodeMode::CHAR, super_block, 0);
-        let (&dev_id, char_dev) = get_device_manager()
-            .devices()
-            .iter()
-            .filter(|(dev_id, _device)| dev_id.major == DeviceMajor::Serial)
-            .next()
-            .unwrap();
-        meta.dev_id = Some(dev_id);
-        let char_dev = char_dev
-            .clone()
-            .downcast_arc::<Serial>()
-            .unwrap_or_else(|_| unreachable!());
-        Arc::new(Self { meta, char_dev })
+        meta.dev_id = Some(serial.dev_id());
+        Arc::new(Self {
+            meta,
+            char_dev: serial,
+        })
     }
 }
 
@@ -83,11 +79,16 @@ impl Inode for TtyInode {
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
-            st_mode: self.meta.mode.bits(),
+            st_mode: inner.mode.bits(),
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
-            st_rdev: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: self.meta.dev_id.map_or(0, |dev_id| {
+                makedev(
+                    dev_id.major as usize,
+                    TTYS_MINOR_BASE as usize + dev_id.minor,
+                )
+            }),
             __pad: 0,
             st_size: inner.size as u64,
             st_blksize: 0,
@@ -103,6 +104,15 @@ impl Inode for TtyInode {
 
 type Pid = u32;
 
+/// Make the terminal the controlling terminal of the session of the caller.
+/// Ioctls on the session of the caller are resolved by `sys_ioctl`, since the
+/// terminal does not know the caller.
+pub const TIOCSCTTY: usize = 0x540E;
+/// Give up the controlling terminal.
+pub const TIOCNOTTY: usize = 0x5422;
+/// Get the session of which the terminal is the controlling terminal.
+pub const TIOCGSID: usize = 0x5429;
+
 /// Defined in <asm-generic/ioctls.h>
 #[derive(FromRepr, Debug)]
 #[repr(usize)]
@@ -316,6 +326,10 @@ impl File for TtyFile {
     fn flush(&self) -> SysResult<usize> {
         todo!()
     }
+
+    async fn seek(&self, _pos: SeekFrom) -> SyscallResult {
+        Err(SysError::ESPIPE)
+    }
 }
 
 /// Defined in <asm-generic/termbits.h>
@@ -97,6 +97,17 @@ impl FdTable {
         }
     }
 
+    /// Table without any file, not even stdio.
+    pub fn empty() -> Self {
+        Self {
+            table: Vec::new(),
+            rlimit: RLimit {
+                rlim_cur: MAX_FDS,
+                rlim_max: MAX_FDS,
+            },
+        }
+    }
+
     pub fn clear(&mut self) {
         self.table.clear();
     }
@@ -186,6 +197,16 @@ impl FdTable {
         Ok(self.get(fd)?.file())
     }
 
+    /// Like `get_file`, but the file is used for IO, which is not allowed on
+    /// files opened with `O_PATH`.
+    pub fn get_io_file(&self, fd: Fd) -> SysResult<Arc<dyn File>> {
+        let file = self.get_file(fd)?;
+        if file.flags().contains(OpenFlags::O_PATH) {
+            return Err(SysError::EBADF);
+        }
+        Ok(file)
+    }
+
     pub fn remove(&mut self, fd: Fd) -> SysResult<()> {
         if fd >= self.table.len() {
             Err(SysError::EBADF)
@@ -5,23 +5,32 @@
 
 pub mod devfs;
 pub mod fd_table;
+pub mod path_file;
 pub mod pipefs;
+pub mod probe;
 pub mod procfs;
 pub mod simplefs;
 pub mod sockfs;
-mod tmpfs;
+pub mod tmpfs;
 
 extern crate alloc;
 
-use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec, vec::Vec};
 
+use device_core::BlockDevice;
 use driver::BLOCK_DEVICE;
+use ext4::Ext4FsType;
+use fat32::FatFsType;
 use memory::FrameReleaseIf;
+use probe::probe_fs_type;
 use procfs::init_procfs;
 use sockfs::SockFsType;
 use spin::Once;
 use sync::mutex::SpinNoIrqLock;
-use vfs_core::{Dentry, DentryState, FileSystemType, InodeMode, MountFlags, OpenFlags, Path};
+use systype::{SysError, SysResult};
+use vfs_core::{
+    dcache_shrink, Dentry, DentryState, FileSystemType, InodeMode, InodeState, MountFlags,
+};
 
 use crate::{
     devfs::{init_devfs, DevFsType},
@@ -36,15 +45,20 @@ pub static FS_MANAGER: Mutex<BTreeMap<String, Arc<dyn FileSystemType>>> =
 
 static SYS_ROOT_DENTRY: Once<Arc<dyn Dentry>> = Once::new();
 
-// type DiskFsType = fat32::FatFsType;
-type DiskFsType = ext4::Ext4FsType;
+/// Filesystem of the root if it is neither given by `rootfstype=` nor
+/// recognized.
+const DEFAULT_ROOT_FS_TYPE: &str = "ext4";
 
-// pub const DISK_FS_NAME: &str = "fat32";
-pub const DISK_FS_NAME: &str = "ext4";
+/// Options of the tmpfs mounted at /dev/shm, which holds a quarter of the
+/// memory at most.
+const SHM_MOUNT_OPTIONS: &str = "size=25%";
 
 fn register_all_fs() {
-    let diskfs = DiskFsType::new();
-    FS_MANAGER.lock().insert(diskfs.name_string(), diskfs);
+    let ext4fs = Ext4FsType::new();
+    FS_MANAGER.lock().insert(ext4fs.name_string(), ext4fs);
+
+    let fatfs = FatFsType::new();
+    FS_MANAGER.lock().insert(fatfs.name_string(), fatfs);
 
     let devfs = DevFsType::new();
     FS_MANAGER.lock().insert(devfs.name_string(), devfs);
@@ -61,18 +75,31 @@ fn register_all_fs() {
     log::info!("[vfs] register fs success");
 }
 
+/// Filesystem of the root given by `rootfstype=`, or else the one probed on
+/// `device`.
+fn root_fs_type(device: &Arc<dyn BlockDevice>) -> &'static str {
+    match config::cmdline().get("rootfstype") {
+        Some(name) if name != "auto" => name,
+        _ => probe_fs_type(device).unwrap_or_else(|| {
+            log::warn!("[vfs] unknown fs on the root device, try {DEFAULT_ROOT_FS_TYPE}");
+            DEFAULT_ROOT_FS_TYPE
+        }),
+    }
+}
+
 /// Init the filesystem.
 pub fn init() {
     register_all_fs();
-    let diskfs = FS_MANAGER.lock().get(DISK_FS_NAME).unwrap().clone();
-    log::info!("[vfs] mounting disk fs");
+    let device = BLOCK_DEVICE.get().unwrap().clone();
+    let diskfs_name = root_fs_type(&device);
+    let diskfs = FS_MANAGER
+        .lock()
+        .get(diskfs_name)
+        .unwrap_or_else(|| panic!("[vfs] unknown root fs type {diskfs_name}"))
+        .clone();
+    log::info!("[vfs] mounting disk fs {diskfs_name}");
     let diskfs_root = diskfs
-        .mount(
-            "/",
-            None,
-            MountFlags::empty(),
-            Some(BLOCK_DEVICE.get().unwrap().clone()),
-        )
+        .mount("/", None, MountFlags::empty(), Some(device), "")
         .unwrap();
     // WARN: for "lmbench_all lat_sig -P 1 prot lat_sig" test
     diskfs_root
@@ -92,54 +119,157 @@ pub fn init() {
     log::info!("[vfs] mounting dev fs");
     let devfs = FS_MANAGER.lock().get("devfs").unwrap().clone();
     let devfs_dentry = devfs
-        .mount("dev", Some(diskfs_root.clone()), MountFlags::empty(), None)
+        .mount(
+            "dev",
+            Some(diskfs_root.clone()),
+            MountFlags::empty(),
+            None,
+            "",
+        )
         .unwrap();
     devfs_dentry.set_state(DentryState::Sync);
-    init_devfs(devfs_dentry).unwrap();
+    init_devfs(devfs_dentry.clone()).unwrap();
+
+    // POSIX shared memory objects are files here, see shm_open(3)
+    let tmpfs = FS_MANAGER.lock().get("tmpfs").unwrap().clone();
+    let shm_dentry = tmpfs
+        .mount(
+            "shm",
+            Some(devfs_dentry),
+            MountFlags::empty(),
+            None,
+            SHM_MOUNT_OPTIONS,
+        )
+        .unwrap();
+    shm_dentry.set_state(DentryState::Sync);
 
     let procfs = FS_MANAGER.lock().get("procfs").unwrap().clone();
     let procfs_dentry = procfs
-        .mount("proc", Some(diskfs_root.clone()), MountFlags::empty(), None)
+        .mount(
+            "proc",
+            Some(diskfs_root.clone()),
+            MountFlags::empty(),
+            None,
+            "",
+        )
         .unwrap();
     procfs_dentry.set_state(DentryState::Sync);
     init_procfs(procfs_dentry).unwrap();
 
     let tmpfs = FS_MANAGER.lock().get("tmpfs").unwrap().clone();
     let tmpfs_dentry = tmpfs
-        .mount("tmp", Some(diskfs_root.clone()), MountFlags::empty(), None)
+        .mount(
+            "tmp",
+            Some(diskfs_root.clone()),
+            MountFlags::empty(),
+            None,
+            "",
+        )
         .unwrap();
     tmpfs_dentry.set_state(DentryState::Sync);
 
     let sockfs = FS_MANAGER.lock().get("sockfs").unwrap().clone();
     let sockfs_dentry = sockfs
-        .mount("sock", Some(diskfs_root.clone()), MountFlags::empty(), None)
+        .mount(
+            "sock",
+            Some(diskfs_root.clone()),
+            MountFlags::empty(),
+            None,
+            "",
+        )
         .unwrap();
     sockfs_dentry.set_state(DentryState::Sync);
 
     SYS_ROOT_DENTRY.call_once(|| diskfs_root);
-
-    sys_root_dentry().open().unwrap().load_dir().unwrap();
 }
 
 pub fn sys_root_dentry() -> Arc<dyn Dentry> {
     SYS_ROOT_DENTRY.get().unwrap().clone()
 }
 
+/// Write all dirty data back to disks, i.e. dirty pages of files cached in
+/// the dentry tree, blocks cached by filesystems and then by block devices.
+pub async fn sync_all() {
+    let device = BLOCK_DEVICE.get();
+    // hold back the writes to merge them, until all are issued
+    if let Some(device) = device {
+        device.plug();
+    }
+    writeback_tree(sys_root_dentry()).await;
+
+    let fs_types: Vec<_> = FS_MANAGER.lock().values().cloned().collect();
+    for fs_type in fs_types {
+        let supers: Vec<_> = fs_type.meta().supers.lock().values().cloned().collect();
+        // filesystems in memory have nothing to write back
+        for sb in supers.iter().filter(|sb| sb.meta().device.is_some()) {
+            if let Err(err) = sb.sync_fs(1) {
+                log::error!("[sync_all] failed to sync {}: {err:?}", fs_type.name());
+            }
+        }
+    }
+    if let Some(device) = device {
+        device.unplug();
+        device.sync();
+        device.wait_writes();
+    }
+    log::info!("[vfs] all filesystems synced");
+}
+
+/// Write dirty pages of files under `root` back to their filesystems.
+async fn writeback_tree(root: Arc<dyn Dentry>) {
+    // dirty inodes are never evicted from the dentry tree
+    let mut stack = vec![root];
+    while let Some(dentry) = stack.pop() {
+        stack.extend(dentry.children().into_values());
+        let Ok(inode) = dentry.inode() else {
+            continue;
+        };
+        if !inode.itype().is_file() || inode.state() != InodeState::Dirty {
+            continue;
+        }
+        let ret = match dentry.open() {
+            Ok(file) => file.writeback().await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = ret {
+            log::error!("[vfs] failed to write back {}: {err:?}", dentry.path());
+        }
+    }
+}
+
+/// Unmount the filesystem whose root is `mount_root`, after writing its dirty
+/// data back. The directory it covers is looked up again afterwards.
+pub async fn umount(mount_root: Arc<dyn Dentry>) -> SysResult<()> {
+    let path = mount_root.path();
+    let Some(parent) = mount_root.parent() else {
+        return Err(SysError::EBUSY);
+    };
+    let fs_types: Vec<_> = FS_MANAGER.lock().values().cloned().collect();
+    let (fs_type, sb) = fs_types
+        .into_iter()
+        .find_map(|fs_type| {
+            let sb = fs_type.get_sb(&path).ok()?;
+            Some((fs_type, sb))
+        })
+        .ok_or(SysError::EINVAL)?;
+    if let Some(device) = sb.meta().device.clone() {
+        writeback_tree(mount_root.clone()).await;
+        sb.sync_fs(1)?;
+        device.sync();
+        device.wait_writes();
+    }
+    fs_type.meta().supers.lock().remove(&path);
+    parent.remove_child(&mount_root.name());
+    log::info!("[vfs] {} at {path} unmounted", fs_type.name());
+    Ok(())
+}
+
 struct FrameReleaseIfImpl;
 
 #[crate_interface::impl_interface]
 impl FrameReleaseIf for FrameReleaseIfImpl {
     fn release_frames() {
-        let ltp_dentry = Path::new(sys_root_dentry(), sys_root_dentry(), "/ltp/testcases/bin/")
-            .walk(OpenFlags::empty())
-            .unwrap();
-        for (_, child) in ltp_dentry.children() {
-            if let Ok(inode) = child.inode() {
-                if let Some(page_cache) = inode.page_cache() {
-                    page_cache.clear();
-                    inode.set_state(vfs_core::InodeState::UnInit)
-                }
-            }
-        }
+        let evicted = dcache_shrink(usize::MAX);
+        log::info!("[vfs] release frames, {evicted} dentries evicted");
     }
 }
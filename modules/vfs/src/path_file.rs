@@ -0,0 +1,55 @@
+use alloc::{boxed::Box, sync::Arc};
+
+use async_trait::async_trait;
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{Dentry, DirEntry, File, FileMeta, Inode, OpenFlags};
+
+/// File opened with `O_PATH`, which only indicates a location in the
+/// filesystem tree, and the file itself is not opened, e.g. a FIFO will not
+/// block and a symlink is not followed with `O_NOFOLLOW`.
+///
+/// It can be used for operations acting purely at the file descriptor level,
+/// e.g. fstat, fchdir and as dirfd of *at system calls, while IO on it fails
+/// with `EBADF`, see `FdTable::get_io_file`.
+pub struct PathFile {
+    meta: FileMeta,
+}
+
+impl PathFile {
+    pub fn new(dentry: Arc<dyn Dentry>, inode: Arc<dyn Inode>, flags: OpenFlags) -> Arc<Self> {
+        let meta = FileMeta::new(dentry, inode);
+        *meta.flags.lock() = flags;
+        Arc::new(Self { meta })
+    }
+}
+
+#[async_trait]
+impl File for PathFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, _offset: usize, _buf: &mut [u8]) -> SyscallResult {
+        Err(SysError::EBADF)
+    }
+
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SyscallResult {
+        Err(SysError::EBADF)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::EBADF)
+    }
+
+    async fn base_load_dir(&self) -> SysResult<()> {
+        Err(SysError::EBADF)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        Ok(0)
+    }
+
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> SyscallResult {
+        Err(SysError::EBADF)
+    }
+}
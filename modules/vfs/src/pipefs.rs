@@ -1,49 +1,224 @@
-use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
-use core::{
-    future::Future,
-    pin::Pin,
-    task::{Context, Poll, Waker},
-};
+use alloc::{boxed::Box, sync::Arc};
+use core::{future::Future, task::Waker};
 
 use async_trait::async_trait;
 use async_utils::get_waker;
 use config::fs::PIPE_BUF_LEN;
 use ring_buffer::RingBuffer;
-use sync::mutex::SpinNoIrqLock;
+use sync::{mutex::SpinNoIrqLock, WaitQueue};
 use systype::{SysError, SysResult};
-use vfs_core::{arc_zero, File, FileMeta, Inode, InodeMeta, InodeMode, PollEvents, Stat};
+use vfs_core::{
+    arc_zero, Dentry, File, FileMeta, Inode, InodeMeta, InodeMode, OpenFlags, PollEvents, SeekFrom,
+    Stat, SuperBlock,
+};
 
 type Mutex<T> = SpinNoIrqLock<T>;
 
 pub struct PipeInode {
     meta: InodeMeta,
     inner: Mutex<PipeInodeInner>,
+    /// Tasks waiting for the pipe to be readable.
+    read_queue: WaitQueue,
+    /// Tasks waiting for the pipe to be writable.
+    write_queue: WaitQueue,
+    /// Tasks opening a FIFO and waiting for the other end to be opened.
+    open_queue: WaitQueue,
 }
 
 pub struct PipeInodeInner {
-    is_write_closed: bool,
-    is_read_closed: bool,
+    /// Number of open read ends.
+    readers: usize,
+    /// Number of open write ends.
+    writers: usize,
+    /// Times the read end has been opened, so that an opener waiting for
+    /// readers can see one which opened and closed before it is woken up.
+    r_counter: usize,
+    /// Times the write end has been opened.
+    w_counter: usize,
     ring_buffer: RingBuffer,
-    // WARN: `Waker` may not wake the task exactly, it may be abandoned.
-    // Rust only guarentees that waker will wake the task from the last poll where the waker is
-    // passed in.
-    // FIXME: `sys_ppoll` and `sys_pselect6` may return because of other wake ups
-    // while the waker registered here is not removed.
-    read_waker: VecDeque<Waker>,
-    write_waker: VecDeque<Waker>,
+}
+
+impl PipeInodeInner {
+    fn is_read_closed(&self) -> bool {
+        self.readers == 0
+    }
+
+    fn is_write_closed(&self) -> bool {
+        self.writers == 0
+    }
 }
 
 impl PipeInode {
+    /// An anonymous pipe, whose both ends are opened by `new_pipe`.
     pub fn new(len: usize) -> Arc<Self> {
         let meta = InodeMeta::new(InodeMode::FIFO, Arc::<usize>::new_uninit(), PIPE_BUF_LEN);
+        Self::with_meta(meta, 1, len)
+    }
+
+    /// A named pipe created by `mknod` or found in a filesystem, which has no
+    /// ends opened. The node is stored by the filesystem, see `Dentry::mknod`,
+    /// while the data only lives in memory.
+    pub fn new_fifo(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        let meta = InodeMeta::new(InodeMode::FIFO, super_block, PIPE_BUF_LEN);
+        Self::with_meta(meta, 0, PIPE_BUF_LEN)
+    }
+
+    fn with_meta(meta: InodeMeta, ends: usize, len: usize) -> Arc<Self> {
         let inner = Mutex::new(PipeInodeInner {
-            is_write_closed: false,
-            is_read_closed: false,
+            readers: ends,
+            writers: ends,
+            r_counter: ends,
+            w_counter: ends,
             ring_buffer: RingBuffer::new(len),
-            read_waker: VecDeque::new(),
-            write_waker: VecDeque::new(),
         });
-        Arc::new(Self { meta, inner })
+        Arc::new(Self {
+            meta,
+            inner,
+            read_queue: WaitQueue::new(),
+            write_queue: WaitQueue::new(),
+            open_queue: WaitQueue::new(),
+        })
+    }
+
+    fn readable(&self) -> bool {
+        let inner = self.inner.lock();
+        !inner.ring_buffer.is_empty() || inner.is_write_closed()
+    }
+
+    fn writable(&self) -> bool {
+        let inner = self.inner.lock();
+        !inner.ring_buffer.is_full() || inner.is_read_closed()
+    }
+
+    /// Open the FIFO for reading, writing or both according to `flags`.
+    ///
+    /// Opening for reading or writing blocks until the other end is opened,
+    /// which is done by awaiting the returned future, unless `O_NONBLOCK` is
+    /// given. With `O_NONBLOCK`, opening for reading succeeds immediately,
+    /// while opening for writing fails with `ENXIO` if there are no readers.
+    /// Opening for both never blocks, since the file is an end of either kind.
+    pub fn open_fifo(
+        self: &Arc<Self>,
+        dentry: Arc<dyn Dentry>,
+        flags: OpenFlags,
+    ) -> SysResult<(Arc<dyn File>, impl Future<Output = ()> + '_)> {
+        let (is_reader, is_writer) = (flags.readable(), flags.writable());
+        let mut inner = self.inner.lock();
+        if !is_reader && flags.contains(OpenFlags::O_NONBLOCK) && inner.is_read_closed() {
+            return Err(SysError::ENXIO);
+        }
+        if is_reader {
+            inner.readers += 1;
+            inner.r_counter += 1;
+        }
+        if is_writer {
+            inner.writers += 1;
+            inner.w_counter += 1;
+        }
+        let (r_counter, w_counter) = (inner.r_counter, inner.w_counter);
+        drop(inner);
+        let file: Arc<dyn File> = match (is_reader, is_writer) {
+            (true, true) => PipeRdWrFile::new(dentry, self.clone()),
+            (true, false) => PipeReadFile::new(dentry, self.clone()),
+            _ => PipeWriteFile::new(dentry, self.clone()),
+        };
+        self.open_queue.wake_all();
+        // writers waiting for data to be read and readers waiting for data may
+        // see a new end
+        self.read_queue.wake_all();
+        self.write_queue.wake_all();
+        let wait_peer = self.open_queue.wait_until(move || {
+            let inner = self.inner.lock();
+            let has_writer = inner.writers > 0 || inner.w_counter != w_counter;
+            let has_reader = inner.readers > 0 || inner.r_counter != r_counter;
+            (!is_reader || has_writer) && (!is_writer || has_reader)
+        });
+        Ok((file, wait_peer))
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        log::info!("[PipeInode::read] read pipe ino {}", self.meta.ino);
+        loop {
+            self.read_queue.wait_until(|| self.readable()).await;
+            let mut inner = self.inner.lock();
+            if inner.ring_buffer.is_empty() {
+                if inner.is_write_closed() {
+                    return Ok(0);
+                }
+                // taken by other readers
+                continue;
+            }
+            let len = inner.ring_buffer.read(buf);
+            drop(inner);
+            self.write_queue.wake_one();
+            return Ok(len);
+        }
+    }
+
+    async fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        log::info!("[PipeInode::write] write pipe ino {}", self.meta.ino);
+        loop {
+            self.write_queue.wait_until(|| self.writable()).await;
+            let mut inner = self.inner.lock();
+            if inner.is_read_closed() {
+                return Err(SysError::EPIPE);
+            }
+            if inner.ring_buffer.is_full() {
+                // taken by other writers
+                continue;
+            }
+            let len = inner.ring_buffer.write(buf);
+            drop(inner);
+            self.read_queue.wake_one();
+            log::trace!("[Pipe::write] already write buf {buf:?} with data len {len:?}");
+            return Ok(len);
+        }
+    }
+
+    fn poll_read(&self, events: PollEvents, waker: &Waker) -> PollEvents {
+        let inner = self.inner.lock();
+        let mut res = PollEvents::empty();
+        if inner.is_write_closed() {
+            res |= PollEvents::HUP;
+        }
+        if events.contains(PollEvents::IN) && !inner.ring_buffer.is_empty() {
+            res |= PollEvents::IN;
+        } else {
+            self.read_queue.register(waker);
+        }
+        res
+    }
+
+    fn poll_write(&self, events: PollEvents, waker: &Waker) -> PollEvents {
+        let inner = self.inner.lock();
+        let mut res = PollEvents::empty();
+        if inner.is_read_closed() {
+            res |= PollEvents::ERR;
+        }
+        if events.contains(PollEvents::OUT) && !inner.ring_buffer.is_full() {
+            res |= PollEvents::OUT;
+        } else {
+            self.write_queue.register(waker);
+        }
+        res
+    }
+
+    fn close_read(&self) {
+        log::info!(
+            "[PipeInode::close_read] pipe ino {} read end is closed",
+            self.meta.ino
+        );
+        self.inner.lock().readers -= 1;
+        self.write_queue.wake_all();
+    }
+
+    fn close_write(&self) {
+        log::info!(
+            "[PipeInode::close_write] pipe ino {} write end is closed",
+            self.meta.ino
+        );
+        self.inner.lock().writers -= 1;
+        self.read_queue.wake_all();
     }
 }
 
@@ -57,10 +232,10 @@ impl Inode for PipeInode {
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
-            st_mode: self.meta.mode.bits(),
+            st_mode: inner.mode.bits(),
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: inner.size as u64,
@@ -75,93 +250,65 @@ impl Inode for PipeInode {
     }
 }
 
-struct PipeWritePollFuture {
-    events: PollEvents,
-    pipe: Arc<PipeInode>,
+pub struct PipeWriteFile {
+    meta: FileMeta,
 }
 
-impl PipeWritePollFuture {
-    fn new(pipe: Arc<PipeInode>, events: PollEvents) -> Self {
-        Self { pipe, events }
+impl PipeWriteFile {
+    pub fn new(dentry: Arc<dyn Dentry>, inode: Arc<PipeInode>) -> Arc<Self> {
+        let meta = FileMeta::new(dentry, inode);
+        Arc::new(Self { meta })
     }
 }
 
-impl Future for PipeWritePollFuture {
-    type Output = PollEvents;
+/// Pipe of the inode of a pipe file.
+fn pipe_of(file: &impl File) -> Arc<PipeInode> {
+    file.inode()
+        .downcast_arc::<PipeInode>()
+        .unwrap_or_else(|_| unreachable!())
+}
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let mut inner = self.pipe.inner.lock();
-        let mut res = PollEvents::empty();
-        if inner.is_read_closed {
-            res |= PollEvents::ERR;
-            return Poll::Ready(res);
-        }
-        if self.events.contains(PollEvents::OUT) && !inner.ring_buffer.is_full() {
-            res |= PollEvents::OUT;
-            Poll::Ready(res)
-        } else {
-            inner.write_waker.push_back(cx.waker().clone());
-            Poll::Pending
-        }
+// NOTE: `PipeReadFile` is hold by task as `Arc<dyn File>`.
+impl Drop for PipeWriteFile {
+    fn drop(&mut self) {
+        pipe_of(self).close_write();
     }
 }
 
-pub struct PipeWriteFile {
+pub struct PipeReadFile {
     meta: FileMeta,
 }
 
-impl PipeWriteFile {
-    pub fn new(inode: Arc<PipeInode>) -> Arc<Self> {
-        let meta = FileMeta::new(arc_zero(), inode);
+impl PipeReadFile {
+    pub fn new(dentry: Arc<dyn Dentry>, inode: Arc<PipeInode>) -> Arc<Self> {
+        let meta = FileMeta::new(dentry, inode);
         Arc::new(Self { meta })
     }
 }
 
-// NOTE: `PipeReadFile` is hold by task as `Arc<dyn File>`.
-impl Drop for PipeWriteFile {
+impl Drop for PipeReadFile {
     fn drop(&mut self) {
-        let pipe = self
-            .inode()
-            .downcast_arc::<PipeInode>()
-            .unwrap_or_else(|_| unreachable!());
-        log::info!(
-            "[PipeWriteFile::drop] pipe ino {} write end is closed",
-            pipe.meta().ino
-        );
-        let mut inner = pipe.inner.lock();
-        inner.is_write_closed = true;
-        while let Some(waker) = inner.read_waker.pop_front() {
-            waker.wake();
-        }
+        pipe_of(self).close_read();
     }
 }
 
-pub struct PipeReadFile {
+/// A FIFO opened with `O_RDWR`, which is both a read end and a write end.
+pub struct PipeRdWrFile {
     meta: FileMeta,
 }
 
-impl PipeReadFile {
-    pub fn new(inode: Arc<PipeInode>) -> Arc<Self> {
-        let meta = FileMeta::new(arc_zero(), inode);
+impl PipeRdWrFile {
+    pub fn new(dentry: Arc<dyn Dentry>, inode: Arc<PipeInode>) -> Arc<Self> {
+        let meta = FileMeta::new(dentry, inode);
         Arc::new(Self { meta })
     }
 }
 
-impl Drop for PipeReadFile {
+impl Drop for PipeRdWrFile {
     fn drop(&mut self) {
-        let pipe = self
-            .inode()
-            .downcast_arc::<PipeInode>()
-            .unwrap_or_else(|_| unreachable!());
-        log::info!(
-            "[PipeReadFile::drop] pipe ino {} read end is closed",
-            pipe.meta().ino
-        );
-        let mut inner = pipe.inner.lock();
-        inner.is_read_closed = true;
-        while let Some(waker) = inner.write_waker.pop_front() {
-            waker.wake();
-        }
+        let pipe = pipe_of(self);
+        pipe.close_read();
+        pipe.close_write();
     }
 }
 
@@ -176,136 +323,71 @@ impl File for PipeWriteFile {
     }
 
     async fn base_write_at(&self, _offset: usize, buf: &[u8]) -> SysResult<usize> {
-        let pipe = self
-            .inode()
-            .downcast_arc::<PipeInode>()
-            .unwrap_or_else(|_| unreachable!());
-        log::info!(
-            "[PipeWriteFile::base_write_at] read pipe ino {}",
-            pipe.meta().ino
-        );
-        let revents = PipeWritePollFuture::new(pipe.clone(), PollEvents::OUT).await;
-        if revents.contains(PollEvents::ERR) {
-            return Err(SysError::EPIPE);
-        }
-        assert!(revents.contains(PollEvents::OUT));
-        let mut inner = pipe.inner.lock();
-        let len = inner.ring_buffer.write(buf);
-        if let Some(waker) = inner.read_waker.pop_front() {
-            waker.wake();
-        }
-        log::trace!("[Pipe::write] already write buf {buf:?} with data len {len:?}");
-        return Ok(len);
+        pipe_of(self).write(buf).await
     }
 
     async fn base_poll(&self, events: PollEvents) -> PollEvents {
         let waker = get_waker().await;
-        let pipe = self
-            .inode()
-            .downcast_arc::<PipeInode>()
-            .unwrap_or_else(|_| unreachable!());
-        let mut inner = pipe.inner.lock();
-        let mut res = PollEvents::empty();
-        if inner.is_read_closed {
-            res |= PollEvents::ERR;
-        }
-        if events.contains(PollEvents::OUT) && !inner.ring_buffer.is_full() {
-            res |= PollEvents::OUT;
-        } else {
-            inner.write_waker.push_back(waker);
-        }
-        res
+        pipe_of(self).poll_write(events, &waker)
     }
-}
 
-struct PipeReadPollFuture {
-    events: PollEvents,
-    pipe: Arc<PipeInode>,
+    async fn seek(&self, _pos: SeekFrom) -> SysResult<usize> {
+        Err(SysError::ESPIPE)
+    }
 }
 
-impl PipeReadPollFuture {
-    fn new(pipe: Arc<PipeInode>, events: PollEvents) -> Self {
-        Self { pipe, events }
+#[async_trait]
+impl File for PipeReadFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
     }
-}
 
-impl Future for PipeReadPollFuture {
-    type Output = PollEvents;
+    async fn base_read_at(&self, _offset: usize, buf: &mut [u8]) -> SysResult<usize> {
+        pipe_of(self).read(buf).await
+    }
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let mut inner = self.pipe.inner.lock();
-        let mut res = PollEvents::empty();
-        if self.events.contains(PollEvents::IN) && !inner.ring_buffer.is_empty() {
-            res |= PollEvents::IN;
-            Poll::Ready(res)
-        } else {
-            if inner.is_write_closed {
-                res |= PollEvents::HUP;
-                return Poll::Ready(res);
-            }
-            inner.read_waker.push_back(cx.waker().clone());
-            Poll::Pending
-        }
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SysResult<usize> {
+        Err(SysError::EBADF)
+    }
+
+    async fn base_poll(&self, events: PollEvents) -> PollEvents {
+        let waker = get_waker().await;
+        pipe_of(self).poll_read(events, &waker)
+    }
+
+    async fn seek(&self, _pos: SeekFrom) -> SysResult<usize> {
+        Err(SysError::ESPIPE)
     }
 }
 
 #[async_trait]
-impl File for PipeReadFile {
+impl File for PipeRdWrFile {
     fn meta(&self) -> &FileMeta {
         &self.meta
     }
 
     async fn base_read_at(&self, _offset: usize, buf: &mut [u8]) -> SysResult<usize> {
-        let pipe = self
-            .inode()
-            .downcast_arc::<PipeInode>()
-            .unwrap_or_else(|_| unreachable!());
-        log::info!(
-            "[PipeReadFile::base_read_at] read pipe ino {}",
-            pipe.meta().ino
-        );
-        let events = PollEvents::IN;
-        let revents = PipeReadPollFuture::new(pipe.clone(), events).await;
-        if revents.contains(PollEvents::HUP) {
-            return Ok(0);
-        }
-        assert!(revents.contains(PollEvents::IN));
-        let mut inner = pipe.inner.lock();
-
-        let len = inner.ring_buffer.read(buf);
-        if let Some(waker) = inner.write_waker.pop_front() {
-            waker.wake();
-        }
-        return Ok(len);
+        pipe_of(self).read(buf).await
     }
 
-    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SysResult<usize> {
-        Err(SysError::EBADF)
+    async fn base_write_at(&self, _offset: usize, buf: &[u8]) -> SysResult<usize> {
+        pipe_of(self).write(buf).await
     }
 
     async fn base_poll(&self, events: PollEvents) -> PollEvents {
-        let pipe = self
-            .inode()
-            .downcast_arc::<PipeInode>()
-            .unwrap_or_else(|_| unreachable!());
         let waker = get_waker().await;
-        let mut inner = pipe.inner.lock();
-        let mut res = PollEvents::empty();
-        if inner.is_write_closed {
-            res |= PollEvents::HUP;
-        }
-        if events.contains(PollEvents::IN) && !inner.ring_buffer.is_empty() {
-            res |= PollEvents::IN;
-        } else {
-            inner.read_waker.push_back(waker);
-        }
-        res
+        let pipe = pipe_of(self);
+        pipe.poll_read(events, &waker) | pipe.poll_write(events, &waker)
+    }
+
+    async fn seek(&self, _pos: SeekFrom) -> SysResult<usize> {
+        Err(SysError::ESPIPE)
     }
 }
 
 pub fn new_pipe(len: usize) -> (Arc<dyn File>, Arc<dyn File>) {
     let pipe_inode = PipeInode::new(len);
-    let read_end = PipeReadFile::new(pipe_inode.clone());
-    let write_end = PipeWriteFile::new(pipe_inode);
+    let read_end = PipeReadFile::new(arc_zero(), pipe_inode.clone());
+    let write_end = PipeWriteFile::new(arc_zero(), pipe_inode);
     (read_end, write_end)
 }
@@ -0,0 +1,60 @@
+//! Detection of the filesystem on a block device by its superblock, as
+//! `blkid` does, so that images of different filesystems are mounted by the
+//! same kernel.
+
+use alloc::{sync::Arc, vec};
+
+use device_core::BlockDevice;
+
+/// Bytes read from the start of the device, which cover the boot sector of
+/// fat32 and the superblock of ext4.
+const PROBE_SIZE: usize = 2048;
+
+/// Offset of `s_magic` of the ext4 superblock, which starts at 1024.
+const EXT4_MAGIC_OFFSET: usize = 1080;
+const EXT4_MAGIC: u16 = 0xEF53;
+
+/// Signature at the end of the boot sector of fat.
+const FAT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// Name of the filesystem on `dev` registered in `FS_MANAGER`, or `None` if it
+/// is not recognized.
+pub fn probe_fs_type(dev: &Arc<dyn BlockDevice>) -> Option<&'static str> {
+    let block_size = dev.block_size();
+    let mut buf = vec![0; PROBE_SIZE.next_multiple_of(block_size)];
+    for (block_id, block) in buf.chunks_exact_mut(block_size).enumerate() {
+        dev.read_block(block_id, block);
+    }
+
+    if is_ext4(&buf) {
+        Some("ext4")
+    } else if is_fat32(&buf) {
+        Some("fat32")
+    } else {
+        None
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn is_ext4(buf: &[u8]) -> bool {
+    read_u16(buf, EXT4_MAGIC_OFFSET) == EXT4_MAGIC
+}
+
+/// Check the BIOS parameter block in the boot sector, where the sectors of
+/// each fat in 16 bits is zero only for fat32.
+fn is_fat32(buf: &[u8]) -> bool {
+    let bytes_per_sector = read_u16(buf, 11);
+    let sectors_per_cluster = buf[13];
+    let reserved_sectors = read_u16(buf, 14);
+    let fats = buf[16];
+    let sectors_per_fat_16 = read_u16(buf, 22);
+    buf[510..512] == FAT_SIGNATURE
+        && matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+        && sectors_per_cluster.is_power_of_two()
+        && reserved_sectors != 0
+        && fats != 0
+        && sectors_per_fat_16 == 0
+}
@@ -0,0 +1,128 @@
+use alloc::{boxed::Box, format, string::String, sync::Arc};
+use core::cmp;
+
+use async_trait::async_trait;
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{
+    dcache_stat, Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, Stat,
+    SuperBlock,
+};
+
+/// Statistics of the dentry cache, which is read from /proc/sys/fs/dcache-stat.
+pub fn serialize_dcache_stat() -> String {
+    let stat = dcache_stat();
+    format!(
+        "nr_dentry {}\nhits {}\nmisses {}\nevictions {}\n",
+        stat.nr_dentry, stat.hits, stat.misses, stat.evictions
+    )
+}
+
+pub struct DcacheStatDentry {
+    meta: DentryMeta,
+}
+
+impl DcacheStatDentry {
+    pub fn new(super_block: Arc<dyn SuperBlock>, parent: Option<Arc<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("dcache-stat", super_block, parent),
+        })
+    }
+}
+
+impl Dentry for DcacheStatDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(DcacheStatFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct DcacheStatInode {
+    meta: InodeMeta,
+}
+
+impl DcacheStatInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::FILE, super_block, 0),
+        })
+    }
+}
+
+impl Inode for DcacheStatInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct DcacheStatFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for DcacheStatFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let info = serialize_dcache_stat();
+        if offset >= info.len() {
+            return Ok(0);
+        }
+        let len = cmp::min(info.len() - offset, buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SyscallResult {
+        Err(SysError::EACCES)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
@@ -0,0 +1,144 @@
+use alloc::{boxed::Box, string::String, sync::Arc};
+use core::{cmp, fmt::Write};
+
+use async_trait::async_trait;
+use driver::BLOCK_DEVICE;
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{
+    Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, Stat, SuperBlock,
+};
+
+/// I/O statistics of the block device, which is read from /proc/diskstats in
+/// the layout of Linux, with the fields not counted as 0.
+pub fn serialize_diskstats() -> String {
+    let mut info = String::new();
+    if let Some(device) = BLOCK_DEVICE.get() {
+        let dev_id = device.dev_id();
+        let stats = device.disk_stats();
+        let _ = writeln!(
+            info,
+            "{:>4} {:>7} {} {} {} {} 0 {} {} {} 0 {} 0 0",
+            dev_id.major as usize,
+            dev_id.minor,
+            device.name(),
+            stats.reads,
+            stats.reads_merged,
+            stats.sectors_read,
+            stats.writes,
+            stats.writes_merged,
+            stats.sectors_written,
+            stats.in_flight,
+        );
+    }
+    info
+}
+
+pub struct DiskstatsDentry {
+    meta: DentryMeta,
+}
+
+impl DiskstatsDentry {
+    pub fn new(super_block: Arc<dyn SuperBlock>, parent: Option<Arc<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("diskstats", super_block, parent),
+        })
+    }
+}
+
+impl Dentry for DiskstatsDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(DiskstatsFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct DiskstatsInode {
+    meta: InodeMeta,
+}
+
+impl DiskstatsInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::FILE, super_block, 0),
+        })
+    }
+}
+
+impl Inode for DiskstatsInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct DiskstatsFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for DiskstatsFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let info = serialize_diskstats();
+        if offset >= info.len() {
+            return Ok(0);
+        }
+        let len = cmp::min(info.len() - offset, buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SyscallResult {
+        Err(SysError::EACCES)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
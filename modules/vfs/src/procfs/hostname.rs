@@ -0,0 +1,148 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::cmp;
+
+use async_trait::async_trait;
+use spin::Lazy;
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{
+    Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, Stat, SuperBlock,
+};
+
+use crate::Mutex;
+
+/// Max length of the hostname, not including the terminating null byte.
+pub const HOST_NAME_MAX: usize = 64;
+
+/// Hostname of the system, which is the nodename of `uname`, and can be
+/// changed by `sethostname` or writing /proc/sys/kernel/hostname.
+pub static HOSTNAME: Lazy<Mutex<String>> = Lazy::new(|| Mutex::new("localhost".to_string()));
+
+pub fn hostname() -> String {
+    HOSTNAME.lock().clone()
+}
+
+pub fn set_hostname(name: &[u8]) -> SysResult<()> {
+    if name.len() > HOST_NAME_MAX {
+        return Err(SysError::EINVAL);
+    }
+    let name = core::str::from_utf8(name).map_err(|_| SysError::EINVAL)?;
+    *HOSTNAME.lock() = name.to_string();
+    Ok(())
+}
+
+pub struct HostnameDentry {
+    meta: DentryMeta,
+}
+
+impl HostnameDentry {
+    pub fn new(super_block: Arc<dyn SuperBlock>, parent: Option<Arc<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("hostname", super_block, parent),
+        })
+    }
+}
+
+impl Dentry for HostnameDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(HostnameFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct HostnameInode {
+    meta: InodeMeta,
+}
+
+impl HostnameInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::FILE, super_block, 0),
+        })
+    }
+}
+
+impl Inode for HostnameInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct HostnameFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for HostnameFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let hostname = hostname() + "\n";
+        if offset >= hostname.len() {
+            return Ok(0);
+        }
+        let len = cmp::min(hostname.len() - offset, buf.len());
+        buf[..len].copy_from_slice(&hostname.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    /// The whole hostname is replaced by each write, as Linux does.
+    async fn base_write_at(&self, _offset: usize, buf: &[u8]) -> SyscallResult {
+        let name = buf.strip_suffix(b"\n").unwrap_or(buf);
+        set_hostname(name)?;
+        Ok(buf.len())
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
@@ -0,0 +1,139 @@
+use alloc::{boxed::Box, format, string::String, sync::Arc};
+use core::{cmp, fmt::Write};
+
+use async_trait::async_trait;
+use config::board::{self, MAX_HARTS};
+use driver::get_device_manager;
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{
+    Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, Stat, SuperBlock,
+};
+
+/// Number of the external interrupts handled by each hart for each IRQ of the
+/// PLIC, which is read from /proc/interrupts in the layout of Linux.
+pub fn serialize_interrupts() -> String {
+    let harts = board::harts().min(MAX_HARTS);
+    let mut info = String::from("    ");
+    for hart in 0..harts {
+        let _ = write!(info, " {:>10}", format!("CPU{hart}"));
+    }
+    info += "\n";
+    for (irq, counts, name) in get_device_manager().irq_counts() {
+        let _ = write!(info, "{irq:>3}:");
+        for count in &counts[..harts] {
+            let _ = write!(info, " {count:>10}");
+        }
+        let _ = writeln!(info, "  PLIC {irq:>4}  {name}");
+    }
+    info
+}
+
+pub struct InterruptsDentry {
+    meta: DentryMeta,
+}
+
+impl InterruptsDentry {
+    pub fn new(super_block: Arc<dyn SuperBlock>, parent: Option<Arc<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("interrupts", super_block, parent),
+        })
+    }
+}
+
+impl Dentry for InterruptsDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(InterruptsFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct InterruptsInode {
+    meta: InodeMeta,
+}
+
+impl InterruptsInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::FILE, super_block, 0),
+        })
+    }
+}
+
+impl Inode for InterruptsInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct InterruptsFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for InterruptsFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let info = serialize_interrupts();
+        if offset >= info.len() {
+            return Ok(0);
+        }
+        let len = cmp::min(info.len() - offset, buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SyscallResult {
+        Err(SysError::EACCES)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
@@ -6,20 +6,14 @@ use alloc::{
 use core::cmp;
 
 use async_trait::async_trait;
+use config::mm::PAGE_SIZE;
+use crate_interface::call_interface;
 use systype::{SysError, SysResult, SyscallResult};
 use vfs_core::{
     Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, Stat, SuperBlock,
 };
 
-use crate::Mutex;
-
-pub static MEM_INFO: Mutex<MemInfo> = Mutex::new(MemInfo::new());
-
-const TOTAL_MEM: usize = 16251136;
-const FREE_MEM: usize = 327680;
-const BUFFER: usize = 373336;
-const CACHED: usize = 10391984;
-const TOTAL_SWAP: usize = 4194300;
+use super::KernelProcIf;
 
 /// Mapping to free output: https://access.redhat.com/solutions/406773.
 pub struct MemInfo {
@@ -39,19 +33,25 @@ pub struct MemInfo {
 }
 
 impl MemInfo {
-    pub const fn new() -> Self {
+    /// Collect the memory usage from the frame allocator, in KB.
+    pub fn collect() -> Self {
+        let stats = memory::frame_stats();
+        let (total_swap, free_swap) = call_interface!(KernelProcIf::swap_pages());
+        let kb = |frames: usize| frames * PAGE_SIZE / 1024;
         Self {
-            total_mem: TOTAL_MEM,
-            free_mem: FREE_MEM,
-            avail_mem: TOTAL_MEM - FREE_MEM,
-            buffers: BUFFER,
-            cached: CACHED,
-            total_swap: TOTAL_SWAP,
-            free_swap: TOTAL_SWAP,
+            total_mem: kb(stats.total),
+            free_mem: kb(stats.free),
+            // frames below the min watermark are kept for the kernel
+            avail_mem: kb(stats.free.saturating_sub(stats.min_watermark)),
+            buffers: 0,
+            cached: 0,
+            total_swap: kb(total_swap),
+            free_swap: kb(free_swap),
             shmem: 0,
             slab: 0,
         }
     }
+
     pub fn serialize(&self) -> String {
         let mut res = "".to_string();
         let end = " KB\n";
@@ -125,7 +125,7 @@ pub struct MemInfoInode {
 
 impl MemInfoInode {
     pub fn new(super_block: Arc<dyn SuperBlock>, _size: usize) -> Arc<Self> {
-        let size = MEM_INFO.lock().serialize().len();
+        let size = MemInfo::collect().serialize().len();
         Arc::new(Self {
             meta: InodeMeta::new(InodeMode::FILE, super_block, size),
         })
@@ -139,15 +139,15 @@ impl Inode for MemInfoInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -173,8 +173,10 @@ impl File for MemInfoFile {
     }
 
     async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
-        let meminfo = MEM_INFO.lock();
-        let info = meminfo.serialize();
+        let info = MemInfo::collect().serialize();
+        if offset >= info.len() {
+            return Ok(0);
+        }
         let len = cmp::min(info.len() - offset, buf.len());
         buf[..len].copy_from_slice(&info.as_bytes()[offset..offset + len]);
         Ok(len)
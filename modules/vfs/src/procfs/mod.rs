@@ -1,24 +1,50 @@
+mod dcache;
+mod diskstats;
+mod hostname;
+mod interrupts;
 mod meminfo;
 mod mounts;
+mod net;
+mod random;
+mod schedstat;
 mod self_;
+mod slabinfo;
+mod syscall_stats;
+mod timer_stats;
+mod vmstat;
 
-use alloc::sync::Arc;
+use alloc::{format, sync::Arc};
 
 use async_utils::block_on;
 use device_core::BlockDevice;
+pub use hostname::{hostname, set_hostname, HOST_NAME_MAX};
 pub use self_::KernelProcIf;
 use systype::SysResult;
 use vfs_core::{
-    Dentry, FileSystemType, FileSystemTypeMeta, InodeMode, MountFlags, SuperBlock, SuperBlockMeta,
+    Dentry, FileSystemType, FileSystemTypeMeta, InodeMode, MountFlags, StatFs, SuperBlock,
+    SuperBlockMeta,
 };
 
 use self::{
+    dcache::{DcacheStatDentry, DcacheStatInode},
+    diskstats::{DiskstatsDentry, DiskstatsInode},
+    hostname::{HostnameDentry, HostnameInode},
+    interrupts::{InterruptsDentry, InterruptsInode},
     meminfo::{MemInfoDentry, MemInfoInode},
     mounts::{MountsDentry, MountsInode},
-    self_::{ExeDentry, ExeFile, ExeInode},
+    net::{serialize_net_dev, serialize_snmp, NetStatDentry, NetStatInode},
+    random::{EntropyAvailDentry, EntropyAvailInode},
+    schedstat::{SchedStatDentry, SchedStatInode},
+    self_::{ExeDentry, ExeFile, ExeInode, StatDentry, StatInode},
+    slabinfo::{SlabInfoDentry, SlabInfoInode},
+    syscall_stats::{SyscallStatsDentry, SyscallStatsEnableDentry, SyscallStatsInode},
+    timer_stats::{TimerStatsDentry, TimerStatsInode},
+    vmstat::{VmStatDentry, VmStatInode},
 };
 use crate::simplefs::{dentry::SimpleDentry, inode::SimpleDirInode};
 
+const PROC_SUPER_MAGIC: i64 = 0x9fa0;
+
 pub fn init_procfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
     let mem_info_dentry = MemInfoDentry::new(
         "meminfo",
@@ -38,6 +64,55 @@ pub fn init_procfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
     mounts_dentry.set_inode(mounts_inode);
     root_dentry.insert(mounts_dentry);
 
+    let vmstat_dentry: Arc<dyn Dentry> =
+        VmStatDentry::new(root_dentry.super_block(), Some(root_dentry.clone()));
+    vmstat_dentry.set_inode(VmStatInode::new(root_dentry.super_block()));
+    root_dentry.insert(vmstat_dentry);
+
+    let schedstat_dentry: Arc<dyn Dentry> =
+        SchedStatDentry::new(root_dentry.super_block(), Some(root_dentry.clone()));
+    schedstat_dentry.set_inode(SchedStatInode::new(root_dentry.super_block()));
+    root_dentry.insert(schedstat_dentry);
+
+    let timer_stats_dentry: Arc<dyn Dentry> =
+        TimerStatsDentry::new(root_dentry.super_block(), Some(root_dentry.clone()));
+    timer_stats_dentry.set_inode(TimerStatsInode::new(root_dentry.super_block()));
+    root_dentry.insert(timer_stats_dentry);
+
+    let interrupts_dentry: Arc<dyn Dentry> =
+        InterruptsDentry::new(root_dentry.super_block(), Some(root_dentry.clone()));
+    interrupts_dentry.set_inode(InterruptsInode::new(root_dentry.super_block()));
+    root_dentry.insert(interrupts_dentry);
+
+    let diskstats_dentry: Arc<dyn Dentry> =
+        DiskstatsDentry::new(root_dentry.super_block(), Some(root_dentry.clone()));
+    diskstats_dentry.set_inode(DiskstatsInode::new(root_dentry.super_block()));
+    root_dentry.insert(diskstats_dentry);
+
+    let syscall_stats_dentry: Arc<dyn Dentry> =
+        SyscallStatsDentry::new(root_dentry.super_block(), Some(root_dentry.clone()));
+    syscall_stats_dentry.set_inode(SyscallStatsInode::new(root_dentry.super_block()));
+    root_dentry.insert(syscall_stats_dentry);
+
+    let net_dentry: Arc<dyn Dentry> =
+        SimpleDentry::new("net", root_dentry.super_block(), Some(root_dentry.clone()));
+    let net_inode = SimpleDirInode::new(InodeMode::DIR, root_dentry.super_block(), 0);
+    net_dentry.set_inode(net_inode);
+    root_dentry.insert(net_dentry.clone());
+    for (name, serialize) in [
+        ("dev", serialize_net_dev as fn() -> _),
+        ("snmp", serialize_snmp),
+    ] {
+        let dentry: Arc<dyn Dentry> = NetStatDentry::new(
+            name,
+            serialize,
+            root_dentry.super_block(),
+            Some(net_dentry.clone()),
+        );
+        dentry.set_inode(NetStatInode::new(root_dentry.super_block()));
+        net_dentry.insert(dentry);
+    }
+
     let sys_dentry: Arc<dyn Dentry> =
         SimpleDentry::new("sys", root_dentry.super_block(), Some(root_dentry.clone()));
     let sys_inode = SimpleDirInode::new(InodeMode::DIR, root_dentry.super_block(), 0);
@@ -48,6 +123,33 @@ pub fn init_procfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
     let pid_max_dentry = kernel_dentry.create("pid_max", InodeMode::FILE)?;
     let pid_max_file = pid_max_dentry.open()?;
     block_on(async { pid_max_file.write("32768\0".as_bytes()).await });
+    let hostname_dentry: Arc<dyn Dentry> =
+        HostnameDentry::new(root_dentry.super_block(), Some(kernel_dentry.clone()));
+    hostname_dentry.set_inode(HostnameInode::new(root_dentry.super_block()));
+    kernel_dentry.insert(hostname_dentry);
+    let slab_info_dentry: Arc<dyn Dentry> =
+        SlabInfoDentry::new(root_dentry.super_block(), Some(kernel_dentry.clone()));
+    slab_info_dentry.set_inode(SlabInfoInode::new(root_dentry.super_block()));
+    kernel_dentry.insert(slab_info_dentry);
+    let syscall_stats_enable_dentry: Arc<dyn Dentry> =
+        SyscallStatsEnableDentry::new(root_dentry.super_block(), Some(kernel_dentry.clone()));
+    syscall_stats_enable_dentry.set_inode(SyscallStatsInode::new(root_dentry.super_block()));
+    kernel_dentry.insert(syscall_stats_enable_dentry);
+    let random_dentry = kernel_dentry.create("random", InodeMode::DIR)?;
+    let poolsize_dentry = random_dentry.create("poolsize", InodeMode::FILE)?;
+    let poolsize_file = poolsize_dentry.open()?;
+    let poolsize = format!("{}\n", driver::random::POOL_BITS);
+    block_on(async { poolsize_file.write(poolsize.as_bytes()).await });
+    let entropy_avail_dentry: Arc<dyn Dentry> =
+        EntropyAvailDentry::new(root_dentry.super_block(), Some(random_dentry.clone()));
+    entropy_avail_dentry.set_inode(EntropyAvailInode::new(root_dentry.super_block()));
+    random_dentry.insert(entropy_avail_dentry);
+
+    let fs_dentry = sys_dentry.create("fs", InodeMode::DIR)?;
+    let dcache_stat_dentry: Arc<dyn Dentry> =
+        DcacheStatDentry::new(root_dentry.super_block(), Some(fs_dentry.clone()));
+    dcache_stat_dentry.set_inode(DcacheStatInode::new(root_dentry.super_block()));
+    fs_dentry.insert(dcache_stat_dentry);
 
     let self_dentry: Arc<dyn Dentry> =
         SimpleDentry::new("self", root_dentry.super_block(), Some(root_dentry.clone()));
@@ -64,6 +166,11 @@ pub fn init_procfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
     let exe_inode = ExeInode::new(root_dentry.super_block(), 0);
     exe_dentry.set_inode(exe_inode);
     self_dentry.insert(exe_dentry);
+    let stat_dentry: Arc<dyn Dentry> =
+        StatDentry::new(root_dentry.super_block(), Some(self_dentry.clone()));
+    let stat_inode = StatInode::new(root_dentry.super_block());
+    stat_dentry.set_inode(stat_inode);
+    self_dentry.insert(stat_dentry);
 
     root_dentry.insert(self_dentry.clone());
 
@@ -93,6 +200,7 @@ impl FileSystemType for ProcFsType {
         parent: Option<Arc<dyn Dentry>>,
         _flags: MountFlags,
         dev: Option<Arc<dyn BlockDevice>>,
+        _data: &str,
     ) -> SysResult<Arc<dyn Dentry>> {
         let sb = ProcSuperBlock::new(dev, self.clone());
         let mount_dentry = SimpleDentry::new(name, sb.clone(), parent.clone());
@@ -130,8 +238,8 @@ impl SuperBlock for ProcSuperBlock {
         &self.meta
     }
 
-    fn stat_fs(&self) -> SysResult<vfs_core::StatFs> {
-        todo!()
+    fn stat_fs(&self) -> SysResult<StatFs> {
+        Ok(StatFs::pseudo(PROC_SUPER_MAGIC))
     }
 
     fn sync_fs(&self, _wait: isize) -> SysResult<()> {
@@ -0,0 +1,188 @@
+use alloc::{boxed::Box, string::String, sync::Arc};
+use core::{cmp, fmt::Write};
+
+use async_trait::async_trait;
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{
+    Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, Stat, SuperBlock,
+};
+
+/// Statistics of the network interface, which is read from /proc/net/dev in
+/// the layout of Linux, with the fields not counted as 0.
+pub fn serialize_net_dev() -> String {
+    let mut info = String::from(
+        "Inter-|   Receive                                                |  Transmit\n \
+         face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n",
+    );
+    if let Some((name, stats)) = ::net::interface_stats() {
+        let _ = writeln!(
+            info,
+            "{:>6}: {:>7} {:>7} {:>4} {:>4} {:>4} {:>5} {:>10} {:>9} {:>8} {:>7} {:>4} {:>4} {:>4} {:>5} {:>7} {:>10}",
+            name,
+            stats.rx_bytes,
+            stats.rx_packets,
+            stats.rx_errors,
+            stats.rx_no_socket,
+            0,
+            0,
+            0,
+            0,
+            stats.tx_bytes,
+            stats.tx_packets,
+            stats.tx_errors,
+            0,
+            0,
+            0,
+            0,
+            0,
+        );
+    }
+    info
+}
+
+/// Counters of the protocols, which is read from /proc/net/snmp in the layout
+/// of Linux, with a header line and a value line for each protocol, and only
+/// the fields counted.
+pub fn serialize_snmp() -> String {
+    let stats = ::net::interface_stats()
+        .map(|(_, stats)| stats)
+        .unwrap_or_default();
+    let mut info = String::new();
+    let _ = writeln!(
+        info,
+        "Ip: InReceives InHdrErrors InUnknownProtos InCsumErrors\nIp: {} {} {} {}",
+        stats.ip.received, stats.ip.errors, stats.ip_unknown_protos, stats.ip.csum_errors
+    );
+    let _ = writeln!(
+        info,
+        "Tcp: InSegs InErrs InCsumErrors\nTcp: {} {} {}",
+        stats.tcp.received, stats.tcp.errors, stats.tcp.csum_errors
+    );
+    let _ = writeln!(
+        info,
+        "Udp: InDatagrams InErrors InCsumErrors\nUdp: {} {} {}",
+        stats.udp.received, stats.udp.errors, stats.udp.csum_errors
+    );
+    info
+}
+
+/// A file under /proc/net, whose content is generated by `serialize` when it
+/// is read.
+pub struct NetStatDentry {
+    meta: DentryMeta,
+    serialize: fn() -> String,
+}
+
+impl NetStatDentry {
+    pub fn new(
+        name: &str,
+        serialize: fn() -> String,
+        super_block: Arc<dyn SuperBlock>,
+        parent: Option<Arc<dyn Dentry>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new(name, super_block, parent),
+            serialize,
+        })
+    }
+}
+
+impl Dentry for NetStatDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(NetStatFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+            serialize: self.serialize,
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct NetStatInode {
+    meta: InodeMeta,
+}
+
+impl NetStatInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::FILE, super_block, 0),
+        })
+    }
+}
+
+impl Inode for NetStatInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct NetStatFile {
+    meta: FileMeta,
+    serialize: fn() -> String,
+}
+
+#[async_trait]
+impl File for NetStatFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let info = (self.serialize)();
+        if offset >= info.len() {
+            return Ok(0);
+        }
+        let len = cmp::min(info.len() - offset, buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SyscallResult {
+        Err(SysError::EACCES)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
@@ -1,4 +1,5 @@
 use alloc::{boxed::Box, ffi::CString, sync::Arc};
+use core::cmp;
 
 use async_trait::async_trait;
 use config::board::BLOCK_SIZE;
@@ -11,6 +12,23 @@ use vfs_core::{
 #[crate_interface::def_interface]
 pub trait KernelProcIf {
     fn exe() -> alloc::string::String;
+    /// Status information about the current process, in the format of
+    /// /proc/[pid]/stat.
+    fn stat() -> alloc::string::String;
+    /// Counters of virtual memory events, in the format of /proc/vmstat.
+    fn vmstat() -> alloc::string::String;
+    /// Counters of the run queues of each hart, in the format of
+    /// /proc/schedstat.
+    fn schedstat() -> alloc::string::String;
+    /// Counters of timers, in the format of /proc/timer_stats.
+    fn timer_stats() -> alloc::string::String;
+    /// Pages of the swap space in total and free.
+    fn swap_pages() -> (usize, usize);
+    /// Latency statistics of syscalls, in the format of /proc/syscall_stats.
+    fn syscall_stats() -> alloc::string::String;
+    fn reset_syscall_stats();
+    fn syscall_stats_enabled() -> bool;
+    fn set_syscall_stats_enabled(enabled: bool);
 }
 
 pub struct ExeDentry {
@@ -69,15 +87,15 @@ impl Inode for ExeInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -129,3 +147,115 @@ impl File for ExeFile {
         Ok(exe.len())
     }
 }
+
+pub struct StatDentry {
+    meta: DentryMeta,
+}
+
+impl StatDentry {
+    pub fn new(super_block: Arc<dyn SuperBlock>, parent: Option<Arc<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("stat", super_block, parent),
+        })
+    }
+}
+
+impl Dentry for StatDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(StatFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct StatInode {
+    meta: InodeMeta,
+}
+
+impl StatInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        // NOTE: size of files in procfs is zero since their contents are generated
+        // on the fly
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::FILE, super_block, 0),
+        })
+    }
+}
+
+impl Inode for StatInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct StatFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for StatFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let stat = call_interface!(KernelProcIf::stat());
+        if offset >= stat.len() {
+            return Ok(0);
+        }
+        let len = cmp::min(stat.len() - offset, buf.len());
+        buf[..len].copy_from_slice(&stat.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SyscallResult {
+        Err(SysError::EACCES)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
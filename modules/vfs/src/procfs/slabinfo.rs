@@ -0,0 +1,162 @@
+use alloc::{boxed::Box, collections::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+use core::{cmp, fmt::Write};
+
+use async_trait::async_trait;
+use memory::heap::{heap_stats, live_allocations, CALLER_DEPTH};
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{
+    Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, Stat, SuperBlock,
+};
+
+/// Call stacks with the most live bytes listed, when the allocations are
+/// tracked.
+const TOP_CALLERS: usize = 32;
+
+/// Statistics of the kernel heap in size classes, which is read from
+/// /proc/sys/kernel/slabinfo, followed by the call stacks holding the most
+/// live bytes if the `heap-tracking` feature is enabled.
+pub fn serialize_slab_info() -> String {
+    let stats = heap_stats();
+    let mut info = format!(
+        "total {}\ncurrent {}\npeak {}\nallocs {}\nfrees {}\nfailures {}\n",
+        stats.total, stats.current, stats.peak, stats.allocs, stats.frees, stats.failures
+    );
+    info += "# name            <active_objs> <allocs> <objsize>\n";
+    for class in stats.classes.iter() {
+        let (name, size) = match class.size {
+            Some(size) => (format!("kmalloc-{size}"), size),
+            None => (String::from("kmalloc-large"), 0),
+        };
+        let _ = writeln!(
+            info,
+            "{name:<17} {:13} {:8} {size:9}",
+            class.live, class.allocs
+        );
+    }
+
+    let mut callers: BTreeMap<[usize; CALLER_DEPTH], (usize, usize)> = BTreeMap::new();
+    for allocation in live_allocations() {
+        let (bytes, count) = callers.entry(allocation.callers).or_default();
+        *bytes += allocation.size;
+        *count += 1;
+    }
+    if !callers.is_empty() {
+        let mut callers: Vec<_> = callers.into_iter().collect();
+        callers.sort_by_key(|&(_, (bytes, _))| cmp::Reverse(bytes));
+        info += "# <bytes> <objs> <callers>\n";
+        for (stack, (bytes, count)) in callers.into_iter().take(TOP_CALLERS) {
+            let _ = writeln!(info, "{bytes} {count} {stack:x?}");
+        }
+    }
+    info
+}
+
+pub struct SlabInfoDentry {
+    meta: DentryMeta,
+}
+
+impl SlabInfoDentry {
+    pub fn new(super_block: Arc<dyn SuperBlock>, parent: Option<Arc<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("slabinfo", super_block, parent),
+        })
+    }
+}
+
+impl Dentry for SlabInfoDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(SlabInfoFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+pub struct SlabInfoInode {
+    meta: InodeMeta,
+}
+
+impl SlabInfoInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::FILE, super_block, 0),
+        })
+    }
+}
+
+impl Inode for SlabInfoInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct SlabInfoFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for SlabInfoFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let info = serialize_slab_info();
+        if offset >= info.len() {
+            return Ok(0);
+        }
+        let len = cmp::min(info.len() - offset, buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write_at(&self, _offset: usize, _buf: &[u8]) -> SyscallResult {
+        Err(SysError::EACCES)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
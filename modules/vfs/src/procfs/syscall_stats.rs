@@ -0,0 +1,204 @@
+use alloc::{boxed::Box, sync::Arc};
+use core::cmp;
+
+use async_trait::async_trait;
+use crate_interface::call_interface;
+use systype::{SysError, SysResult, SyscallResult};
+use vfs_core::{
+    Dentry, DentryMeta, DirEntry, File, FileMeta, Inode, InodeMeta, InodeMode, Stat, SuperBlock,
+};
+
+use super::KernelProcIf;
+
+fn read_str(info: &str, offset: usize, buf: &mut [u8]) -> SyscallResult {
+    if offset >= info.len() {
+        return Ok(0);
+    }
+    let len = cmp::min(info.len() - offset, buf.len());
+    buf[..len].copy_from_slice(&info.as_bytes()[offset..offset + len]);
+    Ok(len)
+}
+
+/// /proc/syscall_stats, which lists the count, total and max cycles of each
+/// syscall, and is cleared by writing "reset".
+pub struct SyscallStatsDentry {
+    meta: DentryMeta,
+}
+
+impl SyscallStatsDentry {
+    pub fn new(super_block: Arc<dyn SuperBlock>, parent: Option<Arc<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("syscall_stats", super_block, parent),
+        })
+    }
+}
+
+impl Dentry for SyscallStatsDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(SyscallStatsFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+/// /proc/sys/kernel/syscall_stats_enable, where 1 means syscalls are counted.
+pub struct SyscallStatsEnableDentry {
+    meta: DentryMeta,
+}
+
+impl SyscallStatsEnableDentry {
+    pub fn new(super_block: Arc<dyn SuperBlock>, parent: Option<Arc<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("syscall_stats_enable", super_block, parent),
+        })
+    }
+}
+
+impl Dentry for SyscallStatsEnableDentry {
+    fn meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        Ok(Arc::new(SyscallStatsEnableFile {
+            meta: FileMeta::new(self.clone(), self.inode()?),
+        }))
+    }
+
+    fn base_lookup(self: Arc<Self>, _name: &str) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_create(self: Arc<Self>, _name: &str, _mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_unlink(self: Arc<Self>, _name: &str) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+}
+
+/// Inode of both /proc/syscall_stats and /proc/sys/kernel/syscall_stats_enable.
+pub struct SyscallStatsInode {
+    meta: InodeMeta,
+}
+
+impl SyscallStatsInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: InodeMeta::new(InodeMode::FILE, super_block, 0),
+        })
+    }
+}
+
+impl Inode for SyscallStatsInode {
+    fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0,
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
+
+pub struct SyscallStatsFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for SyscallStatsFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let info = call_interface!(KernelProcIf::syscall_stats());
+        read_str(&info, offset, buf)
+    }
+
+    async fn base_write_at(&self, _offset: usize, buf: &[u8]) -> SyscallResult {
+        match buf.strip_suffix(b"\n").unwrap_or(buf) {
+            b"reset" => call_interface!(KernelProcIf::reset_syscall_stats()),
+            _ => return Err(SysError::EINVAL),
+        }
+        Ok(buf.len())
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
+
+pub struct SyscallStatsEnableFile {
+    meta: FileMeta,
+}
+
+#[async_trait]
+impl File for SyscallStatsEnableFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallResult {
+        let enabled = call_interface!(KernelProcIf::syscall_stats_enabled());
+        let info = if enabled { "1\n" } else { "0\n" };
+        read_str(info, offset, buf)
+    }
+
+    async fn base_write_at(&self, _offset: usize, buf: &[u8]) -> SyscallResult {
+        let enabled = match buf.strip_suffix(b"\n").unwrap_or(buf) {
+            b"0" => false,
+            b"1" => true,
+            _ => return Err(SysError::EINVAL),
+        };
+        call_interface!(KernelProcIf::set_syscall_stats_enabled(enabled));
+        Ok(buf.len())
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn flush(&self) -> SysResult<usize> {
+        todo!()
+    }
+}
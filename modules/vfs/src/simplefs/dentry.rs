@@ -51,10 +51,14 @@ impl Dentry for SimpleDentry {
     fn base_create(self: Arc<Self>, name: &str, mode: InodeMode) -> SysResult<Arc<dyn Dentry>> {
         let sb = self.super_block();
         let sub_dentry = self.into_dyn().get_child_or_create(name);
+        if !matches!(mode.to_type(), InodeType::Dir | InodeType::File) {
+            return Err(SysError::EPERM);
+        }
+        // given back when the inode is dropped
+        sb.alloc_inode()?;
         let sub_inode: Arc<dyn Inode> = match mode.to_type() {
             InodeType::Dir => SimpleDirInode::new(mode, sb, 0),
-            InodeType::File => SimpleFileInode::new(mode, sb, 0),
-            _ => return Err(SysError::EPERM),
+            _ => SimpleFileInode::new(mode, sb, 0),
         };
         sub_dentry.set_inode(sub_inode);
         Ok(sub_dentry)
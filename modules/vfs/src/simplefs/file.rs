@@ -36,7 +36,7 @@ impl File for SimpleDirFile {
         todo!()
     }
 
-    fn base_load_dir(&self) -> SysResult<()> {
+    async fn base_load_dir(&self) -> SysResult<()> {
         Ok(())
     }
 
@@ -75,7 +75,7 @@ impl File for SimpleFileFile {
         Err(SysError::ENOTDIR)
     }
 
-    fn base_load_dir(&self) -> SysResult<()> {
+    async fn base_load_dir(&self) -> SysResult<()> {
         Err(SysError::ENOTDIR)
     }
 
@@ -100,17 +100,16 @@ impl File for SimpleFileFile {
         log::debug!("[File::read] read with address_space");
         while !buf_it.is_empty() && offset_it < self.size() {
             let (offset_aligned, offset_in_page) = align_offset_to_page(offset_it);
-            let page = if let Some(page) = page_cache.get_page(offset_aligned) {
-                page
-            } else {
-                // no page means EOF
-                break;
-            };
             let len = (buf_it.len())
                 .min(PAGE_SIZE - offset_in_page)
                 .min(self.size() - offset_it);
-            buf_it[0..len]
-                .copy_from_slice(page.bytes_array_range(offset_in_page..offset_in_page + len));
+            if let Some(page) = page_cache.get_page(offset_aligned) {
+                buf_it[0..len]
+                    .copy_from_slice(page.bytes_array_range(offset_in_page..offset_in_page + len));
+            } else {
+                // no page within size means a hole
+                buf_it[0..len].fill(0);
+            }
             log::trace!("[File::read] read count {len}, buf len {}", buf_it.len());
             offset_it += len;
             buf_it = &mut buf_it[len..];
@@ -129,10 +128,7 @@ impl File for SimpleFileFile {
         let inode = self.inode();
 
         let page_cache = inode.page_cache().unwrap();
-        if offset > self.size() {
-            todo!("offset greater than size, will create hole");
-        }
-
+        let sb = self.super_block();
         let mut buf_it = buf;
         let mut offset_it = offset;
 
@@ -142,7 +138,15 @@ impl File for SimpleFileFile {
                 page
             } else {
                 log::info!("[File::write_at] create new page");
+                if let Err(err) = sb.alloc_pages(1) {
+                    // short write when the filesystem gets full
+                    if offset_it == offset {
+                        return Err(err);
+                    }
+                    break;
+                }
                 let page = Page::new();
+                page.fill_zero();
                 page_cache.insert_page(offset_aligned, page.clone());
                 page
             };
@@ -161,7 +165,7 @@ impl File for SimpleFileFile {
             let new_size = offset_it;
             inode.set_size(new_size);
         }
-        Ok(buf.len())
+        Ok(offset_it - offset)
     }
 
     async fn get_page_at(&self, offset_aligned: usize) -> SysResult<Option<Arc<Page>>> {
@@ -169,11 +173,24 @@ impl File for SimpleFileFile {
         let page_cache = inode.page_cache().unwrap();
         if let Some(page) = page_cache.get_page(offset_aligned) {
             Ok(Some(page))
+        } else if offset_aligned < self.size() {
+            // fill the hole as it is going to be mapped
+            self.super_block().alloc_pages(1)?;
+            let page = Page::new();
+            page.fill_zero();
+            page_cache.insert_page(offset_aligned, page.clone());
+            Ok(Some(page))
         } else {
             // no page means EOF
             Ok(None)
         }
     }
+
+    async fn base_is_hole(&self, offset_aligned: usize) -> SysResult<bool> {
+        let inode = self.inode();
+        let page_cache = inode.page_cache().unwrap();
+        Ok(page_cache.get_page(offset_aligned).is_none())
+    }
 }
 
 pub struct SimpleLinkFile {
@@ -206,7 +223,7 @@ impl File for SimpleLinkFile {
         Err(SysError::ENOTDIR)
     }
 
-    fn base_load_dir(&self) -> SysResult<()> {
+    async fn base_load_dir(&self) -> SysResult<()> {
         Err(SysError::ENOTDIR)
     }
 
@@ -1,7 +1,8 @@
-use alloc::sync::Arc;
+use alloc::{boxed::Box, sync::Arc};
 
-use config::mm::{round_up_to_page, PAGE_SIZE};
-use page::{Page, PageCache};
+use async_trait::async_trait;
+use config::mm::{align_offset_to_page, round_up_to_page, PAGE_SIZE};
+use page::PageCache;
 use systype::SysResult;
 use vfs_core::{Inode, InodeMeta, InodeMode, InodeState, Stat, SuperBlock};
 
@@ -19,6 +20,18 @@ impl SimpleFileInode {
     }
 }
 
+/// Give back pages and the inode reserved in the super block, e.g. when a file
+/// in tmpfs is unlinked and closed.
+impl Drop for SimpleFileInode {
+    fn drop(&mut self) {
+        if let Some(sb) = self.meta.super_block.upgrade() {
+            sb.free_pages(self.meta.page_cache.as_ref().unwrap().len());
+            sb.free_inode();
+        }
+    }
+}
+
+#[async_trait]
 impl Inode for SimpleFileInode {
     fn meta(&self) -> &InodeMeta {
         &self.meta
@@ -26,15 +39,15 @@ impl Inode for SimpleFileInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -48,21 +61,27 @@ impl Inode for SimpleFileInode {
         })
     }
 
-    fn base_truncate(&self, len: usize) -> SysResult<()> {
+    async fn base_truncate(&self, len: usize) -> SysResult<()> {
+        let page_cache = self.meta().page_cache.as_ref().unwrap();
+        let sb = self.meta().super_block.upgrade().unwrap();
         if len == self.size() {
             return Ok(());
         } else if len < self.size() {
-            todo!()
-        } else {
-            let page_cache = self.meta().page_cache.as_ref().unwrap();
-            let offset_aligned_start = round_up_to_page(self.size());
-            for offset_aligned in (offset_aligned_start..len).step_by(PAGE_SIZE) {
-                let page = Page::new();
-                page.fill_zero();
-                page_cache.insert_page(offset_aligned, page.clone());
+            let freed = page_cache.truncate(round_up_to_page(len));
+            sb.free_pages(freed);
+            // The tail of the last page is read as zeros if the file grows again.
+            let (offset_aligned, offset_in_page) = align_offset_to_page(len);
+            if offset_in_page != 0 {
+                if let Some(page) = page_cache.get_page(offset_aligned) {
+                    page.bytes_array_range(offset_in_page..PAGE_SIZE).fill(0);
+                }
             }
             self.set_size(len);
             Ok(())
+        } else {
+            // Pages are allocated lazily, the gap stays a hole until written.
+            self.set_size(len);
+            Ok(())
         }
     }
 }
@@ -80,6 +99,14 @@ impl SimpleDirInode {
     }
 }
 
+impl Drop for SimpleDirInode {
+    fn drop(&mut self) {
+        if let Some(sb) = self.meta.super_block.upgrade() {
+            sb.free_inode();
+        }
+    }
+}
+
 impl Inode for SimpleDirInode {
     fn meta(&self) -> &InodeMeta {
         &self.meta
@@ -87,15 +114,15 @@ impl Inode for SimpleDirInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
@@ -130,15 +157,15 @@ impl Inode for SimpleLinkInode {
 
     fn get_attr(&self) -> SysResult<Stat> {
         let inner = self.meta.inner.lock();
-        let mode = self.meta.mode.bits();
+        let mode = inner.mode.bits();
         let len = inner.size;
         Ok(Stat {
             st_dev: 0,
             st_ino: self.meta.ino as u64,
             st_mode: mode,
             st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
             st_rdev: 0,
             __pad: 0,
             st_size: len as u64,
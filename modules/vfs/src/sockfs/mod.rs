@@ -6,6 +6,8 @@ use vfs_core::*;
 
 use crate::simplefs::{dentry::SimpleDentry, inode::SimpleDirInode};
 
+const SOCKFS_MAGIC: i64 = 0x534f434b;
+
 /// 参考https://zhuanlan.zhihu.com/p/497849394 【Linux内核 | socket底层的来龙去脉】
 pub struct SockFsType {
     meta: FileSystemTypeMeta,
@@ -30,6 +32,7 @@ impl FileSystemType for SockFsType {
         parent: Option<Arc<dyn Dentry>>,
         _flags: MountFlags,
         dev: Option<Arc<dyn BlockDevice>>,
+        _data: &str,
     ) -> SysResult<Arc<dyn Dentry>> {
         let sb = SockSuperBlock::new(dev, self.clone());
         let mount_dentry = SimpleDentry::new(name, sb.clone(), parent.clone());
@@ -69,8 +72,7 @@ impl SuperBlock for SockSuperBlock {
     }
 
     fn stat_fs(&self) -> SysResult<StatFs> {
-        // 应该是没有这个方法的？因为不涉及磁盘存储？
-        todo!()
+        Ok(StatFs::pseudo(SOCKFS_MAGIC))
     }
 
     fn sync_fs(&self, _wait: isize) -> SysResult<()> {
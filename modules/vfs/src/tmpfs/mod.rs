@@ -1,13 +1,24 @@
-use alloc::sync::Arc;
+use alloc::{format, sync::Arc};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+use config::mm::PAGE_SIZE;
 use device_core::BlockDevice;
-use systype::SysResult;
+use spin::Once;
+use systype::{SysError, SysResult};
 use vfs_core::{
-    Dentry, FileSystemType, FileSystemTypeMeta, InodeMode, MountFlags, StatFs, SuperBlock,
+    Dentry, File, FileSystemType, FileSystemTypeMeta, InodeMode, MountFlags, StatFs, SuperBlock,
     SuperBlockMeta,
 };
 
-use crate::simplefs::{dentry::SimpleDentry, inode::SimpleDirInode};
+use crate::{
+    simplefs::{
+        dentry::SimpleDentry,
+        inode::{SimpleDirInode, SimpleFileInode},
+    },
+    FS_MANAGER,
+};
+
+pub const TMPFS_MAGIC: i64 = 0x01021994;
 
 pub struct TmpFsType {
     meta: FileSystemTypeMeta,
@@ -32,8 +43,11 @@ impl FileSystemType for TmpFsType {
         parent: Option<Arc<dyn Dentry>>,
         _flags: MountFlags,
         dev: Option<Arc<dyn BlockDevice>>,
+        data: &str,
     ) -> SysResult<Arc<dyn Dentry>> {
-        let sb = TmpSuperBlock::new(dev, self.clone());
+        let options = TmpFsOptions::parse(data)?;
+        let sb = TmpSuperBlock::new(dev, self.clone(), options);
+        sb.alloc_inode()?;
         let mount_dentry = SimpleDentry::new(name, sb.clone(), parent.clone());
         let mount_inode = SimpleDirInode::new(InodeMode::DIR, sb.clone(), 0);
         mount_dentry.set_inode(mount_inode.clone());
@@ -49,31 +63,166 @@ impl FileSystemType for TmpFsType {
     }
 }
 
+/// Limits of a tmpfs instance given by mount options.
+#[derive(Debug, Clone, Copy)]
+pub struct TmpFsOptions {
+    /// Max pages of file data, set by `size=`.
+    pub max_pages: usize,
+    /// Max number of inodes, set by `nr_inodes=`.
+    pub max_inodes: usize,
+}
+
+impl TmpFsOptions {
+    /// Parse options like "size=1m,nr_inodes=1k". Both limits default to half
+    /// of the physical memory in pages, as Linux does, and zero means
+    /// unlimited. Options other than these two are ignored.
+    pub fn parse(data: &str) -> SysResult<Self> {
+        let default = memory::total_frames() / 2;
+        let mut options = Self {
+            max_pages: default,
+            max_inodes: default,
+        };
+        for option in data.split(',').filter(|s| !s.is_empty()) {
+            let (key, value) = option.split_once('=').unwrap_or((option, ""));
+            match key {
+                "size" => {
+                    let bytes = match value.strip_suffix('%') {
+                        Some(percent) => {
+                            let percent: usize = percent.parse().map_err(|_| SysError::EINVAL)?;
+                            memory::total_frames() * PAGE_SIZE / 100 * percent
+                        }
+                        None => parse_size(value)?,
+                    };
+                    options.max_pages = bytes.div_ceil(PAGE_SIZE);
+                }
+                "nr_inodes" => options.max_inodes = parse_size(value)?,
+                _ => log::warn!("[TmpFsOptions::parse] ignore option {option}"),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Parse a number with an optional suffix of k, m or g.
+fn parse_size(value: &str) -> SysResult<usize> {
+    let (num, shift) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+        Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let num = num.parse::<usize>().map_err(|_| SysError::EINVAL)?;
+    num.checked_shl(shift)
+        .filter(|n| n >> shift == num)
+        .ok_or(SysError::EINVAL)
+}
+
 pub struct TmpSuperBlock {
     meta: SuperBlockMeta,
+    options: TmpFsOptions,
+    /// Pages of file data in use.
+    pages: AtomicUsize,
+    /// Inodes in use.
+    inodes: AtomicUsize,
 }
 
 impl TmpSuperBlock {
     pub fn new(
         device: Option<Arc<dyn BlockDevice>>,
         fs_type: Arc<dyn FileSystemType>,
+        options: TmpFsOptions,
     ) -> Arc<Self> {
         Arc::new(Self {
             meta: SuperBlockMeta::new(device, fs_type),
+            options,
+            pages: AtomicUsize::new(0),
+            inodes: AtomicUsize::new(0),
         })
     }
 }
 
+/// Add `nr` to `used` if it stays within `max`, which is unlimited if zero.
+fn reserve(used: &AtomicUsize, max: usize, nr: usize) -> SysResult<()> {
+    used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+        used.checked_add(nr).filter(|&new| max == 0 || new <= max)
+    })
+    .map(|_| ())
+    .map_err(|_| SysError::ENOSPC)
+}
+
+fn unreserve(used: &AtomicUsize, nr: usize) {
+    let _ = used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+        Some(used.saturating_sub(nr))
+    });
+}
+
 impl SuperBlock for TmpSuperBlock {
     fn meta(&self) -> &SuperBlockMeta {
         &self.meta
     }
 
     fn stat_fs(&self) -> SysResult<StatFs> {
-        todo!()
+        let pages = self.pages.load(Ordering::Relaxed);
+        let inodes = self.inodes.load(Ordering::Relaxed);
+        let (max_pages, max_inodes) = (self.options.max_pages, self.options.max_inodes);
+        Ok(StatFs {
+            f_type: TMPFS_MAGIC,
+            f_bsize: PAGE_SIZE as i64,
+            f_blocks: max_pages as u64,
+            f_bfree: max_pages.saturating_sub(pages) as u64,
+            f_bavail: max_pages.saturating_sub(pages) as u64,
+            f_files: max_inodes as u64,
+            f_ffree: max_inodes.saturating_sub(inodes) as u64,
+            f_fsid: [0; 2],
+            f_namelen: 255,
+            f_frsize: PAGE_SIZE as isize,
+            f_flags: 0,
+            f_spare: [0; 4],
+        })
     }
 
     fn sync_fs(&self, _wait: isize) -> SysResult<()> {
         todo!()
     }
+
+    fn alloc_pages(&self, nr: usize) -> SysResult<()> {
+        reserve(&self.pages, self.options.max_pages, nr)
+    }
+
+    fn free_pages(&self, nr: usize) {
+        unreserve(&self.pages, nr)
+    }
+
+    fn alloc_inode(&self) -> SysResult<()> {
+        reserve(&self.inodes, self.options.max_inodes, 1)
+    }
+
+    fn free_inode(&self) {
+        unreserve(&self.inodes, 1)
+    }
+}
+
+/// Root of the tmpfs instance holding files created by memfd_create, which is
+/// not mounted anywhere.
+static MEMFD_ROOT: Once<Arc<dyn Dentry>> = Once::new();
+
+/// Create an anonymous file named "memfd:`name`" in memory, which is never
+/// linked into a directory, so it is gone once the last reference is closed.
+pub fn memfd_create(name: &str) -> SysResult<Arc<dyn File>> {
+    let root = MEMFD_ROOT.call_once(|| {
+        let fs_type = FS_MANAGER.lock().get("tmpfs").unwrap().clone();
+        let options = TmpFsOptions::parse("").unwrap();
+        let sb = TmpSuperBlock::new(None, fs_type, options);
+        let root = SimpleDentry::new("/", sb.clone(), None);
+        root.set_inode(SimpleDirInode::new(InodeMode::DIR, sb, 0));
+        root.into_dyn()
+    });
+    let sb = root.super_block();
+    sb.alloc_inode()?;
+    let mode =
+        InodeMode::FILE | InodeMode::OWNER_MASK | InodeMode::GROUP_MASK | InodeMode::OTHER_MASK;
+    let dentry =
+        SimpleDentry::new(&format!("memfd:{name}"), sb.clone(), Some(root.clone())).into_dyn();
+    dentry.set_inode(SimpleFileInode::new(mode, sb, 0));
+    dentry.open()
 }
@@ -0,0 +1,56 @@
+//! Auxiliary vector passed by the kernel on the initial stack, following the
+//! null terminator of envp.
+
+/// Address of the program headers.
+pub const AT_PHDR: usize = 3;
+/// Size of a program header.
+pub const AT_PHENT: usize = 4;
+/// Number of program headers.
+pub const AT_PHNUM: usize = 5;
+/// Page size.
+pub const AT_PAGESZ: usize = 6;
+/// Base address of the interpreter.
+pub const AT_BASE: usize = 7;
+/// Entry point of the program.
+pub const AT_ENTRY: usize = 9;
+/// Real uid.
+pub const AT_UID: usize = 11;
+/// Effective uid.
+pub const AT_EUID: usize = 12;
+/// Real gid.
+pub const AT_GID: usize = 13;
+/// Effective gid.
+pub const AT_EGID: usize = 14;
+/// Address of the string identifying the platform.
+pub const AT_PLATFORM: usize = 15;
+/// ISA extensions of the CPU.
+pub const AT_HWCAP: usize = 16;
+/// Frequency of times().
+pub const AT_CLKTCK: usize = 17;
+/// Whether the program runs with changed ids.
+pub const AT_SECURE: usize = 23;
+/// Address of 16 random bytes.
+pub const AT_RANDOM: usize = 25;
+/// Address of the filename of the program.
+pub const AT_EXECFN: usize = 31;
+/// Address of the elf header of the vDSO.
+pub const AT_SYSINFO_EHDR: usize = 33;
+
+static mut AUXV: *const [usize; 2] = core::ptr::null();
+
+pub(crate) unsafe fn init(auxv: *const usize) {
+    AUXV = auxv.cast();
+}
+
+/// Value of the entry of `aux_type`, like getauxval(3) of glibc.
+pub fn getauxval(aux_type: usize) -> Option<usize> {
+    let auxv = unsafe { AUXV };
+    if auxv.is_null() {
+        return None;
+    }
+    (0..)
+        .map(|i| unsafe { auxv.add(i).read_volatile() })
+        .take_while(|&[ty, _]| ty != 0)
+        .find(|&[ty, _]| ty == aux_type)
+        .map(|[_, value]| value)
+}
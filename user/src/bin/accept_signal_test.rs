@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::time::Duration;
+
+use user_lib::*;
+
+const PORT: u16 = 5558;
+
+fn now() -> Duration {
+    let mut ts = TimeSpec::default();
+    clock_gettime(CLOCK_MONOTONIC, &mut ts);
+    ts.into()
+}
+
+fn on_usr1(_signal: usize) {}
+
+/// Listen on `PORT` and block in accept(2), which is never connected to.
+/// Exit with 0 if it is interrupted by a signal with EINTR.
+fn block_in_accept() -> i32 {
+    let mut act = SigAction::default();
+    let mut old = SigAction::default();
+    act.sa_handler = on_usr1 as usize;
+    sigaction(Sig::SIGUSR1, &act, &mut old);
+    let addr = SockAddr::In(SockAddrIn::new([127, 0, 0, 1], PORT));
+    let Ok(sockfd) = socket(SaFamily::Inet, SocketType::Stream, SocketFlags::empty()) else {
+        return -1;
+    };
+    if bind(sockfd, &addr).is_err() || listen(sockfd, 1).is_err() {
+        return -1;
+    }
+    match accept(sockfd) {
+        Err(SyscallErr::EINTR) => 0,
+        _ => -1,
+    }
+}
+
+/// Fork a child blocking in accept, send it `sig` once it blocks, and return
+/// its exit status and the time it takes to exit after the signal.
+fn signal_accept(sig: Sig) -> (ExitStatus, Duration) {
+    let pid = fork();
+    if pid == 0 {
+        exit(block_in_accept());
+    }
+    sleep(100);
+    let begin = now();
+    kill(pid, sig);
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    (ExitStatus(wstatus), now() - begin)
+}
+
+/// A task blocking in accept(2) must be killed by SIGKILL, and interrupted by
+/// a caught signal with EINTR, at once rather than when a connection arrives.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("accept signal");
+
+    let (status, elapsed) = signal_accept(Sig::SIGKILL);
+    result.check(
+        "accept killed by SIGKILL",
+        status.signal() == Some(Sig::SIGKILL.raw() as i32),
+    );
+    result.check("time to kill accept", elapsed < Duration::from_secs(1));
+
+    let (status, elapsed) = signal_accept(Sig::SIGUSR1);
+    result.check("accept interrupted by SIGUSR1", status.success());
+    result.check("time to interrupt accept", elapsed < Duration::from_secs(1));
+
+    result.finish()
+}
@@ -0,0 +1,111 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use user_lib::*;
+
+/// Hart to pin on, which is skipped if it is not online.
+const TARGET_HART: u32 = 1;
+/// Time the threads spin for, in ms.
+const SPIN_MS: usize = 500;
+
+static SPINS: AtomicUsize = AtomicUsize::new(0);
+/// Times a pinned thread finds itself on another hart.
+static MISPLACED: AtomicUsize = AtomicUsize::new(0);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Spin on `TARGET_HART`, sleeping once in a while to be woken up again, and
+/// count the times running on another hart.
+fn spin() {
+    if sched_setaffinity(0, 1 << TARGET_HART) != 0 {
+        MISPLACED.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    while !STOP.load(Ordering::Relaxed) {
+        let mut cpu = u32::MAX;
+        getcpu(Some(&mut cpu), None);
+        if cpu != TARGET_HART {
+            MISPLACED.fetch_add(1, Ordering::Relaxed);
+        }
+        if SPINS.fetch_add(1, Ordering::Relaxed) % 1000 == 0 {
+            sleep(1);
+        }
+    }
+}
+
+/// Fork a child of another user, which must not be allowed to change the
+/// affinity of this process.
+fn pin_without_permission(online: usize) -> bool {
+    let parent = getpid() as usize;
+    let pid = fork();
+    if pid == 0 {
+        let denied = setuid(1000) == 0
+            && matches!(
+                SyscallErr::from_ret(sched_setaffinity(parent, online)),
+                Err(SyscallErr::EPERM)
+            );
+        exit(if denied { 0 } else { -1 });
+    }
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus) == pid && wstatus == 0
+}
+
+/// Two threads pinned to one hart compete for it while the other harts are
+/// idle and looking for tasks to steal, and must never run elsewhere.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin affinity test");
+    let mut online = 0;
+    if sched_getaffinity(0, &mut online) < 0 {
+        println!("sched_getaffinity failed");
+        return -1;
+    }
+    if online & (1 << TARGET_HART) == 0 {
+        println!("hart {} is offline, skipped", TARGET_HART);
+        return 0;
+    }
+
+    let offline = !online;
+    if !matches!(
+        SyscallErr::from_ret(sched_setaffinity(0, offline)),
+        Err(SyscallErr::EINVAL)
+    ) {
+        println!("sched_setaffinity to offline harts does not fail with EINVAL");
+        return -1;
+    }
+    let mut mask = 0;
+    sched_setaffinity(0, 1 << TARGET_HART | offline);
+    sched_getaffinity(0, &mut mask);
+    sched_setaffinity(0, online);
+    if mask != 1 << TARGET_HART {
+        println!("effective mask is {:#x}, not only the online harts", mask);
+        return -1;
+    }
+    if !pin_without_permission(online) {
+        println!("sched_setaffinity of another user's process does not fail with EPERM");
+        return -1;
+    }
+
+    let first = thread::spawn(spin).unwrap();
+    let second = thread::spawn(spin).unwrap();
+    sleep(SPIN_MS);
+    STOP.store(true, Ordering::Relaxed);
+    first.join();
+    second.join();
+
+    let misplaced = MISPLACED.load(Ordering::Relaxed);
+    println!(
+        "{} of {} spins on other harts",
+        misplaced,
+        SPINS.load(Ordering::Relaxed)
+    );
+    if misplaced != 0 {
+        println!("affinity test failed");
+        return -1;
+    }
+    println!("affinity test passed");
+    0
+}
@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::vec;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const PROCS: usize = 3;
+/// Pages each process writes with its own id, at the same addresses in all of
+/// them.
+const PAGES: usize = 16;
+const ROUNDS: usize = 200;
+/// Short-lived processes created per round by the first process, so that
+/// ASIDs keep being handed out.
+const CHURN: usize = 4;
+/// Hart all processes are pinned on, so that they share one TLB.
+const HART: usize = 0;
+
+/// Take the token from `rx`, check and rewrite the pages, then pass the token
+/// to `tx`, for `ROUNDS` rounds.
+fn run(id: usize, buf: &mut [u8], rx: usize, tx: usize) -> i32 {
+    let mut token = [0u8; 1];
+    for round in 0..ROUNDS {
+        if read(rx, &mut token) != 1 {
+            println!("process {} lost the token in round {}", id, round);
+            return -1;
+        }
+        for (page, chunk) in buf.chunks_mut(PAGE_SIZE).enumerate() {
+            if round > 0 && chunk.iter().any(|&b| b != id as u8) {
+                println!(
+                    "process {} reads {} on page {} in round {}",
+                    id, chunk[0], page, round
+                );
+                return -1;
+            }
+            chunk.fill(id as u8);
+        }
+        if id == 0 {
+            for _ in 0..CHURN {
+                let pid = fork();
+                if pid == 0 {
+                    exit(0);
+                }
+                let mut wstatus = 0;
+                waitpid(pid as usize, &mut wstatus);
+            }
+        }
+        write(tx, &token);
+    }
+    0
+}
+
+/// Round-robin three processes on one hart, each writing its id to the same
+/// virtual pages, which are backed by distinct frames after copy-on-write.
+/// Any process seeing an id other than its own has used a translation of
+/// another process left in the TLB, e.g. when an ASID is reused.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin asid test");
+    let mut online = 0;
+    if sched_getaffinity(0, &mut online) < 0 {
+        println!("sched_getaffinity failed");
+        return -1;
+    }
+    if online & (1 << HART) == 0 {
+        println!("hart {} is offline, skipped", HART);
+        return 0;
+    }
+    sched_setaffinity(0, 1 << HART);
+
+    let mut buf = vec![0u8; PAGES * PAGE_SIZE];
+    // pipes[i] passes the token from process i to process i + 1
+    let mut pipes = [[0i32; 2]; PROCS];
+    for fds in pipes.iter_mut() {
+        if pipe(fds) != 0 {
+            println!("pipe failed");
+            return -1;
+        }
+    }
+    let mut pids = [0; PROCS];
+    for (id, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            let rx = pipes[(id + PROCS - 1) % PROCS][0] as usize;
+            let tx = pipes[id][1] as usize;
+            exit(run(id, &mut buf, rx, tx));
+        }
+    }
+    // the first process takes the token from the last one
+    write(pipes[PROCS - 1][1] as usize, &[0]);
+
+    let mut passed = true;
+    for pid in pids {
+        let mut wstatus = 0;
+        waitpid(pid as usize, &mut wstatus);
+        passed &= wstatus == 0;
+    }
+    sched_setaffinity(0, online);
+    if !passed {
+        return -1;
+    }
+    println!("asid test passed");
+    0
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::{auxv::*, *};
+
+/// Type of loadable program headers.
+const PT_LOAD: u32 = 1;
+
+/// Borrow the null terminated string at `addr`.
+fn c_str(addr: usize) -> &'static [u8] {
+    let len = (0..)
+        .find(|&i| unsafe { ((addr + i) as *const u8).read() } == 0)
+        .unwrap();
+    unsafe { core::slice::from_raw_parts(addr as *const u8, len) }
+}
+
+/// Check the entries of auxv, which glibc relies on when starting up.
+#[no_mangle]
+fn main(_argc: usize, argv: &[&str]) -> i32 {
+    let mut result = TestResult::begin("auxv");
+
+    result.check("AT_PAGESZ", getauxval(AT_PAGESZ) == Some(4096));
+    result.check("AT_CLKTCK", getauxval(AT_CLKTCK) == Some(100));
+    result.check("AT_SECURE", getauxval(AT_SECURE) == Some(0));
+    result.check(
+        "AT_UID",
+        getauxval(AT_UID).is_some() && getauxval(AT_UID) == getauxval(AT_EUID),
+    );
+    result.check("AT_HWCAP", getauxval(AT_HWCAP).is_some());
+
+    let execfn = getauxval(AT_EXECFN).map(c_str);
+    result.check("AT_EXECFN", execfn == Some(argv[0].as_bytes()));
+    let platform = getauxval(AT_PLATFORM).map(c_str);
+    result.check("AT_PLATFORM", platform == Some(b"riscv64".as_slice()));
+
+    let random = getauxval(AT_RANDOM)
+        .map(|addr| unsafe { core::slice::from_raw_parts(addr as *const u8, 16) });
+    result.check(
+        "AT_RANDOM",
+        random.is_some_and(|bytes| bytes.iter().any(|&b| b != 0)),
+    );
+
+    // AT_PHDR should point at the program headers mapped in memory.
+    let phdr_ok = match (getauxval(AT_PHDR), getauxval(AT_PHENT), getauxval(AT_PHNUM)) {
+        (Some(phdr), Some(56), Some(phnum)) if phdr != 0 => {
+            (0..phnum).any(|i| unsafe { ((phdr + i * 56) as *const u32).read() } == PT_LOAD)
+        }
+        _ => false,
+    };
+    result.check("AT_PHDR", phdr_ok);
+
+    result.finish()
+}
@@ -0,0 +1,207 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::{string::String, vec};
+use core::{slice, str};
+
+use user_lib::*;
+
+/// Where the kernel image starts, which must never be accessible by syscalls.
+const KERNEL_ADDR: usize = 0xffff_ffc0_8020_0000;
+/// An address in user space which is never mapped.
+const UNMAPPED_ADDR: usize = 0x3f_0000_0000;
+/// The end of user space.
+const U_SEG_END: usize = 0x40_0000_0000;
+
+const PAGE_SIZE: usize = 0x1000;
+const PATH_MAX: usize = 4096;
+const MAX_ARG_STRLEN: usize = 32 * PAGE_SIZE;
+
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+/// Read the time of /dev/rtc, see <linux/rtc.h>.
+const RTC_RD_TIME: usize = 0x80247009;
+
+/// Forge a slice at `addr`, which is only passed to syscalls.
+fn bytes<'a>(addr: usize, len: usize) -> &'a mut [u8] {
+    unsafe { slice::from_raw_parts_mut(addr as *mut u8, len) }
+}
+
+/// Forge a string at `addr`, which is only passed to syscalls.
+fn path<'a>(addr: usize) -> &'a str {
+    unsafe { str::from_utf8_unchecked(bytes(addr, 1)) }
+}
+
+/// Forge a reference at `addr`, which is only passed to syscalls.
+fn obj<'a, T>(addr: usize) -> &'a mut T {
+    unsafe { &mut *(addr as *mut T) }
+}
+
+fn check(name: &str, ret: isize, err: SyscallErr) -> bool {
+    if ret != -(err as isize) {
+        println!("{} returns {}, expected {:?}", name, ret, err);
+        return false;
+    }
+    true
+}
+
+/// Pass pointers to kernel memory, to unmapped memory, and to buffers which
+/// run off the end of a mapping to syscalls, which should fail with `EFAULT`
+/// rather than take the kernel down. Strings longer than the limits should
+/// fail with `ENAMETOOLONG` or `E2BIG`.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin bad ptr test");
+    let mut fds = [0; 2];
+    if pipe(&mut fds) != 0 {
+        println!("pipe failed");
+        return -1;
+    }
+    let (rfd, wfd) = (fds[0] as usize, fds[1] as usize);
+    let rtc = openat("/dev/rtc\0", OpenFlags::O_RDONLY);
+    if rtc < 0 {
+        println!("can not open /dev/rtc");
+        return -1;
+    }
+    // allocated ahead, so that the heap does not grow into the hole below
+    let mut long_path = vec![b'a'; PATH_MAX + PAGE_SIZE];
+    long_path[0] = b'/';
+    let long_path = String::from_utf8(long_path).unwrap();
+    let long_arg = String::from_utf8(vec![b'a'; MAX_ARG_STRLEN]).unwrap();
+
+    // a page followed by a hole
+    let page = mmap(
+        core::ptr::null(),
+        2 * PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    if page < 0 {
+        println!("mmap failed");
+        return -1;
+    }
+    let page = page as usize;
+    munmap((page + PAGE_SIZE) as *const u8, PAGE_SIZE);
+    let straddle = page + PAGE_SIZE - 8;
+    // not null terminated within the page
+    bytes(straddle, 8).fill(b'a');
+    let mut buf = [0u8; 16];
+    let mut ts = TimeSpec::default();
+
+    let checks = [
+        (
+            "write kernel buf",
+            write(wfd, bytes(KERNEL_ADDR, 16)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "read kernel buf",
+            read(rfd, bytes(KERNEL_ADDR, 16)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "write straddling buf",
+            write(wfd, bytes(straddle, 16)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "read straddling buf",
+            read(rfd, bytes(straddle, 16)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "write buf beyond user space",
+            write(wfd, bytes(buf.as_ptr() as usize, U_SEG_END)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "openat kernel path",
+            openat(path(KERNEL_ADDR), OpenFlags::O_RDONLY),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "openat unmapped path",
+            openat(path(UNMAPPED_ADDR), OpenFlags::O_RDONLY),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "openat long path",
+            openat(&long_path, OpenFlags::O_RDONLY),
+            SyscallErr::ENAMETOOLONG,
+        ),
+        (
+            "mkdir straddling path",
+            mkdir(path(straddle + 7), 0o755),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "uname kernel buf",
+            uname(obj(KERNEL_ADDR)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "pipe unmapped fds",
+            pipe(obj(UNMAPPED_ADDR)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "clock_gettime kernel buf",
+            clock_gettime(CLOCK_MONOTONIC, obj(KERNEL_ADDR)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "gettimeofday unmapped buf",
+            gettimeofday(obj(UNMAPPED_ADDR)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "nanosleep kernel req",
+            nanosleep(obj(KERNEL_ADDR), &mut ts),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "sigaction kernel act",
+            sigaction(Sig::SIGUSR1, obj(KERNEL_ADDR), obj(UNMAPPED_ADDR)),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "RTC_RD_TIME kernel buf",
+            ioctl(rtc as usize, RTC_RD_TIME, KERNEL_ADDR),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "RTC_RD_TIME unmapped buf",
+            ioctl(rtc as usize, RTC_RD_TIME, UNMAPPED_ADDR),
+            SyscallErr::EFAULT,
+        ),
+        (
+            "execve long arg",
+            execve("/no_such_program", &["bad_ptr_test", &long_arg], &[]),
+            SyscallErr::E2BIG,
+        ),
+    ];
+    let mut passed = true;
+    for (name, ret, err) in checks {
+        passed &= check(name, ret, err);
+    }
+    close(rtc as usize);
+
+    // the kernel and the pipe still work
+    if write(wfd, b"alive") != 5 || read(rfd, &mut buf) != 5 || &buf[..5] != b"alive" {
+        println!("pipe is broken");
+        return -1;
+    }
+    if !passed {
+        return -1;
+    }
+    println!("bad ptr test passed");
+    0
+}
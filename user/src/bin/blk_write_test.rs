@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::time::Duration;
+
+use user_lib::*;
+
+const FILE: &str = "/blk_write_test\0";
+/// Bytes written by the child, enough to keep the disk busy for a while.
+const BYTES: usize = 8 << 20;
+/// Longest a 10ms sleep may take while the disk is busy.
+const MAX_LATENCY: Duration = Duration::from_millis(200);
+
+fn now() -> Duration {
+    let mut ts = TimeSpec::default();
+    clock_gettime(CLOCK_MONOTONIC, &mut ts);
+    ts.into()
+}
+
+/// Write `BYTES` to a file and sync it, in a child, while checking that this
+/// process is still woken on time from short sleeps, since a task waiting for
+/// the disk must not hold up the others on its hart.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin blk write test");
+    let mut pipe_fd = [0i32; 2];
+    pipe(&mut pipe_fd);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0] as usize);
+        let fd = openat(FILE, OpenFlags::O_CREATE | OpenFlags::O_WRONLY);
+        let chunk = [0x5au8; 4096];
+        let mut written = 0;
+        while fd >= 0 && written < BYTES {
+            if write(fd as usize, &chunk) != chunk.len() as isize {
+                break;
+            }
+            written += chunk.len();
+        }
+        close(fd as usize);
+        sync();
+        write(pipe_fd[1] as usize, b"x");
+        exit(if written == BYTES { 0 } else { -1 });
+    }
+    close(pipe_fd[1] as usize);
+
+    let mut longest = Duration::ZERO;
+    let mut sleeps = 0;
+    loop {
+        let mut fds = [PollFd {
+            fd: pipe_fd[0],
+            events: POLLIN,
+            revents: 0,
+        }];
+        if ppoll(&mut fds, 0) != Ok(0) {
+            break;
+        }
+        let begin = now();
+        sleep(10);
+        longest = longest.max(now() - begin);
+        sleeps += 1;
+    }
+    close(pipe_fd[0] as usize);
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    unlink(FILE);
+    println!(
+        "{} sleeps while writing, the longest takes {:?}",
+        sleeps, longest
+    );
+    if !ExitStatus(wstatus).success() {
+        println!("can not write the file");
+        return -1;
+    }
+    if longest > MAX_LATENCY {
+        println!("blk write test failed");
+        return -1;
+    }
+    println!("blk write test passed");
+    0
+}
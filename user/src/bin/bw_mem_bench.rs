@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::ptr;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+const SIZE: usize = 16 * 1024 * 1024;
+const ROUNDS: usize = 8;
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+const MADV_HUGEPAGE: usize = 14;
+
+fn now_usec() -> usize {
+    let mut time_val = TimeVal::from_usec(0);
+    gettimeofday(&mut time_val);
+    time_val.into_usec()
+}
+
+/// Read `thp_fault_alloc` and `thp_split_page` from /proc/vmstat.
+fn thp_stat() -> Option<(usize, usize)> {
+    let fd = openat("/proc/vmstat\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 512];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let text = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let field = |name: &str| -> Option<usize> {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name)?.trim().parse().ok())
+    };
+    Some((field("thp_fault_alloc ")?, field("thp_split_page ")?))
+}
+
+/// Map `SIZE` bytes of anonymous memory aligned to a huge page, which is
+/// faulted in by huge pages if `huge`.
+fn map_buffer(huge: bool) -> Option<*mut usize> {
+    let len = SIZE + HUGE_PAGE_SIZE;
+    let addr = mmap(
+        ptr::null(),
+        len,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    if addr < 0 {
+        return None;
+    }
+    let start = (addr as usize + HUGE_PAGE_SIZE - 1) & !(HUGE_PAGE_SIZE - 1);
+    // leave only the aligned part mapped
+    munmap(addr as *const u8, start - addr as usize);
+    munmap(
+        (start + SIZE) as *const u8,
+        addr as usize + len - start - SIZE,
+    );
+    if huge {
+        madvise(start as *const u8, SIZE, MADV_HUGEPAGE);
+    }
+    Some(start as *mut usize)
+}
+
+/// Write every word of the buffer, then read it `ROUNDS` times as bw_mem rd
+/// does, and touch one word of each page `ROUNDS` times, which misses the TLB
+/// on every access unless the pages are huge. Return the read bandwidth in
+/// MiB/s and the time of a page-strided access in nanoseconds.
+fn bench(buf: *mut usize) -> (usize, usize) {
+    let words = SIZE / core::mem::size_of::<usize>();
+    for i in 0..words {
+        unsafe { buf.add(i).write_volatile(i) };
+    }
+
+    let begin = now_usec();
+    let mut sum = 0usize;
+    for _ in 0..ROUNDS {
+        for i in 0..words {
+            sum = sum.wrapping_add(unsafe { buf.add(i).read_volatile() });
+        }
+    }
+    let usec = (now_usec() - begin).max(1);
+    let bandwidth = SIZE * ROUNDS / usec * 1_000_000 / (1024 * 1024);
+
+    let stride = PAGE_SIZE / core::mem::size_of::<usize>();
+    let begin = now_usec();
+    for _ in 0..ROUNDS {
+        for i in (0..words).step_by(stride) {
+            sum = sum.wrapping_add(unsafe { buf.add(i).read_volatile() });
+        }
+    }
+    let usec = now_usec() - begin;
+    let latency = usec * 1000 / (ROUNDS * SIZE / PAGE_SIZE);
+    // keep the reads from being optimized out
+    core::hint::black_box(sum);
+    (bandwidth, latency)
+}
+
+/// Compare the memory bandwidth of base pages and huge pages, the latter of
+/// which are taken after madvise(MADV_HUGEPAGE).
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin bw_mem bench");
+    for huge in [false, true] {
+        let (allocs, splits) = thp_stat().unwrap_or_default();
+        let Some(buf) = map_buffer(huge) else {
+            println!("mmap {} bytes failed", SIZE);
+            return -1;
+        };
+        let (bandwidth, latency) = bench(buf);
+        let (new_allocs, new_splits) = thp_stat().unwrap_or_default();
+        munmap(buf as *const u8, SIZE);
+        println!(
+            "{} pages: rd {} MiB/s, page stride {} ns, {} huge pages faulted in, {} split",
+            if huge { "huge" } else { "base" },
+            bandwidth,
+            latency,
+            new_allocs - allocs,
+            new_splits - splits
+        );
+    }
+    0
+}
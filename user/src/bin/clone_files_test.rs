@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Whether `fd` is open in the current process.
+fn is_open(fd: usize) -> bool {
+    let new = dup(fd);
+    if new < 0 {
+        return false;
+    }
+    close(new as usize);
+    true
+}
+
+/// Run `f` in a child created with `flags` and wait for it.
+fn in_child(flags: CloneFlags, f: impl FnOnce()) -> bool {
+    let pid = fork_with(flags);
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut wstatus = 0;
+    pid > 0 && waitpid(pid as usize, &mut wstatus) > 0 && ExitStatus(wstatus).success()
+}
+
+/// Check the fd table and the umask are shared with the child only if
+/// `CLONE_FILES` and `CLONE_FS` are given, or until unshare(2).
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("clone files");
+
+    let fd = openat("/dev/null\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        println!("open /dev/null failed: {}", fd);
+        return -1;
+    }
+    let fd = fd as usize;
+
+    result.check(
+        "fork",
+        in_child(CloneFlags::empty(), || {
+            close(fd);
+        }),
+    );
+    result.check("close in a forked child", is_open(fd));
+
+    result.check(
+        "unshare",
+        in_child(CloneFlags::FILES, || {
+            unshare(CloneFlags::FILES);
+            close(fd);
+        }),
+    );
+    result.check("close after unshare", is_open(fd));
+
+    result.check(
+        "CLONE_FILES",
+        in_child(CloneFlags::FILES, || {
+            close(fd);
+        }),
+    );
+    result.check("close in a CLONE_FILES child", !is_open(fd));
+
+    let old = umask(0o022);
+    result.check(
+        "CLONE_FS",
+        in_child(CloneFlags::FS, || {
+            umask(0o077);
+        }),
+    );
+    result.check("umask set by a CLONE_FS child", umask(0o022) == 0o077);
+    result.check(
+        "fork",
+        in_child(CloneFlags::empty(), || {
+            umask(0o077);
+        }),
+    );
+    result.check("umask set by a forked child", umask(old as usize) == 0o022);
+
+    result.finish()
+}
@@ -0,0 +1,210 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::{format, vec, vec::Vec};
+use core::convert::TryInto;
+
+use user_lib::*;
+
+const LIMIT: usize = 1024 * 1024;
+const MAGIC: u64 = 0x706d_7564_6572_6f63;
+
+/// Written by the child before it crashes, to be found in its core.
+static mut DATA: u64 = 0;
+
+fn u16_at(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+/// Fork a child which crashes by `f`, and return its pid and wait status.
+fn crash(f: fn()) -> (isize, ExitStatus) {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    (pid, ExitStatus(wstatus))
+}
+
+fn segfault() {
+    unsafe {
+        core::ptr::write_volatile(core::ptr::addr_of_mut!(DATA), MAGIC);
+        core::ptr::write_volatile(core::ptr::null_mut::<u8>(), 0);
+    }
+}
+
+fn abort() {
+    kill(getpid(), Sig::SIGABRT);
+}
+
+fn core_path(pid: isize) -> alloc::string::String {
+    format!("core.{}\0", pid)
+}
+
+fn read_core(pid: isize) -> Option<Vec<u8>> {
+    let fd = openat(&core_path(pid), OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let size = lseek(fd, 0, SEEK_END);
+    lseek(fd, 0, SEEK_SET);
+    let mut buf = vec![0u8; size.max(0) as usize];
+    let n = read(fd, &mut buf);
+    close(fd);
+    (n == size).then_some(buf)
+}
+
+/// Check the core of the child `pid` crashed by SIGSEGV: the ELF header, the
+/// notes, and the data written before the crash.
+fn check_core(pid: isize, core: &[u8]) -> Result<(), &'static str> {
+    const ET_CORE: u16 = 4;
+    const EM_RISCV: u16 = 243;
+    const PT_LOAD: u32 = 1;
+    const PT_NOTE: u32 = 4;
+    const NT_PRSTATUS: u32 = 1;
+    const NT_PRPSINFO: u32 = 3;
+
+    if core.len() < 64 || &core[..4] != b"\x7fELF" || core[4] != 2 {
+        return Err("ELF header");
+    }
+    if u16_at(core, 16) != ET_CORE || u16_at(core, 18) != EM_RISCV {
+        return Err("ELF type or machine");
+    }
+    if core.len() > LIMIT {
+        return Err("size over the limit");
+    }
+    let phoff = u64_at(core, 32) as usize;
+    let phnum = u16_at(core, 56) as usize;
+    let phdrs: Vec<&[u8]> = (0..phnum)
+        .map(|i| &core[phoff + i * 56..phoff + (i + 1) * 56])
+        .collect();
+
+    let note = phdrs
+        .iter()
+        .find(|ph| u32_at(ph, 0) == PT_NOTE)
+        .ok_or("no PT_NOTE")?;
+    let mut off = u64_at(note, 8) as usize;
+    let end = off + u64_at(note, 32) as usize;
+    let (mut status, mut psinfo) = (None, None);
+    while off < end {
+        let namesz = u32_at(core, off) as usize;
+        let descsz = u32_at(core, off + 4) as usize;
+        let ntype = u32_at(core, off + 8);
+        let desc = off + 12 + namesz.next_multiple_of(4);
+        match ntype {
+            NT_PRSTATUS => status = Some(&core[desc..desc + descsz]),
+            NT_PRPSINFO => psinfo = Some(&core[desc..desc + descsz]),
+            _ => {}
+        }
+        off = desc + descsz.next_multiple_of(4);
+    }
+    let status = status.ok_or("no NT_PRSTATUS")?;
+    if u16_at(status, 12) as usize != Sig::SIGSEGV.raw() || u32_at(status, 32) != pid as u32 {
+        return Err("NT_PRSTATUS");
+    }
+    let psinfo = psinfo.ok_or("no NT_PRPSINFO")?;
+    if !psinfo[40..56].starts_with(b"coredump_test\0") {
+        return Err("NT_PRPSINFO");
+    }
+
+    let addr = unsafe { core::ptr::addr_of!(DATA) } as u64;
+    let load = phdrs
+        .iter()
+        .find(|ph| {
+            let vaddr = u64_at(ph, 16);
+            u32_at(ph, 0) == PT_LOAD && vaddr <= addr && addr + 8 <= vaddr + u64_at(ph, 32)
+        })
+        .ok_or("no PT_LOAD of the data")?;
+    let off = (u64_at(load, 8) + addr - u64_at(load, 16)) as usize;
+    if u64_at(core, off) != MAGIC {
+        return Err("data in PT_LOAD");
+    }
+    Ok(())
+}
+
+/// Check core dumps of processes terminated by signals, which are limited by
+/// `RLIMIT_CORE`.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("coredump");
+    mkdir("/coredump_test\0", 0o755);
+    if mount("tmpfs\0", "/coredump_test\0", "tmpfs\0", 0, "size=4m\0") != 0 {
+        println!("mount tmpfs failed");
+        return -1;
+    }
+    chdir("/coredump_test\0");
+
+    let mut limit = RLimit {
+        rlim_cur: 1,
+        rlim_max: 0,
+    };
+    result.check(
+        "default RLIMIT_CORE",
+        prlimit(0, RLIMIT_CORE, None, Some(&mut limit)) == 0 && limit.rlim_cur == 0,
+    );
+    let (pid, status) = crash(segfault);
+    result.check(
+        "SIGSEGV without core",
+        status.signal() == Some(Sig::SIGSEGV.raw() as i32) && !status.core_dumped(),
+    );
+    result.check("core file with zero limit", read_core(pid).is_none());
+
+    let limit = RLimit {
+        rlim_cur: LIMIT,
+        rlim_max: RLIM_INFINITY,
+    };
+    prlimit(0, RLIMIT_CORE, Some(&limit), None);
+    let (pid, status) = crash(segfault);
+    result.check(
+        "SIGSEGV with core",
+        status.signal() == Some(Sig::SIGSEGV.raw() as i32) && status.core_dumped(),
+    );
+    match read_core(pid) {
+        Some(core) => {
+            if let Err(what) = check_core(pid, &core) {
+                result.check(what, false);
+            }
+        }
+        None => result.check("core file", false),
+    }
+    unlink(&core_path(pid));
+
+    let (pid, status) = crash(abort);
+    result.check(
+        "SIGABRT with core",
+        status.signal() == Some(Sig::SIGABRT.raw() as i32)
+            && status.core_dumped()
+            && read_core(pid).is_some(),
+    );
+    unlink(&core_path(pid));
+
+    // Not even the headers fit in the limit.
+    let limit = RLimit {
+        rlim_cur: 64,
+        rlim_max: RLIM_INFINITY,
+    };
+    prlimit(0, RLIMIT_CORE, Some(&limit), None);
+    let (pid, status) = crash(segfault);
+    result.check(
+        "SIGSEGV with a tiny limit",
+        !status.core_dumped() && read_core(pid).is_none(),
+    );
+
+    chdir("/\0");
+    umount("/coredump_test\0");
+    result.finish()
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::vec;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 256;
+const ROUNDS: usize = 20;
+
+fn now_usec() -> usize {
+    let mut time_val = TimeVal::from_usec(0);
+    gettimeofday(&mut time_val);
+    time_val.into_usec()
+}
+
+/// Read `cow_copy` and `cow_reuse` from /proc/vmstat.
+fn cow_stat() -> Option<(usize, usize)> {
+    let fd = openat("/proc/vmstat\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 256];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let text = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let field = |name: &str| -> Option<usize> {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name)?.trim().parse().ok())
+    };
+    Some((field("cow_copy ")?, field("cow_reuse ")?))
+}
+
+/// Fork and write every page of `buf` in the parent, either after the child
+/// has exited, so that the pages are no longer shared and are written in
+/// place, or while the child is alive, so that they are copied. Return the
+/// time in microseconds, along with the copies and reuses counted meanwhile.
+fn bench(buf: &mut [u8], child_alive: bool) -> (usize, usize, usize) {
+    let (copies, reuses) = cow_stat().unwrap_or_default();
+    let begin = now_usec();
+    for round in 0..ROUNDS {
+        let mut fds = [0; 2];
+        pipe(&mut fds);
+        let pid = fork();
+        if pid == 0 {
+            // wait until the parent has written the pages
+            close(fds[1] as usize);
+            if child_alive {
+                read(fds[0] as usize, &mut [0u8; 1]);
+            }
+            exit(0);
+        }
+        close(fds[0] as usize);
+        let mut wstatus = 0;
+        if !child_alive {
+            waitpid(pid as usize, &mut wstatus);
+        }
+        for page in buf.chunks_mut(PAGE_SIZE) {
+            page[0] = round as u8;
+        }
+        close(fds[1] as usize);
+        if child_alive {
+            waitpid(pid as usize, &mut wstatus);
+        }
+    }
+    let usec = now_usec() - begin;
+    let (new_copies, new_reuses) = cow_stat().unwrap_or_default();
+    (usec, new_copies - copies, new_reuses - reuses)
+}
+
+/// Measure the cost of the copy-on-write faults taken by a parent after fork,
+/// which copy the pages only if the child still shares them.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin cow bench");
+    let mut buf = vec![0u8; PAGES * PAGE_SIZE];
+    // fault in all pages before forking
+    buf.iter_mut().step_by(PAGE_SIZE).for_each(|b| *b = 1);
+
+    let (usec, copies, reuses) = bench(&mut buf, true);
+    println!(
+        "child alive: {} us, {} copies, {} reuses",
+        usec, copies, reuses
+    );
+    let (usec, copies, reuses) = bench(&mut buf, false);
+    println!(
+        "child exited: {} us, {} copies, {} reuses",
+        usec, copies, reuses
+    );
+    0
+}
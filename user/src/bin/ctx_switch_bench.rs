@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::vec;
+use core::hint::black_box;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const ROUNDS: usize = 10000;
+/// Hart both processes are pinned on, so that every pass is a context switch.
+const HART: usize = 0;
+
+fn now_usec() -> usize {
+    let mut time_val = TimeVal::from_usec(0);
+    gettimeofday(&mut time_val);
+    time_val.into_usec()
+}
+
+/// Pass a token back and forth `ROUNDS` times, reading a working set of `pages`
+/// pages on each pass, and return the time in microseconds.
+fn bench(pages: usize) -> usize {
+    let buf = vec![1u8; pages * PAGE_SIZE];
+    let (mut ping, mut pong) = ([0; 2], [0; 2]);
+    pipe(&mut ping);
+    pipe(&mut pong);
+    let touch = |buf: &[u8]| {
+        buf.iter()
+            .step_by(PAGE_SIZE)
+            .map(|&b| b as usize)
+            .sum::<usize>()
+    };
+    let mut token = [0u8; 1];
+    let pid = fork();
+    if pid == 0 {
+        for _ in 0..ROUNDS {
+            read(ping[0] as usize, &mut token);
+            black_box(touch(&buf));
+            write(pong[1] as usize, &token);
+        }
+        exit(0);
+    }
+    let begin = now_usec();
+    for _ in 0..ROUNDS {
+        write(ping[1] as usize, &token);
+        read(pong[0] as usize, &mut token);
+        black_box(touch(&buf));
+    }
+    let usec = now_usec() - begin;
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    for fd in ping.iter().chain(&pong) {
+        close(*fd as usize);
+    }
+    usec
+}
+
+/// Measure the cost of switching between two processes on one hart like
+/// lmbench lat_ctx, which is dominated by the TLB misses after each switch if
+/// the TLB is flushed on switch.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin context switch bench");
+    let mut online = 0;
+    sched_getaffinity(0, &mut online);
+    sched_setaffinity(0, 1 << HART);
+    for pages in [0, 4, 16, 64] {
+        let usec = bench(pages);
+        println!(
+            "{} pages: {} ns per switch",
+            pages,
+            usec * 1000 / (2 * ROUNDS)
+        );
+    }
+    sched_setaffinity(0, online);
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Path of the current working directory, or the error of getcwd(2).
+fn cwd(buf: &mut [u8]) -> Result<&str, SyscallErr> {
+    SyscallErr::from_ret(getcwd(buf))?;
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok(core::str::from_utf8(&buf[..len]).unwrap_or_default())
+}
+
+/// Check that the cwd follows its directory when another process renames it,
+/// that relative paths and ".." are resolved from there, and that getcwd(2)
+/// fails with ENOENT once the cwd is removed.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("cwd");
+    mkdir("/cwd_test\0", 0o755);
+    mkdir("/cwd_test/old\0", 0o755);
+    mkdir("/cwd_test/gone\0", 0o755);
+    let mut buf = [0u8; 64];
+
+    result.check("chdir", chdir("/cwd_test/old\0") == 0);
+    result.check("cwd", cwd(&mut buf) == Ok("/cwd_test/old"));
+    let pid = fork();
+    if pid == 0 {
+        exit(rename("/cwd_test/old\0", "/cwd_test/new\0") as i32);
+    }
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    result.check("rename by the child", ExitStatus(wstatus).success());
+    result.check("cwd after the rename", cwd(&mut buf) == Ok("/cwd_test/new"));
+    let fd = openat("file\0", OpenFlags::O_CREATE | OpenFlags::O_WRONLY);
+    close(fd as usize);
+    let mut st = Stat::default();
+    result.check(
+        "file created relative to the cwd",
+        fd >= 0 && stat("/cwd_test/new/file\0", &mut st) == 0,
+    );
+    result.check("chdir to ..", chdir("..\0") == 0);
+    result.check("cwd after chdir to ..", cwd(&mut buf) == Ok("/cwd_test"));
+
+    chdir("/cwd_test/gone\0");
+    rmdir("/cwd_test/gone\0");
+    result.check(
+        "cwd after it is removed",
+        cwd(&mut buf) == Err(SyscallErr::ENOENT),
+    );
+
+    chdir("/\0");
+    unlink("/cwd_test/new/file\0");
+    rmdir("/cwd_test/new\0");
+    rmdir("/cwd_test\0");
+    result.finish()
+}
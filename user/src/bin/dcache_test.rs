@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::format;
+
+use user_lib::*;
+
+/// Max number of dentries in the LRU list, `DCACHE_CAPACITY` of the kernel.
+const DCACHE_CAPACITY: usize = 4096;
+
+/// Read the counter named `name` from /proc/sys/fs/dcache-stat.
+fn read_counter(name: &str) -> Option<usize> {
+    let fd = openat("/proc/sys/fs/dcache-stat\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 256];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    info.lines().find_map(|line| {
+        let (key, value) = line.split_once(' ')?;
+        (key == name).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Looking up more missing files than the dentry cache holds keeps the LRU
+/// list bounded by evicting negative dentries, while a name looked up again
+/// right away is answered by the cache.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin dcache test");
+    let Some(evictions) = read_counter("evictions") else {
+        println!("no evictions in dcache-stat");
+        return -1;
+    };
+    for i in 0..DCACHE_CAPACITY + DCACHE_CAPACITY / 2 {
+        let path = format!("/dcache_test_missing_{}\0", i);
+        if openat(&path, OpenFlags::O_RDONLY) >= 0 {
+            println!("{} should not exist", path);
+            return -1;
+        }
+    }
+    let nr_dentry = read_counter("nr_dentry").unwrap_or(usize::MAX);
+    let evicted = read_counter("evictions").unwrap_or(0) - evictions;
+    println!("nr_dentry {}, evicted {}", nr_dentry, evicted);
+    if nr_dentry > DCACHE_CAPACITY || evicted < DCACHE_CAPACITY / 2 {
+        println!("dcache test failed: the LRU list is not bounded");
+        return -1;
+    }
+
+    let hits = read_counter("hits").unwrap_or(0);
+    openat("/dcache_test_missing_0\0", OpenFlags::O_RDONLY);
+    openat("/dcache_test_missing_0\0", OpenFlags::O_RDONLY);
+    if read_counter("hits").unwrap_or(0) <= hits {
+        println!("dcache test failed: negative dentry is not cached");
+        return -1;
+    }
+    println!("dcache test passed");
+    0
+}
@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Execute itself with FOO=bar, where the child checks what it gets with
+/// getenv and setenv.
+#[no_mangle]
+fn main(_argc: usize, argv: &[&str]) -> i32 {
+    if argv.get(1) == Some(&"child") {
+        return child();
+    }
+    println!("begin env test");
+    let pid = fork();
+    if pid == 0 {
+        execve("env_test", &["env_test", "child"], &["FOO=bar", "NOVALUE"]);
+        println!("exec env_test failed");
+        exit(-1);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    if exit_code == 0 {
+        println!("env test passed");
+        0
+    } else {
+        println!("env test failed");
+        -1
+    }
+}
+
+fn child() -> i32 {
+    let foo = getenv("FOO");
+    println!("getenv(\"FOO\") = {:?}", foo);
+    let mut ok = foo.as_deref() == Some("bar");
+    // an entry without '=' is kept but has no value
+    ok &= getenv("NOVALUE").is_none();
+    ok &= environ().any(|entry| entry == "NOVALUE");
+    ok &= setenv("FOO", "baz").is_ok() && getenv("FOO").as_deref() == Some("baz");
+    ok &= setenv("NEW", "").is_ok() && getenv("NEW").as_deref() == Some("");
+    ok &= setenv("A=B", "c") == Err(SyscallErr::EINVAL);
+    if ok {
+        0
+    } else {
+        -1
+    }
+}
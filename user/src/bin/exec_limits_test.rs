@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::{string::String, vec, vec::Vec};
+use core::ptr::null;
+
+use user_lib::*;
+
+const ARG_MAX: usize = 128 * 1024;
+const MAX_ARG_STRLEN: usize = 32 * 4096;
+/// Length of the argument passed to the child, which is within the limits.
+const CHILD_ARG_LEN: usize = 64 * 1024;
+
+/// Null terminated string of `len` bytes besides the null.
+fn string(len: usize) -> String {
+    let mut s = String::from_utf8(vec![b'a'; len]).unwrap();
+    s.push('\0');
+    s
+}
+
+/// `n` pointers to `s` followed by a null pointer.
+fn repeat(s: &str, n: usize) -> Vec<*const u8> {
+    let mut ptrs = vec![s.as_ptr(); n];
+    ptrs.push(null());
+    ptrs
+}
+
+fn check(name: &str, ret: isize, err: SyscallErr) -> bool {
+    if ret != -(err as isize) {
+        println!("{} returns {}, expected {:?}", name, ret, err);
+        return false;
+    }
+    true
+}
+
+/// Pass argv and envp beyond the limits to execve, which should fail with
+/// `E2BIG` instead of exhausting the kernel heap, e.g. 100k pointers to the
+/// same 64 KiB string, which takes little memory here but 6 GiB once copied
+/// string by string. Then exec itself with a large argument within the limits.
+#[no_mangle]
+fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 3 && argv[1] == "child" {
+        return if argv[2].len() == CHILD_ARG_LEN {
+            0
+        } else {
+            -1
+        };
+    }
+    println!("begin exec limits test");
+    // execve fails with ENOENT once the arguments are copied
+    let path = "/no_such_program\0";
+    let arg0 = "exec_limits_test\0";
+    let small = "x\0";
+    let large = string(64 * 1024);
+    let huge = string(MAX_ARG_STRLEN);
+    let with_arg0 = |mut ptrs: Vec<*const u8>| {
+        ptrs.insert(0, arg0.as_ptr());
+        ptrs
+    };
+
+    let checks = [
+        (
+            "100k large arguments",
+            execve_raw(path, &with_arg0(repeat(&large, 100_000)), &[null()]),
+            SyscallErr::E2BIG,
+        ),
+        (
+            "100k small arguments",
+            execve_raw(path, &with_arg0(repeat(small, 100_000)), &[null()]),
+            SyscallErr::E2BIG,
+        ),
+        (
+            "100k large environment strings",
+            execve_raw(path, &with_arg0(repeat(small, 0)), &repeat(&large, 100_000)),
+            SyscallErr::E2BIG,
+        ),
+        (
+            "argument of MAX_ARG_STRLEN",
+            execve_raw(path, &with_arg0(repeat(&huge, 1)), &[null()]),
+            SyscallErr::E2BIG,
+        ),
+        (
+            "arguments just beyond ARG_MAX",
+            execve_raw(
+                path,
+                &with_arg0(repeat(small, ARG_MAX / (small.len() + 8))),
+                &[null()],
+            ),
+            SyscallErr::E2BIG,
+        ),
+        (
+            "arguments within ARG_MAX",
+            execve_raw(path, &with_arg0(repeat(&large, 1)), &repeat(small, 100)),
+            SyscallErr::ENOENT,
+        ),
+    ];
+    let mut passed = true;
+    for (name, ret, err) in checks {
+        passed &= check(name, ret, err);
+    }
+
+    let child_arg = String::from_utf8(vec![b'a'; CHILD_ARG_LEN]).unwrap();
+    match Command::new(argv[0]).arg("child").arg(&child_arg).status() {
+        Ok(status) if status.success() => {}
+        ret => {
+            println!("exec with a large argument returns {:?}", ret);
+            passed = false;
+        }
+    }
+    if !passed {
+        return -1;
+    }
+    println!("exec limits test passed");
+    0
+}
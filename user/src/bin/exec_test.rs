@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::format;
+
+use user_lib::*;
+
+const ENOEXEC: isize = 8;
+
+/// Exit code of this program run as the interpreter of a script.
+const INTERP_EXIT_CODE: i32 = 42;
+
+/// Header of a 64-bit little-endian elf for x86_64, which is cut short.
+const X86_ELF: &[u8] = &[
+    0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0x3e, 0,
+];
+
+/// Write `data` to an executable file at `path`.
+fn create_exec(path: &str, data: &[u8]) -> bool {
+    let fd = openat(
+        path,
+        OpenFlags::O_CREATE | OpenFlags::O_TRUNC | OpenFlags::O_WRONLY,
+    );
+    if fd < 0 {
+        println!("open {} failed: {}", path, fd);
+        return false;
+    }
+    let ret = write(fd as usize, data);
+    close(fd as usize);
+    ret == data.len() as isize && chmod(path, 0o755) == 0
+}
+
+/// Exec files which are not valid elfs. Each should fail with ENOEXEC and
+/// leave this process running. Then exec scripts whose interpreter is this
+/// program, which checks the argv passed with `--script`.
+#[no_mangle]
+fn main(_argc: usize, argv: &[&str]) -> i32 {
+    if argv.get(1) == Some(&"--script") {
+        let expected = ["--script", "/exec_test/script.sh", "arg"];
+        return if argv[1..] == expected {
+            INTERP_EXIT_CODE
+        } else {
+            println!("interp got argv {:?}", argv);
+            -1
+        };
+    }
+
+    println!("begin exec test");
+    mkdir("/exec_test\0", 0o755);
+    if mount("tmpfs\0", "/exec_test\0", "tmpfs\0", 0, "\0") != 0 {
+        println!("mount tmpfs failed");
+        return -1;
+    }
+    let cases: [(&str, &[u8]); 3] = [
+        ("/exec_test/passwd\0", b"root:x:0:0:root:/root:/bin/sh\n"),
+        ("/exec_test/truncated\0", &[0x7f, b'E', b'L', b'F']),
+        ("/exec_test/x86\0", X86_ELF),
+    ];
+    let mut ok = true;
+    for (path, data) in cases {
+        if !create_exec(path, data) {
+            println!("create {} failed", path);
+            ok = false;
+            continue;
+        }
+        let path = path.trim_end_matches('\0');
+        let ret = execve(path, &[path], &[]);
+        if ret != -ENOEXEC {
+            println!("execve {}: got {}, expected {}", path, ret, -ENOEXEC);
+            ok = false;
+        }
+        match Command::new(path).status() {
+            Err(SyscallErr::ENOEXEC) => {}
+            other => {
+                println!("spawn {}: got {:?}", path, other);
+                ok = false;
+            }
+        }
+    }
+
+    let script = format!("#! {} --script \nexit 1\n", argv[0]);
+    if create_exec("/exec_test/script.sh\0", script.as_bytes()) {
+        match Command::new("/exec_test/script.sh").arg("arg").status() {
+            Ok(status) if status.code() == Some(INTERP_EXIT_CODE) => {}
+            other => {
+                println!("run script: got {:?}", other);
+                ok = false;
+            }
+        }
+    } else {
+        ok = false;
+    }
+
+    // A script which is its own interpreter loops until the depth limit.
+    if create_exec("/exec_test/loop\0", b"#!/exec_test/loop\n") {
+        match Command::new("/exec_test/loop").status() {
+            Err(SyscallErr::ELOOP) => {}
+            other => {
+                println!("run loop script: got {:?}", other);
+                ok = false;
+            }
+        }
+    } else {
+        ok = false;
+    }
+
+    if ok {
+        println!("exec test passed");
+        0
+    } else {
+        -1
+    }
+}
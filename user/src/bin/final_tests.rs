@@ -5,7 +5,7 @@ extern crate alloc;
 
 use alloc::format;
 
-use user_lib::{execve, fork, wait, waitpid};
+use user_lib::{fork, wait, Command};
 
 #[macro_use]
 extern crate user_lib;
@@ -26,19 +26,13 @@ const TESTCASES: &[&str] = &[
 ];
 
 fn run_cmd(cmd: &str) {
-    if fork() == 0 {
-        execve(
-            "busybox",
-            &["busybox", "sh", "-c", cmd],
-            &[
-                "PATH=/:/bin",
-                "LD_LIBRARY_PATH=/:/lib:/lib/glibc/:/lib/musl",
-            ],
-        );
-    } else {
-        let mut result: i32 = 0;
-        waitpid((-1isize) as usize, &mut result);
-    }
+    let _ = Command::new("busybox")
+        .args(&["sh", "-c", cmd])
+        .envs(&[
+            "PATH=/:/bin",
+            "LD_LIBRARY_PATH=/:/lib:/lib/glibc/:/lib/musl",
+        ])
+        .status();
 }
 
 #[no_mangle]
@@ -0,0 +1,145 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use user_lib::*;
+
+/// Word the waiters wait on, which is never changed.
+static WORD: AtomicU32 = AtomicU32::new(0);
+/// Bitsets of the waiters that have been woken.
+static WOKEN: AtomicU32 = AtomicU32::new(0);
+
+fn word() -> usize {
+    WORD.as_ptr() as usize
+}
+
+fn now(clockid: usize) -> Duration {
+    let mut ts = TimeSpec::default();
+    clock_gettime(clockid, &mut ts);
+    ts.into()
+}
+
+fn wait_bitset(bitset: u32) {
+    let op = FUTEX_WAIT_BITSET | FUTEX_PRIVATE_FLAG;
+    futex(word(), op, 0, 0, 0, bitset);
+    WOKEN.fetch_or(bitset, Ordering::SeqCst);
+}
+
+/// Wake waiters with `op` until one is woken, as it may not be waiting yet,
+/// and return whether it is the one of `bitset`.
+fn wake_one(op: i32, mask: u32, bitset: u32) -> bool {
+    let woken = WOKEN.load(Ordering::SeqCst);
+    for _ in 0..100 {
+        if futex(word(), op | FUTEX_PRIVATE_FLAG, 1, 0, 0, mask) == 1 {
+            while WOKEN.load(Ordering::SeqCst) == woken {
+                yield_();
+            }
+            return WOKEN.load(Ordering::SeqCst) == woken | bitset;
+        }
+        sleep(10);
+    }
+    false
+}
+
+/// Wait with FUTEX_WAIT_BITSET for an absolute `deadline` of `clockid`, and
+/// return how long it takes to time out.
+fn wait_until(clockid: usize, deadline: Duration) -> Option<Duration> {
+    let mut op = FUTEX_WAIT_BITSET | FUTEX_PRIVATE_FLAG;
+    if clockid == CLOCK_REALTIME {
+        op |= FUTEX_CLOCK_REALTIME;
+    }
+    let timeout = TimeSpec::from(deadline);
+    let begin = now(CLOCK_MONOTONIC);
+    let ret = futex(
+        word(),
+        op,
+        0,
+        &timeout as *const TimeSpec as usize,
+        0,
+        FUTEX_BITSET_MATCH_ANY,
+    );
+    let elapsed = now(CLOCK_MONOTONIC) - begin;
+    matches!(SyscallErr::from_ret(ret), Err(SyscallErr::ETIMEDOUT)).then_some(elapsed)
+}
+
+/// Check FUTEX_WAKE_BITSET only wakes the waiters whose bitset intersects the
+/// mask, FUTEX_BITSET_MATCH_ANY acts as plain FUTEX_WAIT and FUTEX_WAKE, and
+/// the timeouts of FUTEX_WAIT_BITSET are absolute on the clock selected.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("futex bitset");
+
+    let low = thread::spawn(|| wait_bitset(0b01)).unwrap();
+    let high = thread::spawn(|| wait_bitset(0b10)).unwrap();
+    result.check("wake by mask 0b10", wake_one(FUTEX_WAKE_BITSET, 0b10, 0b10));
+    high.join();
+    // give the other waiter time to wait before it is missed on purpose
+    sleep(100);
+    result.check(
+        "wake by a disjoint mask",
+        futex(
+            word(),
+            FUTEX_WAKE_BITSET | FUTEX_PRIVATE_FLAG,
+            1,
+            0,
+            0,
+            0b100,
+        ) == 0,
+    );
+    result.check("wake by mask 0b11", wake_one(FUTEX_WAKE_BITSET, 0b11, 0b01));
+    low.join();
+    result.check(
+        "FUTEX_WAKE_BITSET with an empty mask",
+        matches!(
+            SyscallErr::from_ret(futex(word(), FUTEX_WAKE_BITSET, 1, 0, 0, 0)),
+            Err(SyscallErr::EINVAL)
+        ),
+    );
+
+    WOKEN.store(0, Ordering::SeqCst);
+    let any = thread::spawn(|| wait_bitset(FUTEX_BITSET_MATCH_ANY)).unwrap();
+    result.check(
+        "FUTEX_WAKE of FUTEX_BITSET_MATCH_ANY",
+        wake_one(FUTEX_WAKE, 0, FUTEX_BITSET_MATCH_ANY),
+    );
+    any.join();
+
+    for clockid in [CLOCK_MONOTONIC, CLOCK_REALTIME] {
+        let elapsed = wait_until(clockid, now(clockid));
+        result.check(
+            "an expired deadline",
+            elapsed.is_some_and(|elapsed| elapsed < Duration::from_millis(50)),
+        );
+        let elapsed = wait_until(clockid, now(clockid) + Duration::from_millis(100));
+        result.check(
+            "a deadline 100ms later",
+            elapsed.is_some_and(|elapsed| elapsed >= Duration::from_millis(100)),
+        );
+    }
+
+    // the deadline follows the realtime clock moved forward by an hour
+    let hour = Duration::from_secs(3600);
+    if clock_settime(CLOCK_REALTIME, &TimeSpec::from(now(CLOCK_REALTIME) + hour)) == 0 {
+        let elapsed = wait_until(
+            CLOCK_REALTIME,
+            now(CLOCK_REALTIME) + Duration::from_millis(100),
+        );
+        clock_settime(CLOCK_REALTIME, &TimeSpec::from(now(CLOCK_REALTIME) - hour));
+        result.check(
+            "a deadline after clock_settime",
+            elapsed.is_some_and(|elapsed| {
+                elapsed >= Duration::from_millis(100) && elapsed < Duration::from_secs(1)
+            }),
+        );
+    } else {
+        println!("clock_settime failed, skip");
+    }
+
+    result.finish()
+}
@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Hart to pin on, which is skipped if it is not online.
+const TARGET_HART: u32 = 1;
+const ROUNDS: usize = 100;
+
+/// Pin to a hart with sched_setaffinity and check that getcpu keeps reporting
+/// it, including after yielding.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin getcpu test");
+    let (mut cpu, mut node) = (u32::MAX, u32::MAX);
+    if getcpu(Some(&mut cpu), Some(&mut node)) != 0 || node != 0 {
+        println!("getcpu failed, cpu {} node {}", cpu, node);
+        return -1;
+    }
+    if getcpu(None, None) != 0 {
+        println!("getcpu with NULL pointers failed");
+        return -1;
+    }
+
+    let mut online = 0;
+    if sched_getaffinity(0, &mut online) < 0 {
+        println!("sched_getaffinity failed");
+        return -1;
+    }
+    if online & (1 << TARGET_HART) == 0 {
+        println!("hart {} is offline, skipped", TARGET_HART);
+        return 0;
+    }
+
+    if sched_setaffinity(0, 1 << TARGET_HART) != 0 {
+        println!("sched_setaffinity failed");
+        return -1;
+    }
+    for _ in 0..ROUNDS {
+        let mut cpu = u32::MAX;
+        getcpu(Some(&mut cpu), None);
+        if cpu != TARGET_HART {
+            println!("running on hart {} while pinned to {}", cpu, TARGET_HART);
+            return -1;
+        }
+        yield_();
+    }
+    sched_setaffinity(0, online);
+    println!("getcpu test passed");
+    0
+}
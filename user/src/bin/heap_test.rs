@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::{vec, vec::Vec};
+
+use user_lib::*;
+
+const BIG_SIZE: usize = 4 * 1024 * 1024;
+
+/// Allocate a vector filled with `pattern` and check it is intact.
+fn check_alloc(len: usize, pattern: u8) -> bool {
+    let v = vec![pattern; len];
+    v.iter().all(|&b| b == pattern)
+}
+
+/// Allocate 4 MiB at once, then fork three times and allocate in all the
+/// eight processes, which should not disturb each other.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin heap test");
+    if !check_alloc(BIG_SIZE, 0x5a) {
+        println!("4 MiB allocation corrupted");
+        return -1;
+    }
+    println!("4 MiB allocation ok");
+
+    let root = getpid();
+    let mut children = Vec::new();
+    for _ in 0..3 {
+        let pid = fork();
+        if pid == 0 {
+            children.clear();
+        } else {
+            children.push(pid);
+        }
+    }
+    let mut ok = check_alloc(0x40000 + getpid() as usize * 0x1000, getpid() as u8);
+    for pid in children {
+        let mut exit_code = 0;
+        waitpid(pid as usize, &mut exit_code);
+        ok &= exit_code == 0;
+    }
+    if getpid() != root {
+        exit(if ok { 0 } else { 1 });
+    }
+    if ok {
+        println!("heap test passed");
+        0
+    } else {
+        println!("heap test failed");
+        -1
+    }
+}
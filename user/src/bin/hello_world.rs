@@ -1,12 +1,23 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
 extern crate user_lib;
 
-use user_lib::{exit, println};
+use alloc::string::String;
+
+use user_lib::{exit, print, println, read_line};
 
 #[no_mangle]
 fn main() {
-    println!("hello world");
+    print!("what is your name? ");
+    let mut name = String::new();
+    read_line(&mut name);
+    let name = name.trim();
+    if name.is_empty() {
+        println!("hello world");
+    } else {
+        println!("hello {}", name);
+    }
     exit(3)
 }
@@ -5,29 +5,49 @@ extern crate user_lib;
 
 extern crate alloc;
 
-use alloc::format;
-
-use user_lib::{execve, fork, println, wait, waitpid};
+use user_lib::{
+    execve, fork, println, reboot, sigaction, wait, Command, Sig, SigAction, SyscallErr,
+    LINUX_REBOOT_CMD_HALT, LINUX_REBOOT_CMD_POWER_OFF, LINUX_REBOOT_CMD_RESTART,
+};
 
 fn run_cmd(cmd: &str) {
-    if fork() == 0 {
-        execve(
-            "busybox",
-            &["busybox", "sh", "-c", cmd],
-            &[
-                "PATH=/:/bin",
-                "HOME=/home/crw",
-                "LD_LIBRARY_PATH=/:/lib:/lib/glibc/:/lib/musl",
-            ],
-        );
-    } else {
-        let mut result: i32 = 0;
-        waitpid((-1isize) as usize, &mut result);
+    let _ = Command::new("busybox")
+        .args(&["sh", "-c", cmd])
+        .envs(&[
+            "PATH=/:/bin",
+            "HOME=/home/crw",
+            "LD_LIBRARY_PATH=/:/lib:/lib/glibc/:/lib/musl",
+        ])
+        .status();
+}
+
+/// Signals that busybox `reboot`, `halt` and `poweroff` send to init, unless
+/// they are forced by `-f` to call reboot(2) themselves.
+const SHUTDOWN_SIGNALS: [(Sig, usize); 3] = [
+    (Sig::SIGTERM, LINUX_REBOOT_CMD_RESTART),
+    (Sig::SIGUSR1, LINUX_REBOOT_CMD_HALT),
+    (Sig::SIGUSR2, LINUX_REBOOT_CMD_POWER_OFF),
+];
+
+extern "C" fn on_shutdown_signal(signum: usize) {
+    for (sig, cmd) in SHUTDOWN_SIGNALS {
+        if sig.raw() == signum {
+            println!("[initproc] going down by signal {}", signum);
+            reboot(cmd);
+        }
     }
 }
 
 #[no_mangle]
 fn main() -> i32 {
+    for (sig, _) in SHUTDOWN_SIGNALS {
+        let act = SigAction {
+            sa_handler: on_shutdown_signal as usize,
+            ..Default::default()
+        };
+        sigaction(sig, &act, &mut SigAction::default());
+    }
+
     run_cmd("busybox --install /bin");
     run_cmd("rm /bin/sh");
     run_cmd("ln -s /lib/glibc/ld-linux-riscv64-lp64d.so.1 /lib/ld-linux-riscv64-lp64d.so.1 ");
@@ -46,7 +66,11 @@ fn main() -> i32 {
         loop {
             let mut wstatus: i32 = 0;
             let pid = wait(&mut wstatus);
-            if pid < 0 {
+            // Orphans are reparented to init, so there is no process left once init
+            // has no child.
+            if pid == -(SyscallErr::EINTR as isize) {
+                continue;
+            } else if pid < 0 {
                 break;
             }
             println!(
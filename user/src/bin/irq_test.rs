@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Bytes written to the serial port, more than its transmit buffer holds.
+const BYTES: usize = 2048;
+
+/// Read the interrupts handled by each hart for the device `name` from
+/// /proc/interrupts.
+fn read_counts(name: &str, counts: &mut [usize]) -> Option<usize> {
+    let fd = openat("/proc/interrupts\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 4096];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let line = info.lines().find(|line| line.ends_with(name))?;
+    let mut harts = 0;
+    // the IRQ number, the counts, then the controller
+    for (hart, field) in line.split_whitespace().skip(1).enumerate() {
+        let Ok(count) = field.parse() else {
+            break;
+        };
+        if hart < counts.len() {
+            counts[hart] = count;
+        }
+        harts += 1;
+    }
+    Some(harts)
+}
+
+/// Writing to the serial port raises interrupts to drain its transmit buffer,
+/// which are counted in /proc/interrupts on the harts handling them.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin irq test");
+    let mut before = [0; 8];
+    let Some(harts) = read_counts("ttyS0", &mut before) else {
+        println!("no ttyS0 in /proc/interrupts");
+        return -1;
+    };
+    let line = [b'.'; 64];
+    for _ in 0..BYTES / line.len() {
+        write(1, &line);
+    }
+    println!("");
+    let mut after = [0; 8];
+    read_counts("ttyS0", &mut after);
+    let harts = harts.min(before.len());
+    for hart in 0..harts {
+        println!("hart {}: {} -> {}", hart, before[hart], after[hart]);
+    }
+    let total = |counts: &[usize]| counts[..harts].iter().sum::<usize>();
+    if total(&after) <= total(&before) {
+        println!("irq test failed");
+        return -1;
+    }
+    println!("irq test passed");
+    0
+}
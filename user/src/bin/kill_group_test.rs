@@ -0,0 +1,130 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Fork a child which runs `f` and exits with zero, or sleeps until it is
+/// killed if `f` is not given.
+fn fork_child(f: Option<fn() -> i32>) -> usize {
+    let pid = fork();
+    if pid == 0 {
+        match f {
+            Some(f) => exit(f()),
+            None => loop {
+                sleep(1000);
+            },
+        }
+    }
+    assert!(pid > 0, "fork failed: {}", pid);
+    pid as usize
+}
+
+fn killed_by(pid: usize, sig: Sig) -> bool {
+    let mut wstatus = 0;
+    waitpid(pid, &mut wstatus) == pid as isize
+        && ExitStatus(wstatus).signal() == Some(sig.raw() as i32)
+}
+
+fn exited_ok(pid: usize) -> bool {
+    let mut wstatus = 0;
+    waitpid(pid, &mut wstatus) == pid as isize && ExitStatus(wstatus).success()
+}
+
+fn errno(ret: isize) -> Option<SyscallErr> {
+    SyscallErr::from_ret(ret).err()
+}
+
+/// Wait at most a second for the group `pgid` to be gone, e.g. after its
+/// orphans are reaped by init.
+fn group_gone(pgid: usize) -> bool {
+    for _ in 0..100 {
+        if matches!(
+            errno(kill(-(pgid as isize), Sig::from_i32(0))),
+            Some(SyscallErr::ESRCH)
+        ) {
+            return true;
+        }
+        sleep(10);
+    }
+    false
+}
+
+/// Kill a process group with `kill(0, sig)` from inside it, which kills the
+/// caller as well.
+fn kill_own_group() -> i32 {
+    setpgid(0, 0);
+    fork_child(None);
+    kill(0, Sig::SIGKILL);
+    -1
+}
+
+/// An unprivileged process may not signal the root parent.
+fn kill_without_permission() -> i32 {
+    let parent = getppid() as isize;
+    if setuid(1000) < 0 {
+        return -1;
+    }
+    match (
+        errno(kill(parent, Sig::from_i32(0))),
+        errno(kill(parent, Sig::SIGKILL)),
+    ) {
+        (Some(SyscallErr::EPERM), Some(SyscallErr::EPERM)) => 0,
+        _ => -1,
+    }
+}
+
+/// Check kill(2) on process groups, i.e. `kill(-pgid, sig)` and `kill(0, sig)`,
+/// and the existence and permission checks.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("kill group");
+
+    // kill(-pgid) kills all the processes in the group, and only them
+    let leader = fork_child(None);
+    let member = fork_child(None);
+    let outsider = fork_child(None);
+    result.check("setpgid of the leader", setpgid(leader, 0) == 0);
+    result.check("setpgid of the member", setpgid(member, leader) == 0);
+    result.check("getpgid", getpgid(member) == leader as isize);
+    result.check(
+        "setpgid to a group that does not exist",
+        matches!(errno(setpgid(outsider, 99999)), Some(SyscallErr::EPERM)),
+    );
+    result.check(
+        "kill(-pgid, 0)",
+        kill(-(leader as isize), Sig::from_i32(0)) == 0,
+    );
+    result.check("kill(-pgid)", kill(-(leader as isize), Sig::SIGKILL) == 0);
+    result.check("leader killed", killed_by(leader, Sig::SIGKILL));
+    result.check("member killed", killed_by(member, Sig::SIGKILL));
+    result.check("kill(-pgid) after the group is reaped", group_gone(leader));
+    result.check(
+        "outsider alive",
+        kill(outsider as isize, Sig::from_i32(0)) == 0,
+    );
+    kill(outsider as isize, Sig::SIGKILL);
+    result.check("outsider killed", killed_by(outsider, Sig::SIGKILL));
+
+    // kill(0) kills the group of the caller
+    let pid = fork_child(Some(kill_own_group));
+    result.check("kill(0)", killed_by(pid, Sig::SIGKILL));
+    result.check("kill(0) kills the whole group", group_gone(pid));
+
+    result.check(
+        "kill of a process that does not exist",
+        matches!(
+            errno(kill(99999, Sig::from_i32(0))),
+            Some(SyscallErr::ESRCH)
+        ),
+    );
+    result.check(
+        "kill of a group that does not exist",
+        matches!(errno(kill(-99999, Sig::SIGKILL)), Some(SyscallErr::ESRCH)),
+    );
+    let pid = fork_child(Some(kill_without_permission));
+    result.check("kill without permission", exited_ok(pid));
+
+    result.finish()
+}
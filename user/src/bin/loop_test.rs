@@ -0,0 +1,205 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::format;
+
+use user_lib::*;
+
+const IMAGE: &str = "/loop_test.img\0";
+const MOUNT_POINT: &str = "/loop_test\0";
+const FILE: &str = "/loop_test/hello.txt\0";
+const DATA: &[u8] = b"hello from a loop device";
+
+const LOOP_SET_FD: usize = 0x4c00;
+const LOOP_CLR_FD: usize = 0x4c01;
+const LOOP_SET_STATUS64: usize = 0x4c04;
+const LOOP_GET_STATUS64: usize = 0x4c05;
+const LOOP_CTL_GET_FREE: usize = 0x4c82;
+
+const SECTOR_SIZE: usize = 512;
+/// A 1.44 MB floppy, which is formatted as FAT12.
+const SECTORS: usize = 2880;
+const SECTORS_PER_FAT: usize = 9;
+
+/// The same as `struct loop_info64`.
+#[repr(C)]
+struct LoopInfo64 {
+    lo_device: u64,
+    lo_inode: u64,
+    lo_rdevice: u64,
+    lo_offset: u64,
+    lo_sizelimit: u64,
+    lo_number: u32,
+    lo_encrypt_type: u32,
+    lo_encrypt_key_size: u32,
+    lo_flags: u32,
+    lo_file_name: [u8; 64],
+    lo_crypt_name: [u8; 64],
+    lo_encrypt_key: [u8; 32],
+    lo_init: [u64; 2],
+}
+
+/// Boot sector of an empty FAT12 filesystem of `SECTORS`.
+fn boot_sector() -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    sector[..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+    sector[3..11].copy_from_slice(b"MSDOS5.0");
+    sector[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+    // sectors per cluster
+    sector[13] = 1;
+    // reserved sectors
+    sector[14..16].copy_from_slice(&1u16.to_le_bytes());
+    // FATs
+    sector[16] = 2;
+    // root directory entries
+    sector[17..19].copy_from_slice(&224u16.to_le_bytes());
+    sector[19..21].copy_from_slice(&(SECTORS as u16).to_le_bytes());
+    // media descriptor
+    sector[21] = 0xf0;
+    sector[22..24].copy_from_slice(&(SECTORS_PER_FAT as u16).to_le_bytes());
+    // sectors per track and heads
+    sector[24..26].copy_from_slice(&18u16.to_le_bytes());
+    sector[26..28].copy_from_slice(&2u16.to_le_bytes());
+    // extended boot signature, volume id, label and type
+    sector[38] = 0x29;
+    sector[39..43].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+    sector[43..54].copy_from_slice(b"NO NAME    ");
+    sector[54..62].copy_from_slice(b"FAT12   ");
+    sector[510] = 0x55;
+    sector[511] = 0xaa;
+    sector
+}
+
+/// Write an empty FAT12 filesystem into `fd`.
+fn format_image(fd: usize) -> bool {
+    let zero = [0u8; SECTOR_SIZE];
+    let mut fat = [0u8; SECTOR_SIZE];
+    // the first two entries are reserved, with the media descriptor
+    fat[..3].copy_from_slice(&[0xf0, 0xff, 0xff]);
+    let boot = boot_sector();
+    for i in 0..SECTORS {
+        let sector = match i {
+            0 => &boot,
+            // the first sector of both FATs
+            1 => &fat,
+            i if i == 1 + SECTORS_PER_FAT => &fat,
+            _ => &zero,
+        };
+        if write(fd, sector) != SECTOR_SIZE as isize {
+            return false;
+        }
+    }
+    true
+}
+
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = openat(path, OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+/// Bind a FAT image file to a free loop device, mount it and write a file in
+/// it, then mount it again to read the file back.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin loop test");
+    let image = openat(
+        IMAGE,
+        OpenFlags::O_RDWR | OpenFlags::O_CREATE | OpenFlags::O_TRUNC,
+    );
+    if image < 0 || !format_image(image as usize) {
+        println!("create {} failed", IMAGE);
+        return -1;
+    }
+    let ctl = openat("/dev/loop-control\0", OpenFlags::O_RDWR);
+    if ctl < 0 {
+        println!("open /dev/loop-control failed: {}", ctl);
+        return -1;
+    }
+    let number = ioctl(ctl as usize, LOOP_CTL_GET_FREE, 0);
+    close(ctl as usize);
+    if number < 0 {
+        println!("LOOP_CTL_GET_FREE failed: {}", number);
+        return -1;
+    }
+    let device = format!("/dev/loop{}\0", number);
+    let dev = openat(&device, OpenFlags::O_RDWR);
+    if dev < 0 {
+        println!("open {} failed: {}", device, dev);
+        return -1;
+    }
+    let dev = dev as usize;
+    if ioctl(dev, LOOP_SET_FD, image as usize) != 0 {
+        println!("LOOP_SET_FD failed");
+        return -1;
+    }
+    close(image as usize);
+    // the device is busy once bound
+    let ret = ioctl(dev, LOOP_SET_FD, dev);
+    if ret >= 0 {
+        println!("LOOP_SET_FD on a bound device returns {}", ret);
+        return -1;
+    }
+
+    let mut info: LoopInfo64 = unsafe { core::mem::zeroed() };
+    if ioctl(dev, LOOP_GET_STATUS64, &mut info as *mut _ as usize) != 0
+        || info.lo_number as isize != number
+        || !info.lo_file_name.starts_with(b"/loop_test.img")
+    {
+        println!("LOOP_GET_STATUS64 failed");
+        return -1;
+    }
+    // the device ends at the limit
+    info.lo_sizelimit = (SECTORS * SECTOR_SIZE) as u64;
+    ioctl(dev, LOOP_SET_STATUS64, &info as *const _ as usize);
+    let mut sector = [0u8; SECTOR_SIZE];
+    lseek(dev, (SECTORS * SECTOR_SIZE) as isize, 0);
+    if read(dev, &mut sector) != 0 {
+        println!("read beyond lo_sizelimit");
+        return -1;
+    }
+    lseek(dev, 0, 0);
+    if read(dev, &mut sector) != SECTOR_SIZE as isize || sector[510..] != [0x55, 0xaa] {
+        println!("read the boot sector through {} failed", device);
+        return -1;
+    }
+
+    mkdir(MOUNT_POINT, 0o755);
+    if mount(&device, MOUNT_POINT, "vfat\0", 0, "\0") != 0 {
+        println!("mount {} failed", device);
+        return -1;
+    }
+    let fd = openat(FILE, OpenFlags::O_RDWR | OpenFlags::O_CREATE);
+    if fd < 0 || write(fd as usize, DATA) != DATA.len() as isize {
+        println!("write {} failed", FILE);
+        return -1;
+    }
+    close(fd as usize);
+    if umount(MOUNT_POINT) != 0 {
+        println!("umount failed");
+        return -1;
+    }
+
+    if mount(&device, MOUNT_POINT, "vfat\0", 0, "\0") != 0 {
+        println!("mount {} again failed", device);
+        return -1;
+    }
+    let mut buf = [0u8; 64];
+    let len = read_file(FILE, &mut buf);
+    umount(MOUNT_POINT);
+    ioctl(dev, LOOP_CLR_FD, 0);
+    close(dev);
+    if len != DATA.len() as isize || &buf[..DATA.len()] != DATA {
+        println!("read back {} bytes, which differ", len);
+        return -1;
+    }
+    println!("loop test passed");
+    0
+}
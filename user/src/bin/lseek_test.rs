@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const ENXIO: isize = 6;
+const EINVAL: isize = 22;
+
+const PAGE_SIZE: isize = 4096;
+
+fn check(what: &str, ret: isize, expected: isize) -> bool {
+    if ret != expected {
+        println!("{}: got {}, expected {}", what, ret, expected);
+    }
+    ret == expected
+}
+
+/// Write past the end of a file on tmpfs to make a hole of three pages, then
+/// check lseek with every whence and that the hole reads as zeros.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin lseek test");
+    mkdir("/lseek_test\0", 0o755);
+    if mount("tmpfs\0", "/lseek_test\0", "tmpfs\0", 0, "\0") != 0 {
+        println!("mount tmpfs failed");
+        return -1;
+    }
+    let fd = openat(
+        "/lseek_test/file\0",
+        OpenFlags::O_CREATE | OpenFlags::O_RDWR,
+    );
+    if fd < 0 {
+        println!("open failed: {}", fd);
+        return -1;
+    }
+    let fd = fd as usize;
+    let hole = 3 * PAGE_SIZE;
+    let size = hole + 5;
+    let mut ok = true;
+    ok &= check("seek past end", lseek(fd, hole, SEEK_SET), hole);
+    ok &= check("write after hole", write(fd, b"hello"), 5);
+    ok &= check("seek end", lseek(fd, 0, SEEK_END), size);
+    ok &= check("seek data", lseek(fd, 0, SEEK_DATA), hole);
+    ok &= check("seek hole", lseek(fd, 0, SEEK_HOLE), 0);
+    ok &= check("seek hole in data", lseek(fd, hole + 1, SEEK_HOLE), size);
+    ok &= check("seek data at end", lseek(fd, size, SEEK_DATA), -ENXIO);
+    ok &= check("seek before start", lseek(fd, -1, SEEK_SET), -EINVAL);
+    ok &= check(
+        "seek cur before start",
+        lseek(fd, -size - 1, SEEK_CUR),
+        -EINVAL,
+    );
+
+    let mut buf = [0xffu8; PAGE_SIZE as usize];
+    ok &= check("seek start", lseek(fd, 0, SEEK_SET), 0);
+    ok &= check("read hole", read(fd, &mut buf), PAGE_SIZE);
+    if buf.iter().any(|&b| b != 0) {
+        println!("hole is not zero filled");
+        ok = false;
+    }
+    ok &= check("seek end back", lseek(fd, -5, SEEK_END), hole);
+    ok &= check("read data", read(fd, &mut buf[..5]), 5);
+    if &buf[..5] != b"hello" {
+        println!("data mismatch");
+        ok = false;
+    }
+    close(fd);
+
+    if ok {
+        println!("lseek test passed");
+        0
+    } else {
+        println!("lseek test failed");
+        -1
+    }
+}
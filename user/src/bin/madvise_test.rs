@@ -0,0 +1,104 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::ptr;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const SIZE: usize = 64 * 1024 * 1024;
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+const MADV_DONTNEED: usize = 4;
+const MADV_FREE: usize = 8;
+const ENOMEM: isize = 12;
+
+/// Free memory in KB, the MemFree line of /proc/meminfo.
+fn mem_free() -> Option<usize> {
+    let mut buf = [0u8; 1024];
+    let fd = openat("/proc/meminfo\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let line = info.lines().find(|line| line.starts_with("MemFree:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn touch(addr: *mut u8) {
+    for offset in (0..SIZE).step_by(PAGE_SIZE) {
+        unsafe { addr.add(offset).write_volatile(0xa5) };
+    }
+}
+
+/// Touch 64 MiB and madvise it away, which gives the frames back and reads as
+/// zeros afterwards.
+fn test_advice(advice: usize) -> bool {
+    let addr = mmap(
+        ptr::null(),
+        SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    if addr < 0 {
+        println!("mmap failed");
+        return false;
+    }
+    let addr = addr as *mut u8;
+    touch(addr);
+    let touched = mem_free().unwrap_or(0);
+    if madvise(addr, SIZE, advice) != 0 {
+        println!("madvise {} failed", advice);
+        return false;
+    }
+    let released = mem_free().unwrap_or(0);
+    println!("MemFree: {} KB touched, {} KB released", touched, released);
+    // most of the frames come back, some may be taken by others meanwhile
+    if released < touched + SIZE / 1024 * 3 / 4 {
+        println!("frames are not released by madvise {}", advice);
+        return false;
+    }
+    let zeroed = (0..SIZE)
+        .step_by(PAGE_SIZE)
+        .all(|offset| unsafe { addr.add(offset).read_volatile() } == 0);
+    munmap(addr, SIZE);
+    if !zeroed {
+        println!("pages read after madvise {} are not zeros", advice);
+        return false;
+    }
+    true
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin madvise test");
+    if !test_advice(MADV_DONTNEED) || !test_advice(MADV_FREE) {
+        return -1;
+    }
+    // the range unmapped above
+    let addr = mmap(
+        ptr::null(),
+        PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    munmap(addr as *const u8, PAGE_SIZE);
+    let ret = madvise(addr as *const u8, PAGE_SIZE, MADV_DONTNEED);
+    if ret != -ENOMEM {
+        println!("madvise on an unmapped range returns {}", ret);
+        return -1;
+    }
+    println!("madvise test passed");
+    0
+}
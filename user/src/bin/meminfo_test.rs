@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::ptr;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const MIB: usize = 1024 * 1024;
+/// RAM the kernel used to assume, whatever QEMU was given.
+const OLD_RAM_SIZE: usize = 128 * MIB;
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+/// Value in KB of the line `key` of /proc/meminfo.
+fn meminfo(key: &str) -> Option<usize> {
+    let mut buf = [0u8; 1024];
+    let fd = openat("/proc/meminfo\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let line = info.lines().find(|line| line.starts_with(key))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Check that /proc/meminfo is consistent, and when QEMU is given more RAM
+/// than the kernel used to assume, that the RAM beyond it is usable.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin meminfo test");
+    let (Some(total), Some(free)) = (meminfo("MemTotal:"), meminfo("MemFree:")) else {
+        println!("can not read /proc/meminfo");
+        return -1;
+    };
+    println!("MemTotal: {} KB, MemFree: {} KB", total, free);
+    if total == 0 || free > total {
+        println!("meminfo test failed");
+        return -1;
+    }
+    if total * 1024 < OLD_RAM_SIZE + 64 * MIB {
+        println!("no more RAM than {} MiB, skipped", OLD_RAM_SIZE / MIB);
+        println!("meminfo test passed");
+        return 0;
+    }
+
+    // more than fits below the old end of RAM
+    let size = OLD_RAM_SIZE + 32 * MIB;
+    let addr = mmap(
+        ptr::null(),
+        size,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    if addr < 0 {
+        println!("mmap failed");
+        return -1;
+    }
+    let addr = addr as *mut u8;
+    for offset in (0..size).step_by(PAGE_SIZE) {
+        unsafe { addr.add(offset).write_volatile((offset / PAGE_SIZE) as u8) };
+    }
+    let intact = (0..size)
+        .step_by(PAGE_SIZE)
+        .all(|offset| unsafe { addr.add(offset).read_volatile() } == (offset / PAGE_SIZE) as u8);
+    munmap(addr, size);
+    if !intact {
+        println!("pages touched are corrupted");
+        return -1;
+    }
+    println!("meminfo test passed");
+    0
+}
@@ -0,0 +1,111 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::ptr;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 8;
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+const MCL_CURRENT: usize = 0x1;
+const MCL_FUTURE: usize = 0x2;
+const ENOMEM: isize = 12;
+const EINVAL: isize = 22;
+
+fn map_pages() -> Option<*mut u8> {
+    let addr = mmap(
+        ptr::null(),
+        PAGES * PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    (addr >= 0).then_some(addr as *mut u8)
+}
+
+/// Minor page faults of this process, the 10th field of /proc/self/stat.
+fn minflt(buf: &mut [u8]) -> Option<usize> {
+    let fd = openat("/proc/self/stat\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    let stat = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    // the command name in parentheses may hold spaces
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(7)?.parse().ok()
+}
+
+/// Whether writing every page of `addr` takes no page fault.
+fn touch_without_fault(addr: *mut u8) -> bool {
+    let mut buf = [0u8; 512];
+    // fault in the buffer first
+    minflt(&mut buf);
+    let Some(before) = minflt(&mut buf) else {
+        return false;
+    };
+    for i in 0..PAGES {
+        unsafe { addr.add(i * PAGE_SIZE).write_volatile(i as u8) };
+    }
+    minflt(&mut buf) == Some(before)
+}
+
+/// Locked pages are faulted in at once, and locking an unmapped range fails.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin mlock test");
+    let Some(addr) = map_pages() else {
+        println!("mmap failed");
+        return -1;
+    };
+    if mlock(addr, PAGES * PAGE_SIZE) != 0 {
+        println!("mlock failed");
+        return -1;
+    }
+    if !touch_without_fault(addr) {
+        println!("locked pages are not faulted in");
+        return -1;
+    }
+    if munlock(addr, PAGES * PAGE_SIZE) != 0 {
+        println!("munlock failed");
+        return -1;
+    }
+    munmap(addr, PAGES * PAGE_SIZE);
+    let ret = mlock(addr, PAGE_SIZE);
+    if ret != -ENOMEM {
+        println!("mlock on an unmapped range returns {}", ret);
+        return -1;
+    }
+
+    if mlockall(0) != -EINVAL {
+        println!("mlockall without flags does not fail with EINVAL");
+        return -1;
+    }
+    if mlockall(MCL_CURRENT | MCL_FUTURE) != 0 {
+        println!("mlockall failed");
+        return -1;
+    }
+    // mapped after mlockall(MCL_FUTURE), so locked as well
+    let Some(addr) = map_pages() else {
+        println!("mmap after mlockall failed");
+        return -1;
+    };
+    let locked = touch_without_fault(addr);
+    munlockall();
+    munmap(addr, PAGES * PAGE_SIZE);
+    if !locked {
+        println!("pages mapped after mlockall(MCL_FUTURE) are not faulted in");
+        return -1;
+    }
+    println!("mlock test passed");
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Number of pipes, of which all but the last one have data to read.
+const PIPES: usize = 4;
+
+/// Check that ppoll(2) and pselect6(2) report all the ready file descriptors
+/// in one call, rather than only the first one found ready.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("multi ready");
+    let mut pipes = [[0i32; 2]; PIPES];
+    for (i, fds) in pipes.iter_mut().enumerate() {
+        if pipe(fds) < 0 {
+            println!("pipe failed");
+            return -1;
+        }
+        if i + 1 < PIPES {
+            write(fds[1] as usize, b"x");
+        }
+    }
+
+    let mut fds = pipes.map(|fds| PollFd {
+        fd: fds[0],
+        events: POLLIN,
+        revents: 0,
+    });
+    let ret = ppoll(&mut fds, 1000);
+    result.check(
+        "number of ready fds of ppoll",
+        matches!(ret, Ok(n) if n == PIPES - 1),
+    );
+    for (i, fd) in fds.iter().enumerate() {
+        let expected = if i + 1 < PIPES { POLLIN } else { 0 };
+        result.check("revents of ppoll", fd.revents & POLLIN == expected);
+    }
+
+    let mut readfds = FdSet::default();
+    let mut writefds = FdSet::default();
+    for fds in pipes.iter() {
+        readfds.set(fds[0] as usize);
+    }
+    writefds.set(pipes[PIPES - 1][1] as usize);
+    let nfds = pipes.iter().flatten().max().unwrap() + 1;
+    let mut timeout = TimeSpec::from_ms(1000);
+    let ret = pselect(nfds as usize, &mut readfds, &mut writefds, &mut timeout);
+    result.check(
+        "number of ready fds of pselect",
+        matches!(ret, Ok(n) if n == PIPES),
+    );
+    for (i, fds) in pipes.iter().enumerate() {
+        result.check(
+            "readfds of pselect",
+            readfds.is_set(fds[0] as usize) == (i + 1 < PIPES),
+        );
+    }
+    result.check(
+        "writefds of pselect",
+        writefds.is_set(pipes[PIPES - 1][1] as usize),
+    );
+
+    for fds in pipes {
+        close(fds[0] as usize);
+        close(fds[1] as usize);
+    }
+    result.finish()
+}
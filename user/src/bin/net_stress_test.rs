@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const PORT: u16 = 5556;
+/// Seconds for which connections are made and accepted.
+const DURATION_SEC: usize = 60;
+
+fn now_usec() -> usize {
+    let mut time_val = TimeVal::from_usec(0);
+    gettimeofday(&mut time_val);
+    time_val.into_usec()
+}
+
+fn addr() -> SockAddr {
+    SockAddr::In(SockAddrIn::new([127, 0, 0, 1], PORT))
+}
+
+/// Connect and close at once until `deadline`, and return the number of
+/// connections made.
+fn spam_connects(deadline: usize) -> Result<usize, SyscallErr> {
+    let mut connects = 0;
+    while now_usec() < deadline {
+        let sockfd = socket(SaFamily::Inet, SocketType::Stream, SocketFlags::empty())?;
+        match connect(sockfd, &addr()) {
+            Ok(()) => connects += 1,
+            Err(SyscallErr::ECONNREFUSED) => {}
+            Err(err) => {
+                close(sockfd);
+                return Err(err);
+            }
+        }
+        close(sockfd);
+    }
+    Ok(connects)
+}
+
+/// Wait for connections by ppoll and accept them until `deadline`, and return
+/// the number of connections accepted.
+fn accept_all(listener: usize, deadline: usize) -> Result<usize, SyscallErr> {
+    let mut accepts = 0;
+    while now_usec() < deadline {
+        let mut fds = [PollFd {
+            fd: listener as i32,
+            events: POLLIN,
+            revents: 0,
+        }];
+        if ppoll(&mut fds, 100)? == 0 || fds[0].revents & POLLIN == 0 {
+            continue;
+        }
+        let (conn, _) = accept(listener)?;
+        close(conn);
+        accepts += 1;
+    }
+    Ok(accepts)
+}
+
+/// Make connections to a listener as fast as possible while it is polled and
+/// accepting, which walks the socket set, the listen table and the interface
+/// from both sides along with the poll timer, and must not hang.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin net stress test for {} s", DURATION_SEC);
+    let listener =
+        match socket(SaFamily::Inet, SocketType::Stream, SocketFlags::empty()).and_then(|fd| {
+            setsockopt(fd, SOL_SOCKET, SO_REUSEADDR, &1i32)?;
+            bind(fd, &addr())?;
+            listen(fd, 16)?;
+            Ok(fd)
+        }) {
+            Ok(fd) => fd,
+            Err(err) => {
+                println!("listen failed: {:?}", err);
+                return -1;
+            }
+        };
+    let deadline = now_usec() + DURATION_SEC * 1_000_000;
+    let pid = fork();
+    if pid == 0 {
+        close(listener);
+        match spam_connects(deadline) {
+            Ok(connects) => {
+                println!("{} connections made", connects);
+                exit(0);
+            }
+            Err(err) => {
+                println!("connect failed: {:?}", err);
+                exit(-1);
+            }
+        }
+    }
+    let accepted = accept_all(listener, deadline);
+    close(listener);
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    match accepted {
+        Ok(accepts) if accepts > 0 && ExitStatus(wstatus).success() => {
+            println!("{} connections accepted", accepts);
+            println!("net stress test passed");
+            0
+        }
+        Ok(accepts) => {
+            println!("{} connections accepted, status {:#x}", accepts, wstatus);
+            -1
+        }
+        Err(err) => {
+            println!("accept failed: {:?}", err);
+            -1
+        }
+    }
+}
@@ -0,0 +1,143 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const IPC_PRIVATE: usize = 0;
+const IPC_CREAT: usize = 0o1000;
+const IPC_RMID: usize = 0;
+
+/// Hart both hogs are pinned on.
+const HART: usize = 0;
+/// Time the hogs spin for, in ms.
+const SPIN_MS: usize = 1000;
+const NICES: [i32; 2] = [0, 19];
+
+/// Shared by the hogs and the parent through a System V shared memory segment.
+#[repr(C)]
+struct Shared {
+    stop: AtomicBool,
+    spins: [AtomicUsize; 2],
+}
+
+fn attach(shmid: usize) -> Option<&'static Shared> {
+    let addr = shmat(shmid, ptr::null(), 0);
+    (addr >= 0).then(|| unsafe { &*(addr as *const Shared) })
+}
+
+fn errno(ret: isize) -> Option<SyscallErr> {
+    SyscallErr::from_ret(ret).err()
+}
+
+/// Fork a child that runs `f` and wait for it, which succeeds if the child
+/// exits with 0.
+fn run_child(f: impl FnOnce() -> i32) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus) == pid && ExitStatus(wstatus).success()
+}
+
+/// An unprivileged process may raise its own nice value, but neither lower it
+/// nor renice a process of another user.
+fn renice_without_permission() -> i32 {
+    let parent = getppid() as usize;
+    if setuid(1000) < 0 {
+        return -1;
+    }
+    let raised = setpriority(PRIO_PROCESS, 0, 5) == 0 && getpriority(PRIO_PROCESS, 0) == 5;
+    match (
+        raised,
+        errno(setpriority(PRIO_PROCESS, 0, 0)),
+        errno(setpriority(PRIO_PROCESS, parent, 10)),
+    ) {
+        (true, Some(SyscallErr::EACCES), Some(SyscallErr::EPERM)) => 0,
+        _ => -1,
+    }
+}
+
+/// Spin on `HART` with the nice value of `NICES[i]` until stopped.
+fn hog(shmid: usize, i: usize) -> i32 {
+    let Some(shared) = attach(shmid) else {
+        return -1;
+    };
+    sched_setaffinity(0, 1 << HART);
+    setpriority(PRIO_PROCESS, 0, NICES[i]);
+    while !shared.stop.load(Ordering::Relaxed) {
+        shared.spins[i].fetch_add(1, Ordering::Relaxed);
+    }
+    0
+}
+
+/// Check setpriority(2) and getpriority(2), including the permissions and the
+/// inheritance across fork, and that a hog of nice 0 gets clearly more cpu
+/// time than one of nice 19 on the same hart.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("nice");
+
+    result.check("default nice", getpriority(PRIO_PROCESS, 0) == 0);
+    result.check(
+        "setpriority",
+        setpriority(PRIO_PROCESS, 0, 3) == 0 && getpriority(PRIO_PROCESS, 0) == 3,
+    );
+    result.check(
+        "nice inherited across fork",
+        run_child(|| {
+            if getpriority(PRIO_PROCESS, 0) == 3 {
+                0
+            } else {
+                -1
+            }
+        }),
+    );
+    result.check(
+        "setpriority back to 0",
+        setpriority(PRIO_PROCESS, 0, 0) == 0 && getpriority(PRIO_PROCESS, 0) == 0,
+    );
+    result.check(
+        "renice without permission",
+        run_child(renice_without_permission),
+    );
+
+    let shmid = shmget(IPC_PRIVATE, PAGE_SIZE, IPC_CREAT | 0o600);
+    let Some(shared) = (shmid >= 0).then(|| attach(shmid as usize)).flatten() else {
+        println!("can not share memory with the hogs");
+        return -1;
+    };
+    let shmid = shmid as usize;
+    let pids = [0, 1].map(|i| {
+        let pid = fork();
+        if pid == 0 {
+            exit(hog(shmid, i));
+        }
+        pid as usize
+    });
+    sleep(SPIN_MS);
+    shared.stop.store(true, Ordering::Relaxed);
+    for pid in pids {
+        let mut wstatus = 0;
+        waitpid(pid, &mut wstatus);
+        result.check("hog", ExitStatus(wstatus).success());
+    }
+    let spins = [0, 1].map(|i| shared.spins[i].load(Ordering::Relaxed));
+    println!(
+        "spins of nice {}: {}, nice {}: {}",
+        NICES[0], spins[0], NICES[1], spins[1]
+    );
+    result.check("cpu time shares", spins[0] >= spins[1] * 4);
+    shmdt(shared as *const Shared as *const u8);
+    shmctl(shmid, IPC_RMID, 0);
+
+    result.finish()
+}
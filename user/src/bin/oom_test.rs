@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::vec::Vec;
+use core::ptr;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+const MAP_POPULATE: i32 = 0x08000;
+const ENOMEM: isize = 12;
+const SIGKILL: i32 = 9;
+
+/// Size of each populated mapping.
+const CHUNK: usize = 1024 * 1024;
+/// Size of the lazy mapping, which is larger than the memory of any board but
+/// fits in the mmap range.
+const LAZY_SIZE: usize = 6 * 1024 * 1024 * 1024;
+
+fn map(length: usize, flags: i32) -> isize {
+    mmap(
+        ptr::null(),
+        length,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS | flags,
+        usize::MAX,
+        0,
+    )
+}
+
+/// Populate mappings until mmap fails, which must be with ENOMEM, then unmap
+/// them.
+fn populate_until_enomem() -> i32 {
+    let mut chunks = Vec::new();
+    let ret = loop {
+        let ret = map(CHUNK, MAP_POPULATE);
+        if ret < 0 {
+            break ret;
+        }
+        chunks.push(ret as usize);
+    };
+    println!("{} MB populated before mmap failed", chunks.len());
+    for &chunk in chunks.iter() {
+        munmap(chunk as *const u8, CHUNK);
+    }
+    if ret != -ENOMEM || chunks.is_empty() {
+        println!("mmap returned {}", ret);
+        return -1;
+    }
+    0
+}
+
+/// Touch a lazy mapping larger than the memory, for which the process must
+/// be killed by SIGKILL.
+fn touch_until_killed() -> i32 {
+    let ret = map(LAZY_SIZE, 0);
+    if ret < 0 {
+        println!("lazy mmap returned {}", ret);
+        return -1;
+    }
+    for offset in (0..LAZY_SIZE).step_by(PAGE_SIZE) {
+        unsafe { ptr::write_volatile((ret as usize + offset) as *mut u8, 1) };
+    }
+    println!("touched {} MB without being killed", LAZY_SIZE >> 20);
+    -1
+}
+
+fn run_child(f: fn() -> i32) -> ExitStatus {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    ExitStatus(wstatus)
+}
+
+/// Exhaust the memory in children, which must get ENOMEM from mmap, or be
+/// killed when faulting in a page, while the system stays usable afterward.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin oom test");
+    if !run_child(populate_until_enomem).success() {
+        println!("populated mmap did not fail with ENOMEM");
+        return -1;
+    }
+    let status = run_child(touch_until_killed);
+    if status.signal() != Some(SIGKILL) {
+        println!(
+            "lazy mmap was not killed by SIGKILL, status {:#x}",
+            status.0
+        );
+        return -1;
+    }
+
+    // the memory is given back to the system
+    let ret = map(CHUNK, MAP_POPULATE);
+    if ret < 0 {
+        println!("mmap after oom returned {}", ret);
+        return -1;
+    }
+    munmap(ret as *const u8, CHUNK);
+    if !run_child(|| 0).success() {
+        println!("fork after oom failed");
+        return -1;
+    }
+    println!("oom test passed");
+    0
+}
@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const DIR: &str = "/open_flags_test\0";
+const FILE: &str = "/open_flags_test/file\0";
+const LINK: &str = "/open_flags_test/link\0";
+
+fn errno(ret: isize) -> Option<SyscallErr> {
+    SyscallErr::from_ret(ret).err()
+}
+
+/// File type of the open file `fd`.
+fn type_of(fd: isize) -> Option<u32> {
+    let mut st = Stat::default();
+    (fstat(fd as usize, &mut st) == 0).then_some(st.st_mode & S_IFMT)
+}
+
+/// Check that openat(2) enforces O_DIRECTORY and O_NOFOLLOW, and that O_PATH
+/// gives a descriptor which refuses IO but serves as a directory fd, for fstat
+/// and for fchdir, and refers to a symlink itself with O_NOFOLLOW.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("open flags");
+    mkdir(DIR, 0o755);
+    let fd = openat(FILE, OpenFlags::O_CREATE | OpenFlags::O_WRONLY);
+    if fd < 0 || symlink("file\0", LINK) != 0 {
+        println!("can not create the files");
+        return -1;
+    }
+    write(fd as usize, b"data");
+    close(fd as usize);
+
+    result.check(
+        "O_DIRECTORY on a file",
+        errno(openat(FILE, OpenFlags::O_DIRECTORY)) == Some(SyscallErr::ENOTDIR),
+    );
+    result.check(
+        "O_NOFOLLOW on a symlink",
+        errno(openat(LINK, OpenFlags::O_NOFOLLOW)) == Some(SyscallErr::ELOOP),
+    );
+    let fd = openat(LINK, OpenFlags::O_RDONLY);
+    result.check("open through a symlink", type_of(fd) == Some(S_IFREG));
+    close(fd as usize);
+
+    let fd = openat(FILE, OpenFlags::O_PATH | OpenFlags::O_RDWR);
+    result.check("fstat of an O_PATH file", type_of(fd) == Some(S_IFREG));
+    result.check(
+        "read of an O_PATH file",
+        errno(read(fd as usize, &mut [0u8; 4])) == Some(SyscallErr::EBADF),
+    );
+    result.check(
+        "write of an O_PATH file",
+        errno(write(fd as usize, b"data")) == Some(SyscallErr::EBADF),
+    );
+    close(fd as usize);
+
+    let dirfd = openat(DIR, OpenFlags::O_PATH | OpenFlags::O_DIRECTORY);
+    result.check(
+        "fstat of an O_PATH directory",
+        type_of(dirfd) == Some(S_IFDIR),
+    );
+    let fd = openat_dirfd(dirfd, "file\0", OpenFlags::O_RDONLY, 0);
+    let mut buf = [0u8; 4];
+    result.check(
+        "open relative to an O_PATH directory",
+        fd >= 0 && read(fd as usize, &mut buf) == 4 && &buf == b"data",
+    );
+    close(fd as usize);
+    let fd = openat_dirfd(
+        dirfd,
+        "link\0",
+        OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW,
+        0,
+    );
+    result.check(
+        "O_PATH | O_NOFOLLOW on a symlink",
+        type_of(fd) == Some(S_IFLNK),
+    );
+    close(fd as usize);
+    let mut st = Stat::default();
+    result.check(
+        "fchdir to an O_PATH directory",
+        fchdir(dirfd as usize) == 0 && stat("file\0", &mut st) == 0,
+    );
+    chdir("/\0");
+    close(dirfd as usize);
+
+    unlink(LINK);
+    unlink(FILE);
+    rmdir(DIR);
+    result.finish()
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::convert::TryInto;
+
+use user_lib::*;
+
+/// Times to check every 10 ms, i.e. a second.
+const RETRIES: usize = 100;
+
+fn retry(mut f: impl FnMut() -> bool) -> bool {
+    for _ in 0..RETRIES {
+        if f() {
+            return true;
+        }
+        sleep(10);
+    }
+    false
+}
+
+/// Report its pid, ppid and pgid once it is reparented, and exit.
+fn grandchild(fd: usize) -> ! {
+    retry(|| getppid() == 1);
+    let ids = [getpid(), getppid(), getpgid(0)].map(|id| id as usize);
+    let mut buf = [0u8; 24];
+    for (chunk, id) in buf.chunks_mut(8).zip(ids) {
+        chunk.copy_from_slice(&id.to_ne_bytes());
+    }
+    write(fd, &buf);
+    exit(0);
+}
+
+/// Exit the middle process of three, whose child must be reparented to init
+/// with its process group unchanged, and reaped by init after it exits.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin orphan test");
+    let mut fds = [0i32; 2];
+    if pipe(&mut fds) < 0 {
+        println!("pipe failed");
+        return -1;
+    }
+    let (rfd, wfd) = (fds[0] as usize, fds[1] as usize);
+
+    let middle = fork();
+    if middle == 0 {
+        close(rfd);
+        setpgid(0, 0);
+        if fork() == 0 {
+            grandchild(wfd);
+        }
+        exit(0);
+    }
+    close(wfd);
+    let mut wstatus = 0;
+    if middle < 0 || waitpid(middle as usize, &mut wstatus) != middle {
+        println!("wait for the middle process failed");
+        return -1;
+    }
+
+    let mut buf = [0u8; 24];
+    if read(rfd, &mut buf) != buf.len() as isize {
+        println!("no report from the grandchild");
+        return -1;
+    }
+    close(rfd);
+    let [pid, ppid, pgid] =
+        [0, 1, 2].map(|i| usize::from_ne_bytes(buf[i * 8..i * 8 + 8].try_into().unwrap()) as isize);
+    println!("grandchild {}: ppid {}, pgid {}", pid, ppid, pgid);
+    let mut ok = true;
+    if ppid != 1 {
+        println!("grandchild is not reparented to init");
+        ok = false;
+    }
+    if pgid != middle {
+        println!("process group of the grandchild changed");
+        ok = false;
+    }
+    let reaped = retry(|| {
+        matches!(
+            SyscallErr::from_ret(kill(pid, Sig::from_i32(0))),
+            Err(SyscallErr::ESRCH)
+        )
+    });
+    if !reaped {
+        println!("grandchild is not reaped by init");
+        ok = false;
+    }
+
+    if ok {
+        println!("orphan test passed");
+        0
+    } else {
+        -1
+    }
+}
@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::time::Duration;
+
+use user_lib::*;
+
+/// Times the calling thread has blocked in the kernel.
+fn blocked_times() -> usize {
+    let mut usage = Rusage::default();
+    getrusage(RUSAGE_THREAD, &mut usage);
+    usage.nvcsw
+}
+
+fn now() -> Duration {
+    let mut ts = TimeSpec::default();
+    clock_gettime(CLOCK_MONOTONIC, &mut ts);
+    ts.into()
+}
+
+/// Check that ppoll(2) on an idle pipe sleeps until its timeout instead of
+/// waking up repeatedly, and that pselect6(2) returning early writes back the
+/// time remaining.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("poll wakeup");
+    let mut fds = [0i32; 2];
+    if pipe(&mut fds) < 0 {
+        println!("pipe failed");
+        return -1;
+    }
+    let (rfd, wfd) = (fds[0] as usize, fds[1] as usize);
+
+    let mut pollfds = [PollFd {
+        fd: rfd as i32,
+        events: POLLIN,
+        revents: 0,
+    }];
+    let blocked = blocked_times();
+    let begin = now();
+    let ret = ppoll(&mut pollfds, 1000);
+    let elapsed = now() - begin;
+    let wakeups = blocked_times() - blocked;
+    println!("ppoll of 1s blocked {} times", wakeups);
+    result.check("ppoll expired", matches!(ret, Ok(0)));
+    result.check("time of ppoll", elapsed >= Duration::from_secs(1));
+    result.check("wakeups of ppoll", wakeups <= 3);
+
+    let writer = thread::spawn(move || {
+        sleep(100);
+        write(wfd, b"x");
+    })
+    .unwrap();
+    let mut readfds = FdSet::default();
+    readfds.set(rfd);
+    let mut timeout = TimeSpec::from_ms(1000);
+    let ret = pselect(rfd + 1, &mut readfds, &mut FdSet::default(), &mut timeout);
+    writer.join();
+    let remaining: Duration = timeout.into();
+    println!("pselect of 1s returned with {:?} remaining", remaining);
+    result.check("pselect returned early", matches!(ret, Ok(1)));
+    result.check(
+        "remaining time of pselect",
+        remaining > Duration::from_millis(500) && remaining < Duration::from_millis(950),
+    );
+
+    close(rfd);
+    close(wfd);
+    result.finish()
+}
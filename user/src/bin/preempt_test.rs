@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use user_lib::*;
+
+/// Hart both spinning threads are pinned on.
+const HART: usize = 0;
+/// Time the threads spin for, in ms.
+const SPIN_MS: usize = 1000;
+
+static SPINS: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Spin on `HART` without ever blocking or yielding.
+fn spin(i: usize) {
+    sched_setaffinity(0, 1 << HART);
+    while !STOP.load(Ordering::Relaxed) {
+        SPINS[i].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Two threads that never give up the hart they share are preempted when
+/// their time slices are used up, so both make progress with roughly equal
+/// shares of the time.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin preempt test");
+    let first = thread::spawn(|| spin(0)).unwrap();
+    let second = thread::spawn(|| spin(1)).unwrap();
+    sleep(SPIN_MS);
+    STOP.store(true, Ordering::Relaxed);
+    first.join();
+    second.join();
+
+    let spins = [
+        SPINS[0].load(Ordering::Relaxed),
+        SPINS[1].load(Ordering::Relaxed),
+    ];
+    println!("spins of the threads: {} {}", spins[0], spins[1]);
+    let (min, max) = (spins[0].min(spins[1]), spins[0].max(spins[1]));
+    if min == 0 || min * 3 < max {
+        println!("preempt test failed: the hart is not shared fairly");
+        return -1;
+    }
+    println!("preempt test passed");
+    0
+}
@@ -8,7 +8,7 @@ extern crate alloc;
 use alloc::{borrow::ToOwned, ffi::CString, string::ToString};
 use core::ffi::CStr;
 
-use user_lib::{execve, fork, println, wait, waitpid};
+use user_lib::{fork, println, spawn, wait};
 
 const TESTCASES: [&str; 32] = [
     "brk",
@@ -52,15 +52,11 @@ fn main() -> i32 {
     println!("******************************");
     if fork() == 0 {
         for testcase in TESTCASES {
-            let pid = fork();
-            if pid == 0 {
-                if execve(testcase, &[testcase], &[]) != 0 {
-                    println!("Error when executing!");
-                    return 0;
+            match spawn(testcase, &[testcase], &[]) {
+                Ok(pid) => {
+                    let _ = pid.wait();
                 }
-            } else {
-                let mut exit_code: i32 = 0;
-                waitpid(pid as usize, &mut exit_code);
+                Err(err) => println!("Error when executing {}: {:?}", testcase, err),
             }
         }
         println!("******************************");
@@ -0,0 +1,191 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const SYSCALL_GETPPID: usize = 173;
+const VALUE: usize = 0x6563_6172_7470;
+const POKED: usize = 0x6465_6b6f_70;
+
+/// Read and written by the tracer at the same address in the tracee, which is
+/// a fork of it.
+static mut DATA: usize = VALUE;
+
+fn errno(ret: isize) -> Option<SyscallErr> {
+    SyscallErr::from_ret(ret).err()
+}
+
+fn wait_status(pid: usize) -> ExitStatus {
+    let mut wstatus = 0;
+    waitpid(pid, &mut wstatus);
+    ExitStatus(wstatus)
+}
+
+fn stopped_by(status: ExitStatus, sig: Sig) -> bool {
+    status.stopped() == Some(sig.raw() as i32)
+}
+
+/// Be traced, stop, and then exit with zero if the tracer has poked `DATA`.
+fn tracee() -> ! {
+    ptrace(PTRACE_TRACEME, 0, 0, 0);
+    kill(getpid(), Sig::SIGSTOP);
+    getppid();
+    let data = unsafe { core::ptr::read_volatile(core::ptr::addr_of!(DATA)) };
+    exit(if data == POKED { 0 } else { 1 });
+}
+
+/// Check ptrace(2) into `result`: the tracee stops for the tracer, whose memory
+/// and registers are accessed, and whose syscalls are stopped at.
+fn trace_child(result: &mut TestResult) {
+    let pid = fork();
+    if pid == 0 {
+        tracee();
+    }
+    let pid = pid as usize;
+    result.check(
+        "signal-delivery-stop",
+        stopped_by(wait_status(pid), Sig::SIGSTOP),
+    );
+
+    let addr = unsafe { core::ptr::addr_of!(DATA) } as usize;
+    let mut word = 0usize;
+    let ret = ptrace(PTRACE_PEEKDATA, pid, addr, &mut word as *mut usize as usize);
+    result.check("PTRACE_PEEKDATA", ret == 0 && word == VALUE);
+    result.check(
+        "PTRACE_POKEDATA",
+        ptrace(PTRACE_POKEDATA, pid, addr, POKED) == 0,
+    );
+    result.check(
+        "PTRACE_PEEKDATA at null",
+        matches!(
+            errno(ptrace(
+                PTRACE_PEEKDATA,
+                pid,
+                0,
+                &mut word as *mut usize as usize
+            )),
+            Some(SyscallErr::EIO)
+        ),
+    );
+    let mut regs = [0usize; 32];
+    result.check(
+        "PTRACE_GETREGS",
+        ptrace(PTRACE_GETREGS, pid, 0, regs.as_mut_ptr() as usize) == 0 && regs[0] != 0,
+    );
+    result.check(
+        "PTRACE_SETREGS",
+        ptrace(PTRACE_SETREGS, pid, 0, regs.as_ptr() as usize) == 0,
+    );
+
+    // The SIGSTOP is suppressed, and the tracee stops at each syscall entry and
+    // exit from now on.
+    let mut getppid_stops = 0;
+    let mut sig = 0;
+    let status = loop {
+        ptrace(PTRACE_SYSCALL, pid, 0, sig);
+        let status = wait_status(pid);
+        sig = 0;
+        match status.stopped() {
+            Some(s) if s == Sig::SIGTRAP.raw() as i32 => {
+                ptrace(PTRACE_GETREGS, pid, 0, regs.as_mut_ptr() as usize);
+                if regs[17] == SYSCALL_GETPPID {
+                    getppid_stops += 1;
+                }
+            }
+            Some(s) => sig = s as usize,
+            None => break status,
+        }
+    };
+    result.check("syscall-stops of getppid", getppid_stops == 2);
+    result.check("exit of the tracee", status.success());
+}
+
+/// Attach to a running child, which is sent SIGSTOP, and detach from it.
+fn attach_child(result: &mut TestResult) {
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            sleep(10);
+        }
+    }
+    let pid = pid as usize;
+    let mut word = 0usize;
+    result.check(
+        "PTRACE_PEEKDATA of a child not traced",
+        matches!(
+            errno(ptrace(
+                PTRACE_PEEKDATA,
+                pid,
+                0,
+                &mut word as *mut usize as usize
+            )),
+            Some(SyscallErr::ESRCH)
+        ),
+    );
+    result.check("PTRACE_ATTACH", ptrace(PTRACE_ATTACH, pid, 0, 0) == 0);
+    result.check(
+        "PTRACE_ATTACH twice",
+        matches!(
+            errno(ptrace(PTRACE_ATTACH, pid, 0, 0)),
+            Some(SyscallErr::EPERM)
+        ),
+    );
+    result.check("stop on attach", stopped_by(wait_status(pid), Sig::SIGSTOP));
+    result.check("PTRACE_DETACH", ptrace(PTRACE_DETACH, pid, 0, 0) == 0);
+    result.check(
+        "PTRACE_CONT after detach",
+        matches!(
+            errno(ptrace(PTRACE_CONT, pid, 0, 0)),
+            Some(SyscallErr::ESRCH)
+        ),
+    );
+    kill(pid as isize, Sig::SIGKILL);
+    result.check(
+        "SIGKILL after detach",
+        wait_status(pid).signal() == Some(Sig::SIGKILL.raw() as i32),
+    );
+}
+
+/// Check ptrace(2) on children, and trace this program by strace.
+#[no_mangle]
+fn main(_argc: usize, argv: &[&str]) -> i32 {
+    // Run as the program traced by strace.
+    if argv.get(1) == Some(&"traced") {
+        getppid();
+        return 42;
+    }
+
+    let mut result = TestResult::begin("ptrace");
+
+    result.check(
+        "PTRACE_ATTACH to init",
+        matches!(
+            errno(ptrace(PTRACE_ATTACH, 1, 0, 0)),
+            Some(SyscallErr::EPERM)
+        ),
+    );
+    result.check(
+        "PTRACE_ATTACH to itself",
+        matches!(
+            errno(ptrace(PTRACE_ATTACH, getpid() as usize, 0, 0)),
+            Some(SyscallErr::EPERM)
+        ),
+    );
+    trace_child(&mut result);
+    attach_child(&mut result);
+
+    match spawn("strace", &["strace", "ptrace_test", "traced"], &[]) {
+        Ok(child) => result.check(
+            "exit code through strace",
+            child.wait().is_ok_and(|status| status.code() == Some(42)),
+        ),
+        Err(err) => {
+            println!("spawn failed: {:?}", err);
+            result.check("spawn", false);
+        }
+    }
+
+    result.finish()
+}
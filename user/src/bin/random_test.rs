@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Read a procfs file into `buf` and return its content.
+fn read_file<'a>(path: &str, buf: &'a mut [u8]) -> Option<&'a str> {
+    let fd = openat(path, OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    core::str::from_utf8(&buf[..len.max(0) as usize]).ok()
+}
+
+fn entropy_avail() -> Option<usize> {
+    let mut buf = [0u8; 32];
+    read_file("/proc/sys/kernel/random/entropy_avail\0", &mut buf)?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Whether there is an entropy device, which is listed in /proc/interrupts.
+fn has_hwrng() -> bool {
+    let mut buf = [0u8; 4096];
+    read_file("/proc/interrupts\0", &mut buf).is_some_and(|info| info.contains("virtio-rng"))
+}
+
+/// Reads of /dev/urandom differ from each other, and the entropy device, if
+/// there is one, keeps crediting the pool as it is drained.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin random test");
+    let fd = openat("/dev/urandom\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        println!("open /dev/urandom failed: {}", fd);
+        return -1;
+    }
+    let mut first = [0u8; 64];
+    let mut second = [0u8; 64];
+    read(fd as usize, &mut first);
+    read(fd as usize, &mut second);
+    if first == second || first.iter().all(|&b| b == 0) {
+        println!("reads of /dev/urandom repeat");
+        close(fd as usize);
+        return -1;
+    }
+    let Some(before) = entropy_avail() else {
+        println!("no /proc/sys/kernel/random/entropy_avail");
+        close(fd as usize);
+        return -1;
+    };
+    println!("entropy_avail: {}", before);
+    if !has_hwrng() {
+        println!("no entropy device, random test passed");
+        close(fd as usize);
+        return 0;
+    }
+    // drain the pool below its low watermark, then wait for the refill
+    let mut buf = [0u8; 256];
+    read(fd as usize, &mut buf);
+    close(fd as usize);
+    for _ in 0..100 {
+        let avail = entropy_avail().unwrap();
+        if avail > 0 {
+            println!("entropy_avail after drained: {}", avail);
+            println!("random test passed");
+            return 0;
+        }
+        sleep(10);
+    }
+    println!("no entropy mixed in from the device");
+    -1
+}
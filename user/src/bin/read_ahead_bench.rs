@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const FILE: &str = "/busybox\0";
+const BUF_SIZE: usize = 4096;
+
+fn now_usec() -> usize {
+    let mut time_val = TimeVal::from_usec(0);
+    gettimeofday(&mut time_val);
+    time_val.into_usec()
+}
+
+/// Read pages in `[start, end)` of `fd` one by one, backwards or forwards, and
+/// return the throughput in KiB/s. Reading backwards seeks before every read,
+/// so nothing is read ahead.
+fn bench(fd: usize, start: usize, end: usize, backwards: bool) -> usize {
+    let mut buf = [0u8; BUF_SIZE];
+    let begin = now_usec();
+    let mut offset = start;
+    lseek(fd, start as isize, SEEK_SET);
+    while offset < end {
+        if backwards {
+            lseek(fd, (end - BUF_SIZE - (offset - start)) as isize, SEEK_SET);
+        }
+        if read(fd, &mut buf) <= 0 {
+            break;
+        }
+        offset += BUF_SIZE;
+    }
+    let usec = (now_usec() - begin).max(1);
+    (offset - start) / 1024 * 1_000_000 / usec
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin read ahead bench");
+    let fd = openat(FILE, OpenFlags::O_RDONLY);
+    if fd < 0 {
+        println!("open {} failed: {}", FILE, fd);
+        return -1;
+    }
+    let fd = fd as usize;
+    // Both halves are cold in the page cache.
+    let half = (lseek(fd, 0, SEEK_END) as usize / 2) & !(BUF_SIZE - 1);
+    let random = bench(fd, 0, half, true);
+    let sequential = bench(fd, half, 2 * half, false);
+    println!("without read ahead: {} KiB/s", random);
+    println!("with read ahead: {} KiB/s", sequential);
+    close(fd);
+    0
+}
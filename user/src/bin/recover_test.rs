@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Exit code of a child whose kernel does not panic on `PR_PANIC`.
+const UNSUPPORTED: i32 = 2;
+
+/// Fork a child which panics the kernel in a syscall while another of its
+/// threads sleeps, and return its exit status.
+fn panic_in_child() -> ExitStatus {
+    let pid = fork();
+    if pid == 0 {
+        thread::spawn(|| {
+            sleep(10000);
+        })
+        .unwrap();
+        prctl(PR_PANIC, 0, 0, 0);
+        exit(UNSUPPORTED);
+    }
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    ExitStatus(wstatus)
+}
+
+/// A panic in a syscall must only kill the process making it, with all its
+/// threads, when the kernel recovers from panics in syscalls. It must be
+/// recovered from more than once, and other processes keep running.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin recover test");
+    for round in 0..2 {
+        let status = panic_in_child();
+        if status.code() == Some(UNSUPPORTED) {
+            println!("the kernel does not recover from panics, skipped");
+            return 0;
+        }
+        if status.signal() != Some(Sig::SIGKILL.raw() as i32) {
+            println!("child panicking in round {} is not killed", round);
+            return -1;
+        }
+    }
+
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    let mut wstatus = 0;
+    if waitpid(pid as usize, &mut wstatus) != pid || !ExitStatus(wstatus).success() {
+        println!("can not run a new process after the panics");
+        return -1;
+    }
+    println!("recover test passed");
+    0
+}
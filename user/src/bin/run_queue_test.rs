@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user_lib::*;
+
+const THREADS: usize = 4000;
+/// Threads running at the same time.
+const BATCH: usize = 200;
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Read the numbers of tasks fetched and stolen by each hart from
+/// /proc/schedstat.
+fn read_schedstat() -> Option<Vec<(usize, usize)>> {
+    let fd = openat("/proc/schedstat\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 512];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    info.lines()
+        .map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            let fetched = fields.next()?.parse().ok()?;
+            let stolen = fields.next()?.parse().ok()?;
+            Some((fetched, stolen))
+        })
+        .collect()
+}
+
+/// Spawn many trivial threads from one hart, and check they are all run and
+/// fetched from the run queues, and stolen by the other harts if there are.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin run queue test");
+    let Some(before) = read_schedstat() else {
+        println!("can not read /proc/schedstat");
+        return -1;
+    };
+    for batch in 0..THREADS / BATCH {
+        let mut handles = Vec::with_capacity(BATCH);
+        for _ in 0..BATCH {
+            match thread::spawn(|| {
+                COUNT.fetch_add(1, Ordering::Relaxed);
+                yield_();
+            }) {
+                Ok(handle) => handles.push(handle),
+                Err(err) => {
+                    println!("spawn failed in batch {}: {:?}", batch, err);
+                    return -1;
+                }
+            }
+        }
+        for handle in handles {
+            handle.join();
+        }
+    }
+    let after = read_schedstat().unwrap_or_default();
+    if after.len() != before.len() {
+        println!("harts in /proc/schedstat changed");
+        return -1;
+    }
+
+    let mut ok = COUNT.load(Ordering::Relaxed) == THREADS;
+    let (mut fetched, mut stolen) = (0, 0);
+    for (hart_id, (old, new)) in before.iter().zip(&after).enumerate() {
+        println!(
+            "cpu{}: fetched {}, stolen {}",
+            hart_id,
+            new.0 - old.0,
+            new.1 - old.1
+        );
+        fetched += new.0 - old.0;
+        stolen += new.1 - old.1;
+    }
+    if fetched < THREADS {
+        println!("{} fetched for {} threads", fetched, THREADS);
+        ok = false;
+    }
+    if after.len() > 1 && stolen == 0 {
+        println!("no task is stolen by the other harts");
+        ok = false;
+    }
+    if ok {
+        println!("run queue test passed");
+        0
+    } else {
+        -1
+    }
+}
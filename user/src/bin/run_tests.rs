@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Tests run when none is given in the arguments.
+const TESTS: &[&str] = &[
+    "dcache_test",
+    "lseek_test",
+    "tmpfs_test",
+    "env_test",
+    "heap_test",
+    "exec_test",
+    "auxv_test",
+    "vdso_test",
+    "thread_test",
+    "clone_files_test",
+    "getcpu_test",
+    "scm_rights_test",
+    "bad_ptr_test",
+    "exec_limits_test",
+    "tlb_shootdown_test",
+    "asid_test",
+    "oom_test",
+    "slabinfo_test",
+    "irq_test",
+    "random_test",
+    "loop_test",
+    "shm_test",
+    "mlock_test",
+    "madvise_test",
+    "swap_test",
+    "tcp_echo_client",
+    "sa_restart_test",
+    "shm_futex_test",
+    "futex_bitset_test",
+    "run_queue_test",
+    "preempt_test",
+    "affinity_test",
+    "nice_test",
+    "timer_cancel_test",
+    "timeout_test",
+    "multi_ready_test",
+    "poll_wakeup_test",
+    "accept_signal_test",
+    "umask_test",
+    "open_flags_test",
+    "cwd_test",
+    "recover_test",
+    "net_stress_test",
+    "serial_tx_test",
+    "tty_test",
+    "blk_write_test",
+    "meminfo_test",
+    "syn_flood_test",
+    "kill_group_test",
+    "orphan_test",
+    "session_test",
+    "coredump_test",
+    "ptrace_test",
+    "seccomp_test",
+    "syscall_stats_test",
+];
+
+/// Run test binaries with stdin from /dev/null, and report how many of them
+/// pass, i.e. exit with zero.
+#[no_mangle]
+fn main(_argc: usize, argv: &[&str]) -> i32 {
+    let tests = if argv.len() > 1 { &argv[1..] } else { TESTS };
+    let null = openat("/dev/null\0", OpenFlags::O_RDONLY);
+    let (mut passed, mut failed) = (0, 0);
+    for &test in tests {
+        let mut command = Command::new(test);
+        if null >= 0 {
+            command.stdin(null as usize);
+        }
+        match command.status() {
+            Ok(status) if status.success() => {
+                println!("[run_tests] {} passed", test);
+                passed += 1;
+            }
+            Ok(status) => {
+                match (status.code(), status.signal()) {
+                    (Some(code), _) => println!("[run_tests] {} failed, exit code {}", test, code),
+                    (_, Some(sig)) => println!("[run_tests] {} killed by signal {}", test, sig),
+                    _ => println!("[run_tests] {} failed, status {:#x}", test, status.0),
+                }
+                failed += 1;
+            }
+            Err(err) => {
+                println!("[run_tests] {} can not run: {:?}", test, err);
+                failed += 1;
+            }
+        }
+    }
+    if null >= 0 {
+        close(null as usize);
+    }
+    println!("[run_tests] {} passed, {} failed", passed, failed);
+    if failed == 0 {
+        0
+    } else {
+        -1
+    }
+}
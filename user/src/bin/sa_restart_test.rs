@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user_lib::*;
+
+const MESSAGE: &[u8] = b"restarted read";
+
+const SIG_IGN: usize = 1;
+
+/// Number of SIGALRMs handled.
+static ALARMS: AtomicUsize = AtomicUsize::new(0);
+
+fn on_alarm(_signal: usize) {
+    ALARMS.fetch_add(1, Ordering::SeqCst);
+}
+
+fn catch_alarm(flags: SigActionFlag) -> bool {
+    let mut act = SigAction::default();
+    let mut old = SigAction::default();
+    act.sa_handler = on_alarm as usize;
+    act.sa_flags = flags;
+    sigaction(Sig::SIGALRM, &act, &mut old) == 0
+}
+
+fn ignore_alarm() -> bool {
+    let act = SigAction {
+        sa_handler: SIG_IGN,
+        ..Default::default()
+    };
+    let mut old = SigAction::default();
+    sigaction(Sig::SIGALRM, &act, &mut old) == 0
+}
+
+/// Fork a child which signals the parent while it is blocked in `read`, and
+/// then writes `data` to `wfd` if given.
+fn alarm_reader(wfd: usize, data: Option<&[u8]>) -> usize {
+    let parent = getpid();
+    let pid = fork();
+    if pid == 0 {
+        sleep(100);
+        kill(parent, Sig::SIGALRM);
+        if let Some(data) = data {
+            sleep(100);
+            write(wfd, data);
+        }
+        exit(0);
+    }
+    assert!(pid > 0, "fork failed: {}", pid);
+    pid as usize
+}
+
+fn exited_ok(pid: usize) -> bool {
+    let mut wstatus = 0;
+    waitpid(pid, &mut wstatus) == pid as isize && ExitStatus(wstatus).success()
+}
+
+/// A blocking pipe read interrupted by a handler installed with SA_RESTART
+/// is restarted and completes with the full data, while it fails with EINTR
+/// if the handler is installed without it. An ignored signal never interrupts
+/// it.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("sa_restart");
+    let mut fds = [0i32; 2];
+    if pipe(&mut fds) < 0 {
+        println!("pipe failed");
+        return -1;
+    }
+    let (rfd, wfd) = (fds[0] as usize, fds[1] as usize);
+
+    result.check(
+        "sigaction with SA_RESTART",
+        catch_alarm(SigActionFlag::SA_RESTART),
+    );
+    let pid = alarm_reader(wfd, Some(MESSAGE));
+    let mut buf = [0u8; MESSAGE.len()];
+    let len = read(rfd, &mut buf);
+    result.check("restarted read", len == MESSAGE.len() as isize);
+    result.check("data of the restarted read", buf == MESSAGE);
+    result.check(
+        "handler with SA_RESTART",
+        ALARMS.load(Ordering::SeqCst) == 1,
+    );
+    result.check("writer", exited_ok(pid));
+
+    result.check(
+        "sigaction without SA_RESTART",
+        catch_alarm(SigActionFlag::empty()),
+    );
+    let pid = alarm_reader(wfd, None);
+    let ret = read(rfd, &mut buf);
+    result.check("interrupted read", ret == -(SyscallErr::EINTR as isize));
+    result.check(
+        "handler without SA_RESTART",
+        ALARMS.load(Ordering::SeqCst) == 2,
+    );
+    result.check("signaler", exited_ok(pid));
+
+    result.check("ignoring SIGALRM", ignore_alarm());
+    let pid = alarm_reader(wfd, Some(MESSAGE));
+    let len = read(rfd, &mut buf);
+    result.check("read with SIGALRM ignored", len == MESSAGE.len() as isize);
+    result.check("writer with SIGALRM ignored", exited_ok(pid));
+
+    close(rfd);
+    close(wfd);
+    result.finish()
+}
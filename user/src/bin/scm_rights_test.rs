@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const MESSAGE: &[u8] = b"written through a passed fd";
+
+/// Create a pipe, returning its read end and write end.
+fn new_pipe() -> Option<(usize, usize)> {
+    let mut fds = [0; 2];
+    (pipe(&mut fds) == 0).then_some((fds[0] as usize, fds[1] as usize))
+}
+
+/// Receive the write end of a pipe over the socket, which is the only copy of
+/// it in the child, and write through it.
+fn child(sock: usize) -> i32 {
+    let mut buf = [0u8; 16];
+    let mut fds = [-1; 1];
+    match recv_fds(sock, &mut buf, &mut fds) {
+        Ok((len, 1, 0)) if &buf[..len] == b"pipe" => {}
+        ret => {
+            println!("child receives {:?}", ret);
+            return -1;
+        }
+    }
+    if write(fds[0] as usize, MESSAGE) != MESSAGE.len() as isize {
+        println!("write through the passed fd failed");
+        return -1;
+    }
+    0
+}
+
+/// Pass the write end of a pipe to a child over a socketpair, and check that
+/// files in flight are closed when they do not fit in the control buffer, or
+/// when the sockets are closed before receiving them.
+///
+/// Each check reads the pipe until EOF, which hangs if the passed write end is
+/// leaked.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin scm rights test");
+    let Ok([sock0, sock1]) = socketpair(SaFamily::Unix, SocketType::Stream, SocketFlags::empty())
+    else {
+        println!("socketpair failed");
+        return -1;
+    };
+    let Some((rfd, wfd)) = new_pipe() else {
+        println!("pipe failed");
+        return -1;
+    };
+
+    let pid = fork();
+    if pid == 0 {
+        close(sock0);
+        close(rfd);
+        close(wfd);
+        exit(child(sock1));
+    }
+    if send_fds(sock0, b"pipe", &[wfd as i32]) != Ok(4) {
+        println!("send_fds failed");
+        return -1;
+    }
+    close(wfd);
+    let mut wstatus = 0;
+    if waitpid(pid as usize, &mut wstatus) < 0 || !ExitStatus(wstatus).success() {
+        println!("child failed, status {:#x}", wstatus);
+        return -1;
+    }
+    let mut buf = [0u8; 64];
+    let len = read(rfd, &mut buf);
+    if len < 0 || &buf[..len as usize] != MESSAGE || read(rfd, &mut buf) != 0 {
+        println!("read {} bytes from the pipe", len);
+        return -1;
+    }
+    close(rfd);
+
+    // no room for any fd
+    let Some((rfd, wfd)) = new_pipe() else {
+        return -1;
+    };
+    send_fds(sock0, b"x", &[wfd as i32]).ok();
+    close(wfd);
+    match recv_fds(sock1, &mut buf, &mut []) {
+        Ok((1, 0, flags)) if flags & MSG_CTRUNC != 0 => {}
+        ret => {
+            println!("recv_fds without room returns {:?}", ret);
+            return -1;
+        }
+    }
+    if read(rfd, &mut buf) != 0 {
+        println!("truncated fd is not closed");
+        return -1;
+    }
+    close(rfd);
+
+    // discarded with the sockets
+    let Some((rfd, wfd)) = new_pipe() else {
+        return -1;
+    };
+    send_fds(sock0, b"y", &[wfd as i32]).ok();
+    close(wfd);
+    close(sock0);
+    close(sock1);
+    if read(rfd, &mut buf) != 0 {
+        println!("fd in flight is not closed with the sockets");
+        return -1;
+    }
+    close(rfd);
+
+    println!("scm rights test passed");
+    0
+}
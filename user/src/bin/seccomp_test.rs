@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_PIPE2: usize = 59;
+const SYSCALL_READ: usize = 63;
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_EXIT: usize = 93;
+const SYSCALL_PRCTL: usize = 167;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_WAIT4: usize = 260;
+
+/// Run `f` in a child, which exits with zero if `f` returns, and wait for it.
+/// `f` may report that it has done what is permitted by writing to the fd
+/// passed, before it makes a syscall that is not. Returns the wait status of
+/// the child and whether it has reported.
+fn in_child(f: impl FnOnce(usize)) -> (ExitStatus, bool) {
+    let mut fds = [0i32; 2];
+    pipe(&mut fds);
+    let (rfd, wfd) = (fds[0] as usize, fds[1] as usize);
+    let pid = fork();
+    if pid == 0 {
+        close(rfd);
+        f(wfd);
+        exit(0);
+    }
+    close(wfd);
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    let mut buf = [0u8; 1];
+    let reported = read(rfd, &mut buf) == 1;
+    close(rfd);
+    (ExitStatus(wstatus), reported)
+}
+
+fn killed(status: ExitStatus) -> bool {
+    status.signal() == Some(Sig::SIGKILL.raw() as i32)
+}
+
+/// Permit only the syscalls of `nos`.
+fn set_allowlist(nos: &[usize]) -> isize {
+    let mut bits = [0u64; 8];
+    for &no in nos {
+        bits[no / 64] |= 1 << (no % 64);
+    }
+    prctl(
+        PR_SET_SECCOMP,
+        SECCOMP_MODE_ALLOWLIST,
+        bits.as_ptr() as usize,
+        bits.len(),
+    )
+}
+
+/// Check that a confined process can only make the syscalls permitted, and is
+/// killed by SIGKILL once it makes any other.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("seccomp");
+    result.check("PR_GET_SECCOMP", prctl(PR_GET_SECCOMP, 0, 0, 0) == 0);
+
+    let (status, reported) = in_child(|fd| {
+        prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT, 0, 0);
+        write(fd, b"w");
+        openat("/dev/null\0", OpenFlags::O_RDONLY);
+    });
+    result.check("write in strict mode", reported);
+    result.check("openat in strict mode", killed(status));
+    let (status, _) = in_child(|_| {
+        prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT, 0, 0);
+        exit(7);
+    });
+    result.check("exit in strict mode", status.code() == Some(7));
+
+    let (status, reported) = in_child(|fd| {
+        let mut permitted = [
+            SYSCALL_WRITE,
+            SYSCALL_EXIT,
+            SYSCALL_PRCTL,
+            SYSCALL_GETPID,
+            SYSCALL_GETPID,
+        ];
+        let mut pass = set_allowlist(&permitted) == 0
+            && prctl(PR_GET_SECCOMP, 0, 0, 0) == SECCOMP_MODE_ALLOWLIST as isize
+            && prctl(PR_SET_SECCOMP, SECCOMP_MODE_DISABLED, 0, 0) < 0;
+        // Syscalls denied can not be permitted again.
+        permitted[4] = SYSCALL_GETPPID;
+        pass = pass && set_allowlist(&permitted) == 0 && getpid() > 0;
+        if pass {
+            write(fd, b"a");
+        }
+        getppid();
+    });
+    result.check("syscalls in the allowlist", reported);
+    result.check("getppid out of the allowlist", killed(status));
+
+    // The filter is inherited by the child.
+    let (status, _) = in_child(|_| {
+        set_allowlist(&[
+            SYSCALL_CLOSE,
+            SYSCALL_PIPE2,
+            SYSCALL_READ,
+            SYSCALL_WRITE,
+            SYSCALL_EXIT,
+            SYSCALL_CLONE,
+            SYSCALL_WAIT4,
+        ]);
+        let (status, _) = in_child(|_| {
+            getpid();
+        });
+        exit(if killed(status) { 0 } else { 1 });
+    });
+    result.check("allowlist inherited by fork", status.success());
+
+    result.finish()
+}
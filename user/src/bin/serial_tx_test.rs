@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use user_lib::*;
+
+/// Bytes written to the console, far more than the TX ring holds.
+const BYTES: usize = 8192;
+/// Hart both the writer and the spinner are pinned on.
+const HART: usize = 0;
+
+static SPINS: AtomicUsize = AtomicUsize::new(0);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// A large write to the console must be written in full, while the writer
+/// waits for the TX ring to drain by sleeping instead of spinning, so that a
+/// thread on the same hart keeps running. The console must report itself
+/// writable by ppoll(2).
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin serial tx test");
+    sched_setaffinity(0, 1 << HART);
+    let spinner = thread::spawn(|| {
+        sched_setaffinity(0, 1 << HART);
+        while !STOP.load(Ordering::Relaxed) {
+            SPINS.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+    .unwrap();
+    sleep(10);
+
+    let line = [b'.'; 64];
+    let spins = SPINS.load(Ordering::Relaxed);
+    let mut short_writes = 0;
+    for _ in 0..BYTES / line.len() {
+        if write(1, &line) != line.len() as isize {
+            short_writes += 1;
+        }
+    }
+    let spins = SPINS.load(Ordering::Relaxed) - spins;
+    println!("");
+    STOP.store(true, Ordering::Relaxed);
+    spinner.join();
+
+    let mut fds = [PollFd {
+        fd: 1,
+        events: POLLOUT,
+        revents: 0,
+    }];
+    let writable = matches!(ppoll(&mut fds, 1000), Ok(1)) && fds[0].revents & POLLOUT != 0;
+    println!(
+        "{} short writes, {} spins while writing, writable {}",
+        short_writes, spins, writable
+    );
+    if short_writes != 0 || spins == 0 || !writable {
+        println!("serial tx test failed");
+        return -1;
+    }
+    println!("serial tx test passed");
+    0
+}
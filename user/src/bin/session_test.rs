@@ -0,0 +1,123 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const TIOCSCTTY: usize = 0x540E;
+const TIOCGSID: usize = 0x5429;
+
+fn errno(ret: isize) -> Option<SyscallErr> {
+    SyscallErr::from_ret(ret).err()
+}
+
+/// Run `f` in a child and return whether it exits with zero.
+fn in_child(f: fn() -> bool) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        exit(if f() { 0 } else { -1 });
+    }
+    let mut wstatus = 0;
+    pid > 0 && waitpid(pid as usize, &mut wstatus) == pid && ExitStatus(wstatus).success()
+}
+
+/// A new session is led by the caller, in a new group of its own.
+fn new_session() -> bool {
+    let pid = getpid();
+    setsid() == pid
+        && getsid(0) == pid
+        && getpgid(0) == pid
+        && matches!(errno(setsid()), Some(SyscallErr::EPERM))
+        && matches!(errno(setpgid(0, 0)), Some(SyscallErr::EPERM))
+}
+
+/// A group leader can not create a session.
+fn group_leader_setsid() -> bool {
+    setpgid(0, 0) == 0 && matches!(errno(setsid()), Some(SyscallErr::EPERM))
+}
+
+/// The leader of a new session makes the terminal its controlling terminal.
+fn take_terminal() -> bool {
+    let pid = setsid();
+    let fd = openat("/dev/tty\0", OpenFlags::O_RDWR);
+    if pid < 0 || fd < 0 {
+        return false;
+    }
+    let fd = fd as usize;
+    let mut sid = 0u32;
+    let ok = ioctl(fd, TIOCSCTTY, 0) == 0
+        && ioctl(fd, TIOCGSID, &mut sid as *mut u32 as usize) == 0
+        && sid as isize == pid;
+    close(fd);
+    ok
+}
+
+/// Check setsid(2), getsid(2) and the rules of setpgid(2) on sessions.
+#[no_mangle]
+fn main(_argc: usize, argv: &[&str]) -> i32 {
+    // Run as the program a child executes.
+    if argv.get(1) == Some(&"sleep") {
+        sleep(2000);
+        return 0;
+    }
+
+    let mut result = TestResult::begin("session");
+
+    result.check(
+        "getsid",
+        getsid(0) > 0 && getsid(getpid() as usize) == getsid(0),
+    );
+    result.check(
+        "getsid of a process that does not exist",
+        matches!(errno(getsid(99999)), Some(SyscallErr::ESRCH)),
+    );
+    result.check("setsid", in_child(new_session));
+    result.check("setsid of a group leader", in_child(group_leader_setsid));
+    // The terminal is released when the session leader exits, so that it can
+    // be taken by another session.
+    result.check("TIOCSCTTY", in_child(take_terminal));
+    result.check("TIOCSCTTY after the leader exits", in_child(take_terminal));
+
+    // A child in another session
+    let mut fds = [0i32; 2];
+    pipe(&mut fds);
+    let pid = fork();
+    if pid == 0 {
+        setsid();
+        write(fds[1] as usize, b"x");
+        sleep(2000);
+        exit(0);
+    }
+    let mut buf = [0u8; 1];
+    read(fds[0] as usize, &mut buf);
+    close(fds[0] as usize);
+    close(fds[1] as usize);
+    result.check(
+        "setpgid of a child in another session",
+        matches!(
+            errno(setpgid(pid as usize, pid as usize)),
+            Some(SyscallErr::EPERM)
+        ),
+    );
+    kill(pid, Sig::SIGKILL);
+    waitpid(pid as usize, &mut 0);
+
+    // A child which has executed a program
+    match spawn("session_test", &["session_test", "sleep"], &[]) {
+        Ok(child) => {
+            result.check(
+                "setpgid of a child after exec",
+                matches!(errno(setpgid(child.0, child.0)), Some(SyscallErr::EACCES)),
+            );
+            kill(child.0 as isize, Sig::SIGKILL);
+            let _ = child.wait();
+        }
+        Err(err) => {
+            println!("spawn failed: {:?}", err);
+            result.check("spawn", false);
+        }
+    }
+
+    result.finish()
+}
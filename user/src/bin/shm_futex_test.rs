@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const IPC_PRIVATE: usize = 0;
+const IPC_CREAT: usize = 0o1000;
+const IPC_RMID: usize = 0;
+
+/// Number of times the word is passed to the child and back.
+const ROUNDS: u32 = 100;
+
+/// Wait without FUTEX_PRIVATE_FLAG until `word` is `value`, which fails if no
+/// one wakes us in a second.
+fn wait_for(word: &AtomicU32, value: u32) -> bool {
+    let timeout = TimeSpec {
+        tv_sec: 1,
+        tv_nsec: 0,
+    };
+    loop {
+        let cur = word.load(Ordering::SeqCst);
+        if cur == value {
+            return true;
+        }
+        let ret = futex(
+            word.as_ptr() as usize,
+            FUTEX_WAIT,
+            cur,
+            &timeout as *const TimeSpec as usize,
+            0,
+            0,
+        );
+        if matches!(SyscallErr::from_ret(ret), Err(SyscallErr::ETIMEDOUT)) {
+            println!("timed out waiting for {}, the word is {}", value, cur);
+            return false;
+        }
+    }
+}
+
+fn pass(word: &AtomicU32, value: u32) {
+    word.store(value, Ordering::SeqCst);
+    futex(word.as_ptr() as usize, FUTEX_WAKE, 1, 0, 0, 0);
+}
+
+fn attach(shmid: usize) -> Option<&'static AtomicU32> {
+    let addr = shmat(shmid, ptr::null(), 0);
+    if addr < 0 {
+        println!("shmat failed: {}", addr);
+        return None;
+    }
+    Some(unsafe { &*(addr as *const AtomicU32) })
+}
+
+/// Two processes play ping-pong with a futex word in a System V shared
+/// memory segment, which each of them attaches at its own address, so that
+/// waiters and wakers only meet if shared futexes are keyed by the physical
+/// address.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin shm futex test");
+    let shmid = shmget(IPC_PRIVATE, PAGE_SIZE, IPC_CREAT | 0o600);
+    if shmid < 0 {
+        println!("shmget failed: {}", shmid);
+        return -1;
+    }
+    let shmid = shmid as usize;
+    let Some(word) = attach(shmid) else {
+        return -1;
+    };
+
+    let pid = fork();
+    if pid == 0 {
+        // attach the segment once more, at a different address
+        let Some(word) = attach(shmid) else {
+            exit(-1);
+        };
+        for round in 0..ROUNDS {
+            if !wait_for(word, 2 * round + 1) {
+                exit(-1);
+            }
+            pass(word, 2 * round + 2);
+        }
+        exit(0);
+    }
+    assert!(pid > 0, "fork failed: {}", pid);
+
+    let mut ok = true;
+    for round in 0..ROUNDS {
+        pass(word, 2 * round + 1);
+        if !wait_for(word, 2 * round + 2) {
+            ok = false;
+            break;
+        }
+    }
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    if !ExitStatus(wstatus).success() {
+        println!("the child failed");
+        ok = false;
+    }
+    shmdt(word as *const AtomicU32 as *const u8);
+    shmctl(shmid, IPC_RMID, 0);
+
+    if ok {
+        println!("shm futex test passed");
+        0
+    } else {
+        -1
+    }
+}
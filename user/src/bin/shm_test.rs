@@ -0,0 +1,136 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::{ptr, slice};
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_SHARED: i32 = 0x01;
+
+const F_GETFD: usize = 1;
+const F_ADD_SEALS: usize = 1033;
+const FD_CLOEXEC: isize = 1;
+const MFD_CLOEXEC: usize = 0x1;
+const EINVAL: isize = 22;
+
+const SHM_PATH: &str = "/dev/shm/shm_test\0";
+const DATA: &[u8] = b"hello from the child";
+
+fn map_shared(fd: usize) -> Option<&'static mut [u8]> {
+    let addr = mmap(
+        ptr::null(),
+        PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_SHARED,
+        fd,
+        0,
+    );
+    if addr < 0 {
+        return None;
+    }
+    Some(unsafe { slice::from_raw_parts_mut(addr as *mut u8, PAGE_SIZE) })
+}
+
+/// Fork a child that runs `f` and wait for it, which succeeds if the child
+/// exits with 0.
+fn run_child(f: impl FnOnce() -> i32) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    exit_code == 0
+}
+
+/// Like shm_open(3), the child opens the shared memory object again by name.
+fn test_shm_open() -> bool {
+    let fd = openat(SHM_PATH, OpenFlags::O_RDWR | OpenFlags::O_CREATE);
+    if fd < 0 {
+        println!("open {} failed: {}", SHM_PATH, fd);
+        return false;
+    }
+    let fd = fd as usize;
+    if ftruncate(fd, PAGE_SIZE) != 0 {
+        println!("ftruncate {} failed", SHM_PATH);
+        return false;
+    }
+    let Some(mem) = map_shared(fd) else {
+        println!("mmap {} failed", SHM_PATH);
+        return false;
+    };
+    close(fd);
+    let ok = run_child(|| {
+        let fd = openat(SHM_PATH, OpenFlags::O_RDWR);
+        if fd < 0 {
+            return -1;
+        }
+        let Some(mem) = map_shared(fd as usize) else {
+            return -1;
+        };
+        mem[..DATA.len()].copy_from_slice(DATA);
+        0
+    });
+    unlink(SHM_PATH);
+    if !ok || &mem[..DATA.len()] != DATA {
+        println!("data written through {} is not shared", SHM_PATH);
+        return false;
+    }
+    true
+}
+
+/// The child maps the memfd it inherits.
+fn test_memfd() -> bool {
+    let fd = memfd_create("shm_test\0", MFD_CLOEXEC);
+    if fd < 0 {
+        println!("memfd_create failed: {}", fd);
+        return false;
+    }
+    let fd = fd as usize;
+    if fcntl(fd, F_GETFD, 0) & FD_CLOEXEC == 0 {
+        println!("MFD_CLOEXEC is not set on the memfd");
+        return false;
+    }
+    if fcntl(fd, F_ADD_SEALS, 0) != -EINVAL {
+        println!("F_ADD_SEALS on the memfd does not fail with EINVAL");
+        return false;
+    }
+    if ftruncate(fd, PAGE_SIZE) != 0 {
+        println!("ftruncate the memfd failed");
+        return false;
+    }
+    let Some(mem) = map_shared(fd) else {
+        println!("mmap the memfd failed");
+        return false;
+    };
+    let ok = run_child(|| {
+        let Some(mem) = map_shared(fd) else {
+            return -1;
+        };
+        mem[..DATA.len()].copy_from_slice(DATA);
+        0
+    });
+    close(fd);
+    if !ok || &mem[..DATA.len()] != DATA {
+        println!("data written through the memfd is not shared");
+        return false;
+    }
+    true
+}
+
+/// Share a page between two processes through a file in /dev/shm, and then
+/// through a memfd.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin shm test");
+    if !test_shm_open() || !test_memfd() {
+        return -1;
+    }
+    println!("shm test passed");
+    0
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Files opened, each of which allocates at least one object on the kernel
+/// heap.
+const OPENS: usize = 100;
+
+/// Read the counter named `name` from /proc/sys/kernel/slabinfo.
+fn read_counter(name: &str) -> Option<usize> {
+    let fd = openat("/proc/sys/kernel/slabinfo\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 4096];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    info.lines().find_map(|line| {
+        let (key, value) = line.split_once(' ')?;
+        (key == name).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// The heap allocations counted in procfs climb as files are opened.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin slabinfo test");
+    let Some(before) = read_counter("allocs") else {
+        println!("no allocs in slabinfo");
+        return -1;
+    };
+    for _ in 0..OPENS {
+        let fd = openat("/dev/null\0", OpenFlags::O_RDONLY);
+        if fd < 0 {
+            println!("open /dev/null failed");
+            return -1;
+        }
+        close(fd as usize);
+    }
+    let after = read_counter("allocs").unwrap_or(0);
+    let peak = read_counter("peak").unwrap_or(0);
+    let current = read_counter("current").unwrap_or(0);
+    println!(
+        "allocs {} -> {}, current {}, peak {}",
+        before, after, current, peak
+    );
+    if after < before + OPENS || current == 0 || peak < current {
+        println!("slabinfo test failed");
+        return -1;
+    }
+    println!("slabinfo test passed");
+    0
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const SHELL: &str = "/busybox";
+const ROUNDS: usize = 100;
+
+fn now_usec() -> usize {
+    let mut time_val = TimeVal::from_usec(0);
+    gettimeofday(&mut time_val);
+    time_val.into_usec()
+}
+
+/// Run `sh -c true` by fork and exec, which copies the page table of the
+/// parent.
+fn run_by_fork(argv: &[&str]) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        execve(SHELL, argv, &[]);
+        exit(127);
+    }
+    let mut wstatus = 0;
+    pid > 0 && waitpid(pid as usize, &mut wstatus) > 0 && ExitStatus(wstatus).success()
+}
+
+/// Run `sh -c true` by `Command`, which uses vfork.
+fn run_by_vfork(argv: &[&str]) -> bool {
+    Command::new(SHELL)
+        .argv(argv)
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Average microseconds of running `sh -c true` by `run`.
+fn bench(argv: &[&str], run: fn(&[&str]) -> bool) -> Option<usize> {
+    let begin = now_usec();
+    for _ in 0..ROUNDS {
+        if !run(argv) {
+            return None;
+        }
+    }
+    Some((now_usec() - begin) / ROUNDS)
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin spawn bench");
+    let argv = ["busybox", "sh", "-c", "true"];
+    match (bench(&argv, run_by_fork), bench(&argv, run_by_vfork)) {
+        (Some(fork), Some(vfork)) => {
+            println!("sh -c true: fork {}us, vfork {}us", fork, vfork);
+            0
+        }
+        _ => {
+            println!("sh -c true failed");
+            -1
+        }
+    }
+}
@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Run a program and print the number and return value of each syscall it
+/// makes to stderr, e.g. `strace hello_world`. Exits with the exit code of the
+/// program.
+#[no_mangle]
+fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        eprintln!("usage: strace program [args...]");
+        return -1;
+    }
+    let pid = fork();
+    if pid == 0 {
+        ptrace(PTRACE_TRACEME, 0, 0, 0);
+        // The child stops with SIGTRAP once it has executed the program.
+        execve(argv[1], &argv[1..], &[]);
+        eprintln!("strace: exec {} failed", argv[1]);
+        exit(127);
+    }
+    if pid < 0 {
+        eprintln!("strace: fork failed");
+        return -1;
+    }
+    let pid = pid as usize;
+    let mut wstatus = 0;
+    waitpid(pid, &mut wstatus);
+    if ExitStatus(wstatus).stopped() != Some(Sig::SIGTRAP.raw() as i32) {
+        return ExitStatus(wstatus).code().unwrap_or(-1);
+    }
+
+    let mut regs = [0usize; 32];
+    let mut in_syscall = false;
+    let mut sig = 0;
+    loop {
+        ptrace(PTRACE_SYSCALL, pid, 0, sig);
+        if waitpid(pid, &mut wstatus) != pid as isize {
+            eprintln!("strace: wait failed");
+            return -1;
+        }
+        let status = ExitStatus(wstatus);
+        sig = 0;
+        match status.stopped() {
+            // Stops at syscall entry and exit alternate.
+            Some(s) if s == Sig::SIGTRAP.raw() as i32 => {
+                ptrace(PTRACE_GETREGS, pid, 0, regs.as_mut_ptr() as usize);
+                if in_syscall {
+                    eprintln!("syscall {} = {}", regs[17], regs[10] as isize);
+                }
+                in_syscall = !in_syscall;
+            }
+            // A signal is about to be delivered, which is passed on.
+            Some(s) => {
+                eprintln!("--- signal {} ---", s);
+                sig = s as usize;
+            }
+            None => {
+                if let Some(code) = status.code() {
+                    eprintln!("+++ exited with {} +++", code);
+                    return code;
+                }
+                let s = status.signal().unwrap_or(0);
+                eprintln!("+++ killed by signal {} +++", s);
+                return 128 + s;
+            }
+        }
+    }
+}
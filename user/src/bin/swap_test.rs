@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::ptr;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+const SWAP_FILE: &str = "/swap_test.swap\0";
+
+/// Value of `key` in KB or in pages from a file like /proc/meminfo.
+fn read_stat(path: &str, key: &str) -> Option<usize> {
+    let mut buf = [0u8; 1024];
+    let fd = openat(path, OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let line = info
+        .lines()
+        .find(|line| line.split_whitespace().next() == Some(key))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Create a swap file of `size` bytes, with the header written by mkswap(8).
+fn mkswap(size: usize) -> bool {
+    let fd = openat(
+        SWAP_FILE,
+        OpenFlags::O_RDWR | OpenFlags::O_CREATE | OpenFlags::O_TRUNC,
+    );
+    if fd < 0 {
+        return false;
+    }
+    let fd = fd as usize;
+    let mut header = [0u8; PAGE_SIZE];
+    // version and the last page
+    header[1024..1028].copy_from_slice(&1u32.to_le_bytes());
+    header[1028..1032].copy_from_slice(&((size / PAGE_SIZE - 1) as u32).to_le_bytes());
+    header[PAGE_SIZE - 10..].copy_from_slice(b"SWAPSPACE2");
+    let ok = write(fd, &header) == PAGE_SIZE as isize && ftruncate(fd, size) == 0;
+    close(fd);
+    ok
+}
+
+/// Allocate and fill 1.5 times the physical memory, which only completes with
+/// pages swapped out, and check that they read back the same.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin swap test");
+    let Some(mem_kb) = read_stat("/proc/meminfo\0", "MemTotal:") else {
+        println!("read /proc/meminfo failed");
+        return -1;
+    };
+    let mem = mem_kb * 1024;
+    if !mkswap(mem + PAGE_SIZE) {
+        println!("create {} failed", SWAP_FILE);
+        return -1;
+    }
+    if swapon(SWAP_FILE, 0) != 0 {
+        println!("swapon {} failed", SWAP_FILE);
+        return -1;
+    }
+    println!(
+        "SwapTotal: {} KB",
+        read_stat("/proc/meminfo\0", "SwapTotal:").unwrap_or(0)
+    );
+
+    let size = mem / 2 * 3 / PAGE_SIZE * PAGE_SIZE;
+    let addr = mmap(
+        ptr::null(),
+        size,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    if addr < 0 {
+        println!("mmap {} bytes failed", size);
+        return -1;
+    }
+    let addr = addr as *mut usize;
+    let words = PAGE_SIZE / core::mem::size_of::<usize>();
+    for i in 0..size / PAGE_SIZE {
+        unsafe {
+            addr.add(i * words).write_volatile(i);
+            addr.add(i * words + words - 1).write_volatile(!i);
+        }
+    }
+    let same = (0..size / PAGE_SIZE).all(|i| unsafe {
+        addr.add(i * words).read_volatile() == i
+            && addr.add(i * words + words - 1).read_volatile() == !i
+    });
+    let swapped_out = read_stat("/proc/vmstat\0", "pswpout").unwrap_or(0);
+    munmap(addr as *const u8, size);
+
+    let ret = swapoff(SWAP_FILE);
+    unlink(SWAP_FILE);
+    if !same {
+        println!("pages read back from the swap file differ");
+        return -1;
+    }
+    if swapped_out == 0 {
+        println!("no page is swapped out");
+        return -1;
+    }
+    if ret != 0 {
+        println!("swapoff failed: {}", ret);
+        return -1;
+    }
+    println!("swap test passed, {} pages swapped out", swapped_out);
+    0
+}
@@ -0,0 +1,176 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const PORT: u16 = 5557;
+/// Connections started and abandoned at once.
+const SYNS: usize = 10000;
+/// Growth of the kernel heap allowed, which covers the sockets of the
+/// embryonic connections and of a full backlog, but not one socket per SYN.
+const MAX_HEAP_GROWTH: usize = 16 * 1024 * 1024;
+/// Milliseconds for which a normal connection is retried after the flood.
+const CONNECT_TIMEOUT_MS: usize = 10_000;
+
+fn now_usec() -> usize {
+    let mut time_val = TimeVal::from_usec(0);
+    gettimeofday(&mut time_val);
+    time_val.into_usec()
+}
+
+fn addr() -> SockAddr {
+    SockAddr::In(SockAddrIn::new([127, 0, 0, 1], PORT))
+}
+
+/// Bytes allocated on the kernel heap, from /proc/sys/kernel/slabinfo.
+fn heap_current() -> Option<usize> {
+    let fd = openat("/proc/sys/kernel/slabinfo\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 4096];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    info.lines().find_map(|line| {
+        let (key, value) = line.split_once(' ')?;
+        (key == "current").then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Start a connection, let its SYN out by polling it, and abandon it.
+fn syn_once() -> Result<(), SyscallErr> {
+    let sockfd = socket(SaFamily::Inet, SocketType::Stream, SocketFlags::NONBLOCK)?;
+    match connect(sockfd, &addr()) {
+        Ok(()) | Err(SyscallErr::EINPROGRESS) | Err(SyscallErr::ECONNREFUSED) => {}
+        Err(err) => {
+            close(sockfd);
+            return Err(err);
+        }
+    }
+    let mut fds = [PollFd {
+        fd: sockfd as i32,
+        events: POLLOUT,
+        revents: 0,
+    }];
+    let ret = ppoll(&mut fds, 0);
+    close(sockfd);
+    ret.map(|_| ())
+}
+
+/// Accept the connections completed during the flood, which fill the backlog.
+fn drain(listener: usize) -> usize {
+    let mut accepts = 0;
+    while let Ok((conn, _)) = accept(listener) {
+        close(conn);
+        accepts += 1;
+    }
+    accepts
+}
+
+/// Connect to the listener as a normal client would, retrying while it drops
+/// the SYNs, and accept the connection.
+fn connect_normally(listener: usize) -> Result<bool, SyscallErr> {
+    let deadline = now_usec() + CONNECT_TIMEOUT_MS * 1000;
+    while now_usec() < deadline {
+        let sockfd = socket(SaFamily::Inet, SocketType::Stream, SocketFlags::NONBLOCK)?;
+        match connect(sockfd, &addr()) {
+            Ok(()) | Err(SyscallErr::EINPROGRESS) => {}
+            Err(_) => {
+                close(sockfd);
+                sleep(100);
+                continue;
+            }
+        }
+        let mut fds = [
+            PollFd {
+                fd: listener as i32,
+                events: POLLIN,
+                revents: 0,
+            },
+            PollFd {
+                fd: sockfd as i32,
+                events: POLLOUT,
+                revents: 0,
+            },
+        ];
+        let connected = ppoll(&mut fds, 1000)? > 0
+            && fds[0].revents & POLLIN != 0
+            && match accept(listener) {
+                Ok((conn, _)) => {
+                    close(conn);
+                    true
+                }
+                Err(_) => false,
+            };
+        close(sockfd);
+        if connected {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Start `SYNS` connections to a listener that does not accept them, which
+/// must neither grow the kernel heap without bound nor keep the listener from
+/// accepting a normal connection afterwards.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin syn flood test");
+    let listener =
+        match socket(SaFamily::Inet, SocketType::Stream, SocketFlags::NONBLOCK).and_then(|fd| {
+            setsockopt(fd, SOL_SOCKET, SO_REUSEADDR, &1i32)?;
+            bind(fd, &addr())?;
+            listen(fd, 4)?;
+            Ok(fd)
+        }) {
+            Ok(fd) => fd,
+            Err(err) => {
+                println!("listen failed: {:?}", err);
+                return -1;
+            }
+        };
+    let Some(before) = heap_current() else {
+        println!("no current in slabinfo");
+        return -1;
+    };
+    let mut peak = before;
+    for i in 0..SYNS {
+        if let Err(err) = syn_once() {
+            println!("SYN {} failed: {:?}", i, err);
+            close(listener);
+            return -1;
+        }
+        if i % 500 == 0 {
+            peak = peak.max(heap_current().unwrap_or(0));
+        }
+    }
+    peak = peak.max(heap_current().unwrap_or(0));
+    let accepts = drain(listener);
+    let connected = connect_normally(listener);
+    close(listener);
+    println!(
+        "heap {} -> peak {} bytes, {} connections accepted after the flood",
+        before, peak, accepts
+    );
+    if peak - before > MAX_HEAP_GROWTH {
+        println!("kernel heap grew by {} bytes", peak - before);
+        return -1;
+    }
+    match connected {
+        Ok(true) => {
+            println!("syn flood test passed");
+            0
+        }
+        Ok(false) => {
+            println!("listener does not accept after the flood");
+            -1
+        }
+        Err(err) => {
+            println!("connect failed: {:?}", err);
+            -1
+        }
+    }
+}
@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const CALLS: usize = 100;
+
+fn write_file(path: &str, data: &[u8]) -> bool {
+    let fd = openat(path, OpenFlags::O_WRONLY);
+    if fd < 0 {
+        return false;
+    }
+    let ret = write(fd as usize, data);
+    close(fd as usize);
+    ret == data.len() as isize
+}
+
+/// Calls of getppid counted in /proc/syscall_stats.
+fn getppid_count() -> Option<usize> {
+    let mut buf = [0u8; 8192];
+    let fd = openat("/proc/syscall_stats\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let count = info
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next()? != "GETPPID" {
+                return None;
+            }
+            fields.next()?.parse().ok()
+        })
+        .unwrap_or(0);
+    Some(count)
+}
+
+/// Check that syscalls are counted in /proc/syscall_stats only while it is
+/// enabled, and that writing "reset" clears the counts.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("syscall stats");
+    if !write_file("/proc/sys/kernel/syscall_stats_enable\0", b"1\n") {
+        println!("enable syscall stats failed");
+        return -1;
+    }
+    result.check(
+        "reset",
+        write_file("/proc/syscall_stats\0", b"reset\n") && getppid_count() == Some(0),
+    );
+    for _ in 0..CALLS {
+        getppid();
+    }
+    result.check("count of getppid", getppid_count() == Some(CALLS));
+    result.check(
+        "write other than reset",
+        !write_file("/proc/syscall_stats\0", b"clear\n"),
+    );
+    result.check(
+        "count after reset",
+        write_file("/proc/syscall_stats\0", b"reset\n") && getppid_count() == Some(0),
+    );
+
+    write_file("/proc/sys/kernel/syscall_stats_enable\0", b"0\n");
+    getppid();
+    result.check("count while disabled", getppid_count() == Some(0));
+
+    result.finish()
+}
@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const PORT: u16 = 5555;
+
+const MESSAGES: &[&[u8]] = &[b"hello", b"echo over loopback", &[0x5a; 1000]];
+
+/// Start `tcp_echo_server`, then check that every message sent comes back
+/// unchanged.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin tcp echo test");
+    let pid = fork();
+    if pid == 0 {
+        execve("tcp_echo_server", &["tcp_echo_server"], &[]);
+        eprintln!("exec tcp_echo_server failed");
+        exit(-1);
+    }
+    let ret = match run() {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("[tcp_echo_client] failed: {:?}", err);
+            -1
+        }
+    };
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    if ret == 0 && exit_code == 0 {
+        println!("tcp echo test passed");
+        0
+    } else {
+        println!("tcp echo test failed");
+        -1
+    }
+}
+
+/// Connect to the server, retrying while it is not listening yet.
+fn connect_server() -> Result<usize, SyscallErr> {
+    let addr = SockAddr::In(SockAddrIn::new([127, 0, 0, 1], PORT));
+    let mut retries = 50;
+    loop {
+        let sockfd = socket(SaFamily::Inet, SocketType::Stream, SocketFlags::empty())?;
+        match connect(sockfd, &addr) {
+            Ok(()) => return Ok(sockfd),
+            Err(SyscallErr::ECONNREFUSED) if retries > 0 => {
+                close(sockfd);
+                retries -= 1;
+                sleep(100);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn run() -> Result<(), SyscallErr> {
+    let sockfd = connect_server()?;
+    setsockopt(sockfd, IPPROTO_TCP, TCP_NODELAY, &1i32)?;
+    let mut buf = [0u8; 1024];
+    for msg in MESSAGES {
+        let mut sent = 0;
+        while sent < msg.len() {
+            sent += sendto(sockfd, &msg[sent..], 0, None)?;
+        }
+        let mut received = 0;
+        while received < msg.len() {
+            let (len, _) = recvfrom(sockfd, &mut buf[received..msg.len()], 0)?;
+            if len == 0 {
+                eprintln!("[tcp_echo_client] connection closed early");
+                return Err(SyscallErr::ECONNRESET);
+            }
+            received += len;
+        }
+        if &buf[..msg.len()] != *msg {
+            eprintln!("[tcp_echo_client] echo mismatch");
+            return Err(SyscallErr::EIO);
+        }
+    }
+    shutdown(sockfd, Shutdown::Write)?;
+    let (len, _) = recvfrom(sockfd, &mut buf, 0)?;
+    close(sockfd);
+    if len != 0 {
+        return Err(SyscallErr::EIO);
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const PORT: u16 = 5555;
+
+/// Accept one connection on 127.0.0.1:5555 and echo everything back until
+/// the client shuts down its writing side.
+#[no_mangle]
+fn main() -> i32 {
+    match serve() {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("[tcp_echo_server] failed: {:?}", err);
+            -1
+        }
+    }
+}
+
+fn serve() -> Result<(), SyscallErr> {
+    let listener = socket(SaFamily::Inet, SocketType::Stream, SocketFlags::empty())?;
+    setsockopt(listener, SOL_SOCKET, SO_REUSEADDR, &1i32)?;
+    bind(
+        listener,
+        &SockAddr::In(SockAddrIn::new([127, 0, 0, 1], PORT)),
+    )?;
+    listen(listener, 1)?;
+    println!("[tcp_echo_server] listening on port {}", PORT);
+
+    let (conn, peer) = accept(listener)?;
+    if let Some(SockAddr::In(peer)) = peer {
+        println!("[tcp_echo_server] accept {:?}:{}", peer.addr, peer.port());
+    }
+    let mut buf = [0u8; 1024];
+    loop {
+        let (len, _) = recvfrom(conn, &mut buf, 0)?;
+        if len == 0 {
+            break;
+        }
+        let mut sent = 0;
+        while sent < len {
+            sent += sendto(conn, &buf[sent..len], 0, None)?;
+        }
+    }
+    close(conn);
+    close(listener);
+    Ok(())
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user_lib::*;
+
+const THREADS: usize = 10000;
+/// Threads running at the same time.
+const BATCH: usize = 100;
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Create and join many threads, which hangs if the kernel misses clearing
+/// the tid or waking the joiner when a thread exits.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin thread test");
+    for batch in 0..THREADS / BATCH {
+        let mut handles = Vec::with_capacity(BATCH);
+        for _ in 0..BATCH {
+            match thread::spawn(|| {
+                COUNT.fetch_add(1, Ordering::Relaxed);
+            }) {
+                Ok(handle) => handles.push(handle),
+                Err(err) => {
+                    println!("spawn failed in batch {}: {:?}", batch, err);
+                    return -1;
+                }
+            }
+        }
+        for handle in handles {
+            handle.join();
+        }
+    }
+    let count = COUNT.load(Ordering::Relaxed);
+    if count != THREADS {
+        println!("{} of {} threads have run", count, THREADS);
+        return -1;
+    }
+    println!("thread test passed");
+    0
+}
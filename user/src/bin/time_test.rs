@@ -1,8 +1,10 @@
 #![no_std]
 #![no_main]
 
+use alloc::string::String;
+
 use time::timeval::TimeVal;
-use user_lib::{gettimeofday, println};
+use user_lib::{gettimeofday, print, println, read_line};
 
 extern crate user_lib;
 
@@ -14,5 +16,11 @@ fn main() -> i32 {
     let mut timeval = TimeVal::default();
     gettimeofday(&mut timeval);
     println!("timeval: {:?}", timeval);
+    print!("press enter to stop the clock ");
+    let mut line = String::new();
+    read_line(&mut line);
+    let mut end = TimeVal::default();
+    gettimeofday(&mut end);
+    println!("elapsed {} usecs", end.into_usec() - timeval.into_usec());
     0
 }
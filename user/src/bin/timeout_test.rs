@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::time::Duration;
+
+use user_lib::*;
+
+/// Read the counter named `name` from /proc/timer_stats.
+fn read_counter(name: &str) -> usize {
+    let fd = openat("/proc/timer_stats\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return 0;
+    }
+    let mut buf = [0u8; 256];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).unwrap_or_default();
+    info.lines()
+        .find_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            (key == name).then(|| value.trim().parse().ok())?
+        })
+        .unwrap_or(0)
+}
+
+fn now() -> Duration {
+    let mut ts = TimeSpec::default();
+    clock_gettime(CLOCK_MONOTONIC, &mut ts);
+    ts.into()
+}
+
+fn on_usr1(_signal: usize) {}
+
+/// Poll the read end `rfd` of a pipe for at most `timeout_ms`, and return the
+/// result with the time taken and the timers fired and cancelled meanwhile.
+fn poll_pipe(rfd: usize, timeout_ms: usize) -> (Result<usize, SyscallErr>, Duration, usize, usize) {
+    let (fired, cancelled) = (read_counter("fired"), read_counter("cancelled"));
+    let mut fds = [PollFd {
+        fd: rfd as i32,
+        events: POLLIN,
+        revents: 0,
+    }];
+    let begin = now();
+    let ret = ppoll(&mut fds, timeout_ms);
+    let elapsed = now() - begin;
+    (
+        ret,
+        elapsed,
+        read_counter("fired") - fired,
+        read_counter("cancelled") - cancelled,
+    )
+}
+
+/// Check the timer of a timed wait in the kernel fires when the wait expires,
+/// and is cancelled when the wait completes early or is interrupted, which
+/// drops the wait before the timer expires.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("timeout");
+    let mut fds = [0i32; 2];
+    if pipe(&mut fds) < 0 {
+        println!("pipe failed");
+        return -1;
+    }
+    let (rfd, wfd) = (fds[0] as usize, fds[1] as usize);
+
+    let (ret, elapsed, fired, _) = poll_pipe(rfd, 100);
+    result.check("ppoll expired", matches!(ret, Ok(0)));
+    result.check(
+        "time of the expired ppoll",
+        elapsed >= Duration::from_millis(100),
+    );
+    result.check("timer of the expired ppoll", fired >= 1);
+
+    let writer = thread::spawn(move || {
+        sleep(50);
+        write(wfd, b"x");
+    })
+    .unwrap();
+    let (ret, elapsed, _, cancelled) = poll_pipe(rfd, 10000);
+    writer.join();
+    result.check("ppoll completed early", matches!(ret, Ok(1)));
+    result.check(
+        "time of the ppoll completed early",
+        elapsed < Duration::from_secs(1),
+    );
+    result.check("timer of the ppoll completed early", cancelled >= 1);
+    read(rfd, &mut [0u8; 1]);
+
+    let mut act = SigAction::default();
+    let mut old = SigAction::default();
+    act.sa_handler = on_usr1 as usize;
+    sigaction(Sig::SIGUSR1, &act, &mut old);
+    let parent = getpid();
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        kill(parent, Sig::SIGUSR1);
+        exit(0);
+    }
+    let (ret, elapsed, _, cancelled) = poll_pipe(rfd, 10000);
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    result.check("ppoll interrupted", matches!(ret, Err(SyscallErr::EINTR)));
+    result.check(
+        "time of the ppoll interrupted",
+        elapsed < Duration::from_secs(1),
+    );
+    result.check("timer of the ppoll interrupted", cancelled >= 1);
+
+    close(rfd);
+    close(wfd);
+    result.finish()
+}
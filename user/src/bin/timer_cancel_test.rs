@@ -0,0 +1,128 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use user_lib::*;
+
+/// Number of times the word is passed to the other thread and back, each of
+/// which arms two timers and cancels them before they expire.
+const ROUNDS: u32 = 20000;
+/// Hart both threads are pinned on, so that every wait blocks.
+const HART: usize = 0;
+
+static WORD: AtomicU32 = AtomicU32::new(0);
+/// Waits returning without the word changed or with ETIMEDOUT.
+static SPURIOUS: AtomicUsize = AtomicUsize::new(0);
+
+/// Read the counter named `name` from /proc/timer_stats.
+fn read_counter(name: &str) -> Option<usize> {
+    let fd = openat("/proc/timer_stats\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 256];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    info.lines().find_map(|line| {
+        let (key, value) = line.split_once(' ')?;
+        (key == name).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Wait until the word is `value`, with a timeout far longer than the other
+/// thread takes to pass it.
+fn wait_for(value: u32) {
+    let timeout = TimeSpec {
+        tv_sec: 10,
+        tv_nsec: 0,
+    };
+    loop {
+        let cur = WORD.load(Ordering::SeqCst);
+        if cur == value {
+            return;
+        }
+        let ret = futex(
+            WORD.as_ptr() as usize,
+            FUTEX_WAIT | FUTEX_PRIVATE_FLAG,
+            cur,
+            &timeout as *const TimeSpec as usize,
+            0,
+            0,
+        );
+        if (ret == 0 && WORD.load(Ordering::SeqCst) == cur)
+            || matches!(SyscallErr::from_ret(ret), Err(SyscallErr::ETIMEDOUT))
+        {
+            SPURIOUS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn pass(value: u32) {
+    WORD.store(value, Ordering::SeqCst);
+    futex(
+        WORD.as_ptr() as usize,
+        FUTEX_WAKE | FUTEX_PRIVATE_FLAG,
+        1,
+        0,
+        0,
+        0,
+    );
+}
+
+/// Two threads play ping-pong with timed futex waits, which are woken long
+/// before their timers expire. The timers must all be cancelled without any
+/// firing, and purged from the timer queues to bound the memory.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin timer cancel test");
+    let (Some(fired), Some(cancelled)) = (read_counter("fired"), read_counter("cancelled")) else {
+        println!("can not read /proc/timer_stats");
+        return -1;
+    };
+    sched_setaffinity(0, 1 << HART);
+    let other = thread::spawn(|| {
+        sched_setaffinity(0, 1 << HART);
+        for round in 0..ROUNDS {
+            wait_for(2 * round + 1);
+            pass(2 * round + 2);
+        }
+    })
+    .unwrap();
+    for round in 0..ROUNDS {
+        pass(2 * round + 1);
+        wait_for(2 * round + 2);
+    }
+    other.join();
+
+    let fired = read_counter("fired").unwrap_or(usize::MAX) - fired;
+    let cancelled = read_counter("cancelled").unwrap_or(0) - cancelled;
+    let queued = read_counter("queued").unwrap_or(usize::MAX);
+    let spurious = SPURIOUS.load(Ordering::Relaxed);
+    println!(
+        "fired {}, cancelled {}, queued {}, spurious wakeups {}",
+        fired, cancelled, queued, spurious
+    );
+    let mut ok = true;
+    if spurious != 0 || fired >= ROUNDS as usize / 100 {
+        println!("cancelled timers fire");
+        ok = false;
+    }
+    if cancelled < ROUNDS as usize {
+        println!("timers are not cancelled");
+        ok = false;
+    }
+    if queued >= 1000 {
+        println!("cancelled timers are not purged from the queues");
+        ok = false;
+    }
+    if ok {
+        println!("timer cancel test passed");
+        0
+    } else {
+        -1
+    }
+}
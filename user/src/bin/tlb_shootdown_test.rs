@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+/// Harts the two threads are pinned on, which are skipped if offline.
+const HART_A: usize = 0;
+const HART_B: usize = 1;
+/// Writes done by thread B before the page is made read-only, so that its TLB
+/// surely caches the writable entry.
+const WARM_WRITES: usize = 10000;
+
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+static FAULTED: AtomicBool = AtomicBool::new(false);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+fn on_segv(_signal: usize) {
+    FAULTED.store(true, Ordering::SeqCst);
+    // exit the faulting thread only, instead of retrying the write
+    exit(0);
+}
+
+/// Thread B keeps writing a page on one hart while thread A makes it read-only
+/// on another hart, after which B must fault on its next write rather than
+/// write through a stale TLB entry.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin tlb shootdown test");
+    let mut online = 0;
+    if sched_getaffinity(0, &mut online) < 0 {
+        println!("sched_getaffinity failed");
+        return -1;
+    }
+    if online & (1 << HART_A) == 0 || online & (1 << HART_B) == 0 {
+        println!("hart {} or {} is offline, skipped", HART_A, HART_B);
+        return 0;
+    }
+
+    let page = mmap(
+        ptr::null(),
+        PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    if page < 0 {
+        println!("mmap failed");
+        return -1;
+    }
+    let page = page as usize;
+    let mut act = SigAction::default();
+    let mut old = SigAction::default();
+    act.sa_handler = on_segv as usize;
+    if sigaction(Sig::SIGSEGV, &act, &mut old) < 0 {
+        println!("sigaction failed");
+        return -1;
+    }
+
+    sched_setaffinity(0, 1 << HART_A);
+    let writer = thread::spawn(move || {
+        sched_setaffinity(0, 1 << HART_B);
+        let mut n = 0usize;
+        while !STOP.load(Ordering::SeqCst) {
+            unsafe { ptr::write_volatile(page as *mut usize, n) };
+            WRITES.fetch_add(1, Ordering::SeqCst);
+            n += 1;
+        }
+    });
+    let Ok(writer) = writer else {
+        println!("spawn failed");
+        return -1;
+    };
+    while WRITES.load(Ordering::SeqCst) < WARM_WRITES {
+        core::hint::spin_loop();
+    }
+
+    if mprotect(page as *const u8, PAGE_SIZE, PROT_READ) != 0 {
+        println!("mprotect failed");
+        return -1;
+    }
+    // at most one write may have been done before the flush and counted after
+    let writes = WRITES.load(Ordering::SeqCst);
+    sleep(50);
+    let late_writes = WRITES.load(Ordering::SeqCst) - writes;
+    let faulted = FAULTED.load(Ordering::SeqCst);
+    STOP.store(true, Ordering::SeqCst);
+    writer.join();
+    sched_setaffinity(0, online);
+
+    if late_writes > 1 || !faulted {
+        println!(
+            "{} writes after mprotect, faulted: {}",
+            late_writes, faulted
+        );
+        return -1;
+    }
+    println!("tlb shootdown test passed");
+    0
+}
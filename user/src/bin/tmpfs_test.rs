@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const ENOSPC: isize = 28;
+
+/// Mount a tmpfs of 1 MiB, and write 2 MiB to it, which should fail with
+/// ENOSPC after about 1 MiB written.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin tmpfs test");
+    mkdir("/tmpfs_test\0", 0o755);
+    if mount("tmpfs\0", "/tmpfs_test\0", "tmpfs\0", 0, "size=1m\0") != 0 {
+        println!("mount tmpfs failed");
+        return -1;
+    }
+    let fd = openat(
+        "/tmpfs_test/file\0",
+        OpenFlags::O_CREATE | OpenFlags::O_RDWR,
+    );
+    if fd < 0 {
+        println!("open failed: {}", fd);
+        return -1;
+    }
+    let buf = [0x5au8; 4096];
+    let mut written = 0;
+    let ret = loop {
+        let ret = write(fd as usize, &buf);
+        if ret <= 0 {
+            break ret;
+        }
+        written += ret as usize;
+        if written >= 2 * 1024 * 1024 {
+            break 0;
+        }
+    };
+    close(fd as usize);
+    println!("written {} bytes, last write returns {}", written, ret);
+    if ret == -ENOSPC && written == 1024 * 1024 {
+        println!("tmpfs test passed");
+        0
+    } else {
+        println!("tmpfs test failed");
+        -1
+    }
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// Major of the serial ports, and minor of ttyS0, as Linux numbers them.
+const TTY_MAJOR: u64 = 4;
+const TTYS_MINOR_BASE: u64 = 64;
+
+/// Device number of the character device at `path`.
+fn rdev_of(path: &str) -> Option<u64> {
+    let mut st = Stat::default();
+    (stat(path, &mut st) == 0 && st.st_mode & S_IFMT == S_IFCHR).then_some(st.st_rdev)
+}
+
+/// Check that /dev/console and every /dev/ttyS<N> are character devices, that
+/// each port reports its own device number, and that ttyS0 can be written to.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("tty");
+
+    result.check("/dev/console", rdev_of("/dev/console\0").is_some());
+    result.check(
+        "device number of /dev/ttyS0",
+        rdev_of("/dev/ttyS0\0") == Some((TTY_MAJOR << 8) | TTYS_MINOR_BASE),
+    );
+    match rdev_of("/dev/ttyS1\0") {
+        Some(rdev) => result.check(
+            "device number of /dev/ttyS1",
+            rdev == (TTY_MAJOR << 8) | (TTYS_MINOR_BASE + 1),
+        ),
+        None => println!("there is no second serial port, skipped"),
+    }
+
+    let fd = openat("/dev/ttyS0\0", OpenFlags::O_WRONLY);
+    result.check("open /dev/ttyS0", fd >= 0);
+    result.check("write to /dev/ttyS0", write(fd as usize, b"ttyS0\n") == 6);
+    close(fd as usize);
+    result.finish()
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const DIR: &str = "/umask_test\0";
+const FILE: &str = "/umask_test/file\0";
+const FIFO: &str = "/umask_test/fifo\0";
+
+/// File type and permission bits of `path`, or `None` if it can not be stat.
+fn mode_of(path: &str) -> Option<(u32, u32)> {
+    let mut st = Stat::default();
+    (stat(path, &mut st) == 0).then_some((st.st_mode & S_IFMT, st.st_mode & 0o7777))
+}
+
+/// Create a directory, a regular file and a named pipe under umask 077, which
+/// must take away all permissions of the group and others, and check that the
+/// umask is inherited across fork.
+#[no_mangle]
+fn main() -> i32 {
+    let mut result = TestResult::begin("umask");
+
+    let old = umask(0o077);
+    result.check("default umask", old == 0o022);
+    result.check("umask returns the previous one", umask(0o077) == 0o077);
+
+    result.check("mkdir", mkdir(DIR, 0o777) == 0);
+    result.check(
+        "mode of the directory",
+        mode_of(DIR) == Some((S_IFDIR, 0o700)),
+    );
+    let fd = openat_dirfd(
+        AT_FDCWD,
+        FILE,
+        OpenFlags::O_CREATE | OpenFlags::O_RDWR,
+        0o666,
+    );
+    result.check("open with O_CREAT", fd >= 0);
+    close(fd as usize);
+    result.check("mode of the file", mode_of(FILE) == Some((S_IFREG, 0o600)));
+    result.check("mknod", mknod(FIFO, (S_IFIFO | 0o666) as usize, 0) == 0);
+    result.check("mode of the fifo", mode_of(FIFO) == Some((S_IFIFO, 0o600)));
+
+    let pid = fork();
+    if pid == 0 {
+        exit(if umask(0) == 0o077 { 0 } else { -1 });
+    }
+    let mut wstatus = 0;
+    waitpid(pid as usize, &mut wstatus);
+    result.check("umask inherited across fork", ExitStatus(wstatus).success());
+
+    unlink(FIFO);
+    unlink(FILE);
+    rmdir(DIR);
+    umask(old as usize);
+    result.finish()
+}
@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const ROUNDS: usize = 10000;
+
+fn nanos(ts: &TimeSpec) -> i128 {
+    ts.tv_sec as i128 * 1_000_000_000 + ts.tv_nsec as i128
+}
+
+/// Nanoseconds taken by `ROUNDS` calls of `f`.
+fn bench(mut f: impl FnMut()) -> i128 {
+    let (mut begin, mut end) = (TimeSpec::default(), TimeSpec::default());
+    clock_gettime(CLOCK_MONOTONIC, &mut begin);
+    for _ in 0..ROUNDS {
+        f();
+    }
+    clock_gettime(CLOCK_MONOTONIC, &mut end);
+    nanos(&end) - nanos(&begin)
+}
+
+/// Check the clocks read through the vDSO agree with the syscall, follow
+/// clock_settime, and are faster.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin vdso test");
+    if vdso::vdso_sym("__vdso_clock_gettime").is_none()
+        || vdso::vdso_sym("__vdso_gettimeofday").is_none()
+    {
+        println!("vdso symbols are missing");
+        return -1;
+    }
+    let mut ok = true;
+
+    for clockid in [CLOCK_REALTIME, CLOCK_MONOTONIC] {
+        let (mut before, mut fast, mut after) = Default::default();
+        clock_gettime(clockid, &mut before);
+        vdso::clock_gettime(clockid, &mut fast);
+        clock_gettime(clockid, &mut after);
+        if nanos(&fast) < nanos(&before) || nanos(&fast) > nanos(&after) {
+            println!(
+                "clock {} from vdso {:?} is not between {:?} and {:?}",
+                clockid, fast, before, after
+            );
+            ok = false;
+        }
+    }
+
+    // move the realtime forward by an hour and back
+    let mut now = TimeSpec::default();
+    clock_gettime(CLOCK_REALTIME, &mut now);
+    let later = TimeSpec {
+        tv_sec: now.tv_sec + 3600,
+        tv_nsec: now.tv_nsec,
+    };
+    if clock_settime(CLOCK_REALTIME, &later) == 0 {
+        let mut fast = TimeSpec::default();
+        vdso::clock_gettime(CLOCK_REALTIME, &mut fast);
+        if fast.tv_sec < later.tv_sec || fast.tv_sec > later.tv_sec + 1 {
+            println!("vdso does not follow clock_settime, got {:?}", fast);
+            ok = false;
+        }
+        clock_gettime(CLOCK_REALTIME, &mut now);
+        now.tv_sec -= 3600;
+        clock_settime(CLOCK_REALTIME, &now);
+    } else {
+        println!("clock_settime failed, skip");
+    }
+
+    let mut ts = TimeSpec::default();
+    let slow = bench(|| {
+        clock_gettime(CLOCK_MONOTONIC, &mut ts);
+    });
+    let fast = bench(|| {
+        vdso::clock_gettime(CLOCK_MONOTONIC, &mut ts);
+    });
+    println!("{} calls: syscall {}ns, vdso {}ns", ROUNDS, slow, fast);
+    if fast >= slow {
+        println!("vdso is not faster than the syscall");
+        ok = false;
+    }
+
+    if ok {
+        println!("vdso test passed");
+        0
+    } else {
+        -1
+    }
+}
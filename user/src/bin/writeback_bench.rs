@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const FILE: &str = "/writeback_bench\0";
+const FILE_SIZE: usize = 16 * 1024 * 1024;
+const BUF_SIZE: usize = 4096;
+
+fn now_usec() -> usize {
+    let mut time_val = TimeVal::from_usec(0);
+    gettimeofday(&mut time_val);
+    time_val.into_usec()
+}
+
+/// Read the writes issued to the block device and the ones merged into them
+/// from /proc/diskstats.
+fn read_writes() -> Option<(usize, usize)> {
+    let fd = openat("/proc/diskstats\0", OpenFlags::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 512];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let mut fields = info.lines().next()?.split_whitespace().skip(7);
+    let writes = fields.next()?.parse().ok()?;
+    let merged = fields.next()?.parse().ok()?;
+    Some((writes, merged))
+}
+
+/// Write a file sequentially and sync it, where the blocks written back are
+/// merged into fewer requests to the device.
+#[no_mangle]
+fn main() -> i32 {
+    println!("begin writeback bench");
+    let fd = openat(
+        FILE,
+        OpenFlags::O_RDWR | OpenFlags::O_CREATE | OpenFlags::O_TRUNC,
+    );
+    if fd < 0 {
+        println!("open {} failed: {}", FILE, fd);
+        return -1;
+    }
+    let fd = fd as usize;
+    let buf = [0x5au8; BUF_SIZE];
+    for _ in 0..FILE_SIZE / BUF_SIZE {
+        write(fd, &buf);
+    }
+    close(fd);
+    let Some((writes, merged)) = read_writes() else {
+        println!("no block device in /proc/diskstats");
+        return -1;
+    };
+    let begin = now_usec();
+    sync();
+    let usec = (now_usec() - begin).max(1);
+    let (writes_after, merged_after) = read_writes().unwrap();
+    let requests = writes_after - writes;
+    let writes = requests + merged_after - merged;
+    println!(
+        "{} writes merged into {} requests in {} us",
+        writes, requests, usec
+    );
+    0
+}
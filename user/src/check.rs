@@ -0,0 +1,37 @@
+//! Reporting of checks in user tests.
+
+/// Report `what` if a check does not `pass`, and return whether it passed.
+pub fn check(what: &str, pass: bool) -> bool {
+    if !pass {
+        println!("{} is wrong", what);
+    }
+    pass
+}
+
+/// Outcome of a test, which fails once any of its checks fails.
+pub struct TestResult {
+    name: &'static str,
+    ok: bool,
+}
+
+impl TestResult {
+    /// Begin the test called `name`.
+    pub fn begin(name: &'static str) -> Self {
+        println!("begin {} test", name);
+        Self { name, ok: true }
+    }
+
+    pub fn check(&mut self, what: &str, pass: bool) {
+        self.ok &= check(what, pass);
+    }
+
+    /// Finish the test, and return the exit code of it.
+    pub fn finish(self) -> i32 {
+        if self.ok {
+            println!("{} test passed", self.name);
+            0
+        } else {
+            -1
+        }
+    }
+}
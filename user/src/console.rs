@@ -1,21 +1,148 @@
-use core::fmt::{self, Write};
+use alloc::{string::String, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Write},
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 const STDIN: usize = 0;
 const STDOUT: usize = 1;
+const STDERR: usize = 2;
+
+const STDOUT_BUF_LEN: usize = 1024;
 
 use super::{read, write};
 
-struct Stdout;
+/// Write all of `buf` to `fd`, retrying on short writes.
+fn write_all(fd: usize, mut buf: &[u8]) {
+    while !buf.is_empty() {
+        let ret = write(fd, buf);
+        if ret <= 0 {
+            return;
+        }
+        buf = &buf[ret as usize..];
+    }
+}
+
+struct StdoutBuf {
+    buf: [u8; STDOUT_BUF_LEN],
+    len: usize,
+}
+
+impl StdoutBuf {
+    fn flush(&mut self) {
+        write_all(STDOUT, &self.buf[..self.len]);
+        self.len = 0;
+    }
+}
 
-impl Write for Stdout {
+impl Write for StdoutBuf {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        write(STDOUT, s.as_bytes());
+        for &c in s.as_bytes() {
+            if self.len == STDOUT_BUF_LEN {
+                self.flush();
+            }
+            self.buf[self.len] = c;
+            self.len += 1;
+        }
+        if s.contains('\n') {
+            self.flush();
+        }
+        Ok(())
+    }
+}
+
+/// Line buffered stdout, which is flushed on '\n' and when the buffer gets
+/// full. It must also be flushed before the process exits, forks or execs, or
+/// the output buffered will be lost or printed twice.
+struct Stdout {
+    locked: AtomicBool,
+    inner: UnsafeCell<StdoutBuf>,
+}
+
+// Threads created by `create_thread` share the buffer, which is protected by
+// `locked`.
+unsafe impl Sync for Stdout {}
+
+static STDOUT_BUF: Stdout = Stdout {
+    locked: AtomicBool::new(false),
+    inner: UnsafeCell::new(StdoutBuf {
+        buf: [0; STDOUT_BUF_LEN],
+        len: 0,
+    }),
+};
+
+struct StdoutGuard<'a>(&'a Stdout);
+
+impl Stdout {
+    fn try_lock(&self) -> Option<StdoutGuard<'_>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| StdoutGuard(self))
+    }
+
+    fn lock(&self) -> StdoutGuard<'_> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            hint::spin_loop();
+        }
+    }
+}
+
+impl Deref for StdoutGuard<'_> {
+    type Target = StdoutBuf;
+
+    fn deref(&self) -> &StdoutBuf {
+        unsafe { &*self.0.inner.get() }
+    }
+}
+
+impl DerefMut for StdoutGuard<'_> {
+    fn deref_mut(&mut self) -> &mut StdoutBuf {
+        unsafe { &mut *self.0.inner.get() }
+    }
+}
+
+impl Drop for StdoutGuard<'_> {
+    fn drop(&mut self) {
+        self.0.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Unbuffered stderr.
+struct Stderr;
+
+impl Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(STDERR, s.as_bytes());
         Ok(())
     }
 }
 
 pub fn print(args: fmt::Arguments) {
-    Stdout.write_fmt(args).unwrap();
+    STDOUT_BUF.lock().write_fmt(args).unwrap();
+}
+
+pub fn eprint(args: fmt::Arguments) {
+    Stderr.write_fmt(args).unwrap();
+}
+
+/// Write out what is buffered in stdout.
+pub fn flush() {
+    STDOUT_BUF.lock().flush();
+}
+
+/// Flush stdout when panicking, which is skipped if the panic comes from a
+/// print holding the buffer.
+pub(crate) fn flush_on_panic() {
+    if let Some(mut stdout) = STDOUT_BUF.try_lock() {
+        stdout.flush();
+    }
 }
 
 #[macro_export]
@@ -32,8 +159,51 @@ macro_rules! println {
     }
 }
 
+#[macro_export]
+macro_rules! eprint {
+    ($fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::eprint(format_args!($fmt $(, $($arg)+)?));
+    }
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    ($fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::eprint(format_args!(concat!($fmt, "\n") $(, $($arg)+)?));
+    }
+}
+
 pub fn getchar() -> u8 {
+    flush();
     let mut c = [0u8; 1];
     read(STDIN, &mut c);
     c[0]
 }
+
+/// Read a line from stdin and append it to `buf`, including the '\n' if any.
+/// Stdout is flushed first so that a prompt printed without '\n' shows up.
+///
+/// Returns the number of bytes read, which is zero at EOF, or a negative
+/// error.
+pub fn read_line(buf: &mut String) -> isize {
+    flush();
+    let mut line = Vec::new();
+    let mut c = [0u8; 1];
+    // Read byte by byte to leave the rest in stdin for children we exec.
+    let ret = loop {
+        match read(STDIN, &mut c) {
+            1 => {
+                line.push(c[0]);
+                if c[0] == b'\n' {
+                    break 0;
+                }
+            }
+            ret => break ret,
+        }
+    };
+    if ret < 0 {
+        return ret;
+    }
+    buf.push_str(&String::from_utf8_lossy(&line));
+    line.len() as isize
+}
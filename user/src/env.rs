@@ -0,0 +1,76 @@
+//! Environment variables of the process.
+//!
+//! Entries are borrowed from the initial stack set up by the kernel, and only
+//! copied into the heap when changed by `setenv`.
+
+use alloc::{
+    borrow::Cow,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::SyscallErr;
+
+static mut ENVIRON: Vec<Cow<'static, str>> = Vec::new();
+
+fn environ_mut() -> &'static mut Vec<Cow<'static, str>> {
+    unsafe { &mut *core::ptr::addr_of_mut!(ENVIRON) }
+}
+
+/// Collect entries of the null terminated `envp`.
+///
+/// Return the address following the terminator, where auxv starts.
+pub(crate) unsafe fn init(envp: *const usize) -> *const usize {
+    let environ = environ_mut();
+    for i in 0.. {
+        let str_start = envp.add(i).read_volatile();
+        if str_start == 0 {
+            return envp.add(i + 1);
+        }
+        environ.push(Cow::Borrowed(crate::c_str_at(str_start)));
+    }
+    unreachable!()
+}
+
+/// Split an entry into name and value. Entries without '=' have no value, and
+/// are never matched by name, as glibc does.
+fn split(entry: &str) -> Option<(&str, &str)> {
+    entry.split_once('=')
+}
+
+/// Get the value of the environment variable `name`.
+pub fn getenv(name: &str) -> Option<String> {
+    environ_mut()
+        .iter()
+        .filter_map(|entry| split(entry))
+        .find(|&(n, _)| n == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Set the environment variable `name` to `value`, overwriting the existing
+/// one.
+pub fn setenv(name: &str, value: &str) -> Result<(), SyscallErr> {
+    if name.is_empty() || name.contains('=') {
+        return Err(SyscallErr::EINVAL);
+    }
+    let entry = Cow::Owned(format!("{name}={value}"));
+    let environ = environ_mut();
+    match environ
+        .iter()
+        .position(|entry| split(entry).is_some_and(|(n, _)| n == name))
+    {
+        Some(i) => environ[i] = entry,
+        None => environ.push(entry),
+    }
+    Ok(())
+}
+
+/// Iterate over all entries of the environment, in the form of "NAME=value".
+pub fn environ() -> impl Iterator<Item = String> {
+    environ_mut()
+        .iter()
+        .map(|entry| entry.to_string())
+        .collect::<Vec<_>>()
+        .into_iter()
+}
@@ -1,3 +1,5 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(isize)]
 pub enum SyscallErr {
     EUNDEF = 0,
     EPERM = 1,
@@ -134,3 +136,19 @@ pub enum SyscallErr {
     ERFKILL = 132,
     EHWPOISON = 133,
 }
+
+impl SyscallErr {
+    /// Convert the return value of a syscall, which is a negative errno on
+    /// failure.
+    pub fn from_ret(ret: isize) -> Result<usize, SyscallErr> {
+        if ret >= 0 {
+            return Ok(ret as usize);
+        }
+        let errno = -ret;
+        // 41 and 58 are aliases and have no variants
+        if errno > SyscallErr::EHWPOISON as isize || errno == 41 || errno == 58 {
+            return Err(SyscallErr::EUNDEF);
+        }
+        Err(unsafe { core::mem::transmute::<isize, SyscallErr>(errno) })
+    }
+}
@@ -0,0 +1,70 @@
+//! User heap, which starts empty and grows on demand by moving the program
+//! break, or by mapping anonymous memory when the break can not move.
+
+use core::alloc::Layout;
+
+use buddy_system_allocator::{Heap, LockedHeapWithRescue};
+
+use crate::syscall::{sys_brk, sys_mmap};
+
+const HEAP_ORDER: usize = 32;
+
+/// The heap grows by multiples of this size.
+const HEAP_GROW_SIZE: usize = 0x10000;
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_ANONYMOUS: usize = 0x20;
+
+#[global_allocator]
+static HEAP: LockedHeapWithRescue<HEAP_ORDER> = LockedHeapWithRescue::new(grow);
+
+#[alloc_error_handler]
+pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
+    panic!("Heap allocation error, layout = {:?}", layout);
+}
+
+const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// Called by the allocator when it runs out of memory, adding a range which
+/// holds a free block for `layout` to `heap`.
+fn grow(heap: &mut Heap<HEAP_ORDER>, layout: &Layout) {
+    // Blocks of a buddy allocator are aligned to their sizes, and ranges added
+    // separately are never merged into a larger block.
+    let block = layout
+        .size()
+        .max(layout.align())
+        .max(core::mem::size_of::<usize>())
+        .next_power_of_two();
+    let brk = sys_brk(0) as usize;
+    let end = align_up(align_up(brk, block) + block, HEAP_GROW_SIZE);
+    let (start, end) = if sys_brk(end) as usize == end {
+        (brk, end)
+    } else {
+        let len = align_up(2 * block, HEAP_GROW_SIZE);
+        let start = sys_mmap(
+            0,
+            len,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            usize::MAX,
+            0,
+        );
+        if start < 0 {
+            return;
+        }
+        (start as usize, start as usize + len)
+    };
+    unsafe { heap.add_to_heap(start, end) };
+}
+
+/// Set up a small heap to begin with.
+pub(crate) fn init() {
+    grow(
+        &mut HEAP.lock(),
+        &Layout::from_size_align(HEAP_GROW_SIZE, 1).unwrap(),
+    );
+}
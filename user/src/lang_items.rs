@@ -1,16 +1,20 @@
+use crate::{console::flush_on_panic, syscall::sys_exit};
+
 #[panic_handler]
 fn panic_handler(panic_info: &core::panic::PanicInfo) -> ! {
+    flush_on_panic();
     let err = panic_info.message().unwrap();
     if let Some(location) = panic_info.location() {
-        println!(
+        eprintln!(
             "Panicked at {}:{}, {}",
             location.file(),
             location.line(),
             err
         );
     } else {
-        println!("Panicked: {}", err);
+        eprintln!("Panicked: {}", err);
     }
+    // `exit` flushes stdout, which may be held by the print panicking
+    sys_exit(-1);
     loop {}
-    // exit(-1)
 }
@@ -3,102 +3,206 @@
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
 
+pub mod auxv;
 #[macro_use]
 pub mod console;
+mod check;
+mod env;
 mod error;
+mod heap;
 mod lang_items;
+mod process;
 #[allow(unused)]
 mod syscall;
+pub mod thread;
 pub mod types;
+pub mod vdso;
 
 #[macro_use]
 extern crate bitflags;
 extern crate alloc;
 
-use alloc::{ffi::CString, vec::Vec};
+use alloc::{ffi::CString, vec, vec::Vec};
 
+pub use auxv::getauxval;
 use bitflags::Flags;
-use buddy_system_allocator::LockedHeap;
+pub use check::{check, TestResult};
+pub use console::{flush, read_line};
+pub use env::{environ, getenv, setenv};
 pub use error::SyscallErr;
+pub use process::{spawn, Command, ExitStatus, Pid};
 use syscall::*;
 pub use types::*;
 
-// const USER_HEAP_SIZE: usize = 16384;
-const USER_HEAP_SIZE: usize = 0x32000;
-
-// Note that heap space is allocated in .data segment
-// TODO: can we change to dynamically allocate by invoking sys_sbrk?
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
-
-#[global_allocator]
-static HEAP: LockedHeap<32> = LockedHeap::empty();
-
-#[alloc_error_handler]
-pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
-    panic!("Heap allocation error, layout = {:?}", layout);
-}
-
 #[no_mangle]
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
-    unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
-
-        // FIXME: heap alloc will meet trouble when triple fork
-        // const HEAP_START: usize = 0x0000_0002_0000_0000;
-        // sys_brk(HEAP_START + USER_HEAP_SIZE);
-        // HEAP.lock().init(HEAP_START, USER_HEAP_SIZE);
-    }
+    heap::init();
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
         let str_start =
             unsafe { ((argv + i * core::mem::size_of::<usize>()) as *const usize).read_volatile() };
-        let len = (0usize..)
-            .find(|i| unsafe { ((str_start + *i) as *const u8).read_volatile() == 0 })
-            .unwrap();
-        v.push(
-            core::str::from_utf8(unsafe {
-                core::slice::from_raw_parts(str_start as *const u8, len)
-            })
-            .unwrap(),
-        );
+        v.push(unsafe { c_str_at(str_start) });
+    }
+    // envp follows the null terminator of argv, and auxv follows that of envp
+    if argv != 0 {
+        let envp = argv + (argc + 1) * core::mem::size_of::<usize>();
+        unsafe {
+            let auxv = env::init(envp as *const usize);
+            auxv::init(auxv);
+        }
     }
     let exit_code = main(argc, v.as_slice());
     // println!("program {} will exit", v[0]);
     exit(exit_code);
 }
 
+/// Borrow the null terminated string at `str_start` on the initial stack.
+unsafe fn c_str_at(str_start: usize) -> &'static str {
+    let len = (0usize..)
+        .find(|i| ((str_start + *i) as *const u8).read_volatile() == 0)
+        .unwrap();
+    core::str::from_utf8(core::slice::from_raw_parts(str_start as *const u8, len)).unwrap()
+}
+
 #[linkage = "weak"]
 #[no_mangle]
 fn main(_: usize, _: &[&str]) -> i32 {
     panic!("Cannot find main!");
 }
 
-#[macro_export]
-macro_rules! wexitstatus {
-    ($a:expr) => {
-        ($a & 0xffffff00) >> 8
-    };
+/// Get the path of the current working directory into `buf`, which ends with a
+/// null byte.
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf.as_mut_ptr(), buf.len())
+}
+
+pub fn mount(source: &str, target: &str, fstype: &str, flags: usize, data: &str) -> isize {
+    sys_mount(
+        source.as_ptr(),
+        target.as_ptr(),
+        fstype.as_ptr(),
+        flags,
+        data.as_ptr(),
+    )
 }
 
-// pub fn getcwd(path: usize, len: usize) -> isize {
-//     sys_getcwd(path, len)
-// }
+pub fn umount(target: &str) -> isize {
+    sys_umount2(target.as_ptr(), 0)
+}
 
-// pub fn mount(dev_name: usize, target_path: usize, ftype: usize, flags: u32,
-// data: usize) -> isize {     sys_mount(dev_name, target_path, ftype, flags,
-// data) }
+pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_ioctl(fd, cmd, arg)
+}
 
-// pub fn uname(buf: usize) -> isize {
-//     sys_uname(buf)
-// }
+pub fn chmod(path: &str, mode: usize) -> isize {
+    sys_fchmodat(AT_FDCWD, path.as_ptr(), mode)
+}
+
+pub fn uname(buf: &mut UtsName) -> isize {
+    sys_uname(buf as *mut UtsName as *mut usize)
+}
+
+pub fn sethostname(name: &str) -> isize {
+    sys_sethostname(name.as_ptr(), name.len())
+}
 
-pub fn futex(uaddd: usize, op: i32, val: u32, timeout: usize, uaddr2: usize, val3: u32) {
-    sys_futex(uaddd, op, val, timeout, uaddr2, val3);
+/// Restart, halt or power off the machine by `cmd`, e.g.
+/// `LINUX_REBOOT_CMD_POWER_OFF`, which only returns on failure.
+pub fn reboot(cmd: usize) -> isize {
+    const LINUX_REBOOT_MAGIC1: usize = 0xfee1dead;
+    const LINUX_REBOOT_MAGIC2: usize = 672274793;
+    console::flush();
+    sys_reboot(LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2, cmd, 0)
+}
+
+pub fn sync() {
+    sys_sync();
+}
+
+/// Copy the null terminated hostname into `buf`, which is the nodename of
+/// `uname` as glibc does.
+pub fn gethostname(buf: &mut [u8]) -> isize {
+    let mut uts = UtsName::default();
+    let ret = uname(&mut uts);
+    if ret < 0 {
+        return ret;
+    }
+    let len = uts.nodename.iter().position(|&c| c == 0).unwrap();
+    if buf.len() <= len {
+        return -(SyscallErr::ENAMETOOLONG as isize);
+    }
+    buf[..=len].copy_from_slice(&uts.nodename[..=len]);
+    0
+}
+
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path.as_ptr())
+}
+
+pub fn fchdir(fd: usize) -> isize {
+    sys_fchdir(fd)
+}
+
+pub fn unlink(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path.as_ptr(), 0)
+}
+
+pub fn rmdir(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path.as_ptr(), AT_REMOVEDIR)
+}
+
+pub fn rename(oldpath: &str, newpath: &str) -> isize {
+    sys_renameat2(AT_FDCWD, oldpath.as_ptr(), AT_FDCWD, newpath.as_ptr(), 0)
+}
+
+pub fn ftruncate(fd: usize, length: usize) -> isize {
+    sys_ftruncate(fd, length)
+}
+
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+
+pub fn memfd_create(name: &str, flags: usize) -> isize {
+    sys_memfd_create(name.as_ptr(), flags)
+}
+
+pub fn mkdir(path: &str, mode: usize) -> isize {
+    sys_mkdirat(AT_FDCWD, path.as_ptr(), mode)
+}
+
+/// Create a special file, e.g. a named pipe if `mode` has `S_IFIFO`.
+pub fn mknod(path: &str, mode: usize, dev: usize) -> isize {
+    sys_mknodat(AT_FDCWD, path.as_ptr(), mode, dev)
+}
+
+pub fn fstatat(dirfd: isize, path: &str, stat: &mut Stat, flags: usize) -> isize {
+    sys_newfstatat(dirfd, path.as_ptr(), stat as *mut Stat as *mut usize, flags)
+}
+
+pub fn stat(path: &str, stat: &mut Stat) -> isize {
+    fstatat(AT_FDCWD, path, stat, 0)
+}
+
+pub fn fstat(fd: usize, stat: &mut Stat) -> isize {
+    sys_fstat(fd, stat as *mut Stat as *mut usize)
+}
+
+/// Create a symbolic link `linkpath` which contains `target`.
+pub fn symlink(target: &str, linkpath: &str) -> isize {
+    sys_symlinkat(target.as_ptr(), AT_FDCWD, linkpath.as_ptr())
+}
+
+pub fn futex(uaddd: usize, op: i32, val: u32, timeout: usize, uaddr2: usize, val3: u32) -> isize {
+    sys_futex(uaddd, op, val, timeout, uaddr2, val3)
 }
 
 //************file system***************/
+/// Set the umask and return the previous one.
+pub fn umask(mask: usize) -> isize {
+    sys_umask(mask)
+}
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
@@ -109,12 +213,20 @@ pub fn openat(path: &str, flags: OpenFlags) -> isize {
     // TODO: change to the version that has `mode` arg
     sys_openat(AT_FDCWD as usize, path.as_ptr(), flags.bits() as usize, 0)
 }
+/// Open `path` relative to the directory `dirfd`, where a file created gets
+/// `mode` masked by the umask.
+pub fn openat_dirfd(dirfd: isize, path: &str, flags: OpenFlags, mode: usize) -> isize {
+    sys_openat(dirfd as usize, path.as_ptr(), flags.bits() as usize, mode)
+}
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf.as_mut_ptr(), buf.len())
 }
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf.as_ptr(), buf.len())
 }
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
 pub fn mmap(
     addr: *const u8,
     length: usize,
@@ -132,13 +244,54 @@ pub fn mmap(
         offset,
     )
 }
+pub fn munmap(addr: *const u8, length: usize) -> isize {
+    sys_munmap(addr as usize, length)
+}
+pub fn mprotect(addr: *const u8, length: usize, prot: i32) -> isize {
+    sys_mprotect(addr as usize, length, prot as usize)
+}
+pub fn mlock(addr: *const u8, length: usize) -> isize {
+    sys_mlock(addr as usize, length)
+}
+pub fn munlock(addr: *const u8, length: usize) -> isize {
+    sys_munlock(addr as usize, length)
+}
+pub fn mlockall(flags: usize) -> isize {
+    sys_mlockall(flags)
+}
+pub fn munlockall() -> isize {
+    sys_munlockall()
+}
+pub fn madvise(addr: *const u8, length: usize, advice: usize) -> isize {
+    sys_madvise(addr as usize, length, advice)
+}
+pub fn swapon(path: &str, flags: usize) -> isize {
+    sys_swapon(path.as_ptr(), flags)
+}
+pub fn swapoff(path: &str) -> isize {
+    sys_swapoff(path.as_ptr())
+}
+pub fn shmget(key: usize, size: usize, flags: usize) -> isize {
+    sys_shmget(key, size, flags)
+}
+pub fn shmat(shmid: usize, addr: *const u8, flags: usize) -> isize {
+    sys_shmat(shmid, addr as usize, flags)
+}
+pub fn shmdt(addr: *const u8) -> isize {
+    sys_shmdt(addr as usize)
+}
+pub fn shmctl(shmid: usize, cmd: usize, buf: usize) -> isize {
+    sys_shmctl(shmid, cmd, buf)
+}
 
 //************ task ***************/
 pub fn exit(exit_code: i32) -> ! {
+    console::flush();
     sys_exit(exit_code);
     loop {}
 }
 pub fn exit_group(exit_code: i32) -> ! {
+    console::flush();
     sys_exit_group(exit_code);
     loop {}
 }
@@ -150,10 +303,66 @@ pub fn getpid() -> isize {
     sys_getpid()
 }
 
+pub fn getppid() -> isize {
+    sys_getppid()
+}
+
+/// Restrict the thread `pid`, or the calling thread if zero, to the harts in
+/// the bitmask `mask`.
+pub fn sched_setaffinity(pid: usize, mask: usize) -> isize {
+    sys_sched_setaffinity(pid, core::mem::size_of::<usize>(), &mask)
+}
+
+/// Get the bitmask of harts that the thread `pid` may run on.
+pub fn sched_getaffinity(pid: usize, mask: &mut usize) -> isize {
+    sys_sched_getaffinity(pid, core::mem::size_of::<usize>(), mask)
+}
+
+/// Get the hart the calling thread is running on and its NUMA node.
+pub fn getcpu(cpu: Option<&mut u32>, node: Option<&mut u32>) -> isize {
+    sys_getcpu(
+        cpu.map_or(core::ptr::null_mut(), |cpu| cpu as *mut u32),
+        node.map_or(core::ptr::null_mut(), |node| node as *mut u32),
+    )
+}
+
+/// Set the nice value of the processes specified by `which` and `who`.
+pub fn setpriority(which: usize, who: usize, nice: i32) -> isize {
+    sys_setpriority(which, who, nice)
+}
+
+/// Get the nice value of the processes specified by `which` and `who`, which
+/// is converted from `20 - nice` returned by the raw syscall as libc does.
+pub fn getpriority(which: usize, who: usize) -> isize {
+    let ret = sys_getpriority(which, who);
+    if ret < 0 {
+        ret
+    } else {
+        20 - ret
+    }
+}
+
+pub fn getrusage(who: i32, usage: &mut Rusage) -> isize {
+    sys_getrusage(who, usage as *mut Rusage as *mut usize)
+}
+
 pub fn fork() -> isize {
+    // or the child will print what is buffered again
+    console::flush();
     sys_fork()
 }
 
+/// Like fork, but share with the child what `flags` selects, e.g. the fd table
+/// by `CloneFlags::FILES`.
+pub fn fork_with(flags: CloneFlags) -> isize {
+    console::flush();
+    sys_clone(flags.bits() as usize | Sig::SIGCHLD.raw(), 0, 0, 0)
+}
+
+pub fn unshare(flags: CloneFlags) -> isize {
+    sys_unshare(flags.bits() as usize)
+}
+
 pub fn create_thread(flags: CloneFlags) -> isize {
     let mut stack: [usize; 1024] = [0; 1024];
     sys_clone(flags.bits() as _, stack.as_mut_ptr() as usize, 0, 0)
@@ -162,15 +371,122 @@ pub fn create_thread(flags: CloneFlags) -> isize {
 pub fn kill(pid: isize, sig: Sig) -> isize {
     sys_kill(pid as usize, sig.raw() as i32)
 }
+
+/// Move the process `pid` to the group `pgid`, where zero means the caller and
+/// its own pid respectively.
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+pub fn getsid(pid: usize) -> isize {
+    sys_getsid(pid)
+}
+
+/// Create a session led by the caller, and return its id.
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+
+pub fn setuid(uid: u32) -> isize {
+    sys_setuid(uid)
+}
+
+/// Set the resource limit of process `pid` to `new` and get the old one, see
+/// prlimit(2).
+pub fn prlimit(
+    pid: usize,
+    resource: usize,
+    new: Option<&RLimit>,
+    old: Option<&mut RLimit>,
+) -> isize {
+    sys_prlimit64(
+        pid,
+        resource,
+        new.map_or(core::ptr::null(), |new| {
+            new as *const RLimit as *const usize
+        }),
+        old.map_or(core::ptr::null_mut(), |old| {
+            old as *mut RLimit as *mut usize
+        }),
+    )
+}
+
+/// Trace the thread `pid` as `request` asks, see ptrace(2). Unlike the libc
+/// wrapper, the word read by `PTRACE_PEEKDATA` is stored at `data`.
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> isize {
+    sys_ptrace(request, pid, addr, data)
+}
+
+/// Operate on the calling thread or process as `option` asks, see prctl(2).
+pub fn prctl(option: usize, arg2: usize, arg3: usize, arg4: usize) -> isize {
+    sys_prctl(option, arg2, arg3, arg4)
+}
+
+/// Execute `path`, passing the current environment if `envp` is empty.
 pub fn execve(path: &str, argv: &[&str], envp: &[&str]) -> isize {
-    let path = CString::new(path).unwrap();
-    let argv: Vec<_> = argv.iter().map(|s| CString::new(*s).unwrap()).collect();
-    let envp: Vec<_> = envp.iter().map(|s| CString::new(*s).unwrap()).collect();
-    let mut argv = argv.iter().map(|s| s.as_ptr() as usize).collect::<Vec<_>>();
-    let mut envp = envp.iter().map(|s| s.as_ptr() as usize).collect::<Vec<_>>();
-    argv.push(0);
-    envp.push(0);
-    sys_execve(path.as_ptr() as *const u8, argv.as_ptr(), envp.as_ptr())
+    ExecArgs::new(path, argv, envp).exec()
+}
+
+/// Exec with `argv` and `envp` passed as they are, which must end with null
+/// pointers, e.g. to pass the same string many times without copying it.
+/// `path` and the strings must end with null.
+pub fn execve_raw(path: &str, argv: &[*const u8], envp: &[*const u8]) -> isize {
+    console::flush();
+    sys_execve(
+        path.as_ptr(),
+        argv.as_ptr() as *const usize,
+        envp.as_ptr() as *const usize,
+    )
+}
+
+/// Arguments of execve built ahead, so that a vfork child can exec without
+/// allocating on the heap shared with the parent.
+pub(crate) struct ExecArgs {
+    path: CString,
+    _strings: Vec<CString>,
+    argv: Vec<usize>,
+    envp: Vec<usize>,
+}
+
+impl ExecArgs {
+    /// Arguments passing the current environment if `envp` is empty.
+    pub(crate) fn new(path: &str, argv: &[&str], envp: &[&str]) -> Self {
+        let path = CString::new(path).unwrap();
+        let argv: Vec<_> = argv.iter().map(|s| CString::new(*s).unwrap()).collect();
+        let envp: Vec<_> = if envp.is_empty() {
+            environ().map(|s| CString::new(s).unwrap()).collect()
+        } else {
+            envp.iter().map(|s| CString::new(*s).unwrap()).collect()
+        };
+        let ptrs = |strings: &[CString]| -> Vec<usize> {
+            let mut ptrs: Vec<_> = strings.iter().map(|s| s.as_ptr() as usize).collect();
+            ptrs.push(0);
+            ptrs
+        };
+        let (argv_ptrs, envp_ptrs) = (ptrs(&argv), ptrs(&envp));
+        let mut strings = argv;
+        strings.extend(envp);
+        Self {
+            path,
+            _strings: strings,
+            argv: argv_ptrs,
+            envp: envp_ptrs,
+        }
+    }
+
+    /// Returns only on failure.
+    pub(crate) fn exec(&self) -> isize {
+        console::flush();
+        sys_execve(
+            self.path.as_ptr() as *const u8,
+            self.argv.as_ptr(),
+            self.envp.as_ptr(),
+        )
+    }
 }
 
 pub fn wait(exit_code: &mut i32) -> isize {
@@ -181,19 +497,227 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     sys_waitpid(pid as isize, exit_code as *mut _)
 }
 
-pub fn pipe(pipe_fd: &mut [i32]) -> isize {
-    sys_pipe(pipe_fd[0] as *mut _)
+pub fn pipe(pipe_fd: &mut [i32; 2]) -> isize {
+    sys_pipe2(pipe_fd.as_mut_ptr(), 0)
+}
+
+pub fn pipe2(pipe_fd: &mut [i32; 2], flags: OpenFlags) -> isize {
+    sys_pipe2(pipe_fd.as_mut_ptr(), flags.bits() as usize)
 }
 
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 
+//************ net ***************/
+pub fn socket(domain: SaFamily, ty: SocketType, flags: SocketFlags) -> Result<usize, SyscallErr> {
+    SyscallErr::from_ret(sys_socket(domain as usize, ty as i32 | flags.bits(), 0))
+}
+
+pub fn bind(sockfd: usize, addr: &SockAddr) -> Result<(), SyscallErr> {
+    let (addr, addrlen) = addr.as_raw();
+    SyscallErr::from_ret(sys_bind(sockfd, addr, addrlen)).map(|_| ())
+}
+
+pub fn listen(sockfd: usize, backlog: usize) -> Result<(), SyscallErr> {
+    SyscallErr::from_ret(sys_listen(sockfd, backlog)).map(|_| ())
+}
+
+/// Returns the new connected socket and the address of the peer.
+pub fn accept(sockfd: usize) -> Result<(usize, Option<SockAddr>), SyscallErr> {
+    let mut raw = RawSockAddr::zeroed();
+    let mut addrlen = core::mem::size_of::<RawSockAddr>() as u32;
+    let fd = SyscallErr::from_ret(sys_accept(
+        sockfd,
+        &mut raw as *mut _ as *mut u8,
+        &mut addrlen,
+    ))?;
+    Ok((fd, SockAddr::from_raw(&raw)))
+}
+
+pub fn connect(sockfd: usize, addr: &SockAddr) -> Result<(), SyscallErr> {
+    let (addr, addrlen) = addr.as_raw();
+    SyscallErr::from_ret(sys_connect(sockfd, addr, addrlen)).map(|_| ())
+}
+
+/// Send to `dest`, which must be `None` for connected sockets.
+pub fn sendto(
+    sockfd: usize,
+    buf: &[u8],
+    flags: usize,
+    dest: Option<&SockAddr>,
+) -> Result<usize, SyscallErr> {
+    let (addr, addrlen) = dest.map_or((core::ptr::null(), 0), |addr| addr.as_raw());
+    SyscallErr::from_ret(sys_sendto(
+        sockfd,
+        buf.as_ptr(),
+        buf.len(),
+        flags,
+        addr,
+        addrlen,
+    ))
+}
+
+/// Returns the number of bytes received and the address of the sender.
+pub fn recvfrom(
+    sockfd: usize,
+    buf: &mut [u8],
+    flags: usize,
+) -> Result<(usize, Option<SockAddr>), SyscallErr> {
+    let mut raw = RawSockAddr::zeroed();
+    let mut addrlen = core::mem::size_of::<RawSockAddr>() as u32;
+    let len = SyscallErr::from_ret(sys_recvfrom(
+        sockfd,
+        buf.as_mut_ptr(),
+        buf.len(),
+        flags,
+        &mut raw as *mut _ as *mut u8,
+        &mut addrlen,
+    ))?;
+    Ok((len, SockAddr::from_raw(&raw)))
+}
+
+pub fn setsockopt<T>(
+    sockfd: usize,
+    level: usize,
+    optname: usize,
+    optval: &T,
+) -> Result<(), SyscallErr> {
+    SyscallErr::from_ret(sys_setsockopt(
+        sockfd,
+        level,
+        optname,
+        optval as *const T as *const u8,
+        core::mem::size_of::<T>(),
+    ))
+    .map(|_| ())
+}
+
+pub fn getsockname(sockfd: usize) -> Result<Option<SockAddr>, SyscallErr> {
+    let mut raw = RawSockAddr::zeroed();
+    let mut addrlen = core::mem::size_of::<RawSockAddr>() as u32;
+    SyscallErr::from_ret(sys_getsockname(
+        sockfd,
+        &mut raw as *mut _ as *mut u8,
+        &mut addrlen,
+    ))?;
+    Ok(SockAddr::from_raw(&raw))
+}
+
+pub fn shutdown(sockfd: usize, how: Shutdown) -> Result<(), SyscallErr> {
+    SyscallErr::from_ret(sys_shutdown(sockfd, how as usize)).map(|_| ())
+}
+
+/// Returns a pair of connected sockets.
+pub fn socketpair(
+    domain: SaFamily,
+    ty: SocketType,
+    flags: SocketFlags,
+) -> Result<[usize; 2], SyscallErr> {
+    let mut sv = [0u32; 2];
+    SyscallErr::from_ret(sys_socketpair(
+        domain as usize,
+        ty as i32 | flags.bits(),
+        0,
+        sv.as_mut_ptr(),
+    ))?;
+    Ok(sv.map(|fd| fd as usize))
+}
+
+pub fn sendmsg(sockfd: usize, msg: &MsgHdr, flags: usize) -> Result<usize, SyscallErr> {
+    SyscallErr::from_ret(sys_sendmsg(sockfd, msg as *const _ as *const u8, flags))
+}
+
+pub fn recvmsg(sockfd: usize, msg: &mut MsgHdr, flags: usize) -> Result<usize, SyscallErr> {
+    SyscallErr::from_ret(sys_recvmsg(sockfd, msg as *mut _ as *mut u8, flags))
+}
+
+/// Send `buf` on a unix domain socket with `fds` passed by `SCM_RIGHTS`.
+pub fn send_fds(sockfd: usize, buf: &[u8], fds: &[i32]) -> Result<usize, SyscallErr> {
+    let data_len = core::mem::size_of_val(fds);
+    // words keep the header aligned
+    let mut control = vec![0usize; CMsgHdr::space(data_len) / core::mem::size_of::<usize>()];
+    let cmsg = control.as_mut_ptr() as *mut CMsgHdr;
+    unsafe {
+        cmsg.write(CMsgHdr {
+            len: CMsgHdr::msg_len(data_len),
+            level: SOL_SOCKET as i32,
+            ty: SCM_RIGHTS,
+        });
+        core::ptr::copy_nonoverlapping(fds.as_ptr(), cmsg.add(1) as *mut i32, fds.len());
+    }
+    let mut iov = IoVec {
+        base: buf.as_ptr() as *mut u8,
+        len: buf.len(),
+    };
+    let msg = MsgHdr {
+        name: core::ptr::null_mut(),
+        namelen: 0,
+        iov: &mut iov,
+        iovlen: 1,
+        control: cmsg as *mut u8,
+        controllen: CMsgHdr::space(data_len),
+        flags: 0,
+    };
+    sendmsg(sockfd, &msg, 0)
+}
+
+/// Receive into `buf` from a unix domain socket, with file descriptors passed
+/// by `SCM_RIGHTS` stored in `fds`.
+///
+/// Returns the number of bytes and file descriptors received, and the flags of
+/// the message, where `MSG_CTRUNC` is set if some files do not fit in `fds`.
+pub fn recv_fds(
+    sockfd: usize,
+    buf: &mut [u8],
+    fds: &mut [i32],
+) -> Result<(usize, usize, i32), SyscallErr> {
+    let space = CMsgHdr::space(core::mem::size_of_val(fds));
+    let mut control = vec![0usize; space / core::mem::size_of::<usize>()];
+    let cmsg = control.as_mut_ptr() as *mut CMsgHdr;
+    let mut iov = IoVec {
+        base: buf.as_mut_ptr(),
+        len: buf.len(),
+    };
+    let mut msg = MsgHdr {
+        name: core::ptr::null_mut(),
+        namelen: 0,
+        iov: &mut iov,
+        iovlen: 1,
+        control: cmsg as *mut u8,
+        controllen: space,
+        flags: 0,
+    };
+    let len = recvmsg(sockfd, &mut msg, 0)?;
+    let mut nfds = 0;
+    if msg.controllen >= CMsgHdr::msg_len(0) {
+        let header = unsafe { cmsg.read() };
+        if header.level == SOL_SOCKET as i32 && header.ty == SCM_RIGHTS {
+            nfds =
+                ((header.len - CMsgHdr::msg_len(0)) / core::mem::size_of::<i32>()).min(fds.len());
+            unsafe {
+                core::ptr::copy_nonoverlapping(cmsg.add(1) as *const i32, fds.as_mut_ptr(), nfds)
+            };
+        }
+    }
+    Ok((len, nfds, msg.flags))
+}
+
 //************ time ***************/
 pub fn gettimeofday(time_val: &mut TimeVal) -> isize {
     sys_gettimeofday(time_val as *mut TimeVal as *mut usize, 0 as *mut usize)
 }
 
+/// Read the clock by the syscall. See `vdso::clock_gettime` for the faster
+/// one.
+pub fn clock_gettime(clockid: usize, tp: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clockid, tp as *mut TimeSpec as *mut usize)
+}
+
+pub fn clock_settime(clockid: usize, tp: &TimeSpec) -> isize {
+    sys_clock_settime(clockid, tp as *const TimeSpec as *const usize)
+}
+
 pub fn nanosleep(req: &TimeSpec, rem: &mut TimeSpec) -> isize {
     sys_nanosleep(
         req as *const TimeSpec as *const usize,
@@ -201,6 +725,39 @@ pub fn nanosleep(req: &TimeSpec, rem: &mut TimeSpec) -> isize {
     )
 }
 
+/// Wait at most `timeout_ms` for events on `fds`, and return the number of
+/// them with events.
+pub fn ppoll(fds: &mut [PollFd], timeout_ms: usize) -> Result<usize, SyscallErr> {
+    let timeout = TimeSpec::from_ms(timeout_ms);
+    SyscallErr::from_ret(sys_ppoll(
+        fds.as_mut_ptr() as *mut u8,
+        fds.len(),
+        &timeout as *const TimeSpec as *const usize,
+        core::ptr::null(),
+        0,
+    ))
+}
+
+/// Wait until some of the first `nfds` file descriptors in `readfds` or
+/// `writefds` are ready, or `timeout` passes, which is updated with the time
+/// remaining. Only the ready ones are left in the sets, and the number of them
+/// is returned.
+pub fn pselect(
+    nfds: usize,
+    readfds: &mut FdSet,
+    writefds: &mut FdSet,
+    timeout: &mut TimeSpec,
+) -> Result<usize, SyscallErr> {
+    SyscallErr::from_ret(sys_pselect6(
+        nfds,
+        readfds as *mut FdSet as *mut u8,
+        writefds as *mut FdSet as *mut u8,
+        core::ptr::null_mut(),
+        timeout as *mut TimeSpec as *mut usize,
+        core::ptr::null(),
+    ))
+}
+
 pub fn sleep(ms: usize) -> isize {
     let req = TimeSpec::from_ms(ms);
     let mut rem = TimeSpec::from_ms(0);
@@ -0,0 +1,198 @@
+//! Helpers to spawn processes and wait for them.
+
+use alloc::{vec, vec::Vec};
+use core::ptr::{null, read_volatile};
+
+use crate::{dup3, thread::__clone, waitpid, CloneFlags, ExecArgs, OpenFlags, Sig, SyscallErr};
+
+/// Exit code of the child when it fails to exec.
+const EXEC_FAILED: i32 = 127;
+/// Size of the stack of the child before it execs.
+const SPAWN_STACK_SIZE: usize = 0x4000;
+
+/// Shared by the parent and the vfork child, which are prepared by the parent
+/// so that the child does not allocate.
+struct SpawnArgs<'a> {
+    stdio: &'a [Option<usize>; 3],
+    exec_args: ExecArgs,
+    /// Error of the child, which is left zero if it execs.
+    ret: isize,
+}
+
+extern "C" fn spawn_child(arg: usize) -> i32 {
+    let args = unsafe { &mut *(arg as *mut SpawnArgs) };
+    args.ret = redirect_and_exec(args.stdio, &args.exec_args);
+    EXEC_FAILED
+}
+
+/// Redirect stdio and exec in the child, which returns only on failure.
+fn redirect_and_exec(stdio: &[Option<usize>; 3], exec_args: &ExecArgs) -> isize {
+    for (newfd, oldfd) in stdio.iter().enumerate() {
+        match *oldfd {
+            Some(oldfd) if oldfd != newfd => {
+                let ret = dup3(oldfd, newfd, OpenFlags::empty());
+                if ret < 0 {
+                    return ret;
+                }
+            }
+            _ => {}
+        }
+    }
+    exec_args.exec()
+}
+
+/// Status of a child reported by wait, in the layout of Linux.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExitStatus(pub i32);
+
+impl ExitStatus {
+    /// Exit code if the child exited normally.
+    pub fn code(&self) -> Option<i32> {
+        (self.0 & 0x7f == 0).then_some((self.0 >> 8) & 0xff)
+    }
+
+    /// Number of the signal which terminated the child.
+    pub fn signal(&self) -> Option<i32> {
+        let sig = self.0 & 0x7f;
+        (sig != 0 && sig != 0x7f).then_some(sig)
+    }
+
+    /// Whether the child produced a core dump when terminated by a signal.
+    pub fn core_dumped(&self) -> bool {
+        self.signal().is_some() && self.0 & 0x80 != 0
+    }
+
+    /// Number of the signal which stopped the child.
+    pub fn stopped(&self) -> Option<i32> {
+        (self.0 & 0xff == 0x7f).then_some((self.0 >> 8) & 0xff)
+    }
+
+    pub fn success(&self) -> bool {
+        self.code() == Some(0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pid(pub usize);
+
+impl Pid {
+    /// Wait for the child to change state.
+    pub fn wait(&self) -> Result<ExitStatus, SyscallErr> {
+        let mut wstatus = 0;
+        SyscallErr::from_ret(waitpid(self.0, &mut wstatus))?;
+        Ok(ExitStatus(wstatus))
+    }
+}
+
+/// Fork and exec `path` with `argv` and `envp`, where an empty `envp` passes
+/// the current environment.
+///
+/// Returns the error of execve in the parent if it fails.
+pub fn spawn(path: &str, argv: &[&str], envp: &[&str]) -> Result<Pid, SyscallErr> {
+    Command::new(path).argv(argv).envs(envp).spawn()
+}
+
+/// Builder of a child process, with its stdio redirected to file descriptors
+/// by dup3.
+pub struct Command<'a> {
+    path: &'a str,
+    argv: Vec<&'a str>,
+    envp: Vec<&'a str>,
+    /// File descriptors to redirect to stdin, stdout and stderr.
+    stdio: [Option<usize>; 3],
+}
+
+impl<'a> Command<'a> {
+    /// Command to run `path`, which is also `argv[0]`.
+    pub fn new(path: &'a str) -> Self {
+        Self {
+            path,
+            argv: vec![path],
+            envp: Vec::new(),
+            stdio: [None; 3],
+        }
+    }
+
+    /// Replace the whole argv including `argv[0]`.
+    pub fn argv(&mut self, argv: &[&'a str]) -> &mut Self {
+        self.argv = argv.to_vec();
+        self
+    }
+
+    pub fn arg(&mut self, arg: &'a str) -> &mut Self {
+        self.argv.push(arg);
+        self
+    }
+
+    pub fn args(&mut self, args: &[&'a str]) -> &mut Self {
+        self.argv.extend_from_slice(args);
+        self
+    }
+
+    /// Add an entry of "NAME=value". The current environment is passed if
+    /// none is added.
+    pub fn env(&mut self, entry: &'a str) -> &mut Self {
+        self.envp.push(entry);
+        self
+    }
+
+    pub fn envs(&mut self, entries: &[&'a str]) -> &mut Self {
+        self.envp.extend_from_slice(entries);
+        self
+    }
+
+    pub fn stdin(&mut self, fd: usize) -> &mut Self {
+        self.stdio[0] = Some(fd);
+        self
+    }
+
+    pub fn stdout(&mut self, fd: usize) -> &mut Self {
+        self.stdio[1] = Some(fd);
+        self
+    }
+
+    pub fn stderr(&mut self, fd: usize) -> &mut Self {
+        self.stdio[2] = Some(fd);
+        self
+    }
+
+    /// Start the child with `CLONE_VM | CLONE_VFORK` as posix_spawn(3) of musl
+    /// does, so that the memory of the parent is not copied just to be
+    /// replaced by exec. The child runs on its own stack while the parent is
+    /// suspended, and reports the errno by the shared memory if it fails to
+    /// redirect stdio or exec.
+    pub fn spawn(&mut self) -> Result<Pid, SyscallErr> {
+        let stack = vec![0u8; SPAWN_STACK_SIZE];
+        let mut args = SpawnArgs {
+            stdio: &self.stdio,
+            exec_args: ExecArgs::new(self.path, &self.argv, &self.envp),
+            ret: 0,
+        };
+        let flags = CloneFlags::VM | CloneFlags::VFORK;
+        let pid = SyscallErr::from_ret(unsafe {
+            __clone(
+                spawn_child,
+                stack.as_ptr() as usize + SPAWN_STACK_SIZE,
+                flags.bits() as usize | Sig::SIGCHLD.raw(),
+                &mut args as *mut SpawnArgs as usize,
+                null(),
+                0,
+                null(),
+            )
+        })?;
+        let ret = unsafe { read_volatile(&args.ret) };
+        if ret < 0 {
+            // reap the child which has exited
+            Pid(pid).wait()?;
+            return Err(SyscallErr::from_ret(ret)
+                .err()
+                .unwrap_or(SyscallErr::EUNDEF));
+        }
+        Ok(Pid(pid))
+    }
+
+    /// Spawn the child and wait for it to exit.
+    pub fn status(&mut self) -> Result<ExitStatus, SyscallErr> {
+        self.spawn()?.wait()
+    }
+}
@@ -8,12 +8,14 @@ const SYSCALL_IOCTL: usize = 29;
 const SYSCALL_UNLINK: usize = 35;
 const SYSCALL_MKNOD: usize = 33;
 const SYSCALL_MKDIR: usize = 34;
+const SYSCALL_SYMLINKAT: usize = 36;
 const SYSCALL_UMOUNT: usize = 39;
 const SYSCALL_MOUNT: usize = 40;
 const SYSCALL_STATFS: usize = 43;
 const SYSCALL_FTRUNCATE: usize = 46;
 const SYSCALL_FACCESSAT: usize = 48;
 const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_FCHDIR: usize = 50;
 const SYSCALL_FCHMODAT: usize = 53;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
@@ -38,6 +40,7 @@ const SYSCALL_UTIMENSAT: usize = 88;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_EXIT_GROUP: usize = 94;
 const SYSCALL_SET_TID_ADDRESS: usize = 96;
+const SYSCALL_UNSHARE: usize = 97;
 const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SET_ROBUST_LIST: usize = 99;
 const SYSCALL_GET_ROBUST_LIST: usize = 100;
@@ -49,6 +52,7 @@ const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_CLOCK_GETRES: usize = 114;
 const SYSCALL_CLOCK_NANOSLEEP: usize = 115;
 const SYSCALL_SYSLOG: usize = 116;
+const SYSCALL_PTRACE: usize = 117;
 const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
 const SYSCALL_SCHED_GETSCHEDULER: usize = 120;
 const SYSCALL_SCHED_GETPARAM: usize = 121;
@@ -63,13 +67,21 @@ const SYSCALL_RT_SIGACTION: usize = 134;
 const SYSCALL_RT_SIGPROCMASK: usize = 135;
 const SYSCALL_RT_SIGTIMEDWAIT: usize = 137;
 const SYSCALL_RT_SIGRETURN: usize = 139;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_REBOOT: usize = 142;
+const SYSCALL_SETUID: usize = 146;
 const SYSCALL_TIMES: usize = 153;
 const SYSCALL_SETPGID: usize = 154;
 const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
 const SYSCALL_SETSID: usize = 157;
 const SYSCALL_UNAME: usize = 160;
+const SYSCALL_SETHOSTNAME: usize = 161;
 const SYSCALL_GETRUSAGE: usize = 165;
 const SYSCALL_UMASK: usize = 166;
+const SYSCALL_PRCTL: usize = 167;
+const SYSCALL_GETCPU: usize = 168;
 const SYSCALL_GETTIMEOFDAY: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_GETPPID: usize = 173;
@@ -82,6 +94,7 @@ const SYSCALL_SYSINFO: usize = 179;
 const SYSCALL_SHMGET: usize = 194;
 const SYSCALL_SHMCTL: usize = 195;
 const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
 const SYSCALL_SOCKET: usize = 198;
 const SYSCALL_SOCKETPAIR: usize = 199;
 const SYSCALL_BIND: usize = 200;
@@ -95,18 +108,27 @@ const SYSCALL_RECVFROM: usize = 207;
 const SYSCALL_SETSOCKOPT: usize = 208;
 const SYSCALL_GETSOCKOPT: usize = 209;
 const SYSCALL_SHUTDOWN: usize = 210;
+const SYSCALL_SENDMSG: usize = 211;
+const SYSCALL_RECVMSG: usize = 212;
 const SYSCALL_BRK: usize = 214;
 const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_CLONE: usize = 220;
 const SYSCALL_EXECVE: usize = 221;
 const SYSCALL_MMAP: usize = 222;
+const SYSCALL_SWAPON: usize = 224;
+const SYSCALL_SWAPOFF: usize = 225;
 const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_MSYNC: usize = 227;
+const SYSCALL_MLOCK: usize = 228;
+const SYSCALL_MUNLOCK: usize = 229;
+const SYSCALL_MLOCKALL: usize = 230;
+const SYSCALL_MUNLOCKALL: usize = 231;
 const SYSCALL_MADVISE: usize = 233;
 const SYSCALL_WAIT4: usize = 260;
 const SYSCALL_PRLIMIT64: usize = 261;
 const SYSCALL_REMANEAT2: usize = 276;
 const SYSCALL_GETRANDOM: usize = 278;
+const SYSCALL_MEMFD_CREATE: usize = 279;
 const SYSCALL_MEMBARRIER: usize = 283;
 const SYSCALL_COPY_FILE_RANGE: usize = 285;
 
@@ -207,8 +229,30 @@ syscall!(sys_futex, SYSCALL_FUTEX, usize, i32, u32, usize, usize, u32);
 syscall!(sys_close, SYSCALL_CLOSE, usize);
 syscall!(sys_getcwd, SYSCALL_GETCWD, *mut u8, usize);
 syscall!(sys_chdir, SYSCALL_CHDIR, *const u8);
-syscall!(sys_mkdir, SYSCALL_MKDIR, *const u8);
+syscall!(sys_fchdir, SYSCALL_FCHDIR, usize);
+syscall!(sys_umask, SYSCALL_UMASK, usize);
+syscall!(sys_mkdirat, SYSCALL_MKDIR, isize, *const u8, usize);
+syscall!(sys_mknodat, SYSCALL_MKNOD, isize, *const u8, usize, usize);
+syscall!(
+    sys_symlinkat,
+    SYSCALL_SYMLINKAT,
+    *const u8,
+    isize,
+    *const u8
+);
+syscall!(sys_fstat, SYSCALL_FSTAT, usize, *mut usize);
+syscall!(
+    sys_newfstatat,
+    SYSCALL_NEWFSTATAT,
+    isize,
+    *const u8,
+    *mut usize,
+    usize
+);
+syscall!(sys_fchmodat, SYSCALL_FCHMODAT, isize, *const u8, usize);
 syscall!(sys_uname, SYSCALL_UNAME, *mut usize);
+syscall!(sys_sethostname, SYSCALL_SETHOSTNAME, *const u8, usize);
+syscall!(sys_reboot, SYSCALL_REBOOT, usize, usize, usize, usize);
 syscall!(sys_dup, SYSCALL_DUP, usize);
 syscall!(sys_dup3, SYSCALL_DUP3, usize, usize, usize);
 syscall!(sys_read, SYSCALL_READ, usize, *mut u8, usize);
@@ -223,19 +267,103 @@ syscall!(
     usize,
     usize
 );
+syscall!(sys_munmap, SYSCALL_MUNMAP, usize, usize);
+syscall!(sys_mprotect, SYSCALL_MPROTECT, usize, usize, usize);
+syscall!(sys_mlock, SYSCALL_MLOCK, usize, usize);
+syscall!(sys_munlock, SYSCALL_MUNLOCK, usize, usize);
+syscall!(sys_mlockall, SYSCALL_MLOCKALL, usize);
+syscall!(sys_munlockall, SYSCALL_MUNLOCKALL);
+syscall!(sys_madvise, SYSCALL_MADVISE, usize, usize, usize);
+syscall!(sys_swapon, SYSCALL_SWAPON, *const u8, usize);
+syscall!(sys_swapoff, SYSCALL_SWAPOFF, *const u8);
+syscall!(sys_shmget, SYSCALL_SHMGET, usize, usize, usize);
+syscall!(sys_shmat, SYSCALL_SHMAT, usize, usize, usize);
+syscall!(sys_shmdt, SYSCALL_SHMDT, usize);
+syscall!(sys_shmctl, SYSCALL_SHMCTL, usize, usize, usize);
 syscall!(sys_openat, SYSCALL_OPEN, usize, *const u8, usize, usize);
+syscall!(sys_lseek, SYSCALL_LSEEK, usize, isize, usize);
+syscall!(sys_ioctl, SYSCALL_IOCTL, usize, usize, usize);
+syscall!(sys_umount2, SYSCALL_UMOUNT, *const u8, usize);
+syscall!(sys_unlinkat, SYSCALL_UNLINK, isize, *const u8, usize);
+syscall!(sys_ftruncate, SYSCALL_FTRUNCATE, usize, usize);
+syscall!(sys_fcntl, SYSCALL_FCNTL, usize, usize, usize);
+syscall!(
+    sys_renameat2,
+    SYSCALL_REMANEAT2,
+    isize,
+    *const u8,
+    isize,
+    *const u8,
+    usize
+);
+syscall!(sys_memfd_create, SYSCALL_MEMFD_CREATE, *const u8, usize);
+syscall!(sys_sync, SYSCALL_SYNC);
+syscall!(
+    sys_pselect6,
+    SYSCALL_PSELECT6,
+    usize,
+    *mut u8,
+    *mut u8,
+    *mut u8,
+    *mut usize,
+    *const u8
+);
+syscall!(
+    sys_ppoll,
+    SYSCALL_PPOLL,
+    *mut u8,
+    usize,
+    *const usize,
+    *const u8,
+    usize
+);
 
 // task
 syscall!(sys_getpid, SYSCALL_GETPID);
+syscall!(sys_getppid, SYSCALL_GETPPID);
 syscall!(sys_exit, SYSCALL_EXIT, i32);
 syscall!(sys_exit_group, SYSCALL_EXIT_GROUP, i32);
 syscall!(sys_kill, SYSCALL_KILL, usize, i32);
+syscall!(sys_setpgid, SYSCALL_SETPGID, usize, usize);
+syscall!(sys_getpgid, SYSCALL_GETPGID, usize);
+syscall!(sys_getsid, SYSCALL_GETSID, usize);
+syscall!(sys_setsid, SYSCALL_SETSID);
+syscall!(sys_setuid, SYSCALL_SETUID, u32);
+syscall!(
+    sys_prlimit64,
+    SYSCALL_PRLIMIT64,
+    usize,
+    usize,
+    *const usize,
+    *mut usize
+);
+syscall!(sys_ptrace, SYSCALL_PTRACE, usize, usize, usize, usize);
+syscall!(sys_prctl, SYSCALL_PRCTL, usize, usize, usize, usize);
 syscall!(sys_fork, SYSCALL_CLONE);
 syscall!(sys_clone, SYSCALL_CLONE, usize, usize, usize, usize);
+syscall!(sys_unshare, SYSCALL_UNSHARE, usize);
 syscall!(sys_waitpid, SYSCALL_WAIT4, isize, *mut i32);
-syscall!(sys_pipe, SYSCALL_PIPE, *mut i32);
+syscall!(sys_pipe2, SYSCALL_PIPE, *mut i32, usize);
 syscall!(sys_brk, SYSCALL_BRK, usize);
 syscall!(sys_yield, SYSCALL_SCHED_YIELD);
+syscall!(
+    sys_sched_setaffinity,
+    SYSCALL_SCHED_SETAFFINITY,
+    usize,
+    usize,
+    *const usize
+);
+syscall!(
+    sys_sched_getaffinity,
+    SYSCALL_SCHED_GETAFFINITY,
+    usize,
+    usize,
+    *mut usize
+);
+syscall!(sys_getcpu, SYSCALL_GETCPU, *mut u32, *mut u32);
+syscall!(sys_setpriority, SYSCALL_SETPRIORITY, usize, usize, i32);
+syscall!(sys_getpriority, SYSCALL_GETPRIORITY, usize, usize);
+syscall!(sys_getrusage, SYSCALL_GETRUSAGE, i32, *mut usize);
 syscall!(
     sys_execve,
     SYSCALL_EXECVE,
@@ -269,4 +397,65 @@ syscall!(
     *mut usize
 );
 syscall!(sys_nanosleep, SYSCALL_NANOSLEEP, *const usize, *mut usize);
+syscall!(sys_clock_gettime, SYSCALL_CLOCK_GETTIME, usize, *mut usize);
+syscall!(
+    sys_clock_settime,
+    SYSCALL_CLOCK_SETTIME,
+    usize,
+    *const usize
+);
 syscall!(sys_sleep, SYSCALL_NANOSLEEP, *const usize);
+
+// net
+syscall!(sys_socket, SYSCALL_SOCKET, usize, i32, usize);
+syscall!(sys_bind, SYSCALL_BIND, usize, *const u8, usize);
+syscall!(sys_listen, SYSCALL_LISTEN, usize, usize);
+syscall!(sys_accept, SYSCALL_ACCEPT, usize, *mut u8, *mut u32);
+syscall!(sys_connect, SYSCALL_CONNECT, usize, *const u8, usize);
+syscall!(
+    sys_getsockname,
+    SYSCALL_GETSOCKNAME,
+    usize,
+    *mut u8,
+    *mut u32
+);
+syscall!(
+    sys_sendto,
+    SYSCALL_SENDTO,
+    usize,
+    *const u8,
+    usize,
+    usize,
+    *const u8,
+    usize
+);
+syscall!(
+    sys_recvfrom,
+    SYSCALL_RECVFROM,
+    usize,
+    *mut u8,
+    usize,
+    usize,
+    *mut u8,
+    *mut u32
+);
+syscall!(
+    sys_setsockopt,
+    SYSCALL_SETSOCKOPT,
+    usize,
+    usize,
+    usize,
+    *const u8,
+    usize
+);
+syscall!(sys_shutdown, SYSCALL_SHUTDOWN, usize, usize);
+syscall!(
+    sys_socketpair,
+    SYSCALL_SOCKETPAIR,
+    usize,
+    i32,
+    usize,
+    *mut u32
+);
+syscall!(sys_sendmsg, SYSCALL_SENDMSG, usize, *const u8, usize);
+syscall!(sys_recvmsg, SYSCALL_RECVMSG, usize, *mut u8, usize);
@@ -0,0 +1,133 @@
+//! Threads sharing the memory, which are joined by waiting on the futex at
+//! their clear_child_tid address as pthread does.
+
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{
+    arch::global_asm,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{futex, CloneFlags, SyscallErr, FUTEX_WAIT};
+
+const STACK_SIZE: usize = 0x4000;
+
+// int __clone(int (*func)(usize), usize stack, usize flags, usize arg,
+//             u32 *ptid, usize tls, u32 *ctid)
+//
+// The same as the one of musl, where the child calls `func(arg)` on the new
+// stack and exits the thread with what it returns.
+global_asm!(
+    r#"
+    .section .text
+    .global __clone
+__clone:
+    andi    a1, a1, -16
+    addi    a1, a1, -16
+    sd      a0, 0(a1)
+    sd      a3, 8(a1)
+    mv      a0, a2
+    mv      a2, a4
+    mv      a3, a5
+    mv      a4, a6
+    li      a7, 220
+    ecall
+    beqz    a0, 1f
+    ret
+1:
+    ld      a1, 0(sp)
+    ld      a0, 8(sp)
+    jalr    a1
+    li      a7, 93
+    ecall
+"#
+);
+
+extern "C" {
+    pub(crate) fn __clone(
+        func: extern "C" fn(usize) -> i32,
+        stack: usize,
+        flags: usize,
+        arg: usize,
+        ptid: *const AtomicU32,
+        tls: usize,
+        ctid: *const AtomicU32,
+    ) -> isize;
+}
+
+type Main = Box<dyn FnOnce() + Send>;
+
+extern "C" fn thread_start(arg: usize) -> i32 {
+    let main = unsafe { Box::from_raw(arg as *mut Main) };
+    main();
+    0
+}
+
+/// Handle of a thread, which owns the stack of the thread.
+pub struct JoinHandle {
+    /// Stored by the kernel with `CLONE_PARENT_SETTID`, and cleared when the
+    /// thread exits with `CLONE_CHILD_CLEARTID`.
+    tid: Box<AtomicU32>,
+    _stack: Vec<u8>,
+}
+
+impl JoinHandle {
+    pub fn tid(&self) -> u32 {
+        self.tid.load(Ordering::Relaxed)
+    }
+
+    /// Wait for the thread to exit.
+    pub fn join(self) {
+        self.wait();
+    }
+
+    fn wait(&self) {
+        let addr = &*self.tid as *const AtomicU32 as usize;
+        loop {
+            let tid = self.tid.load(Ordering::Acquire);
+            if tid == 0 {
+                return;
+            }
+            futex(addr, FUTEX_WAIT, tid, 0, 0, 0);
+        }
+    }
+}
+
+impl Drop for JoinHandle {
+    /// The stack can only be freed after the thread exits, so dropping the
+    /// handle also waits for it.
+    fn drop(&mut self) {
+        self.wait();
+    }
+}
+
+/// Run `main` in a new thread.
+pub fn spawn(main: impl FnOnce() + Send + 'static) -> Result<JoinHandle, SyscallErr> {
+    let flags = CloneFlags::VM
+        | CloneFlags::FS
+        | CloneFlags::FILES
+        | CloneFlags::SIGHAND
+        | CloneFlags::THREAD
+        | CloneFlags::SYSVSEM
+        | CloneFlags::PARENT_SETTID
+        | CloneFlags::CHILD_CLEARTID;
+    let stack = vec![0u8; STACK_SIZE];
+    let tid = Box::new(AtomicU32::new(0));
+    let main: Main = Box::new(main);
+    let arg = Box::into_raw(Box::new(main));
+    let ret = unsafe {
+        __clone(
+            thread_start,
+            stack.as_ptr() as usize + STACK_SIZE,
+            flags.bits() as usize,
+            arg as usize,
+            &*tid,
+            0,
+            &*tid,
+        )
+    };
+    if let Err(err) = SyscallErr::from_ret(ret) {
+        drop(unsafe { Box::from_raw(arg) });
+        return Err(err);
+    }
+    Ok(JoinHandle { tid, _stack: stack })
+}
@@ -1,6 +1,6 @@
 pub use signal::*;
 pub use sigset::*;
-pub use time::{timespec::TimeSpec, timeval::TimeVal};
+pub use time::{timespec::TimeSpec, timeval::TimeVal, CLOCK_MONOTONIC, CLOCK_REALTIME};
 
 #[derive(Clone, Copy, Default)]
 #[repr(C)]
@@ -38,18 +38,137 @@ bitflags! {
         const O_RDONLY = 0;
         const O_WRONLY = 1 << 0;
         const O_RDWR = 1 << 1;
-        const O_CLOEXEC = 1 << 7;
-        const O_CREATE = 1 << 9;
-        const O_TRUNC = 1 << 10;
+        const O_CREATE = 0o100;
+        const O_TRUNC = 0o1000;
+        const O_DIRECTORY = 0o200000;
+        const O_NOFOLLOW = 0o400000;
+        const O_CLOEXEC = 0o2000000;
+        const O_PATH = 0o10000000;
     }
 }
 pub const AT_FDCWD: isize = -100;
+pub const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+pub const AT_REMOVEDIR: usize = 0x200;
+
+// File types in `Stat::st_mode`.
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFCHR: u32 = 0o020000;
+
+/// Status of a file, the same as `struct stat`.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Stat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    __pad: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    __pad2: i32,
+    pub st_blocks: i64,
+    pub st_atime_sec: isize,
+    pub st_atime_nsec: isize,
+    pub st_mtime_sec: isize,
+    pub st_mtime_nsec: isize,
+    pub st_ctime_sec: isize,
+    pub st_ctime_nsec: isize,
+}
+
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+pub const SEEK_DATA: usize = 3;
+pub const SEEK_HOLE: usize = 4;
 
 pub const FUTEX_PRIVATE_FLAG: i32 = 0x80;
 pub const FUTEX_WAIT: i32 = 0;
 pub const FUTEX_WAKE: i32 = 1;
 pub const FUTEX_REQUEUE: i32 = 3;
 pub const FUTEX_CMP_REQUEUE: i32 = 4;
+pub const FUTEX_WAIT_BITSET: i32 = 9;
+pub const FUTEX_WAKE_BITSET: i32 = 10;
+pub const FUTEX_CLOCK_REALTIME: i32 = 0x100;
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+// which of setpriority(2) and getpriority(2)
+pub const PRIO_PROCESS: usize = 0;
+pub const PRIO_PGRP: usize = 1;
+pub const PRIO_USER: usize = 2;
+
+// Who of getrusage(2).
+pub const RUSAGE_SELF: i32 = 0;
+pub const RUSAGE_CHILDREN: i32 = -1;
+pub const RUSAGE_THREAD: i32 = 1;
+
+/// Resource usage, the same as `struct rusage`.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Rusage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    pub maxrss: usize,
+    pub ixrss: usize,
+    pub idrss: usize,
+    pub isrss: usize,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nswap: usize,
+    pub inblock: usize,
+    pub oublock: usize,
+    pub msgsnd: usize,
+    pub msgrcv: usize,
+    pub nsignals: usize,
+    /// Voluntary context switches, e.g. when blocked waiting for something.
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+pub const RLIM_INFINITY: usize = usize::MAX;
+pub const RLIMIT_CORE: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct RLimit {
+    pub rlim_cur: usize,
+    pub rlim_max: usize,
+}
+
+// Requests of ptrace(2).
+pub const PTRACE_TRACEME: usize = 0;
+pub const PTRACE_PEEKDATA: usize = 2;
+pub const PTRACE_POKEDATA: usize = 5;
+pub const PTRACE_CONT: usize = 7;
+pub const PTRACE_KILL: usize = 8;
+pub const PTRACE_GETREGS: usize = 12;
+pub const PTRACE_SETREGS: usize = 13;
+pub const PTRACE_ATTACH: usize = 16;
+pub const PTRACE_DETACH: usize = 17;
+pub const PTRACE_SYSCALL: usize = 24;
+
+// Options of prctl(2).
+pub const PR_GET_SECCOMP: usize = 21;
+pub const PR_SET_SECCOMP: usize = 22;
+/// Panic in the kernel on purpose, which is Phoenix specific and only
+/// supported by kernels recovering from panics in syscalls.
+pub const PR_PANIC: usize = 0x5048_0000;
+
+// Modes of PR_SET_SECCOMP, where the allowlist mode is Phoenix specific.
+pub const SECCOMP_MODE_DISABLED: usize = 0;
+pub const SECCOMP_MODE_STRICT: usize = 1;
+pub const SECCOMP_MODE_ALLOWLIST: usize = 3;
+
+// Commands of reboot(2).
+pub const LINUX_REBOOT_CMD_RESTART: usize = 0x01234567;
+pub const LINUX_REBOOT_CMD_HALT: usize = 0xcdef0123;
+pub const LINUX_REBOOT_CMD_POWER_OFF: usize = 0x4321fedc;
 
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -65,6 +184,8 @@ bitflags! {
         const SIGHAND = 0x00000800;
         /// Set if a pidfd should be placed in parent.
         const PIDFD = 0x00001000;
+        /// Set if the parent wants the child to wake it up on mm_release.
+        const VFORK = 0x00004000;
         /// Set if we want to have the same parent as the cloner.
         const PARENT = 0x00008000;
         /// Set to add to same thread group.
@@ -140,3 +261,259 @@ pub struct MContext {
     pub sepc: usize,
     pub user_x: [usize; 32],
 }
+
+/// Defined in <sys/utsname.h>.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct UtsName {
+    pub sysname: [u8; 65],
+    pub nodename: [u8; 65],
+    pub release: [u8; 65],
+    pub version: [u8; 65],
+    pub machine: [u8; 65],
+    pub domainname: [u8; 65],
+}
+
+impl Default for UtsName {
+    fn default() -> Self {
+        Self {
+            sysname: [0; 65],
+            nodename: [0; 65],
+            release: [0; 65],
+            version: [0; 65],
+            machine: [0; 65],
+            domainname: [0; 65],
+        }
+    }
+}
+
+/// Socket address family, the same as `SaFamily` in the kernel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub enum SaFamily {
+    Unix = 1,
+    Inet = 2,
+    Inet6 = 10,
+}
+
+/// Socket type, the same as `SocketType` in the kernel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i32)]
+pub enum SocketType {
+    /// TCP
+    Stream = 1,
+    /// UDP
+    Dgram = 2,
+    SeqPacket = 5,
+}
+
+bitflags! {
+    /// Flags or-ed into the socket type.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct SocketFlags: i32 {
+        const NONBLOCK = 0x800;
+        const CLOEXEC = 0x80000;
+    }
+}
+
+/// How to shut down a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Shutdown {
+    Read = 0,
+    Write = 1,
+    Both = 2,
+}
+
+pub const SOL_SOCKET: usize = 1;
+pub const IPPROTO_TCP: usize = 6;
+pub const SO_REUSEADDR: usize = 2;
+pub const SO_SNDBUF: usize = 7;
+pub const SO_RCVBUF: usize = 8;
+pub const SO_KEEPALIVE: usize = 9;
+pub const TCP_NODELAY: usize = 1;
+
+/// Type of the ancillary message at `SOL_SOCKET` level passing file
+/// descriptors.
+pub const SCM_RIGHTS: i32 = 1;
+/// Set in `MsgHdr::flags` when some ancillary data does not fit.
+pub const MSG_CTRUNC: i32 = 0x8;
+/// Set in `MsgHdr::flags` when the tail of a datagram does not fit.
+pub const MSG_TRUNC: i32 = 0x20;
+/// Set close-on-exec flag on file descriptors received by `SCM_RIGHTS`.
+pub const MSG_CMSG_CLOEXEC: usize = 0x40000000;
+
+/// File descriptor polled by `ppoll`, the same as `struct pollfd`.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+pub const POLLIN: i16 = 0x1;
+pub const POLLOUT: i16 = 0x4;
+
+/// Set of file descriptors selected by `pselect`, the same as `fd_set`.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct FdSet {
+    pub fds_bits: [u64; 16],
+}
+
+impl FdSet {
+    pub fn set(&mut self, fd: usize) {
+        self.fds_bits[fd / 64] |= 1 << (fd % 64);
+    }
+
+    pub fn is_set(&self, fd: usize) -> bool {
+        self.fds_bits[fd / 64] & (1 << (fd % 64)) != 0
+    }
+}
+
+/// Buffer of scatter/gather IO.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct IoVec {
+    pub base: *mut u8,
+    pub len: usize,
+}
+
+/// Message passed to `sendmsg` and `recvmsg`, the same as `struct msghdr`.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MsgHdr {
+    pub name: *mut u8,
+    pub namelen: u32,
+    pub iov: *mut IoVec,
+    pub iovlen: usize,
+    pub control: *mut u8,
+    pub controllen: usize,
+    pub flags: i32,
+}
+
+/// Header of an ancillary message in `MsgHdr::control`, which is followed by
+/// the data.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct CMsgHdr {
+    pub len: usize,
+    pub level: i32,
+    pub ty: i32,
+}
+
+impl CMsgHdr {
+    /// Length of a message with `len` bytes of data, like `CMSG_LEN`.
+    pub const fn msg_len(len: usize) -> usize {
+        core::mem::size_of::<Self>() + len
+    }
+
+    /// Space taken by a message with `len` bytes of data, like `CMSG_SPACE`.
+    pub const fn space(len: usize) -> usize {
+        Self::msg_len(len).next_multiple_of(core::mem::size_of::<usize>())
+    }
+}
+
+/// IPv4 socket address, with port and address in network byte order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct SockAddrIn {
+    pub family: u16,
+    pub port: [u8; 2],
+    pub addr: [u8; 4],
+    pub zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    pub fn new(addr: [u8; 4], port: u16) -> Self {
+        Self {
+            family: SaFamily::Inet as u16,
+            port: port.to_be_bytes(),
+            addr,
+            zero: [0; 8],
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        u16::from_be_bytes(self.port)
+    }
+}
+
+/// Unix domain socket address with a null terminated path.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct SockAddrUn {
+    pub family: u16,
+    pub path: [u8; 108],
+}
+
+impl SockAddrUn {
+    /// Returns `None` if `path` is too long.
+    pub fn new(path: &str) -> Option<Self> {
+        let mut addr = Self {
+            family: SaFamily::Unix as u16,
+            path: [0; 108],
+        };
+        let path = path.as_bytes();
+        if path.len() >= addr.path.len() {
+            return None;
+        }
+        addr.path[..path.len()].copy_from_slice(path);
+        Some(addr)
+    }
+}
+
+/// Socket address passed to and returned by the socket wrappers.
+#[derive(Clone, Copy)]
+pub enum SockAddr {
+    In(SockAddrIn),
+    Un(SockAddrUn),
+}
+
+/// Storage large enough for any socket address the kernel writes back.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub(crate) union RawSockAddr {
+    pub family: u16,
+    pub ipv4: SockAddrIn,
+    pub ipv6: [u8; 28],
+    pub unix: SockAddrUn,
+}
+
+impl SockAddr {
+    pub(crate) fn as_raw(&self) -> (*const u8, usize) {
+        match self {
+            SockAddr::In(addr) => (
+                addr as *const _ as *const u8,
+                core::mem::size_of::<SockAddrIn>(),
+            ),
+            SockAddr::Un(addr) => (
+                addr as *const _ as *const u8,
+                core::mem::size_of::<SockAddrUn>(),
+            ),
+        }
+    }
+
+    /// Returns `None` for families not supported here, e.g. IPv6.
+    pub(crate) fn from_raw(raw: &RawSockAddr) -> Option<Self> {
+        unsafe {
+            match raw.family {
+                f if f == SaFamily::Inet as u16 => Some(SockAddr::In(raw.ipv4)),
+                f if f == SaFamily::Unix as u16 => Some(SockAddr::Un(raw.unix)),
+                _ => None,
+            }
+        }
+    }
+}
+
+impl RawSockAddr {
+    pub(crate) fn zeroed() -> Self {
+        RawSockAddr {
+            unix: SockAddrUn {
+                family: 0,
+                path: [0; 108],
+            },
+        }
+    }
+}
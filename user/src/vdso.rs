@@ -0,0 +1,94 @@
+//! Symbols of the vDSO, whose address is passed by AT_SYSINFO_EHDR, looked up
+//! in the way of musl.
+
+use core::{
+    mem::transmute,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{auxv::*, sys_clock_gettime, TimeSpec};
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const DT_NULL: usize = 0;
+const DT_HASH: usize = 4;
+const DT_STRTAB: usize = 5;
+const DT_SYMTAB: usize = 6;
+/// Size of Elf64_Sym.
+const SYM_SIZE: usize = 24;
+
+type ClockGettime = extern "C" fn(usize, *mut TimeSpec) -> isize;
+
+/// Address of `__vdso_clock_gettime`, where 0 is not looked up yet and
+/// `usize::MAX` is missing.
+static CLOCK_GETTIME: AtomicUsize = AtomicUsize::new(0);
+
+unsafe fn read<T: Copy>(addr: usize) -> T {
+    (addr as *const T).read_unaligned()
+}
+
+/// Whether the null terminated string at `addr` is `name`.
+unsafe fn c_str_eq(addr: usize, name: &str) -> bool {
+    name.bytes()
+        .chain(Some(0))
+        .enumerate()
+        .all(|(i, c)| read::<u8>(addr + i) == c)
+}
+
+/// Address of the symbol `name` in the vDSO.
+pub fn vdso_sym(name: &str) -> Option<usize> {
+    let ehdr = getauxval(AT_SYSINFO_EHDR)?;
+    unsafe {
+        let phoff = read::<u64>(ehdr + 0x20) as usize;
+        let phentsize = read::<u16>(ehdr + 0x36) as usize;
+        let phnum = read::<u16>(ehdr + 0x38) as usize;
+        let (mut base, mut dynamic) = (None, None);
+        for i in 0..phnum {
+            let phdr = ehdr + phoff + i * phentsize;
+            let p_offset = read::<u64>(phdr + 0x8) as usize;
+            let p_vaddr = read::<u64>(phdr + 0x10) as usize;
+            match read::<u32>(phdr) {
+                PT_LOAD => base = Some(ehdr + p_offset - p_vaddr),
+                PT_DYNAMIC => dynamic = Some(ehdr + p_offset),
+                _ => {}
+            }
+        }
+        let (base, dynamic) = (base?, dynamic?);
+
+        let (mut hash, mut strtab, mut symtab) = (None, None, None);
+        for entry in (dynamic..).step_by(16) {
+            let d_val = base + read::<usize>(entry + 8);
+            match read::<usize>(entry) {
+                DT_NULL => break,
+                DT_HASH => hash = Some(d_val),
+                DT_STRTAB => strtab = Some(d_val),
+                DT_SYMTAB => symtab = Some(d_val),
+                _ => {}
+            }
+        }
+        let (hash, strtab, symtab) = (hash?, strtab?, symtab?);
+
+        // nchain, i.e. the number of symbols
+        let nsyms = read::<u32>(hash + 4) as usize;
+        (1..nsyms)
+            .map(|i| symtab + i * SYM_SIZE)
+            .filter(|&sym| read::<u16>(sym + 6) != 0)
+            .find(|&sym| c_str_eq(strtab + read::<u32>(sym) as usize, name))
+            .map(|sym| base + read::<u64>(sym + 8) as usize)
+    }
+}
+
+/// Read the clock through the vDSO without trapping into the kernel, falling
+/// back to the syscall if there is no vDSO.
+pub fn clock_gettime(clockid: usize, tp: &mut TimeSpec) -> isize {
+    let mut addr = CLOCK_GETTIME.load(Ordering::Relaxed);
+    if addr == 0 {
+        addr = vdso_sym("__vdso_clock_gettime").unwrap_or(usize::MAX);
+        CLOCK_GETTIME.store(addr, Ordering::Relaxed);
+    }
+    if addr == usize::MAX {
+        return sys_clock_gettime(clockid, tp as *mut TimeSpec as *mut usize);
+    }
+    let func: ClockGettime = unsafe { transmute(addr) };
+    func(clockid, tp)
+}